@@ -2,6 +2,115 @@ use sha2::{Digest, Sha256};
 use hex::ToHex;
 use anyhow::{Result, bail};
 
+mod analyze;
+mod append;
+mod archive;
+#[cfg(feature = "arweave")]
+mod arweave_store;
+mod blob_cache;
+mod blob_file;
+mod blob_store;
+mod borsh_layout;
+mod canonical;
+mod car;
+mod cli;
+#[cfg(feature = "client")]
+mod client;
+mod cnft;
+mod compression;
+mod compute;
+mod config;
+mod cost;
+mod das;
+mod delta;
+#[cfg(feature = "devnet")]
+mod devnet;
+mod diagnose;
+mod dirty;
+mod dot;
+mod erasure;
+mod error;
+mod events;
+mod game;
+mod genesis;
+#[cfg(feature = "grpc")]
+mod grpc_server;
+#[cfg(feature = "http")]
+mod http_server;
+mod indexer;
+#[cfg(feature = "ipfs")]
+mod ipfs_store;
+mod json_proof;
+mod keypair;
+mod leaf_cache;
+mod light_client;
+mod mapped;
+mod memory;
+mod mempool;
+mod metrics;
+mod migration;
+mod multisig;
+mod network;
+mod optimistic;
+mod packer;
+mod parallel;
+mod partial;
+mod program;
+mod proof_file;
+mod pruned;
+mod realloc;
+mod pubkey;
+mod pubsub;
+mod rent;
+mod repl;
+mod replication;
+mod retrieval;
+mod rng;
+mod rollup;
+mod rpc;
+#[cfg(feature = "s3")]
+mod s3_store;
+mod savings;
+mod schema;
+mod schemes;
+mod serde_support;
+mod signer;
+mod sim_metrics;
+mod simulate;
+mod state;
+mod storage;
+mod timelock;
+mod token;
+mod vectors;
+mod view;
+mod witness_file;
+mod workload;
+#[cfg(feature = "zk")]
+mod zk;
+use archive::{ArchiveNode, RetentionPolicy};
+use blob_cache::{CacheConfig, CachedBlobStore};
+use blob_store::FsBlobStore;
+use compression::Compression;
+use compute::{ComputeCosts, ComputeMeter, DEFAULT_COMPUTE_BUDGET};
+use dirty::DirtyBitmap;
+use error::WitnessError;
+use events::{EventLog, EventSubscriber, StdoutSubscriber, WitnessEvent};
+use genesis::GenesisConfig;
+use indexer::Indexer;
+use leaf_cache::LeafCache;
+use light_client::BankHeader;
+use mempool::Mempool;
+use multisig::Multisig;
+use optimistic::OptimisticState;
+use program::{DemoProgram, InvokeContext, Program, ProxyProgram, DEMO_PROGRAM_ID};
+use pubkey::PdaSeeds;
+use realloc::Frontier;
+use rent::{HotAccount, RentConfig, RentLedger};
+use state::{AccountProof, StateTree};
+use storage::StorageState;
+use timelock::TimeLock;
+use witness_core::ShapeError;
+
 /// Simple helper: SHA256 of bytes
 fn sha256(data: &[u8]) -> [u8; 32] {
     let mut hasher = Sha256::new();
@@ -18,201 +127,885 @@ fn h(h: &[u8;32]) -> String {
 }
 
 /// Chunk an arbitrary blob into fixed-size leaves (padding last chunk with zeros)
-fn chunk_blob(blob: &[u8], chunk_size: usize) -> Vec<Vec<u8>> {
-    let mut out = Vec::new();
-    let mut i = 0;
-    while i < blob.len() {
-        let end = std::cmp::min(i + chunk_size, blob.len());
-        let mut chunk = blob[i..end].to_vec();
-        if chunk.len() < chunk_size {
-            chunk.resize(chunk_size, 0);
-        }
-        out.push(chunk);
-        i += chunk_size;
-    }
-    // If blob is empty, still create a single zero chunk to avoid empty tree issues
-    if out.is_empty() {
-        out.push(vec![0u8; chunk_size]);
-    }
-    out
+fn chunk_blob(blob: &[u8], chunk_size: usize) -> witness_core::Chunks<'_> {
+    witness_core::chunk_blob(blob, chunk_size)
 }
 
 /// Simple Merkle tree implementation (binary). Leaves are hash(chunk).
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 struct MerkleTree {
-    /// layers[0] = leaves hashes, last layer = root (single element)
-    layers: Vec<Vec<[u8;32]>>,
+    /// Every node hash in one buffer: the leaf layer first, each layer above right after
+    /// the one below and the root last (see `witness_core::layer_offset`)
+    nodes: Vec<[u8;32]>,
+    /// Leaves after padding, a power of two
+    width: usize,
 }
 
 impl MerkleTree {
-    /// Build tree from raw leaves (pre-hashed chunks are allowed; we hash chunk bytes ourselves)
-    fn from_chunks(chunks: &[Vec<u8>]) -> Self {
-        let mut leaves: Vec<[u8;32]> = chunks.iter().map(|c| sha256(c)).collect();
-        // If number of leaves is not power of two, duplicate last leaf (simple padding)
-        let mut n = leaves.len();
-        if n & (n-1) != 0 {
-            // round up to next power of two
-            let mut pow = 1;
-            while pow < n { pow <<= 1; }
-            while leaves.len() < pow {
-                leaves.push(*leaves.last().unwrap());
-            }
-            n = pow;
-        }
-        let mut layers = vec![leaves];
-        // build upper layers
-        while layers.last().unwrap().len() > 1 {
-            let prev = layers.last().unwrap();
-            let mut next = Vec::with_capacity((prev.len()+1)/2);
-            for pair in prev.chunks(2) {
-                let left = pair[0];
-                let right = pair[1];
-                let mut data = [0u8; 64];
-                data[..32].copy_from_slice(&left);
-                data[32..].copy_from_slice(&right);
-                next.push(sha256(&data));
-            }
-            layers.push(next);
-        }
-        MerkleTree { layers }
+    /// Build tree from raw leaves (pre-hashed chunks are allowed; we hash chunk bytes ourselves).
+    /// If number of leaves is not power of two, the last leaf is duplicated (simple padding).
+    fn from_chunks<I>(chunks: I) -> Self
+    where
+        I: IntoIterator,
+        I::Item: AsRef<[u8]>,
+    {
+        let _span = tracing::trace_span!("tree_build").entered();
+        let nodes = witness_core::build_flat(chunks);
+        let width = nodes.len().div_ceil(2);
+        tracing::trace!(width, "built tree");
+        MerkleTree { width, nodes }
+    }
+
+    /// Tree over already-hashed leaves, padded the same way
+    fn from_leaves(leaves: Vec<[u8;32]>) -> Self {
+        MerkleTree { width: leaves.len().next_power_of_two(), nodes: witness_core::flat_from_leaves(leaves) }
     }
 
     /// Root of tree
     fn root(&self) -> [u8;32] {
-        self.layers.last().unwrap()[0]
+        *self.nodes.last().unwrap()
+    }
+
+    /// Levels above the leaves; a proof has this many steps
+    fn depth(&self) -> usize {
+        self.width.trailing_zeros() as usize
+    }
+
+    /// Nodes of one level, 0 being the (padded) leaf hashes
+    fn layer(&self, level: usize) -> &[[u8;32]] {
+        let start = witness_core::layer_offset(self.width, level);
+        &self.nodes[start..start + (self.width >> level)]
+    }
+
+    fn leaves(&self) -> &[[u8;32]] {
+        self.layer(0)
+    }
+
+    /// Every level, leaves first and the root last
+    fn layers(&self) -> impl Iterator<Item = &[[u8;32]]> {
+        (0..=self.depth()).map(|level| self.layer(level))
     }
 
     /// Produce proof for leaf index (original chunk index)
     /// Proof is Vec<(sibling_hash, is_left_sibling?)>
     fn gen_proof(&self, leaf_index: usize) -> Vec<([u8;32], bool)> {
-        let mut proof = Vec::new();
-        let mut idx = leaf_index;
-        for layer in &self.layers {
-            if layer.len() == 1 { break; }
-            let pair_idx = if idx % 2 == 0 { idx + 1 } else { idx - 1 };
-            let sibling = layer[pair_idx];
-            let is_left = pair_idx < idx; // sibling is left of our node?
-            proof.push((sibling, is_left));
-            idx /= 2;
-        }
-        proof
-    }
-
-    /// Verify a proof for a given leaf chunk and expected root
-    fn verify_proof(leaf_chunk: &[u8], proof: &Vec<([u8;32], bool)>, expected_root: &[u8;32]) -> bool {
-        let mut computed = sha256(leaf_chunk);
-        for (sibling, is_left) in proof {
-            let mut data = [0u8; 64];
-            if *is_left {
-                // sibling is left, so sibling || computed
-                data[..32].copy_from_slice(sibling);
-                data[32..].copy_from_slice(&computed);
-            } else {
-                // computed || sibling
-                data[..32].copy_from_slice(&computed);
-                data[32..].copy_from_slice(sibling);
-            }
-            computed = sha256(&data);
+        tracing::trace!(leaf_index, depth = self.depth(), "generated proof");
+        (0..self.depth()).map(|level| {
+            let idx = leaf_index >> level;
+            (self.layer(level)[idx ^ 1], idx & 1 == 1)
+        }).collect()
+    }
+
+    /// Leaf index a proof is for: the direction flags spell it out bit by bit
+    fn proof_index(proof: &[([u8;32], bool)]) -> usize {
+        witness_core::proof_index(proof)
+    }
+
+    /// Verify a proof for a given leaf chunk and expected root. Delegates to the
+    /// `no_std` core so browser, FFI and on-chain verifiers check exactly the same bytes.
+    fn verify_proof(leaf_chunk: &[u8], proof: &[([u8;32], bool)], expected_root: &[u8;32]) -> bool {
+        witness_core::verify_proof(leaf_chunk, proof, expected_root)
+    }
+
+    /// `verify_proof` for leaf `leaf_index` of a tree over `leaf_count` chunks, which also
+    /// rejects a proof of the wrong depth or directions
+    fn verify_proof_at(leaf_chunk: &[u8], proof: &[([u8;32], bool)], expected_root: &[u8;32], leaf_index: usize, leaf_count: usize) -> bool {
+        witness_core::verify_proof_at(leaf_chunk, proof, expected_root, leaf_index, leaf_count)
+    }
+}
+
+/// Hash function an account's Merkle tree is built with
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+enum HashAlgo {
+    Sha256,
+}
+
+impl HashAlgo {
+    fn id(self) -> u8 {
+        match self {
+            HashAlgo::Sha256 => 0,
+        }
+    }
+
+    fn from_id(id: u8) -> Option<Self> {
+        match id {
+            0 => Some(HashAlgo::Sha256),
+            _ => None,
         }
-        &computed == expected_root
     }
 }
 
-/// A toy "on-chain" stub that stores the merkle root of an account blob
-#[derive(Debug, Clone)]
+/// A toy "on-chain" stub that stores the merkle root of an account blob.
+/// Mirrors the fields of Solana's `Account`, with the data replaced by its commitment.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
 struct AccountStub {
-    pub owner: String,       // owner pubkey placeholder
+    pub owner: String,       // owner program id; only it may update the root
     pub lamports: u64,       // lamports balance (fake)
+    #[serde(with = "crate::serde_support::hex_form")]
     pub merkle_root: [u8;32],// commitment to full blob
+    pub executable: bool,    // program accounts are immutable
+    pub data_len: u64,       // length of the committed blob in bytes
+    pub rent_epoch: u64,     // next epoch rent is due
+    pub chunk_size: usize,   // bytes per leaf the root was built with
+    pub hash_algo: HashAlgo, // leaf and node hash
+    pub arity: u8,           // children per tree node
+    pub append_only: bool,   // root commits to an MMR of entries; only `append_entry` updates it
+    pub dirty: Option<DirtyBitmap>, // opt-in record of recently written chunks
+    pub multisig: Option<Multisig>, // if set, writes also need m-of-n signatures
+    pub timelock: Option<TimeLock>, // slot/epoch gates on writes
+    pub pda: Option<PdaSeeds>,      // set for program-derived addresses
+    pub compression: Option<Compression>, // committed bytes are compressed; revive to use
 }
 
 impl AccountStub {
-    fn new(owner: &str, lamports: u64, merkle_root: [u8;32]) -> Self {
-        Self { owner: owner.to_string(), lamports, merkle_root }
+    /// Stub committing to a blob split into `chunk_size`-byte leaves of a binary SHA-256 tree
+    fn new(owner: &str, lamports: u64, merkle_root: [u8;32], data_len: u64, chunk_size: usize) -> Self {
+        Self { owner: owner.to_string(), lamports, merkle_root, executable: false, data_len, rent_epoch: 0, chunk_size, hash_algo: HashAlgo::Sha256, arity: 2, append_only: false, dirty: None, multisig: None, timelock: None, pda: None, compression: None }
+    }
+
+    /// Reject tree layouts the verifier can't check, and data programs can't read as-is
+    fn check_layout(&self) -> Result<(), WitnessError> {
+        if let Some(c) = &self.compression {
+            return Err(WitnessError::Compressed(c.codec));
+        }
+        self.check_tree()
+    }
+
+    /// Reject tree layouts the verifier can't check
+    fn check_tree(&self) -> Result<(), WitnessError> {
+        if self.append_only {
+            return Err(WitnessError::AppendOnly);
+        }
+        if self.chunk_size == 0 {
+            return Err(WitnessError::ZeroChunkSize);
+        }
+        if self.hash_algo != HashAlgo::Sha256 || self.arity != 2 {
+            return Err(WitnessError::UnsupportedLayout { hash_algo: self.hash_algo, arity: self.arity });
+        }
+        Ok(())
+    }
+
+    /// Leaves of the committed blob, not counting the duplicates padding the tree
+    fn chunk_count(&self) -> usize {
+        (self.data_len as usize).div_ceil(self.chunk_size).max(1)
+    }
+
+    /// Levels of the committed tree, so the steps in every proof against it
+    fn depth(&self) -> usize {
+        witness_core::tree_depth(self.chunk_count())
+    }
+
+    /// Reject proofs that could reach the root without proving a real leaf; see
+    /// `witness_core::check_proof_shape`
+    fn check_proof_shape(&self, pubkey: &str, leaf_index: usize, proof: &[([u8;32], bool)]) -> Result<(), WitnessError> {
+        match witness_core::check_proof_shape(proof, leaf_index, self.chunk_count()) {
+            Ok(()) => Ok(()),
+            Err(ShapeError::LeafRange { leaf_index, leaf_count }) => Err(WitnessError::ProofIndexOutOfRange { index: leaf_index, chunks: leaf_count }),
+            Err(ShapeError::Depth { expected, actual }) => Err(WitnessError::ProofLength { pubkey: pubkey.to_string(), expected, actual }),
+            Err(ShapeError::Index { claimed, proven }) => Err(WitnessError::ProofIndexMismatch { claimed, proven }),
+            Err(ShapeError::Malformed) => unreachable!("decoded steps are well formed"),
+        }
+    }
+
+    /// Canonical byte encoding committed to by the state tree; see `canonical` for the
+    /// layout and `decode` for the inverse
+    fn encode(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        out.extend_from_slice(&(self.owner.len() as u32).to_le_bytes());
+        out.extend_from_slice(self.owner.as_bytes());
+        out.extend_from_slice(&self.lamports.to_le_bytes());
+        out.extend_from_slice(&self.merkle_root);
+        out.push(self.executable as u8);
+        out.extend_from_slice(&self.data_len.to_le_bytes());
+        out.extend_from_slice(&self.rent_epoch.to_le_bytes());
+        out.extend_from_slice(&(self.chunk_size as u64).to_le_bytes());
+        out.push(self.hash_algo.id());
+        out.push(self.arity);
+        out.push(self.append_only as u8);
+        match &self.dirty {
+            Some(d) => {
+                out.push(1);
+                out.extend_from_slice(&d.bits);
+                out.extend_from_slice(&d.since_slot.to_le_bytes());
+            }
+            None => out.push(0),
+        }
+        match &self.multisig {
+            Some(m) => {
+                out.push(1);
+                out.extend_from_slice(&m.encode());
+            }
+            None => out.push(0),
+        }
+        match &self.timelock {
+            Some(t) => {
+                out.push(1);
+                out.extend_from_slice(&t.encode());
+            }
+            None => out.push(0),
+        }
+        match &self.pda {
+            Some(p) => {
+                out.push(1);
+                out.extend_from_slice(&p.encode());
+            }
+            None => out.push(0),
+        }
+        match &self.compression {
+            Some(c) => {
+                out.push(1);
+                out.extend_from_slice(&c.encode());
+            }
+            None => out.push(0),
+        }
+        out
+    }
+}
+
+/// Account data carried by a transaction: full blob plus a proof for one of its leaves.
+/// The chunking comes from the account's stub, never from the tx.
+#[derive(Debug, Clone)]
+struct AccountWitness {
+    pub pubkey: String,
+    pub blob: Vec<u8>,
+    pub leaf_index: usize,
+    pub proof: Vec<([u8;32], bool)>,
+    /// Whether the tx may write this account; read-only accounts only take a shared lock
+    pub is_writable: bool,
+}
+
+impl AccountWitness {
+    /// Check the proven leaf of `blob` against the root of `stub`, chunked the way `stub` says
+    fn verify(&self, stub: &AccountStub) -> Result<(), WitnessError> {
+        stub.check_layout()?;
+        self.check_root(stub, &self.proven_root(stub.chunk_size)?)
+    }
+
+    /// Root the proof leads to from the proven leaf of `blob` in `chunk_size`-byte chunks
+    fn proven_root(&self, chunk_size: usize) -> Result<[u8;32], WitnessError> {
+        // chunk blob and pick the leaf chunk for which proof was provided
+        let chunks = chunk_blob(&self.blob, chunk_size);
+        if self.leaf_index >= chunks.len() {
+            return Err(WitnessError::ProofIndexOutOfRange { index: self.leaf_index, chunks: chunks.len() });
+        }
+        Ok(witness_core::compute_root(&chunks[self.leaf_index], &self.proof))
+    }
+
+    /// The last step of `verify`, given the root `proven_root` computed
+    fn check_root(&self, stub: &AccountStub, proven: &[u8;32]) -> Result<(), WitnessError> {
+        stub.check_proof_shape(&self.pubkey, self.leaf_index, &self.proof)?;
+        if !witness_core::ct::eq(proven, &stub.merkle_root) {
+            return Err(WitnessError::ProofVerificationFailed {
+                pubkey: self.pubkey.clone(),
+                leaf_index: self.leaf_index,
+                expected: stub.merkle_root,
+                computed: *proven,
+            });
+        }
+        Ok(())
     }
 }
 
-/// Simulated "blockchain state" mapping pubkey -> stub
-use std::collections::HashMap;
+/// A witness transaction as submitted by a client: one instruction for `program_id`
+/// over the accounts in `witnesses`.
+/// `recent_blockhash` ties the tx to a recent slot so it expires and can't be replayed.
+#[derive(Debug, Clone)]
+struct WitnessTx {
+    pub program_id: String,
+    pub instruction_data: Vec<u8>,
+    pub witnesses: Vec<AccountWitness>,
+    pub recent_blockhash: [u8;32],
+    /// Lamports offered to the block producer; higher fees are scheduled first
+    pub priority_fee: u64,
+    /// (signer pubkey, ed25519 signature over `id()`); only multisig-owned accounts need any
+    pub signatures: Vec<([u8;32], [u8;64])>,
+}
+
+impl WitnessTx {
+    /// Transaction id: hash over every field except the signatures, which sign it. Every
+    /// variable-length field and list is length-prefixed, so no two txs encode alike.
+    fn id(&self) -> [u8;32] {
+        fn put(data: &mut Vec<u8>, bytes: &[u8]) {
+            data.extend_from_slice(&(bytes.len() as u64).to_le_bytes());
+            data.extend_from_slice(bytes);
+        }
+        let mut data = Vec::new();
+        put(&mut data, self.program_id.as_bytes());
+        put(&mut data, &self.instruction_data);
+        data.extend_from_slice(&(self.witnesses.len() as u64).to_le_bytes());
+        for w in &self.witnesses {
+            put(&mut data, w.pubkey.as_bytes());
+            put(&mut data, &w.blob);
+            data.extend_from_slice(&(w.leaf_index as u64).to_le_bytes());
+            data.push(w.is_writable as u8);
+            data.extend_from_slice(&(w.proof.len() as u64).to_le_bytes());
+            for (sibling, is_left) in &w.proof {
+                data.extend_from_slice(sibling);
+                data.push(*is_left as u8);
+            }
+        }
+        data.extend_from_slice(&self.recent_blockhash);
+        data.extend_from_slice(&self.priority_fee.to_le_bytes());
+        sha256(&data)
+    }
+
+    /// Pubkey reported in events: the first account the tx touches
+    fn primary_pubkey(&self) -> String {
+        self.witnesses.first().map(|w| w.pubkey.clone()).unwrap_or_default()
+    }
+}
+
+/// The state-independent half of verifying a tx: its signatures, and the root each
+/// witness's proof leads to. Computed for a whole block at once, in parallel; each root
+/// is still checked against the stub as it stands when the tx executes.
+struct PreVerified {
+    signers: Result<HashSet<[u8;32]>, WitnessError>,
+    /// (chunk size, proven root) per witness, for the chunk size its stub had when this
+    /// was computed; None if there was no stub or the proof was out of range
+    roots: Vec<Option<(usize, [u8;32])>>,
+}
+
+/// Outcome of a successfully applied witness transaction
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+struct TxReceipt {
+    pub compute_units: u64,
+    /// (pubkey, new root) for every account the program wrote
+    #[serde(with = "crate::serde_support::named_hex")]
+    pub new_roots: Vec<(String, [u8;32])>,
+    /// Proofs for the leaves around the new end of every account that changed size
+    pub frontiers: Vec<(String, Frontier)>,
+}
+
+/// One account write produced by executing a tx, ready to commit
+#[derive(Debug, Clone)]
+struct PendingWrite {
+    pubkey: String,
+    old_root: [u8;32],
+    stub: AccountStub,
+    chunk_size: usize,
+    data: Vec<u8>,
+    /// Set when the write changed the account's length
+    frontier: Option<Frontier>,
+    /// `memory_usage` of the account's new tree
+    tree_bytes: usize,
+}
+
+/// A verified and executed tx whose effects have not been applied yet
+#[derive(Debug, Clone)]
+struct ExecutedTx {
+    tx_id: [u8;32],
+    /// Stub root each witness verified against, in witness order
+    verified_roots: Vec<[u8;32]>,
+    updates: Vec<PendingWrite>,
+    compute_units: u64,
+}
+
+/// How many slots a blockhash stays valid for (mirrors Solana's 150)
+const MAX_RECENT_BLOCKHASHES: usize = 150;
+
+/// Slots per epoch, scaled down from Solana's 432,000 so simulations cross epochs
+const SLOTS_PER_EPOCH: u64 = 432;
+
+/// Uncommitted changes of an open batch; reads see them, nothing else does until commit
+#[derive(Default)]
+struct Staging {
+    stubs: BTreeMap<String, AccountStub>,
+    /// (blockhash, tx id) pairs to add to the status cache
+    processed: Vec<([u8;32], [u8;32])>,
+    /// Events held back so subscribers never observe rolled-back updates
+    events: Vec<WitnessEvent>,
+}
+
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet, VecDeque};
+use std::sync::Arc;
+use std::time::Instant;
+
+/// Simulated "blockchain state" mapping pubkey -> stub. What is state sits in ordered
+/// maps, so iterating, hashing or snapshotting it gives the same bytes on every run.
 struct ChainState {
-    stubs: HashMap<String, AccountStub>,
+    stubs: BTreeMap<String, AccountStub>,
+    subscribers: Vec<Box<dyn EventSubscriber>>,
+    slot: u64,
+    /// Oldest first; the back is the latest blockhash
+    recent_blockhashes: VecDeque<[u8;32]>,
+    /// Processed tx ids grouped by the blockhash they referenced, dropped once it expires
+    status_cache: BTreeMap<[u8;32], BTreeSet<[u8;32]>>,
+    compute_costs: ComputeCosts,
+    /// Max compute units a single witness tx may consume
+    compute_budget: u64,
+    programs: HashMap<String, Box<dyn Program>>,
+    /// Header of every frozen slot, oldest first
+    bank_headers: Vec<BankHeader>,
+    /// Stakes and challengeable updates of the optimistic (unverified) mode
+    optimistic: OptimisticState,
+    /// Accounts still holding their full data on-chain (not yet compressed)
+    hot_accounts: BTreeMap<String, HotAccount>,
+    rent: RentConfig,
+    /// Lamports refunded/charged by compress and revive
+    rent_ledger: RentLedger,
+    /// Storage-provider attestations and open retrieval challenges
+    storage: StorageState,
+    /// Open batch, if any (see `begin_batch`)
+    staging: Option<Staging>,
+    /// Trees of recently written accounts, so a write rehashes only the chunks it changed
+    leaf_cache: LeafCache,
 }
 
 impl ChainState {
-    fn new() -> Self { Self { stubs: HashMap::new() } }
+    fn new() -> Self {
+        let genesis = sha256(b"genesis");
+        Self {
+            stubs: BTreeMap::new(),
+            subscribers: Vec::new(),
+            slot: 0,
+            recent_blockhashes: VecDeque::from([genesis]),
+            status_cache: BTreeMap::new(),
+            compute_costs: ComputeCosts::default(),
+            compute_budget: DEFAULT_COMPUTE_BUDGET,
+            programs: HashMap::new(),
+            bank_headers: Vec::new(),
+            optimistic: OptimisticState::default(),
+            hot_accounts: BTreeMap::new(),
+            rent: RentConfig::default(),
+            rent_ledger: RentLedger::default(),
+            storage: StorageState::default(),
+            staging: None,
+            leaf_cache: LeafCache::default(),
+        }
+    }
+
+    fn set_compute_budget(&mut self, budget: u64) {
+        self.compute_budget = budget;
+    }
+
+    /// Make `program` callable by transactions addressed to `program_id`
+    fn register_program(&mut self, program_id: &str, program: Box<dyn Program>) {
+        self.programs.insert(program_id.to_string(), program);
+    }
+
+    /// Register a subscriber that receives every event emitted from now on
+    fn subscribe(&mut self, subscriber: Box<dyn EventSubscriber>) {
+        self.subscribers.push(subscriber);
+    }
+
+    fn emit(&mut self, event: WitnessEvent) {
+        if let Some(staging) = self.staging.as_mut() {
+            staging.events.push(event);
+            return;
+        }
+        for s in self.subscribers.iter_mut() {
+            s.on_event(&event);
+        }
+    }
 
     fn put_stub(&mut self, pubkey: &str, stub: AccountStub) {
-        self.stubs.insert(pubkey.to_string(), stub);
+        match self.staging.as_mut() {
+            Some(staging) => { staging.stubs.insert(pubkey.to_string(), stub); }
+            None => { self.stubs.insert(pubkey.to_string(), stub); }
+        }
     }
 
     fn get_stub(&self, pubkey: &str) -> Option<&AccountStub> {
-        self.stubs.get(pubkey)
+        self.staging.as_ref()
+            .and_then(|staging| staging.stubs.get(pubkey))
+            .or_else(|| self.stubs.get(pubkey))
+    }
+
+    /// Header of a frozen slot (slots are frozen by `advance_slot`)
+    fn bank_header(&self, slot: u64) -> Option<&BankHeader> {
+        self.bank_headers.iter().rev().find(|b| b.slot == slot)
+    }
+
+    /// Single root committing to every (committed) stub in the state
+    fn state_root(&self) -> [u8;32] {
+        StateTree::build(&self.stubs).root()
+    }
+
+    /// Proof that `pubkey` with its current stub is part of `state_root()`
+    fn prove_account(&self, pubkey: &str) -> Option<AccountProof> {
+        let stub = self.stubs.get(pubkey)?;
+        StateTree::build(&self.stubs).prove(pubkey, stub)
+    }
+
+    fn is_processed(&self, blockhash: &[u8;32], tx_id: &[u8;32]) -> bool {
+        self.status_cache.get(blockhash).is_some_and(|ids| ids.contains(tx_id))
+            || self.staging.as_ref().is_some_and(|staging| staging.processed.contains(&(*blockhash, *tx_id)))
+    }
+
+    /// Start staging: until `commit` or `rollback`, stub writes, replay entries
+    /// and events are buffered instead of applied
+    fn begin_batch(&mut self) -> Result<(), WitnessError> {
+        if self.staging.is_some() {
+            return Err(WitnessError::BatchAlreadyOpen);
+        }
+        self.staging = Some(Staging::default());
+        Ok(())
+    }
+
+    /// Apply everything staged since `begin_batch` and release the held-back events
+    fn commit(&mut self) -> Result<(), WitnessError> {
+        let staging = match self.staging.take() {
+            Some(s) => s,
+            None => return Err(WitnessError::NoOpenBatch),
+        };
+        self.stubs.extend(staging.stubs);
+        for (blockhash, tx_id) in staging.processed {
+            self.status_cache.entry(blockhash).or_default().insert(tx_id);
+        }
+        for event in staging.events {
+            self.emit(event);
+        }
+        Ok(())
+    }
+
+    /// Discard everything staged since `begin_batch`
+    fn rollback(&mut self) {
+        self.staging = None;
+    }
+
+    /// Apply `txs` atomically: either every tx succeeds and all their writes land,
+    /// or the first failure rolls the whole batch back and state is untouched
+    fn process_batch(&mut self, txs: &[WitnessTx]) -> Result<Vec<TxReceipt>, WitnessError> {
+        self.begin_batch()?;
+        let mut receipts = Vec::with_capacity(txs.len());
+        for (i, tx) in txs.iter().enumerate() {
+            match self.process_tx_witness(tx) {
+                Ok(r) => receipts.push(r),
+                Err(e) => {
+                    self.rollback();
+                    let err = WitnessError::BatchRolledBack { index: i, source: Box::new(e) };
+                    self.emit(WitnessEvent::TxFailed { pubkey: tx.primary_pubkey(), reason: err.to_string() });
+                    return Err(err);
+                }
+            }
+        }
+        self.commit()?;
+        Ok(receipts)
+    }
+
+    fn epoch(&self) -> u64 {
+        self.slot / SLOTS_PER_EPOCH
+    }
+
+    /// Blockhash clients should put in new transactions
+    fn latest_blockhash(&self) -> [u8;32] {
+        *self.recent_blockhashes.back().unwrap()
+    }
+
+    /// Freeze the current slot into a bank header, then move to the next slot:
+    /// derive a new blockhash and expire the oldest one (together with the replay
+    /// entries that referenced it)
+    fn advance_slot(&mut self) {
+        let header = BankHeader {
+            slot: self.slot,
+            parent_hash: self.bank_headers.last().map(|b| b.hash()).unwrap_or([0u8; 32]),
+            state_root: self.state_root(),
+            blockhash: self.latest_blockhash(),
+        };
+        self.bank_headers.push(header);
+        self.slot += 1;
+        let mut data = self.latest_blockhash().to_vec();
+        data.extend_from_slice(&self.slot.to_le_bytes());
+        self.recent_blockhashes.push_back(sha256(&data));
+        while self.recent_blockhashes.len() > MAX_RECENT_BLOCKHASHES {
+            let expired = self.recent_blockhashes.pop_front().unwrap();
+            self.status_cache.remove(&expired);
+        }
+        self.finalize_optimistic();
+        self.expire_retrieval_challenges();
     }
 
     /// Process a transaction that carries:
-    /// - pubkey of account to act on
-    /// - full blob bytes (account bytes)
-    /// - proofs for each leaf (we simplify: provide proof for the first leaf and trust the blob matches the root if proof verifies)
+    /// - the program to invoke and its instruction data
+    /// - for each account: full blob bytes plus a proof for one leaf (we simplify: prove one leaf and trust the blob matches the root if proof verifies)
+    /// - a recent blockhash; txs with an expired blockhash or an already-seen id are rejected
     ///
-    /// If verification passes, we "apply" the transaction: compute new root and update stub.
-    fn process_tx_witness(&mut self, pubkey: &str, blob: &[u8], chunk_size: usize, proof_for_index: usize, proof: &Vec<([u8;32], bool)>) -> Result<()> {
-        // read stub
-        let stub = match self.stubs.get(pubkey) {
-            Some(s) => s.clone(),
-            None => bail!("no stub for pubkey {}", pubkey),
+    /// If every witness verifies, the program runs over the verified blobs (possibly invoking other
+    /// programs) and its writes are applied: new roots are computed and stubs updated.
+    /// Only the owning program may write an account.
+    /// Hashing and copying are metered; exceeding the compute budget fails the tx before anything is applied.
+    /// Emits `ProofVerified` + `RootUpdated` on success, `TxFailed` on any error.
+    fn process_tx_witness(&mut self, tx: &WitnessTx) -> Result<TxReceipt, WitnessError> {
+        let res = self.execute_tx(tx).and_then(|executed| self.commit_tx(tx, executed));
+        metrics::metrics().transactions.with_label_values(&[if res.is_ok() { "ok" } else { "failed" }]).inc();
+        if let Err(e) = &res {
+            tracing::info!(tx = %h(&tx.id()), pubkey = %tx.primary_pubkey(), error = %e, "tx failed");
+            self.emit(WitnessEvent::TxFailed { pubkey: tx.primary_pubkey(), reason: e.to_string() });
+        }
+        res
+    }
+
+    /// Verify and run `tx` against current state without changing anything.
+    /// Only reads `self`, so non-conflicting txs can be executed concurrently.
+    fn execute_tx(&self, tx: &WitnessTx) -> Result<ExecutedTx, WitnessError> {
+        self.execute_pre_verified(tx, None)
+    }
+
+    /// Check `tx`'s signatures and hash its proofs without reading anything a tx can
+    /// change except chunk sizes, which `execute_pre_verified` checks again
+    fn pre_verify(&self, tx: &WitnessTx) -> PreVerified {
+        let roots = tx.witnesses.iter().map(|w| {
+            let stub = self.get_stub(&w.pubkey).filter(|s| s.check_layout().is_ok())?;
+            w.proven_root(stub.chunk_size).ok().map(|root| (stub.chunk_size, root))
+        }).collect();
+        PreVerified { signers: tx.verified_signers(), roots }
+    }
+
+    /// `execute_tx`, reusing the signature and proof checks in `pre` where they still apply
+    fn execute_pre_verified(&self, tx: &WitnessTx, pre: Option<PreVerified>) -> Result<ExecutedTx, WitnessError> {
+        let _span = tracing::debug_span!("execute_tx", tx = %h(&tx.id()), program = %tx.program_id, witnesses = tx.witnesses.len()).entered();
+        // replay protection
+        if !self.recent_blockhashes.contains(&tx.recent_blockhash) {
+            return Err(WitnessError::BlockhashNotFound(tx.recent_blockhash));
+        }
+        let tx_id = tx.id();
+        if self.is_processed(&tx.recent_blockhash, &tx_id) {
+            return Err(WitnessError::AlreadyProcessed(tx_id));
+        }
+        let costs = self.compute_costs.clone();
+        let mut meter = ComputeMeter::new(self.compute_budget);
+        meter.consume(costs.ed25519_verify * tx.signatures.len() as u64, "signature verification")?;
+        let (signers, proven) = match pre {
+            Some(pre) => (pre.signers?, pre.roots),
+            None => (tx.verified_signers()?, Vec::new()),
         };
-        // chunk blob and pick the leaf chunk for which proof was provided
-        let chunks = chunk_blob(blob, chunk_size);
-        if proof_for_index >= chunks.len() {
-            bail!("proof index {} out of range ({} chunks)", proof_for_index, chunks.len());
-        }
-        let leaf_chunk = &chunks[proof_for_index];
-
-        // verify proof against stub.merkle_root
-        let ok = MerkleTree::verify_proof(leaf_chunk, proof, &stub.merkle_root);
-        if !ok {
-            bail!("proof verification failed");
-        }
-        println!("✅ Proof verified for pubkey {} leaf {} (stub root {})", pubkey, proof_for_index, h(&stub.merkle_root));
-
-        // For demo: mutate the blob in a deterministic way (toggle first byte), recompute new root
-        let mut new_blob = blob.to_vec();
-        if new_blob.is_empty() {
-            new_blob.push(1u8);
-        } else {
-            new_blob[0] = new_blob[0].wrapping_add(1);
-        }
-        let new_chunks = chunk_blob(&new_blob, chunk_size);
-        let new_tree = MerkleTree::from_chunks(&new_chunks);
-        let new_root = new_tree.root();
-        // update stub on "chain"
-        let new_stub = AccountStub::new(&stub.owner, stub.lamports, new_root);
-        self.stubs.insert(pubkey.to_string(), new_stub);
-        println!("🔁 Applied tx: updated merkle root -> {}", h(&new_root));
-        Ok(())
+
+        // verify every witness against its stub before the program sees any data
+        let started = Instant::now();
+        let mut stubs = Vec::with_capacity(tx.witnesses.len());
+        for (i, w) in tx.witnesses.iter().enumerate() {
+            meter.consume(costs.mem_copy(w.blob.len()), "blob load")?;
+            let stub = match self.get_stub(&w.pubkey) {
+                Some(s) => s.clone(),
+                None => return Err(WitnessError::UnknownAccount(w.pubkey.clone())),
+            };
+            // verify proof against stub.merkle_root
+            meter.consume(costs.verify_proof(stub.chunk_size, w.proof.len()), "proof verification")?;
+            match proven.get(i).copied().flatten() {
+                Some((chunk_size, root)) if chunk_size == stub.chunk_size => {
+                    stub.check_layout()?;
+                    w.check_root(&stub, &root)?;
+                }
+                _ => w.verify(&stub)?,
+            }
+            tracing::debug!(pubkey = %w.pubkey, leaf_index = w.leaf_index, root = %h(&stub.merkle_root), "witness verified");
+            stubs.push(stub);
+        }
+        metrics::metrics().observe_verification("tx", started.elapsed());
+
+        // run the program (and any programs it invokes) over the verified views
+        let all_pubkeys: Vec<&str> = tx.witnesses.iter().map(|w| w.pubkey.as_str()).collect();
+        let mut ctx = InvokeContext::new(&self.programs, &tx.witnesses, &stubs, costs.clone(), meter).with_signers(signers.clone());
+        ctx.invoke(&tx.program_id, &all_pubkeys, &tx.instruction_data)?;
+        let (writes, mut meter) = ctx.into_writes();
+
+        // recompute roots for every written account before applying any of them
+        let mut updates = Vec::with_capacity(writes.len());
+        for (idx, new_blob) in writes {
+            let stub = &stubs[idx];
+            if let Some(multisig) = &stub.multisig {
+                multisig.check(&signers, &tx.witnesses[idx].pubkey)?;
+            }
+            if let Some(lock) = &stub.timelock {
+                lock.check(&tx.witnesses[idx].pubkey, self.slot, self.epoch())?;
+            }
+            let chunk_size = stub.chunk_size;
+            meter.consume(costs.mem_copy(new_blob.len()), "blob copy")?;
+            // metered as a full rebuild whatever the cache saves, so CU stay deterministic
+            let new_tree = self.leaf_cache.rebuild(&stub.merkle_root, &new_blob, chunk_size);
+            meter.consume(costs.build_tree(chunk_size, new_tree.leaves().len()), "root recomputation")?;
+            let mut new_stub = stub.clone();
+            new_stub.merkle_root = new_tree.root();
+            new_stub.data_len = new_blob.len() as u64;
+            if let Some(lock) = new_stub.timelock.as_mut() {
+                lock.last_write_epoch = Some(self.epoch());
+            }
+            if let Some(dirty) = new_stub.dirty.as_mut() {
+                let old_blob = &tx.witnesses[idx].blob;
+                meter.consume(costs.mem_copy(old_blob.len().max(new_blob.len())), "dirty tracking")?;
+                dirty.record_write(old_blob, &new_blob, chunk_size, self.slot);
+            }
+            let frontier = (new_stub.data_len != stub.data_len).then(|| Frontier::build(stub.data_len, &new_blob, chunk_size, &new_tree));
+            updates.push(PendingWrite { pubkey: tx.witnesses[idx].pubkey.clone(), old_root: stub.merkle_root, stub: new_stub, chunk_size, data: new_blob, frontier, tree_bytes: new_tree.memory_usage() });
+        }
+        let verified_roots = stubs.iter().map(|s| s.merkle_root).collect();
+        tracing::debug!(compute_units = meter.consumed(), compute_left = meter.remaining(), writes = updates.len(), "tx executed");
+        Ok(ExecutedTx { tx_id, verified_roots, updates, compute_units: meter.consumed() })
+    }
+
+    /// Apply the result of `execute_tx`: update stubs, record the tx for replay protection, emit events
+    fn commit_tx(&mut self, tx: &WitnessTx, executed: ExecutedTx) -> Result<TxReceipt, WitnessError> {
+        // a duplicate may have been executed alongside this one
+        if self.is_processed(&tx.recent_blockhash, &executed.tx_id) {
+            return Err(WitnessError::AlreadyProcessed(executed.tx_id));
+        }
+        for (w, root) in tx.witnesses.iter().zip(&executed.verified_roots) {
+            self.emit(WitnessEvent::ProofVerified { pubkey: w.pubkey.clone(), leaf_index: w.leaf_index, root: *root });
+        }
+        // update stubs on "chain"
+        let mut new_roots = Vec::with_capacity(executed.updates.len());
+        let mut frontiers = Vec::new();
+        for PendingWrite { pubkey, old_root, stub, chunk_size, data, frontier, .. } in executed.updates {
+            let new = stub.merkle_root;
+            tracing::info!(pubkey = %pubkey, slot = self.slot, old = %h(&old_root), new = %h(&new), data_len = stub.data_len, "root updated");
+            self.put_stub(&pubkey, stub);
+            self.emit(WitnessEvent::RootUpdated { pubkey: pubkey.clone(), slot: self.slot, old: old_root, new });
+            self.emit(WitnessEvent::AccountDataWritten { pubkey: pubkey.clone(), slot: self.slot, root: new, chunk_size, data });
+            if let Some(f) = frontier {
+                frontiers.push((pubkey.clone(), f));
+            }
+            new_roots.push((pubkey, new));
+        }
+        match self.staging.as_mut() {
+            Some(staging) => staging.processed.push((tx.recent_blockhash, executed.tx_id)),
+            None => { self.status_cache.entry(tx.recent_blockhash).or_default().insert(executed.tx_id); }
+        }
+        Ok(TxReceipt { compute_units: executed.compute_units, new_roots, frontiers })
     }
 }
 
+/// `simulate --genesis <file>`: load a genesis config and print the resulting state
+fn run_genesis(path: &str, metrics_out: Option<&std::path::Path>) -> Result<()> {
+    let cfg = GenesisConfig::load(std::path::Path::new(path))?;
+    let chain = ChainState::from_genesis(&cfg)?;
+    for acc in &cfg.accounts {
+        match chain.get_stub(&acc.pubkey) {
+            Some(stub) => println!("{}: compressed, {} bytes, root {}", acc.pubkey, stub.data_len, h(&stub.merkle_root)),
+            None => println!("{}: hot", acc.pubkey),
+        }
+    }
+    println!("State root: {}", h(&chain.state_root()));
+    let mem = chain.memory_usage();
+    println!("Memory: {} bytes ({} stubs, {} hot accounts, {} cached blobs, {} cached trees)",
+        mem.total(), mem.stubs, mem.hot_accounts, mem.cached_blobs, mem.cached_trees);
+    if let Some(out) = metrics_out {
+        metrics::metrics().write_textfile(out, &chain)?;
+    }
+    Ok(())
+}
+
+/// Chain from a genesis file with an archive holding its compressed blobs and an
+/// index locating them at `location`, both subscribed, for the network servers
+#[cfg(any(feature = "http", feature = "grpc"))]
+fn load_served_genesis(path: &str, location: &str, mode: pruned::TreeMode) -> Result<(ChainState, ArchiveNode, Indexer)> {
+    let cfg = GenesisConfig::load(std::path::Path::new(path))?;
+    let mut chain = ChainState::from_genesis(&cfg)?;
+    let archive = ArchiveNode::new();
+    for (acc, blob) in cfg.blobs()?.into_iter().filter(|(acc, _)| !acc.hot) {
+        archive.ingest(&acc.pubkey, chain.slot, acc.chunk_size, &blob)?;
+    }
+    // the example genesis accounts belong to the demo program
+    chain.register_program("WitnessProgram1", Box::new(DemoProgram));
+    let indexer = Indexer::new().with_location(location).with_tree_mode(mode);
+    chain.subscribe(Box::new(archive.clone()));
+    chain.subscribe(Box::new(indexer.clone()));
+    indexer.sync(&chain);
+    Ok((chain, archive, indexer))
+}
+
+/// Load a genesis file and serve proofs for its compressed accounts over HTTP
+#[cfg(feature = "http")]
+fn run_proof_server(path: &str, addr: &str, mode: pruned::TreeMode) -> Result<()> {
+    let (mut chain, archive, indexer) = load_served_genesis(path, &format!("http://{}", addr), mode)?;
+    let pubsub = pubsub::PubSub::new();
+    chain.subscribe(Box::new(pubsub.clone()));
+    println!("Serving proofs, JSON-RPC and subscriptions (ws://{}/ws) for {} account(s) on http://{}", addr, indexer.len(), addr);
+    let rpc = rpc::RpcServer::new(chain, archive.clone(), indexer.clone()).with_pubsub(pubsub.clone());
+    http_server::ProofService::new(indexer, archive).with_rpc(rpc).with_pubsub(pubsub).serve(addr)
+}
+
+/// Load a genesis file and serve the `witness.v1.Witness` gRPC service
+#[cfg(feature = "grpc")]
+fn run_grpc_server(path: &str, addr: &str, mode: pruned::TreeMode) -> Result<()> {
+    let (chain, archive, indexer) = load_served_genesis(path, &format!("grpc://{}", addr), mode)?;
+    println!("Serving witness.v1.Witness for {} account(s) on {}", indexer.len(), addr);
+    grpc_server::GrpcService::new(chain, archive, indexer).serve(addr)
+}
+
 fn main() -> Result<()> {
+    cli::run()
+}
+
+/// A 64-byte chunk can hold two leaf hashes, and then hashes to their parent: with the
+/// first step dropped, the proof still climbs to the root from the forged chunk. It must
+/// be rejected alone, and roll back the whole batch it lands in.
+fn forged_chunk_demo() -> Result<()> {
+    let pair_blob: Vec<u8> = (0..=255).collect();
+    let pair_tree = MerkleTree::from_chunks(&chunk_blob(&pair_blob, 64));
+    let mut forged = [pair_tree.leaves()[0], pair_tree.leaves()[1]].concat();
+    forged.resize(pair_blob.len(), 0);
+    let mut pair_chain = ChainState::new();
+    pair_chain.register_program(DEMO_PROGRAM_ID, Box::new(DemoProgram));
+    pair_chain.put_stub("Pairs1", AccountStub::new(DEMO_PROGRAM_ID, 1_000, pair_tree.root(), pair_blob.len() as u64, 64));
+    let short_tx = WitnessTx {
+        program_id: DEMO_PROGRAM_ID.to_string(),
+        instruction_data: Vec::new(),
+        witnesses: vec![AccountWitness { pubkey: "Pairs1".to_string(), blob: forged, leaf_index: 0, proof: pair_tree.gen_proof(0)[1..].to_vec(), is_writable: true }],
+        recent_blockhash: pair_chain.latest_blockhash(),
+        priority_fee: 0,
+        signatures: Vec::new(),
+    };
+    match pair_chain.simulate_tx(&short_tx) {
+        Ok(_) => bail!("an interior node was accepted as a chunk"),
+        Err(e) => println!("Interior node passed off as a chunk: {}", e),
+    }
+    // a batch lands whole or not at all: the forged tx undoes the honest write before it
+    let mut honest_tx = short_tx.clone();
+    honest_tx.witnesses[0].blob = pair_blob.clone();
+    honest_tx.witnesses[0].proof = pair_tree.gen_proof(0);
+    match pair_chain.process_batch(&[honest_tx.clone(), short_tx]) {
+        Err(WitnessError::BatchRolledBack { index: 1, .. }) => {}
+        other => bail!("batch with a forged tx was not rolled back at it: {:?}", other.map(|r| r.len())),
+    }
+    println!("Batch rolled back at the forged tx, Pairs1 root unchanged: {}", pair_chain.get_stub("Pairs1").unwrap().merkle_root == pair_tree.root());
+    let batch = pair_chain.process_batch(&[honest_tx])?;
+    println!("Batch without it committed: Pairs1 root {}", h(&batch[0].new_roots[0].1));
+    Ok(())
+}
+
+/// `simulate`: walk through every feature on a demo chain, with `cfg`'s chunk size and blob store
+fn run_demo(cfg: &config::Config, metrics_out: Option<&std::path::Path>) -> Result<()> {
     println!("=== Account Witness Prototype ===");
 
     // Example account blob (metadata or large account data)
     let account_blob = b"Example account blob: this could be an NFT metadata JSON or game state. It's larger than a chunk so we create multiple leaves.".to_vec();
-    let chunk_size = 32;
+    let chunk_size = cfg.chunk_size;
 
     // Build merkle tree representing the on-chain commitment
     let chunks = chunk_blob(&account_blob, chunk_size);
     let tree = MerkleTree::from_chunks(&chunks);
     let root = tree.root();
     println!("Initial merkle root: {}", h(&root));
-    println!("Leaf count (after padding to power of two): {}", tree.layers[0].len());
+    println!("Leaf count (after padding to power of two): {}", tree.leaves().len());
+    let snapshot = std::env::temp_dir().join("account-witness-snapshot.bin");
+    std::fs::write(&snapshot, &account_blob)?;
+    println!("Root from a memory-mapped snapshot matches: {}", MerkleTree::from_mmap(&snapshot, chunk_size)?.root() == root);
 
     // Create an on-chain stub for pubkey "Acct1"
     let mut chain = ChainState::new();
-    let stub = AccountStub::new("owner_pubkey_1", 1_000, root);
+    chain.subscribe(Box::new(StdoutSubscriber));
+    let log = EventLog::new();
+    chain.subscribe(Box::new(log.clone()));
+    let program_id = DEMO_PROGRAM_ID;
+    chain.register_program(program_id, Box::new(DemoProgram));
+    let stub = AccountStub::new(program_id, 1_000, root, account_blob.len() as u64, chunk_size);
     chain.put_stub("Acct1", stub);
     println!("Stored stub for Acct1.");
 
+    // Archive node keeps every blob version so the data stays available off-chain,
+    // here as content-addressed files behind an in-memory LRU cache, with later
+    // versions stored as diffs against the one before
+    let blob_dir = cfg.blob_store_dir().unwrap_or_else(|| std::env::temp_dir().join("account-witness-blobs"));
+    let blob_cache = Arc::new(CachedBlobStore::new(FsBlobStore::new(&blob_dir)?, CacheConfig::default()));
+    let archive = ArchiveNode::with_store(blob_cache.clone())
+        .retention(RetentionPolicy { keep_last: Some(2), keep_epochs: None, history_epochs: None })
+        .delta_encoding(8);
+    archive.ingest("Acct1", 0, chunk_size, &account_blob)?;
+    chain.subscribe(Box::new(archive.clone()));
+    // and an index tells clients where each account's data lives
+    let indexer = Indexer::new().with_location(&format!("fs:{}", blob_dir.display()));
+    chain.subscribe(Box::new(indexer.clone()));
+
     // Simulate client constructing a tx:
     // choose a leaf index (0) and get proof from tree
     let leaf_index = 0usize;
@@ -226,11 +1019,328 @@ fn main() -> Result<()> {
 
     // Now process a tx on-chain that includes: full blob + proof for leaf_index
     println!("\nProcessing transaction that carries full blob + proof...");
-    chain.process_tx_witness("Acct1", &account_blob, chunk_size, leaf_index, &proof)?;
+    let tx = WitnessTx {
+        program_id: program_id.to_string(),
+        instruction_data: Vec::new(),
+        witnesses: vec![AccountWitness {
+            pubkey: "Acct1".to_string(),
+            blob: account_blob.clone(),
+            leaf_index,
+            proof,
+            is_writable: true,
+        }],
+        recent_blockhash: chain.latest_blockhash(),
+        priority_fee: 0,
+        signatures: Vec::new(),
+    };
+    let sim = chain.simulate_tx(&tx)?;
+    println!("Simulated: {} CU, {} bytes written to {} under new root {}, post-state root {}, {} bytes of tree",
+        sim.compute_units, sim.writes[0].1.len(), sim.writes[0].0, h(&sim.new_roots[0].1), h(&sim.state_root_after), sim.tree_bytes);
+    let receipt = chain.process_tx_witness(&tx)?;
+    println!("Compute units consumed: {} / {}", receipt.compute_units, DEFAULT_COMPUTE_BUDGET);
+
+    // Replaying the exact same tx must be rejected
+    println!("\nReplaying the same transaction...");
+    if chain.process_tx_witness(&tx).is_ok() {
+        bail!("replayed transaction was accepted");
+    }
+
+    forged_chunk_demo()?;
+
+    // Same update routed through a second program via CPI
+    println!("\nProcessing transaction through a proxy program (CPI)...");
+    let proxy_id = "MarketProgram1";
+    chain.register_program(proxy_id, Box::new(ProxyProgram { target: program_id.to_string() }));
+    // the client no longer holds the current blob; fetch a witness from the archive
+    let current_root = chain.get_stub("Acct1").unwrap().merkle_root;
+    let cpi_tx = WitnessTx {
+        program_id: proxy_id.to_string(),
+        instruction_data: Vec::new(),
+        witnesses: vec![archive.get_witness("Acct1", &current_root, leaf_index).unwrap()],
+        recent_blockhash: chain.latest_blockhash(),
+        priority_fee: 0,
+        signatures: Vec::new(),
+    };
+    let mut mempool = Mempool::default();
+    mempool.submit(cpi_tx)?;
+    let block = chain.produce_block_parallel(&mut mempool, 64);
+    println!("Block for slot {}: {} applied, {} failed in {} batch(es), mempool drained: {}, verify {:?} ({:.0} witnesses/s) / execute {:?} ({:.0} tx/s) / commit {:?}",
+        block.slot, block.receipts.len(), block.failed, block.batches, mempool.is_empty(), block.verify_time, block.verify_throughput(), block.execute_time, block.execute_tps(), block.commit_time);
 
     // Show updated stub
     let new_stub = chain.get_stub("Acct1").unwrap();
     println!("Final stub merkle root stored on chain: {}", h(&new_stub.merkle_root));
+    println!("Events recorded: {}", log.events().len());
+    archive::demo(&archive, "Acct1", chain.slot, &blob_dir)?;
+    indexer::demo(&chain, &indexer, &archive, "Acct1")?;
+    pruned::demo(chunk_size);
+    let cache = blob_cache.stats();
+    println!("Blob cache: {} hits, {} misses, {} entries ({} bytes)", cache.hits, cache.misses, cache.entries, cache.bytes);
+    let mem = chain.memory_usage();
+    println!("Memory: {} bytes ({} stubs, {} cached blobs, {} cached trees, {} status cache); Acct1's first tree held {} bytes",
+        mem.total(), mem.stubs, mem.cached_blobs, mem.cached_trees, mem.status_cache, tree.memory_usage());
+    state::demo(&chain, "Acct1");
+    light_client::demo(&mut chain, &archive, "Acct1")?;
+    optimistic::demo(&mut chain, &archive, "Acct1")?;
+    rent::demo(chunk_size)?;
+    realloc::demo(chunk_size)?;
+    append::demo()?;
+    multisig::demo(chunk_size)?;
+    pubkey::demo(chunk_size)?;
+    view::demo(chunk_size)?;
+    schema::demo(chunk_size)?;
+    json_proof::demo(chunk_size)?;
+    timelock::demo(chunk_size)?;
+    storage::demo(&mut chain, &archive, "Acct1")?;
+    let acct1_root = chain.get_stub("Acct1").unwrap().merkle_root;
+    let acct1_blob = archive.get_blob("Acct1", &acct1_root).unwrap().data;
+    erasure::demo("Acct1", &acct1_blob, chunk_size, &acct1_root)?;
+    retrieval::demo(&chain, &archive, "Acct1")?;
+    das::demo()?;
+    replication::demo("Acct1", &acct1_root, chunk_size, &acct1_blob)?;
+    migration::demo(&mut chain, &archive, "Acct1", chunk_size)?;
+
+    println!("\n=== Compressed NFT collection ===");
+    cnft::demo()?;
+
+    println!("\n=== Compressed token balances ===");
+    token::demo()?;
+
+    println!("\n=== Rollup batch ===");
+    rollup::demo()?;
 
+    println!("\n=== Corruption bisection ===");
+    diagnose::demo()?;
+
+    println!("\n=== Game checkpoints ===");
+    game::demo()?;
+
+    println!("\n=== JSON-RPC ===");
+    rpc::demo()?;
+
+    compute::demo(chunk_size);
+    packer::demo()?;
+    network::demo(&account_blob, chunk_size)?;
+    workload::demo(chunk_size)?;
+
+    if let Some(out) = metrics_out {
+        metrics::metrics().write_textfile(out, &chain)?;
+        println!("\nMetrics written to {}", out.display());
+    }
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ed25519_dalek::SigningKey;
+
+    const PROGRAM: &str = "Prog1";
+    const CHUNK_SIZE: usize = 16;
+
+    fn chain() -> ChainState {
+        let mut chain = ChainState::new();
+        chain.register_program(PROGRAM, Box::new(DemoProgram));
+        chain
+    }
+
+    fn blob(seed: u8) -> Vec<u8> {
+        (0..60u8).map(|i| i.wrapping_mul(31) ^ seed).collect()
+    }
+
+    /// Store a stub for `blob(seed)` at `pubkey`, passing it through `edit` first
+    fn account(chain: &mut ChainState, pubkey: &str, seed: u8, edit: impl FnOnce(&mut AccountStub)) {
+        let data = blob(seed);
+        let tree = MerkleTree::from_chunks(&chunk_blob(&data, CHUNK_SIZE));
+        let mut stub = AccountStub::new(PROGRAM, 1_000, tree.root(), data.len() as u64, CHUNK_SIZE);
+        edit(&mut stub);
+        chain.put_stub(pubkey, stub);
+    }
+
+    /// A write to `pubkey` proving its leaf 1, against the latest blockhash
+    fn write_tx(chain: &ChainState, pubkey: &str, seed: u8) -> WitnessTx {
+        let data = blob(seed);
+        let proof = MerkleTree::from_chunks(&chunk_blob(&data, CHUNK_SIZE)).gen_proof(1);
+        WitnessTx {
+            program_id: PROGRAM.to_string(),
+            instruction_data: Vec::new(),
+            witnesses: vec![AccountWitness { pubkey: pubkey.to_string(), blob: data, leaf_index: 1, proof, is_writable: true }],
+            recent_blockhash: chain.latest_blockhash(),
+            priority_fee: 0,
+            signatures: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn replayed_tx_is_rejected() {
+        let mut chain = chain();
+        account(&mut chain, "A", 0, |_| {});
+        let tx = write_tx(&chain, "A", 0);
+        chain.process_tx_witness(&tx).unwrap();
+        assert!(matches!(chain.process_tx_witness(&tx), Err(WitnessError::AlreadyProcessed(id)) if id == tx.id()));
+    }
+
+    #[test]
+    fn tx_id_separates_every_field() {
+        let chain = chain();
+        let mut tx = write_tx(&chain, "A", 0);
+        tx.witnesses.push(AccountWitness { pubkey: "B".to_string(), blob: Vec::new(), leaf_index: 0, proof: Vec::new(), is_writable: false });
+        // unprefixed, a last proof step of A's reads the same as the start of B's pubkey
+        let mut moved = tx.clone();
+        tx.witnesses[0].proof.push(([b'a'; 32], false));
+        moved.witnesses[1].pubkey = format!("{}\0B", "a".repeat(32));
+        assert_ne!(tx.id(), moved.id());
+    }
+
+    #[test]
+    fn unknown_and_expired_blockhashes_are_rejected() {
+        let mut chain = chain();
+        account(&mut chain, "A", 0, |_| {});
+        account(&mut chain, "B", 1, |_| {});
+        let mut unknown = write_tx(&chain, "A", 0);
+        unknown.recent_blockhash = [9; 32];
+        assert!(matches!(chain.process_tx_witness(&unknown), Err(WitnessError::BlockhashNotFound(_))));
+        let (a, b) = (write_tx(&chain, "A", 0), write_tx(&chain, "B", 1));
+        for _ in 1..MAX_RECENT_BLOCKHASHES {
+            chain.advance_slot();
+        }
+        chain.process_tx_witness(&a).unwrap();
+        chain.advance_slot();
+        assert!(matches!(chain.process_tx_witness(&b), Err(WitnessError::BlockhashNotFound(h)) if h == b.recent_blockhash));
+        // with its blockhash gone, so is the status cache entry of the tx that used it
+        assert!(!chain.is_processed(&a.recent_blockhash, &a.id()));
+    }
+
+    #[test]
+    fn failed_batch_leaves_state_untouched() {
+        let mut chain = chain();
+        account(&mut chain, "A", 0, |_| {});
+        account(&mut chain, "B", 1, |_| {});
+        let before = chain.state_root();
+        let good = write_tx(&chain, "A", 0);
+        let mut bad = write_tx(&chain, "B", 1);
+        bad.witnesses[0].blob[CHUNK_SIZE] ^= 1;
+        match chain.process_batch(&[good.clone(), bad]) {
+            Err(WitnessError::BatchRolledBack { index: 1, source }) => assert!(matches!(*source, WitnessError::ProofVerificationFailed { .. })),
+            other => panic!("expected a rollback at tx 1, got {:?}", other.map(|r| r.len())),
+        }
+        assert_eq!(chain.state_root(), before);
+        // the rolled-back tx was never recorded, so it still applies on its own
+        chain.process_tx_witness(&good).unwrap();
+    }
+
+    #[test]
+    fn batch_commits_every_write() {
+        let mut chain = chain();
+        account(&mut chain, "A", 0, |_| {});
+        account(&mut chain, "B", 1, |_| {});
+        let (a, b) = (write_tx(&chain, "A", 0), write_tx(&chain, "B", 1));
+        let receipts = chain.process_batch(&[a.clone(), b]).unwrap();
+        assert_eq!(receipts.len(), 2);
+        for (pubkey, receipt) in ["A", "B"].iter().zip(&receipts) {
+            assert_eq!(chain.get_stub(pubkey).unwrap().merkle_root, receipt.new_roots[0].1);
+        }
+        assert!(matches!(chain.process_tx_witness(&a), Err(WitnessError::AlreadyProcessed(_))));
+    }
+
+    #[test]
+    fn batches_open_and_close_in_pairs() {
+        let mut chain = chain();
+        assert!(matches!(chain.commit(), Err(WitnessError::NoOpenBatch)));
+        chain.begin_batch().unwrap();
+        assert!(matches!(chain.begin_batch(), Err(WitnessError::BatchAlreadyOpen)));
+        account(&mut chain, "A", 0, |_| {});
+        assert!(chain.get_stub("A").is_some());
+        chain.rollback();
+        assert!(chain.get_stub("A").is_none());
+        chain.begin_batch().unwrap();
+        account(&mut chain, "A", 0, |_| {});
+        chain.commit().unwrap();
+        assert!(chain.get_stub("A").is_some());
+    }
+
+    #[test]
+    fn multisig_stub_needs_its_threshold() {
+        let council: Vec<SigningKey> = (0..3u8).map(|i| SigningKey::from_bytes(&sha256(&[b'c', i]))).collect();
+        let members = council.iter().map(|k| k.verifying_key().to_bytes()).collect();
+        let mut chain = chain();
+        account(&mut chain, "Dao", 0, |stub| stub.multisig = Some(Multisig::new(2, members).unwrap()));
+        let mut tx = write_tx(&chain, "Dao", 0);
+        assert!(matches!(chain.process_tx_witness(&tx), Err(WitnessError::MultisigThreshold { approvals: 0, .. })));
+        tx.sign(&council[0]);
+        assert!(matches!(chain.process_tx_witness(&tx), Err(WitnessError::MultisigThreshold { approvals: 1, .. })));
+        // a signature over anything but this tx's id fails the whole tx
+        let mut forged = tx.clone();
+        forged.signatures.push(multisig::sign_message(&council[1], &[0; 32]));
+        assert!(matches!(chain.process_tx_witness(&forged), Err(WitnessError::BadSignature(_))));
+        tx.sign(&council[1]);
+        chain.process_tx_witness(&tx).unwrap();
+    }
+
+    #[test]
+    fn timelocked_stub_waits_for_its_slot() {
+        let mut chain = chain();
+        account(&mut chain, "Vest", 0, |stub| stub.timelock = Some(TimeLock::until(3)));
+        assert!(matches!(chain.process_tx_witness(&write_tx(&chain, "Vest", 0)), Err(WitnessError::Locked { unlock_slot: 3, slot: 0, .. })));
+        for _ in 0..3 {
+            chain.advance_slot();
+        }
+        chain.process_tx_witness(&write_tx(&chain, "Vest", 0)).unwrap();
+    }
+
+    #[test]
+    fn once_per_epoch_stub_takes_one_write_per_epoch() {
+        let mut chain = chain();
+        account(&mut chain, "Escrow", 0, |stub| stub.timelock = Some(TimeLock::default().once_per_epoch()));
+        let receipt = chain.process_tx_witness(&write_tx(&chain, "Escrow", 0)).unwrap();
+        assert_eq!(chain.get_stub("Escrow").unwrap().timelock.as_ref().unwrap().last_write_epoch, Some(0));
+        // the demo program bumps the first byte
+        let mut written = blob(0);
+        written[0] = written[0].wrapping_add(1);
+        let tree = MerkleTree::from_chunks(&chunk_blob(&written, CHUNK_SIZE));
+        assert_eq!(tree.root(), receipt.new_roots[0].1);
+        let mut again = write_tx(&chain, "Escrow", 0);
+        again.witnesses[0].blob = written;
+        again.witnesses[0].proof = tree.gen_proof(1);
+        assert!(matches!(chain.process_tx_witness(&again), Err(WitnessError::AlreadyWrittenInEpoch { epoch: 0, .. })));
+        for _ in 0..SLOTS_PER_EPOCH {
+            chain.advance_slot();
+        }
+        again.recent_blockhash = chain.latest_blockhash();
+        chain.process_tx_witness(&again).unwrap();
+    }
+
+    #[test]
+    fn derived_stub_is_written_only_under_its_own_seeds() {
+        let mut chain = chain();
+        let data = blob(0);
+        let root = MerkleTree::from_chunks(&chunk_blob(&data, CHUNK_SIZE)).root();
+        let vault = chain.create_pda_account(PROGRAM, &[b"vault"], AccountStub::new("Other", 0, root, data.len() as u64, CHUNK_SIZE)).unwrap();
+        assert_eq!(chain.get_stub(&vault).unwrap().owner, PROGRAM);
+        assert!(chain.create_pda_account(PROGRAM, &[b"vault"], AccountStub::new(PROGRAM, 0, root, 0, CHUNK_SIZE)).is_err());
+        chain.process_tx_witness(&write_tx(&chain, &vault, 0)).unwrap();
+        // seeds that do not lead to the address make the stub unwritable
+        account(&mut chain, "Squatter", 0, |stub| stub.pda = Some(PdaSeeds { seeds: vec![b"vault".to_vec()], bump: 255 }));
+        assert!(matches!(chain.process_tx_witness(&write_tx(&chain, "Squatter", 0)), Err(WitnessError::NotDerived { .. })));
+    }
+
+    #[test]
+    fn proofs_must_have_the_stub_tree_shape() {
+        let mut chain = chain();
+        account(&mut chain, "A", 0, |_| {});
+        let stub = chain.get_stub("A").unwrap().clone();
+        assert_eq!((stub.chunk_count(), stub.depth()), (4, 2));
+        let proof = write_tx(&chain, "A", 0).witnesses[0].proof.clone();
+        stub.check_proof_shape("A", 1, &proof).unwrap();
+        assert!(matches!(stub.check_proof_shape("A", 1, &proof[1..]), Err(WitnessError::ProofLength { expected: 2, actual: 1, .. })));
+        assert!(matches!(stub.check_proof_shape("A", 2, &proof), Err(WitnessError::ProofIndexMismatch { claimed: 2, proven: 1 })));
+        assert!(matches!(stub.check_proof_shape("A", 4, &proof), Err(WitnessError::ProofIndexOutOfRange { index: 4, chunks: 4 })));
+
+        let mut long = write_tx(&chain, "A", 0);
+        long.witnesses[0].proof.push(([0; 32], false));
+        assert!(matches!(chain.process_tx_witness(&long), Err(WitnessError::ProofLength { expected: 2, actual: 3, .. })));
+        let mut other_leaf = write_tx(&chain, "A", 0);
+        other_leaf.witnesses[0].leaf_index = 0;
+        assert!(matches!(chain.process_tx_witness(&other_leaf), Err(WitnessError::ProofIndexMismatch { claimed: 0, proven: 1 })));
+    }
+}
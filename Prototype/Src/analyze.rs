@@ -0,0 +1,300 @@
+//! `analyze`: what witnessing a directory of real account dumps would cost, across a
+//! grid of chunk sizes and arities. For each account and layout it works out the chunk
+//! count, proof depth and the bytes a one-chunk witness carries, and for each account
+//! the on-chain bytes and rent a stub saves over holding the data.
+//!
+//! A dump is either the raw account data (`solana account <pubkey> -o <file>`) or the
+//! JSON `solana account --output json` and `getAccountInfo` print, data encoded as
+//! base64, base64+zstd or base58. Trees wider than binary are not built here, only
+//! sized: a level of an `arity`-ary tree adds `arity - 1` sibling hashes and a position
+//! byte to a proof, which for binary trees is `witness_core::STEP_LEN`.
+
+use std::fmt::Write as _;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result, bail};
+use base64::Engine;
+use serde::Deserialize;
+
+use crate::packer::MAX_PACKED_CHUNK;
+use crate::rent::RentConfig;
+use crate::AccountStub;
+
+/// Chunk sizes analyzed unless others are asked for: powers of two from 32 to 4096
+pub const DEFAULT_CHUNK_SIZES: &[usize] = &[32, 64, 128, 256, 512, 1024, 2048, 4096];
+/// Arities analyzed unless others are asked for
+pub const DEFAULT_ARITIES: &[u8] = &[2, 4, 8, 16];
+/// Owner assumed for raw dumps, which do not record one
+const SYSTEM_PROGRAM: &str = "11111111111111111111111111111111";
+
+/// One account of the directory
+#[derive(Debug, Clone)]
+pub struct Dump {
+    /// The pubkey a JSON dump names, else the file name
+    pub name: String,
+    pub owner: String,
+    pub data_len: u64,
+}
+
+#[derive(Deserialize)]
+struct JsonDump {
+    pubkey: Option<String>,
+    account: Option<JsonAccount>,
+    /// `getAccountInfo` responses put the account under `result.value`
+    result: Option<JsonResult>,
+}
+
+#[derive(Deserialize)]
+struct JsonResult {
+    value: JsonAccount,
+}
+
+#[derive(Deserialize)]
+struct JsonAccount {
+    owner: String,
+    /// `[encoded data, encoding]`
+    data: (String, String),
+}
+
+fn decode_data(data: &str, encoding: &str) -> Result<Vec<u8>> {
+    let b64 = || base64::engine::general_purpose::STANDARD.decode(data).context("data is not base64");
+    match encoding {
+        "base64" => b64(),
+        "base64+zstd" => zstd::stream::decode_all(b64()?.as_slice()).context("data is not zstd"),
+        "base58" => bs58::decode(data).into_vec().context("data is not base58"),
+        other => bail!("unsupported data encoding {:?}", other),
+    }
+}
+
+/// Name, owner and data of a JSON dump
+fn load_json(path: &Path, name: String) -> Result<(String, String, Vec<u8>)> {
+    let dump: JsonDump = serde_json::from_slice(&std::fs::read(path)?).context("not an account dump")?;
+    let account = match (dump.account, dump.result) {
+        (Some(account), _) => account,
+        (None, Some(result)) => result.value,
+        (None, None) => bail!("no account in the dump"),
+    };
+    let data = decode_data(&account.data.0, &account.data.1)?;
+    Ok((dump.pubkey.unwrap_or(name), account.owner, data))
+}
+
+fn is_json(path: &Path) -> bool {
+    path.extension().is_some_and(|ext| ext == "json")
+}
+
+/// Files in `dir` (not descending into subdirectories) with their names, in order
+fn dump_paths(dir: &Path) -> Result<Vec<(String, PathBuf)>> {
+    let mut paths: Vec<PathBuf> = std::fs::read_dir(dir).with_context(|| format!("reading {}", dir.display()))?
+        .map(|entry| Ok(entry?.path()))
+        .collect::<Result<Vec<_>>>()?;
+    paths.retain(|p| p.is_file());
+    paths.sort();
+    if paths.is_empty() {
+        bail!("no account dumps in {}", dir.display());
+    }
+    Ok(paths.into_iter().map(|p| (p.file_name().unwrap().to_string_lossy().into_owned(), p)).collect())
+}
+
+/// Every dump in `dir`, ordered by name
+pub fn load_dir(dir: &Path) -> Result<Vec<Dump>> {
+    let mut dumps = Vec::new();
+    for (name, path) in dump_paths(dir)? {
+        let dump = match is_json(&path) {
+            true => {
+                let (name, owner, data) = load_json(&path, name).with_context(|| format!("{}", path.display()))?;
+                Dump { name, owner, data_len: data.len() as u64 }
+            }
+            // raw data: only its length matters
+            false => Dump { name, owner: SYSTEM_PROGRAM.to_string(), data_len: std::fs::metadata(&path)?.len() },
+        };
+        dumps.push(dump);
+    }
+    dumps.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(dumps)
+}
+
+/// Every dump in `dir` with its data, ordered by name
+pub fn load_data(dir: &Path) -> Result<Vec<(String, Vec<u8>)>> {
+    let mut accounts = Vec::new();
+    for (name, path) in dump_paths(dir)? {
+        accounts.push(match is_json(&path) {
+            true => load_json(&path, name).map(|(name, _, data)| (name, data)).with_context(|| format!("{}", path.display()))?,
+            false => (name, std::fs::read(&path).with_context(|| format!("reading {}", path.display()))?),
+        });
+    }
+    accounts.sort_by(|a, b| a.0.cmp(&b.0));
+    Ok(accounts)
+}
+
+/// A tree of `data_len` bytes in `chunk_size`-byte leaves, `arity` children a node
+#[derive(Debug, Clone, Copy)]
+pub struct Shape {
+    pub chunk_size: usize,
+    pub arity: u8,
+    pub chunks: u64,
+    /// Levels above the leaves; the leaf layer is padded to a power of `arity`
+    pub depth: usize,
+    pub proof_bytes: u64,
+    /// A chunk and its proof
+    pub witness_bytes: u64,
+}
+
+impl Shape {
+    pub fn new(data_len: u64, chunk_size: usize, arity: u8) -> Self {
+        let chunks = data_len.div_ceil(chunk_size as u64).max(1);
+        let mut depth = 0;
+        let mut width = 1u128;
+        while width < chunks as u128 {
+            width *= arity as u128;
+            depth += 1;
+        }
+        let proof_bytes = depth as u64 * ((arity as u64 - 1) * 32 + 1);
+        Self { chunk_size, arity, chunks, depth, proof_bytes, witness_bytes: chunk_size as u64 + proof_bytes }
+    }
+}
+
+/// The on-chain side of one account
+#[derive(Debug, Clone)]
+pub struct Account {
+    pub dump: Dump,
+    /// Encoded stub bytes that replace the data
+    pub stub_bytes: u64,
+    /// Data bytes minus stub bytes; negative for accounts smaller than a stub
+    pub saved_bytes: i64,
+    /// Rent-exempt minimum of the data minus that of the stub
+    pub saved_rent: i64,
+    /// One per grid point, chunk sizes outer
+    pub shapes: Vec<Shape>,
+}
+
+/// The grid over every dump
+#[derive(Debug, Clone)]
+pub struct Report {
+    pub chunk_sizes: Vec<usize>,
+    pub arities: Vec<u8>,
+    pub accounts: Vec<Account>,
+}
+
+/// Totals over every account at one grid point
+#[derive(Debug, Clone, Copy)]
+pub struct Aggregate {
+    pub chunk_size: usize,
+    pub arity: u8,
+    pub chunks: u64,
+    pub max_depth: usize,
+    pub mean_witness: f64,
+    pub max_witness: u64,
+    pub total_witness: u64,
+}
+
+impl Report {
+    pub fn new(dumps: Vec<Dump>, chunk_sizes: &[usize], arities: &[u8]) -> Result<Self> {
+        if chunk_sizes.is_empty() || arities.is_empty() {
+            bail!("no chunk sizes or no arities to analyze");
+        }
+        if let Some(&size) = chunk_sizes.iter().find(|&&s| s == 0) {
+            bail!("chunk size {} must be positive", size);
+        }
+        if let Some(&arity) = arities.iter().find(|&&a| a < 2) {
+            bail!("arity {} is below 2", arity);
+        }
+        let rent = RentConfig::default();
+        let accounts = dumps.into_iter().map(|dump| {
+            // the stub's encoding is fixed width but for the owner, so any layout sizes it
+            let stub_bytes = AccountStub::new(&dump.owner, 0, [0; 32], dump.data_len, chunk_sizes[0]).encode().len() as u64;
+            let shapes = chunk_sizes.iter()
+                .flat_map(|&size| arities.iter().map(move |&arity| Shape::new(dump.data_len, size, arity)))
+                .collect();
+            Account {
+                saved_bytes: dump.data_len as i64 - stub_bytes as i64,
+                saved_rent: rent.minimum_balance(dump.data_len) as i64 - rent.minimum_balance(stub_bytes) as i64,
+                stub_bytes,
+                shapes,
+                dump,
+            }
+        }).collect();
+        Ok(Self { chunk_sizes: chunk_sizes.to_vec(), arities: arities.to_vec(), accounts })
+    }
+
+    /// One entry per grid point, in the order of `Account::shapes`
+    pub fn aggregates(&self) -> Vec<Aggregate> {
+        let grid = self.chunk_sizes.iter().flat_map(|&size| self.arities.iter().map(move |&arity| (size, arity)));
+        grid.enumerate().map(|(i, (chunk_size, arity))| {
+            let shapes = self.accounts.iter().map(|a| a.shapes[i]);
+            let total_witness = shapes.clone().map(|s| s.witness_bytes).sum();
+            Aggregate {
+                chunk_size,
+                arity,
+                chunks: shapes.clone().map(|s| s.chunks).sum(),
+                max_depth: shapes.clone().map(|s| s.depth).max().unwrap_or(0),
+                mean_witness: total_witness as f64 / self.accounts.len() as f64,
+                max_witness: shapes.map(|s| s.witness_bytes).max().unwrap_or(0),
+                total_witness,
+            }
+        }).collect()
+    }
+
+    /// Per-account and grid tables, as `analyze` prints them
+    pub fn table(&self) -> String {
+        let mut out = String::new();
+        let (data, stubs): (u64, u64) = self.accounts.iter().map(|a| (a.dump.data_len, a.stub_bytes)).fold((0, 0), |t, a| (t.0 + a.0, t.1 + a.1));
+        let saved: i64 = self.accounts.iter().map(|a| a.saved_bytes).sum();
+        let rent: i64 = self.accounts.iter().map(|a| a.saved_rent).sum();
+        writeln!(out, "{} accounts, {} data bytes held in {} stub bytes: {} bytes and {} lamports of rent saved",
+            self.accounts.len(), data, stubs, saved, rent).unwrap();
+        writeln!(out).unwrap();
+        writeln!(out, "{:<44} {:>10} {:>5} {:>10} {:>13}   smallest witness", "account", "data", "stub", "saved", "rent saved").unwrap();
+        for a in &self.accounts {
+            let best = a.shapes.iter().min_by_key(|s| (s.witness_bytes, s.depth)).unwrap();
+            writeln!(out, "{:<44} {:>10} {:>5} {:>10} {:>13}   {}B ({} B chunks, arity {}, depth {})", a.dump.name, a.dump.data_len,
+                a.stub_bytes, a.saved_bytes, a.saved_rent, best.witness_bytes, best.chunk_size, best.arity, best.depth).unwrap();
+        }
+        writeln!(out).unwrap();
+        writeln!(out, "{:>6} {:>5} {:>10} {:>9} {:>12} {:>11} {:>13}", "chunk", "arity", "chunks", "max depth", "mean witness", "max witness", "total witness").unwrap();
+        for g in self.aggregates() {
+            writeln!(out, "{:>6} {:>5} {:>10} {:>9} {:>11.0}B {:>10}B {:>12}B{}", g.chunk_size, g.arity, g.chunks, g.max_depth,
+                g.mean_witness, g.max_witness, g.total_witness, if g.chunk_size > MAX_PACKED_CHUNK { "  chunk too large for a transaction" } else { "" }).unwrap();
+        }
+        out
+    }
+
+    /// A row per account and grid point, then a row per grid point over all accounts
+    /// (account `*`, sizes summed and depth the maximum)
+    pub fn csv(&self) -> String {
+        let mut out = String::from("account,owner,data_bytes,stub_bytes,saved_bytes,saved_rent,chunk_size,arity,chunks,depth,proof_bytes,witness_bytes\n");
+        for a in &self.accounts {
+            for s in &a.shapes {
+                writeln!(out, "{},{},{},{},{},{},{},{},{},{},{},{}", csv_field(&a.dump.name), csv_field(&a.dump.owner), a.dump.data_len, a.stub_bytes,
+                    a.saved_bytes, a.saved_rent, s.chunk_size, s.arity, s.chunks, s.depth, s.proof_bytes, s.witness_bytes).unwrap();
+            }
+        }
+        let data: u64 = self.accounts.iter().map(|a| a.dump.data_len).sum();
+        let stubs: u64 = self.accounts.iter().map(|a| a.stub_bytes).sum();
+        let saved: i64 = self.accounts.iter().map(|a| a.saved_bytes).sum();
+        let rent: i64 = self.accounts.iter().map(|a| a.saved_rent).sum();
+        for (i, g) in self.aggregates().iter().enumerate() {
+            let proofs: u64 = self.accounts.iter().map(|a| a.shapes[i].proof_bytes).sum();
+            writeln!(out, "*,,{},{},{},{},{},{},{},{},{},{}", data, stubs, saved, rent, g.chunk_size, g.arity, g.chunks, g.max_depth, proofs, g.total_witness).unwrap();
+        }
+        out
+    }
+}
+
+/// Quoted if it holds a comma, quote or newline
+fn csv_field(s: &str) -> String {
+    match s.contains([',', '"', '\n']) {
+        true => format!("\"{}\"", s.replace('"', "\"\"")),
+        false => s.to_string(),
+    }
+}
+
+/// Print the tables for the dumps in `dir`, and write the CSV to `csv` if given
+pub fn run(dir: &Path, chunk_sizes: &[usize], arities: &[u8], csv: Option<&Path>) -> Result<()> {
+    let report = Report::new(load_dir(dir)?, chunk_sizes, arities)?;
+    print!("{}", report.table());
+    if let Some(path) = csv {
+        std::fs::write(path, report.csv()).with_context(|| format!("writing {}", path.display()))?;
+        println!("CSV written to {}", path.display());
+    }
+    Ok(())
+}
@@ -0,0 +1,188 @@
+use anyhow::Result;
+
+use crate::compute::ComputeMeter;
+use crate::error::WitnessError;
+use crate::events::{StdoutSubscriber, WitnessEvent};
+use crate::program::DEMO_PROGRAM_ID;
+use crate::{h, sha256, AccountStub, ChainState, TxReceipt};
+
+fn hash_pair(left: &[u8;32], right: &[u8;32]) -> [u8;32] {
+    let mut data = [0u8; 64];
+    data[..32].copy_from_slice(left);
+    data[32..].copy_from_slice(right);
+    sha256(&data)
+}
+
+/// Everything needed to append to a Merkle mountain range without its entries: the
+/// leaf count and one peak per perfect subtree, tallest (oldest) first
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MmrFrontier {
+    pub leaf_count: u64,
+    pub peaks: Vec<[u8;32]>,
+}
+
+impl MmrFrontier {
+    /// Commitment stored in the stub: hash of the leaf count and the peaks bagged right to left
+    pub fn root(&self) -> [u8;32] {
+        let bagged = self.peaks.iter().rev().fold(None, |acc: Option<[u8;32]>, peak| match acc {
+            Some(right) => Some(hash_pair(peak, &right)),
+            None => Some(*peak),
+        }).unwrap_or([0u8; 32]);
+        let mut data = self.leaf_count.to_le_bytes().to_vec();
+        data.extend_from_slice(&bagged);
+        sha256(&data)
+    }
+
+    /// A frontier is well formed if it has one peak per set bit of the leaf count
+    pub fn is_well_formed(&self) -> bool {
+        self.peaks.len() == self.leaf_count.count_ones() as usize
+    }
+
+    /// Add `entry` as the next leaf, merging equal-height peaks. Returns the node hashes computed.
+    pub fn push(&mut self, entry: &[u8]) -> usize {
+        let mut node = sha256(entry);
+        // each trailing one bit of the old count is a peak the new leaf merges into
+        let merges = self.leaf_count.trailing_ones() as usize;
+        for _ in 0..merges {
+            let left = self.peaks.pop().unwrap();
+            node = hash_pair(&left, &node);
+        }
+        self.peaks.push(node);
+        self.leaf_count += 1;
+        merges + 1
+    }
+}
+
+impl AccountStub {
+    /// Empty append-only account; its root commits to an MMR rather than a chunked blob
+    pub fn new_append_only(owner: &str, lamports: u64) -> Self {
+        let mut stub = AccountStub::new(owner, lamports, MmrFrontier::default().root(), 0, 1);
+        stub.append_only = true;
+        stub
+    }
+}
+
+impl ChainState {
+    /// Append `entry` to the log account `pubkey` on behalf of its owner `program_id`.
+    /// The client supplies only the current frontier; no existing entry is needed.
+    pub fn append_entry(&mut self, program_id: &str, pubkey: &str, entry: &[u8], frontier: &MmrFrontier) -> Result<TxReceipt, WitnessError> {
+        let _span = tracing::debug_span!("append_entry", pubkey = %pubkey, program = %program_id, len = entry.len()).entered();
+        let res = self.try_append(program_id, pubkey, entry, frontier);
+        if let Err(e) = &res {
+            tracing::info!(error = %e, "append failed");
+            self.emit(WitnessEvent::TxFailed { pubkey: pubkey.to_string(), reason: e.to_string() });
+        }
+        res
+    }
+
+    fn try_append(&mut self, program_id: &str, pubkey: &str, entry: &[u8], frontier: &MmrFrontier) -> Result<TxReceipt, WitnessError> {
+        let mut stub = match self.get_stub(pubkey) {
+            Some(s) => s.clone(),
+            None => return Err(WitnessError::UnknownAccount(pubkey.to_string())),
+        };
+        if !stub.append_only {
+            return Err(WitnessError::NotAppendOnly(pubkey.to_string()));
+        }
+        if stub.owner != program_id {
+            return Err(WitnessError::NotOwner { program: program_id.to_string(), pubkey: pubkey.to_string(), owner: stub.owner.clone() });
+        }
+        let mut meter = ComputeMeter::new(self.compute_budget);
+        let costs = self.compute_costs.clone();
+        meter.consume(costs.mem_copy(entry.len()), "entry load")?;
+        if !frontier.is_well_formed() {
+            return Err(WitnessError::MalformedFrontier { peaks: frontier.peaks.len(), leaves: frontier.leaf_count });
+        }
+        // bagging the peaks costs one hash per peak, plus the count prefix
+        meter.consume(costs.sha256(64) * frontier.peaks.len() as u64 + costs.sha256(40), "frontier verification")?;
+        let old_root = stub.merkle_root;
+        if !witness_core::ct::eq(&frontier.root(), &old_root) {
+            return Err(WitnessError::FrontierRootMismatch { frontier: frontier.root(), stub: old_root });
+        }
+        let mut next = frontier.clone();
+        let hashed = next.push(entry);
+        meter.consume(costs.sha256(entry.len()) + costs.sha256(64) * (hashed as u64 - 1), "leaf append")?;
+        meter.consume(costs.sha256(64) * next.peaks.len() as u64 + costs.sha256(40), "root recomputation")?;
+        let new_root = next.root();
+
+        stub.merkle_root = new_root;
+        stub.data_len += entry.len() as u64;
+        tracing::info!(pubkey = %pubkey, slot = self.slot, old = %h(&old_root), new = %h(&new_root), index = frontier.leaf_count, "entry appended");
+        self.put_stub(pubkey, stub);
+        self.emit(WitnessEvent::RootUpdated { pubkey: pubkey.to_string(), slot: self.slot, old: old_root, new: new_root });
+        self.emit(WitnessEvent::EntryAppended { pubkey: pubkey.to_string(), index: frontier.leaf_count, root: new_root, entry: entry.to_vec() });
+        Ok(TxReceipt { compute_units: meter.consumed(), new_roots: vec![(pubkey.to_string(), new_root)], frontiers: Vec::new() })
+    }
+}
+
+/// Three appends to a log account, each carrying only the MMR frontier
+pub fn demo() -> Result<()> {
+    let mut chain = ChainState::new();
+    chain.subscribe(Box::new(StdoutSubscriber));
+    chain.put_stub("Log1", AccountStub::new_append_only(DEMO_PROGRAM_ID, 1_000));
+    let mut frontier = MmrFrontier::default();
+    for entry in [&b"mint #1"[..], b"transfer #1", b"burn #1"] {
+        let receipt = chain.append_entry(DEMO_PROGRAM_ID, "Log1", entry, &frontier)?;
+        frontier.push(entry);
+        println!("  appended {} bytes with {} peaks in hand: {} CU", entry.len(), frontier.peaks.len(), receipt.compute_units);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn log() -> ChainState {
+        let mut chain = ChainState::new();
+        chain.put_stub("Log1", AccountStub::new_append_only(DEMO_PROGRAM_ID, 0));
+        chain
+    }
+
+    #[test]
+    fn peaks_follow_the_leaf_count() {
+        let entries: Vec<Vec<u8>> = (0..7u8).map(|i| vec![i]).collect();
+        let mut frontier = MmrFrontier::default();
+        for e in &entries {
+            frontier.push(e);
+            assert!(frontier.is_well_formed());
+        }
+        let leaf = |i: usize| sha256(&entries[i]);
+        let four = hash_pair(&hash_pair(&leaf(0), &leaf(1)), &hash_pair(&leaf(2), &leaf(3)));
+        assert_eq!(frontier.peaks, vec![four, hash_pair(&leaf(4), &leaf(5)), leaf(6)]);
+        assert_eq!(frontier.push(b"7"), 4);
+        assert_eq!(frontier.peaks.len(), 1);
+    }
+
+    #[test]
+    fn appends_keep_the_stub_on_the_frontier_root() {
+        let mut chain = log();
+        let mut frontier = MmrFrontier::default();
+        for entry in [&b"a"[..], b"bb", b"ccc"] {
+            let receipt = chain.append_entry(DEMO_PROGRAM_ID, "Log1", entry, &frontier).unwrap();
+            frontier.push(entry);
+            assert_eq!(receipt.new_roots, vec![("Log1".to_string(), frontier.root())]);
+            assert_eq!(chain.get_stub("Log1").unwrap().merkle_root, frontier.root());
+        }
+        assert_eq!(chain.get_stub("Log1").unwrap().data_len, 6);
+    }
+
+    #[test]
+    fn rejects_stale_and_malformed_frontiers() {
+        let mut chain = log();
+        let stale = MmrFrontier::default();
+        chain.append_entry(DEMO_PROGRAM_ID, "Log1", b"a", &stale).unwrap();
+        assert!(matches!(chain.append_entry(DEMO_PROGRAM_ID, "Log1", b"b", &stale), Err(WitnessError::FrontierRootMismatch { .. })));
+        let malformed = MmrFrontier { leaf_count: 1, peaks: Vec::new() };
+        assert!(matches!(chain.append_entry(DEMO_PROGRAM_ID, "Log1", b"b", &malformed), Err(WitnessError::MalformedFrontier { peaks: 0, leaves: 1 })));
+    }
+
+    #[test]
+    fn only_the_owner_appends_to_log_accounts() {
+        let mut chain = log();
+        let frontier = MmrFrontier::default();
+        assert!(matches!(chain.append_entry("Other", "Log1", b"a", &frontier), Err(WitnessError::NotOwner { .. })));
+        chain.put_stub("Blob", AccountStub::new(DEMO_PROGRAM_ID, 0, frontier.root(), 0, 32));
+        assert!(matches!(chain.append_entry(DEMO_PROGRAM_ID, "Blob", b"a", &frontier), Err(WitnessError::NotAppendOnly(_))));
+        assert!(matches!(chain.append_entry(DEMO_PROGRAM_ID, "Missing", b"a", &frontier), Err(WitnessError::UnknownAccount(_))));
+    }
+}
@@ -0,0 +1,509 @@
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+use anyhow::{Result, bail};
+
+use crate::blob_store::{BlobStore, MemoryBlobStore};
+use crate::delta::{apply_delta, encode_delta};
+use crate::error::WitnessError;
+use crate::events::{EventSubscriber, WitnessEvent};
+use crate::metrics::metrics;
+use crate::view::ChunkWitness;
+use crate::{chunk_blob, h, sha256, AccountWitness, MerkleTree, SLOTS_PER_EPOCH};
+
+/// One stored version of an account blob
+#[derive(Debug, Clone)]
+pub struct ArchivedBlob {
+    pub slot: u64,
+    pub root: [u8;32],
+    pub chunk_size: usize,
+    pub data: Vec<u8>,
+}
+
+/// Which superseded versions survive a GC pass. A version is kept if any enabled rule
+/// keeps it; the current version of an account is always kept. With no rule enabled
+/// (the default) nothing is ever collected.
+#[derive(Debug, Clone, Default)]
+pub struct RetentionPolicy {
+    /// Keep the newest N versions per account
+    pub keep_last: Option<usize>,
+    /// Keep versions that were current at any point during the last E epochs
+    pub keep_epochs: Option<u64>,
+    /// Forget `(slot, root)` history entries superseded before the last E epochs.
+    /// History is only roots, so it normally outlives the blobs (None: keep forever).
+    pub history_epochs: Option<u64>,
+}
+
+/// Outcome of GC passes (per pass, or accumulated over the node's lifetime)
+#[derive(Debug, Clone, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct GcReport {
+    pub passes: u64,
+    pub versions_removed: u64,
+    pub bytes_reclaimed: u64,
+    pub history_pruned: u64,
+}
+
+/// What the node remembers about a version; the bytes live in the blob store
+#[derive(Debug, Clone)]
+struct VersionMeta {
+    slot: u64,
+    root: [u8;32],
+    chunk_size: usize,
+    len: u64,
+    /// Set when stored as a diff against this earlier version of the same account
+    base: Option<[u8;32]>,
+    /// Deltas to apply on top of the nearest full version
+    depth: usize,
+    /// Bytes actually written to the blob store (the diff, for delta versions)
+    stored_len: u64,
+}
+
+impl VersionMeta {
+    fn key(&self) -> [u8;32] {
+        match self.base {
+            Some(_) => delta_key(&self.root),
+            None => self.root,
+        }
+    }
+}
+
+/// Deltas live under their own key so the store never maps a root to bytes that
+/// don't hash to it
+fn delta_key(root: &[u8;32]) -> [u8;32] {
+    let mut buf = b"delta".to_vec();
+    buf.extend_from_slice(root);
+    sha256(&buf)
+}
+
+#[derive(Debug, Default)]
+struct ArchiveInner {
+    versions: HashMap<String, Vec<VersionMeta>>,
+    /// Every root each account has had, by slot, including ones whose blob was collected
+    history: HashMap<String, Vec<(u64, [u8;32])>>,
+    retention: RetentionPolicy,
+    /// Longest delta chain before a full version is stored again (None: always full)
+    max_delta_chain: Option<usize>,
+    gc_totals: GcReport,
+    store_errors: u64,
+}
+
+/// Data-availability actor for offloaded state: keeps every blob version it sees in
+/// `AccountDataWritten` (or patches from `ChunksWritten`) events and serves them back (with proofs) by `(pubkey, root)`.
+/// Clones share the same store, so one handle can subscribe while another serves.
+#[derive(Debug, Clone)]
+pub struct ArchiveNode {
+    inner: Arc<Mutex<ArchiveInner>>,
+    store: Arc<dyn BlobStore>,
+}
+
+impl Default for ArchiveNode {
+    fn default() -> Self {
+        Self::with_store(Arc::new(MemoryBlobStore::new()))
+    }
+}
+
+impl ArchiveNode {
+    pub fn new() -> Self { Self::default() }
+
+    /// Archive whose blob bytes go to `store` (e.g. an `FsBlobStore`)
+    pub fn with_store(store: Arc<dyn BlobStore>) -> Self {
+        Self { inner: Arc::default(), store }
+    }
+
+    /// Replace the retention policy (builder-style, after `new` or `with_store`)
+    pub fn retention(self, retention: RetentionPolicy) -> Self {
+        self.inner.lock().unwrap().retention = retention;
+        self
+    }
+
+    /// Store each new version of an account as a binary diff against its previous
+    /// version, with a full copy every `max_chain + 1` versions to bound rebuild cost.
+    /// Diffs are only readable through the node, so don't share the store with
+    /// anything expecting plain blobs. A store that checks roots (S3, IPFS, Arweave)
+    /// can't hold diffs at all, so over one every version is kept in full.
+    pub fn delta_encoding(self, max_chain: usize) -> Self {
+        self.inner.lock().unwrap().max_delta_chain = Some(max_chain);
+        self
+    }
+
+    /// Drop superseded versions not protected by the retention policy.
+    /// Versions are ordered by slot; version i was current until version i+1's slot.
+    pub fn gc(&self, current_slot: u64, slots_per_epoch: u64) -> GcReport {
+        let mut inner = self.inner.lock().unwrap();
+        let retention = inner.retention.clone();
+        let mut report = GcReport { passes: 1, ..GcReport::default() };
+        let cutoff = |epochs: Option<u64>| epochs.map(|e| {
+            let epoch = current_slot / slots_per_epoch;
+            epoch.saturating_sub(e) * slots_per_epoch
+        });
+        if let Some(cutoff) = cutoff(retention.history_epochs) {
+            for entries in inner.history.values_mut() {
+                // entry i was current until entry i+1's slot; the latest always stays
+                let drop = entries.windows(2).take_while(|w| w[1].0 < cutoff).count();
+                entries.drain(..drop);
+                report.history_pruned += drop as u64;
+            }
+        }
+        inner.gc_totals.history_pruned += report.history_pruned;
+        if retention.keep_last.is_none() && retention.keep_epochs.is_none() {
+            inner.gc_totals.passes += 1;
+            return report;
+        }
+        let cutoff_slot = cutoff(retention.keep_epochs);
+        let mut removed = Vec::new();
+        for list in inner.versions.values_mut() {
+            let n = list.len();
+            let superseded_at: Vec<u64> = (0..n).map(|i| list.get(i + 1).map_or(u64::MAX, |next| next.slot)).collect();
+            let mut keep: Vec<bool> = (0..n).map(|idx| {
+                let latest = idx + 1 == n;
+                let by_count = retention.keep_last.is_some_and(|k| idx + k >= n);
+                let by_epoch = cutoff_slot.is_some_and(|cutoff| superseded_at[idx] >= cutoff);
+                latest || by_count || by_epoch
+            }).collect();
+            // a kept delta needs its whole chain back to a full version; a base moved last
+            // by a repeated root can come after the deltas on it
+            let mut pending: Vec<usize> = (0..n).filter(|&idx| keep[idx]).collect();
+            while let Some(idx) = pending.pop() {
+                let Some(base) = list[idx].base else { continue };
+                if let Some(j) = list.iter().position(|v| v.root == base) {
+                    if !keep[j] {
+                        keep[j] = true;
+                        pending.push(j);
+                    }
+                }
+            }
+            let mut i = 0;
+            list.retain(|v| {
+                let kept = keep[i];
+                i += 1;
+                if !kept {
+                    report.versions_removed += 1;
+                    report.bytes_reclaimed += v.stored_len;
+                    removed.push(v.key());
+                }
+                kept
+            });
+        }
+        // identical blobs share a root, so only delete bytes nothing references any more
+        let live: HashSet<[u8;32]> = inner.versions.values().flatten().map(VersionMeta::key).collect();
+        for key in removed.iter().filter(|k| !live.contains(*k)) {
+            if self.store.delete(key).is_err() {
+                inner.store_errors += 1;
+            }
+        }
+        inner.gc_totals.passes += 1;
+        inner.gc_totals.versions_removed += report.versions_removed;
+        inner.gc_totals.bytes_reclaimed += report.bytes_reclaimed;
+        report
+    }
+
+    /// Totals over every GC pass run so far
+    pub fn gc_totals(&self) -> GcReport {
+        self.inner.lock().unwrap().gc_totals.clone()
+    }
+
+    /// Bytes of blob data currently held in the store
+    pub fn stored_bytes(&self) -> u64 {
+        let inner = self.inner.lock().unwrap();
+        inner.versions.values().flatten().map(|v| v.stored_len).sum()
+    }
+
+    /// Total size of the versions held, as if each were stored in full
+    pub fn logical_bytes(&self) -> u64 {
+        let inner = self.inner.lock().unwrap();
+        inner.versions.values().flatten().map(|v| v.len).sum()
+    }
+
+    /// Versions dropped (or left behind on GC) because the blob store failed
+    pub fn store_errors(&self) -> u64 {
+        self.inner.lock().unwrap().store_errors
+    }
+
+    /// Store a blob that never went through a tx (e.g. the initial account data)
+    pub fn ingest(&self, pubkey: &str, slot: u64, chunk_size: usize, data: &[u8]) -> Result<[u8;32], WitnessError> {
+        let root = MerkleTree::from_chunks(&chunk_blob(data, chunk_size)).root();
+        self.store(pubkey, slot, root, chunk_size, data)?;
+        Ok(root)
+    }
+
+    fn store(&self, pubkey: &str, slot: u64, root: [u8;32], chunk_size: usize, data: &[u8]) -> Result<(), WitnessError> {
+        let mut inner = self.inner.lock().unwrap();
+        let entries = inner.history.entry(pubkey.to_string()).or_default();
+        if entries.last().is_none_or(|(_, r)| *r != root) {
+            entries.push((slot, root));
+        }
+        let list = inner.versions.entry(pubkey.to_string()).or_default();
+        // a root held already is the newest again: move it last, where retention counts from
+        if let Some(i) = list.iter().position(|v| v.root == root) {
+            let meta = list.remove(i);
+            list.push(VersionMeta { slot, ..meta });
+            return Ok(());
+        }
+        let prev = list.last().cloned();
+        let mut meta = VersionMeta { slot, root, chunk_size, len: data.len() as u64, base: None, depth: 0, stored_len: data.len() as u64 };
+        let delta = match (inner.max_delta_chain, prev) {
+            (Some(max), Some(prev)) if prev.depth < max && !self.store.checks_roots() => {
+                let base = self.load(&inner.versions[pubkey], &prev)?;
+                Some((prev, encode_delta(&base, data)))
+            }
+            _ => None,
+        };
+        match delta {
+            // a rewrite of most of the blob is cheaper to keep in full
+            Some((prev, diff)) if diff.len() < data.len() => {
+                meta = VersionMeta { base: Some(prev.root), depth: prev.depth + 1, stored_len: diff.len() as u64, ..meta };
+                self.store.put(&meta.key(), &diff).map_err(WitnessError::Store)?;
+            }
+            _ => self.store.put(&root, data).map_err(WitnessError::Store)?,
+        }
+        inner.versions.get_mut(pubkey).unwrap().push(meta);
+        Ok(())
+    }
+
+    /// Bytes of `meta`'s version, applying its delta chain if it has one
+    fn load(&self, list: &[VersionMeta], meta: &VersionMeta) -> Result<Vec<u8>, WitnessError> {
+        let mut chain = vec![meta];
+        while let Some(base) = chain.last().unwrap().base {
+            match list.iter().find(|v| v.root == base) {
+                Some(v) => chain.push(v),
+                None => return Err(WitnessError::DeltaBaseMissing { base, version: meta.root }),
+            }
+        }
+        let full = chain.pop().unwrap();
+        let mut data = match self.store.get(&full.root).map_err(WitnessError::Store)? {
+            Some(d) => d,
+            None => return Err(WitnessError::BlobMissing(full.root)),
+        };
+        if chain.is_empty() {
+            return Ok(data);
+        }
+        for v in chain.iter().rev() {
+            let diff = match self.store.get(&v.key()).map_err(WitnessError::Store)? {
+                Some(d) => d,
+                None => return Err(WitnessError::DeltaMissing(v.root)),
+            };
+            data = apply_delta(&data, &diff)?;
+        }
+        if MerkleTree::from_chunks(&chunk_blob(&data, meta.chunk_size)).root() != meta.root {
+            return Err(WitnessError::DeltaChainMismatch(meta.root));
+        }
+        Ok(data)
+    }
+
+    /// Like `store`, for event handlers that have nowhere to return the error
+    fn store_or_count(&self, pubkey: &str, slot: u64, root: [u8;32], chunk_size: usize, data: &[u8]) {
+        if self.store(pubkey, slot, root, chunk_size, data).is_err() {
+            self.inner.lock().unwrap().store_errors += 1;
+        }
+    }
+
+    /// Blob version of `pubkey` committed by `root`
+    pub fn get_blob(&self, pubkey: &str, root: &[u8;32]) -> Option<ArchivedBlob> {
+        let (meta, data) = {
+            let inner = self.inner.lock().unwrap();
+            let list = inner.versions.get(pubkey)?;
+            let meta = list.iter().find(|v| &v.root == root)?;
+            (meta.clone(), self.load(list, meta).ok()?)
+        };
+        Some(ArchivedBlob { slot: meta.slot, root: meta.root, chunk_size: meta.chunk_size, data })
+    }
+
+    /// Ready-to-submit witness for `leaf_index` of the blob committed by `root`
+    pub fn get_witness(&self, pubkey: &str, root: &[u8;32], leaf_index: usize) -> Option<AccountWitness> {
+        let blob = self.get_blob(pubkey, root)?;
+        let chunks = chunk_blob(&blob.data, blob.chunk_size);
+        if leaf_index >= chunks.len() {
+            return None;
+        }
+        let proof = MerkleTree::from_chunks(&chunks).gen_proof(leaf_index);
+        metrics().proofs_served.with_label_values(&["archive"]).inc();
+        Some(AccountWitness { pubkey: pubkey.to_string(), blob: blob.data, leaf_index, proof, is_writable: true })
+    }
+
+    /// Just chunk `leaf_index` of the blob committed by `root`, with its proof
+    pub fn get_chunk_witness(&self, pubkey: &str, root: &[u8;32], leaf_index: usize) -> Option<ChunkWitness> {
+        let blob = self.get_blob(pubkey, root)?;
+        let chunks = chunk_blob(&blob.data, blob.chunk_size);
+        let chunk = chunks.get(leaf_index)?.to_vec();
+        let proof = MerkleTree::from_chunks(&chunks).gen_proof(leaf_index);
+        metrics().proofs_served.with_label_values(&["archive"]).inc();
+        Some(ChunkWitness { pubkey: pubkey.to_string(), leaf_index, chunk, proof })
+    }
+
+    /// Every `(slot, root)` `pubkey` has had, oldest first (subject to `history_epochs`)
+    pub fn history(&self, pubkey: &str) -> Vec<(u64, [u8;32])> {
+        self.inner.lock().unwrap().history.get(pubkey).cloned().unwrap_or_default()
+    }
+
+    /// Root `pubkey` was committed to as of `slot`: the last one written at or before it
+    pub fn root_at(&self, pubkey: &str, slot: u64) -> Option<[u8;32]> {
+        let inner = self.inner.lock().unwrap();
+        inner.history.get(pubkey)?.iter().rev().find(|(s, _)| *s <= slot).map(|(_, r)| *r)
+    }
+
+    /// What `pubkey` contained at `slot`, if that version's blob is still retained
+    pub fn blob_at(&self, pubkey: &str, slot: u64) -> Option<ArchivedBlob> {
+        self.get_blob(pubkey, &self.root_at(pubkey, slot)?)
+    }
+
+    /// Proof that chunk `leaf_index` was part of `pubkey`'s data at `slot`; it verifies
+    /// against `root_at(pubkey, slot)`, which in turn matches the stub in that slot's state
+    pub fn prove_at(&self, pubkey: &str, slot: u64, leaf_index: usize) -> Option<ChunkWitness> {
+        self.get_chunk_witness(pubkey, &self.root_at(pubkey, slot)?, leaf_index)
+    }
+
+    /// Number of versions held for `pubkey`
+    pub fn version_count(&self, pubkey: &str) -> usize {
+        self.inner.lock().unwrap().versions.get(pubkey).map_or(0, Vec::len)
+    }
+}
+
+impl EventSubscriber for ArchiveNode {
+    fn on_event(&mut self, event: &WitnessEvent) {
+        match event {
+            WitnessEvent::AccountDataWritten { pubkey, slot, root, chunk_size, data } => {
+                self.store_or_count(pubkey, *slot, *root, *chunk_size, data);
+            }
+            // partial writes only carry the touched chunks: patch the version they were proven against
+            WitnessEvent::ChunksWritten { pubkey, slot, old_root, root, chunk_size, chunks } => {
+                if let Some(mut blob) = self.get_blob(pubkey, old_root) {
+                    for (index, chunk) in chunks {
+                        let start = index * chunk_size;
+                        let end = (start + chunk_size).min(blob.data.len());
+                        blob.data[start..end].copy_from_slice(&chunk[..end - start]);
+                    }
+                    self.store_or_count(pubkey, *slot, *root, *chunk_size, &blob.data);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// `pubkey`'s versions as stored, collected and still provable, with GC as of `slot`
+/// over blobs kept in `dir`
+pub fn demo(archive: &ArchiveNode, pubkey: &str, slot: u64, dir: &Path) -> Result<()> {
+    println!("Archived versions of {}: {} ({} bytes stored for {} bytes of versions)",
+        pubkey, archive.version_count(pubkey), archive.stored_bytes(), archive.logical_bytes());
+    let gc = archive.gc(slot, SLOTS_PER_EPOCH);
+    println!("GC: removed {} versions, reclaimed {} bytes from {}", gc.versions_removed, gc.bytes_reclaimed, dir.display());
+    let totals = archive.gc_totals();
+    println!("GC totals: {} passes, {} versions and {} bytes reclaimed, {} store errors",
+        totals.passes, totals.versions_removed, totals.bytes_reclaimed, archive.store_errors());
+    // auditors can still ask what the account held at an earlier slot
+    let history = archive.history(pubkey);
+    println!("{} history: {}", pubkey, history.iter().map(|(slot, root)| format!("{}@{}", h(root), slot)).collect::<Vec<_>>().join(" -> "));
+    let Some(&(audit_slot, _)) = history.first() else { bail!("archive has no history for {}", pubkey) };
+    let root_then = archive.root_at(pubkey, audit_slot).unwrap();
+    match archive.prove_at(pubkey, audit_slot, 0) {
+        Some(w) => println!("Chunk 0 as of slot {} proves against {}: {}", audit_slot, h(&root_then), MerkleTree::verify_proof(&w.chunk, &w.proof, &root_then)),
+        None => println!("Blob for slot {} was collected; only its root {} remains", audit_slot, h(&root_then)),
+    }
+    let latest_slot = history.last().unwrap().0;
+    let kept = archive.blob_at(pubkey, latest_slot).unwrap();
+    println!("{} as of slot {}: {} bytes that rebuild its root {}: {}",
+        pubkey, latest_slot, kept.data.len(), h(&kept.root), MerkleTree::from_chunks(&chunk_blob(&kept.data, kept.chunk_size)).root() == kept.root);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CHUNK: usize = 32;
+
+    /// Like the S3, IPFS and Arweave stores, refuses bytes that don't rebuild their key
+    #[derive(Debug, Default)]
+    struct CheckedStore(MemoryBlobStore);
+
+    impl BlobStore for CheckedStore {
+        fn put(&self, root: &[u8;32], bytes: &[u8]) -> Result<()> {
+            if MerkleTree::from_chunks(&chunk_blob(bytes, CHUNK)).root() != *root {
+                bail!("blob does not hash to {}", h(root));
+            }
+            self.0.put(root, bytes)
+        }
+
+        fn get(&self, root: &[u8;32]) -> Result<Option<Vec<u8>>> {
+            self.0.get(root)
+        }
+
+        fn has(&self, root: &[u8;32]) -> Result<bool> {
+            self.0.has(root)
+        }
+
+        fn delete(&self, root: &[u8;32]) -> Result<()> {
+            self.0.delete(root)
+        }
+
+        fn checks_roots(&self) -> bool {
+            true
+        }
+    }
+
+    /// Four versions of a 1 KiB blob, each rewriting a few bytes of the one before
+    fn versions() -> Vec<Vec<u8>> {
+        let mut blob: Vec<u8> = (0..1024u32).map(|i| (i * 13) as u8).collect();
+        (0..4).map(|v| {
+            blob[v * 100] ^= 0xff;
+            blob.clone()
+        }).collect()
+    }
+
+    fn ingest_all(archive: &ArchiveNode) -> Vec<[u8;32]> {
+        versions().iter().enumerate().map(|(slot, blob)| archive.ingest("Acct1", slot as u64, CHUNK, blob).unwrap()).collect()
+    }
+
+    fn assert_all_readable(archive: &ArchiveNode, roots: &[[u8;32]]) {
+        for (root, blob) in roots.iter().zip(versions()) {
+            assert_eq!(archive.get_blob("Acct1", root).unwrap().data, blob);
+        }
+    }
+
+    #[test]
+    fn delta_versions_rebuild_from_their_chain() {
+        let archive = ArchiveNode::new().delta_encoding(8);
+        let roots = ingest_all(&archive);
+        assert_all_readable(&archive, &roots);
+        assert!(archive.stored_bytes() < archive.logical_bytes() / 2);
+    }
+
+    #[test]
+    fn stores_that_check_roots_keep_full_versions() {
+        let archive = ArchiveNode::with_store(Arc::new(CheckedStore::default())).delta_encoding(8);
+        let roots = ingest_all(&archive);
+        assert_all_readable(&archive, &roots);
+        assert_eq!(archive.stored_bytes(), archive.logical_bytes());
+        assert_eq!(archive.store_errors(), 0);
+    }
+
+    #[test]
+    fn a_repeated_root_is_the_newest_version() {
+        let archive = ArchiveNode::new().retention(RetentionPolicy { keep_last: Some(1), ..RetentionPolicy::default() });
+        let (a, b) = (b"version a".to_vec(), b"version b".to_vec());
+        let root_a = archive.ingest("Acct1", 1, CHUNK, &a).unwrap();
+        let root_b = archive.ingest("Acct1", 2, CHUNK, &b).unwrap();
+        archive.ingest("Acct1", 3, CHUNK, &a).unwrap();
+        assert_eq!(archive.gc(3, SLOTS_PER_EPOCH).versions_removed, 1);
+        assert_eq!(archive.get_blob("Acct1", &root_a).unwrap().data, a);
+        assert!(archive.get_blob("Acct1", &root_b).is_none());
+        assert_eq!(archive.history("Acct1").iter().map(|(_, r)| *r).collect::<Vec<_>>(), [root_a, root_b, root_a]);
+    }
+
+    #[test]
+    fn gc_keeps_delta_bases_that_moved_after_their_deltas() {
+        let archive = ArchiveNode::new().delta_encoding(8).retention(RetentionPolicy { keep_last: Some(1), ..RetentionPolicy::default() });
+        let v = versions();
+        let roots: Vec<[u8;32]> = v[..3].iter().enumerate().map(|(slot, blob)| archive.ingest("Acct1", slot as u64, CHUNK, blob).unwrap()).collect();
+        // v1 is a delta on v0 and v2 on v1; repeating v0 then v2 leaves v1 first, with its
+        // base v0 after it
+        archive.ingest("Acct1", 3, CHUNK, &v[0]).unwrap();
+        archive.ingest("Acct1", 4, CHUNK, &v[2]).unwrap();
+        assert_eq!(archive.gc(4, SLOTS_PER_EPOCH).versions_removed, 0);
+        for (root, blob) in roots.iter().zip(&v) {
+            assert_eq!(archive.get_blob("Acct1", root).unwrap().data, *blob);
+        }
+        assert_eq!(archive.store_errors(), 0);
+    }
+}
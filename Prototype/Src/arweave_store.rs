@@ -0,0 +1,190 @@
+use std::io::Read;
+use std::path::Path;
+
+use anyhow::{Context, Result, bail};
+use base64::Engine;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use ed25519_dalek::{Signer, SigningKey};
+use sha2::{Digest, Sha384};
+
+use crate::blob_store::{BlobStore, RootIndex};
+use crate::{chunk_blob, h, MerkleTree};
+
+/// ANS-104 signature type for ed25519 (Solana) signers
+const SIG_TYPE_ED25519: u16 = 2;
+
+/// Arweave's deep hash (SHA-384) over a data item's fields; what ANS-104 signers sign
+enum DeepHashItem<'a> {
+    Blob(&'a [u8]),
+    List(Vec<DeepHashItem<'a>>),
+}
+
+fn sha384(parts: &[&[u8]]) -> [u8; 48] {
+    let mut hasher = Sha384::new();
+    for p in parts {
+        hasher.update(p);
+    }
+    hasher.finalize().into()
+}
+
+fn deep_hash(item: &DeepHashItem) -> [u8; 48] {
+    match item {
+        DeepHashItem::Blob(data) => {
+            let tag = sha384(&[format!("blob{}", data.len()).as_bytes()]);
+            sha384(&[&tag, &sha384(&[data])])
+        }
+        DeepHashItem::List(items) => {
+            let mut acc = sha384(&[format!("list{}", items.len()).as_bytes()]);
+            for i in items {
+                acc = sha384(&[&acc, &deep_hash(i)]);
+            }
+            acc
+        }
+    }
+}
+
+/// Avro zig-zag varint, as used for ANS-104 tag lengths and counts
+fn avro_long(out: &mut Vec<u8>, n: i64) {
+    let mut z = ((n << 1) ^ (n >> 63)) as u64;
+    while z >= 0x80 {
+        out.push((z as u8 & 0x7f) | 0x80);
+        z >>= 7;
+    }
+    out.push(z as u8);
+}
+
+fn encode_tags(tags: &[(&str, String)]) -> Vec<u8> {
+    let mut out = Vec::new();
+    if !tags.is_empty() {
+        avro_long(&mut out, tags.len() as i64);
+        for (name, value) in tags {
+            for field in [name.as_bytes(), value.as_bytes()] {
+                avro_long(&mut out, field.len() as i64);
+                out.extend_from_slice(field);
+            }
+        }
+        avro_long(&mut out, 0);
+    }
+    out
+}
+
+/// Signed ANS-104 data item carrying `data`, and its id (base64url of the signature hash)
+fn data_item(key: &SigningKey, tags: &[(&str, String)], data: &[u8]) -> (Vec<u8>, String) {
+    let owner = key.verifying_key().to_bytes();
+    let raw_tags = encode_tags(tags);
+    let sig_type = SIG_TYPE_ED25519.to_string();
+    let message = deep_hash(&DeepHashItem::List(vec![
+        DeepHashItem::Blob(b"dataitem"),
+        DeepHashItem::Blob(b"1"),
+        DeepHashItem::Blob(sig_type.as_bytes()),
+        DeepHashItem::Blob(&owner),
+        DeepHashItem::Blob(&[]),
+        DeepHashItem::Blob(&[]),
+        DeepHashItem::Blob(&raw_tags),
+        DeepHashItem::Blob(data),
+    ]));
+    let signature = key.sign(&message).to_bytes();
+    let mut item = SIG_TYPE_ED25519.to_le_bytes().to_vec();
+    item.extend_from_slice(&signature);
+    item.extend_from_slice(&owner);
+    // no target, no anchor
+    item.extend_from_slice(&[0, 0]);
+    item.extend_from_slice(&(tags.len() as u64).to_le_bytes());
+    item.extend_from_slice(&(raw_tags.len() as u64).to_le_bytes());
+    item.extend_from_slice(&raw_tags);
+    item.extend_from_slice(data);
+    let id = URL_SAFE_NO_PAD.encode(sha2::Sha256::digest(signature));
+    (item, id)
+}
+
+/// Permanent archival on Arweave: blobs are uploaded as signed ANS-104 data items through a
+/// bundler node (Irys/bundlr style, paid from `key`'s funded balance) and read back from a
+/// gateway. The store keeps a root → transaction id index so a stub's root is enough to
+/// fetch the blob for revival. Each item is also tagged with its root, so the index can be
+/// rebuilt from the gateway's GraphQL if lost.
+#[derive(Debug)]
+pub struct ArweaveBlobStore {
+    /// e.g. `https://node1.irys.xyz`
+    pub bundler_url: String,
+    /// e.g. `https://arweave.net`
+    pub gateway_url: String,
+    /// Chunk size blobs are committed with; fetched bytes must rebuild the root
+    pub chunk_size: usize,
+    key: SigningKey,
+    index: RootIndex,
+}
+
+impl ArweaveBlobStore {
+    pub fn new(bundler_url: &str, gateway_url: &str, key: SigningKey, chunk_size: usize) -> Self {
+        Self {
+            bundler_url: bundler_url.trim_end_matches('/').to_string(),
+            gateway_url: gateway_url.trim_end_matches('/').to_string(),
+            chunk_size,
+            key,
+            index: RootIndex::default(),
+        }
+    }
+
+    /// Persist the root → transaction id index at `path` (loading it if present)
+    pub fn with_index_file(mut self, path: impl AsRef<Path>) -> Result<Self> {
+        self.index = RootIndex::open(path.as_ref())?;
+        Ok(self)
+    }
+
+    /// Arweave transaction id the blob committed by `root` was uploaded as, if any
+    pub fn tx_id(&self, root: &[u8;32]) -> Option<String> {
+        self.index.get(root)
+    }
+}
+
+impl BlobStore for ArweaveBlobStore {
+    fn put(&self, root: &[u8;32], bytes: &[u8]) -> Result<()> {
+        if self.tx_id(root).is_some() {
+            return Ok(());
+        }
+        if MerkleTree::from_chunks(&chunk_blob(bytes, self.chunk_size)).root() != *root {
+            bail!("blob does not hash to {} with chunk size {}", h(root), self.chunk_size);
+        }
+        let tags = [
+            ("Content-Type", "application/octet-stream".to_string()),
+            ("Witness-Root", hex::encode(root)),
+            ("Witness-Chunk-Size", self.chunk_size.to_string()),
+        ];
+        let (item, id) = data_item(&self.key, &tags, bytes);
+        ureq::post(&format!("{}/tx/solana", self.bundler_url))
+            .set("Content-Type", "application/octet-stream")
+            .send_bytes(&item)
+            .with_context(|| format!("uploading blob {} to {}", h(root), self.bundler_url))?;
+        self.index.insert(root, id)
+    }
+
+    fn get(&self, root: &[u8;32]) -> Result<Option<Vec<u8>>> {
+        let id = match self.tx_id(root) {
+            Some(id) => id,
+            None => return Ok(None),
+        };
+        let mut bytes = Vec::new();
+        ureq::get(&format!("{}/{}", self.gateway_url, id)).call()
+            .with_context(|| format!("fetching {} (root {})", id, h(root)))?
+            .into_reader()
+            .read_to_end(&mut bytes)?;
+        // gateways are untrusted: the bytes must still be what the stub commits to
+        if MerkleTree::from_chunks(&chunk_blob(&bytes, self.chunk_size)).root() != *root {
+            bail!("content of {} does not match root {}", id, h(root));
+        }
+        Ok(Some(bytes))
+    }
+
+    fn has(&self, root: &[u8;32]) -> Result<bool> {
+        Ok(self.tx_id(root).is_some())
+    }
+
+    /// Arweave data is permanent; an archive GC pass will count this as a store error
+    fn delete(&self, root: &[u8;32]) -> Result<()> {
+        bail!("blob {} is on Arweave and cannot be deleted", h(root))
+    }
+
+    fn checks_roots(&self) -> bool {
+        true
+    }
+}
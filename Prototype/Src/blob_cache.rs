@@ -0,0 +1,143 @@
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Mutex;
+
+use anyhow::Result;
+
+use crate::blob_store::BlobStore;
+use crate::metrics::metrics;
+
+/// Size limits for a `CachedBlobStore`; a blob larger than `max_bytes` is never cached
+#[derive(Debug, Clone)]
+pub struct CacheConfig {
+    pub max_bytes: u64,
+    pub max_entries: usize,
+}
+
+impl Default for CacheConfig {
+    fn default() -> Self {
+        Self { max_bytes: 256 * 1024 * 1024, max_entries: 4096 }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CacheStats {
+    pub hits: u64,
+    pub misses: u64,
+    pub evictions: u64,
+    pub entries: usize,
+    pub bytes: u64,
+}
+
+#[derive(Debug, Default)]
+struct CacheInner {
+    /// root -> (bytes, last-use tick)
+    blobs: HashMap<[u8;32], (Vec<u8>, u64)>,
+    /// last-use tick -> root, oldest first
+    lru: BTreeMap<u64, [u8;32]>,
+    tick: u64,
+    stats: CacheStats,
+}
+
+impl CacheInner {
+    fn touch(&mut self, root: &[u8;32]) {
+        self.tick += 1;
+        let tick = self.tick;
+        if let Some((_, last)) = self.blobs.get_mut(root) {
+            self.lru.remove(last);
+            *last = tick;
+            self.lru.insert(tick, *root);
+        }
+    }
+
+    fn remove(&mut self, root: &[u8;32]) {
+        if let Some((bytes, last)) = self.blobs.remove(root) {
+            self.lru.remove(&last);
+            self.stats.entries -= 1;
+            self.stats.bytes -= bytes.len() as u64;
+        }
+    }
+
+    fn insert(&mut self, root: &[u8;32], bytes: Vec<u8>, config: &CacheConfig) {
+        if bytes.len() as u64 > config.max_bytes || config.max_entries == 0 {
+            return;
+        }
+        self.remove(root);
+        while self.stats.entries >= config.max_entries || self.stats.bytes + bytes.len() as u64 > config.max_bytes {
+            let oldest = match self.lru.first_key_value() {
+                Some((_, r)) => *r,
+                None => break,
+            };
+            self.remove(&oldest);
+            self.stats.evictions += 1;
+        }
+        self.tick += 1;
+        self.stats.entries += 1;
+        self.stats.bytes += bytes.len() as u64;
+        self.lru.insert(self.tick, *root);
+        self.blobs.insert(*root, (bytes, self.tick));
+    }
+}
+
+/// LRU read-through, write-through cache in front of another store. Blobs are immutable
+/// under their root, so cached entries never go stale; only deletes invalidate.
+#[derive(Debug)]
+pub struct CachedBlobStore<S: BlobStore> {
+    inner: S,
+    config: CacheConfig,
+    cache: Mutex<CacheInner>,
+}
+
+impl<S: BlobStore> CachedBlobStore<S> {
+    pub fn new(inner: S, config: CacheConfig) -> Self {
+        Self { inner, config, cache: Mutex::default() }
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        self.cache.lock().unwrap().stats.clone()
+    }
+}
+
+impl<S: BlobStore> BlobStore for CachedBlobStore<S> {
+    fn put(&self, root: &[u8;32], bytes: &[u8]) -> Result<()> {
+        self.inner.put(root, bytes)?;
+        self.cache.lock().unwrap().insert(root, bytes.to_vec(), &self.config);
+        Ok(())
+    }
+
+    fn get(&self, root: &[u8;32]) -> Result<Option<Vec<u8>>> {
+        {
+            let mut cache = self.cache.lock().unwrap();
+            if let Some((bytes, _)) = cache.blobs.get(root) {
+                let bytes = bytes.clone();
+                cache.touch(root);
+                cache.stats.hits += 1;
+                metrics().blob_cache_requests.with_label_values(&["hit"]).inc();
+                return Ok(Some(bytes));
+            }
+            cache.stats.misses += 1;
+            metrics().blob_cache_requests.with_label_values(&["miss"]).inc();
+        }
+        // fetch without holding the lock so slow backends don't serialize readers
+        let fetched = self.inner.get(root)?;
+        if let Some(bytes) = &fetched {
+            self.cache.lock().unwrap().insert(root, bytes.clone(), &self.config);
+        }
+        Ok(fetched)
+    }
+
+    fn has(&self, root: &[u8;32]) -> Result<bool> {
+        if self.cache.lock().unwrap().blobs.contains_key(root) {
+            return Ok(true);
+        }
+        self.inner.has(root)
+    }
+
+    fn delete(&self, root: &[u8;32]) -> Result<()> {
+        self.cache.lock().unwrap().remove(root);
+        self.inner.delete(root)
+    }
+
+    fn checks_roots(&self) -> bool {
+        self.inner.checks_roots()
+    }
+}
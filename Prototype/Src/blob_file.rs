@@ -0,0 +1,173 @@
+//! Committed-blob files: offloaded bytes behind a manifest of how they were committed,
+//! so a blob never travels apart from the chunk size its root was built with. `commit
+//! --container` writes one; `prove` takes its chunk size from it and `verify --root`
+//! holds proofs to it.
+//!
+//! Layout, integers little-endian: `MAGIC`, version u8, chunk_size u32, chunk_count
+//! u64, root, codec id u8 (0 for none) and, with a codec, the raw length u64, then the
+//! stored bytes to the end of the file. The tree covers the stored bytes, compressed or
+//! not, exactly as a stub's root does.
+
+use std::path::Path;
+
+use anyhow::{Context, Result};
+
+use crate::compression::{commit_blob, Codec, Compression};
+use crate::error::WitnessError;
+use crate::{chunk_blob, MerkleTree};
+
+/// Leading bytes of a committed-blob file
+pub const MAGIC: &[u8;4] = b"SBBL";
+/// Bumped whenever the layout changes; readers reject other versions
+pub const VERSION: u8 = 1;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BlobFile {
+    pub chunk_size: usize,
+    pub chunk_count: usize,
+    pub root: [u8;32],
+    pub compression: Option<Compression>,
+    /// The committed bytes
+    pub data: Vec<u8>,
+}
+
+fn take<'a>(bytes: &mut &'a [u8], n: usize) -> Result<&'a [u8], WitnessError> {
+    if bytes.len() < n {
+        return Err(WitnessError::Truncated("blob file"));
+    }
+    let (head, rest) = bytes.split_at(n);
+    *bytes = rest;
+    Ok(head)
+}
+
+impl BlobFile {
+    /// Commit `raw`, compressing it first with `codec` if given
+    pub fn commit(raw: &[u8], chunk_size: usize, codec: Option<Codec>) -> Result<Self, WitnessError> {
+        if chunk_size == 0 {
+            return Err(WitnessError::ZeroChunkSize);
+        }
+        let (data, root, compression) = commit_blob(raw, chunk_size, codec)?;
+        Ok(Self { chunk_size, chunk_count: chunk_blob(&data, chunk_size).len(), root, compression, data })
+    }
+
+    /// Whether `bytes` start like a committed-blob file
+    pub fn sniff(bytes: &[u8]) -> bool {
+        bytes.starts_with(MAGIC)
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(4 + 1 + 4 + 8 + 32 + 9 + self.data.len());
+        out.extend_from_slice(MAGIC);
+        out.push(VERSION);
+        out.extend_from_slice(&(self.chunk_size as u32).to_le_bytes());
+        out.extend_from_slice(&(self.chunk_count as u64).to_le_bytes());
+        out.extend_from_slice(&self.root);
+        match &self.compression {
+            Some(c) => out.extend_from_slice(&c.encode()),
+            None => out.push(0),
+        }
+        out.extend_from_slice(&self.data);
+        out
+    }
+
+    /// Parse and check the bytes against the manifest: same chunk count, same root
+    pub fn from_bytes(mut bytes: &[u8]) -> Result<Self, WitnessError> {
+        let b = &mut bytes;
+        if take(b, 4)? != MAGIC {
+            return Err(WitnessError::BadMagic("blob file"));
+        }
+        let version = take(b, 1)?[0];
+        if version != VERSION {
+            return Err(WitnessError::UnsupportedVersion { what: "blob file", version });
+        }
+        let chunk_size = u32::from_le_bytes(take(b, 4)?.try_into().unwrap()) as usize;
+        let chunk_count = u64::from_le_bytes(take(b, 8)?.try_into().unwrap()) as usize;
+        let root: [u8;32] = take(b, 32)?.try_into().unwrap();
+        let compression = match take(b, 1)?[0] {
+            0 => None,
+            id => match Codec::from_id(id) {
+                Some(codec) => Some(Compression { codec, raw_len: u64::from_le_bytes(take(b, 8)?.try_into().unwrap()) }),
+                None => return Err(WitnessError::Malformed(format!("unknown codec id {}", id))),
+            },
+        };
+        if chunk_size == 0 {
+            return Err(WitnessError::ZeroChunkSize);
+        }
+        let data = b.to_vec();
+        let chunks = chunk_blob(&data, chunk_size);
+        if chunks.len() != chunk_count {
+            return Err(WitnessError::ManifestChunks { held: chunks.len(), manifest: chunk_count });
+        }
+        let computed = MerkleTree::from_chunks(&chunks).root();
+        if computed != root {
+            return Err(WitnessError::ManifestRoot { data: computed, manifest: root });
+        }
+        Ok(Self { chunk_size, chunk_count, root, compression, data })
+    }
+
+    pub fn write(&self, path: &Path) -> Result<()> {
+        std::fs::write(path, self.to_bytes()).with_context(|| format!("writing {}", path.display()))
+    }
+
+    /// The account data, decompressed if need be
+    pub fn raw(&self) -> Result<Vec<u8>, WitnessError> {
+        match &self.compression {
+            Some(c) => c.decompress(&self.data),
+            None => Ok(self.data.clone()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn blob() -> Vec<u8> {
+        (0..1000u32).map(|i| (i % 7) as u8).collect()
+    }
+
+    #[test]
+    fn round_trips_plain_and_compressed() {
+        for codec in [None, Some(Codec::Zstd)] {
+            let file = BlobFile::commit(&blob(), 64, codec).unwrap();
+            assert_eq!(file.chunk_count, chunk_blob(&file.data, 64).len());
+            let path = std::env::temp_dir().join(format!("blob-file-test-{}.sbbl", codec.is_some()));
+            file.write(&path).unwrap();
+            let read = BlobFile::from_bytes(&std::fs::read(&path).unwrap()).unwrap();
+            std::fs::remove_file(&path).unwrap();
+            assert_eq!(read, file);
+            assert_eq!(read.raw().unwrap(), blob());
+        }
+    }
+
+    #[test]
+    fn rejects_data_the_manifest_does_not_commit_to() {
+        let file = BlobFile::commit(&blob(), 64, None).unwrap();
+        let mut bytes = file.to_bytes();
+        *bytes.last_mut().unwrap() ^= 1;
+        assert!(BlobFile::from_bytes(&bytes).unwrap_err().to_string().contains("root"));
+        let mut grown = file.to_bytes();
+        grown.extend_from_slice(&[0; 64]);
+        assert!(BlobFile::from_bytes(&grown).unwrap_err().to_string().contains("chunks"));
+    }
+
+    #[test]
+    fn rejects_malformed_headers() {
+        let bytes = BlobFile::commit(&blob(), 64, None).unwrap().to_bytes();
+        let with = |at: usize, value: u8| {
+            let mut b = bytes.clone();
+            b[at] = value;
+            BlobFile::from_bytes(&b)
+        };
+        assert!(with(0, b'X').is_err());
+        assert!(with(4, VERSION + 1).is_err());
+        // chunk size 0
+        let mut zero = bytes.clone();
+        zero[5..9].copy_from_slice(&0u32.to_le_bytes());
+        assert!(BlobFile::from_bytes(&zero).is_err());
+        // codec id after magic, version, chunk size, count and root
+        assert!(with(4 + 1 + 4 + 8 + 32, 0xee).unwrap_err().to_string().contains("codec"));
+        assert!(BlobFile::from_bytes(&bytes[..20]).unwrap_err().to_string().contains("truncated"));
+        assert!(BlobFile::commit(&blob(), 0, None).is_err());
+    }
+}
@@ -0,0 +1,165 @@
+use std::collections::HashMap;
+use std::fmt::Debug;
+use std::fs;
+use std::io::ErrorKind;
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+use anyhow::{Context, Result};
+
+use crate::h;
+
+/// Where offloaded blobs live, addressed by the Merkle root that commits to them.
+/// Implementations don't check that `bytes` hash to `root`; callers that fetch from an
+/// untrusted store verify against the stub themselves.
+pub trait BlobStore: Debug + Send + Sync {
+    fn put(&self, root: &[u8;32], bytes: &[u8]) -> Result<()>;
+    fn get(&self, root: &[u8;32]) -> Result<Option<Vec<u8>>>;
+    fn has(&self, root: &[u8;32]) -> Result<bool>;
+    /// Remove the blob; deleting a missing root is not an error
+    fn delete(&self, root: &[u8;32]) -> Result<()>;
+    /// Whether `put` refuses bytes that don't rebuild `root`, so the store can't hold
+    /// anything but whole blobs
+    fn checks_roots(&self) -> bool {
+        false
+    }
+}
+
+/// Blobs kept in process memory (the default for demos)
+#[derive(Debug, Default)]
+pub struct MemoryBlobStore {
+    blobs: Mutex<HashMap<[u8;32], Vec<u8>>>,
+}
+
+impl MemoryBlobStore {
+    pub fn new() -> Self { Self::default() }
+}
+
+impl BlobStore for MemoryBlobStore {
+    fn put(&self, root: &[u8;32], bytes: &[u8]) -> Result<()> {
+        self.blobs.lock().unwrap().insert(*root, bytes.to_vec());
+        Ok(())
+    }
+
+    fn get(&self, root: &[u8;32]) -> Result<Option<Vec<u8>>> {
+        Ok(self.blobs.lock().unwrap().get(root).cloned())
+    }
+
+    fn has(&self, root: &[u8;32]) -> Result<bool> {
+        Ok(self.blobs.lock().unwrap().contains_key(root))
+    }
+
+    fn delete(&self, root: &[u8;32]) -> Result<()> {
+        self.blobs.lock().unwrap().remove(root);
+        Ok(())
+    }
+}
+
+/// One file per blob under `dir`, named by the full hex root and fanned out by its
+/// first byte (`ab/abcd…`) to keep directories small
+#[derive(Debug, Clone)]
+pub struct FsBlobStore {
+    pub dir: PathBuf,
+}
+
+impl FsBlobStore {
+    pub fn new(dir: impl Into<PathBuf>) -> Result<Self> {
+        let dir = dir.into();
+        fs::create_dir_all(&dir).with_context(|| format!("creating blob store at {}", dir.display()))?;
+        Ok(Self { dir })
+    }
+
+    fn path(&self, root: &[u8;32]) -> PathBuf {
+        let name = hex::encode(root);
+        self.dir.join(&name[..2]).join(name)
+    }
+}
+
+impl BlobStore for FsBlobStore {
+    fn put(&self, root: &[u8;32], bytes: &[u8]) -> Result<()> {
+        let path = self.path(root);
+        if path.exists() {
+            return Ok(());
+        }
+        fs::create_dir_all(path.parent().unwrap())?;
+        // write then rename so readers never see a partial blob
+        let tmp = path.with_extension("tmp");
+        fs::write(&tmp, bytes).with_context(|| format!("writing blob {}", h(root)))?;
+        fs::rename(&tmp, &path).with_context(|| format!("writing blob {}", h(root)))?;
+        Ok(())
+    }
+
+    fn get(&self, root: &[u8;32]) -> Result<Option<Vec<u8>>> {
+        match fs::read(self.path(root)) {
+            Ok(bytes) => Ok(Some(bytes)),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e).with_context(|| format!("reading blob {}", h(root))),
+        }
+    }
+
+    fn has(&self, root: &[u8;32]) -> Result<bool> {
+        Ok(self.path(root).exists())
+    }
+
+    fn delete(&self, root: &[u8;32]) -> Result<()> {
+        match fs::remove_file(self.path(root)) {
+            Err(e) if e.kind() != ErrorKind::NotFound => Err(e).with_context(|| format!("deleting blob {}", h(root))),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Root → external id (CID, transaction id, ...) map for backends that address content
+/// by their own ids, optionally persisted as JSON so it survives restarts
+#[cfg(any(feature = "arweave", feature = "ipfs"))]
+#[derive(Debug, Default)]
+pub struct RootIndex {
+    ids: Mutex<HashMap<[u8;32], String>>,
+    file: Option<PathBuf>,
+}
+
+#[cfg(any(feature = "arweave", feature = "ipfs"))]
+impl RootIndex {
+    /// Load (or start) an index at `path`; every change rewrites it
+    pub fn open(path: &std::path::Path) -> Result<Self> {
+        let mut ids = HashMap::new();
+        if path.exists() {
+            let text = fs::read_to_string(path).with_context(|| format!("reading index {}", path.display()))?;
+            let entries: HashMap<String, String> = serde_json::from_str(&text).context("parsing root index")?;
+            for (root, id) in entries {
+                match hex::decode(&root).ok().and_then(|b| <[u8;32]>::try_from(b).ok()) {
+                    Some(r) => ids.insert(r, id),
+                    None => anyhow::bail!("bad root {} in index {}", root, path.display()),
+                };
+            }
+        }
+        Ok(Self { ids: Mutex::new(ids), file: Some(path.to_path_buf()) })
+    }
+
+    pub fn get(&self, root: &[u8;32]) -> Option<String> {
+        self.ids.lock().unwrap().get(root).cloned()
+    }
+
+    pub fn insert(&self, root: &[u8;32], id: String) -> Result<()> {
+        let mut ids = self.ids.lock().unwrap();
+        ids.insert(*root, id);
+        self.save(&ids)
+    }
+
+    pub fn remove(&self, root: &[u8;32]) -> Result<()> {
+        let mut ids = self.ids.lock().unwrap();
+        if ids.remove(root).is_some() {
+            self.save(&ids)?;
+        }
+        Ok(())
+    }
+
+    fn save(&self, ids: &HashMap<[u8;32], String>) -> Result<()> {
+        let path = match &self.file {
+            Some(p) => p,
+            None => return Ok(()),
+        };
+        let entries: HashMap<String, &String> = ids.iter().map(|(r, id)| (hex::encode(r), id)).collect();
+        fs::write(path, serde_json::to_string_pretty(&entries)?).with_context(|| format!("writing index {}", path.display()))
+    }
+}
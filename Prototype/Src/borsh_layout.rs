@@ -0,0 +1,243 @@
+//! Borsh encodings of the types that cross into Solana account and instruction data.
+//! Field order is written out by hand rather than derived, so reordering a struct
+//! cannot silently change the layout; stubs, proofs and transactions lead with
+//! `LAYOUT_VERSION`, and decoders reject any other.
+//!
+//! | type           | layout                                                                     |
+//! |----------------|----------------------------------------------------------------------------|
+//! | AccountStub    | version u8, owner string, lamports u64, merkle_root [32], executable bool,  |
+//! |                | data_len u64, rent_epoch u64, chunk_size u64, hash_algo u8, arity u8,       |
+//! |                | append_only bool, then Option of dirty, multisig, timelock, pda, compression |
+//! | ProofFile      | version u8, root [32], leaf_index u64, chunk_size u32, chunk bytes, steps   |
+//! | AccountWitness | pubkey string, blob bytes, leaf_index u64, steps, is_writable bool          |
+//! | WitnessTx      | version u8, program_id string, instruction_data bytes, witnesses,           |
+//! |                | recent_blockhash [32], priority_fee u64, signatures [(pubkey [32], sig [64])] |
+//!
+//! `steps` is `Vec<([u8;32], bool)>`: a u32 count, then per step the sibling and 1 if it
+//! is the left child, the layout the on-chain verifier reads.
+
+use std::io::{self, Read, Write};
+
+use borsh::{BorshDeserialize, BorshSerialize};
+
+use crate::compression::{Codec, Compression};
+use crate::dirty::DirtyBitmap;
+use crate::multisig::Multisig;
+use crate::proof_file::ProofFile;
+use crate::pubkey::PdaSeeds;
+use crate::timelock::TimeLock;
+use crate::{AccountStub, AccountWitness, HashAlgo, WitnessTx};
+
+/// Bumped whenever any layout here changes
+pub const LAYOUT_VERSION: u8 = 1;
+
+fn invalid(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+fn read_version<R: Read>(reader: &mut R, what: &str) -> io::Result<()> {
+    let version = u8::deserialize_reader(reader)?;
+    if version != LAYOUT_VERSION {
+        return Err(invalid(format!("unsupported {} layout version {}", what, version)));
+    }
+    Ok(())
+}
+
+fn read_usize<R: Read>(reader: &mut R, what: &str) -> io::Result<usize> {
+    let v = u64::deserialize_reader(reader)?;
+    usize::try_from(v).map_err(|_| invalid(format!("{} {} does not fit in memory", what, v)))
+}
+
+impl BorshSerialize for DirtyBitmap {
+    fn serialize<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        self.bits.serialize(writer)?;
+        self.since_slot.serialize(writer)
+    }
+}
+
+impl BorshDeserialize for DirtyBitmap {
+    fn deserialize_reader<R: Read>(reader: &mut R) -> io::Result<Self> {
+        Ok(Self { bits: <[u8;32]>::deserialize_reader(reader)?, since_slot: u64::deserialize_reader(reader)? })
+    }
+}
+
+impl BorshSerialize for Multisig {
+    fn serialize<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        self.threshold.serialize(writer)?;
+        self.signers.serialize(writer)
+    }
+}
+
+impl BorshDeserialize for Multisig {
+    fn deserialize_reader<R: Read>(reader: &mut R) -> io::Result<Self> {
+        let threshold = u8::deserialize_reader(reader)?;
+        let signers = Vec::<[u8;32]>::deserialize_reader(reader)?;
+        Multisig::new(threshold, signers).map_err(|e| invalid(e.to_string()))
+    }
+}
+
+impl BorshSerialize for TimeLock {
+    fn serialize<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        self.unlock_slot.serialize(writer)?;
+        self.once_per_epoch.serialize(writer)?;
+        self.last_write_epoch.serialize(writer)
+    }
+}
+
+impl BorshDeserialize for TimeLock {
+    fn deserialize_reader<R: Read>(reader: &mut R) -> io::Result<Self> {
+        Ok(Self {
+            unlock_slot: Option::deserialize_reader(reader)?,
+            once_per_epoch: bool::deserialize_reader(reader)?,
+            last_write_epoch: Option::deserialize_reader(reader)?,
+        })
+    }
+}
+
+impl BorshSerialize for PdaSeeds {
+    fn serialize<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        self.seeds.serialize(writer)?;
+        self.bump.serialize(writer)
+    }
+}
+
+impl BorshDeserialize for PdaSeeds {
+    fn deserialize_reader<R: Read>(reader: &mut R) -> io::Result<Self> {
+        Ok(Self { seeds: Vec::deserialize_reader(reader)?, bump: u8::deserialize_reader(reader)? })
+    }
+}
+
+impl BorshSerialize for Compression {
+    fn serialize<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        self.codec.id().serialize(writer)?;
+        self.raw_len.serialize(writer)
+    }
+}
+
+impl BorshDeserialize for Compression {
+    fn deserialize_reader<R: Read>(reader: &mut R) -> io::Result<Self> {
+        let codec = match u8::deserialize_reader(reader)? {
+            1 => Codec::Zstd,
+            other => return Err(invalid(format!("unknown codec id {}", other))),
+        };
+        Ok(Self { codec, raw_len: u64::deserialize_reader(reader)? })
+    }
+}
+
+impl BorshSerialize for AccountStub {
+    fn serialize<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        LAYOUT_VERSION.serialize(writer)?;
+        self.owner.serialize(writer)?;
+        self.lamports.serialize(writer)?;
+        self.merkle_root.serialize(writer)?;
+        self.executable.serialize(writer)?;
+        self.data_len.serialize(writer)?;
+        self.rent_epoch.serialize(writer)?;
+        (self.chunk_size as u64).serialize(writer)?;
+        self.hash_algo.id().serialize(writer)?;
+        self.arity.serialize(writer)?;
+        self.append_only.serialize(writer)?;
+        self.dirty.serialize(writer)?;
+        self.multisig.serialize(writer)?;
+        self.timelock.serialize(writer)?;
+        self.pda.serialize(writer)?;
+        self.compression.serialize(writer)
+    }
+}
+
+impl BorshDeserialize for AccountStub {
+    fn deserialize_reader<R: Read>(reader: &mut R) -> io::Result<Self> {
+        read_version(reader, "stub")?;
+        Ok(Self {
+            owner: String::deserialize_reader(reader)?,
+            lamports: u64::deserialize_reader(reader)?,
+            merkle_root: <[u8;32]>::deserialize_reader(reader)?,
+            executable: bool::deserialize_reader(reader)?,
+            data_len: u64::deserialize_reader(reader)?,
+            rent_epoch: u64::deserialize_reader(reader)?,
+            chunk_size: read_usize(reader, "chunk size")?,
+            hash_algo: match u8::deserialize_reader(reader)? {
+                0 => HashAlgo::Sha256,
+                other => return Err(invalid(format!("unknown hash algorithm id {}", other))),
+            },
+            arity: u8::deserialize_reader(reader)?,
+            append_only: bool::deserialize_reader(reader)?,
+            dirty: Option::deserialize_reader(reader)?,
+            multisig: Option::deserialize_reader(reader)?,
+            timelock: Option::deserialize_reader(reader)?,
+            pda: Option::deserialize_reader(reader)?,
+            compression: Option::deserialize_reader(reader)?,
+        })
+    }
+}
+
+impl BorshSerialize for ProofFile {
+    fn serialize<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        LAYOUT_VERSION.serialize(writer)?;
+        self.root.serialize(writer)?;
+        (self.leaf_index as u64).serialize(writer)?;
+        u32::try_from(self.chunk_size).map_err(|_| invalid(format!("chunk size {} over u32", self.chunk_size)))?.serialize(writer)?;
+        self.chunk.serialize(writer)?;
+        self.steps().serialize(writer)
+    }
+}
+
+impl BorshDeserialize for ProofFile {
+    fn deserialize_reader<R: Read>(reader: &mut R) -> io::Result<Self> {
+        read_version(reader, "proof")?;
+        let root = <[u8;32]>::deserialize_reader(reader)?;
+        let leaf_index = read_usize(reader, "leaf index")?;
+        let chunk_size = u32::deserialize_reader(reader)? as usize;
+        let chunk = Vec::deserialize_reader(reader)?;
+        let steps = Vec::<([u8;32], bool)>::deserialize_reader(reader)?;
+        Ok(ProofFile::new(root, leaf_index, chunk_size, chunk, &steps))
+    }
+}
+
+impl BorshSerialize for AccountWitness {
+    fn serialize<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        self.pubkey.serialize(writer)?;
+        self.blob.serialize(writer)?;
+        (self.leaf_index as u64).serialize(writer)?;
+        self.proof.serialize(writer)?;
+        self.is_writable.serialize(writer)
+    }
+}
+
+impl BorshDeserialize for AccountWitness {
+    fn deserialize_reader<R: Read>(reader: &mut R) -> io::Result<Self> {
+        Ok(Self {
+            pubkey: String::deserialize_reader(reader)?,
+            blob: Vec::deserialize_reader(reader)?,
+            leaf_index: read_usize(reader, "leaf index")?,
+            proof: Vec::deserialize_reader(reader)?,
+            is_writable: bool::deserialize_reader(reader)?,
+        })
+    }
+}
+
+impl BorshSerialize for WitnessTx {
+    fn serialize<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        LAYOUT_VERSION.serialize(writer)?;
+        self.program_id.serialize(writer)?;
+        self.instruction_data.serialize(writer)?;
+        self.witnesses.serialize(writer)?;
+        self.recent_blockhash.serialize(writer)?;
+        self.priority_fee.serialize(writer)?;
+        self.signatures.serialize(writer)
+    }
+}
+
+impl BorshDeserialize for WitnessTx {
+    fn deserialize_reader<R: Read>(reader: &mut R) -> io::Result<Self> {
+        read_version(reader, "transaction")?;
+        Ok(Self {
+            program_id: String::deserialize_reader(reader)?,
+            instruction_data: Vec::deserialize_reader(reader)?,
+            witnesses: Vec::deserialize_reader(reader)?,
+            recent_blockhash: <[u8;32]>::deserialize_reader(reader)?,
+            priority_fee: u64::deserialize_reader(reader)?,
+            signatures: Vec::deserialize_reader(reader)?,
+        })
+    }
+}
@@ -0,0 +1,284 @@
+//! The canonical byte encodings of stubs and of chain state, so two nodes that hold the
+//! same state produce the same bytes (and so the same bank hash and snapshot) whatever
+//! their platform or the order things happened in. Integers are little-endian and fixed
+//! width (`usize` fields as u64), strings and byte strings carry a u32 length, optional
+//! parts a 0/1 flag, and maps are written in ascending key order.
+//!
+//! A stub is `AccountStub::encode`: owner, lamports, merkle_root, executable,
+//! data_len, rent_epoch, chunk_size, hash_algo id, arity, append_only, then the dirty
+//! bitmap, multisig, timelock, PDA seeds and compression, each behind its flag.
+//! Decoding accepts only what encoding writes: every flag is 0 or 1, a flag of 0 has
+//! zeros behind it, and nothing follows the last field.
+//!
+//! A snapshot is `SNAPSHOT_MAGIC`, `SNAPSHOT_VERSION`, the slot, then u32-counted lists
+//! of recent blockhashes (oldest first), `(pubkey, stub)` pairs, hot accounts
+//! `(pubkey, owner, lamports, data)` and status-cache entries `(blockhash, tx ids)`.
+//! It holds what the state root and the status cache cover; bank headers, optimistic
+//! stakes, the rent ledger, storage attestations, programs and caches are not in it.
+
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
+
+use crate::compression::{Codec, Compression};
+use crate::dirty::DirtyBitmap;
+use crate::error::WitnessError;
+use crate::multisig::Multisig;
+use crate::pubkey::PdaSeeds;
+use crate::rent::HotAccount;
+use crate::timelock::TimeLock;
+use crate::{AccountStub, ChainState, HashAlgo};
+
+/// Leading bytes of a state snapshot
+pub const SNAPSHOT_MAGIC: &[u8;4] = b"SBSS";
+/// Bumped whenever the snapshot or stub encoding changes
+pub const SNAPSHOT_VERSION: u8 = 1;
+
+/// Reads the fields of a canonical encoding front to back
+pub struct Reader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    pub fn new(bytes: &'a [u8]) -> Self {
+        Self { bytes }
+    }
+
+    pub fn take(&mut self, n: usize) -> Result<&'a [u8], WitnessError> {
+        if self.bytes.len() < n {
+            return Err(WitnessError::Truncated("encoding"));
+        }
+        let (head, rest) = self.bytes.split_at(n);
+        self.bytes = rest;
+        Ok(head)
+    }
+
+    pub fn u8(&mut self) -> Result<u8, WitnessError> {
+        Ok(self.take(1)?[0])
+    }
+
+    pub fn u32(&mut self) -> Result<u32, WitnessError> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    pub fn u64(&mut self) -> Result<u64, WitnessError> {
+        Ok(u64::from_le_bytes(self.take(8)?.try_into().unwrap()))
+    }
+
+    pub fn hash(&mut self) -> Result<[u8;32], WitnessError> {
+        Ok(self.take(32)?.try_into().unwrap())
+    }
+
+    pub fn flag(&mut self) -> Result<bool, WitnessError> {
+        match self.u8()? {
+            0 => Ok(false),
+            1 => Ok(true),
+            b => Err(WitnessError::Malformed(format!("flag byte {} is not 0 or 1", b))),
+        }
+    }
+
+    /// u32 length, then that many bytes
+    pub fn bytes(&mut self) -> Result<&'a [u8], WitnessError> {
+        let len = self.u32()? as usize;
+        self.take(len)
+    }
+
+    pub fn string(&mut self) -> Result<String, WitnessError> {
+        match std::str::from_utf8(self.bytes()?) {
+            Ok(s) => Ok(s.to_string()),
+            Err(_) => Err(WitnessError::Malformed("string is not UTF-8".to_string())),
+        }
+    }
+
+    /// A u32 count, never more items than bytes left to hold them
+    pub fn count(&mut self, min_item_len: usize) -> Result<usize, WitnessError> {
+        let count = self.u32()? as usize;
+        if count.saturating_mul(min_item_len.max(1)) > self.bytes.len() {
+            return Err(WitnessError::Malformed(format!("{} items cannot fit in {} bytes", count, self.bytes.len())));
+        }
+        Ok(count)
+    }
+
+    pub fn finish(self) -> Result<(), WitnessError> {
+        if !self.bytes.is_empty() {
+            return Err(WitnessError::TrailingBytes { what: "encoding", len: self.bytes.len() });
+        }
+        Ok(())
+    }
+}
+
+fn put_bytes(out: &mut Vec<u8>, bytes: &[u8]) {
+    out.extend_from_slice(&(bytes.len() as u32).to_le_bytes());
+    out.extend_from_slice(bytes);
+}
+
+fn usize_field(v: u64, what: &str) -> Result<usize, WitnessError> {
+    usize::try_from(v).map_err(|_| WitnessError::Malformed(format!("{} {} does not fit this platform", what, v)))
+}
+
+/// An optional u64 written as a flag and the value, zero when absent
+fn optional_u64(r: &mut Reader) -> Result<Option<u64>, WitnessError> {
+    let present = r.flag()?;
+    let v = r.u64()?;
+    match present {
+        true => Ok(Some(v)),
+        false if v == 0 => Ok(None),
+        false => Err(WitnessError::Malformed(format!("absent value encoded as {}", v))),
+    }
+}
+
+impl AccountStub {
+    /// Inverse of `encode`, accepting only canonical bytes
+    pub fn decode(bytes: &[u8]) -> Result<Self, WitnessError> {
+        let mut r = Reader::new(bytes);
+        let owner = r.string()?;
+        let lamports = r.u64()?;
+        let merkle_root = r.hash()?;
+        let executable = r.flag()?;
+        let data_len = r.u64()?;
+        let rent_epoch = r.u64()?;
+        let chunk_size = usize_field(r.u64()?, "chunk_size")?;
+        let hash_algo = match HashAlgo::from_id(r.u8()?) {
+            Some(algo) => algo,
+            None => return Err(WitnessError::Malformed("unknown hash algorithm".to_string())),
+        };
+        let arity = r.u8()?;
+        let append_only = r.flag()?;
+        let dirty = match r.flag()? {
+            true => Some(DirtyBitmap { bits: r.hash()?, since_slot: r.u64()? }),
+            false => None,
+        };
+        let multisig = match r.flag()? {
+            true => {
+                let threshold = r.u8()?;
+                let signers = (0..r.u8()?).map(|_| r.hash()).collect::<Result<_, WitnessError>>()?;
+                Some(Multisig::new(threshold, signers)?)
+            }
+            false => None,
+        };
+        let timelock = match r.flag()? {
+            true => {
+                let unlock_slot = optional_u64(&mut r)?;
+                let last_write_epoch = optional_u64(&mut r)?;
+                Some(TimeLock { unlock_slot, last_write_epoch, once_per_epoch: r.flag()? })
+            }
+            false => None,
+        };
+        let pda = match r.flag()? {
+            true => {
+                let count = r.u8()?;
+                let seeds = (0..count).map(|_| {
+                    let len = r.u8()? as usize;
+                    Ok(r.take(len)?.to_vec())
+                }).collect::<Result<_, WitnessError>>()?;
+                Some(PdaSeeds { seeds, bump: r.u8()? })
+            }
+            false => None,
+        };
+        let compression = match r.flag()? {
+            true => {
+                let codec = match Codec::from_id(r.u8()?) {
+                    Some(codec) => codec,
+                    None => return Err(WitnessError::Malformed("unknown compression codec".to_string())),
+                };
+                Some(Compression { codec, raw_len: r.u64()? })
+            }
+            false => None,
+        };
+        r.finish()?;
+        Ok(Self { owner, lamports, merkle_root, executable, data_len, rent_epoch, chunk_size, hash_algo, arity, append_only, dirty, multisig, timelock, pda, compression })
+    }
+}
+
+impl ChainState {
+    /// The chain's state in the canonical snapshot encoding. Refused while a batch is
+    /// open, since its changes are neither in nor out yet.
+    pub fn snapshot(&self) -> Result<Vec<u8>, WitnessError> {
+        if self.staging.is_some() {
+            return Err(WitnessError::SnapshotInBatch);
+        }
+        let mut out = Vec::new();
+        out.extend_from_slice(SNAPSHOT_MAGIC);
+        out.push(SNAPSHOT_VERSION);
+        out.extend_from_slice(&self.slot.to_le_bytes());
+        out.extend_from_slice(&(self.recent_blockhashes.len() as u32).to_le_bytes());
+        for hash in &self.recent_blockhashes {
+            out.extend_from_slice(hash);
+        }
+        out.extend_from_slice(&(self.stubs.len() as u32).to_le_bytes());
+        for (pubkey, stub) in &self.stubs {
+            put_bytes(&mut out, pubkey.as_bytes());
+            put_bytes(&mut out, &stub.encode());
+        }
+        out.extend_from_slice(&(self.hot_accounts.len() as u32).to_le_bytes());
+        for (pubkey, account) in &self.hot_accounts {
+            put_bytes(&mut out, pubkey.as_bytes());
+            put_bytes(&mut out, account.owner.as_bytes());
+            out.extend_from_slice(&account.lamports.to_le_bytes());
+            put_bytes(&mut out, &account.data);
+        }
+        out.extend_from_slice(&(self.status_cache.len() as u32).to_le_bytes());
+        for (blockhash, ids) in &self.status_cache {
+            out.extend_from_slice(blockhash);
+            out.extend_from_slice(&(ids.len() as u32).to_le_bytes());
+            for id in ids {
+                out.extend_from_slice(id);
+            }
+        }
+        Ok(out)
+    }
+
+    /// A chain holding the state `snapshot` encodes, with no programs registered and
+    /// default configuration. Keys out of order or repeated are rejected, so a snapshot
+    /// has only one encoding.
+    pub fn from_snapshot(bytes: &[u8]) -> Result<Self, WitnessError> {
+        let mut r = Reader::new(bytes);
+        if r.take(4)? != SNAPSHOT_MAGIC {
+            return Err(WitnessError::BadMagic("state snapshot"));
+        }
+        let version = r.u8()?;
+        if version != SNAPSHOT_VERSION {
+            return Err(WitnessError::UnsupportedVersion { what: "snapshot", version });
+        }
+        let mut chain = ChainState::new();
+        chain.slot = r.u64()?;
+        let count = r.count(32)?;
+        chain.recent_blockhashes = (0..count).map(|_| r.hash()).collect::<Result<VecDeque<_>, WitnessError>>()?;
+        if chain.recent_blockhashes.is_empty() {
+            return Err(WitnessError::Malformed("snapshot has no recent blockhash".to_string()));
+        }
+        let count = r.count(8)?;
+        for _ in 0..count {
+            let pubkey = r.string()?;
+            let stub = AccountStub::decode(r.bytes()?).map_err(|e| WitnessError::Malformed(format!("stub of {}: {}", pubkey, e)))?;
+            insert_ordered(&mut chain.stubs, pubkey, stub)?;
+        }
+        let count = r.count(20)?;
+        for _ in 0..count {
+            let pubkey = r.string()?;
+            let account = HotAccount { owner: r.string()?, lamports: r.u64()?, data: r.bytes()?.to_vec() };
+            insert_ordered(&mut chain.hot_accounts, pubkey, account)?;
+        }
+        let count = r.count(36)?;
+        for _ in 0..count {
+            let blockhash = r.hash()?;
+            let mut ids = BTreeSet::new();
+            for _ in 0..r.count(32)? {
+                let id = r.hash()?;
+                if ids.last().is_some_and(|last| *last >= id) {
+                    return Err(WitnessError::Malformed("status cache ids are not in ascending order".to_string()));
+                }
+                ids.insert(id);
+            }
+            insert_ordered(&mut chain.status_cache, blockhash, ids)?;
+        }
+        r.finish()?;
+        Ok(chain)
+    }
+}
+
+fn insert_ordered<K: Ord + std::fmt::Debug, V>(map: &mut BTreeMap<K, V>, key: K, value: V) -> Result<(), WitnessError> {
+    if map.last_key_value().is_some_and(|(last, _)| *last >= key) {
+        return Err(WitnessError::Malformed(format!("snapshot key {:?} is out of order or repeated", key)));
+    }
+    map.insert(key, value);
+    Ok(())
+}
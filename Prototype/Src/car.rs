@@ -0,0 +1,300 @@
+//! CAR (CARv1) export of a committed blob, for IPFS tooling to import, pin and serve
+//! as is (`ipfs dag import blob.car`). The DAG is the Merkle tree itself: every chunk
+//! is a `raw` block, so its CID's SHA-256 digest is exactly our leaf hash, and every
+//! node above is a DAG-CBOR list of its two children's links, left first. Padding
+//! leaves are the last chunk and dedupe to its block; `ipfs dag get <root>/1/0` walks
+//! down the same path a proof climbs.
+//!
+//! Node CIDs hash their CBOR, not the pair of child hashes, so only leaf CIDs carry
+//! tree hashes. `read` walks a CAR back down from its root and rebuilds the Merkle root
+//! from the leaves it reaches.
+
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+
+use anyhow::{Context, Result, bail};
+
+use crate::error::WitnessError;
+use crate::{chunk_blob, sha256, MerkleTree};
+
+/// Multicodec of a chunk block
+const RAW: u64 = 0x55;
+/// Multicodec of a node block
+const DAG_CBOR: u64 = 0x71;
+/// Multihash prefix of a SHA-256 digest: code 0x12, length 32
+const SHA2_256: [u8;2] = [0x12, 0x20];
+/// CBOR tag of an IPLD link
+const CID_TAG: [u8;2] = [0xd8, 0x2a];
+
+/// A CIDv1 with a SHA-256 multihash, in its binary form
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Cid {
+    pub codec: u64,
+    pub digest: [u8;32],
+}
+
+fn push_varint(out: &mut Vec<u8>, mut n: u64) {
+    while n >= 0x80 {
+        out.push(n as u8 | 0x80);
+        n >>= 7;
+    }
+    out.push(n as u8);
+}
+
+fn read_varint(bytes: &mut &[u8]) -> Result<u64, WitnessError> {
+    let mut n = 0u64;
+    for shift in (0..64).step_by(7) {
+        let Some((&b, rest)) = bytes.split_first() else { return Err(WitnessError::Truncated("CAR varint")) };
+        *bytes = rest;
+        n |= ((b & 0x7f) as u64) << shift;
+        if b & 0x80 == 0 {
+            return Ok(n);
+        }
+    }
+    Err(WitnessError::Malformed("varint longer than 64 bits".to_string()))
+}
+
+fn take<'a>(bytes: &mut &'a [u8], n: usize) -> Result<&'a [u8], WitnessError> {
+    if bytes.len() < n {
+        return Err(WitnessError::Truncated("CAR"));
+    }
+    let (head, rest) = bytes.split_at(n);
+    *bytes = rest;
+    Ok(head)
+}
+
+impl Cid {
+    fn of(codec: u64, block: &[u8]) -> Self {
+        Self { codec, digest: sha256(block) }
+    }
+
+    pub fn to_bytes(self) -> Vec<u8> {
+        let mut out = vec![0x01];
+        push_varint(&mut out, self.codec);
+        out.extend_from_slice(&SHA2_256);
+        out.extend_from_slice(&self.digest);
+        out
+    }
+
+    fn read(bytes: &mut &[u8]) -> Result<Self, WitnessError> {
+        if read_varint(bytes)? != 1 {
+            return Err(WitnessError::Malformed("only CIDv1 is supported".to_string()));
+        }
+        let codec = read_varint(bytes)?;
+        if take(bytes, 2)? != SHA2_256 {
+            return Err(WitnessError::Malformed("only sha2-256 multihashes are supported".to_string()));
+        }
+        Ok(Self { codec, digest: take(bytes, 32)?.try_into().unwrap() })
+    }
+
+    /// Base32 multibase string, the form `ipfs` prints (`bafk...` for raw, `bafy...`
+    /// for DAG-CBOR)
+    pub fn to_text(self) -> String {
+        const ALPHABET: &[u8;32] = b"abcdefghijklmnopqrstuvwxyz234567";
+        let bytes = self.to_bytes();
+        let mut out = String::from("b");
+        let (mut acc, mut bits) = (0u32, 0);
+        for b in bytes {
+            acc = (acc << 8) | b as u32;
+            bits += 8;
+            while bits >= 5 {
+                bits -= 5;
+                out.push(ALPHABET[(acc >> bits) as usize & 31] as char);
+            }
+        }
+        if bits > 0 {
+            out.push(ALPHABET[(acc << (5 - bits)) as usize & 31] as char);
+        }
+        out
+    }
+}
+
+fn push_link(out: &mut Vec<u8>, cid: &Cid) {
+    let bytes = cid.to_bytes();
+    out.extend_from_slice(&CID_TAG);
+    // byte string of the CID behind the multibase identity prefix
+    out.extend_from_slice(&[0x58, bytes.len() as u8 + 1, 0x00]);
+    out.extend_from_slice(&bytes);
+}
+
+fn read_link(bytes: &mut &[u8]) -> Result<Cid, WitnessError> {
+    if take(bytes, 2)? != CID_TAG {
+        return Err(WitnessError::Malformed("expected a CID link".to_string()));
+    }
+    let head = take(bytes, 3)?;
+    if head[0] != 0x58 || head[2] != 0x00 {
+        return Err(WitnessError::Malformed("malformed CID link".to_string()));
+    }
+    let mut cid = take(bytes, head[1] as usize - 1)?;
+    let parsed = Cid::read(&mut cid)?;
+    if !cid.is_empty() {
+        return Err(WitnessError::Malformed("trailing bytes in CID link".to_string()));
+    }
+    Ok(parsed)
+}
+
+/// DAG-CBOR node over two children: a two-element list of links
+fn node_block(left: &Cid, right: &Cid) -> Vec<u8> {
+    let mut out = vec![0x82];
+    push_link(&mut out, left);
+    push_link(&mut out, right);
+    out
+}
+
+/// DAG-CBOR `{"roots": [root], "version": 1}`, keys in canonical order
+fn header(root: &Cid) -> Vec<u8> {
+    let mut out = vec![0xa2, 0x65];
+    out.extend_from_slice(b"roots");
+    out.push(0x81);
+    push_link(&mut out, root);
+    out.push(0x67);
+    out.extend_from_slice(b"version");
+    out.push(0x01);
+    out
+}
+
+fn push_section(out: &mut Vec<u8>, data: &[&[u8]]) {
+    push_varint(out, data.iter().map(|d| d.len() as u64).sum());
+    for d in data {
+        out.extend_from_slice(d);
+    }
+}
+
+/// What an export wrote
+#[derive(Debug, Clone)]
+pub struct Exported {
+    pub root_cid: Cid,
+    pub merkle_root: [u8;32],
+    /// Distinct blocks, chunks and nodes
+    pub blocks: usize,
+    pub bytes: Vec<u8>,
+}
+
+/// `blob` split into `chunk_size` chunks as a CAR, root node first and then depth
+/// first, left before right
+pub fn export(blob: &[u8], chunk_size: usize) -> Result<Exported, WitnessError> {
+    if chunk_size == 0 {
+        return Err(WitnessError::ZeroChunkSize);
+    }
+    let chunks = chunk_blob(blob, chunk_size);
+    if chunks.is_empty() {
+        return Err(WitnessError::NoLeaves);
+    }
+    let tree = MerkleTree::from_chunks(&chunks);
+    let depth = tree.depth();
+    // padding leaves repeat the last chunk
+    let chunk = |i: usize| chunks.get(i.min(chunks.len() - 1)).unwrap();
+    let mut cids: Vec<Vec<Cid>> = vec![tree.leaves().iter().map(|&digest| Cid { codec: RAW, digest }).collect()];
+    for level in 1..=depth {
+        let below = &cids[level - 1];
+        let layer = below.chunks(2).map(|pair| Cid::of(DAG_CBOR, &node_block(&pair[0], &pair[1]))).collect();
+        cids.push(layer);
+    }
+    let root_cid = cids[depth][0];
+
+    let mut bytes = Vec::new();
+    push_section(&mut bytes, &[&header(&root_cid)]);
+    let mut written = HashSet::new();
+    let mut stack = vec![(depth, 0usize)];
+    while let Some((level, i)) = stack.pop() {
+        let cid = cids[level][i];
+        if !written.insert(cid) {
+            continue;
+        }
+        let cid_bytes = cid.to_bytes();
+        if level == 0 {
+            push_section(&mut bytes, &[&cid_bytes[..], chunk(i)]);
+        } else {
+            push_section(&mut bytes, &[&cid_bytes[..], &node_block(&cids[level - 1][2 * i], &cids[level - 1][2 * i + 1])]);
+            stack.push((level - 1, 2 * i + 1));
+            stack.push((level - 1, 2 * i));
+        }
+    }
+    Ok(Exported { root_cid, merkle_root: tree.root(), blocks: written.len(), bytes })
+}
+
+/// A CAR read back down from its root
+#[derive(Debug, Clone)]
+pub struct Imported {
+    pub root_cid: Cid,
+    pub merkle_root: [u8;32],
+    pub blocks: usize,
+    /// Leaves left to right, padding included
+    pub leaves: Vec<Vec<u8>>,
+}
+
+/// Parse a CAR written by `export`, checking every block against its CID, and walk
+/// the DAG from its root gathering the chunks
+pub fn read(mut bytes: &[u8]) -> Result<Imported, WitnessError> {
+    let b = &mut bytes;
+    let len = read_varint(b)? as usize;
+    let mut head = take(b, len)?;
+    let expected = header(&Cid { codec: DAG_CBOR, digest: [0; 32] });
+    // the root link sits at a fixed offset in the only header `export` writes
+    let link_at = 2 + 5 + 1;
+    if head.len() != expected.len() || head[..link_at] != expected[..link_at] || head[head.len() - 9..] != expected[expected.len() - 9..] {
+        return Err(WitnessError::Malformed("unsupported CAR header: expected version 1 with a single root".to_string()));
+    }
+    let h = &mut head;
+    take(h, link_at)?;
+    let root_cid = read_link(h)?;
+
+    let mut blocks = HashMap::new();
+    while !b.is_empty() {
+        let len = read_varint(b)? as usize;
+        let mut section = take(b, len)?;
+        let cid = Cid::read(&mut section)?;
+        if sha256(section) != cid.digest {
+            return Err(WitnessError::Malformed(format!("block {} does not hash to its CID", cid.to_text())));
+        }
+        blocks.insert(cid, section);
+    }
+
+    let mut leaves = Vec::new();
+    let mut stack = vec![(root_cid, 0usize)];
+    while let Some((cid, level)) = stack.pop() {
+        let block = match blocks.get(&cid) {
+            Some(block) => *block,
+            None => return Err(WitnessError::Malformed(format!("CAR has no block {}", cid.to_text()))),
+        };
+        match cid.codec {
+            RAW => leaves.push(block.to_vec()),
+            DAG_CBOR if level < 64 => {
+                let mut node = block;
+                if take(&mut node, 1)? != [0x82] {
+                    return Err(WitnessError::Malformed(format!("node {} is not a two-link list", cid.to_text())));
+                }
+                let (left, right) = (read_link(&mut node)?, read_link(&mut node)?);
+                if !node.is_empty() {
+                    return Err(WitnessError::Malformed(format!("trailing bytes in node {}", cid.to_text())));
+                }
+                stack.push((right, level + 1));
+                stack.push((left, level + 1));
+            }
+            DAG_CBOR => return Err(WitnessError::Malformed("DAG deeper than 64 levels".to_string())),
+            other => return Err(WitnessError::Malformed(format!("block {} has unexpected codec {:#x}", cid.to_text(), other))),
+        }
+    }
+    if !leaves.len().is_power_of_two() {
+        return Err(WitnessError::Malformed(format!("DAG has {} leaves, not a full binary tree", leaves.len())));
+    }
+    let merkle_root = MerkleTree::from_chunks(&leaves).root();
+    Ok(Imported { root_cid, merkle_root, blocks: blocks.len(), leaves })
+}
+
+/// `export-car`: write `blob` as a CAR at `out` and read it back
+pub fn run(blob: &[u8], chunk_size: usize, out: &Path) -> Result<()> {
+    let exported = export(blob, chunk_size)?;
+    std::fs::write(out, &exported.bytes).with_context(|| format!("writing {}", out.display()))?;
+    let imported = read(&exported.bytes)?;
+    if imported.root_cid != exported.root_cid || imported.blocks != exported.blocks {
+        bail!("CAR reads back as {} blocks under {}, not the {} written", imported.blocks, imported.root_cid.to_text(), exported.blocks);
+    }
+    if imported.merkle_root != exported.merkle_root {
+        bail!("CAR reads back to a different Merkle root");
+    }
+    println!("wrote {} ({} bytes, {} blocks)", out.display(), exported.bytes.len(), exported.blocks);
+    println!("root CID    {}", exported.root_cid.to_text());
+    println!("merkle root {}", hex::encode(exported.merkle_root));
+    Ok(())
+}
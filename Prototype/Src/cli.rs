@@ -0,0 +1,1092 @@
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result, bail};
+use base64::Engine;
+use clap::{Args, Parser, Subcommand};
+use serde_json::json;
+
+use crate::blob_file::BlobFile;
+use crate::blob_store::BlobStore;
+use crate::compression::Codec;
+use crate::compute::{ComputeCosts, SyscallHasher, DEFAULT_COMPUTE_BUDGET};
+use crate::config::Config;
+use crate::diagnose::NodeSource;
+use crate::cost::{AccountProfile, CostModel};
+use crate::keypair;
+use crate::mapped::{self, MappedBlob};
+use crate::packer::{Packer, MAX_PACKED_CHUNK};
+use crate::program::MAX_ACCOUNT_DATA_LEN;
+use crate::proof_file::{OutputFormat, ProofFile};
+use crate::signer::Signer;
+use crate::sim_metrics::ExportFormat;
+use crate::view::ChunkWitness;
+use crate::witness_file::{self, WitnessFile};
+use crate::workload::WorkloadConfig;
+use crate::{chunk_blob, h, MerkleTree};
+
+/// Commit large account data as Merkle roots and work with chunk proofs
+#[derive(Debug, Parser)]
+#[command(name = "solana-bloat", version, arg_required_else_help = true)]
+pub struct Cli {
+    /// Config file with defaults (otherwise ./solana-bloat.toml, if present)
+    #[arg(long, global = true)]
+    pub config: Option<PathBuf>,
+    /// Log spans and events to stderr, filtered like RUST_LOG (e.g. `info`,
+    /// `solana_bloat=debug`); RUST_LOG itself works too. Off by default.
+    #[arg(long, global = true, value_name = "FILTER")]
+    pub log: Option<String>,
+    #[command(subcommand)]
+    pub command: Command,
+}
+
+/// How a blob is split into leaves
+#[derive(Debug, Clone, Args)]
+pub struct TreeArgs {
+    /// Bytes per leaf; the last chunk is zero-padded [default: config chunk_size]
+    #[arg(long)]
+    pub chunk_size: Option<usize>,
+}
+
+impl TreeArgs {
+    fn chunk_size(&self, cfg: &Config) -> usize {
+        self.chunk_size.unwrap_or(cfg.chunk_size)
+    }
+}
+
+/// Who signs: a keypair file or, with the `remote-signer` feature, a signing service
+#[derive(Debug, Clone, Args)]
+pub struct SignerArgs {
+    /// Keypair file [default: ~/.config/solana/id.json]
+    #[arg(long)]
+    pub keypair: Option<PathBuf>,
+    /// URL of a signing service to use instead of a keypair file
+    #[cfg(feature = "remote-signer")]
+    #[arg(long, conflicts_with = "keypair")]
+    pub remote_signer: Option<String>,
+}
+
+impl SignerArgs {
+    /// The signer asked for, else the default keypair file if there is one
+    fn resolve(&self) -> Result<Option<Box<dyn Signer>>> {
+        #[cfg(feature = "remote-signer")]
+        if let Some(url) = &self.remote_signer {
+            return Ok(Some(Box::new(crate::signer::RemoteSigner::connect(url)?)));
+        }
+        match self.keypair.clone().or_else(|| keypair::default_keypair_path().filter(|p| p.is_file())) {
+            Some(path) => Ok(Some(Box::new(keypair::read_keypair_file(&path)?))),
+            None => Ok(None),
+        }
+    }
+
+    fn signer(&self) -> Result<Box<dyn Signer>> {
+        self.resolve()?.context("no --keypair given and no ~/.config/solana/id.json")
+    }
+}
+
+/// Where `diagnose` gets the nodes of the tree the root commits to
+#[derive(Debug, Clone, Args)]
+pub struct ReferenceArgs {
+    /// Known-good copy: plain file, `commit --container` file or `export-car` output (`.car`)
+    #[arg(long)]
+    pub reference: Option<PathBuf>,
+    /// Proof server to fetch chunk proofs of `--pubkey` from instead
+    #[cfg(feature = "client")]
+    #[arg(long, conflicts_with = "reference", requires = "pubkey")]
+    pub url: Option<String>,
+    #[cfg(feature = "client")]
+    #[arg(long, requires = "url")]
+    pub pubkey: Option<String>,
+}
+
+impl ReferenceArgs {
+    fn source(&self, root: [u8;32], chunk_size: usize, cfg: &Config) -> Result<Box<dyn NodeSource>> {
+        #[cfg(feature = "client")]
+        if let (Some(url), Some(pubkey)) = (&self.url, &self.pubkey) {
+            let client = crate::client::WitnessClient::new(url)?;
+            let runtime = tokio::runtime::Builder::new_current_thread().enable_all().build()?;
+            let remote = runtime.block_on(client.get_root(pubkey))?;
+            if remote.root != root {
+                bail!("{} holds root {}, not {}", pubkey, h(&remote.root), h(&root));
+            }
+            let pubkey = pubkey.clone();
+            let fetch = move |leaf| runtime.block_on(client.get_proof_against(&pubkey, leaf, &root, remote.chunk_count));
+            return Ok(Box::new(crate::diagnose::ProofNodes::new(root, remote.chunk_count, fetch)));
+        }
+        let Some(path) = &self.reference else { bail!("diagnose needs a --reference to compare against") };
+        let tree = if path.extension().is_some_and(|ext| ext == "car") {
+            MerkleTree::from_chunks(&crate::car::read(&read_input(path)?)?.leaves)
+        } else {
+            let (blob, _) = open_input(path, &TreeArgs { chunk_size: Some(chunk_size) }, cfg)?;
+            tree_of(&blob, chunk_size)?.1
+        };
+        if tree.root() != root {
+            bail!("{} has root {}, not {}", path.display(), h(&tree.root()), h(&root));
+        }
+        Ok(Box::new(tree))
+    }
+}
+
+/// Which blob store `archive` writes to
+#[derive(Debug, Clone, Args)]
+pub struct StoreArgs {
+    /// `fs:<dir>`, or with their features `ipfs:<api url>`, `arweave:<bundler url>,<gateway url>`
+    /// or an object store URL such as `s3://bucket/prefix`
+    #[arg(long)]
+    pub store: String,
+    /// Where an IPFS or Arweave store keeps its root index, so later runs can fetch by root
+    #[cfg(any(feature = "ipfs", feature = "arweave"))]
+    #[arg(long)]
+    pub index: Option<PathBuf>,
+    /// Key that signs Arweave uploads [default: ~/.config/solana/id.json]
+    #[cfg(feature = "arweave")]
+    #[arg(long)]
+    pub keypair: Option<PathBuf>,
+}
+
+impl StoreArgs {
+    /// Store `blob` under `root`, read it back and print where it went
+    fn archive(&self, root: &[u8;32], blob: &[u8], chunk_size: usize) -> Result<()> {
+        let Some((kind, target)) = self.store.split_once(':') else { bail!("--store {:?} does not start with a kind such as fs:", self.store) };
+        match kind {
+            "fs" => round_trip(&crate::blob_store::FsBlobStore::new(target)?, root, blob, chunk_size),
+            #[cfg(feature = "ipfs")]
+            "ipfs" => {
+                let mut store = crate::ipfs_store::IpfsBlobStore::new(target, chunk_size);
+                if let Some(path) = &self.index {
+                    store = store.with_index_file(path)?;
+                }
+                round_trip(&store, root, blob, chunk_size)?;
+                println!("cid {}", store.cid(root).unwrap_or_default());
+                Ok(())
+            }
+            #[cfg(feature = "arweave")]
+            "arweave" => {
+                let Some((bundler, gateway)) = target.split_once(',') else { bail!("an arweave store is arweave:<bundler url>,<gateway url>") };
+                let path = self.keypair.clone().or_else(keypair::default_keypair_path).context("no --keypair given and no ~/.config/solana/id.json")?;
+                let mut store = crate::arweave_store::ArweaveBlobStore::new(bundler, gateway, keypair::read_keypair_file(&path)?, chunk_size);
+                if let Some(path) = &self.index {
+                    store = store.with_index_file(path)?;
+                }
+                round_trip(&store, root, blob, chunk_size)?;
+                println!("arweave tx {}", store.tx_id(root).unwrap_or_default());
+                Ok(())
+            }
+            #[cfg(feature = "s3")]
+            _ if target.starts_with("//") => {
+                let store = crate::s3_store::ObjectBlobStore::new(&self.store, &[], crate::s3_store::ObjectStoreConfig::new(chunk_size))?;
+                round_trip(&store, root, blob, chunk_size)
+            }
+            other => bail!("unknown blob store kind {:?}", other),
+        }
+    }
+}
+
+/// Put `blob` in `store` and fetch it back by `root`, holding what comes back to the root
+fn round_trip(store: &dyn BlobStore, root: &[u8;32], blob: &[u8], chunk_size: usize) -> Result<()> {
+    store.put(root, blob)?;
+    if !store.has(root)? {
+        bail!("store has no blob {} right after storing it", h(root));
+    }
+    let fetched = store.get(root)?.with_context(|| format!("store lost blob {}", h(root)))?;
+    if tree_of(&fetched, chunk_size)?.1.root() != *root {
+        bail!("store returned bytes that do not commit to {}", h(root));
+    }
+    Ok(())
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Print the Merkle root a stub would store for a file
+    Commit {
+        file: PathBuf,
+        #[command(flatten)]
+        tree: TreeArgs,
+        /// `json` adds the blob's shape; `binary` writes the raw 32 bytes
+        #[arg(long, value_enum, default_value_t = OutputFormat::Hex)]
+        output: OutputFormat,
+        /// Also write the committed bytes and their chunk size, count and root here, for
+        /// `prove` and `verify --root` to read back
+        #[arg(long, value_name = "PATH")]
+        container: Option<PathBuf>,
+        /// Compress the bytes before committing them; the root covers the compressed bytes
+        #[arg(long, value_enum, requires = "container")]
+        compress: Option<Codec>,
+    },
+    /// Print a chunk of a file with its inclusion proof
+    Prove {
+        /// Plain file, or a `commit --container` file, which brings its own chunk size
+        file: PathBuf,
+        #[arg(long)]
+        leaf: usize,
+        #[command(flatten)]
+        tree: TreeArgs,
+        #[arg(long, value_enum, default_value_t = OutputFormat::Json)]
+        output: OutputFormat,
+        /// Write the proof as a `.witness` file here instead of printing it
+        #[arg(long, value_name = "PATH", conflicts_with = "output")]
+        witness: Option<PathBuf>,
+    },
+    /// Check a proof file (any `prove` output format) against a root
+    Verify {
+        /// Root the proof must lead to: hex, base64 or base58, a file holding `commit` output
+        /// in any format, or a `commit --container` file. A container or JSON output also
+        /// gives the chunk size and count the proof must match.
+        #[arg(long)]
+        root: String,
+        /// Proof file, or `-` for stdin
+        #[arg(long)]
+        proof: PathBuf,
+        /// Chunks the root commits to, when `--root` does not say
+        #[arg(long)]
+        chunk_count: Option<usize>,
+    },
+    /// Check a `.witness` file against the root it carries and print its header
+    VerifyFile {
+        file: PathBuf,
+        /// Root the witness must also be for, in any form `verify --root` takes. A container
+        /// or JSON output also gives the leaf count the witness must claim.
+        #[arg(long)]
+        root: Option<String>,
+        /// Chunks the root commits to, when `--root` does not say
+        #[arg(long, requires = "root")]
+        chunk_count: Option<usize>,
+    },
+    /// Overwrite bytes of a file in place and show how its root changes
+    Update {
+        file: PathBuf,
+        #[arg(long)]
+        offset: usize,
+        /// Hex bytes to write at `offset`; the file grows if they run past its end
+        #[arg(long)]
+        hex: String,
+        #[command(flatten)]
+        tree: TreeArgs,
+    },
+    /// Show the shape of a file's tree: chunks, padding, depth and layer hashes
+    Inspect {
+        file: PathBuf,
+        #[command(flatten)]
+        tree: TreeArgs,
+        /// Print the tree as a Graphviz digraph instead (`| dot -Tsvg > tree.svg`)
+        #[arg(long)]
+        dot: bool,
+        /// Highlight this chunk's proof path in the digraph
+        #[arg(long, requires = "dot")]
+        leaf: Option<usize>,
+    },
+    /// Write a file's chunks and tree as a CAR for `ipfs dag import`
+    ExportCar {
+        /// Plain file, or a `commit --container` file, which brings its own chunk size
+        file: PathBuf,
+        #[command(flatten)]
+        tree: TreeArgs,
+        #[arg(long, value_name = "PATH")]
+        out: PathBuf,
+    },
+    /// Put a file in a blob store and read it back against its root
+    Archive {
+        /// Plain file, or a `commit --container` file, which brings its own chunk size
+        file: PathBuf,
+        #[command(flatten)]
+        tree: TreeArgs,
+        #[command(flatten)]
+        store: StoreArgs,
+    },
+    /// Find the chunks where a file and the root it should commit to differ
+    Diagnose {
+        /// Plain file, or a `commit --container` file, which brings its own chunk size
+        file: PathBuf,
+        #[command(flatten)]
+        tree: TreeArgs,
+        /// Root the file should commit to, in any form `verify --root` takes
+        #[arg(long)]
+        root: String,
+        #[command(flatten)]
+        reference: ReferenceArgs,
+    },
+    /// Predict the compute units of verifying one chunk on chain, per chunk size
+    Estimate {
+        /// Account data size in bytes
+        #[arg(long)]
+        data_len: u64,
+        /// Only this chunk size [default: powers of two from 32 to 4096]
+        #[arg(long)]
+        chunk_size: Option<usize>,
+        /// sha256, keccak256, blake3 or poseidon
+        #[arg(long, default_value = "sha256")]
+        hasher: String,
+        /// Levels the program keeps on chain [default: config canopy_depth]
+        #[arg(long)]
+        canopy_depth: Option<usize>,
+        #[arg(long, default_value_t = DEFAULT_COMPUTE_BUDGET)]
+        budget: u64,
+    },
+    /// Report witness sizes, proof depths and on-chain savings for a directory of
+    /// account dumps, across chunk sizes and arities
+    Analyze {
+        /// Raw account data files, or `solana account --output json` dumps
+        dir: PathBuf,
+        /// [default: powers of two from 32 to 4096]
+        #[arg(long, value_delimiter = ',')]
+        chunk_sizes: Vec<usize>,
+        /// [default: 2,4,8,16]
+        #[arg(long, value_delimiter = ',')]
+        arities: Vec<u8>,
+        /// Also write every row as CSV here
+        #[arg(long)]
+        csv: Option<PathBuf>,
+    },
+    /// Commit a directory of account dumps under every commitment scheme with a backend
+    /// and compare witness sizes and commit, open and verify times
+    CompareSchemes {
+        /// Raw account data files, or `solana account --output json` dumps
+        dir: PathBuf,
+        #[command(flatten)]
+        tree: TreeArgs,
+        /// Chunks opened and verified per account, spread evenly over it
+        #[arg(long, default_value_t = 16)]
+        openings: usize,
+    },
+    /// Compare a year of keeping an account on chain against offloading it with
+    /// witnesses, and find the update rate where they break even
+    Cost {
+        /// Account data size in bytes
+        #[arg(long)]
+        data_len: u64,
+        #[arg(long, default_value_t = 1.0)]
+        updates_per_day: f64,
+        /// Bytes each update writes
+        #[arg(long, default_value_t = 32)]
+        bytes_per_update: usize,
+        #[command(flatten)]
+        tree: TreeArgs,
+        /// Priority fee in micro-lamports per compute unit
+        #[arg(long, default_value_t = 0)]
+        priority_fee: u64,
+        /// Yearly return forgone on locked rent deposits
+        #[arg(long, default_value_t = 0.07)]
+        capital_rate: f64,
+    },
+    /// Create or inspect solana-keygen compatible keypair files
+    Keygen {
+        #[command(subcommand)]
+        command: KeygenCommand,
+    },
+    /// Sign a witness transaction given as `sendWitnessTransaction` JSON
+    SignTx {
+        /// Transaction file, or `-` for stdin
+        tx: PathBuf,
+        #[command(flatten)]
+        signer: SignerArgs,
+    },
+    /// Write or check the golden test vectors other implementations are held to
+    Vectors {
+        #[command(subcommand)]
+        command: VectorsCommand,
+    },
+    /// Explore a tree interactively: load, prove, tamper and re-verify
+    Repl,
+    /// Run the built-in walkthrough, load a genesis file and print its state, or run a
+    /// workload for `--slots` slots with and without witness offloading and compare them
+    Simulate {
+        #[arg(long, conflicts_with_all = ["slots", "workload"])]
+        genesis: Option<String>,
+        /// Slots to run the workload for
+        #[arg(long)]
+        slots: Option<u64>,
+        /// Workload TOML [default: the built-in mix, see `workload`]
+        #[arg(long)]
+        workload: Option<PathBuf>,
+        #[arg(long, default_value_t = 100)]
+        ops_per_slot: usize,
+        /// Write the run's metrics here at the end, for node_exporter's textfile collector
+        #[arg(long, conflicts_with_all = ["slots", "workload"])]
+        metrics_out: Option<PathBuf>,
+        /// Stream per-slot and per-transaction metrics of a `--slots` run to files
+        /// in this directory (see `sim_metrics` for the columns)
+        #[arg(long, conflicts_with_all = ["genesis", "metrics_out"])]
+        export_dir: Option<PathBuf>,
+        #[arg(long, value_enum, default_value_t = ExportFormat::Csv, requires = "export_dir")]
+        export_format: ExportFormat,
+    },
+    /// Serve proofs, JSON-RPC and subscriptions for a genesis over HTTP
+    #[cfg(feature = "http")]
+    Serve {
+        genesis: String,
+        /// [default: config rpc.http]
+        addr: Option<String>,
+        /// Keep only the leaf hashes of served trees, recomputing the layers above
+        /// as proofs are asked for
+        #[arg(long)]
+        pruned: bool,
+    },
+    /// Experimental: prove a batch of chunk writes with one Groth16 proof over a Poseidon
+    /// tree and report its constraints, prover and verifier times and sizes
+    #[cfg(feature = "zk")]
+    Zk {
+        /// Account data size in bytes
+        #[arg(long, default_value_t = 4096)]
+        data_len: usize,
+        #[command(flatten)]
+        tree: TreeArgs,
+        /// Chunks written in the batch
+        #[arg(long, default_value_t = 1)]
+        writes: usize,
+    },
+    /// Commit a file's root and send one chunk's proof in memo transactions on a real
+    /// cluster, then read both back and verify
+    #[cfg(feature = "devnet")]
+    Devnet {
+        file: PathBuf,
+        /// Name the root is committed under
+        #[arg(long)]
+        account: String,
+        #[arg(long, default_value_t = 0)]
+        leaf: usize,
+        #[command(flatten)]
+        tree: TreeArgs,
+        #[arg(long, default_value = crate::devnet::DEVNET_URL)]
+        url: String,
+        /// Payer; with --dry-run and no keypair anywhere, a fresh one
+        #[command(flatten)]
+        signer: SignerArgs,
+        /// Build and sign the transactions but do not send them
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// Fetch a chunk proof from a proof server, verify it and print it as a proof file
+    #[cfg(feature = "client")]
+    Fetch {
+        pubkey: String,
+        #[arg(long)]
+        leaf: usize,
+        /// Server base URL [default: http:// + config rpc.http]
+        #[arg(long)]
+        url: Option<String>,
+        /// Hex root the proof must be against, instead of the one the server reports
+        #[arg(long, requires = "chunk_count")]
+        root: Option<String>,
+        /// Chunks `--root` commits to, which fixes the proof's depth
+        #[arg(long, requires = "root")]
+        chunk_count: Option<usize>,
+        #[arg(long, value_enum, default_value_t = OutputFormat::Json)]
+        output: OutputFormat,
+    },
+    /// Show an account's current root, shape and data locations on a proof server
+    #[cfg(feature = "client")]
+    Lookup {
+        pubkey: String,
+        /// Server base URL [default: http:// + config rpc.http]
+        #[arg(long)]
+        url: Option<String>,
+    },
+    /// Submit a witness transaction (`sendWitnessTransaction` JSON) to a proof server,
+    /// filling in the server's latest blockhash if it has none and signing it if a
+    /// signer is given
+    #[cfg(feature = "client")]
+    Send {
+        /// Transaction file, or `-` for stdin
+        tx: PathBuf,
+        /// Server base URL [default: http:// + config rpc.http]
+        #[arg(long)]
+        url: Option<String>,
+        #[command(flatten)]
+        signer: SignerArgs,
+        /// Attempts after the first when the server is unreachable or overloaded
+        #[arg(long, default_value_t = 3)]
+        retries: u32,
+    },
+    /// Serve the `witness.v1.Witness` gRPC service for a genesis
+    #[cfg(feature = "grpc")]
+    Grpc {
+        genesis: String,
+        /// [default: config rpc.grpc]
+        addr: Option<String>,
+        /// Keep only the leaf hashes of served trees, recomputing the layers above
+        /// as proofs are asked for
+        #[arg(long)]
+        pruned: bool,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+pub enum KeygenCommand {
+    /// Generate a keypair and print its public key
+    New {
+        /// [default: ~/.config/solana/id.json]
+        #[arg(long, short)]
+        outfile: Option<PathBuf>,
+        #[arg(long)]
+        force: bool,
+    },
+    /// Print the public key of a keypair file
+    Pubkey {
+        /// [default: ~/.config/solana/id.json]
+        file: Option<PathBuf>,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+pub enum VectorsCommand {
+    /// Print the canonical vector set as JSON
+    Generate {
+        /// Write it here instead of stdout
+        #[arg(long, short)]
+        out: Option<PathBuf>,
+    },
+    /// Check a vector file, from this or any other implementation, against this one
+    Check {
+        /// Vector file, or `-` for stdin
+        file: PathBuf,
+    },
+}
+
+/// Install a stderr subscriber if `--log` or RUST_LOG asks for one
+fn init_logging(filter: Option<&str>) -> Result<()> {
+    let Some(filter) = filter.map(str::to_string).or_else(|| std::env::var("RUST_LOG").ok()) else {
+        return Ok(());
+    };
+    let filter = tracing_subscriber::EnvFilter::try_new(&filter).with_context(|| format!("bad log filter {:?}", filter))?;
+    tracing_subscriber::fmt().with_env_filter(filter).with_writer(std::io::stderr).init();
+    Ok(())
+}
+
+fn keypair_path(arg: Option<PathBuf>) -> Result<PathBuf> {
+    match arg.or_else(keypair::default_keypair_path) {
+        Some(path) => Ok(path),
+        None => bail!("no keypair given and no home directory for the default"),
+    }
+}
+
+fn read_input(path: &Path) -> Result<Vec<u8>> {
+    let mut bytes = Vec::new();
+    if path == Path::new("-") {
+        std::io::stdin().read_to_end(&mut bytes).context("reading stdin")?;
+    } else {
+        bytes = std::fs::read(path).with_context(|| format!("reading {}", path.display()))?;
+    }
+    Ok(bytes)
+}
+
+fn keygen(command: KeygenCommand) -> Result<()> {
+    match command {
+        KeygenCommand::New { outfile, force } => {
+            let path = keypair_path(outfile)?;
+            let key = keypair::generate()?;
+            keypair::write_keypair_file(&key, &path, force)?;
+            println!("wrote {}", path.display());
+            println!("pubkey: {}", keypair::pubkey_base58(&key.verifying_key().to_bytes()));
+        }
+        KeygenCommand::Pubkey { file } => {
+            let key = keypair::read_keypair_file(&keypair_path(file)?)?;
+            println!("{}", keypair::pubkey_base58(&key.verifying_key().to_bytes()));
+        }
+    }
+    Ok(())
+}
+
+fn vectors(command: VectorsCommand) -> Result<()> {
+    match command {
+        VectorsCommand::Generate { out } => {
+            let json = format!("{}\n", serde_json::to_string_pretty(&crate::vectors::generate())?);
+            match out {
+                Some(path) => std::fs::write(&path, json).with_context(|| format!("writing {}", path.display()))?,
+                None => write_stdout(json.as_bytes())?,
+            }
+        }
+        VectorsCommand::Check { file } => {
+            let set = serde_json::from_slice(&read_input(&file)?).context("parsing vector file")?;
+            crate::vectors::check(&set)?;
+        }
+    }
+    Ok(())
+}
+
+fn sign_tx(tx: &Path, signer: &SignerArgs) -> Result<()> {
+    let json: serde_json::Value = serde_json::from_slice(&read_input(tx)?).context("transaction is not JSON")?;
+    let mut tx = crate::rpc::tx_from_json(&json)?;
+    let signer = signer.signer()?;
+    tx.sign_with(signer.as_ref())?;
+    eprintln!("signed {} as {}", h(&tx.id()), keypair::pubkey_base58(&signer.pubkey()));
+    println!("{}", serde_json::to_string_pretty(&crate::rpc::tx_to_json(&tx))?);
+    Ok(())
+}
+
+fn read_blob(file: &Path) -> Result<Vec<u8>> {
+    std::fs::read(file).with_context(|| format!("reading {}", file.display()))
+}
+
+/// For the read-only commands: snapshots can be far larger than RAM
+fn map_blob(file: &Path) -> Result<MappedBlob> {
+    MappedBlob::open(file)
+}
+
+fn tree_of(blob: &[u8], chunk_size: usize) -> Result<(witness_core::Chunks<'_>, MerkleTree)> {
+    if chunk_size == 0 {
+        bail!("--chunk-size must be positive");
+    }
+    let chunks = chunk_blob(blob, chunk_size);
+    let merkle = MerkleTree::from_chunks(&chunks);
+    Ok((chunks, merkle))
+}
+
+#[cfg(any(feature = "http", feature = "grpc"))]
+fn tree_mode(pruned: bool) -> crate::pruned::TreeMode {
+    match pruned {
+        true => crate::pruned::TreeMode::Pruned { cached_nodes: crate::pruned::DEFAULT_CACHED_NODES },
+        false => crate::pruned::TreeMode::Full,
+    }
+}
+
+fn parse_hash(s: &str, what: &str) -> Result<[u8;32]> {
+    let bytes = hex::decode(s).with_context(|| format!("{} is not hex", what))?;
+    match bytes.try_into() {
+        Ok(b) => Ok(b),
+        Err(_) => bail!("{} must be 32 bytes", what),
+    }
+}
+
+fn write_stdout(bytes: &[u8]) -> Result<()> {
+    let mut out = std::io::stdout().lock();
+    out.write_all(bytes)?;
+    out.flush().context("writing to stdout")
+}
+
+fn commit(file: &Path, chunk_size: usize, output: OutputFormat, container: Option<&Path>, compress: Option<Codec>) -> Result<()> {
+    let blob = map_blob(file)?;
+    let (root, data_len, chunk_count) = match container {
+        Some(path) => {
+            let committed = BlobFile::commit(&blob, chunk_size, compress)?;
+            committed.write(path)?;
+            (committed.root, committed.data.len(), committed.chunk_count)
+        }
+        None => {
+            let (chunks, merkle) = tree_of(&blob, chunk_size)?;
+            (merkle.root(), blob.len(), chunks.len())
+        }
+    };
+    match output {
+        OutputFormat::Hex => println!("{}", hex::encode(root)),
+        OutputFormat::Json => println!("{}", serde_json::to_string_pretty(&json!({
+            "version": crate::proof_file::VERSION,
+            "root": hex::encode(root),
+            "data_len": data_len,
+            "chunk_size": chunk_size,
+            "chunk_count": chunk_count,
+        }))?),
+        OutputFormat::Base64 => println!("{}", base64::engine::general_purpose::STANDARD.encode(root)),
+        OutputFormat::Base58 => println!("{}", keypair::pubkey_base58(&root)),
+        OutputFormat::Binary => write_stdout(&root)?,
+    }
+    Ok(())
+}
+
+/// Bytes to prove from: a plain file, or what a committed-blob file holds
+enum Input {
+    Plain(MappedBlob),
+    Committed(BlobFile),
+}
+
+impl std::ops::Deref for Input {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        match self {
+            Input::Plain(blob) => blob,
+            Input::Committed(file) => &file.data,
+        }
+    }
+}
+
+/// Open `file` with the chunk size to split it by. A committed-blob file's own chunk
+/// size wins, and `--chunk-size` may only repeat it.
+fn open_input(file: &Path, tree: &TreeArgs, cfg: &Config) -> Result<(Input, usize)> {
+    let blob = map_blob(file)?;
+    if !BlobFile::sniff(&blob) {
+        return Ok((Input::Plain(blob), tree.chunk_size(cfg)));
+    }
+    let committed = BlobFile::from_bytes(&blob).with_context(|| format!("reading {}", file.display()))?;
+    let chunk_size = committed.chunk_size;
+    if let Some(given) = tree.chunk_size.filter(|&given| given != chunk_size) {
+        bail!("{} was committed with {}-byte chunks, not {}", file.display(), chunk_size, given);
+    }
+    Ok((Input::Committed(committed), chunk_size))
+}
+
+fn prove(file: &Path, leaf: usize, tree: &TreeArgs, cfg: &Config, output: OutputFormat, witness: Option<&Path>) -> Result<()> {
+    let (blob, chunk_size) = open_input(file, tree, cfg)?;
+    let (chunks, merkle) = tree_of(&blob, chunk_size)?;
+    let chunk = match chunks.get(leaf) {
+        Some(c) => c,
+        None => bail!("leaf {} out of range: {} has {} chunks", leaf, file.display(), chunks.len()),
+    };
+    if let Some(path) = witness {
+        let bundle = WitnessFile::new(merkle.root(), leaf, chunks.len(), chunk_size, chunk.to_vec(), &merkle.gen_proof(leaf));
+        bundle.write(path)?;
+        println!("wrote {} ({} bytes)", path.display(), bundle.to_bytes().len());
+        return Ok(());
+    }
+    let proof = ProofFile::new(merkle.root(), leaf, chunk_size, chunk.to_vec(), &merkle.gen_proof(leaf));
+    write_stdout(&proof.encode(output))
+}
+
+/// A root as `commit` prints it: 64 hex digits, otherwise base64 or base58
+fn parse_root(s: &str, what: &str) -> Result<[u8;32]> {
+    if s.len() == 64 {
+        return parse_hash(s, what);
+    }
+    // a 44-character base58 string can also parse as base64, just not to 32 bytes
+    let bytes = base64::engine::general_purpose::STANDARD.decode(s).ok().filter(|b| b.len() == 32)
+        .or_else(|| bs58::decode(s).into_vec().ok())
+        .with_context(|| format!("{} is not hex, base64 or base58", what))?;
+    bytes.try_into().map_err(|_| anyhow::anyhow!("{} must be 32 bytes", what))
+}
+
+/// Root given on the command line, or read back from a `commit` output file
+fn read_root(arg: &str) -> Result<[u8;32]> {
+    if !Path::new(arg).is_file() {
+        return parse_root(arg, "--root");
+    }
+    let bytes = std::fs::read(arg).with_context(|| format!("reading {}", arg))?;
+    if let Ok(root) = <[u8;32]>::try_from(bytes.as_slice()) {
+        return Ok(root);
+    }
+    let text = String::from_utf8(bytes).with_context(|| format!("{} is not a root", arg))?;
+    let text = text.trim();
+    if text.starts_with('{') {
+        let json: serde_json::Value = serde_json::from_str(text).with_context(|| format!("parsing {}", arg))?;
+        return parse_hash(json["root"].as_str().unwrap_or_default(), "root");
+    }
+    parse_root(text, "root")
+}
+
+/// Root to check a proof against and, read from a committed-blob file or `commit
+/// --output json`, the chunk size and count of the tree behind it
+struct Commitment {
+    root: [u8;32],
+    chunk_size: Option<usize>,
+    chunk_count: Option<usize>,
+}
+
+impl Commitment {
+    /// Chunks the root commits to, from the commitment or `--chunk-count`. Without it a
+    /// proof's depth is whatever the prover says, and a short one proves an interior node.
+    fn chunk_count(&self, given: Option<usize>) -> Result<usize> {
+        match (self.chunk_count, given) {
+            (Some(committed), Some(given)) if committed != given => bail!("--chunk-count is {}, the commitment says {}", given, committed),
+            (Some(count), _) | (None, Some(count)) => Ok(count),
+            (None, None) => bail!("the chunk count behind the root is unknown: pass --chunk-count, or a container or `commit --output json` file as --root"),
+        }
+    }
+}
+
+fn read_commitment(arg: &str) -> Result<Commitment> {
+    let path = Path::new(arg);
+    if path.is_file() {
+        let bytes = read_input(path)?;
+        if BlobFile::sniff(&bytes) {
+            let committed = BlobFile::from_bytes(&bytes).with_context(|| format!("reading {}", arg))?;
+            return Ok(Commitment { root: committed.root, chunk_size: Some(committed.chunk_size), chunk_count: Some(committed.chunk_count) });
+        }
+        if let Ok(json) = serde_json::from_slice::<serde_json::Value>(&bytes) {
+            let field = |name: &str| json[name].as_u64().map(|n| n as usize);
+            return Ok(Commitment { root: parse_hash(json["root"].as_str().unwrap_or_default(), "root")?, chunk_size: field("chunk_size"), chunk_count: field("chunk_count") });
+        }
+    }
+    Ok(Commitment { root: read_root(arg)?, chunk_size: None, chunk_count: None })
+}
+
+fn check_chunk_size(proof: usize, committed: Option<usize>) -> Result<()> {
+    match committed {
+        Some(size) if size != proof => bail!("proof is for {}-byte chunks, the blob was committed with {}", proof, size),
+        _ => Ok(()),
+    }
+}
+
+fn verify(root: &str, proof: &Path, chunk_count: Option<usize>) -> Result<()> {
+    let commitment = read_commitment(root)?;
+    let chunk_count = commitment.chunk_count(chunk_count)?;
+    let root = commitment.root;
+    let file = ProofFile::decode(&read_input(proof)?)?;
+    check_chunk_size(file.chunk_size, commitment.chunk_size)?;
+    file.verify(&root, chunk_count)?;
+    println!("ok: leaf {} is committed by {}", file.leaf_index, hex::encode(root));
+    Ok(())
+}
+
+fn verify_file(file: &Path, root: Option<&str>, chunk_count: Option<usize>) -> Result<()> {
+    let commitment = root.map(read_commitment).transpose()?;
+    let witness = WitnessFile::read(file)?;
+    println!("{}: version {}, {:?}, chunk size {}, leaf {} of {}, {} siblings",
+        file.display(), witness_file::VERSION, witness.hasher, witness.chunk_size, witness.leaf_index, witness.leaf_count, witness.siblings.len());
+    if let Some(commitment) = &commitment {
+        check_chunk_size(witness.chunk_size, commitment.chunk_size)?;
+    }
+    let leaf_count = match &commitment {
+        Some(c) if c.chunk_count.is_some() || chunk_count.is_some() => Some(c.chunk_count(chunk_count)?),
+        _ => None,
+    };
+    witness.verify(commitment.as_ref().map(|c| &c.root), leaf_count)?;
+    if leaf_count.is_none() {
+        eprintln!("note: the leaf count is the witness's own; pass a container as --root, or --chunk-count, to check it");
+    }
+    println!("ok: leaf {} is committed by {}", witness.leaf_index, hex::encode(witness.root));
+    Ok(())
+}
+
+#[cfg(feature = "client")]
+fn fetch(url: &str, pubkey: &str, leaf: usize, root: Option<(&str, usize)>, output: OutputFormat) -> Result<()> {
+    let expected = root.map(|(r, count)| Ok::<_, anyhow::Error>((parse_hash(r, "--root")?, count))).transpose()?;
+    let client = crate::client::WitnessClient::new(url)?;
+    let runtime = tokio::runtime::Builder::new_current_thread().enable_all().build()?;
+    let (root, witness) = runtime.block_on(async {
+        match expected {
+            Some((root, count)) => client.get_proof_against(pubkey, leaf, &root, count).await.map(|w| (root, w)),
+            None => client.get_proof(pubkey, leaf).await,
+        }
+    })?;
+    let file = ProofFile::new(root, leaf, witness.chunk.len(), witness.chunk, &witness.proof);
+    write_stdout(&file.encode(output))
+}
+
+/// `--url`, else the configured HTTP address
+#[cfg(feature = "client")]
+fn server_url(url: Option<String>, cfg: &Config) -> Result<String> {
+    match url.or(cfg.rpc.http.as_ref().map(|addr| format!("http://{}", addr))) {
+        Some(url) => Ok(url),
+        None => bail!("no --url given and no rpc.http in the config"),
+    }
+}
+
+#[cfg(feature = "client")]
+fn lookup(url: &str, pubkey: &str) -> Result<()> {
+    let client = crate::client::WitnessClient::new(url)?;
+    let runtime = tokio::runtime::Builder::new_current_thread().enable_all().build()?;
+    let remote = runtime.block_on(client.get_root(pubkey))?;
+    println!("{} at slot {}: root {}", remote.pubkey, remote.slot, hex::encode(remote.root));
+    println!("{} bytes in {} chunks of {}", remote.data_len, remote.chunk_count, remote.chunk_size);
+    for location in &remote.locations {
+        println!("  {}", location);
+    }
+    Ok(())
+}
+
+#[cfg(feature = "client")]
+fn send(url: &str, tx: &Path, signer: &SignerArgs, retries: u32) -> Result<()> {
+    let client = crate::client::WitnessClient::new(url)?.with_retries(retries, crate::client::RetryPolicy::default().backoff);
+    let runtime = tokio::runtime::Builder::new_current_thread().enable_all().build()?;
+    let mut json: serde_json::Value = serde_json::from_slice(&read_input(tx)?).context("transaction is not JSON")?;
+    if json["recentBlockhash"].is_null() {
+        json["recentBlockhash"] = json!(hex::encode(runtime.block_on(client.get_latest_blockhash())?));
+    }
+    let mut tx = crate::rpc::tx_from_json(&json)?;
+    if let Some(signer) = signer.resolve()? {
+        tx.sign_with(signer.as_ref())?;
+    }
+    let id = runtime.block_on(client.submit_update(&tx))?;
+    println!("applied {}", hex::encode(id));
+    Ok(())
+}
+
+fn update(file: &Path, offset: usize, data: &str, chunk_size: usize) -> Result<()> {
+    let data = hex::decode(data).context("--hex is not hex")?;
+    let old_blob = read_blob(file)?;
+    let (old_chunks, old_tree) = tree_of(&old_blob, chunk_size)?;
+    let mut blob = old_blob.clone();
+    // the file only grows up to what an account could hold
+    let end = match offset.checked_add(data.len()) {
+        Some(end) if end <= blob.len().max(MAX_ACCOUNT_DATA_LEN) => end,
+        _ => bail!("{} bytes at offset {} run past {} bytes, the largest an account can be", data.len(), offset, MAX_ACCOUNT_DATA_LEN),
+    };
+    if blob.len() < end {
+        blob.resize(end, 0);
+    }
+    blob[offset..end].copy_from_slice(&data);
+    let (new_chunks, new_tree) = tree_of(&blob, chunk_size)?;
+    std::fs::write(file, &blob).with_context(|| format!("writing {}", file.display()))?;
+    let changed: Vec<usize> = (0..new_chunks.len()).filter(|&i| old_chunks.get(i) != Some(&new_chunks[i])).collect();
+    println!("old root {}", hex::encode(old_tree.root()));
+    println!("new root {}", hex::encode(new_tree.root()));
+    println!("changed leaves {:?} of {}", changed, new_chunks.len());
+    Ok(())
+}
+
+fn inspect(file: &Path, chunk_size: usize, canopy_depth: usize) -> Result<()> {
+    let blob = map_blob(file)?;
+    let (chunks, merkle) = tree_of(&blob, chunk_size)?;
+    let depth = merkle.depth();
+    println!("{}: {} bytes", file.display(), blob.len());
+    println!("chunks: {} of {} bytes ({} padding bytes, {} duplicated leaves)",
+        chunks.len(), chunk_size, chunks.len() * chunk_size - blob.len(), merkle.leaves().len() - chunks.len());
+    if !mapped::page_aligned(chunk_size) {
+        println!("note: {}-byte chunks straddle {}-byte pages; a multiple or divisor of the page size maps cleaner", chunk_size, mapped::PAGE_SIZE);
+    }
+    println!("depth: {} ({} bytes per proof)", depth, depth * 33);
+    if canopy_depth > 0 {
+        let canopy = canopy_depth.min(depth);
+        println!("canopy: top {} levels ({} hashes kept next to the root); proofs shrink to {} steps, {} bytes",
+            canopy, (1usize << (canopy + 1)) - 2, depth - canopy, (depth - canopy) * 33);
+    }
+    println!("root: {}", hex::encode(merkle.root()));
+    for (level, layer) in merkle.layers().enumerate() {
+        let shown: Vec<String> = layer.iter().take(4).map(h).collect();
+        let more = if layer.len() > 4 { format!(" ... (+{})", layer.len() - 4) } else { String::new() };
+        println!("  layer {:>2}: {:>5} nodes  {}{}", level, layer.len(), shown.join(" "), more);
+    }
+    Ok(())
+}
+
+fn inspect_dot(file: &Path, chunk_size: usize, leaf: Option<usize>) -> Result<()> {
+    let blob = map_blob(file)?;
+    let (chunks, merkle) = tree_of(&blob, chunk_size)?;
+    if let Some(leaf) = leaf.filter(|&leaf| leaf >= chunks.len()) {
+        bail!("leaf {} out of range: {} has {} chunks", leaf, file.display(), chunks.len());
+    }
+    print!("{}", merkle.to_dot(leaf));
+    Ok(())
+}
+
+fn estimate(data_len: u64, chunk_size: Option<usize>, hasher: &str, canopy_depth: usize, budget: u64) -> Result<()> {
+    let hasher = SyscallHasher::parse(hasher)?;
+    let sizes: Vec<usize> = match chunk_size {
+        Some(0) => bail!("--chunk-size must be positive"),
+        Some(size) => vec![size],
+        None => (5..=12).map(|bits| 1 << bits).collect(),
+    };
+    let costs = ComputeCosts::default();
+    println!("{} bytes, {:?} syscalls, canopy {}, budget {} CU", data_len, hasher, canopy_depth, budget);
+    println!("{:>6} {:>9} {:>5} {:>5} {:>9} {:>7} {:>7} {:>8} {:>8}", "chunk", "chunks", "depth", "steps", "witness", "leaf", "nodes", "other", "total");
+    for size in sizes {
+        let chunks = data_len.div_ceil(size as u64).max(1);
+        let depth = chunks.next_power_of_two().trailing_zeros() as usize;
+        let e = costs.estimate_verify(hasher, size, depth, canopy_depth);
+        println!("{:>6} {:>9} {:>5} {:>5} {:>8}B {:>7} {:>7} {:>8} {:>8}{}", size, chunks, depth, e.steps, size + e.steps * 33,
+            e.leaf_hash, e.node_hashes, e.overhead, e.total, if e.fits(budget) { "" } else { "  over budget" });
+        if size > MAX_PACKED_CHUNK {
+            println!("{:>6} chunks over {} bytes do not fit in a transaction", "", MAX_PACKED_CHUNK);
+            continue;
+        }
+        // what the packer would need for one witness of this shape
+        let witness = ChunkWitness { pubkey: String::new(), leaf_index: 0, chunk: vec![0; size], proof: vec![([0; 32], false); e.steps] };
+        let txs = Packer::default().pack([0; 32], &[witness])?.len();
+        if txs > 1 {
+            println!("{:>6} splits across {} transactions", "", txs);
+        }
+    }
+    Ok(())
+}
+
+pub fn run() -> Result<()> {
+    let cli = Cli::parse();
+    init_logging(cli.log.as_deref())?;
+    let cfg = Config::discover(cli.config.as_deref())?;
+    match cli.command {
+        Command::Commit { file, tree, output, container, compress } => commit(&file, tree.chunk_size(&cfg), output, container.as_deref(), compress),
+        Command::Prove { file, leaf, tree, output, witness } => prove(&file, leaf, &tree, &cfg, output, witness.as_deref()),
+        Command::Verify { root, proof, chunk_count } => verify(&root, &proof, chunk_count),
+        Command::VerifyFile { file, root, chunk_count } => verify_file(&file, root.as_deref(), chunk_count),
+        Command::Update { file, offset, hex, tree } => update(&file, offset, &hex, tree.chunk_size(&cfg)),
+        Command::Inspect { file, tree, dot: true, leaf } => inspect_dot(&file, tree.chunk_size(&cfg), leaf),
+        Command::Inspect { file, tree, .. } => inspect(&file, tree.chunk_size(&cfg), cfg.canopy_depth),
+        Command::ExportCar { file, tree, out } => {
+            let (blob, chunk_size) = open_input(&file, &tree, &cfg)?;
+            crate::car::run(&blob, chunk_size, &out)
+        }
+        Command::Archive { file, tree, store } => {
+            let (blob, chunk_size) = open_input(&file, &tree, &cfg)?;
+            let root = tree_of(&blob, chunk_size)?.1.root();
+            store.archive(&root, &blob, chunk_size)?;
+            println!("stored {} bytes under root {} in {}", blob.len(), hex::encode(root), store.store);
+            Ok(())
+        }
+        Command::Diagnose { file, tree, root, reference } => {
+            let (blob, chunk_size) = open_input(&file, &tree, &cfg)?;
+            let root = read_commitment(&root)?.root;
+            let mut source = reference.source(root, chunk_size, &cfg)?;
+            let diagnosis = crate::diagnose::diagnose(&blob, chunk_size, &root, source.as_mut())?;
+            crate::diagnose::report(&diagnosis, chunk_size, chunk_blob(&blob, chunk_size).len());
+            Ok(())
+        }
+        Command::Estimate { data_len, chunk_size, hasher, canopy_depth, budget } =>
+            estimate(data_len, chunk_size, &hasher, canopy_depth.unwrap_or(cfg.canopy_depth), budget),
+        Command::Analyze { dir, chunk_sizes, arities, csv } => {
+            let chunk_sizes = if chunk_sizes.is_empty() { crate::analyze::DEFAULT_CHUNK_SIZES.to_vec() } else { chunk_sizes };
+            let arities = if arities.is_empty() { crate::analyze::DEFAULT_ARITIES.to_vec() } else { arities };
+            crate::analyze::run(&dir, &chunk_sizes, &arities, csv.as_deref())
+        }
+        Command::CompareSchemes { dir, tree, openings } => crate::schemes::run(&dir, tree.chunk_size(&cfg), openings),
+        Command::Cost { data_len, updates_per_day, bytes_per_update, tree, priority_fee, capital_rate } => {
+            let model = CostModel { micro_lamports_per_cu: priority_fee, capital_rate, ..CostModel::default() };
+            crate::cost::run(&model, &AccountProfile { data_len, chunk_size: tree.chunk_size(&cfg), updates_per_day, bytes_per_update })
+        }
+        Command::Keygen { command } => keygen(command),
+        Command::SignTx { tx, signer } => sign_tx(&tx, &signer),
+        Command::Vectors { command } => vectors(command),
+        Command::Repl => crate::repl::run(&cfg),
+        Command::Simulate { genesis: Some(path), metrics_out, .. } => crate::run_genesis(&path, metrics_out.as_deref()),
+        Command::Simulate { slots, workload: Some(path), ops_per_slot, export_dir, export_format, .. } =>
+            crate::savings::run(WorkloadConfig::load(&path)?, slots.unwrap_or(crate::savings::DEFAULT_SLOTS), ops_per_slot, cfg.chunk_size, export_dir.as_deref(), export_format),
+        Command::Simulate { slots: Some(slots), workload: None, ops_per_slot, export_dir, export_format, .. } =>
+            crate::savings::run(WorkloadConfig::default(), slots, ops_per_slot, cfg.chunk_size, export_dir.as_deref(), export_format),
+        Command::Simulate { export_dir: Some(_), .. } => bail!("--export-dir needs a --slots or --workload run"),
+        Command::Simulate { metrics_out, .. } => crate::run_demo(&cfg, metrics_out.as_deref()),
+        #[cfg(feature = "http")]
+        Command::Serve { genesis, addr, pruned } => match addr.or(cfg.rpc.http) {
+            Some(addr) => crate::run_proof_server(&genesis, &addr, tree_mode(pruned)),
+            None => bail!("no address given and no rpc.http in the config"),
+        },
+        #[cfg(feature = "zk")]
+        Command::Zk { data_len, tree, writes } => crate::zk::run(data_len, tree.chunk_size(&cfg), writes),
+        #[cfg(feature = "devnet")]
+        Command::Devnet { file, account, leaf, tree, url, signer, dry_run } => {
+            let payer = match signer.resolve()? {
+                Some(payer) => payer,
+                None if dry_run => Box::new(keypair::generate()?),
+                None => bail!("no --keypair given and no ~/.config/solana/id.json"),
+            };
+            let devnet = crate::devnet::Devnet::new(&url, payer);
+            crate::devnet::run(&devnet, &account, &file, tree.chunk_size(&cfg), leaf, dry_run)
+        }
+        #[cfg(feature = "client")]
+        Command::Fetch { pubkey, leaf, url, root, chunk_count, output } =>
+            fetch(&server_url(url, &cfg)?, &pubkey, leaf, root.as_deref().zip(chunk_count), output),
+        #[cfg(feature = "client")]
+        Command::Lookup { pubkey, url } => lookup(&server_url(url, &cfg)?, &pubkey),
+        #[cfg(feature = "client")]
+        Command::Send { tx, url, signer, retries } => send(&server_url(url, &cfg)?, &tx, &signer, retries),
+        #[cfg(feature = "grpc")]
+        Command::Grpc { genesis, addr, pruned } => match addr.or(cfg.rpc.grpc) {
+            Some(addr) => crate::run_grpc_server(&genesis, &addr, tree_mode(pruned)),
+            None => bail!("no address given and no rpc.grpc in the config"),
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn update_grows_files_only_up_to_an_account() {
+        let path = std::env::temp_dir().join("cli-update-test.bin");
+        std::fs::write(&path, [1u8; 10]).unwrap();
+        update(&path, 12, "aabb", 4).unwrap();
+        assert_eq!(std::fs::read(&path).unwrap(), [&[1u8; 10][..], &[0, 0, 0xaa, 0xbb]].concat());
+        assert!(update(&path, usize::MAX, "aa", 4).is_err());
+        assert!(update(&path, MAX_ACCOUNT_DATA_LEN, "aa", 4).is_err());
+        assert_eq!(std::fs::read(&path).unwrap().len(), 14);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn round_trip_holds_the_store_to_the_root() {
+        let blob = [7u8; 100];
+        let root = tree_of(&blob, 32).unwrap().1.root();
+        let dir = std::env::temp_dir().join("cli-archive-test");
+        round_trip(&crate::blob_store::FsBlobStore::new(&dir).unwrap(), &root, &blob, 32).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+        // bytes filed under a root they do not hash to are caught on the way back
+        assert!(round_trip(&crate::blob_store::MemoryBlobStore::new(), &[0; 32], &blob, 32).is_err());
+    }
+}
@@ -0,0 +1,254 @@
+use std::time::Duration;
+
+use anyhow::{Context, Result, anyhow, bail};
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+use crate::rpc::tx_to_json;
+use crate::view::ChunkWitness;
+use crate::{h, MerkleTree, WitnessTx};
+
+/// Current commitment of an account as the proof server reports it (`GET /root/{pubkey}`)
+#[derive(Debug, Clone)]
+pub struct RemoteRoot {
+    pub pubkey: String,
+    pub root: [u8;32],
+    pub slot: u64,
+    pub data_len: u64,
+    pub chunk_size: usize,
+    pub chunk_count: usize,
+    pub locations: Vec<String>,
+}
+
+#[derive(Deserialize)]
+struct RootBody {
+    pubkey: String,
+    root: String,
+    slot: u64,
+    data_len: u64,
+    chunk_size: usize,
+    chunk_count: usize,
+    locations: Vec<String>,
+}
+
+#[derive(Deserialize)]
+struct StepBody {
+    sibling: String,
+    is_left: bool,
+}
+
+#[derive(Deserialize)]
+struct ProofBody {
+    pubkey: String,
+    root: String,
+    leaf_index: usize,
+    chunk: String,
+    proof: Vec<StepBody>,
+}
+
+fn hash32(s: &str, what: &str) -> Result<[u8;32]> {
+    let bytes = hex::decode(s).with_context(|| format!("{} is not hex", what))?;
+    bytes.try_into().map_err(|_| anyhow!("{} must be 32 bytes", what))
+}
+
+/// How often and how patiently a request is retried
+#[derive(Debug, Clone, Copy)]
+pub struct RetryPolicy {
+    /// Attempts after the first; 0 never retries
+    pub max_retries: u32,
+    /// Delay before the first retry, doubled for each one after it
+    pub backoff: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self { max_retries: 3, backoff: Duration::from_millis(200) }
+    }
+}
+
+/// A failed attempt, and whether trying again could help
+struct Attempt {
+    error: anyhow::Error,
+    retryable: bool,
+}
+
+impl Attempt {
+    fn fatal(error: anyhow::Error) -> Self {
+        Self { error, retryable: false }
+    }
+}
+
+/// Async client for the HTTP proof server (`solana-bloat serve`).
+///
+/// Connection errors, timeouts, 429 and 5xx replies are retried with exponential
+/// backoff; anything else fails at once. Every proof is checked on receipt, so a
+/// returned `ChunkWitness` is known to be under the root it came with.
+#[derive(Debug, Clone)]
+pub struct WitnessClient {
+    http: reqwest::Client,
+    base_url: String,
+    retry: RetryPolicy,
+}
+
+impl WitnessClient {
+    /// Client for the server at `base_url`, e.g. "http://127.0.0.1:8899"
+    pub fn new(base_url: &str) -> Result<Self> {
+        let http = reqwest::Client::builder().timeout(Duration::from_secs(10)).build().context("building HTTP client")?;
+        Ok(Self { http, base_url: base_url.trim_end_matches('/').to_string(), retry: RetryPolicy::default() })
+    }
+
+    pub fn with_retries(mut self, max_retries: u32, backoff: Duration) -> Self {
+        self.retry = RetryPolicy { max_retries, backoff };
+        self
+    }
+
+    /// Run `attempt` until it succeeds, fails for good or runs out of retries
+    async fn with_retry<T, F, Fut>(&self, what: &str, mut attempt: F) -> Result<T>
+    where
+        F: FnMut() -> Fut,
+        Fut: std::future::Future<Output = Result<T, Attempt>>,
+    {
+        let mut delay = self.retry.backoff;
+        let mut tries = 0;
+        loop {
+            match attempt().await {
+                Ok(v) => return Ok(v),
+                Err(a) if a.retryable && tries < self.retry.max_retries => {
+                    tokio::time::sleep(delay).await;
+                    delay *= 2;
+                    tries += 1;
+                }
+                Err(a) => return Err(a.error.context(format!("{} (after {} attempt(s))", what, tries + 1))),
+            }
+        }
+    }
+
+    /// One request whose reply is JSON; `None` for a 404
+    async fn send(&self, request: reqwest::RequestBuilder) -> Result<Option<Value>, Attempt> {
+        let response = match request.send().await {
+            Ok(r) => r,
+            Err(e) => {
+                let retryable = e.is_connect() || e.is_timeout() || e.is_request();
+                return Err(Attempt { error: e.into(), retryable });
+            }
+        };
+        let status = response.status();
+        if status == reqwest::StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+        if !status.is_success() {
+            let body = response.text().await.unwrap_or_default();
+            let retryable = status.is_server_error() || status == reqwest::StatusCode::TOO_MANY_REQUESTS;
+            return Err(Attempt { error: anyhow!("server replied {}: {}", status, body.trim()), retryable });
+        }
+        match response.json().await {
+            Ok(v) => Ok(Some(v)),
+            // a cut-off body is a transport problem, not a bad reply
+            Err(e) if e.is_body() => Err(Attempt { error: e.into(), retryable: true }),
+            Err(e) => Err(Attempt::fatal(anyhow::Error::new(e).context("reply is not JSON"))),
+        }
+    }
+
+    async fn get(&self, path: &str) -> Result<Option<Value>> {
+        let url = format!("{}{}", self.base_url, path);
+        self.with_retry(&format!("GET {}", url), || self.send(self.http.get(&url))).await
+    }
+
+    /// Raw JSON-RPC reply over `POST /`; an RPC error inside it is never retried
+    async fn rpc(&self, method: &str, params: Value) -> Result<Value> {
+        let body = json!({ "jsonrpc": "2.0", "id": 1, "method": method, "params": params });
+        let url = format!("{}/", self.base_url);
+        let reply = self.with_retry(method, || self.send(self.http.post(&url).json(&body))).await?;
+        reply.ok_or_else(|| anyhow!("{} does not accept JSON-RPC", self.base_url))
+    }
+
+    async fn call(&self, method: &str, params: Value) -> Result<Value> {
+        let reply = self.rpc(method, params).await?;
+        if let Some(err) = reply.get("error") {
+            bail!("{} failed ({}): {}", method, err["code"], err["message"].as_str().unwrap_or_default());
+        }
+        reply.get("result").cloned().ok_or_else(|| anyhow!("{} reply has no result", method))
+    }
+
+    /// Current root and shape of `pubkey`'s data
+    pub async fn get_root(&self, pubkey: &str) -> Result<RemoteRoot> {
+        let value = self.get(&format!("/root/{}", pubkey)).await?.ok_or_else(|| anyhow!("unknown account {}", pubkey))?;
+        let body: RootBody = serde_json::from_value(value).context("parsing root reply")?;
+        if body.pubkey != pubkey {
+            bail!("asked for the root of {}, got {}", pubkey, body.pubkey);
+        }
+        Ok(RemoteRoot {
+            root: hash32(&body.root, "root")?,
+            pubkey: body.pubkey,
+            slot: body.slot,
+            data_len: body.data_len,
+            chunk_size: body.chunk_size,
+            chunk_count: body.chunk_count,
+            locations: body.locations,
+        })
+    }
+
+    /// Chunk `leaf_index` of `pubkey` with its proof, checked against the root and chunk
+    /// count the server reports; returns that root alongside. Use `get_proof_against`
+    /// when the root comes from somewhere trusted.
+    pub async fn get_proof(&self, pubkey: &str, leaf_index: usize) -> Result<([u8;32], ChunkWitness)> {
+        let remote = self.get_root(pubkey).await?;
+        let witness = self.get_proof_against(pubkey, leaf_index, &remote.root, remote.chunk_count).await?;
+        Ok((remote.root, witness))
+    }
+
+    /// Chunk `leaf_index` of `pubkey` with its proof against `expected_root`, a tree of
+    /// `chunk_count` chunks
+    pub async fn get_proof_against(&self, pubkey: &str, leaf_index: usize, expected_root: &[u8;32], chunk_count: usize) -> Result<ChunkWitness> {
+        let value = self.get(&format!("/proof/{}/{}", pubkey, leaf_index)).await?
+            .ok_or_else(|| anyhow!("no proof of leaf {} of {}", leaf_index, pubkey))?;
+        let body: ProofBody = serde_json::from_value(value).context("parsing proof reply")?;
+        let root = hash32(&body.root, "root")?;
+        let proof = body.proof.iter().map(|s| Ok((hash32(&s.sibling, "proof sibling")?, s.is_left))).collect::<Result<Vec<_>>>()?;
+        let witness = ChunkWitness {
+            pubkey: body.pubkey,
+            leaf_index: body.leaf_index,
+            chunk: hex::decode(&body.chunk).context("chunk is not hex")?,
+            proof,
+        };
+        if witness.pubkey != pubkey || witness.leaf_index != leaf_index {
+            bail!("asked for leaf {} of {}, got leaf {} of {}", leaf_index, pubkey, witness.leaf_index, witness.pubkey);
+        }
+        if root != *expected_root {
+            bail!("server proved leaf {} of {} against {}, expected {}", leaf_index, pubkey, hex::encode(root), hex::encode(expected_root));
+        }
+        // a proof for another leaf, or cut short to an interior node, could still verify
+        if let Err(e) = witness_core::check_proof_shape(&witness.proof, leaf_index, chunk_count) {
+            bail!("proof of leaf {} of {} does not fit its tree: {}", leaf_index, pubkey, e);
+        }
+        if !MerkleTree::verify_proof(&witness.chunk, &witness.proof, &root) {
+            bail!("proof of leaf {} of {} does not verify against {}", leaf_index, pubkey, hex::encode(root));
+        }
+        Ok(witness)
+    }
+
+    pub async fn get_latest_blockhash(&self) -> Result<[u8;32]> {
+        let result = self.call("getLatestBlockhash", json!([])).await?;
+        hash32(result["value"]["blockhash"].as_str().unwrap_or_default(), "blockhash")
+    }
+
+    /// Submit a witness transaction and return its id once the server has applied it.
+    /// A retry after a lost reply may find the tx already processed; that counts as
+    /// success, since the id is the same.
+    pub async fn submit_update(&self, tx: &WitnessTx) -> Result<[u8;32]> {
+        let id = tx.id();
+        let reply = self.rpc("sendWitnessTransaction", json!([tx_to_json(tx)])).await?;
+        if let Some(err) = reply.get("error") {
+            let message = err["message"].as_str().unwrap_or_default();
+            if message.contains("already processed") && message.contains(&h(&id)) {
+                return Ok(id);
+            }
+            bail!("sendWitnessTransaction failed ({}): {}", err["code"], message);
+        }
+        let returned = hash32(reply["result"].as_str().unwrap_or_default(), "transaction id")?;
+        if returned != id {
+            bail!("server returned id {} for transaction {}", hex::encode(returned), hex::encode(id));
+        }
+        Ok(id)
+    }
+}
@@ -0,0 +1,280 @@
+use anyhow::{Result, bail};
+use ed25519_dalek::SigningKey;
+
+use crate::archive::ArchiveNode;
+use crate::error::WitnessError;
+use crate::program::{AccountView, Effects, InvokeContext, Program};
+use crate::{h, sha256, AccountStub, AccountWitness, ChainState, TxReceipt, WitnessTx};
+
+pub const CNFT_PROGRAM_ID: &str = "CompressedNft1";
+
+/// Every NFT is one leaf of this many bytes: owner key, then hash of its metadata.
+/// Leaf 0 is the collection header: mint authority, then the number minted so far.
+pub const LEAF_SIZE: usize = 64;
+
+/// All-zero owner marks a burned leaf
+const BURNED: [u8;32] = [0u8; 32];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum NftInstruction {
+    /// Append a leaf; needs the collection authority's signature
+    Mint { owner: [u8;32], data_hash: [u8;32] },
+    /// Change the owner of leaf `index`; needs the current owner's signature
+    Transfer { index: u32, new_owner: [u8;32] },
+    /// Clear leaf `index`; needs the current owner's signature
+    Burn { index: u32 },
+}
+
+impl NftInstruction {
+    pub fn encode(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        match self {
+            NftInstruction::Mint { owner, data_hash } => {
+                out.push(0);
+                out.extend_from_slice(owner);
+                out.extend_from_slice(data_hash);
+            }
+            NftInstruction::Transfer { index, new_owner } => {
+                out.push(1);
+                out.extend_from_slice(&index.to_le_bytes());
+                out.extend_from_slice(new_owner);
+            }
+            NftInstruction::Burn { index } => {
+                out.push(2);
+                out.extend_from_slice(&index.to_le_bytes());
+            }
+        }
+        out
+    }
+
+    pub fn decode(data: &[u8]) -> Result<Self, WitnessError> {
+        let key = |b: &[u8]| -> [u8;32] { b.try_into().unwrap() };
+        let index = |b: &[u8]| u32::from_le_bytes(b.try_into().unwrap());
+        Ok(match (data.first(), data.len()) {
+            (Some(0), 65) => NftInstruction::Mint { owner: key(&data[1..33]), data_hash: key(&data[33..65]) },
+            (Some(1), 37) => NftInstruction::Transfer { index: index(&data[1..5]), new_owner: key(&data[5..37]) },
+            (Some(2), 5) => NftInstruction::Burn { index: index(&data[1..5]) },
+            _ => return Err(WitnessError::Rejected(format!("malformed cNFT instruction ({} bytes)", data.len()))),
+        })
+    }
+}
+
+/// Initial blob of an empty collection minted by `authority`
+pub fn collection_blob(authority: &[u8;32]) -> Vec<u8> {
+    let mut blob = vec![0u8; LEAF_SIZE];
+    blob[..32].copy_from_slice(authority);
+    blob
+}
+
+/// Owner of leaf `index`, `None` if burned or out of range
+pub fn owner_of(blob: &[u8], index: u32) -> Option<[u8;32]> {
+    let start = index as usize * LEAF_SIZE;
+    if index == 0 || start + LEAF_SIZE > blob.len() {
+        return None;
+    }
+    let owner: [u8;32] = blob[start..start + 32].try_into().unwrap();
+    (owner != BURNED).then_some(owner)
+}
+
+/// Bubblegum-style collection: the whole collection is one offloaded account and each
+/// NFT a leaf, so minting costs a leaf instead of a fresh on-chain account
+pub struct CompressedNftProgram;
+
+impl Program for CompressedNftProgram {
+    fn process(&self, ctx: &mut InvokeContext, accounts: &[AccountView], instruction_data: &[u8], _witnesses: &[AccountWitness]) -> Result<Effects, WitnessError> {
+        let collection = match accounts {
+            [a] => a,
+            _ => return Err(WitnessError::Rejected("cNFT instructions take exactly the collection account".to_string())),
+        };
+        let mut blob = collection.data.to_vec();
+        if blob.len() < LEAF_SIZE || blob.len() % LEAF_SIZE != 0 {
+            return Err(WitnessError::Rejected(format!("{} is not a collection ({} bytes)", collection.pubkey, blob.len())));
+        }
+        match NftInstruction::decode(instruction_data)? {
+            NftInstruction::Mint { owner, data_hash } => {
+                let authority: [u8;32] = blob[..32].try_into().unwrap();
+                if !ctx.is_signer(&authority) {
+                    return Err(WitnessError::Rejected(format!("mint needs the collection authority {}", h(&authority))));
+                }
+                let minted = u64::from_le_bytes(blob[32..40].try_into().unwrap()) + 1;
+                blob[32..40].copy_from_slice(&minted.to_le_bytes());
+                blob.extend_from_slice(&owner);
+                blob.extend_from_slice(&data_hash);
+            }
+            NftInstruction::Transfer { index, new_owner } => {
+                let start = self.check_owner(ctx, &blob, index)?;
+                blob[start..start + 32].copy_from_slice(&new_owner);
+            }
+            NftInstruction::Burn { index } => {
+                let start = self.check_owner(ctx, &blob, index)?;
+                blob[start..start + LEAF_SIZE].fill(0);
+            }
+        }
+        let mut effects = Effects::default();
+        effects.write(collection.pubkey, blob);
+        Ok(effects)
+    }
+}
+
+impl CompressedNftProgram {
+    /// Byte offset of leaf `index` once its current owner is confirmed to have signed
+    fn check_owner(&self, ctx: &InvokeContext, blob: &[u8], index: u32) -> Result<usize, WitnessError> {
+        let owner = match owner_of(blob, index) {
+            Some(o) => o,
+            None => return Err(WitnessError::Rejected(format!("no live NFT at leaf {}", index))),
+        };
+        if !ctx.is_signer(&owner) {
+            return Err(WitnessError::Rejected(format!("leaf {} is owned by {}, who did not sign", index, h(&owner))));
+        }
+        Ok(index as usize * LEAF_SIZE)
+    }
+}
+
+/// Mint, transfer and burn through `ChainState`, fetching every witness from an archive node
+pub fn demo() -> Result<()> {
+    let mut chain = ChainState::new();
+    let archive = ArchiveNode::new();
+    chain.subscribe(Box::new(archive.clone()));
+    chain.register_program(CNFT_PROGRAM_ID, Box::new(CompressedNftProgram));
+    let key = |name: &[u8]| SigningKey::from_bytes(&sha256(name));
+    let (authority, alice, bob) = (key(b"authority"), key(b"alice"), key(b"bob"));
+    let pk = |k: &SigningKey| k.verifying_key().to_bytes();
+
+    let blob = collection_blob(&pk(&authority));
+    let root = archive.ingest("Collection1", chain.slot, LEAF_SIZE, &blob)?;
+    chain.put_stub("Collection1", AccountStub::new(CNFT_PROGRAM_ID, 0, root, blob.len() as u64, LEAF_SIZE));
+
+    let send = |chain: &mut ChainState, ix: NftInstruction, leaf: usize, signer: &SigningKey| -> Result<TxReceipt> {
+        let root = chain.get_stub("Collection1").unwrap().merkle_root;
+        let witness = match archive.get_witness("Collection1", &root, leaf) {
+            Some(w) => w,
+            None => bail!("archive has no leaf {} under root {}", leaf, h(&root)),
+        };
+        let mut tx = WitnessTx {
+            program_id: CNFT_PROGRAM_ID.to_string(),
+            instruction_data: ix.encode(),
+            witnesses: vec![witness],
+            recent_blockhash: chain.latest_blockhash(),
+            priority_fee: 0,
+            signatures: Vec::new(),
+        };
+        tx.sign(signer);
+        Ok(chain.process_tx_witness(&tx)?)
+    };
+
+    for i in 0..3u8 {
+        send(&mut chain, NftInstruction::Mint { owner: pk(&alice), data_hash: sha256(&[b'm', i]) }, 0, &authority)?;
+    }
+    send(&mut chain, NftInstruction::Transfer { index: 2, new_owner: pk(&bob) }, 2, &alice)?;
+    if send(&mut chain, NftInstruction::Transfer { index: 1, new_owner: pk(&bob) }, 1, &bob).is_ok() {
+        bail!("bob moved an NFT he does not own");
+    }
+    send(&mut chain, NftInstruction::Burn { index: 3 }, 3, &alice)?;
+
+    let stub = chain.get_stub("Collection1").unwrap();
+    let blob = archive.get_blob("Collection1", &stub.merkle_root).unwrap().data;
+    let names = [(pk(&alice), "alice"), (pk(&bob), "bob")];
+    for index in 1..=3u32 {
+        let owner = owner_of(&blob, index).map(|o| names.iter().find(|(k, _)| *k == o).map_or("?", |(_, n)| *n));
+        println!("  leaf {}: {}", index, owner.unwrap_or("burned"));
+    }
+    println!("Collection root {} over {} bytes", h(&stub.merkle_root), stub.data_len);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(name: &str) -> SigningKey {
+        SigningKey::from_bytes(&sha256(name.as_bytes()))
+    }
+
+    fn pk(k: &SigningKey) -> [u8;32] {
+        k.verifying_key().to_bytes()
+    }
+
+    /// A collection minted by "authority", archived as it changes
+    fn collection() -> (ChainState, ArchiveNode) {
+        let mut chain = ChainState::new();
+        let archive = ArchiveNode::new();
+        chain.subscribe(Box::new(archive.clone()));
+        chain.register_program(CNFT_PROGRAM_ID, Box::new(CompressedNftProgram));
+        let blob = collection_blob(&pk(&key("authority")));
+        let root = archive.ingest("Collection1", chain.slot, LEAF_SIZE, &blob).unwrap();
+        chain.put_stub("Collection1", AccountStub::new(CNFT_PROGRAM_ID, 0, root, blob.len() as u64, LEAF_SIZE));
+        (chain, archive)
+    }
+
+    fn tx(chain: &ChainState, archive: &ArchiveNode, ix: &NftInstruction, leaf: usize, signer: &SigningKey) -> WitnessTx {
+        let root = chain.get_stub("Collection1").unwrap().merkle_root;
+        let mut tx = WitnessTx {
+            program_id: CNFT_PROGRAM_ID.to_string(),
+            instruction_data: ix.encode(),
+            witnesses: vec![archive.get_witness("Collection1", &root, leaf).unwrap()],
+            recent_blockhash: chain.latest_blockhash(),
+            priority_fee: 0,
+            signatures: Vec::new(),
+        };
+        tx.sign(signer);
+        tx
+    }
+
+    fn blob(chain: &ChainState, archive: &ArchiveNode) -> Vec<u8> {
+        archive.get_blob("Collection1", &chain.get_stub("Collection1").unwrap().merkle_root).unwrap().data
+    }
+
+    #[test]
+    fn instructions_round_trip() {
+        for ix in [NftInstruction::Mint { owner: [1; 32], data_hash: [2; 32] }, NftInstruction::Transfer { index: 7, new_owner: [3; 32] }, NftInstruction::Burn { index: 9 }] {
+            assert_eq!(NftInstruction::decode(&ix.encode()).unwrap(), ix);
+        }
+        assert!(NftInstruction::decode(&[1; 5]).is_err());
+        assert!(NftInstruction::decode(&[]).is_err());
+    }
+
+    #[test]
+    fn mints_and_transfers_under_proven_witnesses() {
+        let (mut chain, archive) = collection();
+        let (authority, alice, bob) = (key("authority"), key("alice"), key("bob"));
+        for i in 0..2u8 {
+            chain.process_tx_witness(&tx(&chain, &archive, &NftInstruction::Mint { owner: pk(&alice), data_hash: [i; 32] }, 0, &authority)).unwrap();
+        }
+        chain.process_tx_witness(&tx(&chain, &archive, &NftInstruction::Transfer { index: 2, new_owner: pk(&bob) }, 2, &alice)).unwrap();
+        let blob = blob(&chain, &archive);
+        assert_eq!(u64::from_le_bytes(blob[32..40].try_into().unwrap()), 2);
+        assert_eq!((owner_of(&blob, 1), owner_of(&blob, 2), owner_of(&blob, 3)), (Some(pk(&alice)), Some(pk(&bob)), None));
+        assert_eq!(owner_of(&blob, 0), None);
+
+        chain.process_tx_witness(&tx(&chain, &archive, &NftInstruction::Burn { index: 1 }, 1, &alice)).unwrap();
+        let stub = chain.get_stub("Collection1").unwrap().clone();
+        archive.get_witness("Collection1", &stub.merkle_root, 2).unwrap().verify(&stub).unwrap();
+        assert_eq!(owner_of(&archive.get_blob("Collection1", &stub.merkle_root).unwrap().data, 1), None);
+    }
+
+    #[test]
+    fn rejects_unsigned_mints_and_transfers() {
+        let (mut chain, archive) = collection();
+        let (authority, alice, bob) = (key("authority"), key("alice"), key("bob"));
+        let mint = NftInstruction::Mint { owner: pk(&alice), data_hash: [0; 32] };
+        assert!(chain.process_tx_witness(&tx(&chain, &archive, &mint, 0, &alice)).is_err());
+        chain.process_tx_witness(&tx(&chain, &archive, &mint, 0, &authority)).unwrap();
+        let steal = NftInstruction::Transfer { index: 1, new_owner: pk(&bob) };
+        assert!(chain.process_tx_witness(&tx(&chain, &archive, &steal, 1, &bob)).is_err());
+        assert!(chain.process_tx_witness(&tx(&chain, &archive, &NftInstruction::Burn { index: 2 }, 1, &alice)).is_err());
+        assert_eq!(owner_of(&blob(&chain, &archive), 1), Some(pk(&alice)));
+    }
+
+    #[test]
+    fn rejects_a_witness_that_does_not_prove_its_leaf() {
+        let (mut chain, archive) = collection();
+        let authority = key("authority");
+        let mut forged = tx(&chain, &archive, &NftInstruction::Mint { owner: [1; 32], data_hash: [0; 32] }, 0, &authority);
+        forged.witnesses[0].blob[32] = 5;
+        forged.signatures.clear();
+        forged.sign(&authority);
+        assert!(chain.process_tx_witness(&forged).is_err());
+        let stub = chain.get_stub("Collection1").unwrap();
+        assert!(forged.witnesses[0].verify(stub).is_err());
+    }
+}
@@ -0,0 +1,119 @@
+use crate::error::WitnessError;
+use crate::program::MAX_ACCOUNT_DATA_LEN;
+use crate::{chunk_blob, MerkleTree};
+
+/// zstd level used when committing; favours ratio since blobs are written once
+pub const ZSTD_LEVEL: i32 = 19;
+
+/// Codec an account's committed bytes were compressed with
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum, serde::Serialize, serde::Deserialize)]
+pub enum Codec {
+    Zstd,
+}
+
+impl Codec {
+    pub fn id(self) -> u8 {
+        match self {
+            Codec::Zstd => 1,
+        }
+    }
+
+    pub fn from_id(id: u8) -> Option<Self> {
+        match id {
+            1 => Some(Codec::Zstd),
+            _ => None,
+        }
+    }
+}
+
+/// Recorded in the stub when the committed blob is compressed: the tree (and
+/// `data_len`) cover the compressed bytes, `raw_len` is the account's real data size
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct Compression {
+    pub codec: Codec,
+    pub raw_len: u64,
+}
+
+impl Compression {
+    /// Bytes committed to by the stub encoding
+    pub fn encode(&self) -> Vec<u8> {
+        let mut out = vec![self.codec.id()];
+        out.extend_from_slice(&self.raw_len.to_le_bytes());
+        out
+    }
+
+    /// Original account data from committed bytes; refuses output larger than `raw_len`
+    pub fn decompress(&self, stored: &[u8]) -> Result<Vec<u8>, WitnessError> {
+        if self.raw_len as usize > MAX_ACCOUNT_DATA_LEN {
+            return Err(WitnessError::RawLenTooLarge(self.raw_len));
+        }
+        let data = match self.codec {
+            Codec::Zstd => zstd::bulk::decompress(stored, self.raw_len as usize).map_err(|source| WitnessError::Zstd { action: "decompression", source })?,
+        };
+        if data.len() as u64 != self.raw_len {
+            return Err(WitnessError::DecompressedLength { actual: data.len(), recorded: self.raw_len });
+        }
+        Ok(data)
+    }
+}
+
+/// Bytes to offload, their root, and the metadata for the stub
+pub type Committed = (Vec<u8>, [u8;32], Option<Compression>);
+
+/// Commit pipeline: optionally compress `data`, then chunk and build the tree
+pub fn commit_blob(data: &[u8], chunk_size: usize, codec: Option<Codec>) -> Result<Committed, WitnessError> {
+    let (stored, compression) = match codec {
+        None => (data.to_vec(), None),
+        Some(Codec::Zstd) => {
+            let stored = zstd::bulk::compress(data, ZSTD_LEVEL).map_err(|source| WitnessError::Zstd { action: "compression", source })?;
+            (stored, Some(Compression { codec: Codec::Zstd, raw_len: data.len() as u64 }))
+        }
+    };
+    let root = MerkleTree::from_chunks(&chunk_blob(&stored, chunk_size)).root();
+    Ok((stored, root, compression))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn data() -> Vec<u8> {
+        b"balance=100;".repeat(50)
+    }
+
+    #[test]
+    fn compressed_blobs_round_trip() {
+        let data = data();
+        let (stored, root, compression) = commit_blob(&data, 32, Some(Codec::Zstd)).unwrap();
+        let compression = compression.unwrap();
+        assert!(stored.len() < data.len());
+        assert_eq!(root, MerkleTree::from_chunks(&chunk_blob(&stored, 32)).root());
+        assert_eq!(compression.raw_len, data.len() as u64);
+        assert_eq!(compression.decompress(&stored).unwrap(), data);
+        assert_eq!(Codec::from_id(compression.codec.id()), Some(Codec::Zstd));
+        assert_eq!(Codec::from_id(0), None);
+    }
+
+    #[test]
+    fn uncompressed_blobs_commit_as_is() {
+        let data = data();
+        let (stored, root, compression) = commit_blob(&data, 32, None).unwrap();
+        assert_eq!((stored, compression), (data.clone(), None));
+        assert_eq!(root, MerkleTree::from_chunks(&chunk_blob(&data, 32)).root());
+    }
+
+    #[test]
+    fn rejects_corrupt_or_mislabelled_input() {
+        let data = data();
+        let (stored, _, compression) = commit_blob(&data, 32, Some(Codec::Zstd)).unwrap();
+        let compression = compression.unwrap();
+        assert!(matches!(compression.decompress(&stored[..stored.len() - 1]), Err(WitnessError::Zstd { .. })));
+        assert!(matches!(compression.decompress(b"not zstd"), Err(WitnessError::Zstd { .. })));
+        let long = Compression { raw_len: compression.raw_len + 1, ..compression };
+        assert!(matches!(long.decompress(&stored), Err(WitnessError::DecompressedLength { actual: 600, recorded: 601 })));
+        let short = Compression { raw_len: compression.raw_len - 1, ..compression };
+        assert!(matches!(short.decompress(&stored), Err(WitnessError::Zstd { .. })));
+        let huge = Compression { raw_len: MAX_ACCOUNT_DATA_LEN as u64 + 1, ..compression };
+        assert!(matches!(huge.decompress(&stored), Err(WitnessError::RawLenTooLarge(_))));
+    }
+}
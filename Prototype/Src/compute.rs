@@ -0,0 +1,175 @@
+use anyhow::{Result, bail};
+
+use crate::error::WitnessError;
+
+/// Solana's default per-instruction compute budget
+pub const DEFAULT_COMPUTE_BUDGET: u64 = 200_000;
+
+/// Per-operation compute-unit prices, loosely modelled on Solana's syscall costs
+#[derive(Debug, Clone)]
+pub struct ComputeCosts {
+    /// Flat cost of one `sol_sha256` call
+    pub sha256_base: u64,
+    /// Additional cost per 2 bytes hashed
+    pub sha256_byte: u64,
+    /// Minimum cost of a memcpy-style operation
+    pub mem_op_base: u64,
+    /// Bytes copied per compute unit beyond the base
+    pub bytes_per_unit: u64,
+    /// One ed25519 signature verification
+    pub ed25519_verify: u64,
+    /// `sol_poseidon` costs `a * inputs^2 + c`
+    pub poseidon_a: u64,
+    pub poseidon_c: u64,
+    /// BPF instructions spent per proof level outside the hash syscall: slicing out
+    /// the sibling, branching on its side, copying the pair into place
+    pub verify_step_overhead: u64,
+    /// Entrypoint deserialisation, account checks and the closing comparison
+    pub verify_base_overhead: u64,
+}
+
+impl Default for ComputeCosts {
+    fn default() -> Self {
+        Self {
+            sha256_base: 85,
+            sha256_byte: 1,
+            mem_op_base: 10,
+            bytes_per_unit: 250,
+            ed25519_verify: 2_000,
+            poseidon_a: 61,
+            poseidon_c: 542,
+            verify_step_overhead: 40,
+            verify_base_overhead: 1_200,
+        }
+    }
+}
+
+impl ComputeCosts {
+    pub fn sha256(&self, len: usize) -> u64 {
+        self.sha256_base + self.sha256_byte * (len as u64 / 2)
+    }
+
+    pub fn mem_copy(&self, len: usize) -> u64 {
+        self.mem_op_base.max(len as u64 / self.bytes_per_unit)
+    }
+
+    /// Cost of verifying one leaf proof of `depth` siblings: hash the leaf chunk,
+    /// then one 64-byte hash per level
+    pub fn verify_proof(&self, chunk_size: usize, depth: usize) -> u64 {
+        self.sha256(chunk_size) + depth as u64 * self.sha256(64)
+    }
+
+    /// Cost of rebuilding a full tree over `leaves` chunks (leaves is a power of two)
+    pub fn build_tree(&self, chunk_size: usize, leaves: usize) -> u64 {
+        leaves as u64 * self.sha256(chunk_size) + leaves.saturating_sub(1) as u64 * self.sha256(64)
+    }
+}
+
+/// Hash syscall an on-chain verifier calls for leaves and nodes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SyscallHasher {
+    Sha256,
+    Keccak256,
+    Blake3,
+    Poseidon,
+}
+
+impl SyscallHasher {
+    pub fn parse(name: &str) -> Result<Self> {
+        match name {
+            "sha256" => Ok(SyscallHasher::Sha256),
+            "keccak256" => Ok(SyscallHasher::Keccak256),
+            "blake3" => Ok(SyscallHasher::Blake3),
+            "poseidon" => Ok(SyscallHasher::Poseidon),
+            other => bail!("unknown hasher {:?}; expected sha256, keccak256, blake3 or poseidon", other),
+        }
+    }
+}
+
+/// Predicted cost of one on-chain `Verify`, broken down the way the program spends it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VerifyEstimate {
+    /// Proof steps actually sent: tree depth minus the canopy the program keeps
+    pub steps: usize,
+    pub leaf_hash: u64,
+    pub node_hashes: u64,
+    pub overhead: u64,
+    pub total: u64,
+}
+
+impl VerifyEstimate {
+    pub fn fits(&self, budget: u64) -> bool {
+        self.total <= budget
+    }
+}
+
+impl ComputeCosts {
+    /// One hash syscall over `slices` byte slices of the given lengths. The sha256
+    /// family charges at least a memory op per slice; Poseidon takes 32-byte field
+    /// elements, 12 per call at most.
+    pub fn hash_syscall(&self, hasher: SyscallHasher, slices: &[usize]) -> u64 {
+        match hasher {
+            SyscallHasher::Sha256 | SyscallHasher::Keccak256 | SyscallHasher::Blake3 => {
+                self.sha256_base + slices.iter().map(|&len| self.mem_op_base.max(self.sha256_byte * (len as u64 / 2))).sum::<u64>()
+            }
+            SyscallHasher::Poseidon => {
+                let inputs: u64 = slices.iter().map(|&len| (len as u64).div_ceil(32).max(1)).sum();
+                let calls = inputs.div_ceil(12).max(1);
+                let per_call = inputs.div_ceil(calls);
+                calls * (self.poseidon_a * per_call * per_call + self.poseidon_c)
+            }
+        }
+    }
+
+    /// Cost of verifying a `chunk_size`-byte leaf at `depth` on chain when the program
+    /// keeps the top `canopy_depth` levels of the tree in its account
+    pub fn estimate_verify(&self, hasher: SyscallHasher, chunk_size: usize, depth: usize, canopy_depth: usize) -> VerifyEstimate {
+        let steps = depth.saturating_sub(canopy_depth);
+        let leaf_hash = self.hash_syscall(hasher, &[chunk_size]);
+        let node_hashes = steps as u64 * self.hash_syscall(hasher, &[32, 32]);
+        // reading the canopy node the path ends at is one more memory op
+        let canopy_read = if canopy_depth > 0 { self.mem_op_base } else { 0 };
+        let overhead = self.verify_base_overhead + steps as u64 * self.verify_step_overhead + self.mem_copy(chunk_size) + canopy_read;
+        VerifyEstimate { steps, leaf_hash, node_hashes, overhead, total: leaf_hash + node_hashes + overhead }
+    }
+}
+
+/// Tracks compute units consumed by a single transaction against its budget
+#[derive(Debug, Clone)]
+pub struct ComputeMeter {
+    budget: u64,
+    consumed: u64,
+}
+
+impl ComputeMeter {
+    pub fn new(budget: u64) -> Self {
+        Self { budget, consumed: 0 }
+    }
+
+    /// Charge `units` for `what`; fails once the budget would be exceeded
+    pub fn consume(&mut self, units: u64, what: &str) -> Result<(), WitnessError> {
+        let total = self.consumed.saturating_add(units);
+        if total > self.budget {
+            return Err(WitnessError::BudgetExceeded { what: what.to_string(), consumed: self.consumed, units, budget: self.budget });
+        }
+        self.consumed = total;
+        Ok(())
+    }
+
+    pub fn consumed(&self) -> u64 {
+        self.consumed
+    }
+
+    pub fn remaining(&self) -> u64 {
+        self.budget - self.consumed
+    }
+}
+
+/// Verify cost of `chunk_size`-byte chunks at three proof depths, to see how deep a
+/// proof gets before verification alone blows the default budget
+pub fn demo(chunk_size: usize) {
+    let costs = ComputeCosts::default();
+    for depth in [10, 20, 32] {
+        println!("Verify cost at depth {} ({}-byte chunks): {} CU", depth, chunk_size, costs.verify_proof(chunk_size, depth));
+    }
+}
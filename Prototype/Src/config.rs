@@ -0,0 +1,103 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result, bail};
+use serde::Deserialize;
+
+use crate::HashAlgo;
+
+/// Looked for in the working directory when no `--config` is given
+pub const DEFAULT_CONFIG: &str = "solana-bloat.toml";
+
+/// Defaults for the CLI and the simulator; every key is optional.
+///
+/// ```toml
+/// chunk_size = 32
+/// hasher = "sha256"
+/// arity = 2
+/// canopy_depth = 0
+/// blob_store = "blobs"            # relative to the config file
+///
+/// [rpc]
+/// http = "127.0.0.1:8899"
+/// grpc = "127.0.0.1:50051"
+/// ```
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub chunk_size: usize,
+    /// Leaf and node hash; only "sha256" for now
+    pub hasher: String,
+    pub arity: u8,
+    /// Top tree levels a verifier is assumed to keep next to the root, which proofs
+    /// could then omit; `inspect` reports the saving
+    pub canopy_depth: usize,
+    /// Directory for the simulator's filesystem blob store (a temp dir when unset)
+    pub blob_store: Option<PathBuf>,
+    pub rpc: RpcEndpoints,
+    /// Directory relative paths are resolved against
+    #[serde(skip)]
+    pub base_dir: PathBuf,
+}
+
+/// Addresses the servers bind to when none is given on the command line
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RpcEndpoints {
+    pub http: Option<String>,
+    pub grpc: Option<String>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            chunk_size: 32,
+            hasher: "sha256".to_string(),
+            arity: 2,
+            canopy_depth: 0,
+            blob_store: None,
+            rpc: RpcEndpoints::default(),
+            base_dir: PathBuf::new(),
+        }
+    }
+}
+
+impl Config {
+    pub fn load(path: &Path) -> Result<Self> {
+        let text = std::fs::read_to_string(path).with_context(|| format!("reading config {}", path.display()))?;
+        let mut cfg = Self::from_toml_str(&text).with_context(|| format!("in {}", path.display()))?;
+        cfg.base_dir = path.parent().map(Path::to_path_buf).unwrap_or_default();
+        Ok(cfg)
+    }
+
+    /// `path` if given, else `solana-bloat.toml` if there is one, else the defaults
+    pub fn discover(path: Option<&Path>) -> Result<Self> {
+        match path {
+            Some(path) => Self::load(path),
+            None if Path::new(DEFAULT_CONFIG).is_file() => Self::load(Path::new(DEFAULT_CONFIG)),
+            None => Ok(Self::default()),
+        }
+    }
+
+    pub fn from_toml_str(text: &str) -> Result<Self> {
+        let cfg: Self = toml::from_str(text).context("parsing config TOML")?;
+        cfg.hash_algo()?;
+        if cfg.chunk_size == 0 {
+            bail!("chunk_size must be positive");
+        }
+        if cfg.arity != 2 {
+            bail!("unsupported arity {}; trees are binary", cfg.arity);
+        }
+        Ok(cfg)
+    }
+
+    pub fn hash_algo(&self) -> Result<HashAlgo> {
+        match self.hasher.as_str() {
+            "sha256" => Ok(HashAlgo::Sha256),
+            other => bail!("unsupported hasher {:?}", other),
+        }
+    }
+
+    pub fn blob_store_dir(&self) -> Option<PathBuf> {
+        self.blob_store.as_ref().map(|p| self.base_dir.join(p))
+    }
+}
@@ -0,0 +1,162 @@
+//! Rent versus witnesses: what an account costs a year kept on chain and offloaded,
+//! and the update rate at which the two cost the same.
+//!
+//! Kept on chain, an account locks its rent-exempt minimum (`RentConfig`:
+//! `(128 + data_len) * lamports_per_byte_year * 2`) and an update is one transaction
+//! paying the base fee per signature. Offloaded, it locks only its stub's minimum, but
+//! an update carries each chunk it touches with a proof, possibly over several
+//! transactions (`Packer`), and pays priority fees on the compute units spent verifying
+//! and rehashing them. The locked lamports come back when an account is closed, so they
+//! are charged at `capital_rate`, what they would have earned staked.
+
+use anyhow::{Result, bail};
+
+use crate::compute::ComputeCosts;
+use crate::packer::{Packer, MAX_PACKED_CHUNK};
+use crate::rent::RentConfig;
+use crate::view::ChunkWitness;
+use crate::AccountStub;
+
+/// Solana's base fee per transaction signature
+pub const LAMPORTS_PER_SIGNATURE: u64 = 5_000;
+pub const LAMPORTS_PER_SOL: u64 = 1_000_000_000;
+
+/// Prices the comparison is made at; defaults follow mainnet
+#[derive(Debug, Clone)]
+pub struct CostModel {
+    pub rent: RentConfig,
+    pub lamports_per_signature: u64,
+    /// Priority fee price
+    pub micro_lamports_per_cu: u64,
+    /// Yearly return forgone on locked lamports
+    pub capital_rate: f64,
+    pub costs: ComputeCosts,
+}
+
+impl Default for CostModel {
+    fn default() -> Self {
+        Self { rent: RentConfig::default(), lamports_per_signature: LAMPORTS_PER_SIGNATURE, micro_lamports_per_cu: 0, capital_rate: 0.07, costs: ComputeCosts::default() }
+    }
+}
+
+/// The account being priced
+#[derive(Debug, Clone, Copy)]
+pub struct AccountProfile {
+    pub data_len: u64,
+    pub chunk_size: usize,
+    pub updates_per_day: f64,
+    /// Contiguous bytes an update writes
+    pub bytes_per_update: usize,
+}
+
+/// One way of holding the account
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SideCost {
+    /// Rent-exempt minimum locked while the account exists
+    pub deposit: u64,
+    pub txs_per_update: u64,
+    pub compute_per_update: u64,
+    pub fee_per_update: u64,
+    /// Forgone return on the deposit plus a year of update fees
+    pub lamports_per_year: f64,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Comparison {
+    pub onchain: SideCost,
+    pub offloaded: SideCost,
+    /// Updates a day below which offloading is cheaper; `None` when one side is cheaper
+    /// at any rate (offloaded updates cost no more, or offloading frees no deposit)
+    pub break_even_updates_per_day: Option<f64>,
+    /// Updates whose extra fees add up to the deposit offloading frees; `None` as above
+    pub updates_paid_by_deposit: Option<f64>,
+}
+
+impl Comparison {
+    pub fn offload_is_cheaper(&self) -> bool {
+        self.offloaded.lamports_per_year < self.onchain.lamports_per_year
+    }
+}
+
+impl CostModel {
+    fn priority_fee(&self, compute_units: u64) -> u64 {
+        (compute_units as u128 * self.micro_lamports_per_cu as u128).div_ceil(1_000_000) as u64
+    }
+
+    fn side(&self, p: &AccountProfile, deposit: u64, txs_per_update: u64, compute_per_update: u64) -> SideCost {
+        let fee_per_update = txs_per_update * self.lamports_per_signature + self.priority_fee(compute_per_update);
+        let lamports_per_year = deposit as f64 * self.capital_rate + fee_per_update as f64 * p.updates_per_day * 365.0;
+        SideCost { deposit, txs_per_update, compute_per_update, fee_per_update, lamports_per_year }
+    }
+
+    /// The account holding its data, updated by plain instructions
+    pub fn onchain(&self, p: &AccountProfile) -> SideCost {
+        let txs = p.bytes_per_update.div_ceil(MAX_PACKED_CHUNK).max(1) as u64;
+        self.side(p, self.rent.minimum_balance(p.data_len), txs, 0)
+    }
+
+    /// The account as a stub, each update proving and rewriting the chunks it touches
+    pub fn offloaded(&self, p: &AccountProfile) -> Result<SideCost> {
+        if p.chunk_size == 0 {
+            bail!("chunk size must be positive");
+        }
+        let stub = AccountStub::new("WitnessProgram1", 0, [0; 32], p.data_len, p.chunk_size);
+        let chunks = stub.chunk_count();
+        let depth = stub.depth();
+        // an unaligned write can straddle one more chunk than its length needs
+        let touched = (p.bytes_per_update.div_ceil(p.chunk_size) + 1).min(chunks);
+        let witnesses: Vec<ChunkWitness> = (0..touched).map(|i| ChunkWitness {
+            pubkey: String::new(), leaf_index: i, chunk: vec![0; p.chunk_size], proof: vec![([0; 32], false); depth],
+        }).collect();
+        let txs = Packer::default().pack([0; 32], &witnesses)?.len() as u64;
+        // verify the old chunk, then hash the new one up the same path
+        let c = &self.costs;
+        let per_chunk = c.mem_copy(p.chunk_size) + c.verify_proof(p.chunk_size, depth) + c.sha256(p.chunk_size) + depth as u64 * c.sha256(64);
+        Ok(self.side(p, self.rent.minimum_balance(stub.encode().len() as u64), txs, touched as u64 * per_chunk))
+    }
+
+    pub fn compare(&self, p: &AccountProfile) -> Result<Comparison> {
+        if !p.updates_per_day.is_finite() || p.updates_per_day < 0.0 {
+            bail!("updates per day must be a non-negative number");
+        }
+        let onchain = self.onchain(p);
+        let offloaded = self.offloaded(p)?;
+        let extra_fee = offloaded.fee_per_update as f64 - onchain.fee_per_update as f64;
+        let freed = onchain.deposit as f64 - offloaded.deposit as f64;
+        let (break_even, paid) = match extra_fee > 0.0 && freed > 0.0 {
+            true => (Some(freed * self.capital_rate / (extra_fee * 365.0)), Some(freed / extra_fee)),
+            false => (None, None),
+        };
+        Ok(Comparison { onchain, offloaded, break_even_updates_per_day: break_even, updates_paid_by_deposit: paid })
+    }
+}
+
+/// `cost`: print both sides and the break-even for one account
+pub fn run(model: &CostModel, p: &AccountProfile) -> Result<()> {
+    let cmp = model.compare(p)?;
+    println!("{} bytes in {}-byte chunks, {} updates a day of {} bytes, {} µlamports/CU, capital at {:.1}% a year",
+        p.data_len, p.chunk_size, p.updates_per_day, p.bytes_per_update, model.micro_lamports_per_cu, model.capital_rate * 100.0);
+    println!("{:<24} {:>14} {:>14}", "", "on chain", "offloaded");
+    let (on, off) = (&cmp.onchain, &cmp.offloaded);
+    let rows = [
+        ("deposit (lamports)", on.deposit as f64, off.deposit as f64),
+        ("txs per update", on.txs_per_update as f64, off.txs_per_update as f64),
+        ("CU per update", on.compute_per_update as f64, off.compute_per_update as f64),
+        ("fee per update", on.fee_per_update as f64, off.fee_per_update as f64),
+        ("lamports per year", on.lamports_per_year, off.lamports_per_year),
+    ];
+    for (name, onchain, offloaded) in rows {
+        println!("{:<24} {:>14.0} {:>14.0}", name, onchain, offloaded);
+    }
+    let saved = cmp.onchain.lamports_per_year - cmp.offloaded.lamports_per_year;
+    match cmp.offload_is_cheaper() {
+        true => println!("Offloading is cheaper by {:.0} lamports ({:.6} SOL) a year", saved, saved / LAMPORTS_PER_SOL as f64),
+        false => println!("Keeping it on chain is cheaper by {:.0} lamports ({:.6} SOL) a year", -saved, -saved / LAMPORTS_PER_SOL as f64),
+    }
+    match (cmp.break_even_updates_per_day, cmp.updates_paid_by_deposit) {
+        (Some(rate), Some(updates)) =>
+            println!("Break-even at {:.2} updates a day; the deposit offloading frees pays for {:.0} updates", rate, updates),
+        _ => println!("No break-even: {} is cheaper at any update rate", if cmp.offload_is_cheaper() { "offloading" } else { "keeping it on chain" }),
+    }
+    Ok(())
+}
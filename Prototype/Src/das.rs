@@ -0,0 +1,173 @@
+use std::collections::HashSet;
+
+use anyhow::Result;
+
+use crate::erasure::{ExtendedBlob, ExtendedCommitment, Shard, MAX_SHARDS};
+use crate::rng::SimRng;
+
+/// Storage nodes serving the shards of one extended blob. Each node holds a subset
+/// of shards; a shard can be fetched while some online node holds it and the
+/// adversary isn't withholding it.
+#[derive(Debug)]
+pub struct ShardNetwork {
+    pub blob: ExtendedBlob,
+    pub nodes: Vec<HashSet<usize>>,
+    pub offline: HashSet<usize>,
+    pub withheld: HashSet<usize>,
+}
+
+impl ShardNetwork {
+    /// Spread the shards round-robin over `nodes` nodes, each shard on `replicas` of them
+    pub fn new(blob: ExtendedBlob, nodes: usize, replicas: usize) -> Self {
+        let mut held = vec![HashSet::new(); nodes.max(1)];
+        let n = held.len();
+        for shard in 0..blob.commitment.total_shards() {
+            for r in 0..replicas.clamp(1, n) {
+                held[(shard + r) % n].insert(shard);
+            }
+        }
+        Self { blob, nodes: held, offline: HashSet::new(), withheld: HashSet::new() }
+    }
+
+    /// Worst case for samplers: hide just enough shards (one more than the parity count)
+    /// that the blob can no longer be reconstructed, chosen at random
+    pub fn withhold_minimum(&mut self, rng: &mut SimRng) {
+        let total = self.blob.commitment.total_shards();
+        self.withheld.clear();
+        while self.withheld.len() <= self.blob.commitment.parity_shards {
+            self.withheld.insert(rng.range(0, total as u64 - 1) as usize);
+        }
+    }
+
+    pub fn is_available(&self, index: usize) -> bool {
+        !self.withheld.contains(&index) && (0..self.nodes.len()).any(|n| !self.offline.contains(&n) && self.nodes[n].contains(&index))
+    }
+
+    pub fn is_recoverable(&self) -> bool {
+        let available = (0..self.blob.commitment.total_shards()).filter(|&i| self.is_available(i)).count();
+        available >= self.blob.commitment.data_shards
+    }
+
+    /// Fetch shard `index` from any online node holding it
+    pub fn request(&self, index: usize) -> Option<Shard> {
+        if !self.is_available(index) {
+            return None;
+        }
+        self.blob.shard(index)
+    }
+}
+
+/// Light-client check: draw `samples` random shard indices and accept the blob as
+/// available only if every returned shard verifies against the commitment
+pub fn sample_available(network: &ShardNetwork, commitment: &ExtendedCommitment, samples: usize, rng: &mut SimRng) -> bool {
+    (0..samples).all(|_| {
+        let index = rng.range(0, commitment.total_shards() as u64 - 1) as usize;
+        network.request(index).is_some_and(|s| commitment.verify_shard(&s))
+    })
+}
+
+/// Probability that `samples` uniform draws (with replacement) all miss `withheld` of
+/// `total` shards, i.e. that an unavailable blob is wrongly accepted
+pub fn false_accept_probability(total: usize, withheld: usize, samples: usize) -> f64 {
+    ((total - withheld) as f64 / total as f64).powi(samples as i32)
+}
+
+/// Fewest samples that bring the false-accept probability under `1 - confidence`
+pub fn samples_needed(total: usize, withheld: usize, confidence: f64) -> usize {
+    (1..).find(|&s| 1.0 - false_accept_probability(total, withheld, s) >= confidence).unwrap()
+}
+
+/// One row of the sampling report
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct SamplingReport {
+    pub blob_len: usize,
+    pub data_shards: usize,
+    pub total_shards: usize,
+    pub samples: usize,
+    /// Share of trials with an unrecoverable blob in which sampling caught it
+    pub detection_rate: f64,
+    /// Closed-form detection probability for comparison
+    pub analytic: f64,
+    pub needed_for_99: usize,
+    pub needed_for_99_99: usize,
+}
+
+/// Simulate `trials` rounds against a blob of `blob_len` random bytes extended 2x
+/// (capped at `MAX_SHARDS` shards by growing the shard size), with the adversary
+/// withholding the minimum needed to make it unrecoverable each round
+pub fn simulate(blob_len: usize, samples: usize, trials: usize, seed: u64) -> Result<SamplingReport> {
+    let mut rng = SimRng::new(seed);
+    let data_shards = blob_len.div_ceil(32).clamp(1, MAX_SHARDS / 2);
+    let shard_size = blob_len.div_ceil(data_shards).max(1);
+    let blob: Vec<u8> = (0..blob_len).map(|_| rng.next_u64() as u8).collect();
+    let extended = ExtendedBlob::encode(&blob, shard_size, data_shards)?;
+    let commitment = extended.commitment.clone();
+    let mut network = ShardNetwork::new(extended, 16, 2);
+    let (mut detected, mut unrecoverable) = (0, 0);
+    for _ in 0..trials {
+        network.withhold_minimum(&mut rng);
+        // only a blob that really can't be rebuilt is one sampling should catch
+        if network.is_recoverable() {
+            continue;
+        }
+        unrecoverable += 1;
+        if !sample_available(&network, &commitment, samples, &mut rng) {
+            detected += 1;
+        }
+    }
+    let total = commitment.total_shards();
+    let withheld = commitment.parity_shards + 1;
+    Ok(SamplingReport {
+        blob_len,
+        data_shards: commitment.data_shards,
+        total_shards: total,
+        samples,
+        detection_rate: detected as f64 / unrecoverable.max(1) as f64,
+        analytic: 1.0 - false_accept_probability(total, withheld, samples),
+        needed_for_99: samples_needed(total, withheld, 0.99),
+        needed_for_99_99: samples_needed(total, withheld, 0.9999),
+    })
+}
+
+/// Sampling against an adversary withholding just enough shards, at three blob sizes
+pub fn demo() -> Result<()> {
+    println!("\nDA sampling (2x extension, 1000 trials):");
+    for blob_len in [1024, 16 * 1024, 256 * 1024] {
+        let r = simulate(blob_len, 10, 1000, 11)?;
+        println!("  {:>7} bytes, {:>3}/{:>3} shards, {} samples: detected {:.3} (analytic {:.3}); samples for 99%: {}, 99.99%: {}",
+            r.blob_len, r.data_shards, r.total_shards, r.samples, r.detection_rate, r.analytic, r.needed_for_99, r.needed_for_99_99);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn network() -> ShardNetwork {
+        let blob: Vec<u8> = (0..256u32).map(|i| (i * 7) as u8).collect();
+        ShardNetwork::new(ExtendedBlob::encode(&blob, 32, 8).unwrap(), 4, 1)
+    }
+
+    #[test]
+    fn recoverable_while_enough_shards_are_served() {
+        let mut net = network();
+        assert!(net.is_recoverable());
+        net.withheld.extend(0..net.blob.commitment.parity_shards);
+        assert!(net.is_recoverable());
+        net.withheld.insert(net.blob.commitment.parity_shards);
+        assert!(!net.is_recoverable());
+        net.withheld.clear();
+        // each shard is on one of four nodes, so three going offline leaves a quarter
+        net.offline.extend([0, 1, 2]);
+        assert!(!net.is_recoverable());
+    }
+
+    #[test]
+    fn sampling_catches_withheld_blobs_about_as_often_as_predicted() {
+        let r = simulate(1024, 4, 1000, 3).unwrap();
+        assert_eq!(r.samples, 4);
+        assert!((r.detection_rate - r.analytic).abs() < 0.03, "{:?}", r);
+        assert_eq!(samples_needed(r.total_shards, r.total_shards - r.data_shards + 1, 0.99), r.needed_for_99);
+    }
+}
@@ -0,0 +1,153 @@
+use crate::error::WitnessError;
+use crate::program::MAX_ACCOUNT_DATA_LEN;
+
+/// Granularity the diff compares at; account updates usually rewrite a few fields in
+/// place or append, so aligned blocks catch nearly all shared bytes cheaply
+pub const DELTA_BLOCK: usize = 64;
+
+const OP_COPY: u8 = 0;
+const OP_INSERT: u8 = 1;
+
+/// Binary diff turning `base` into `new`: the new length, then a sequence of
+/// `copy(offset, len)` from `base` and `insert(bytes)` ops
+pub fn encode_delta(base: &[u8], new: &[u8]) -> Vec<u8> {
+    let mut out = (new.len() as u64).to_le_bytes().to_vec();
+    let mut run: Option<(bool, usize)> = None;
+    let flush = |out: &mut Vec<u8>, same: bool, start: usize, end: usize| {
+        if same {
+            out.push(OP_COPY);
+            out.extend_from_slice(&(start as u64).to_le_bytes());
+        } else {
+            out.push(OP_INSERT);
+        }
+        out.extend_from_slice(&((end - start) as u32).to_le_bytes());
+        if !same {
+            out.extend_from_slice(&new[start..end]);
+        }
+    };
+    for start in (0..new.len()).step_by(DELTA_BLOCK) {
+        let end = (start + DELTA_BLOCK).min(new.len());
+        let same = base.get(start..end) == Some(&new[start..end]);
+        match run {
+            Some((s, _)) if s == same => {}
+            Some((s, run_start)) => {
+                flush(&mut out, s, run_start, start);
+                run = Some((same, start));
+            }
+            None => run = Some((same, start)),
+        }
+    }
+    if let Some((same, run_start)) = run {
+        flush(&mut out, same, run_start, new.len());
+    }
+    out
+}
+
+/// Rebuild the new version from `base` and a delta made by `encode_delta`. The header
+/// length is checked against the account size limit before anything is allocated, and
+/// the output against the header as it grows.
+pub fn apply_delta(base: &[u8], delta: &[u8]) -> Result<Vec<u8>, WitnessError> {
+    let read = |pos: usize, n: usize| -> Result<&[u8], WitnessError> {
+        pos.checked_add(n).and_then(|end| delta.get(pos..end)).ok_or(WitnessError::DeltaTruncated(pos))
+    };
+    let header = u64::from_le_bytes(read(0, 8)?.try_into().unwrap());
+    if header > MAX_ACCOUNT_DATA_LEN as u64 {
+        return Err(WitnessError::DeltaTooLarge(header));
+    }
+    let new_len = header as usize;
+    let mut out = Vec::with_capacity(new_len);
+    let mut pos = 8;
+    while pos < delta.len() {
+        let op = delta[pos];
+        pos += 1;
+        match op {
+            OP_COPY => {
+                let offset = u64::from_le_bytes(read(pos, 8)?.try_into().unwrap()) as usize;
+                let len = u32::from_le_bytes(read(pos + 8, 4)?.try_into().unwrap()) as usize;
+                pos += 12;
+                match offset.checked_add(len).and_then(|end| base.get(offset..end)) {
+                    Some(b) => out.extend_from_slice(b),
+                    None => return Err(WitnessError::DeltaPastBase { offset, len }),
+                }
+            }
+            OP_INSERT => {
+                let len = u32::from_le_bytes(read(pos, 4)?.try_into().unwrap()) as usize;
+                out.extend_from_slice(read(pos + 4, len)?);
+                pos += 4 + len;
+            }
+            _ => return Err(WitnessError::UnknownDeltaOp(op)),
+        }
+        // copies are cheap to encode, so a short delta could otherwise repeat its base at will
+        if out.len() > new_len {
+            return Err(WitnessError::DeltaLength { produced: out.len(), header: new_len });
+        }
+    }
+    if out.len() != new_len {
+        return Err(WitnessError::DeltaLength { produced: out.len(), header: new_len });
+    }
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn blob(len: usize, seed: u8) -> Vec<u8> {
+        (0..len).map(|i| (i as u8).wrapping_mul(31).wrapping_add(seed)).collect()
+    }
+
+    fn copy(offset: u64, len: u32) -> Vec<u8> {
+        let mut op = vec![OP_COPY];
+        op.extend_from_slice(&offset.to_le_bytes());
+        op.extend_from_slice(&len.to_le_bytes());
+        op
+    }
+
+    #[test]
+    fn round_trips_edits_appends_and_truncations() {
+        let base = blob(1000, 0);
+        let mut edited = base.clone();
+        edited[300..310].fill(0xee);
+        let mut appended = base.clone();
+        appended.extend_from_slice(&blob(150, 7));
+        for new in [base.clone(), edited, appended, base[..500].to_vec(), blob(1000, 9), Vec::new()] {
+            assert_eq!(apply_delta(&base, &encode_delta(&base, &new)).unwrap(), new);
+        }
+        assert_eq!(apply_delta(&[], &encode_delta(&[], &base)).unwrap(), base);
+        // an unchanged blob is one copy op
+        assert_eq!(encode_delta(&base, &base).len(), 8 + 13);
+    }
+
+    #[test]
+    fn rejects_corrupt_deltas() {
+        let base = blob(200, 0);
+        let mut new = base.clone();
+        new[100] ^= 1;
+        let delta = encode_delta(&base, &new);
+        assert!(matches!(apply_delta(&base, &delta[..5]), Err(WitnessError::DeltaTruncated(0))));
+        assert!(matches!(apply_delta(&base, &delta[..delta.len() - 1]), Err(WitnessError::DeltaTruncated(_))));
+        let mut bad_op = delta.clone();
+        bad_op[8] = 9;
+        assert!(matches!(apply_delta(&base, &bad_op), Err(WitnessError::UnknownDeltaOp(9))));
+        assert!(matches!(apply_delta(&base[..100], &delta), Err(WitnessError::DeltaPastBase { .. })));
+        let mut short = delta.clone();
+        short[..8].copy_from_slice(&199u64.to_le_bytes());
+        assert!(matches!(apply_delta(&base, &short), Err(WitnessError::DeltaLength { header: 199, .. })));
+    }
+
+    #[test]
+    fn rejects_lengths_before_allocating_or_overflowing() {
+        let base = blob(64, 0);
+        let huge = [u64::MAX.to_le_bytes().to_vec(), copy(0, 64)].concat();
+        assert!(matches!(apply_delta(&base, &huge), Err(WitnessError::DeltaTooLarge(u64::MAX))));
+        let past = [64u64.to_le_bytes().to_vec(), copy(u64::MAX, 64)].concat();
+        assert!(matches!(apply_delta(&base, &past), Err(WitnessError::DeltaPastBase { .. })));
+        let mut insert = 64u64.to_le_bytes().to_vec();
+        insert.push(OP_INSERT);
+        insert.extend_from_slice(&u32::MAX.to_le_bytes());
+        assert!(matches!(apply_delta(&base, &insert), Err(WitnessError::DeltaTruncated(_))));
+        // repeated copies stop at the header's length instead of growing without bound
+        let repeated: Vec<u8> = [64u64.to_le_bytes().to_vec()].into_iter().chain((0..1000).map(|_| copy(0, 64))).flatten().collect();
+        assert!(matches!(apply_delta(&base, &repeated), Err(WitnessError::DeltaLength { produced: 128, header: 64 })));
+    }
+}
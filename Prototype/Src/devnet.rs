@@ -0,0 +1,227 @@
+//! The commit / prove / verify flow against a real cluster instead of the simulator.
+//! Roots and proofs travel in SPL Memo instructions, so nothing beyond the memo
+//! program has to be deployed: a commit memo records an account's root in the ledger,
+//! and a witness memo carries a chunk with its proof in a real, signed transaction.
+
+use std::path::Path;
+
+use anyhow::{Context, Result, anyhow, bail};
+use solana_client::rpc_client::RpcClient;
+use solana_client::rpc_config::RpcTransactionConfig;
+use solana_commitment_config::CommitmentConfig;
+use solana_sdk::hash::Hash;
+use solana_sdk::instruction::{AccountMeta, Instruction};
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::message::Message;
+use solana_sdk::signature::Signature;
+use solana_sdk::transaction::Transaction;
+use solana_transaction_status_client_types::UiTransactionEncoding;
+
+use crate::packer::MAX_TX_SIZE;
+use crate::signer::Signer;
+use crate::view::ChunkWitness;
+use crate::{chunk_blob, MerkleTree};
+
+pub const DEVNET_URL: &str = "https://api.devnet.solana.com";
+pub const MEMO_PROGRAM_ID: Pubkey = solana_sdk::pubkey!("MemoSq4gqABAXKb96qnH8TysNcWxMyWCqXgDLGmfcHr");
+
+const COMMIT_TAG: &str = "bloat:commit:v1";
+const WITNESS_TAG: &str = "bloat:witness:v1";
+
+/// What one of our memos says; the memo program only takes UTF-8, so bytes are hex
+#[derive(Debug, Clone, PartialEq)]
+pub enum Memo {
+    /// `account`'s data is `data_len` bytes in `chunk_size`-byte chunks under `root`
+    Commit { account: String, root: [u8;32], data_len: u64, chunk_size: usize },
+    /// A chunk of `account` proven against `root`
+    Witness { root: [u8;32], witness: ChunkWitness },
+}
+
+fn hash32(s: &str, what: &str) -> Result<[u8;32]> {
+    let bytes = hex::decode(s).with_context(|| format!("{} is not hex", what))?;
+    bytes.try_into().map_err(|_| anyhow!("{} must be 32 bytes", what))
+}
+
+impl Memo {
+    pub fn to_text(&self) -> String {
+        match self {
+            Memo::Commit { account, root, data_len, chunk_size } =>
+                format!("{} {} {} {} {}", COMMIT_TAG, account, hex::encode(root), data_len, chunk_size),
+            Memo::Witness { root, witness } => format!("{} {} {} {} {} {}", WITNESS_TAG, witness.pubkey, witness.leaf_index,
+                hex::encode(root), hex::encode(&witness.chunk), hex::encode(witness_core::encode_steps(&witness.proof))),
+        }
+    }
+
+    pub fn parse(text: &str) -> Result<Self> {
+        let words: Vec<&str> = text.split(' ').collect();
+        match words.as_slice() {
+            [COMMIT_TAG, account, root, data_len, chunk_size] => Ok(Memo::Commit {
+                account: account.to_string(),
+                root: hash32(root, "root")?,
+                data_len: data_len.parse().context("data_len is not a number")?,
+                chunk_size: chunk_size.parse().context("chunk_size is not a number")?,
+            }),
+            [WITNESS_TAG, account, leaf, root, chunk, steps] => {
+                let steps = hex::decode(steps).context("proof is not hex")?;
+                Ok(Memo::Witness {
+                    root: hash32(root, "root")?,
+                    witness: ChunkWitness {
+                        pubkey: account.to_string(),
+                        leaf_index: leaf.parse().context("leaf index is not a number")?,
+                        chunk: hex::decode(chunk).context("chunk is not hex")?,
+                        proof: witness_core::decode_steps(&steps).context("malformed proof steps")?,
+                    },
+                })
+            }
+            _ => bail!("not a solana-bloat memo"),
+        }
+    }
+}
+
+/// Memo instruction signed by `signer`, so the memo is attributable to it
+pub fn memo_instruction(memo: &Memo, signer: &Pubkey) -> Instruction {
+    Instruction {
+        program_id: MEMO_PROGRAM_ID,
+        accounts: vec![AccountMeta::new_readonly(*signer, true)],
+        data: memo.to_text().into_bytes(),
+    }
+}
+
+/// Our memo in a transaction, if it has one
+pub fn find_memo(tx: &Transaction) -> Option<Result<Memo>> {
+    tx.message.instructions.iter()
+        .find(|ix| tx.message.account_keys.get(ix.program_id_index as usize) == Some(&MEMO_PROGRAM_ID))
+        .map(|ix| std::str::from_utf8(&ix.data).context("memo is not UTF-8").and_then(Memo::parse))
+}
+
+/// Check a witness memo against a commit memo the way an on-chain verifier would:
+/// same account and root, the chunk and proof leading to that root
+pub fn check_witness(commit: &Memo, witness: &Memo) -> Result<()> {
+    let (Memo::Commit { account, root, data_len, chunk_size }, Memo::Witness { root: proven, witness }) = (commit, witness) else {
+        bail!("expected a commit memo and a witness memo");
+    };
+    if witness.pubkey != *account || proven != root {
+        bail!("witness is for {} under {}, commitment is {} under {}", witness.pubkey, hex::encode(proven), account, hex::encode(root));
+    }
+    if *chunk_size == 0 || witness.chunk.len() != *chunk_size {
+        bail!("chunk is {} bytes, {} commits {}-byte chunks", witness.chunk.len(), account, chunk_size);
+    }
+    let leaf_count = (*data_len as usize).div_ceil(*chunk_size).max(1);
+    if !MerkleTree::verify_proof_at(&witness.chunk, &witness.proof, root, witness.leaf_index, leaf_count) {
+        bail!("proof of leaf {} does not verify against {}", witness.leaf_index, hex::encode(root));
+    }
+    Ok(())
+}
+
+/// A payer and an RPC endpoint; transactions are built locally and signed by whatever
+/// `Signer` holds the payer key
+pub struct Devnet {
+    pub rpc: RpcClient,
+    pub payer: Box<dyn Signer>,
+}
+
+impl Devnet {
+    pub fn new(url: &str, payer: Box<dyn Signer>) -> Self {
+        Self { rpc: RpcClient::new_with_commitment(url.to_string(), CommitmentConfig::confirmed()), payer }
+    }
+
+    pub fn payer_pubkey(&self) -> Pubkey {
+        Pubkey::new_from_array(self.payer.pubkey())
+    }
+
+    /// Signed transaction carrying `memo`, paid for by the payer
+    pub fn memo_tx(&self, memo: &Memo, blockhash: Hash) -> Result<Transaction> {
+        let payer = self.payer_pubkey();
+        let mut message = Message::new(&[memo_instruction(memo, &payer)], Some(&payer));
+        message.recent_blockhash = blockhash;
+        let mut tx = Transaction::new_unsigned(message);
+        tx.signatures = vec![Signature::from(self.payer.sign_message(&tx.message_data())?)];
+        let size = bincode::serialize(&tx)?.len();
+        if size > MAX_TX_SIZE {
+            bail!("transaction is {} bytes, over the {}-byte limit; use a larger chunk size or a shallower tree", size, MAX_TX_SIZE);
+        }
+        Ok(tx)
+    }
+
+    /// Ask the faucet for lamports if the payer has fewer than `min_lamports`
+    pub fn fund(&self, min_lamports: u64) -> Result<()> {
+        let balance = self.rpc.get_balance(&self.payer_pubkey()).context("getting payer balance")?;
+        if balance >= min_lamports {
+            return Ok(());
+        }
+        let sig = self.rpc.request_airdrop(&self.payer_pubkey(), min_lamports - balance).context("requesting airdrop")?;
+        self.rpc.poll_for_signature(&sig).context("waiting for airdrop")?;
+        Ok(())
+    }
+
+    pub fn send(&self, memo: &Memo) -> Result<Signature> {
+        let blockhash = self.rpc.get_latest_blockhash().context("getting latest blockhash")?;
+        let tx = self.memo_tx(memo, blockhash)?;
+        self.rpc.send_and_confirm_transaction(&tx).context("sending memo transaction")
+    }
+
+    /// Read our memo back out of a confirmed transaction
+    pub fn fetch(&self, sig: &Signature) -> Result<Memo> {
+        let config = RpcTransactionConfig {
+            encoding: Some(UiTransactionEncoding::Base64),
+            commitment: Some(CommitmentConfig::confirmed()),
+            max_supported_transaction_version: Some(0),
+        };
+        let confirmed = self.rpc.get_transaction_with_config(sig, config).with_context(|| format!("fetching {}", sig))?;
+        let tx = confirmed.transaction.transaction.decode().context("decoding transaction")?;
+        let tx = tx.into_legacy_transaction().context("not a legacy transaction")?;
+        find_memo(&tx).with_context(|| format!("{} has no solana-bloat memo", sig))?
+    }
+}
+
+/// `devnet`: commit `file`'s root under `account`, send chunk `leaf` with its proof in a
+/// second transaction, then read both back and verify the proof from what landed.
+/// With `dry_run` nothing is sent: the transactions are signed over a dummy
+/// blockhash and decoded from their wire bytes instead.
+pub fn run(devnet: &Devnet, account: &str, file: &Path, chunk_size: usize, leaf: usize, dry_run: bool) -> Result<()> {
+    let blob = std::fs::read(file).with_context(|| format!("reading {}", file.display()))?;
+    if chunk_size == 0 {
+        bail!("chunk size must be positive");
+    }
+    let chunks = chunk_blob(&blob, chunk_size);
+    if leaf >= chunks.len() {
+        bail!("leaf {} out of range: {} chunks", leaf, chunks.len());
+    }
+    let tree = MerkleTree::from_chunks(&chunks);
+    let root = tree.root();
+    let commit = Memo::Commit { account: account.to_string(), root, data_len: blob.len() as u64, chunk_size };
+    let witness = Memo::Witness {
+        root,
+        witness: ChunkWitness { pubkey: account.to_string(), leaf_index: leaf, chunk: chunks[leaf].to_vec(), proof: tree.gen_proof(leaf) },
+    };
+    println!("payer {}", devnet.payer_pubkey());
+    println!("{}: {} bytes, {} chunks, root {}", account, blob.len(), chunks.len(), hex::encode(root));
+
+    let (landed_commit, landed_witness) = if dry_run {
+        let mut landed = Vec::new();
+        for (what, memo) in [("commit", &commit), ("witness", &witness)] {
+            let tx = devnet.memo_tx(memo, Hash::default())?;
+            let wire = bincode::serialize(&tx)?;
+            println!("{} tx {} ({} bytes, not sent)", what, tx.signatures[0], wire.len());
+            let decoded: Transaction = bincode::deserialize(&wire)?;
+            decoded.verify().context("signature check")?;
+            landed.push(find_memo(&decoded).context("memo lost in encoding")??);
+        }
+        let witness = landed.pop().unwrap();
+        (landed.pop().unwrap(), witness)
+    } else {
+        devnet.fund(10_000_000)?;
+        let commit_sig = devnet.send(&commit)?;
+        println!("commit tx {}", commit_sig);
+        let witness_sig = devnet.send(&witness)?;
+        println!("witness tx {}", witness_sig);
+        (devnet.fetch(&commit_sig)?, devnet.fetch(&witness_sig)?)
+    };
+    if landed_commit != commit || landed_witness != witness {
+        bail!("memos changed on the way through the cluster");
+    }
+    check_witness(&landed_commit, &landed_witness)?;
+    let source = if dry_run { "decoded from the wire bytes" } else { "read back from the ledger" };
+    println!("ok: leaf {} {} verifies against the committed root", leaf, source);
+    Ok(())
+}
@@ -0,0 +1,212 @@
+//! `diagnose`: when a local blob does not commit to the root an account holds, find
+//! which chunks are wrong instead of just that one is. The local tree is walked from
+//! the root down, each node's children compared with the root's tree, and only
+//! mismatched subtrees descended into, so k bad chunks cost about 2k·depth comparisons.
+//!
+//! The root's tree is whatever `NodeSource` can supply its nodes: a known-good copy, or
+//! inclusion proofs from an archive or proof server, each of which reveals every node
+//! along its path and every sibling of one.
+
+use std::collections::HashMap;
+
+use anyhow::{Result, bail};
+
+use crate::archive::ArchiveNode;
+use crate::view::ChunkWitness;
+use crate::{chunk_blob, h, sha256, MerkleTree};
+
+/// Nodes of the tree a root commits to, by (level, index), 0 being the leaf hashes
+pub trait NodeSource {
+    /// Levels above the leaves
+    fn depth(&mut self) -> Result<usize>;
+    fn node(&mut self, level: usize, index: usize) -> Result<[u8;32]>;
+}
+
+impl NodeSource for MerkleTree {
+    fn depth(&mut self) -> Result<usize> {
+        Ok(MerkleTree::depth(self))
+    }
+
+    fn node(&mut self, level: usize, index: usize) -> Result<[u8;32]> {
+        match self.layer(level).get(index) {
+            Some(node) => Ok(*node),
+            None => bail!("reference tree has no node {} at level {}", index, level),
+        }
+    }
+}
+
+/// Nodes learnt from chunk proofs against `root`, fetched on demand
+pub struct ProofNodes<F> {
+    root: [u8;32],
+    leaf_count: usize,
+    fetch: F,
+    known: HashMap<(usize, usize), [u8;32]>,
+    /// Proofs fetched so far
+    pub fetched: usize,
+}
+
+impl<F: FnMut(usize) -> Result<ChunkWitness>> ProofNodes<F> {
+    /// `fetch` returns the proof of a leaf against `root`, a tree of `leaf_count` chunks
+    pub fn new(root: [u8;32], leaf_count: usize, fetch: F) -> Self {
+        Self { root, leaf_count, fetch, known: HashMap::new(), fetched: 0 }
+    }
+
+    /// Fetch leaf `leaf`'s proof, check it against the root and keep what it reveals
+    fn learn(&mut self, leaf: usize) -> Result<()> {
+        let witness = (self.fetch)(leaf)?;
+        self.fetched += 1;
+        let depth = witness_core::tree_depth(self.leaf_count);
+        if witness_core::check_proof_shape(&witness.proof, leaf, self.leaf_count).is_err() {
+            bail!("proof served for leaf {} is for a different leaf or tree shape", leaf);
+        }
+        if !MerkleTree::verify_proof(&witness.chunk, &witness.proof, &self.root) {
+            bail!("proof served for leaf {} does not verify against {}", leaf, h(&self.root));
+        }
+        let mut node = sha256(&witness.chunk);
+        for (level, (sibling, is_left)) in witness.proof.iter().enumerate() {
+            let index = leaf >> level;
+            self.known.insert((level, index), node);
+            self.known.insert((level, index ^ 1), *sibling);
+            let mut data = [0u8; 64];
+            let (left, right) = if *is_left { (sibling, &node) } else { (&node, sibling) };
+            data[..32].copy_from_slice(left);
+            data[32..].copy_from_slice(right);
+            node = sha256(&data);
+        }
+        self.known.insert((depth, 0), node);
+        Ok(())
+    }
+}
+
+impl<F: FnMut(usize) -> Result<ChunkWitness>> NodeSource for ProofNodes<F> {
+    fn depth(&mut self) -> Result<usize> {
+        Ok(witness_core::tree_depth(self.leaf_count))
+    }
+
+    fn node(&mut self, level: usize, index: usize) -> Result<[u8;32]> {
+        if let Some(node) = self.known.get(&(level, index)) {
+            return Ok(*node);
+        }
+        // the first leaf below the node has it on its path
+        self.learn(index << level)?;
+        Ok(self.known[&(level, index)])
+    }
+}
+
+/// Where a local blob and a root part ways
+#[derive(Debug, Clone, Default)]
+pub struct Diagnosis {
+    /// Chunks of the local blob that differ, ascending
+    pub chunks: Vec<usize>,
+    /// Leaves past the local blob's last chunk that are not padding in the root's tree
+    /// either: the root's blob is longer
+    pub past_end: Vec<usize>,
+    /// Reference nodes compared
+    pub nodes_compared: usize,
+}
+
+impl Diagnosis {
+    pub fn matches(&self) -> bool {
+        self.chunks.is_empty() && self.past_end.is_empty()
+    }
+}
+
+/// Bisect the `chunk_size`-byte chunks of `blob` against the tree behind `root`.
+/// Errors when the two trees differ in depth, since then no node lines up.
+pub fn diagnose(blob: &[u8], chunk_size: usize, root: &[u8;32], source: &mut dyn NodeSource) -> Result<Diagnosis> {
+    if chunk_size == 0 {
+        bail!("chunk size must be positive");
+    }
+    let chunks = chunk_blob(blob, chunk_size);
+    let mut diagnosis = Diagnosis::default();
+    let local = MerkleTree::from_chunks(&chunks);
+    if local.root() == *root {
+        return Ok(diagnosis);
+    }
+    let depth = local.depth();
+    let theirs = source.depth()?;
+    if theirs != depth {
+        bail!("local tree is {} levels deep over {} chunks, the root's is {}: the blob's length or chunk size differs", depth, chunks.len(), theirs);
+    }
+    if source.node(depth, 0)? != *root {
+        bail!("the reference does not commit to {}", h(root));
+    }
+    // a subtree of nothing but padding is copies of the last leaf, on either side
+    let their_last = source.node(0, chunks.len() - 1)?;
+    let padding = |level: usize| (0..level).fold(their_last, |node, _| witness_core::hash_pair(&node, &node));
+    let mut stack = vec![(depth, 0usize)];
+    while let Some((level, index)) = stack.pop() {
+        if level == 0 {
+            match index < chunks.len() {
+                true => diagnosis.chunks.push(index),
+                false => diagnosis.past_end.push(index),
+            }
+            continue;
+        }
+        // right first so leaves come off the stack in order
+        for child in [2 * index + 1, 2 * index] {
+            diagnosis.nodes_compared += 1;
+            let node = source.node(level - 1, child)?;
+            let past_end = child << (level - 1) >= chunks.len();
+            if node != local.layer(level - 1)[child] && !(past_end && node == padding(level - 1)) {
+                stack.push((level - 1, child));
+            }
+        }
+    }
+    Ok(diagnosis)
+}
+
+/// Runs of consecutive indices, as `a-b` or `a`
+fn ranges(indices: &[usize]) -> String {
+    let mut out: Vec<String> = Vec::new();
+    let mut i = 0;
+    while i < indices.len() {
+        let start = indices[i];
+        while i + 1 < indices.len() && indices[i + 1] == indices[i] + 1 {
+            i += 1;
+        }
+        out.push(if indices[i] == start { start.to_string() } else { format!("{}-{}", start, indices[i]) });
+        i += 1;
+    }
+    out.join(", ")
+}
+
+/// Print a diagnosis of `chunk_size`-byte chunks the way `diagnose` does
+pub fn report(d: &Diagnosis, chunk_size: usize, chunk_count: usize) {
+    if d.matches() {
+        println!("local blob matches the root");
+        return;
+    }
+    println!("{} of {} chunks differ: {}", d.chunks.len(), chunk_count, ranges(&d.chunks));
+    for &i in d.chunks.iter().take(16) {
+        println!("  chunk {}: bytes {}..{}", i, i * chunk_size, (i + 1) * chunk_size);
+    }
+    if d.chunks.len() > 16 {
+        println!("  ... and {} more", d.chunks.len() - 16);
+    }
+    if !d.past_end.is_empty() {
+        println!("{} leaves past the local blob's end differ ({}): the root's blob is longer", d.past_end.len(), ranges(&d.past_end));
+    }
+    println!("{} reference nodes compared", d.nodes_compared);
+}
+
+/// A local copy with two bad chunks, found with proofs from the archive
+pub fn demo() -> Result<()> {
+    const CHUNK_SIZE: usize = 64;
+    let archive = ArchiveNode::new();
+    let blob: Vec<u8> = (0..64 * 1024u32).map(|i| (i.wrapping_mul(2654435761) >> 13) as u8).collect();
+    let root = archive.ingest("Diag1", 0, CHUNK_SIZE, &blob)?;
+    let mut local = blob.clone();
+    local[70 * CHUNK_SIZE + 5] ^= 0xff;
+    local[900 * CHUNK_SIZE] ^= 0x01;
+    let mut source = ProofNodes::new(root, blob.len() / CHUNK_SIZE, |leaf| match archive.get_chunk_witness("Diag1", &root, leaf) {
+        Some(witness) => Ok(witness),
+        None => bail!("archive has no leaf {}", leaf),
+    });
+    let d = diagnose(&local, CHUNK_SIZE, &root, &mut source)?;
+    if d.chunks != [70, 900] {
+        bail!("diagnosis found chunks {:?}", d.chunks);
+    }
+    println!("Bad chunks {} of {} found from {} archive proofs and {} node comparisons", ranges(&d.chunks), blob.len() / CHUNK_SIZE, source.fetched, d.nodes_compared);
+    Ok(())
+}
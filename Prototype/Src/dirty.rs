@@ -0,0 +1,108 @@
+use crate::chunk_blob;
+
+/// Slots a dirty bitmap accumulates before it starts over
+pub const DIRTY_WINDOW_SLOTS: u64 = 32;
+
+/// Bits in a dirty bitmap; chunk `i` maps to bit `i % DIRTY_BITS`, so for accounts with
+/// more chunks it behaves like a one-hash Bloom filter (false positives, never negatives)
+pub const DIRTY_BITS: usize = 256;
+
+/// Chunk indices written since `since_slot`, kept in the stub next to the root. A prover
+/// holding proofs cached before `since_slot` only has to rebuild the paths of dirty leaves.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct DirtyBitmap {
+    #[serde(with = "crate::serde_support::hex_form")]
+    pub bits: [u8;32],
+    pub since_slot: u64,
+}
+
+impl DirtyBitmap {
+    pub fn new(since_slot: u64) -> Self {
+        Self { bits: [0u8; 32], since_slot }
+    }
+
+    pub fn mark(&mut self, index: usize) {
+        let bit = index % DIRTY_BITS;
+        self.bits[bit / 8] |= 1 << (bit % 8);
+    }
+
+    /// False means chunk `index` is certainly unchanged since `since_slot`
+    pub fn may_be_dirty(&self, index: usize) -> bool {
+        let bit = index % DIRTY_BITS;
+        self.bits[bit / 8] & (1 << (bit % 8)) != 0
+    }
+
+    /// Chunks of an account with `leaf_count` leaves whose cached proofs need rebuilding
+    pub fn dirty_indices(&self, leaf_count: usize) -> Vec<usize> {
+        (0..leaf_count).filter(|&i| self.may_be_dirty(i)).collect()
+    }
+
+    /// Mark every chunk that differs between `old` and `new` (including chunks only one
+    /// side has), first starting a new window if the current one is over
+    pub fn record_write(&mut self, old: &[u8], new: &[u8], chunk_size: usize, slot: u64) {
+        if slot >= self.since_slot + DIRTY_WINDOW_SLOTS {
+            *self = Self::new(slot);
+        }
+        let (old_chunks, new_chunks) = (chunk_blob(old, chunk_size), chunk_blob(new, chunk_size));
+        for i in 0..old_chunks.len().max(new_chunks.len()) {
+            if old_chunks.get(i) != new_chunks.get(i) {
+                self.mark(i);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::MerkleTree;
+
+    /// Leaf hashes of `new`, reusing `old`'s for every chunk the bitmap calls clean
+    fn refreshed_leaves(old: &MerkleTree, old_len: usize, new: &[u8], chunk_size: usize, dirty: &DirtyBitmap) -> Vec<[u8;32]> {
+        let (old_count, chunks) = (old_len.div_ceil(chunk_size).max(1), chunk_blob(new, chunk_size));
+        (0..chunks.len()).map(|i| if i < old_count && !dirty.may_be_dirty(i) {
+            old.leaves()[i]
+        } else {
+            witness_core::hash_leaves([chunks.get(i).unwrap()])[0]
+        }).collect()
+    }
+
+    #[test]
+    fn dirty_leaves_rebuild_the_full_tree() {
+        let old: Vec<u8> = (0..300u32).map(|i| i as u8).collect();
+        let tree = MerkleTree::from_chunks(&chunk_blob(&old, 32));
+        for new in [
+            { let mut b = old.clone(); b[40] ^= 1; b[299] ^= 1; b },
+            old[..100].to_vec(),
+            [old.clone(), vec![7; 90]].concat(),
+        ] {
+            let mut dirty = DirtyBitmap::new(0);
+            dirty.record_write(&old, &new, 32, 1);
+            let leaves = refreshed_leaves(&tree, old.len(), &new, 32, &dirty);
+            assert_eq!(MerkleTree::from_leaves(leaves).root(), MerkleTree::from_chunks(&chunk_blob(&new, 32)).root());
+        }
+    }
+
+    #[test]
+    fn marks_only_changed_chunks() {
+        let old = vec![0u8; 128];
+        let mut new = old.clone();
+        new[33] = 1;
+        let mut dirty = DirtyBitmap::new(0);
+        dirty.record_write(&old, &new, 32, 0);
+        assert_eq!(dirty.dirty_indices(4), vec![1]);
+        // chunks a bitmap's width apart share a bit
+        assert!(dirty.may_be_dirty(1 + DIRTY_BITS));
+    }
+
+    #[test]
+    fn a_write_after_the_window_starts_over() {
+        let old = vec![0u8; 64];
+        let mut dirty = DirtyBitmap::new(10);
+        dirty.record_write(&old, &[vec![1; 32], vec![0; 32]].concat(), 32, 10);
+        dirty.record_write(&old, &[vec![0; 32], vec![1; 32]].concat(), 32, 10 + DIRTY_WINDOW_SLOTS - 1);
+        assert_eq!(dirty.dirty_indices(2), vec![0, 1]);
+        dirty.record_write(&old, &[vec![0; 32], vec![2; 32]].concat(), 32, 10 + DIRTY_WINDOW_SLOTS);
+        assert_eq!(dirty, DirtyBitmap { bits: { let mut b = [0u8; 32]; b[0] = 2; b }, since_slot: 10 + DIRTY_WINDOW_SLOTS });
+    }
+}
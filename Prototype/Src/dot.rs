@@ -0,0 +1,46 @@
+//! Graphviz export of a tree: every node with its level, index and short hash, edges
+//! from parent to children, left child first. With a leaf given, the path it climbs
+//! to the root is filled in and each sibling its proof carries is labelled with its
+//! step and direction flag, so a render shows exactly the nodes a witness touches.
+
+use std::fmt::Write;
+
+use crate::{h, MerkleTree};
+
+impl MerkleTree {
+    /// The tree as a DOT digraph, root on top; `proof_leaf` must be below the padded
+    /// width
+    pub fn to_dot(&self, proof_leaf: Option<usize>) -> String {
+        let depth = self.depth();
+        let mut out = String::from("digraph merkle {\n  ordering=out;\n  node [shape=box, fontname=monospace, fontsize=10];\n");
+        for level in (0..=depth).rev() {
+            let _ = writeln!(out, "  {{ rank=same;");
+            for (i, node) in self.layer(level).iter().enumerate() {
+                let name = if level == depth { "root".to_string() } else { format!("L{}:{}", level, i) };
+                let mut label = format!("{}\\n{}", name, h(node));
+                let mut style = String::new();
+                if let Some(leaf) = proof_leaf {
+                    let idx = leaf >> level;
+                    if i == idx {
+                        style = ", style=filled, fillcolor=lightblue".to_string();
+                    } else if level < depth && i == idx ^ 1 {
+                        // the flag gen_proof sets: the sibling is the left child
+                        let _ = write!(label, "\\nproof[{}] is_left={}", level, idx & 1 == 1);
+                        style = ", style=filled, fillcolor=orange".to_string();
+                    }
+                }
+                let _ = writeln!(out, "    n{}_{} [label=\"{}\"{}];", level, i, label, style);
+            }
+            out += "  }\n";
+        }
+        for level in 1..=depth {
+            for child in 0..self.layer(level - 1).len() {
+                let on_path = proof_leaf.is_some_and(|leaf| leaf >> (level - 1) == child);
+                let style = if on_path { " [color=blue, penwidth=2]" } else { "" };
+                let _ = writeln!(out, "  n{}_{} -> n{}_{}{};", level, child / 2, level - 1, child, style);
+            }
+        }
+        out += "}\n";
+        out
+    }
+}
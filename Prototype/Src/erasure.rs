@@ -0,0 +1,169 @@
+use std::collections::BTreeMap;
+
+use anyhow::Result;
+use reed_solomon_erasure::galois_8::ReedSolomon;
+
+use crate::error::WitnessError;
+use crate::{chunk_blob, MerkleTree};
+
+/// GF(2^8) Reed–Solomon allows at most this many shards in total
+pub const MAX_SHARDS: usize = 256;
+
+/// One shard of an extended blob with its proof against the extended root
+#[derive(Debug, Clone)]
+pub struct Shard {
+    pub index: usize,
+    pub data: Vec<u8>,
+    pub proof: Vec<([u8;32], bool)>,
+}
+
+/// What a verifier needs to check and decode shards: a Merkle root over all data and
+/// parity shards, plus the code parameters. The first `data_shards` shards are exactly
+/// the blob's chunks, so their tree alone gives the account's ordinary root.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExtendedCommitment {
+    pub root: [u8;32],
+    pub data_shards: usize,
+    pub parity_shards: usize,
+    pub shard_size: usize,
+    pub blob_len: u64,
+}
+
+/// Blob chunks extended with Reed–Solomon parity, held by whoever serves the shards
+#[derive(Debug)]
+pub struct ExtendedBlob {
+    pub commitment: ExtendedCommitment,
+    pub shards: Vec<Vec<u8>>,
+    tree: MerkleTree,
+}
+
+fn codec(data_shards: usize, parity_shards: usize) -> Result<ReedSolomon, WitnessError> {
+    if data_shards == 0 || parity_shards == 0 || data_shards + parity_shards > MAX_SHARDS {
+        return Err(WitnessError::UnsupportedCode { data_shards, parity_shards });
+    }
+    ReedSolomon::new(data_shards, parity_shards).map_err(|source| WitnessError::Erasure { action: "codec", source })
+}
+
+/// Data shards of `blob` followed by `parity_shards` parity shards
+fn extend(blob: &[u8], chunk_size: usize, parity_shards: usize) -> Result<Vec<Vec<u8>>, WitnessError> {
+    let mut shards = chunk_blob(blob, chunk_size).to_vecs();
+    let rs = codec(shards.len(), parity_shards)?;
+    shards.extend((0..parity_shards).map(|_| vec![0u8; chunk_size]));
+    rs.encode(&mut shards).map_err(|source| WitnessError::Erasure { action: "encoding", source })?;
+    Ok(shards)
+}
+
+impl ExtendedBlob {
+    /// Split `blob` into `chunk_size` data shards and add `parity_shards` parity shards;
+    /// any `data_shards` of the result rebuild the blob
+    pub fn encode(blob: &[u8], chunk_size: usize, parity_shards: usize) -> Result<Self, WitnessError> {
+        let shards = extend(blob, chunk_size, parity_shards)?;
+        let tree = MerkleTree::from_chunks(&shards);
+        let commitment = ExtendedCommitment {
+            root: tree.root(),
+            data_shards: shards.len() - parity_shards,
+            parity_shards,
+            shard_size: chunk_size,
+            blob_len: blob.len() as u64,
+        };
+        Ok(Self { commitment, shards, tree })
+    }
+
+    pub fn shard(&self, index: usize) -> Option<Shard> {
+        let data = self.shards.get(index)?.clone();
+        Some(Shard { index, data, proof: self.tree.gen_proof(index) })
+    }
+}
+
+impl ExtendedCommitment {
+    pub fn total_shards(&self) -> usize {
+        self.data_shards + self.parity_shards
+    }
+
+    pub fn verify_shard(&self, shard: &Shard) -> bool {
+        shard.data.len() == self.shard_size
+            && MerkleTree::verify_proof_at(&shard.data, &shard.proof, &self.root, shard.index, self.total_shards())
+    }
+
+    /// Rebuild the blob from any `data_shards` valid shards. The full extended set is then
+    /// re-derived and checked against the root, so a commitment whose parity was not
+    /// computed honestly fails here instead of decoding to different blobs depending on
+    /// which shards were used.
+    pub fn reconstruct(&self, shards: &[Shard]) -> Result<Vec<u8>, WitnessError> {
+        let mut known = BTreeMap::new();
+        for s in shards {
+            if !self.verify_shard(s) {
+                return Err(WitnessError::ShardNotCommitted(s.index));
+            }
+            known.insert(s.index, s.data.clone());
+        }
+        if known.len() < self.data_shards {
+            return Err(WitnessError::TooFewShards { need: self.data_shards, got: known.len() });
+        }
+        let rs = codec(self.data_shards, self.parity_shards)?;
+        let mut slots: Vec<Option<Vec<u8>>> = (0..self.total_shards()).map(|i| known.remove(&i)).collect();
+        rs.reconstruct_data(&mut slots).map_err(|source| WitnessError::Erasure { action: "reconstruction", source })?;
+        let mut blob: Vec<u8> = slots.into_iter().take(self.data_shards).flat_map(|s| s.unwrap()).collect();
+        blob.truncate(self.blob_len as usize);
+        let recomputed = MerkleTree::from_chunks(&extend(&blob, self.shard_size, self.parity_shards)?).root();
+        if recomputed != self.root {
+            return Err(WitnessError::ExtendedRootMismatch);
+        }
+        Ok(blob)
+    }
+}
+
+/// Rebuild `pubkey`'s blob from every other shard of a 2x extension and check it
+/// against `root`
+pub fn demo(pubkey: &str, blob: &[u8], chunk_size: usize, root: &[u8;32]) -> Result<()> {
+    let extended = ExtendedBlob::encode(blob, chunk_size, 4)?;
+    let survivors: Vec<_> = (0..extended.commitment.total_shards()).filter(|i| i % 2 == 1).filter_map(|i| extended.shard(i)).collect();
+    let rebuilt = extended.commitment.reconstruct(&survivors)?;
+    println!("Rebuilt {} from {} of {} shards; matches stub root: {}", pubkey, survivors.len(), extended.commitment.total_shards(),
+        MerkleTree::from_chunks(&chunk_blob(&rebuilt, chunk_size)).root() == *root);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn blob() -> Vec<u8> {
+        (0..200u32).map(|i| (i * 7) as u8).collect()
+    }
+
+    #[test]
+    fn rebuilds_with_up_to_parity_shards_missing() {
+        let blob = blob();
+        let extended = ExtendedBlob::encode(&blob, 32, 3).unwrap();
+        let c = &extended.commitment;
+        assert_eq!((c.data_shards, c.total_shards()), (7, 10));
+        assert_eq!(MerkleTree::from_chunks(&extended.shards[..7]).root(), MerkleTree::from_chunks(&chunk_blob(&blob, 32)).root());
+        for missing in [vec![], vec![0], vec![0, 3, 6], vec![7, 8, 9], vec![2, 5, 9]] {
+            let held: Vec<Shard> = (0..10).filter(|i| !missing.contains(i)).map(|i| extended.shard(i).unwrap()).collect();
+            assert_eq!(c.reconstruct(&held).unwrap(), blob);
+        }
+    }
+
+    #[test]
+    fn rejects_too_few_or_uncommitted_shards() {
+        let extended = ExtendedBlob::encode(&blob(), 32, 3).unwrap();
+        let c = &extended.commitment;
+        let mut held: Vec<Shard> = (0..6).map(|i| extended.shard(i).unwrap()).collect();
+        // a repeated shard does not count twice
+        held.push(extended.shard(0).unwrap());
+        assert!(matches!(c.reconstruct(&held), Err(WitnessError::TooFewShards { need: 7, got: 6 })));
+        held.push(extended.shard(8).unwrap());
+        held[1].data[0] ^= 1;
+        assert!(matches!(c.reconstruct(&held), Err(WitnessError::ShardNotCommitted(1))));
+        let mut moved = extended.shard(8).unwrap();
+        moved.index = 9;
+        assert!(matches!(c.reconstruct(&[moved]), Err(WitnessError::ShardNotCommitted(9))));
+    }
+
+    #[test]
+    fn rejects_codes_the_field_cannot_hold() {
+        assert!(matches!(ExtendedBlob::encode(&blob(), 32, 0), Err(WitnessError::UnsupportedCode { data_shards: 7, parity_shards: 0 })));
+        assert!(matches!(ExtendedBlob::encode(&[0u8; 250], 1, 7), Err(WitnessError::UnsupportedCode { data_shards: 250, parity_shards: 7 })));
+    }
+}
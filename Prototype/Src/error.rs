@@ -0,0 +1,343 @@
+//! What verifying and executing transactions can fail with, as values callers can
+//! match on. The messages are the ones these checks have always printed. The library
+//! returns it throughout: programs, verifiers, the byte formats it reads, archives,
+//! storage and the rest. `anyhow` is left to the edges, where errors are only printed:
+//! the CLI and its commands, the measurement and diagnosis tools, config and genesis
+//! files, keypairs, file I/O, the servers and clients, blob store backends and the
+//! demos. One converts into the other with `?` in either direction, and a
+//! `WitnessError` that passed through `anyhow` code comes back out as itself rather
+//! than as `Program`.
+
+use thiserror::Error;
+use witness_core::ShapeError;
+
+use crate::compression::Codec;
+use crate::compute::SyscallHasher;
+use crate::{h, HashAlgo};
+
+#[derive(Debug, Error)]
+pub enum WitnessError {
+    #[error("no stub for pubkey {0}")]
+    UnknownAccount(String),
+    #[error("unknown program {0}")]
+    UnknownProgram(String),
+    #[error("program {program} cannot write account {pubkey} owned by {owner}")]
+    NotOwner { program: String, pubkey: String, owner: String },
+    #[error("account {0} is executable and cannot be modified")]
+    Executable(String),
+
+    #[error("account data is committed {0:?}-compressed; revive it before use")]
+    Compressed(Codec),
+    #[error("append-only accounts have no chunk tree; use append_entry")]
+    AppendOnly,
+    #[error("account {0} is not append-only")]
+    NotAppendOnly(String),
+    #[error("stub has chunk_size 0")]
+    ZeroChunkSize,
+    #[error("unsupported tree layout: {hash_algo:?} with arity {arity}")]
+    UnsupportedLayout { hash_algo: HashAlgo, arity: u8 },
+
+    #[error("proof index {index} out of range ({chunks} chunks)")]
+    ProofIndexOutOfRange { index: usize, chunks: usize },
+    /// The proof's direction bits spell out a different leaf than the one claimed
+    #[error("proof is for leaf {proven}, not {claimed}")]
+    ProofIndexMismatch { claimed: usize, proven: usize },
+    #[error("proof verification failed for {pubkey} leaf {leaf_index}")]
+    ProofVerificationFailed { pubkey: String, leaf_index: usize, expected: [u8;32], computed: [u8;32] },
+    #[error("proof for {pubkey} has {actual} steps, its tree is {expected} deep")]
+    ProofLength { pubkey: String, expected: usize, actual: usize },
+    /// A proof outside any account failed `check_proof_shape`
+    #[error(transparent)]
+    MalformedProof(#[from] ShapeError),
+    #[error("leaf {leaf_index} does not verify against {}", h(.root))]
+    LeafNotCommitted { leaf_index: usize, root: [u8;32] },
+    #[error("proof is for root {}, not {}", h(.proof), h(.expected))]
+    WrongRoot { proof: [u8;32], expected: [u8;32] },
+    #[error("proof claims a tree of {claimed} leaves, the commitment has {committed}")]
+    LeafCountMismatch { claimed: usize, committed: usize },
+    #[error("chunk is {actual} bytes, proof says chunks are {expected}")]
+    ProofChunkSize { expected: usize, actual: usize },
+    #[error("cannot verify a {0:?} tree; only sha256 trees are built here")]
+    UnsupportedHasher(SyscallHasher),
+    #[error("proofs of different depths ({0} and {1})")]
+    ProofDepthMismatch(usize, usize),
+    #[error("no leaves given")]
+    NoLeaves,
+    #[error("chunk for {pubkey} is {actual} bytes, stub chunk size is {expected}")]
+    ChunkSizeMismatch { pubkey: String, expected: usize, actual: usize },
+    #[error("chunk for {chunk} in a tx for {tx}")]
+    ChunkForOtherAccount { chunk: String, tx: String },
+    #[error("bad or duplicate chunk {leaf_index} for {pubkey}")]
+    BadChunk { pubkey: String, leaf_index: usize },
+    #[error("program wrote chunk {0} it was not given")]
+    UnprovenChunkWrite(usize),
+    #[error("write past the end of {0}")]
+    WritePastEnd(String),
+    #[error("byte {pos} lies in unproven chunk {chunk}")]
+    UnprovenByte { pos: usize, chunk: usize },
+    #[error("field {0} is not valid UTF-8")]
+    FieldNotUtf8(String),
+    #[error("pointer {0} does not resolve to the claimed span")]
+    PointerMismatch(String),
+    #[error("span {start}..{end} does not fit a blob of {len} bytes")]
+    SpanOutOfBlob { start: usize, end: usize, len: usize },
+    #[error("frontier has {peaks} peaks for {leaves} leaves")]
+    MalformedFrontier { peaks: usize, leaves: u64 },
+    #[error("frontier root {} does not match stub root {}", h(.frontier), h(.stub))]
+    FrontierRootMismatch { frontier: [u8;32], stub: [u8;32] },
+
+    #[error("chunk {index} rejected: {reason}")]
+    StreamChunkRejected { index: usize, reason: String },
+    #[error("provider sent more than the blob's {0} chunks")]
+    StreamTooLong(usize),
+    #[error("stream ended after {received} of {chunks} chunks")]
+    StreamIncomplete { received: usize, chunks: usize },
+
+    #[error("transaction for session {} applied to {}", h(.tx), h(.session))]
+    WrongSession { session: [u8;32], tx: [u8;32] },
+    #[error("session {} expected transaction {expected}, got {got}", h(.session))]
+    SessionOutOfOrder { session: [u8;32], expected: u32, got: u32 },
+    #[error("leaf {0} sent twice")]
+    LeafSentTwice(usize),
+    #[error("steps for leaf {0} before its chunk")]
+    StepsBeforeChunk(usize),
+    #[error("leaf {leaf_index} is at level {level}, steps start at {from_level}")]
+    StepLevelMismatch { leaf_index: usize, level: usize, from_level: usize },
+    #[error("step {level} of leaf {leaf_index} points the wrong way")]
+    StepDirection { leaf_index: usize, level: usize },
+    #[error("session {} closed with leaf {leaf} only partly proven", h(.session))]
+    SessionIncomplete { session: [u8;32], leaf: usize },
+
+    #[error("blockhash {} not found or expired", h(.0))]
+    BlockhashNotFound([u8;32]),
+    #[error("transaction {} already processed", h(.0))]
+    AlreadyProcessed([u8;32]),
+    #[error("invalid signer key {}", h(.0))]
+    InvalidSignerKey([u8;32]),
+    #[error("bad signature from {}", h(.0))]
+    BadSignature([u8;32]),
+    #[error("account {pubkey} needs {threshold} of {signers} multisig signatures, got {approvals}")]
+    MultisigThreshold { pubkey: String, threshold: u8, signers: usize, approvals: usize },
+    #[error("account {pubkey} is locked until slot {unlock_slot} (now {slot})")]
+    Locked { pubkey: String, unlock_slot: u64, slot: u64 },
+    #[error("account {pubkey} was already written in epoch {epoch}")]
+    AlreadyWrittenInEpoch { pubkey: String, epoch: u64 },
+    #[error("compute budget exceeded during {what}: {consumed} + {units} > {budget} CU")]
+    BudgetExceeded { what: String, consumed: u64, units: u64, budget: u64 },
+
+    #[error("max invoke depth {max} exceeded calling {program}")]
+    InvokeDepth { program: String, max: usize },
+    #[error("reentrant call into {program} (stack: {})", .stack.join(" -> "))]
+    Reentrancy { program: String, stack: Vec<String> },
+    #[error("account {pubkey} not available to {program}")]
+    AccountNotAvailable { program: String, pubkey: String },
+    #[error("{caller} passed account {pubkey} it was not given to {program}")]
+    AccountNotPassed { caller: String, program: String, pubkey: String },
+    #[error("program {program} wrote account {pubkey} it was not given")]
+    WroteUngivenAccount { program: String, pubkey: String },
+    #[error("program {program} wrote read-only account {pubkey}")]
+    ReadOnly { program: String, pubkey: String },
+    #[error("account {pubkey} is not derived from program {program}")]
+    NotDerived { program: String, pubkey: String },
+    #[error("account {pubkey} would be {len} bytes, max is {max}")]
+    AccountTooLarge { pubkey: String, len: usize, max: usize },
+    #[error("account {pubkey} grew by {grown} bytes, max per tx is {max}")]
+    GrewTooMuch { pubkey: String, grown: usize, max: usize },
+    /// A program turned down its instruction or accounts, in its own words
+    #[error("{0}")]
+    Rejected(String),
+
+    #[error("no hot account {0}")]
+    NoHotAccount(String),
+    #[error("cannot {action} {pubkey} inside an open batch")]
+    InsideBatch { action: &'static str, pubkey: String },
+    #[error("blob store has no blob for {pubkey} (root {})", h(.root))]
+    BlobNotStored { pubkey: String, root: [u8;32] },
+    #[error("blob root {} does not match stub root {}", h(.blob), h(.stub))]
+    BlobRootMismatch { blob: [u8;32], stub: [u8;32] },
+    #[error("blob of {pubkey} is {len} bytes, its stub holds {data_len}")]
+    BlobLengthMismatch { pubkey: String, len: usize, data_len: u64 },
+
+    #[error("provider {0} has no stake")]
+    NoStake(String),
+    #[error("stale optimistic update: stub root is {}, update builds on {}", h(.stub), h(.update))]
+    StaleOptimisticUpdate { stub: [u8;32], update: [u8;32] },
+    #[error("no pending optimistic update {0} (finalized or unknown)")]
+    NoPendingUpdate(u64),
+    #[error("fraud proof pre-state does not match old root {}", h(.0))]
+    FraudPreStateMismatch([u8;32]),
+    #[error("chunk {leaf_index} of update {id} matches honest execution; challenge rejected")]
+    ChallengeRejected { id: u64, leaf_index: usize },
+
+    #[error("header for slot {slot} is not newer than {latest}")]
+    StaleHeader { slot: u64, latest: u64 },
+    #[error("header for slot {slot} does not extend bank hash {}", h(.parent))]
+    HeaderDoesNotExtend { slot: u64, parent: [u8;32] },
+    #[error("no bank hash for slot {0}")]
+    UnknownBankHash(u64),
+    #[error("header does not match bank hash for slot {0}")]
+    BankHashMismatch(u64),
+    #[error("missing account proof for {0}")]
+    MissingAccountProof(String),
+    #[error("account proof for {pubkey} does not match state root {}", h(.state_root))]
+    AccountProofFailed { pubkey: String, state_root: [u8;32] },
+
+    #[error("a batch is already open")]
+    BatchAlreadyOpen,
+    #[error("no open batch to commit")]
+    NoOpenBatch,
+    #[error("batch rolled back at tx {index}: {source}")]
+    BatchRolledBack { index: usize, source: Box<WitnessError> },
+
+    #[error("rollup built against state root {}, the chain is at {}", h(.batch), h(.expected))]
+    StaleStateRoot { expected: [u8;32], batch: [u8;32] },
+    #[error("rollup state proofs lead to {}, not the state root {}", h(.computed), h(.expected))]
+    StateProofFailed { expected: [u8;32], computed: [u8;32] },
+    #[error("account {0} is updated twice in one rollup")]
+    DuplicateAccount(String),
+    #[error("state leaf {0} is proven twice in one rollup")]
+    DuplicateStateLeaf(usize),
+
+    #[error("{0} truncated")]
+    Truncated(&'static str),
+    #[error("{len} trailing bytes after {what}")]
+    TrailingBytes { what: &'static str, len: usize },
+    #[error("not a {0} (bad magic)")]
+    BadMagic(&'static str),
+    #[error("unsupported {what} version {version}")]
+    UnsupportedVersion { what: &'static str, version: u8 },
+    /// Bytes that break their format some other way, in the decoder's words
+    #[error("{0}")]
+    Malformed(String),
+    #[error("leaf {leaf_index} out of range: the tree has {leaf_count} leaves")]
+    LeafOutOfTree { leaf_index: usize, leaf_count: usize },
+    #[error("{steps} siblings, but a tree of {leaf_count} leaves is {depth} deep")]
+    WrongDepth { steps: usize, leaf_count: usize, depth: usize },
+    #[error("blob file holds {held} chunks, its manifest says {manifest}")]
+    ManifestChunks { held: usize, manifest: usize },
+    #[error("blob file data has root {}, its manifest says {}", h(.data), h(.manifest))]
+    ManifestRoot { data: [u8;32], manifest: [u8;32] },
+    #[error("token record must be {expected} bytes, got {actual}")]
+    RecordSize { expected: usize, actual: usize },
+    #[error("schema has no field {0}")]
+    UnknownField(String),
+    #[error("blob too short for schema ({len} bytes, need {need})")]
+    BlobTooShort { len: usize, need: usize },
+    #[error("'{0}' not found")]
+    PointerNotFound(String),
+
+    #[error("truncated delta at byte {0}")]
+    DeltaTruncated(usize),
+    #[error("delta copies {len} bytes at {offset}, past the end of its base")]
+    DeltaPastBase { offset: usize, len: usize },
+    #[error("delta rebuilds {0} bytes, past the account size limit")]
+    DeltaTooLarge(u64),
+    #[error("unknown delta op {0}")]
+    UnknownDeltaOp(u8),
+    #[error("delta produced {produced} bytes, header says {header}")]
+    DeltaLength { produced: usize, header: usize },
+    #[error("raw length {0} exceeds the account size limit")]
+    RawLenTooLarge(u64),
+    #[error("zstd {action}: {source}")]
+    Zstd { action: &'static str, source: std::io::Error },
+    #[error("decompressed {actual} bytes, stub records {recorded}")]
+    DecompressedLength { actual: usize, recorded: u64 },
+    #[error("unsupported code: {data_shards} data + {parity_shards} parity shards (max {} total)", crate::erasure::MAX_SHARDS)]
+    UnsupportedCode { data_shards: usize, parity_shards: usize },
+    #[error("erasure {action}: {source}")]
+    Erasure { action: &'static str, source: reed_solomon_erasure::Error },
+    #[error("shard {0} does not verify against the extended root")]
+    ShardNotCommitted(usize),
+    #[error("need {need} distinct shards to reconstruct, got {got}")]
+    TooFewShards { need: usize, got: usize },
+    #[error("extended root does not match a correct encoding of the decoded blob")]
+    ExtendedRootMismatch,
+
+    /// The archive's blob store failed underneath it
+    #[error("blob store: {0:#}")]
+    Store(anyhow::Error),
+    #[error("blob {} missing from the store", h(.0))]
+    BlobMissing([u8;32]),
+    #[error("delta base {} of {} is no longer held", h(.base), h(.version))]
+    DeltaBaseMissing { base: [u8;32], version: [u8;32] },
+    #[error("delta for {} missing from the store", h(.0))]
+    DeltaMissing([u8;32]),
+    #[error("delta chain for {} does not rebuild its root", h(.0))]
+    DeltaChainMismatch([u8;32]),
+    #[error("archive has no chunk {leaf_index} of {pubkey} under root {}", h(.root))]
+    ChunkNotArchived { pubkey: String, leaf_index: usize, root: [u8;32] },
+
+    #[error("storage provider {0} has not registered a key")]
+    UnregisteredProvider(String),
+    #[error("storage provider {0} is already registered with another key")]
+    ProviderRegistered(String),
+    #[error("bad attestation signature from {0}")]
+    BadAttestation(String),
+    #[error("provider {provider} stake {stake} below minimum {}", crate::storage::MIN_PROVIDER_STAKE)]
+    StakeTooLow { provider: String, stake: u64 },
+    #[error("attested root {} is not the current root {}", h(.attested), h(.current))]
+    StaleAttestation { attested: [u8;32], current: [u8;32] },
+    #[error("{provider} has not attested to {pubkey} at root {}", h(.root))]
+    NotAttested { provider: String, pubkey: String, root: [u8;32] },
+    #[error("no open retrieval challenge {0}")]
+    NoRetrievalChallenge(u64),
+    #[error("no online storage provider to replicate {0} to")]
+    NoProvider(String),
+
+    #[error("transaction {} already in mempool", h(.0))]
+    AlreadyQueued([u8;32]),
+    #[error("mempool full and fee {fee} does not beat lowest {lowest}")]
+    MempoolFull { fee: u64, lowest: u64 },
+    #[error("mempool has capacity 0 and queues nothing")]
+    MempoolDisabled,
+    #[error("commit or roll back the open batch before taking a snapshot")]
+    SnapshotInBatch,
+    #[error("threshold {threshold} invalid for {signers} signers")]
+    InvalidThreshold { threshold: u8, signers: usize },
+    #[error("duplicate multisig signer")]
+    DuplicateSigner,
+    #[error("{0} multisig signers, max is 255")]
+    TooManySigners(usize),
+    #[error("{0} seeds given, max is {max}", max = crate::pubkey::MAX_SEEDS - 1)]
+    TooManySeeds(usize),
+    #[error("seed of {0} bytes exceeds {max}", max = crate::pubkey::MAX_SEED_LEN)]
+    SeedTooLong(usize),
+    #[error("derived address falls on the ed25519 curve")]
+    OnCurve,
+    #[error("no off-curve address for these seeds under {0}")]
+    NoOffCurveAddress(String),
+    #[error("derived account {0} already exists")]
+    AccountExists(String),
+    #[error("a {0}-byte transaction has no room for a proof step")]
+    TxTooSmall(usize),
+    #[error("chunk {leaf_index} is {len} bytes; a chunk must fit in one transaction")]
+    ChunkTooLarge { leaf_index: usize, len: usize },
+
+    #[error("reading OS randomness: {0}")]
+    Randomness(#[from] getrandom::Error),
+    #[cfg(feature = "zk")]
+    #[error("proof system: {0}")]
+    Synthesis(#[from] ark_relations::r1cs::SynthesisError),
+    #[cfg(feature = "zk")]
+    #[error("malformed proof: {0}")]
+    ProofEncoding(#[from] ark_serialize::SerializationError),
+    #[cfg(feature = "zk")]
+    #[error("the transition does not satisfy its own circuit")]
+    Unsatisfied,
+    #[cfg(feature = "zk")]
+    #[error("a valid transition proof was rejected")]
+    ValidProofRejected,
+    #[cfg(feature = "zk")]
+    #[error("write to chunk {index} is {actual} bytes, chunks are {expected}")]
+    WriteChunkSize { index: usize, expected: usize, actual: usize },
+
+    /// Whatever a program (or other `anyhow` code) failed with
+    #[error(transparent)]
+    Program(anyhow::Error),
+}
+
+impl From<anyhow::Error> for WitnessError {
+    fn from(e: anyhow::Error) -> Self {
+        e.downcast::<WitnessError>().unwrap_or_else(WitnessError::Program)
+    }
+}
@@ -0,0 +1,86 @@
+use std::sync::{Arc, Mutex};
+
+use crate::h;
+
+/// Typed events emitted by `ChainState` while processing witness transactions
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum WitnessEvent {
+    /// A leaf proof verified against the stub's stored root
+    ProofVerified { pubkey: String, leaf_index: usize, root: [u8;32] },
+    /// A stub's merkle root was replaced after applying a transaction
+    RootUpdated { pubkey: String, slot: u64, old: [u8;32], new: [u8;32] },
+    /// Full post-state of an account written by a tx, for data-availability consumers.
+    /// Optimistic updates only post roots, so they never produce this event.
+    AccountDataWritten { pubkey: String, slot: u64, root: [u8;32], chunk_size: usize, data: Vec<u8> },
+    /// Some chunks of an account were rewritten by a `ChunkTx`; `chunks` holds the new
+    /// contents of every chunk the tx proved, so holders of the blob at `old_root` can patch it
+    ChunksWritten { pubkey: String, slot: u64, old_root: [u8;32], root: [u8;32], chunk_size: usize, chunks: Vec<(usize, Vec<u8>)> },
+    /// A transaction was rejected; nothing was applied
+    TxFailed { pubkey: String, reason: String },
+    /// An optimistic update was successfully challenged and its provider slashed
+    FraudProven { pubkey: String, provider: String, slashed: u64 },
+    /// An entry was appended to an append-only account as leaf `index`
+    EntryAppended { pubkey: String, index: u64, root: [u8;32], entry: Vec<u8> },
+    /// A storage provider missed a retrieval challenge for a blob it attested to
+    RetrievalFailed { pubkey: String, provider: String, slashed: u64 },
+}
+
+/// Receives every event emitted by a `ChainState`.
+/// `Send + Sync` so a `ChainState` can be shared with worker threads during parallel execution.
+pub trait EventSubscriber: Send + Sync {
+    fn on_event(&mut self, event: &WitnessEvent);
+}
+
+/// Human-readable subscriber for the demo binary (prints to stdout)
+pub struct StdoutSubscriber;
+
+impl EventSubscriber for StdoutSubscriber {
+    fn on_event(&mut self, event: &WitnessEvent) {
+        match event {
+            WitnessEvent::ProofVerified { pubkey, leaf_index, root } => {
+                println!("✅ Proof verified for pubkey {} leaf {} (stub root {})", pubkey, leaf_index, h(root));
+            }
+            WitnessEvent::RootUpdated { new, .. } => {
+                println!("🔁 Applied tx: updated merkle root -> {}", h(new));
+            }
+            WitnessEvent::AccountDataWritten { .. } => {}
+            WitnessEvent::ChunksWritten { pubkey, chunks, .. } => {
+                println!("🧩 Rewrote {} chunk(s) of {} from proofs alone", chunks.len(), pubkey);
+            }
+            WitnessEvent::TxFailed { pubkey, reason } => {
+                println!("❌ Tx failed for pubkey {}: {}", pubkey, reason);
+            }
+            WitnessEvent::FraudProven { pubkey, provider, slashed } => {
+                println!("⚔️  Fraud proven on {}: provider {} slashed {} lamports", pubkey, provider, slashed);
+            }
+            WitnessEvent::EntryAppended { pubkey, index, root, .. } => {
+                println!("📝 Appended entry {} to {}: root -> {}", index, pubkey, h(root));
+            }
+            WitnessEvent::RetrievalFailed { pubkey, provider, slashed } => {
+                println!("📉 Retrieval challenge failed for {}: provider {} slashed {} lamports", pubkey, provider, slashed);
+            }
+        }
+    }
+}
+
+/// Subscriber that records events in memory; clones share the same log
+/// so a handle can be kept after subscribing (indexers, tests)
+#[derive(Debug, Clone, Default)]
+pub struct EventLog {
+    events: Arc<Mutex<Vec<WitnessEvent>>>,
+}
+
+impl EventLog {
+    pub fn new() -> Self { Self::default() }
+
+    /// Snapshot of all events recorded so far
+    pub fn events(&self) -> Vec<WitnessEvent> {
+        self.events.lock().unwrap().clone()
+    }
+}
+
+impl EventSubscriber for EventLog {
+    fn on_event(&mut self, event: &WitnessEvent) {
+        self.events.lock().unwrap().push(event.clone());
+    }
+}
@@ -0,0 +1,223 @@
+use anyhow::{Result, bail};
+
+use crate::error::WitnessError;
+use crate::optimistic::{FraudProof, OptimisticUpdate, CHALLENGE_WINDOW};
+use crate::program::{AccountView, Effects, InvokeContext, Program};
+use crate::{chunk_blob, h, AccountStub, AccountWitness, ChainState, MerkleTree};
+
+pub const GAME_PROGRAM_ID: &str = "Arena1";
+
+/// Side of the square board; positions wrap around
+pub const BOARD_SIZE: u16 = 32;
+
+/// Per player: x (u16 LE), y (u16 LE), score (u32 LE)
+pub const PLAYER_SIZE: usize = 8;
+
+/// Moves played off-chain between two committed roots
+pub const CHECKPOINT_EVERY: usize = 8;
+
+/// One step of one player; stepping onto a gem cell scores a point
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Move {
+    pub player: u8,
+    pub dx: i8,
+    pub dy: i8,
+}
+
+pub fn encode_moves(moves: &[Move]) -> Vec<u8> {
+    moves.iter().flat_map(|m| [m.player, m.dx as u8, m.dy as u8]).collect()
+}
+
+pub fn decode_moves(data: &[u8]) -> Result<Vec<Move>, WitnessError> {
+    if !data.len().is_multiple_of(3) {
+        return Err(WitnessError::Rejected(format!("malformed move batch ({} bytes)", data.len())));
+    }
+    Ok(data.chunks(3).map(|m| Move { player: m[0], dx: m[1] as i8, dy: m[2] as i8 }).collect())
+}
+
+fn is_gem(x: u16, y: u16) -> bool {
+    (x as u32 + 3 * y as u32).is_multiple_of(5)
+}
+
+/// Game state after `moves`; the rules both the operator and every challenger run
+pub fn apply_moves(state: &[u8], moves: &[Move]) -> Result<Vec<u8>, WitnessError> {
+    if state.is_empty() || !state.len().is_multiple_of(PLAYER_SIZE) {
+        return Err(WitnessError::Rejected(format!("not a game state ({} bytes)", state.len())));
+    }
+    let mut state = state.to_vec();
+    for m in moves {
+        let start = m.player as usize * PLAYER_SIZE;
+        let record = match state.get_mut(start..start + PLAYER_SIZE) {
+            Some(r) => r,
+            None => return Err(WitnessError::Rejected(format!("no player {}", m.player))),
+        };
+        if m.dx.abs() > 1 || m.dy.abs() > 1 {
+            return Err(WitnessError::Rejected(format!("player {} tried to move more than one cell", m.player)));
+        }
+        let step = |pos: u16, d: i8| (pos as i32 + d as i32).rem_euclid(BOARD_SIZE as i32) as u16;
+        let x = step(u16::from_le_bytes([record[0], record[1]]), m.dx);
+        let y = step(u16::from_le_bytes([record[2], record[3]]), m.dy);
+        record[..2].copy_from_slice(&x.to_le_bytes());
+        record[2..4].copy_from_slice(&y.to_le_bytes());
+        if is_gem(x, y) {
+            let score = u32::from_le_bytes(record[4..8].try_into().unwrap()) + 1;
+            record[4..8].copy_from_slice(&score.to_le_bytes());
+        }
+    }
+    Ok(state)
+}
+
+/// Per-player scores in a game state
+pub fn scores(state: &[u8]) -> Vec<u32> {
+    state.chunks(PLAYER_SIZE).map(|r| u32::from_le_bytes(r[4..8].try_into().unwrap())).collect()
+}
+
+/// On-chain rules of the game. Only ever executed when a checkpoint is challenged;
+/// honest checkpoints are accepted on the operator's stake alone.
+pub struct GameProgram;
+
+impl Program for GameProgram {
+    fn process(&self, _ctx: &mut InvokeContext, accounts: &[AccountView], instruction_data: &[u8], _witnesses: &[AccountWitness]) -> Result<Effects, WitnessError> {
+        let game = match accounts {
+            [a] => a,
+            _ => return Err(WitnessError::Rejected("game instructions take exactly the game account".to_string())),
+        };
+        let state = apply_moves(game.data, &decode_moves(instruction_data)?)?;
+        let mut effects = Effects::default();
+        effects.write(game.pubkey, state);
+        Ok(effects)
+    }
+}
+
+/// A committed checkpoint as published by the operator: the moves it covers and
+/// the states on both sides, so anyone can replay it
+#[derive(Debug, Clone)]
+pub struct Checkpoint {
+    pub update_id: u64,
+    pub moves: Vec<Move>,
+    pub old_state: Vec<u8>,
+    pub new_state: Vec<u8>,
+}
+
+/// Operator side: keeps the hot state off-chain and posts a root every
+/// `CHECKPOINT_EVERY` moves as an optimistic update backed by its stake
+pub struct GameSession {
+    pub pubkey: String,
+    pub operator: String,
+    pub chunk_size: usize,
+    pub state: Vec<u8>,
+    pending: Vec<Move>,
+    committed: Vec<u8>,
+    pub checkpoints: Vec<Checkpoint>,
+}
+
+impl GameSession {
+    /// Start a game with `players` at the origin and put its stub on chain
+    pub fn start(chain: &mut ChainState, pubkey: &str, operator: &str, players: u8, chunk_size: usize) -> Self {
+        let state = vec![0u8; players as usize * PLAYER_SIZE];
+        let root = MerkleTree::from_chunks(&chunk_blob(&state, chunk_size)).root();
+        chain.put_stub(pubkey, AccountStub::new(GAME_PROGRAM_ID, 0, root, state.len() as u64, chunk_size));
+        Self {
+            pubkey: pubkey.to_string(),
+            operator: operator.to_string(),
+            chunk_size,
+            committed: state.clone(),
+            state,
+            pending: Vec::new(),
+            checkpoints: Vec::new(),
+        }
+    }
+
+    /// Apply `mv` off-chain; every `CHECKPOINT_EVERY`th move commits a checkpoint
+    pub fn play(&mut self, chain: &mut ChainState, mv: Move) -> Result<Option<&Checkpoint>, WitnessError> {
+        self.state = apply_moves(&self.state, &[mv])?;
+        self.pending.push(mv);
+        if self.pending.len() < CHECKPOINT_EVERY {
+            return Ok(None);
+        }
+        self.checkpoint(chain).map(Some)
+    }
+
+    /// Post the root of the current state, covering every move since the last checkpoint
+    pub fn checkpoint(&mut self, chain: &mut ChainState) -> Result<&Checkpoint, WitnessError> {
+        let chunk_root = |state: &[u8]| MerkleTree::from_chunks(&chunk_blob(state, self.chunk_size)).root();
+        let moves = std::mem::take(&mut self.pending);
+        let (update_id, _) = chain.submit_optimistic(OptimisticUpdate {
+            pubkey: self.pubkey.clone(),
+            provider: self.operator.clone(),
+            program_id: GAME_PROGRAM_ID.to_string(),
+            instruction_data: encode_moves(&moves),
+            old_root: chunk_root(&self.committed),
+            new_root: chunk_root(&self.state),
+            new_data_len: self.state.len() as u64,
+            submitted_slot: 0,
+        })?;
+        let old_state = std::mem::replace(&mut self.committed, self.state.clone());
+        self.checkpoints.push(Checkpoint { update_id, moves, old_state, new_state: self.state.clone() });
+        Ok(self.checkpoints.last().unwrap())
+    }
+}
+
+/// Player side: replay a published checkpoint and, if its state differs from the
+/// honest result, build a fraud proof over the first wrong chunk
+pub fn audit(checkpoint: &Checkpoint, chunk_size: usize) -> Result<Option<FraudProof>, WitnessError> {
+    let honest_state = apply_moves(&checkpoint.old_state, &checkpoint.moves)?;
+    let honest = chunk_blob(&honest_state, chunk_size);
+    let claimed = chunk_blob(&checkpoint.new_state, chunk_size);
+    let leaf_index = match (0..claimed.len()).find(|&i| honest.get(i) != Some(&claimed[i])) {
+        Some(i) => i,
+        None => return Ok(None),
+    };
+    Ok(Some(FraudProof {
+        old_blob: checkpoint.old_state.clone(),
+        leaf_index,
+        claimed_chunk: claimed[leaf_index].to_vec(),
+        claimed_proof: MerkleTree::from_chunks(&claimed).gen_proof(leaf_index),
+    }))
+}
+
+/// Eight players, three checkpoints; the operator pads a score in the last one and
+/// a player catches it within the challenge window
+pub fn demo() -> Result<()> {
+    let mut chain = ChainState::new();
+    chain.register_program(GAME_PROGRAM_ID, Box::new(GameProgram));
+    chain.stake("operator_1", 10_000);
+    let mut game = GameSession::start(&mut chain, "Game1", "operator_1", 8, 16);
+
+    for turn in 0..2 * CHECKPOINT_EVERY {
+        let mv = Move { player: (turn % 4) as u8, dx: 1, dy: (turn % 3) as i8 - 1 };
+        if let Some(cp) = game.play(&mut chain, mv)? {
+            println!("Checkpoint {} after {} moves: root {}", cp.update_id, cp.moves.len(), h(&chain.get_stub("Game1").unwrap().merkle_root));
+        }
+        chain.advance_slot();
+    }
+    for _ in 0..CHECKPOINT_EVERY - 1 {
+        game.play(&mut chain, Move { player: 3, dx: 0, dy: 1 })?;
+    }
+    // the operator quietly gives player 3 a hundred points before committing
+    let start = 3 * PLAYER_SIZE + 4;
+    let padded = scores(&game.state)[3] + 100;
+    game.state[start..start + 4].copy_from_slice(&padded.to_le_bytes());
+    let cheat = game.play(&mut chain, Move { player: 3, dx: 0, dy: 1 })?.unwrap().clone();
+    println!("Checkpoint {} claims scores {:?}", cheat.update_id, scores(&cheat.new_state));
+
+    for cp in &game.checkpoints {
+        match audit(cp, game.chunk_size)? {
+            Some(fraud) => {
+                let slashed = chain.challenge(cp.update_id, "player_5", &fraud)?;
+                println!("Checkpoint {} disproven at chunk {}: operator slashed {}, root reverted to {}",
+                    cp.update_id, fraud.leaf_index, slashed, h(&chain.get_stub("Game1").unwrap().merkle_root));
+            }
+            None => println!("Checkpoint {} replays honestly", cp.update_id),
+        }
+    }
+    for _ in 0..=CHALLENGE_WINDOW {
+        chain.advance_slot();
+    }
+    let honest = game.checkpoints[game.checkpoints.len() - 2].new_state.clone();
+    if chain.get_stub("Game1").unwrap().merkle_root != MerkleTree::from_chunks(&chunk_blob(&honest, game.chunk_size)).root() {
+        bail!("game did not revert to the last honest checkpoint");
+    }
+    println!("Final committed scores {:?}", scores(&honest));
+    Ok(())
+}
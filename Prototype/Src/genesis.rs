@@ -0,0 +1,153 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result, bail};
+use serde::Deserialize;
+
+use crate::blob_file::BlobFile;
+use crate::rent::HotAccount;
+use crate::{chunk_blob, AccountStub, ChainState, MerkleTree};
+
+/// Initial chain state, loaded from TOML or JSON.
+///
+/// ```toml
+/// compute_budget = 200000
+///
+/// [[accounts]]
+/// pubkey = "Acct1"
+/// owner = "WitnessProgram1"
+/// lamports = 1000
+/// chunk_size = 32
+/// blob_file = "blobs/acct1.bin"   # relative to the config file
+/// ```
+#[derive(Debug, Clone, Deserialize)]
+pub struct GenesisConfig {
+    #[serde(default)]
+    pub compute_budget: Option<u64>,
+    #[serde(default)]
+    pub accounts: Vec<GenesisAccount>,
+    /// Directory `blob_file` paths are resolved against
+    #[serde(skip)]
+    pub base_dir: PathBuf,
+}
+
+/// One initial account. Exactly one of `blob_file`, `blob_hex`, `blob_utf8` gives its data;
+/// with none the account starts empty. A `blob_file` written by `commit --container` must
+/// match the account's chunk size and gives its data decompressed.
+#[derive(Debug, Clone, Deserialize)]
+pub struct GenesisAccount {
+    pub pubkey: String,
+    pub owner: String,
+    #[serde(default)]
+    pub lamports: u64,
+    pub chunk_size: usize,
+    #[serde(default)]
+    pub executable: bool,
+    #[serde(default)]
+    pub rent_epoch: u64,
+    /// Keep the full data on-chain instead of committing it to a stub
+    #[serde(default)]
+    pub hot: bool,
+    pub blob_file: Option<PathBuf>,
+    pub blob_hex: Option<String>,
+    pub blob_utf8: Option<String>,
+}
+
+impl GenesisAccount {
+    pub fn load_blob(&self, base_dir: &Path) -> Result<Vec<u8>> {
+        match (&self.blob_file, &self.blob_hex, &self.blob_utf8) {
+            (Some(path), None, None) => {
+                let path = base_dir.join(path);
+                let bytes = std::fs::read(&path).with_context(|| format!("reading blob for {} from {}", self.pubkey, path.display()))?;
+                if !BlobFile::sniff(&bytes) {
+                    return Ok(bytes);
+                }
+                let committed = BlobFile::from_bytes(&bytes).with_context(|| format!("reading {}", path.display()))?;
+                if committed.chunk_size != self.chunk_size {
+                    bail!("{} was committed with {}-byte chunks, account {} uses {}", path.display(), committed.chunk_size, self.pubkey, self.chunk_size);
+                }
+                Ok(committed.raw()?)
+            }
+            (None, Some(hex_str), None) => hex::decode(hex_str).with_context(|| format!("decoding blob_hex for {}", self.pubkey)),
+            (None, None, Some(text)) => Ok(text.as_bytes().to_vec()),
+            (None, None, None) => Ok(Vec::new()),
+            _ => bail!("account {} sets more than one of blob_file/blob_hex/blob_utf8", self.pubkey),
+        }
+    }
+}
+
+impl GenesisConfig {
+    /// Load from `path`; `.json` files are parsed as JSON, everything else as TOML
+    pub fn load(path: &Path) -> Result<Self> {
+        let text = std::fs::read_to_string(path).with_context(|| format!("reading genesis {}", path.display()))?;
+        let mut cfg = if path.extension().is_some_and(|e| e == "json") {
+            Self::from_json_str(&text)?
+        } else {
+            Self::from_toml_str(&text)?
+        };
+        cfg.base_dir = path.parent().map(Path::to_path_buf).unwrap_or_default();
+        Ok(cfg)
+    }
+
+    pub fn from_toml_str(text: &str) -> Result<Self> {
+        toml::from_str(text).context("parsing genesis TOML")
+    }
+
+    pub fn from_json_str(text: &str) -> Result<Self> {
+        serde_json::from_str(text).context("parsing genesis JSON")
+    }
+
+    /// Every account with its resolved blob, in config order
+    pub fn blobs(&self) -> Result<Vec<(&GenesisAccount, Vec<u8>)>> {
+        self.accounts.iter().map(|a| Ok((a, a.load_blob(&self.base_dir)?))).collect()
+    }
+}
+
+impl ChainState {
+    /// Build the initial state described by `cfg`: a stub per compressed account
+    /// (root computed from its blob) and a hot account per `hot = true` entry
+    pub fn from_genesis(cfg: &GenesisConfig) -> Result<Self> {
+        let mut chain = ChainState::new();
+        if let Some(budget) = cfg.compute_budget {
+            chain.set_compute_budget(budget);
+        }
+        for (acc, blob) in cfg.blobs()? {
+            if chain.get_stub(&acc.pubkey).is_some() || chain.hot_accounts.contains_key(&acc.pubkey) {
+                bail!("duplicate genesis account {}", acc.pubkey);
+            }
+            if acc.chunk_size == 0 {
+                bail!("account {} has chunk_size 0", acc.pubkey);
+            }
+            if acc.hot {
+                chain.put_hot_account(&acc.pubkey, HotAccount { owner: acc.owner.clone(), lamports: acc.lamports, data: blob });
+                continue;
+            }
+            let tree = MerkleTree::from_chunks(&chunk_blob(&blob, acc.chunk_size));
+            chain.leaf_cache.insert(&blob, acc.chunk_size, &tree);
+            let mut stub = AccountStub::new(&acc.owner, acc.lamports, tree.root(), blob.len() as u64, acc.chunk_size);
+            stub.executable = acc.executable;
+            stub.rent_epoch = acc.rent_epoch;
+            chain.put_stub(&acc.pubkey, stub);
+        }
+        Ok(chain)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compression::Codec;
+
+    #[test]
+    fn loads_container_blobs_decompressed() {
+        let dir = std::env::temp_dir().join("genesis-container-test");
+        std::fs::create_dir_all(&dir).unwrap();
+        let raw: Vec<u8> = (0..500u32).map(|i| (i % 5) as u8).collect();
+        BlobFile::commit(&raw, 32, Some(Codec::Zstd)).unwrap().write(&dir.join("acct.sbbl")).unwrap();
+        let cfg = GenesisConfig::from_toml_str("[[accounts]]\npubkey = \"A\"\nowner = \"P\"\nchunk_size = 32\nblob_file = \"acct.sbbl\"\n").unwrap();
+        assert_eq!(cfg.accounts[0].load_blob(&dir).unwrap(), raw);
+        let mut other = cfg.accounts[0].clone();
+        other.chunk_size = 64;
+        assert!(other.load_blob(&dir).unwrap_err().to_string().contains("32-byte chunks"));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
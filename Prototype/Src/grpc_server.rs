@@ -0,0 +1,161 @@
+use std::sync::{Arc, Mutex};
+
+use anyhow::{Context, Result, bail};
+use tonic::{Request, Response, Status};
+
+use crate::archive::ArchiveNode;
+use crate::indexer::Indexer;
+use crate::{AccountStub, AccountWitness, ChainState, WitnessTx};
+
+/// Types and service stubs generated from `proto/witness.proto`
+pub mod proto {
+    tonic::include_proto!("witness.v1");
+}
+
+use proto::witness_server::{Witness, WitnessServer};
+
+fn steps(proof: &[([u8;32], bool)]) -> Vec<proto::ProofStep> {
+    proof.iter().map(|(sibling, is_left)| proto::ProofStep { sibling: sibling.to_vec(), is_left: *is_left }).collect()
+}
+
+fn bytes32(b: &[u8], what: &str) -> Result<[u8;32]> {
+    match b.try_into() {
+        Ok(b) => Ok(b),
+        Err(_) => bail!("{} must be 32 bytes", what),
+    }
+}
+
+impl From<&AccountStub> for proto::Stub {
+    fn from(s: &AccountStub) -> Self {
+        proto::Stub {
+            owner: s.owner.clone(),
+            lamports: s.lamports,
+            merkle_root: s.merkle_root.to_vec(),
+            executable: s.executable,
+            data_len: s.data_len,
+            rent_epoch: s.rent_epoch,
+            chunk_size: s.chunk_size as u64,
+            hash_algo: s.hash_algo.id() as u32,
+            arity: s.arity as u32,
+            append_only: s.append_only,
+        }
+    }
+}
+
+impl From<&AccountWitness> for proto::AccountWitness {
+    fn from(w: &AccountWitness) -> Self {
+        proto::AccountWitness {
+            pubkey: w.pubkey.clone(),
+            blob: w.blob.clone(),
+            leaf_index: w.leaf_index as u64,
+            proof: steps(&w.proof),
+            is_writable: w.is_writable,
+        }
+    }
+}
+
+impl TryFrom<proto::AccountWitness> for AccountWitness {
+    type Error = anyhow::Error;
+
+    fn try_from(w: proto::AccountWitness) -> Result<Self> {
+        let proof = w.proof.iter().map(|s| Ok((bytes32(&s.sibling, "proof sibling")?, s.is_left))).collect::<Result<_>>()?;
+        Ok(AccountWitness { pubkey: w.pubkey, blob: w.blob, leaf_index: w.leaf_index as usize, proof, is_writable: w.is_writable })
+    }
+}
+
+impl TryFrom<proto::WitnessTransaction> for WitnessTx {
+    type Error = anyhow::Error;
+
+    fn try_from(tx: proto::WitnessTransaction) -> Result<Self> {
+        let signatures = tx.signatures.iter().map(|s| {
+            let sig: [u8;64] = match s.signature.as_slice().try_into() {
+                Ok(sig) => sig,
+                Err(_) => bail!("signature must be 64 bytes"),
+            };
+            Ok((bytes32(&s.signer, "signer")?, sig))
+        }).collect::<Result<_>>()?;
+        Ok(WitnessTx {
+            program_id: tx.program_id,
+            instruction_data: tx.instruction_data,
+            witnesses: tx.witnesses.into_iter().map(AccountWitness::try_from).collect::<Result<_>>()?,
+            recent_blockhash: bytes32(&tx.recent_blockhash, "recent_blockhash")?,
+            priority_fee: tx.priority_fee,
+            signatures,
+        })
+    }
+}
+
+/// `witness.v1.Witness` over a chain, its archive and index. The archive and
+/// indexer should be subscribed to `chain` so witnesses follow sent transactions.
+#[derive(Clone)]
+pub struct GrpcService {
+    chain: Arc<Mutex<ChainState>>,
+    archive: ArchiveNode,
+    indexer: Indexer,
+}
+
+impl GrpcService {
+    pub(crate) fn new(chain: ChainState, archive: ArchiveNode, indexer: Indexer) -> Self {
+        Self { chain: Arc::new(Mutex::new(chain)), archive, indexer }
+    }
+
+    /// Serve on `addr` (e.g. "127.0.0.1:50051") until the process exits
+    pub fn serve(self, addr: &str) -> Result<()> {
+        let addr = addr.parse().with_context(|| format!("gRPC address {}", addr))?;
+        let runtime = tokio::runtime::Builder::new_current_thread().enable_all().build()?;
+        runtime.block_on(async {
+            tonic::transport::Server::builder().add_service(WitnessServer::new(self)).serve(addr).await.context("gRPC server")
+        })
+    }
+
+    fn current_root(&self, pubkey: &str) -> Option<[u8;32]> {
+        self.chain.lock().unwrap().get_stub(pubkey).map(|s| s.merkle_root)
+    }
+}
+
+#[tonic::async_trait]
+impl Witness for GrpcService {
+    async fn get_stub(&self, request: Request<proto::GetStubRequest>) -> Result<Response<proto::GetStubResponse>, Status> {
+        let chain = self.chain.lock().unwrap();
+        let stub = chain.get_stub(&request.get_ref().pubkey).map(proto::Stub::from);
+        Ok(Response::new(proto::GetStubResponse { slot: chain.slot, stub }))
+    }
+
+    async fn get_proof(&self, request: Request<proto::GetProofRequest>) -> Result<Response<proto::Proof>, Status> {
+        let req = request.into_inner();
+        let root = self.current_root(&req.pubkey).ok_or_else(|| Status::not_found(format!("unknown account {}", req.pubkey)))?;
+        let leaf = req.leaf_index as usize;
+        let w = match self.indexer.proof(&req.pubkey, leaf) {
+            Some(w) => w,
+            None => self.archive.get_chunk_witness(&req.pubkey, &root, leaf)
+                .ok_or_else(|| Status::not_found(format!("no chunk {} held for {} at its current root", leaf, req.pubkey)))?,
+        };
+        Ok(Response::new(proto::Proof { pubkey: req.pubkey, root: root.to_vec(), leaf_index: req.leaf_index, chunk: w.chunk, steps: steps(&w.proof) }))
+    }
+
+    async fn get_witness(&self, request: Request<proto::GetProofRequest>) -> Result<Response<proto::AccountWitness>, Status> {
+        let req = request.into_inner();
+        let root = self.current_root(&req.pubkey).ok_or_else(|| Status::not_found(format!("unknown account {}", req.pubkey)))?;
+        let w = self.archive.get_witness(&req.pubkey, &root, req.leaf_index as usize)
+            .ok_or_else(|| Status::not_found(format!("no witness for leaf {} of {}", req.leaf_index, req.pubkey)))?;
+        Ok(Response::new(proto::AccountWitness::from(&w)))
+    }
+
+    async fn send_transaction(&self, request: Request<proto::WitnessTransaction>) -> Result<Response<proto::SendTransactionResponse>, Status> {
+        let tx = WitnessTx::try_from(request.into_inner()).map_err(|e| Status::invalid_argument(format!("{:#}", e)))?;
+        let mut chain = self.chain.lock().unwrap();
+        match chain.process_tx_witness(&tx) {
+            Ok(_) => Ok(Response::new(proto::SendTransactionResponse { id: tx.id().to_vec() })),
+            Err(e) => Err(Status::failed_precondition(format!("transaction failed: {:#}", e))),
+        }
+    }
+
+    async fn get_state_root(&self, _request: Request<proto::GetStateRootRequest>) -> Result<Response<proto::GetStateRootResponse>, Status> {
+        let chain = self.chain.lock().unwrap();
+        Ok(Response::new(proto::GetStateRootResponse {
+            slot: chain.slot,
+            state_root: chain.state_root().to_vec(),
+            latest_blockhash: chain.latest_blockhash().to_vec(),
+        }))
+    }
+}
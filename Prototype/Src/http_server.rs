@@ -0,0 +1,239 @@
+use std::sync::{Arc, Mutex};
+
+use anyhow::{Context, Result};
+use axum::extract::ws::{Message, WebSocket, WebSocketUpgrade};
+use axum::extract::{Path, State};
+use axum::http::{header, StatusCode};
+use axum::response::{IntoResponse, Response};
+use axum::routing::{get, post};
+use axum::{Json, Router};
+use serde::Serialize;
+use serde_json::{json, Value};
+use tokio::sync::mpsc;
+
+use crate::archive::ArchiveNode;
+use crate::indexer::Indexer;
+use crate::metrics::metrics;
+use crate::pubsub::{PubSub, RootNotification, SubscriptionFilter};
+use crate::rpc::RpcServer;
+use crate::view::ChunkWitness;
+
+type ApiError = (StatusCode, String);
+
+fn not_found(what: String) -> ApiError {
+    (StatusCode::NOT_FOUND, what)
+}
+
+#[derive(Debug, Serialize)]
+pub struct RootView {
+    pub pubkey: String,
+    pub root: String,
+    pub slot: u64,
+    pub data_len: u64,
+    pub chunk_size: usize,
+    pub chunk_count: usize,
+    pub locations: Vec<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ProofStep {
+    pub sibling: String,
+    pub is_left: bool,
+}
+
+/// `ChunkWitness` with hex fields, plus the root it proves against
+#[derive(Debug, Serialize)]
+pub struct ProofView {
+    pub pubkey: String,
+    pub root: String,
+    pub leaf_index: usize,
+    pub chunk: String,
+    pub proof: Vec<ProofStep>,
+}
+
+/// Read-only proof endpoints for clients assembling witness transactions:
+/// `GET /root/{pubkey}`, `GET /proof/{pubkey}/{leaf}` (JSON) and
+/// `GET /chunk/{pubkey}/{leaf}` (raw bytes). Current roots come from the indexer;
+/// proofs come from its tree cache, warmed from the archive's blob store on a miss.
+/// With `with_rpc`, JSON-RPC requests are also accepted as `POST /`; with
+/// `with_pubsub`, `GET /ws` upgrades to a WebSocket taking `rootSubscribe [pubkey]`,
+/// `programSubscribe [owner]` and `rootUnsubscribe [id]` requests and pushing a
+/// `rootNotification` for every matching root change. `GET /metrics` is the
+/// Prometheus scrape endpoint.
+#[derive(Clone)]
+pub struct ProofService {
+    indexer: Indexer,
+    archive: ArchiveNode,
+    rpc: Option<Arc<Mutex<RpcServer>>>,
+    pubsub: Option<PubSub>,
+}
+
+impl ProofService {
+    pub fn new(indexer: Indexer, archive: ArchiveNode) -> Self {
+        Self { indexer, archive, rpc: None, pubsub: None }
+    }
+
+    pub fn with_rpc(mut self, rpc: RpcServer) -> Self {
+        self.rpc = Some(Arc::new(Mutex::new(rpc)));
+        self
+    }
+
+    pub fn with_pubsub(mut self, pubsub: PubSub) -> Self {
+        self.pubsub = Some(pubsub);
+        self
+    }
+
+    pub fn router(self) -> Router {
+        Router::new()
+            .route("/", post(post_rpc))
+            .route("/ws", get(get_ws))
+            .route("/root/{pubkey}", get(get_root))
+            .route("/proof/{pubkey}/{leaf}", get(get_proof))
+            .route("/chunk/{pubkey}/{leaf}", get(get_chunk))
+            .route("/metrics", get(get_metrics))
+            .with_state(self)
+    }
+
+    /// Serve on `addr` (e.g. "127.0.0.1:8899") until the process exits
+    pub fn serve(self, addr: &str) -> Result<()> {
+        let runtime = tokio::runtime::Builder::new_current_thread().enable_all().build()?;
+        runtime.block_on(async {
+            let listener = tokio::net::TcpListener::bind(addr).await.with_context(|| format!("binding {}", addr))?;
+            axum::serve(listener, self.router()).await.context("proof server")
+        })
+    }
+
+    fn witness(&self, pubkey: &str, leaf_index: usize) -> Result<([u8;32], ChunkWitness), ApiError> {
+        let entry = self.indexer.get(pubkey).ok_or_else(|| not_found(format!("unknown account {}", pubkey)))?;
+        if let Some(w) = self.indexer.proof(pubkey, leaf_index) {
+            return Ok((entry.root, w));
+        }
+        let blob = self.archive.get_blob(pubkey, &entry.root)
+            .ok_or_else(|| not_found(format!("no blob held for {} at its current root", pubkey)))?;
+        self.indexer.cache_blob(pubkey, blob.chunk_size, &blob.data);
+        let w = self.indexer.proof(pubkey, leaf_index)
+            .ok_or_else(|| not_found(format!("{} has {} chunks", pubkey, entry.chunk_count)))?;
+        Ok((entry.root, w))
+    }
+}
+
+async fn get_root(State(svc): State<ProofService>, Path(pubkey): Path<String>) -> Result<Json<RootView>, ApiError> {
+    let e = svc.indexer.get(&pubkey).ok_or_else(|| not_found(format!("unknown account {}", pubkey)))?;
+    Ok(Json(RootView {
+        pubkey,
+        root: hex::encode(e.root),
+        slot: e.slot,
+        data_len: e.data_len,
+        chunk_size: e.chunk_size,
+        chunk_count: e.chunk_count,
+        locations: e.locations,
+    }))
+}
+
+async fn get_proof(State(svc): State<ProofService>, Path((pubkey, leaf)): Path<(String, usize)>) -> Result<Json<ProofView>, ApiError> {
+    let (root, w) = svc.witness(&pubkey, leaf)?;
+    Ok(Json(ProofView {
+        pubkey,
+        root: hex::encode(root),
+        leaf_index: w.leaf_index,
+        chunk: hex::encode(&w.chunk),
+        proof: w.proof.iter().map(|(sibling, is_left)| ProofStep { sibling: hex::encode(sibling), is_left: *is_left }).collect(),
+    }))
+}
+
+async fn get_chunk(State(svc): State<ProofService>, Path((pubkey, leaf)): Path<(String, usize)>) -> Result<impl IntoResponse, ApiError> {
+    let (_, w) = svc.witness(&pubkey, leaf)?;
+    Ok(([(header::CONTENT_TYPE, "application/octet-stream")], w.chunk))
+}
+
+async fn get_metrics(State(svc): State<ProofService>) -> impl IntoResponse {
+    let text = match &svc.rpc {
+        Some(rpc) => metrics().encode(Some(rpc.lock().unwrap().chain())),
+        None => metrics().encode(None),
+    };
+    ([(header::CONTENT_TYPE, "text/plain; version=0.0.4")], text)
+}
+
+async fn post_rpc(State(svc): State<ProofService>, body: String) -> Result<Response, ApiError> {
+    let rpc = svc.rpc.ok_or_else(|| not_found("JSON-RPC is not enabled on this server".to_string()))?;
+    let reply = rpc.lock().unwrap().handle(&body);
+    Ok(match reply {
+        Some(json) => ([(header::CONTENT_TYPE, "application/json")], json).into_response(),
+        // only notifications: nothing to say
+        None => StatusCode::NO_CONTENT.into_response(),
+    })
+}
+
+async fn get_ws(State(svc): State<ProofService>, ws: WebSocketUpgrade) -> Result<Response, ApiError> {
+    let pubsub = svc.pubsub.ok_or_else(|| not_found("subscriptions are not enabled on this server".to_string()))?;
+    Ok(ws.on_upgrade(move |socket| subscription_session(socket, pubsub)))
+}
+
+fn notification_json(subscription: u64, n: &RootNotification) -> Value {
+    json!({
+        "jsonrpc": "2.0",
+        "method": "rootNotification",
+        "params": {
+            "subscription": subscription,
+            "result": {
+                "context": { "slot": n.slot },
+                "value": {
+                    "pubkey": n.pubkey,
+                    "owner": n.owner,
+                    "oldRoot": hex::encode(n.old_root),
+                    "root": hex::encode(n.root),
+                    "changedLeaves": n.changed_leaves,
+                },
+            },
+        },
+    })
+}
+
+/// Reply to one subscription request from a WebSocket client
+fn subscription_request(pubsub: &PubSub, ids: &mut Vec<u64>, tx: &mpsc::UnboundedSender<Value>, request: &Value) -> Value {
+    let id = request.get("id").cloned().unwrap_or(Value::Null);
+    let param = request["params"].get(0).cloned().unwrap_or(Value::Null);
+    let filter = match (request["method"].as_str(), param.as_str()) {
+        (Some("rootSubscribe"), Some(pubkey)) => Some(SubscriptionFilter::Account(pubkey.to_string())),
+        (Some("programSubscribe"), Some(owner)) => Some(SubscriptionFilter::Owner(owner.to_string())),
+        _ => None,
+    };
+    if let Some(filter) = filter {
+        let tx = tx.clone();
+        let sub = pubsub.subscribe(filter, Box::new(move |sub, n| tx.send(notification_json(sub, n)).is_ok()));
+        ids.push(sub);
+        return json!({ "jsonrpc": "2.0", "result": sub, "id": id });
+    }
+    match (request["method"].as_str(), param.as_u64()) {
+        (Some("rootUnsubscribe"), Some(sub)) if ids.contains(&sub) => {
+            ids.retain(|i| *i != sub);
+            json!({ "jsonrpc": "2.0", "result": pubsub.unsubscribe(sub), "id": id })
+        }
+        (Some("rootUnsubscribe"), _) => json!({ "jsonrpc": "2.0", "error": { "code": -32602, "message": "unknown subscription" }, "id": id }),
+        _ => json!({ "jsonrpc": "2.0", "error": { "code": -32601, "message": "expected rootSubscribe, programSubscribe or rootUnsubscribe" }, "id": id }),
+    }
+}
+
+async fn subscription_session(mut socket: WebSocket, pubsub: PubSub) {
+    let (tx, mut rx) = mpsc::unbounded_channel();
+    let mut ids = Vec::new();
+    loop {
+        let outgoing = tokio::select! {
+            incoming = socket.recv() => match incoming {
+                Some(Ok(Message::Text(text))) => match serde_json::from_str::<Value>(&text) {
+                    Ok(request) => subscription_request(&pubsub, &mut ids, &tx, &request),
+                    Err(e) => json!({ "jsonrpc": "2.0", "error": { "code": -32700, "message": e.to_string() }, "id": null }),
+                },
+                Some(Ok(_)) => continue,
+                _ => break,
+            },
+            Some(notification) = rx.recv() => notification,
+        };
+        if socket.send(Message::Text(outgoing.to_string().into())).await.is_err() {
+            break;
+        }
+    }
+    for id in ids {
+        pubsub.unsubscribe(id);
+    }
+}
@@ -0,0 +1,319 @@
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, Mutex};
+
+use anyhow::{Result, bail};
+
+use crate::archive::ArchiveNode;
+use crate::events::{EventSubscriber, WitnessEvent};
+use crate::metrics::metrics;
+use crate::pruned::{PrunedTree, TreeMode, DEFAULT_CACHED_NODES};
+use crate::view::ChunkWitness;
+use crate::{chunk_blob, h, ChainState, MerkleTree};
+
+/// What the index knows about one offloaded account
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct IndexEntry {
+    pub root: [u8;32],
+    /// Slot of the last root update seen (0 if only learnt from a chain sync)
+    pub slot: u64,
+    /// Empty until a `sync` with the chain; events don't carry the owner
+    pub owner: String,
+    pub data_len: u64,
+    pub chunk_size: usize,
+    pub chunk_count: usize,
+    /// Where the blob for `root` can be fetched: the index's own locations plus
+    /// storage providers attesting to it
+    pub locations: Vec<String>,
+}
+
+/// A served tree, held the way the index's `TreeMode` says
+#[derive(Debug)]
+enum ProofTree {
+    Full(MerkleTree),
+    Pruned(PrunedTree),
+}
+
+impl ProofTree {
+    fn build(mode: TreeMode, chunks: &[Vec<u8>]) -> Self {
+        let tree = MerkleTree::from_chunks(chunks);
+        match mode {
+            TreeMode::Full => ProofTree::Full(tree),
+            TreeMode::Pruned { cached_nodes } => ProofTree::Pruned(PrunedTree::prune(&tree, cached_nodes)),
+        }
+    }
+
+    fn root(&self) -> [u8;32] {
+        match self {
+            ProofTree::Full(tree) => tree.root(),
+            ProofTree::Pruned(tree) => tree.root(),
+        }
+    }
+
+    fn gen_proof(&mut self, leaf_index: usize) -> Vec<([u8;32], bool)> {
+        match self {
+            ProofTree::Full(tree) => tree.gen_proof(leaf_index),
+            ProofTree::Pruned(tree) => tree.gen_proof(leaf_index),
+        }
+    }
+
+    fn memory_usage(&self) -> usize {
+        match self {
+            ProofTree::Full(tree) => tree.memory_usage(),
+            ProofTree::Pruned(tree) => tree.memory_usage(),
+        }
+    }
+}
+
+/// Tree of the current blob, kept so proofs can be served without refetching it
+#[derive(Debug)]
+struct ProofCache {
+    chunks: Vec<Vec<u8>>,
+    tree: ProofTree,
+}
+
+#[derive(Debug, Default)]
+struct IndexerInner {
+    entries: BTreeMap<String, IndexEntry>,
+    proofs: HashMap<String, ProofCache>,
+    tree_mode: TreeMode,
+    /// Stores every indexed blob is written to (e.g. an archive's directory)
+    locations: Vec<String>,
+}
+
+/// Follows root updates and keeps a queryable map of offloaded accounts: current
+/// root, where the data lives, its shape and owner, plus a proof cache for the
+/// current version. Clones share one index, so one handle can subscribe while
+/// another answers lookups.
+#[derive(Debug, Clone, Default)]
+pub struct Indexer {
+    inner: Arc<Mutex<IndexerInner>>,
+}
+
+impl Indexer {
+    pub fn new() -> Self { Self::default() }
+
+    /// Record that blobs seen by this index are retrievable from `location`
+    pub fn with_location(self, location: &str) -> Self {
+        self.inner.lock().unwrap().locations.push(location.to_string());
+        self
+    }
+
+    /// Hold proof trees this way from now on; trees already cached keep their mode
+    pub fn with_tree_mode(self, mode: TreeMode) -> Self {
+        self.inner.lock().unwrap().tree_mode = mode;
+        self
+    }
+
+    /// Bytes held by the cached proof trees, not counting their chunks
+    pub fn tree_memory(&self) -> usize {
+        self.inner.lock().unwrap().proofs.values().map(|cache| cache.tree.memory_usage()).sum()
+    }
+
+    /// Fill in owner, shape and provider locations from the chain's committed stubs
+    pub(crate) fn sync(&self, chain: &ChainState) {
+        let mut inner = self.inner.lock().unwrap();
+        let locations = inner.locations.clone();
+        for (pubkey, stub) in &chain.stubs {
+            let entry = inner.entries.entry(pubkey.clone()).or_default();
+            entry.root = stub.merkle_root;
+            entry.owner = stub.owner.clone();
+            entry.data_len = stub.data_len;
+            entry.chunk_size = stub.chunk_size;
+            // an empty blob is still one chunk; a zero chunk size is no tree at all
+            entry.chunk_count = if stub.chunk_size == 0 { 0 } else { stub.chunk_count() };
+            entry.locations = locations.clone();
+            entry.locations.extend(chain.storage.providers_for(pubkey, &stub.merkle_root).into_iter().map(|p| format!("provider:{}", p)));
+        }
+        // cached trees for roots the chain has moved past are useless
+        let entries = &inner.entries;
+        let stale: Vec<String> = inner.proofs.iter()
+            .filter(|(pubkey, cache)| entries.get(*pubkey).is_none_or(|e| e.root != cache.tree.root()))
+            .map(|(pubkey, _)| pubkey.clone())
+            .collect();
+        for pubkey in stale {
+            inner.proofs.remove(&pubkey);
+        }
+    }
+
+    pub fn get(&self, pubkey: &str) -> Option<IndexEntry> {
+        self.inner.lock().unwrap().entries.get(pubkey).cloned()
+    }
+
+    pub fn root(&self, pubkey: &str) -> Option<[u8;32]> {
+        self.get(pubkey).map(|e| e.root)
+    }
+
+    pub fn locate(&self, pubkey: &str) -> Vec<String> {
+        self.get(pubkey).map_or(Vec::new(), |e| e.locations)
+    }
+
+    /// Indexed accounts owned by `owner`, in pubkey order
+    pub fn by_owner(&self, owner: &str) -> Vec<String> {
+        let inner = self.inner.lock().unwrap();
+        inner.entries.iter().filter(|(_, e)| e.owner == owner).map(|(pubkey, _)| pubkey.clone()).collect()
+    }
+
+    pub fn len(&self) -> usize {
+        self.inner.lock().unwrap().entries.len()
+    }
+
+    /// Cache the tree of `data` for proofs, if it is the account's current version
+    pub fn cache_blob(&self, pubkey: &str, chunk_size: usize, data: &[u8]) -> bool {
+        let mut inner = self.inner.lock().unwrap();
+        let chunks = chunk_blob(data, chunk_size).to_vecs();
+        let tree = ProofTree::build(inner.tree_mode, &chunks);
+        if inner.entries.get(pubkey).is_none_or(|e| e.root != tree.root()) {
+            return false;
+        }
+        inner.proofs.insert(pubkey.to_string(), ProofCache { chunks, tree });
+        true
+    }
+
+    /// Chunk `leaf_index` of the current version and its proof, if the index saw
+    /// that version's data
+    pub fn proof(&self, pubkey: &str, leaf_index: usize) -> Option<ChunkWitness> {
+        let mut inner = self.inner.lock().unwrap();
+        let root = inner.entries.get(pubkey)?.root;
+        let cache = inner.proofs.get_mut(pubkey)?;
+        if root != cache.tree.root() {
+            return None;
+        }
+        let chunk = cache.chunks.get(leaf_index)?.clone();
+        tracing::debug!(pubkey = %pubkey, leaf_index, root = %h(&root), "serving proof from the index");
+        metrics().proofs_served.with_label_values(&["index"]).inc();
+        Some(ChunkWitness { pubkey: pubkey.to_string(), leaf_index, chunk, proof: cache.tree.gen_proof(leaf_index) })
+    }
+}
+
+impl EventSubscriber for Indexer {
+    fn on_event(&mut self, event: &WitnessEvent) {
+        let mut inner = self.inner.lock().unwrap();
+        let locations = inner.locations.clone();
+        match event {
+            WitnessEvent::RootUpdated { pubkey, slot, new, .. } => {
+                let entry = inner.entries.entry(pubkey.clone()).or_insert_with(|| IndexEntry { locations, ..IndexEntry::default() });
+                entry.root = *new;
+                entry.slot = *slot;
+            }
+            WitnessEvent::AccountDataWritten { pubkey, slot, root, chunk_size, data } => {
+                let chunks = chunk_blob(data, *chunk_size).to_vecs();
+                let entry = inner.entries.entry(pubkey.clone()).or_insert_with(|| IndexEntry { locations, ..IndexEntry::default() });
+                entry.root = *root;
+                entry.slot = *slot;
+                entry.data_len = data.len() as u64;
+                entry.chunk_size = *chunk_size;
+                entry.chunk_count = chunks.len();
+                let tree = ProofTree::build(inner.tree_mode, &chunks);
+                inner.proofs.insert(pubkey.clone(), ProofCache { chunks, tree });
+            }
+            WitnessEvent::ChunksWritten { pubkey, slot, old_root, root, chunks, .. } => {
+                if let Some(entry) = inner.entries.get_mut(pubkey) {
+                    entry.slot = *slot;
+                }
+                // patch the cached tree if it was the version the chunks were proven against
+                let patched = match inner.proofs.remove(pubkey) {
+                    Some(mut cache) if cache.tree.root() == *old_root => {
+                        for (index, chunk) in chunks {
+                            if let Some(c) = cache.chunks.get_mut(*index) {
+                                *c = chunk.clone();
+                            }
+                        }
+                        cache.tree = ProofTree::build(inner.tree_mode, &cache.chunks);
+                        Some(cache).filter(|c| c.tree.root() == *root)
+                    }
+                    _ => None,
+                };
+                if let Some(cache) = patched {
+                    inner.proofs.insert(pubkey.clone(), cache);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Look `pubkey` up in `indexer`, then in a second index that keeps only leaf hashes and
+/// serves the same proofs once handed the latest blob `archive` holds
+pub fn demo(chain: &ChainState, indexer: &Indexer, archive: &ArchiveNode, pubkey: &str) -> Result<()> {
+    indexer.sync(chain);
+    let Some(entry) = indexer.get(pubkey) else { bail!("index has no {}", pubkey) };
+    println!("Index: {} owned by {} at {} ({} chunks of {} bytes), root {}",
+        pubkey, entry.owner, entry.locations.join(", "), entry.chunk_count, entry.chunk_size, h(&entry.root));
+    if let Some(w) = indexer.proof(pubkey, 1) {
+        println!("Cached proof for chunk 1 verifies: {}", MerkleTree::verify_proof(&w.chunk, &w.proof, &entry.root));
+    }
+    let Some(&(latest_slot, _)) = archive.history(pubkey).last() else { bail!("archive has no history for {}", pubkey) };
+    let kept = archive.blob_at(pubkey, latest_slot).unwrap();
+    let lean = Indexer::new().with_tree_mode(TreeMode::Pruned { cached_nodes: DEFAULT_CACHED_NODES });
+    lean.sync(chain);
+    let cached = lean.cache_blob(pubkey, kept.chunk_size, &kept.data);
+    println!("Pruned index: {} account(s), {} owned by {}, root matches: {}, tree bytes {}; chunk 1 proof cached {} and same as the full index: {}",
+        lean.len(), lean.by_owner(&entry.owner).len(), entry.owner, lean.root(pubkey) == Some(entry.root), lean.tree_memory(),
+        cached, lean.proof(pubkey, 1) == indexer.proof(pubkey, 1));
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::AccountStub;
+
+    fn data() -> Vec<u8> {
+        (0..8000u32).map(|i| (i * 7) as u8).collect()
+    }
+
+    /// An index of two accounts, only the first of which it has seen the data of
+    fn indexed(mode: TreeMode) -> Indexer {
+        let root = MerkleTree::from_chunks(&chunk_blob(&data(), 32)).root();
+        let mut chain = ChainState::new();
+        chain.put_stub("A", AccountStub::new("Owner", 1_000, root, 8000, 32));
+        chain.put_stub("B", AccountStub::new("Other", 1_000, [1;32], 64, 32));
+        let indexer = Indexer::new().with_tree_mode(mode);
+        indexer.sync(&chain);
+        assert!(indexer.cache_blob("A", 32, &data()));
+        indexer
+    }
+
+    #[test]
+    fn serves_the_same_proofs_full_or_pruned() {
+        let full = indexed(TreeMode::Full);
+        let pruned = indexed(TreeMode::Pruned { cached_nodes: 8 });
+        for leaf in [0, 5, 249, 5] {
+            assert_eq!(pruned.proof("A", leaf), full.proof("A", leaf));
+        }
+        assert!(pruned.tree_memory() < full.tree_memory());
+    }
+
+    #[test]
+    fn caches_only_the_current_version() {
+        let index = indexed(TreeMode::Full);
+        let mut stale = data();
+        stale[0] ^= 1;
+        assert!(!index.cache_blob("A", 32, &stale));
+        assert!(!index.cache_blob("C", 32, &data()));
+        assert!(index.proof("B", 0).is_none());
+        assert!(index.proof("A", 250).is_none());
+    }
+
+    #[test]
+    fn looks_accounts_up_by_owner() {
+        let index = indexed(TreeMode::Full);
+        assert_eq!(index.len(), 2);
+        assert_eq!(index.by_owner("Owner"), ["A"]);
+        assert_eq!(index.root("B"), Some([1;32]));
+        assert_eq!(index.root("C"), None);
+    }
+
+    #[test]
+    fn counts_chunks_as_the_tree_does() {
+        let mut chain = ChainState::new();
+        chain.put_stub("Empty", AccountStub::new("Owner", 0, [0;32], 0, 32));
+        chain.put_stub("Odd", AccountStub::new("Owner", 0, [0;32], 65, 32));
+        chain.put_stub("Broken", AccountStub::new("Owner", 0, [0;32], 65, 0));
+        let index = Indexer::new();
+        index.sync(&chain);
+        assert_eq!(index.get("Empty").unwrap().chunk_count, 1);
+        assert_eq!(index.get("Odd").unwrap().chunk_count, 3);
+        assert_eq!(index.get("Broken").unwrap().chunk_count, 0);
+    }
+}
@@ -0,0 +1,106 @@
+use std::io::Read;
+use std::path::Path;
+
+use anyhow::{Context, Result, bail};
+
+use crate::blob_store::{BlobStore, RootIndex};
+use crate::{chunk_blob, h, MerkleTree};
+
+/// Blobs pinned on an IPFS node through its HTTP RPC API (Kubo, `http://127.0.0.1:5001`).
+/// IPFS addresses content by CID, not by our Merkle root, so the store keeps a
+/// root → CID index (see `with_index_file`).
+#[derive(Debug)]
+pub struct IpfsBlobStore {
+    pub api_url: String,
+    /// Chunk size blobs are committed with; fetched bytes must rebuild the root
+    pub chunk_size: usize,
+    index: RootIndex,
+}
+
+impl IpfsBlobStore {
+    pub fn new(api_url: &str, chunk_size: usize) -> Self {
+        Self { api_url: api_url.trim_end_matches('/').to_string(), chunk_size, index: RootIndex::default() }
+    }
+
+    /// Persist the root → CID index at `path` (loading it if present)
+    pub fn with_index_file(mut self, path: impl AsRef<Path>) -> Result<Self> {
+        self.index = RootIndex::open(path.as_ref())?;
+        Ok(self)
+    }
+
+    /// CID the blob committed by `root` was pinned under, if any
+    pub fn cid(&self, root: &[u8;32]) -> Option<String> {
+        self.index.get(root)
+    }
+
+    fn rpc(&self, method: &str) -> ureq::Request {
+        ureq::post(&format!("{}/api/v0/{}", self.api_url, method))
+    }
+}
+
+impl BlobStore for IpfsBlobStore {
+    fn put(&self, root: &[u8;32], bytes: &[u8]) -> Result<()> {
+        if self.cid(root).is_some() {
+            return Ok(());
+        }
+        if MerkleTree::from_chunks(&chunk_blob(bytes, self.chunk_size)).root() != *root {
+            bail!("blob does not hash to {} with chunk size {}", h(root), self.chunk_size);
+        }
+        // /add takes the file as multipart form data
+        let boundary = format!("witness-{}", hex::encode(&root[..8]));
+        let mut body = format!("--{}\r\nContent-Disposition: form-data; name=\"file\"; filename=\"{}\"\r\nContent-Type: application/octet-stream\r\n\r\n", boundary, hex::encode(root)).into_bytes();
+        body.extend_from_slice(bytes);
+        body.extend_from_slice(format!("\r\n--{}--\r\n", boundary).as_bytes());
+        let response: serde_json::Value = self.rpc("add")
+            .query("pin", "true")
+            .query("cid-version", "1")
+            .set("Content-Type", &format!("multipart/form-data; boundary={}", boundary))
+            .send_bytes(&body)
+            .with_context(|| format!("pinning blob {}", h(root)))?
+            .into_json()?;
+        let cid = match response["Hash"].as_str() {
+            Some(c) => c.to_string(),
+            None => bail!("IPFS add returned no CID for {}: {}", h(root), response),
+        };
+        self.index.insert(root, cid)
+    }
+
+    fn get(&self, root: &[u8;32]) -> Result<Option<Vec<u8>>> {
+        let cid = match self.cid(root) {
+            Some(c) => c,
+            None => return Ok(None),
+        };
+        let mut bytes = Vec::new();
+        self.rpc("cat").query("arg", &cid).call()
+            .with_context(|| format!("fetching {} (root {})", cid, h(root)))?
+            .into_reader()
+            .read_to_end(&mut bytes)?;
+        // the CID only vouches for what IPFS stored; check it is still the committed blob
+        if MerkleTree::from_chunks(&chunk_blob(&bytes, self.chunk_size)).root() != *root {
+            bail!("content of {} does not match root {}", cid, h(root));
+        }
+        Ok(Some(bytes))
+    }
+
+    fn has(&self, root: &[u8;32]) -> Result<bool> {
+        Ok(self.cid(root).is_some())
+    }
+
+    /// Unpins the blob; the node frees the bytes on its next repo GC
+    fn delete(&self, root: &[u8;32]) -> Result<()> {
+        let cid = match self.cid(root) {
+            Some(c) => c,
+            None => return Ok(()),
+        };
+        match self.rpc("pin/rm").query("arg", &cid).call() {
+            // 500 with "not pinned" means someone already unpinned it
+            Ok(_) | Err(ureq::Error::Status(500, _)) => {}
+            Err(e) => return Err(e).with_context(|| format!("unpinning {}", cid)),
+        }
+        self.index.remove(root)
+    }
+
+    fn checks_roots(&self) -> bool {
+        true
+    }
+}
@@ -0,0 +1,248 @@
+use std::collections::BTreeMap;
+use std::ops::Range;
+
+use anyhow::Result;
+
+use crate::error::WitnessError;
+use crate::view::ChunkWitness;
+use crate::{chunk_blob, MerkleTree};
+
+/// Proof that the JSON blob of an account holds a value at `pointer` (RFC 6901). With
+/// `with_context` every chunk from the start of the blob is included, so the verifier
+/// re-walks the pointer itself. Without it only the chunks covering `span` are
+/// disclosed: they prove which value sits at those bytes, but not that `pointer` leads
+/// there, which the verifier takes on the prover's word.
+#[derive(Debug, Clone)]
+pub struct JsonProof {
+    pub pointer: String,
+    pub span: Range<usize>,
+    pub with_context: bool,
+    pub chunks: Vec<ChunkWitness>,
+}
+
+fn skip_ws(json: &[u8], mut pos: usize) -> usize {
+    while pos < json.len() && matches!(json[pos], b' ' | b'\t' | b'\n' | b'\r') {
+        pos += 1;
+    }
+    pos
+}
+
+/// End of the value starting at `pos` (after whitespace)
+fn value_end(json: &[u8], pos: usize) -> Result<usize, WitnessError> {
+    let pos = skip_ws(json, pos);
+    match json.get(pos) {
+        Some(b'"') => {
+            let mut i = pos + 1;
+            while i < json.len() {
+                match json[i] {
+                    b'\\' => i += 2,
+                    b'"' => return Ok(i + 1),
+                    _ => i += 1,
+                }
+            }
+            Err(WitnessError::Malformed(format!("unterminated string at byte {}", pos)))
+        }
+        Some(b'{') | Some(b'[') => {
+            let mut depth = 0usize;
+            let mut i = pos;
+            while i < json.len() {
+                match json[i] {
+                    b'"' => {
+                        i = value_end(json, i)?;
+                        continue;
+                    }
+                    b'{' | b'[' => depth += 1,
+                    b'}' | b']' => {
+                        depth -= 1;
+                        if depth == 0 {
+                            return Ok(i + 1);
+                        }
+                    }
+                    _ => {}
+                }
+                i += 1;
+            }
+            Err(WitnessError::Malformed(format!("unterminated container at byte {}", pos)))
+        }
+        Some(_) => {
+            let mut i = pos;
+            while i < json.len() && !matches!(json[i], b',' | b'}' | b']' | b' ' | b'\t' | b'\n' | b'\r') {
+                i += 1;
+            }
+            Ok(i)
+        }
+        None => Err(WitnessError::Malformed(format!("expected a value at byte {}", pos))),
+    }
+}
+
+/// Byte range of the value `pointer` refers to. Only reads bytes up to the end of that
+/// value, so a proven prefix of the document is enough.
+pub fn resolve_pointer(json: &[u8], pointer: &str) -> Result<Range<usize>, WitnessError> {
+    if !pointer.is_empty() && !pointer.starts_with('/') {
+        return Err(WitnessError::Malformed(format!("JSON pointer must start with '/': {}", pointer)));
+    }
+    let mut pos = skip_ws(json, 0);
+    for token in pointer.split('/').skip(1) {
+        let token = token.replace("~1", "/").replace("~0", "~");
+        let container = json.get(pos).copied();
+        let close = match container {
+            Some(b'{') => b'}',
+            Some(b'[') => b']',
+            _ => return Err(WitnessError::Malformed(format!("cannot index into a scalar with '{}'", token))),
+        };
+        let target_index: Option<usize> = if container == Some(b'[') {
+            match token.parse() {
+                Ok(index) => Some(index),
+                Err(_) => return Err(WitnessError::Malformed(format!("array index '{}'", token))),
+            }
+        } else {
+            None
+        };
+        let mut i = skip_ws(json, pos + 1);
+        let mut index = 0usize;
+        let found = loop {
+            if json.get(i) == Some(&close) {
+                return Err(WitnessError::PointerNotFound(token));
+            }
+            let matched = match target_index {
+                Some(t) => t == index,
+                None => {
+                    let key_end = value_end(json, i)?;
+                    let key: String = serde_json::from_slice(&json[i..key_end]).map_err(|e| WitnessError::Malformed(format!("object key: {}", e)))?;
+                    i = skip_ws(json, key_end);
+                    if json.get(i) != Some(&b':') {
+                        return Err(WitnessError::Malformed(format!("expected ':' at byte {}", i)));
+                    }
+                    i = skip_ws(json, i + 1);
+                    key == token
+                }
+            };
+            if matched {
+                break i;
+            }
+            i = skip_ws(json, value_end(json, i)?);
+            match json.get(i) {
+                Some(b',') => i = skip_ws(json, i + 1),
+                Some(c) if *c == close => return Err(WitnessError::PointerNotFound(token)),
+                _ => return Err(WitnessError::Malformed(format!("malformed JSON at byte {}", i))),
+            }
+            index += 1;
+        };
+        pos = found;
+    }
+    Ok(pos..value_end(json, pos)?)
+}
+
+impl JsonProof {
+    /// Prover side: locate `pointer` in `blob` and prove the chunks needed to disclose it
+    pub fn prove(pubkey: &str, blob: &[u8], chunk_size: usize, pointer: &str, with_context: bool) -> Result<Self, WitnessError> {
+        let span = resolve_pointer(blob, pointer)?;
+        let first = if with_context { 0 } else { span.start / chunk_size };
+        let last = span.end.saturating_sub(1) / chunk_size;
+        let chunks = chunk_blob(blob, chunk_size);
+        let tree = MerkleTree::from_chunks(&chunks);
+        let chunks = (first..=last).map(|i| ChunkWitness {
+            pubkey: pubkey.to_string(),
+            leaf_index: i,
+            chunk: chunks[i].to_vec(),
+            proof: tree.gen_proof(i),
+        }).collect();
+        Ok(Self { pointer: pointer.to_string(), span, with_context, chunks })
+    }
+
+    /// Verifier side: check the chunks against `root`, a tree of `chunk_count` chunks,
+    /// and parse the disclosed value. Only with context is it checked to be the value at
+    /// `pointer`; bare, it is the value at `span`.
+    pub fn verify(&self, root: &[u8;32], chunk_size: usize, chunk_count: usize) -> Result<serde_json::Value, WitnessError> {
+        let len = chunk_count.saturating_mul(chunk_size);
+        if self.span.start > self.span.end || self.span.end > len {
+            return Err(WitnessError::SpanOutOfBlob { start: self.span.start, end: self.span.end, len });
+        }
+        let mut proven = BTreeMap::new();
+        for c in &self.chunks {
+            if c.chunk.len() != chunk_size {
+                return Err(WitnessError::ChunkSizeMismatch { pubkey: c.pubkey.clone(), expected: chunk_size, actual: c.chunk.len() });
+            }
+            witness_core::check_proof_shape(&c.proof, c.leaf_index, chunk_count)?;
+            if !MerkleTree::verify_proof(&c.chunk, &c.proof, root) {
+                return Err(WitnessError::LeafNotCommitted { leaf_index: c.leaf_index, root: *root });
+            }
+            proven.insert(c.leaf_index, c.chunk.as_slice());
+        }
+        let start = if self.with_context { 0 } else { self.span.start };
+        let mut bytes = Vec::with_capacity(self.span.end - start);
+        for pos in start..self.span.end {
+            match proven.get(&(pos / chunk_size)) {
+                Some(chunk) => bytes.push(chunk[pos % chunk_size]),
+                None => return Err(WitnessError::UnprovenByte { pos, chunk: pos / chunk_size }),
+            }
+        }
+        if self.with_context && resolve_pointer(&bytes, &self.pointer)? != self.span {
+            return Err(WitnessError::PointerMismatch(self.pointer.clone()));
+        }
+        serde_json::from_slice(&bytes[self.span.start - start..]).map_err(|e| WitnessError::Malformed(format!("disclosed bytes are not a JSON value: {}", e)))
+    }
+}
+
+/// Disclose one NFT metadata attribute by JSON pointer, bare (at the bytes the prover
+/// names) and with its context (at the pointer itself)
+pub fn demo(chunk_size: usize) -> Result<()> {
+    let metadata = br#"{"name":"Witness #7","symbol":"WIT","attributes":[{"trait_type":"Background","value":"Teal"},{"trait_type":"Rarity","value":"Legendary"}],"image":"https://example.com/7.png"}"#.to_vec();
+    let chunks = chunk_blob(&metadata, chunk_size);
+    let root = MerkleTree::from_chunks(&chunks).root();
+    for with_context in [false, true] {
+        let proof = JsonProof::prove("Nft7", &metadata, chunk_size, "/attributes/1/value", with_context)?;
+        let value = proof.verify(&root, chunk_size, chunks.len())?;
+        let at = if with_context { proof.pointer.clone() } else { format!("bytes {}..{}", proof.span.start, proof.span.end) };
+        println!("{} = {} ({} of {} chunks disclosed, context: {})", at, value, proof.chunks.len(), chunks.len(), with_context);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DOC: &[u8] = br#"{"a":1,"b":{"c":"deep"},"d":[true,false]}"#;
+    const CHUNK: usize = 8;
+
+    fn root() -> ([u8;32], usize) {
+        let chunks = chunk_blob(DOC, CHUNK);
+        (MerkleTree::from_chunks(&chunks).root(), chunks.len())
+    }
+
+    #[test]
+    fn verifies_bare_and_in_context() {
+        let (root, count) = root();
+        for with_context in [false, true] {
+            let proof = JsonProof::prove("Doc", DOC, CHUNK, "/b/c", with_context).unwrap();
+            assert_eq!(proof.verify(&root, CHUNK, count).unwrap(), serde_json::json!("deep"));
+        }
+        let proof = JsonProof::prove("Doc", DOC, CHUNK, "/d/1", true).unwrap();
+        assert_eq!(proof.verify(&root, CHUNK, count).unwrap(), serde_json::json!(false));
+    }
+
+    #[test]
+    fn rejects_spans_outside_the_blob() {
+        let (root, count) = root();
+        let mut proof = JsonProof::prove("Doc", DOC, CHUNK, "/a", false).unwrap();
+        proof.span = proof.span.end..proof.span.start;
+        assert!(matches!(proof.verify(&root, CHUNK, count), Err(WitnessError::SpanOutOfBlob { .. })));
+        proof.span = 0..count * CHUNK + 1;
+        assert!(matches!(proof.verify(&root, CHUNK, count), Err(WitnessError::SpanOutOfBlob { .. })));
+        proof.span = usize::MAX - 1..usize::MAX;
+        assert!(matches!(proof.verify(&root, CHUNK, count), Err(WitnessError::SpanOutOfBlob { .. })));
+    }
+
+    #[test]
+    fn context_ties_the_value_to_the_pointer() {
+        let (root, count) = root();
+        let mut proof = JsonProof::prove("Doc", DOC, CHUNK, "/b/c", true).unwrap();
+        proof.pointer = "/a".to_string();
+        assert!(matches!(proof.verify(&root, CHUNK, count), Err(WitnessError::PointerMismatch(_))));
+        // bare, the same swap goes unnoticed: the value is only the one at `span`
+        let mut bare = JsonProof::prove("Doc", DOC, CHUNK, "/b/c", false).unwrap();
+        bare.pointer = "/a".to_string();
+        assert_eq!(bare.verify(&root, CHUNK, count).unwrap(), serde_json::json!("deep"));
+    }
+}
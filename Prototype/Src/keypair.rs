@@ -0,0 +1,64 @@
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result, bail};
+use ed25519_dalek::SigningKey;
+
+/// Where `solana-keygen new` writes and the Solana CLI looks by default
+pub fn default_keypair_path() -> Option<PathBuf> {
+    std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config/solana/id.json"))
+}
+
+/// Solana's address form of a public key
+pub fn pubkey_base58(pubkey: &[u8;32]) -> String {
+    bs58::encode(pubkey).into_string()
+}
+
+/// A fresh key from the OS random source
+pub fn generate() -> Result<SigningKey> {
+    let mut seed = [0u8; 32];
+    getrandom::getrandom(&mut seed).context("reading OS randomness")?;
+    Ok(SigningKey::from_bytes(&seed))
+}
+
+/// `solana-keygen`'s file format: a JSON array of the 64 bytes secret key then public key
+pub fn to_json(key: &SigningKey) -> String {
+    serde_json::to_string(&key.to_keypair_bytes().to_vec()).unwrap()
+}
+
+pub fn from_json(text: &str) -> Result<SigningKey> {
+    let bytes: Vec<u8> = serde_json::from_str(text).context("keypair must be a JSON array of bytes")?;
+    let bytes: [u8;64] = match bytes.try_into() {
+        Ok(b) => b,
+        Err(b) => bail!("keypair must be 64 bytes, got {}", b.len()),
+    };
+    // the public half is stored too; a file where it doesn't match was edited or corrupted
+    SigningKey::from_keypair_bytes(&bytes).context("public key does not match the secret key")
+}
+
+pub fn read_keypair_file(path: &Path) -> Result<SigningKey> {
+    let text = std::fs::read_to_string(path).with_context(|| format!("reading keypair {}", path.display()))?;
+    from_json(&text).with_context(|| format!("in {}", path.display()))
+}
+
+/// Write `key` readable by the owner only; an existing file is kept unless `force`
+pub fn write_keypair_file(key: &SigningKey, path: &Path, force: bool) -> Result<()> {
+    if let Some(dir) = path.parent().filter(|d| !d.as_os_str().is_empty()) {
+        std::fs::create_dir_all(dir).with_context(|| format!("creating {}", dir.display()))?;
+    }
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true);
+    if force {
+        options.create(true).truncate(true);
+    } else {
+        options.create_new(true);
+    }
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    let mut file = match options.open(path) {
+        Ok(f) => f,
+        Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => bail!("{} exists; pass --force to overwrite it", path.display()),
+        Err(e) => return Err(e).with_context(|| format!("creating {}", path.display())),
+    };
+    file.write_all(to_json(key).as_bytes()).with_context(|| format!("writing {}", path.display()))
+}
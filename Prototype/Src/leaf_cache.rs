@@ -0,0 +1,182 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::sync::{Arc, Mutex};
+
+use crate::blob_cache::CacheConfig;
+use crate::{chunk_blob, sha256, MerkleTree};
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct LeafCacheStats {
+    pub hits: u64,
+    pub misses: u64,
+    pub evictions: u64,
+    /// Leaf hashes taken from a cached tree instead of rehashing their chunk
+    pub leaves_reused: u64,
+    pub leaves_hashed: u64,
+    pub entries: usize,
+    pub bytes: u64,
+}
+
+/// A committed version of an account: its bytes and its tree. The bytes are our own
+/// copy, so a rebuild never has to trust the old blob a transaction carries (only one of
+/// its chunks is proven).
+#[derive(Debug)]
+struct CachedTree {
+    data: Vec<u8>,
+    tree: MerkleTree,
+}
+
+impl CachedTree {
+    fn size(&self) -> u64 {
+        (self.data.len() + self.tree.nodes.len() * 32) as u64
+    }
+}
+
+type Key = ([u8;32], usize);
+
+#[derive(Debug, Default)]
+struct Inner {
+    /// (root, chunk size) -> (tree, last-use tick)
+    trees: HashMap<Key, (Arc<CachedTree>, u64)>,
+    /// last-use tick -> key, oldest first
+    lru: BTreeMap<u64, Key>,
+    tick: u64,
+    stats: LeafCacheStats,
+}
+
+impl Inner {
+    fn get(&mut self, key: &Key) -> Option<Arc<CachedTree>> {
+        self.tick += 1;
+        let tick = self.tick;
+        let (tree, last) = self.trees.get_mut(key)?;
+        self.lru.remove(last);
+        *last = tick;
+        self.lru.insert(tick, *key);
+        Some(tree.clone())
+    }
+
+    fn remove(&mut self, key: &Key) {
+        if let Some((tree, last)) = self.trees.remove(key) {
+            self.lru.remove(&last);
+            self.stats.entries -= 1;
+            self.stats.bytes -= tree.size();
+        }
+    }
+
+    fn insert(&mut self, key: Key, tree: Arc<CachedTree>, config: &CacheConfig) {
+        let size = tree.size();
+        if size > config.max_bytes || config.max_entries == 0 {
+            return;
+        }
+        self.remove(&key);
+        while self.stats.entries >= config.max_entries || self.stats.bytes + size > config.max_bytes {
+            let oldest = match self.lru.first_key_value() {
+                Some((_, k)) => *k,
+                None => break,
+            };
+            self.remove(&oldest);
+            self.stats.evictions += 1;
+        }
+        self.tick += 1;
+        self.stats.entries += 1;
+        self.stats.bytes += size;
+        self.lru.insert(self.tick, key);
+        self.trees.insert(key, (tree, self.tick));
+    }
+}
+
+/// Trees of recently written account versions, keyed by root and chunk size. Rebuilding
+/// from a cached version rehashes only the chunks whose bytes differ and, while the
+/// padded leaf count stays the same, only the parents above them: O(changed · log n)
+/// hashing instead of O(n). A root fixes its bytes, so entries never go stale.
+#[derive(Debug)]
+pub struct LeafCache {
+    config: CacheConfig,
+    inner: Mutex<Inner>,
+}
+
+impl Default for LeafCache {
+    fn default() -> Self {
+        Self::new(CacheConfig::default())
+    }
+}
+
+impl LeafCache {
+    pub fn new(config: CacheConfig) -> Self {
+        Self { config, inner: Mutex::default() }
+    }
+
+    pub fn stats(&self) -> LeafCacheStats {
+        self.inner.lock().unwrap().stats.clone()
+    }
+
+    /// (bytes of cached account data, bytes of cached trees)
+    pub fn memory_usage(&self) -> (usize, usize) {
+        let inner = self.inner.lock().unwrap();
+        inner.trees.values().fold((0, 0), |(data, trees), (cached, _)| {
+            (data + cached.data.capacity(), trees + cached.tree.memory_usage())
+        })
+    }
+
+    /// Remember `data`'s tree so a later write to it can start from there
+    pub fn insert(&self, data: &[u8], chunk_size: usize, tree: &MerkleTree) {
+        let cached = Arc::new(CachedTree { data: data.to_vec(), tree: tree.clone() });
+        self.inner.lock().unwrap().insert((tree.root(), chunk_size), cached, &self.config);
+    }
+
+    /// Tree over `new_data`, which replaces the version under `old_root`. Gives the same
+    /// tree as `MerkleTree::from_chunks`, however much of the old version is cached.
+    pub fn rebuild(&self, old_root: &[u8;32], new_data: &[u8], chunk_size: usize) -> MerkleTree {
+        let cached = {
+            let mut inner = self.inner.lock().unwrap();
+            let cached = inner.get(&(*old_root, chunk_size));
+            match cached {
+                Some(_) => inner.stats.hits += 1,
+                None => inner.stats.misses += 1,
+            }
+            cached
+        };
+        // hash outside the lock so parallel executions don't serialize on it
+        let new_chunks = chunk_blob(new_data, chunk_size);
+        let old_chunks = cached.as_ref().map(|c| chunk_blob(&c.data, chunk_size));
+        let mut reused = 0;
+        let leaves: Vec<[u8;32]> = new_chunks.iter().enumerate().map(|(i, chunk)| {
+            match (&cached, old_chunks.as_ref().and_then(|old| old.get(i))) {
+                (Some(c), Some(old)) if old == chunk => {
+                    reused += 1;
+                    c.tree.nodes[i]
+                }
+                _ => sha256(chunk),
+            }
+        }).collect();
+        let hashed = leaves.len() as u64 - reused;
+        tracing::trace!(hit = cached.is_some(), reused, hashed, "rebuilt tree from leaf cache");
+        let tree = match &cached {
+            Some(c) if !leaves.is_empty() && leaves.len().next_power_of_two() == c.tree.width => update(&c.tree, leaves),
+            _ => MerkleTree::from_leaves(leaves),
+        };
+        let mut inner = self.inner.lock().unwrap();
+        inner.stats.leaves_reused += reused;
+        inner.stats.leaves_hashed += hashed;
+        inner.insert((tree.root(), chunk_size), Arc::new(CachedTree { data: new_data.to_vec(), tree: tree.clone() }), &self.config);
+        tree
+    }
+}
+
+/// `old` with its leaves replaced by `leaves` (padded to the same width), rehashing only
+/// the parents of leaves that changed
+fn update(old: &MerkleTree, mut leaves: Vec<[u8;32]>) -> MerkleTree {
+    let width = old.width;
+    let last = *leaves.last().unwrap();
+    leaves.resize(width, last);
+    let mut changed: BTreeSet<usize> = (0..width).filter(|&i| leaves[i] != old.nodes[i]).collect();
+    let mut nodes = old.nodes.clone();
+    nodes[..width].copy_from_slice(&leaves);
+    for level in 1..=old.depth() {
+        let (below, here) = (witness_core::layer_offset(width, level - 1), witness_core::layer_offset(width, level));
+        changed = changed.into_iter().map(|i| i / 2).collect();
+        for &i in &changed {
+            nodes[here + i] = witness_core::hash_pair(&nodes[below + 2 * i], &nodes[below + 2 * i + 1]);
+        }
+    }
+    MerkleTree { nodes, width }
+}
@@ -0,0 +1,157 @@
+use std::collections::BTreeMap;
+
+use anyhow::{Result, bail};
+
+use crate::archive::ArchiveNode;
+use crate::error::WitnessError;
+use crate::state::AccountProof;
+use crate::{h, sha256, ChainState, WitnessTx};
+
+/// Summary of a frozen slot. Its hash chains to the parent slot and commits to the
+/// state root, so a bank hash is enough to check any account proof for that slot.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BankHeader {
+    pub slot: u64,
+    pub parent_hash: [u8;32],
+    pub state_root: [u8;32],
+    pub blockhash: [u8;32],
+}
+
+impl BankHeader {
+    pub fn hash(&self) -> [u8;32] {
+        let mut data = Vec::with_capacity(8 + 32 * 3);
+        data.extend_from_slice(&self.slot.to_le_bytes());
+        data.extend_from_slice(&self.parent_hash);
+        data.extend_from_slice(&self.state_root);
+        data.extend_from_slice(&self.blockhash);
+        sha256(&data)
+    }
+}
+
+/// Client that follows the chain by bank hash alone. It never holds a stub or blob:
+/// accounts and witnesses are checked against proofs supplied alongside them.
+#[derive(Debug, Clone)]
+pub struct LightClient {
+    bank_hashes: BTreeMap<u64, [u8;32]>,
+}
+
+impl LightClient {
+    /// Start from a header obtained out of band (checkpoint / genesis)
+    pub fn new(trusted: &BankHeader) -> Self {
+        Self { bank_hashes: BTreeMap::from([(trusted.slot, trusted.hash())]) }
+    }
+
+    pub fn latest_slot(&self) -> u64 {
+        *self.bank_hashes.keys().next_back().unwrap()
+    }
+
+    pub fn bank_hash(&self, slot: u64) -> Option<[u8;32]> {
+        self.bank_hashes.get(&slot).copied()
+    }
+
+    /// Accept the next header if it extends the latest known bank hash
+    pub fn sync_header(&mut self, header: &BankHeader) -> Result<(), WitnessError> {
+        let latest = self.latest_slot();
+        if header.slot <= latest {
+            return Err(WitnessError::StaleHeader { slot: header.slot, latest });
+        }
+        if header.parent_hash != self.bank_hashes[&latest] {
+            return Err(WitnessError::HeaderDoesNotExtend { slot: header.slot, parent: self.bank_hashes[&latest] });
+        }
+        self.bank_hashes.insert(header.slot, header.hash());
+        Ok(())
+    }
+
+    /// Check that `header` is one we have synced, returning its state root
+    fn trusted_state_root(&self, header: &BankHeader) -> Result<[u8;32], WitnessError> {
+        match self.bank_hashes.get(&header.slot) {
+            Some(hash) if *hash == header.hash() => Ok(header.state_root),
+            Some(_) => Err(WitnessError::BankHashMismatch(header.slot)),
+            None => Err(WitnessError::UnknownBankHash(header.slot)),
+        }
+    }
+
+    /// Verify that `proof.pubkey` held `proof.stub` at `header.slot`
+    pub fn verify_account(&self, header: &BankHeader, proof: &AccountProof) -> Result<(), WitnessError> {
+        let state_root = self.trusted_state_root(header)?;
+        if !proof.verify(&state_root) {
+            return Err(WitnessError::AccountProofFailed { pubkey: proof.pubkey.clone(), state_root });
+        }
+        Ok(())
+    }
+
+    /// Verify every witness in `tx` against the stub proven for its account at `header.slot`.
+    /// `account_proofs` must contain one proof per witnessed account.
+    pub fn verify_tx(&self, header: &BankHeader, tx: &WitnessTx, account_proofs: &[AccountProof]) -> Result<(), WitnessError> {
+        for w in &tx.witnesses {
+            let proof = match account_proofs.iter().find(|p| p.pubkey == w.pubkey) {
+                Some(p) => p,
+                None => return Err(WitnessError::MissingAccountProof(w.pubkey.clone())),
+            };
+            self.verify_account(header, proof)?;
+            w.verify(&proof.stub)?;
+        }
+        Ok(())
+    }
+}
+
+/// Check `pubkey` against a bank hash alone, then follow `chain` a slot and check a
+/// witness tx for it, served by `archive`, without holding any stub
+pub fn demo(chain: &mut ChainState, archive: &ArchiveNode, pubkey: &str) -> Result<()> {
+    let account_proof = chain.prove_account(pubkey).unwrap();
+    let slot = chain.slot;
+    chain.advance_slot();
+    let header = chain.bank_header(slot).unwrap().clone();
+    let mut light = LightClient::new(&header);
+    light.verify_account(&header, &account_proof)?;
+    println!("Light client verified {} against bank hash {} for slot {}", pubkey, h(&header.hash()), header.slot);
+    chain.advance_slot();
+    light.sync_header(chain.bank_header(slot + 1).unwrap())?;
+    if light.sync_header(&header).is_ok() {
+        bail!("light client went back to an older header");
+    }
+    let tx = WitnessTx {
+        program_id: account_proof.stub.owner.clone(),
+        instruction_data: Vec::new(),
+        witnesses: vec![archive.get_witness(pubkey, &account_proof.stub.merkle_root, 0).unwrap()],
+        recent_blockhash: chain.latest_blockhash(),
+        priority_fee: 0,
+        signatures: Vec::new(),
+    };
+    light.verify_tx(&header, &tx, std::slice::from_ref(&account_proof))?;
+    println!("Light client synced to slot {} ({}), verified a witness tx for {} against slot {}",
+        light.latest_slot(), h(&light.bank_hash(light.latest_slot()).unwrap()), pubkey, header.slot);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn child(parent: &BankHeader) -> BankHeader {
+        BankHeader { slot: parent.slot + 1, parent_hash: parent.hash(), state_root: sha256(&parent.state_root), blockhash: sha256(&parent.blockhash) }
+    }
+
+    #[test]
+    fn syncs_only_headers_that_extend_the_latest() {
+        let genesis = BankHeader { slot: 0, parent_hash: [0; 32], state_root: [1; 32], blockhash: [2; 32] };
+        let mut light = LightClient::new(&genesis);
+        let one = child(&genesis);
+        let two = child(&one);
+        assert!(matches!(light.sync_header(&two), Err(WitnessError::HeaderDoesNotExtend { slot: 2, .. })));
+        light.sync_header(&one).unwrap();
+        assert!(matches!(light.sync_header(&one), Err(WitnessError::StaleHeader { slot: 1, latest: 1 })));
+        let mut fork = two.clone();
+        fork.parent_hash = [9; 32];
+        assert!(matches!(light.sync_header(&fork), Err(WitnessError::HeaderDoesNotExtend { slot: 2, .. })));
+        light.sync_header(&two).unwrap();
+        assert_eq!(light.latest_slot(), 2);
+        assert_eq!(light.bank_hash(1), Some(one.hash()));
+        assert_eq!(light.bank_hash(3), None);
+        // a header is only trusted as synced, not as its slot number
+        let mut forged = one.clone();
+        forged.state_root = [7; 32];
+        assert!(matches!(light.trusted_state_root(&forged), Err(WitnessError::BankHashMismatch(1))));
+        assert_eq!(light.trusted_state_root(&one).unwrap(), one.state_root);
+    }
+}
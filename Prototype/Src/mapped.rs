@@ -0,0 +1,63 @@
+//! Blobs read through a memory map, so committing a multi-gigabyte snapshot only keeps
+//! its leaf hashes in RAM; the kernel pages the file in as chunks are hashed and can
+//! drop those pages again behind us.
+
+use std::fs::File;
+use std::ops::Deref;
+use std::path::Path;
+
+use anyhow::{Context, Result, bail};
+use memmap2::Mmap;
+
+use crate::{chunk_blob, MerkleTree};
+
+/// Page size chunk boundaries are measured against; 4 KiB is the base page on every
+/// platform we build for
+pub const PAGE_SIZE: usize = 4096;
+
+/// A read-only map of a whole file. The map starts on a page boundary, so with a chunk
+/// size that is a multiple or a divisor of `PAGE_SIZE` no chunk straddles two pages.
+pub struct MappedBlob {
+    /// None for an empty file, which cannot be mapped
+    map: Option<Mmap>,
+}
+
+impl MappedBlob {
+    pub fn open(path: &Path) -> Result<Self> {
+        let file = File::open(path).with_context(|| format!("opening {}", path.display()))?;
+        let len = file.metadata().with_context(|| format!("reading {}", path.display()))?.len();
+        if len == 0 {
+            return Ok(Self { map: None });
+        }
+        // SAFETY: the map is read-only and the file is not expected to change while we
+        // hash it; if another process truncates it we may fault, as with any mapped input
+        let map = unsafe { Mmap::map(&file) }.with_context(|| format!("mapping {}", path.display()))?;
+        #[cfg(unix)]
+        map.advise(memmap2::Advice::Sequential).with_context(|| format!("advising on {}", path.display()))?;
+        Ok(Self { map: Some(map) })
+    }
+}
+
+impl Deref for MappedBlob {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        self.map.as_deref().unwrap_or(&[])
+    }
+}
+
+/// Whether `chunk_size`-byte chunks of a mapped blob each sit within whole pages
+pub fn page_aligned(chunk_size: usize) -> bool {
+    chunk_size > 0 && (chunk_size.is_multiple_of(PAGE_SIZE) || PAGE_SIZE.is_multiple_of(chunk_size))
+}
+
+impl MerkleTree {
+    /// Tree over the file at `path`, hashed straight out of a memory map
+    pub fn from_mmap(path: &Path, chunk_size: usize) -> Result<Self> {
+        if chunk_size == 0 {
+            bail!("chunk size must be positive");
+        }
+        let blob = MappedBlob::open(path)?;
+        Ok(MerkleTree::from_chunks(&chunk_blob(&blob, chunk_size)))
+    }
+}
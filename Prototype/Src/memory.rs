@@ -0,0 +1,57 @@
+//! Bytes held in memory by trees and chain state: the prover-side price of keeping
+//! account data off-chain. Counts what the collections have allocated (capacity, not
+//! length) plus the fixed size of each element, not allocator overhead.
+
+use std::mem::size_of;
+
+use crate::{AccountStub, ChainState, MerkleTree};
+
+/// Where a `ChainState`'s memory goes, in bytes
+#[derive(Debug, Clone, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct MemoryUsage {
+    /// Committed and staged stubs, keys included
+    pub stubs: usize,
+    /// Accounts still holding their data on-chain
+    pub hot_accounts: usize,
+    /// Account versions the leaf cache keeps to diff writes against
+    pub cached_blobs: usize,
+    /// Trees the leaf cache keeps for those versions
+    pub cached_trees: usize,
+    /// Tx ids kept for replay protection
+    pub status_cache: usize,
+}
+
+impl MemoryUsage {
+    pub fn total(&self) -> usize {
+        self.stubs + self.hot_accounts + self.cached_blobs + self.cached_trees + self.status_cache
+    }
+}
+
+impl MerkleTree {
+    pub fn memory_usage(&self) -> usize {
+        size_of::<Self>() + self.nodes.capacity() * 32
+    }
+}
+
+impl AccountStub {
+    pub fn memory_usage(&self) -> usize {
+        size_of::<Self>()
+            + self.owner.capacity()
+            + self.multisig.as_ref().map_or(0, |m| m.signers.capacity() * 32)
+            + self.pda.as_ref().map_or(0, |p| p.seeds.capacity() * size_of::<Vec<u8>>() + p.seeds.iter().map(|s| s.capacity()).sum::<usize>())
+    }
+}
+
+impl ChainState {
+    pub fn memory_usage(&self) -> MemoryUsage {
+        let staged = self.staging.iter().flat_map(|s| s.stubs.iter());
+        let (cached_blobs, cached_trees) = self.leaf_cache.memory_usage();
+        MemoryUsage {
+            stubs: self.stubs.iter().chain(staged).map(|(k, s)| k.capacity() + s.memory_usage()).sum(),
+            hot_accounts: self.hot_accounts.iter().map(|(k, a)| k.capacity() + a.owner.capacity() + a.data.capacity() + size_of_val(a)).sum(),
+            cached_blobs,
+            cached_trees,
+            status_cache: self.status_cache.values().map(|ids| 32 + ids.len() * 32).sum(),
+        }
+    }
+}
@@ -0,0 +1,152 @@
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, HashSet};
+
+use crate::error::WitnessError;
+use crate::WitnessTx;
+
+/// Default cap on queued transactions
+pub const DEFAULT_MEMPOOL_CAPACITY: usize = 10_000;
+
+/// Pending witness transactions ordered by priority fee (ties: arrival order)
+pub struct Mempool {
+    /// (Reverse(fee), arrival seq) -> tx, so iteration yields the highest fee first
+    queue: BTreeMap<(Reverse<u64>, u64), WitnessTx>,
+    ids: HashSet<[u8;32]>,
+    next_seq: u64,
+    capacity: usize,
+}
+
+impl Default for Mempool {
+    fn default() -> Self { Self::new(DEFAULT_MEMPOOL_CAPACITY) }
+}
+
+impl Mempool {
+    pub fn new(capacity: usize) -> Self {
+        Self { queue: BTreeMap::new(), ids: HashSet::new(), next_seq: 0, capacity }
+    }
+
+    pub fn is_empty(&self) -> bool { self.queue.is_empty() }
+
+    /// Queue `tx`. When full, the lowest-fee tx is evicted if `tx` pays more; otherwise `tx` is rejected.
+    pub fn submit(&mut self, tx: WitnessTx) -> Result<(), WitnessError> {
+        let id = tx.id();
+        if self.ids.contains(&id) {
+            return Err(WitnessError::AlreadyQueued(id));
+        }
+        if self.queue.len() >= self.capacity {
+            // an empty pool at capacity has nothing to evict
+            let Some(&lowest) = self.queue.keys().next_back() else { return Err(WitnessError::MempoolDisabled) };
+            if tx.priority_fee <= lowest.0.0 {
+                return Err(WitnessError::MempoolFull { fee: tx.priority_fee, lowest: lowest.0.0 });
+            }
+            let evicted = self.queue.remove(&lowest).unwrap();
+            self.ids.remove(&evicted.id());
+        }
+        self.ids.insert(id);
+        self.queue.insert((Reverse(tx.priority_fee), self.next_seq), tx);
+        self.next_seq += 1;
+        Ok(())
+    }
+
+    /// Pick up to `max_txs` for the next block, highest fee first. A tx whose locks
+    /// conflict with an already selected tx is skipped and stays queued for a later block.
+    pub fn take_block(&mut self, max_txs: usize) -> Vec<WitnessTx> {
+        let mut locks = AccountLocks::default();
+        let mut picked = Vec::new();
+        for (key, tx) in &self.queue {
+            if picked.len() >= max_txs {
+                break;
+            }
+            if locks.conflicts(tx) {
+                continue;
+            }
+            locks.lock(tx);
+            picked.push(*key);
+        }
+        picked.into_iter().map(|key| {
+            let tx = self.queue.remove(&key).unwrap();
+            self.ids.remove(&tx.id());
+            tx
+        }).collect()
+    }
+}
+
+/// Read/write locks held by a set of txs: writes are exclusive, reads are shared
+#[derive(Debug, Default)]
+pub struct AccountLocks {
+    write: HashSet<String>,
+    read: HashSet<String>,
+}
+
+impl AccountLocks {
+    pub fn conflicts(&self, tx: &WitnessTx) -> bool {
+        tx.witnesses.iter().any(|w| {
+            self.write.contains(&w.pubkey) || (w.is_writable && self.read.contains(&w.pubkey))
+        })
+    }
+
+    pub fn lock(&mut self, tx: &WitnessTx) {
+        for w in &tx.witnesses {
+            if w.is_writable {
+                self.write.insert(w.pubkey.clone());
+            } else {
+                self.read.insert(w.pubkey.clone());
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::AccountWitness;
+
+    /// A tx paying `fee` that writes (or reads) `pubkey`
+    fn tx(pubkey: &str, fee: u64, is_writable: bool) -> WitnessTx {
+        WitnessTx {
+            program_id: "Prog1".to_string(),
+            instruction_data: fee.to_le_bytes().to_vec(),
+            witnesses: vec![AccountWitness { pubkey: pubkey.to_string(), blob: Vec::new(), leaf_index: 0, proof: Vec::new(), is_writable }],
+            recent_blockhash: [0; 32],
+            priority_fee: fee,
+            signatures: Vec::new(),
+        }
+    }
+
+    fn fees(txs: &[WitnessTx]) -> Vec<u64> {
+        txs.iter().map(|t| t.priority_fee).collect()
+    }
+
+    #[test]
+    fn evicts_the_lowest_fee_when_full() {
+        let mut pool = Mempool::new(2);
+        pool.submit(tx("A", 5, true)).unwrap();
+        pool.submit(tx("B", 1, true)).unwrap();
+        assert!(matches!(pool.submit(tx("A", 5, true)), Err(WitnessError::AlreadyQueued(_))));
+        assert!(matches!(pool.submit(tx("C", 1, true)), Err(WitnessError::MempoolFull { fee: 1, lowest: 1 })));
+        pool.submit(tx("C", 3, true)).unwrap();
+        assert_eq!(fees(&pool.take_block(10)), [5, 3]);
+        assert!(pool.is_empty());
+    }
+
+    #[test]
+    fn zero_capacity_rejects_instead_of_panicking() {
+        let mut pool = Mempool::new(0);
+        assert!(matches!(pool.submit(tx("A", u64::MAX, true)), Err(WitnessError::MempoolDisabled)));
+        assert!(pool.is_empty());
+    }
+
+    #[test]
+    fn conflicting_writes_wait_for_a_later_block() {
+        let mut pool = Mempool::default();
+        pool.submit(tx("A", 9, true)).unwrap();
+        pool.submit(tx("A", 8, false)).unwrap();
+        pool.submit(tx("B", 7, false)).unwrap();
+        pool.submit(tx("B", 6, false)).unwrap();
+        assert_eq!(fees(&pool.take_block(10)), [9, 7, 6]);
+        assert_eq!(fees(&pool.take_block(10)), [8]);
+        pool.submit(tx("C", 1, true)).unwrap();
+        pool.submit(tx("D", 2, true)).unwrap();
+        assert_eq!(fees(&pool.take_block(1)), [2]);
+    }
+}
@@ -0,0 +1,82 @@
+//! Prometheus metrics for witness providers: what was served, how long verification
+//! took, how the blob cache is doing and how much account data stays off-chain. One
+//! process-wide registry; the proof server exposes it on `GET /metrics` and
+//! `simulate --metrics-out` writes it for node_exporter's textfile collector.
+
+use std::path::Path;
+use std::sync::LazyLock;
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use prometheus::{Encoder, HistogramOpts, HistogramVec, IntCounterVec, IntGauge, Opts, Registry, TextEncoder};
+
+use crate::ChainState;
+
+pub struct Metrics {
+    registry: Registry,
+    /// By `source`: "index" (its tree cache) or "archive" (rebuilt from a stored blob)
+    pub proofs_served: IntCounterVec,
+    /// By `kind`: "tx", "view" or "chunk_tx"; the witness checks only, not the program
+    pub verification_seconds: HistogramVec,
+    /// By `result`: "hit" or "miss"
+    pub blob_cache_requests: IntCounterVec,
+    /// By `outcome`: "ok" or "failed"
+    pub transactions: IntCounterVec,
+    /// Committed account bytes minus the stubs standing in for them
+    pub onchain_bytes_saved: IntGauge,
+}
+
+impl Metrics {
+    fn new() -> Self {
+        let registry = Registry::new_custom(Some("witness".to_string()), None).unwrap();
+        let proofs_served = IntCounterVec::new(Opts::new("proofs_served_total", "Chunk proofs handed out"), &["source"]).unwrap();
+        let verification_seconds = HistogramVec::new(
+            HistogramOpts::new("verification_seconds", "Time spent checking a transaction's witnesses")
+                .buckets(prometheus::exponential_buckets(1e-6, 4.0, 10).unwrap()),
+            &["kind"],
+        ).unwrap();
+        let blob_cache_requests = IntCounterVec::new(Opts::new("blob_cache_requests_total", "Blob store reads through the cache"), &["result"]).unwrap();
+        let transactions = IntCounterVec::new(Opts::new("transactions_total", "Witness transactions processed"), &["outcome"]).unwrap();
+        let onchain_bytes_saved = IntGauge::new("onchain_bytes_saved", "Account data bytes replaced by stubs").unwrap();
+        registry.register(Box::new(proofs_served.clone())).unwrap();
+        registry.register(Box::new(verification_seconds.clone())).unwrap();
+        registry.register(Box::new(blob_cache_requests.clone())).unwrap();
+        registry.register(Box::new(transactions.clone())).unwrap();
+        registry.register(Box::new(onchain_bytes_saved.clone())).unwrap();
+        Self { registry, proofs_served, verification_seconds, blob_cache_requests, transactions, onchain_bytes_saved }
+    }
+
+    pub fn observe_verification(&self, kind: &str, elapsed: Duration) {
+        self.verification_seconds.with_label_values(&[kind]).observe(elapsed.as_secs_f64());
+    }
+
+    /// Text exposition format, with gauges that are read off `chain` refreshed first
+    pub fn encode(&self, chain: Option<&ChainState>) -> String {
+        if let Some(chain) = chain {
+            self.onchain_bytes_saved.set(chain.onchain_bytes_saved());
+        }
+        let mut out = Vec::new();
+        TextEncoder::new().encode(&self.registry.gather(), &mut out).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    /// `encode` into `path` through a temporary file, so the collector never reads half of it
+    pub fn write_textfile(&self, path: &Path, chain: &ChainState) -> Result<()> {
+        let tmp = path.with_extension("prom.tmp");
+        std::fs::write(&tmp, self.encode(Some(chain))).with_context(|| format!("writing {}", tmp.display()))?;
+        std::fs::rename(&tmp, path).with_context(|| format!("renaming {} to {}", tmp.display(), path.display()))
+    }
+}
+
+static METRICS: LazyLock<Metrics> = LazyLock::new(Metrics::new);
+
+pub fn metrics() -> &'static Metrics {
+    &METRICS
+}
+
+impl ChainState {
+    /// What committed accounts would take as plain account data, less their stubs
+    pub fn onchain_bytes_saved(&self) -> i64 {
+        self.stubs.values().map(|s| s.data_len as i64 - s.encode().len() as i64).sum()
+    }
+}
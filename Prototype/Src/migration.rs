@@ -0,0 +1,251 @@
+use std::collections::VecDeque;
+
+use anyhow::{Result, bail};
+
+use crate::archive::ArchiveNode;
+use crate::dirty::DirtyBitmap;
+use crate::error::WitnessError;
+use crate::events::WitnessEvent;
+use crate::{chunk_blob, h, sha256, AccountStub, ChainState, HashAlgo, MerkleTree};
+
+/// How a blob is split and hashed into its committed root
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TreeParams {
+    pub chunk_size: usize,
+    pub hash_algo: HashAlgo,
+    pub arity: u8,
+}
+
+impl TreeParams {
+    /// Binary SHA-256 tree over `chunk_size`-byte leaves, the only layout verifiers check today
+    pub fn new(chunk_size: usize) -> Self {
+        Self { chunk_size, hash_algo: HashAlgo::Sha256, arity: 2 }
+    }
+
+    pub fn of(stub: &AccountStub) -> Self {
+        Self { chunk_size: stub.chunk_size, hash_algo: stub.hash_algo, arity: stub.arity }
+    }
+
+    /// Refuse to migrate into a layout no verifier could check
+    fn check(&self) -> Result<(), WitnessError> {
+        if self.chunk_size == 0 {
+            return Err(WitnessError::ZeroChunkSize);
+        }
+        if self.hash_algo != HashAlgo::Sha256 || self.arity != 2 {
+            return Err(WitnessError::UnsupportedLayout { hash_algo: self.hash_algo, arity: self.arity });
+        }
+        Ok(())
+    }
+
+    pub fn root(&self, blob: &[u8]) -> [u8;32] {
+        MerkleTree::from_chunks(&chunk_blob(blob, self.chunk_size)).root()
+    }
+
+    fn encode(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(&(self.chunk_size as u64).to_le_bytes());
+        out.push(self.hash_algo.id());
+        out.push(self.arity);
+    }
+}
+
+/// Record that one account's bytes were re-committed under a new layout: anyone holding
+/// the blob (or proofs) for `old_root` can check it is the same data as `new_root`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Transition {
+    pub pubkey: String,
+    pub slot: u64,
+    pub data_len: u64,
+    pub from: TreeParams,
+    pub old_root: [u8;32],
+    pub to: TreeParams,
+    pub new_root: [u8;32],
+}
+
+impl Transition {
+    /// Hash of the record, for referencing it from logs or other accounts
+    pub fn id(&self) -> [u8;32] {
+        let mut out = Vec::new();
+        out.extend_from_slice(&(self.pubkey.len() as u32).to_le_bytes());
+        out.extend_from_slice(self.pubkey.as_bytes());
+        out.extend_from_slice(&self.slot.to_le_bytes());
+        out.extend_from_slice(&self.data_len.to_le_bytes());
+        self.from.encode(&mut out);
+        out.extend_from_slice(&self.old_root);
+        self.to.encode(&mut out);
+        out.extend_from_slice(&self.new_root);
+        sha256(&out)
+    }
+
+    /// Check `blob` commits to both roots, i.e. the migration kept the data intact
+    pub fn verify(&self, blob: &[u8]) -> bool {
+        blob.len() as u64 == self.data_len && self.from.root(blob) == self.old_root && self.to.root(blob) == self.new_root
+    }
+}
+
+impl ChainState {
+    /// Re-commit `pubkey`'s blob under `to`. The blob must match the current root; the
+    /// stub keeps everything but its layout, and the new version is emitted like a write
+    /// so archives and indexes store it next to the old one.
+    pub fn migrate_account(&mut self, pubkey: &str, blob: &[u8], to: TreeParams) -> Result<Transition, WitnessError> {
+        let stub = match self.get_stub(pubkey) {
+            Some(s) => s.clone(),
+            None => return Err(WitnessError::UnknownAccount(pubkey.to_string())),
+        };
+        if self.staging.is_some() {
+            return Err(WitnessError::InsideBatch { action: "migrate", pubkey: pubkey.to_string() });
+        }
+        stub.check_tree()?;
+        to.check()?;
+        // zero padding in the last chunk hashes alike, so the root alone doesn't fix the length
+        if blob.len() as u64 != stub.data_len {
+            return Err(WitnessError::BlobLengthMismatch { pubkey: pubkey.to_string(), len: blob.len(), data_len: stub.data_len });
+        }
+        let from = TreeParams::of(&stub);
+        let old_root = from.root(blob);
+        if old_root != stub.merkle_root {
+            return Err(WitnessError::BlobRootMismatch { blob: old_root, stub: stub.merkle_root });
+        }
+        let new_root = to.root(blob);
+
+        let mut new_stub = stub.clone();
+        new_stub.merkle_root = new_root;
+        new_stub.chunk_size = to.chunk_size;
+        new_stub.hash_algo = to.hash_algo;
+        new_stub.arity = to.arity;
+        // chunk indices mean different bytes now, so every proof cached before is stale
+        if let Some(dirty) = new_stub.dirty.as_mut() {
+            *dirty = DirtyBitmap { bits: [0xff; 32], since_slot: dirty.since_slot };
+        }
+        self.put_stub(pubkey, new_stub);
+        self.emit(WitnessEvent::RootUpdated { pubkey: pubkey.to_string(), slot: self.slot, old: stub.merkle_root, new: new_root });
+        self.emit(WitnessEvent::AccountDataWritten { pubkey: pubkey.to_string(), slot: self.slot, root: new_root, chunk_size: to.chunk_size, data: blob.to_vec() });
+        Ok(Transition { pubkey: pubkey.to_string(), slot: self.slot, data_len: stub.data_len, from, old_root: stub.merkle_root, to, new_root })
+    }
+}
+
+/// Moves every account not yet on `target` over to it a few at a time, fetching blobs
+/// from an archive, so a parameter upgrade can run alongside normal traffic. Accounts
+/// whose blob can't be found or doesn't match are set aside, not retried.
+#[derive(Debug)]
+pub struct MigrationJob {
+    target: TreeParams,
+    pending: VecDeque<String>,
+    transitions: Vec<Transition>,
+    failed: Vec<(String, String)>,
+}
+
+impl MigrationJob {
+    /// Queue the chain's chunk-tree accounts whose layout differs from `target`
+    pub fn new(chain: &ChainState, target: TreeParams) -> Result<Self, WitnessError> {
+        target.check()?;
+        let mut pending: Vec<String> = chain.stubs.iter()
+            .filter(|(_, s)| !s.append_only && TreeParams::of(s) != target)
+            .map(|(pubkey, _)| pubkey.clone())
+            .collect();
+        pending.sort();
+        Ok(Self { target, pending: pending.into(), transitions: Vec::new(), failed: Vec::new() })
+    }
+
+    /// Migrate up to `max_accounts` more accounts; returns how many succeeded
+    pub fn step(&mut self, chain: &mut ChainState, archive: &ArchiveNode, max_accounts: usize) -> usize {
+        let mut migrated = 0;
+        for _ in 0..max_accounts {
+            let pubkey = match self.pending.pop_front() {
+                Some(p) => p,
+                None => break,
+            };
+            let result = match chain.get_stub(&pubkey) {
+                // rewritten under the new layout by someone else since it was queued
+                Some(s) if TreeParams::of(s) == self.target => continue,
+                Some(s) => match archive.get_blob(&pubkey, &s.merkle_root) {
+                    Some(blob) => chain.migrate_account(&pubkey, &blob.data, self.target),
+                    None => Err(WitnessError::BlobNotStored { pubkey: pubkey.clone(), root: s.merkle_root }),
+                },
+                None => continue,
+            };
+            match result {
+                Ok(t) => {
+                    self.transitions.push(t);
+                    migrated += 1;
+                }
+                Err(e) => self.failed.push((pubkey, format!("{:#}", e))),
+            }
+        }
+        migrated
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.pending.is_empty()
+    }
+
+    pub fn remaining(&self) -> usize {
+        self.pending.len()
+    }
+
+    pub fn transitions(&self) -> &[Transition] {
+        &self.transitions
+    }
+
+    /// Accounts that could not be migrated, with why
+    pub fn failed(&self) -> &[(String, String)] {
+        &self.failed
+    }
+}
+
+/// Re-commit every archived account under twice `chunk_size`, two per step, and show
+/// `pubkey`'s transition
+pub fn demo(chain: &mut ChainState, archive: &ArchiveNode, pubkey: &str, chunk_size: usize) -> Result<()> {
+    let mut migration = MigrationJob::new(chain, TreeParams::new(chunk_size * 2))?;
+    let queued = migration.remaining();
+    let mut steps = 0;
+    while !migration.is_done() {
+        migration.step(chain, archive, 2);
+        steps += 1;
+    }
+    println!("\nMigrated {} of {} accounts to {}-byte chunks in {} steps; {} left behind",
+        migration.transitions().len(), queued, chunk_size * 2, steps, migration.failed().len());
+    if let Some(t) = migration.transitions().iter().find(|t| t.pubkey == pubkey) {
+        let Some(old) = archive.get_blob(pubkey, &t.old_root) else { bail!("archive lost {}'s blob before migration", pubkey) };
+        println!("{} transition {}: {} -> {}, same bytes: {}, archive holds both: {}", pubkey, h(&t.id()), h(&t.old_root), h(&t.new_root),
+            t.verify(&old.data), archive.get_blob(pubkey, &t.new_root).is_some());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chain(blob: &[u8]) -> ChainState {
+        let mut chain = ChainState::new();
+        let root = TreeParams::new(32).root(blob);
+        chain.put_stub("Acct1", AccountStub::new("Prog1", 0, root, blob.len() as u64, 32));
+        chain
+    }
+
+    #[test]
+    fn migrates_to_the_new_layout() {
+        let blob: Vec<u8> = (0..100u8).collect();
+        let mut chain = chain(&blob);
+        let transition = chain.migrate_account("Acct1", &blob, TreeParams::new(64)).unwrap();
+        assert!(transition.verify(&blob));
+        let stub = chain.get_stub("Acct1").unwrap();
+        assert_eq!((stub.merkle_root, stub.chunk_size, stub.data_len), (TreeParams::new(64).root(&blob), 64, 100));
+    }
+
+    #[test]
+    fn rejects_blobs_that_are_not_the_account() {
+        let blob: Vec<u8> = (0..100u8).collect();
+        let mut chain = chain(&blob);
+        // trailing zeros land in the last chunk's padding and keep the root
+        let padded = [blob.clone(), vec![0; 4]].concat();
+        assert_eq!(TreeParams::new(32).root(&padded), TreeParams::new(32).root(&blob));
+        assert!(matches!(chain.migrate_account("Acct1", &padded, TreeParams::new(64)), Err(WitnessError::BlobLengthMismatch { len: 104, data_len: 100, .. })));
+        let mut tampered = blob.clone();
+        tampered[0] ^= 1;
+        assert!(matches!(chain.migrate_account("Acct1", &tampered, TreeParams::new(64)), Err(WitnessError::BlobRootMismatch { .. })));
+        assert!(matches!(chain.migrate_account("Acct1", &blob, TreeParams::new(0)), Err(WitnessError::ZeroChunkSize)));
+        assert!(matches!(chain.migrate_account("Acct2", &blob, TreeParams::new(64)), Err(WitnessError::UnknownAccount(_))));
+        assert_eq!(chain.get_stub("Acct1").unwrap().chunk_size, 32);
+    }
+}
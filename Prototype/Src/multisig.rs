@@ -0,0 +1,188 @@
+use std::collections::HashSet;
+
+use anyhow::{Result, bail};
+use ed25519_dalek::{Signature, Signer, SigningKey, Verifier, VerifyingKey};
+
+use crate::error::WitnessError;
+use crate::events::StdoutSubscriber;
+use crate::program::{DemoProgram, DEMO_PROGRAM_ID};
+use crate::signer::MockSigner;
+use crate::{chunk_blob, sha256, AccountStub, AccountWitness, ChainState, MerkleTree, WitnessTx};
+
+/// m-of-n ed25519 authority over a stub: writes need `threshold` distinct signers
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct Multisig {
+    pub threshold: u8,
+    #[serde(with = "crate::serde_support::hex_list")]
+    pub signers: Vec<[u8;32]>,
+}
+
+impl Multisig {
+    pub fn new(threshold: u8, signers: Vec<[u8;32]>) -> Result<Self, WitnessError> {
+        // the encoding stores the signer count in one byte
+        if signers.len() > u8::MAX as usize {
+            return Err(WitnessError::TooManySigners(signers.len()));
+        }
+        if threshold == 0 || threshold as usize > signers.len() {
+            return Err(WitnessError::InvalidThreshold { threshold, signers: signers.len() });
+        }
+        if signers.iter().collect::<HashSet<_>>().len() != signers.len() {
+            return Err(WitnessError::DuplicateSigner);
+        }
+        Ok(Self { threshold, signers })
+    }
+
+    /// Bytes committed to by the stub encoding
+    pub fn encode(&self) -> Vec<u8> {
+        let mut out = vec![self.threshold, self.signers.len() as u8];
+        for s in &self.signers {
+            out.extend_from_slice(s);
+        }
+        out
+    }
+
+    /// Fail unless at least `threshold` of the members are in `signed`
+    pub fn check(&self, signed: &HashSet<[u8;32]>, pubkey: &str) -> Result<(), WitnessError> {
+        let approvals = self.signers.iter().filter(|s| signed.contains(*s)).count();
+        if approvals < self.threshold as usize {
+            return Err(WitnessError::MultisigThreshold { pubkey: pubkey.to_string(), threshold: self.threshold, signers: self.signers.len(), approvals });
+        }
+        Ok(())
+    }
+}
+
+/// (signer pubkey, signature) of `key` over a tx id
+pub fn sign_message(key: &SigningKey, message: &[u8;32]) -> ([u8;32], [u8;64]) {
+    (key.verifying_key().to_bytes(), key.sign(message).to_bytes())
+}
+
+/// Public keys whose signatures over `message` verify; any bad signature is an error
+pub fn verify_signatures(message: &[u8;32], signatures: &[([u8;32], [u8;64])]) -> Result<HashSet<[u8;32]>, WitnessError> {
+    let mut signers = HashSet::new();
+    for (pubkey, sig) in signatures {
+        let key = match VerifyingKey::from_bytes(pubkey) {
+            Ok(k) => k,
+            Err(_) => return Err(WitnessError::InvalidSignerKey(*pubkey)),
+        };
+        if key.verify(message, &Signature::from_bytes(sig)).is_err() {
+            return Err(WitnessError::BadSignature(*pubkey));
+        }
+        signers.insert(*pubkey);
+    }
+    Ok(signers)
+}
+
+impl WitnessTx {
+    /// Add `key`'s signature over the tx id; signatures are not part of the id
+    pub fn sign(&mut self, key: &SigningKey) {
+        let sig = sign_message(key, &self.id());
+        self.signatures.push(sig);
+    }
+
+    /// Public keys whose signatures over the tx id verify; any bad signature fails the tx
+    pub fn verified_signers(&self) -> Result<HashSet<[u8;32]>, WitnessError> {
+        verify_signatures(&self.id(), &self.signatures)
+    }
+}
+
+/// A DAO account whose writes need 2 of 3 council signatures, the second from a wallet
+/// outside the simulator, then its stub and tx round-tripped through Borsh
+pub fn demo(chunk_size: usize) -> Result<()> {
+    let mut chain = ChainState::new();
+    chain.subscribe(Box::new(StdoutSubscriber));
+    chain.register_program(DEMO_PROGRAM_ID, Box::new(DemoProgram));
+    let council: Vec<SigningKey> = (0..3u8).map(|i| SigningKey::from_bytes(&sha256(&[b'c', i]))).collect();
+    let blob = b"treasury config v1".to_vec();
+    let tree = MerkleTree::from_chunks(&chunk_blob(&blob, chunk_size));
+    let mut stub = AccountStub::new(DEMO_PROGRAM_ID, 1_000, tree.root(), blob.len() as u64, chunk_size);
+    stub.multisig = Some(Multisig::new(2, council.iter().map(|k| k.verifying_key().to_bytes()).collect())?);
+    chain.put_stub("Dao1", stub);
+    let mut tx = WitnessTx {
+        program_id: DEMO_PROGRAM_ID.to_string(),
+        instruction_data: Vec::new(),
+        witnesses: vec![AccountWitness { pubkey: "Dao1".to_string(), blob, leaf_index: 0, proof: tree.gen_proof(0), is_writable: true }],
+        recent_blockhash: chain.latest_blockhash(),
+        priority_fee: 0,
+        signatures: Vec::new(),
+    };
+    tx.sign(&council[0]);
+    if chain.process_tx_witness(&tx).is_ok() {
+        bail!("1-of-3 signed update was accepted");
+    }
+    // the second approval comes once the wallet's user agrees
+    if let Err(e) = tx.sign_with(&MockSigner::refusing(council[2].clone())) {
+        println!("Council wallet 2: {}", e);
+    }
+    let wallet = MockSigner::new(council[2].clone());
+    tx.sign_with(&wallet)?;
+    println!("Council wallet 2 approved after {} request(s)", wallet.requests().len());
+    let receipt = chain.process_tx_witness(&tx)?;
+    println!("Dao1 updated with 2 of 3 signatures: {} CU", receipt.compute_units);
+    // what a real program would keep in account data and receive as instruction data
+    let stub = chain.get_stub("Dao1").unwrap();
+    let stub_bytes = borsh::to_vec(stub)?;
+    let tx_bytes = borsh::to_vec(&tx)?;
+    let (stub_back, tx_back): (AccountStub, WitnessTx) = (borsh::from_slice(&stub_bytes)?, borsh::from_slice(&tx_bytes)?);
+    if stub_back.encode() != stub.encode() || tx_back.id() != tx.id() || tx_back.signatures != tx.signatures {
+        bail!("Borsh round trip changed Dao1's stub or transaction");
+    }
+    println!("Borsh: Dao1 stub {} bytes, its update tx {} bytes, both round-trip", stub_bytes.len(), tx_bytes.len());
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn keys() -> Vec<SigningKey> {
+        (0..3u8).map(|i| SigningKey::from_bytes(&sha256(&[b'k', i]))).collect()
+    }
+
+    fn members(keys: &[SigningKey]) -> Vec<[u8;32]> {
+        keys.iter().map(|k| k.verifying_key().to_bytes()).collect()
+    }
+
+    #[test]
+    fn rejects_bad_thresholds_and_duplicates() {
+        let members = members(&keys());
+        assert!(Multisig::new(0, members.clone()).is_err());
+        assert!(Multisig::new(4, members.clone()).is_err());
+        assert!(Multisig::new(2, vec![members[0], members[1], members[0]]).is_err());
+        Multisig::new(3, members).unwrap();
+    }
+
+    #[test]
+    fn rejects_more_signers_than_the_encoding_counts() {
+        let signers: Vec<[u8;32]> = (0..=u8::MAX as usize).map(|i| sha256(&i.to_le_bytes())).collect();
+        assert!(matches!(Multisig::new(1, signers.clone()), Err(WitnessError::TooManySigners(256))));
+        let multisig = Multisig::new(1, signers[1..].to_vec()).unwrap();
+        assert_eq!(multisig.encode()[1], u8::MAX);
+    }
+
+    #[test]
+    fn needs_threshold_distinct_members() {
+        let keys = keys();
+        let multisig = Multisig::new(2, members(&keys)).unwrap();
+        let message = sha256(b"tx");
+        let one = verify_signatures(&message, &[sign_message(&keys[0], &message), sign_message(&keys[0], &message)]).unwrap();
+        assert!(matches!(multisig.check(&one, "Dao"), Err(WitnessError::MultisigThreshold { approvals: 1, .. })));
+        // signatures from outside the set do not count
+        let outsider = SigningKey::from_bytes(&sha256(b"outsider"));
+        let with_outsider = verify_signatures(&message, &[sign_message(&keys[0], &message), sign_message(&outsider, &message)]).unwrap();
+        assert!(multisig.check(&with_outsider, "Dao").is_err());
+        let two = verify_signatures(&message, &[sign_message(&keys[0], &message), sign_message(&keys[2], &message)]).unwrap();
+        multisig.check(&two, "Dao").unwrap();
+    }
+
+    #[test]
+    fn rejects_bad_signature_and_key() {
+        let keys = keys();
+        let message = sha256(b"tx");
+        let (key, sig) = sign_message(&keys[0], &message);
+        let other = sha256(b"other tx");
+        assert!(matches!(verify_signatures(&other, &[(key, sig)]), Err(WitnessError::BadSignature(k)) if k == key));
+        // not every 32 bytes decompress to a curve point
+        let not_a_key = (0..=u8::MAX).map(|b| [b; 32]).find(|k| VerifyingKey::from_bytes(k).is_err()).unwrap();
+        assert!(matches!(verify_signatures(&message, &[(not_a_key, sig)]), Err(WitnessError::InvalidSignerKey(_))));
+    }
+}
@@ -0,0 +1,308 @@
+use std::collections::BTreeMap;
+use std::sync::mpsc::{channel, Receiver, Sender};
+
+use anyhow::{Result, bail};
+
+use crate::error::WitnessError;
+use crate::mempool::Mempool;
+use crate::program::{DemoProgram, DEMO_PROGRAM_ID};
+use crate::rng::SimRng;
+use crate::{chunk_blob, h, AccountStub, AccountWitness, ChainState, MerkleTree, WitnessTx};
+
+/// Knobs of the simulated network
+#[derive(Debug, Clone)]
+pub struct NetworkConfig {
+    pub nodes: usize,
+    /// Per-message delivery delay, uniformly drawn from this inclusive range of ticks
+    pub latency_ticks: (u64, u64),
+    /// Probability that any single message is dropped
+    pub loss_rate: f64,
+    /// The leader produces a block every `slot_ticks` ticks
+    pub slot_ticks: u64,
+    /// Ticks a follower waits on a gap before asking the leader for the missing block
+    pub repair_timeout: u64,
+    pub max_txs_per_block: usize,
+    pub seed: u64,
+}
+
+impl Default for NetworkConfig {
+    fn default() -> Self {
+        Self { nodes: 4, latency_ticks: (1, 3), loss_rate: 0.0, slot_ticks: 4, repair_timeout: 8, max_txs_per_block: 64, seed: 1 }
+    }
+}
+
+/// A block as gossiped by the leader: its txs plus the bank hash followers must reproduce
+#[derive(Debug, Clone)]
+pub struct Block {
+    pub slot: u64,
+    pub txs: Vec<WitnessTx>,
+    pub bank_hash: [u8;32],
+}
+
+#[derive(Debug, Clone)]
+enum Message {
+    Block(Block),
+    /// Leader's latest slot, gossiped every slot so followers notice lag
+    Tip { slot: u64 },
+    RepairRequest { from: usize, slot: u64 },
+}
+
+struct InFlight {
+    deliver_at: u64,
+    to: usize,
+    msg: Message,
+}
+
+struct Node {
+    chain: ChainState,
+    inbox_tx: Sender<Message>,
+    inbox: Receiver<Message>,
+    /// Next slot this node needs to replay
+    next_slot: u64,
+    /// Blocks received ahead of `next_slot`
+    buffered: BTreeMap<u64, Block>,
+    /// Highest slot this node has heard of, from blocks or tip gossip
+    known_tip: Option<u64>,
+    /// Tick at which the current gap was first noticed
+    gap_since: Option<u64>,
+}
+
+/// Counters collected over a run
+#[derive(Debug, Clone, Default)]
+pub struct NetworkStats {
+    pub blocks_produced: u64,
+    pub messages_sent: u64,
+    pub messages_lost: u64,
+    pub repair_requests: u64,
+    /// Blocks whose replayed bank hash differed from the leader's
+    pub divergences: u64,
+}
+
+/// Several `ChainState` replicas exchanging blocks over lossy, delayed channels.
+/// Node 0 is the leader: it owns the mempool, produces blocks and answers repairs;
+/// followers replay blocks in slot order and check they reach the same bank hash.
+pub struct NetworkSim {
+    cfg: NetworkConfig,
+    nodes: Vec<Node>,
+    inflight: Vec<InFlight>,
+    produced: Vec<Block>,
+    mempool: Mempool,
+    rng: SimRng,
+    tick: u64,
+    stats: NetworkStats,
+}
+
+impl NetworkSim {
+    /// `make_node` must build the same genesis state (and programs) every time it is called
+    pub fn new(cfg: NetworkConfig, make_node: impl Fn() -> ChainState) -> Self {
+        let nodes = (0..cfg.nodes.max(1)).map(|_| {
+            let (inbox_tx, inbox) = channel();
+            Node { chain: make_node(), inbox_tx, inbox, next_slot: 0, buffered: BTreeMap::new(), known_tip: None, gap_since: None }
+        }).collect();
+        let rng = SimRng::new(cfg.seed);
+        Self { cfg, nodes, inflight: Vec::new(), produced: Vec::new(), mempool: Mempool::default(), rng, tick: 0, stats: NetworkStats::default() }
+    }
+
+    /// The leader's state (clients read blockhashes and roots from it)
+    pub fn leader(&self) -> &ChainState {
+        &self.nodes[0].chain
+    }
+
+    pub fn node(&self, i: usize) -> &ChainState {
+        &self.nodes[i].chain
+    }
+
+    pub fn stats(&self) -> &NetworkStats {
+        &self.stats
+    }
+
+    /// Hand a tx to the leader's mempool
+    pub fn submit(&mut self, tx: WitnessTx) -> Result<(), WitnessError> {
+        self.mempool.submit(tx)
+    }
+
+    fn send(&mut self, to: usize, msg: Message) {
+        self.stats.messages_sent += 1;
+        if self.rng.chance(self.cfg.loss_rate) {
+            self.stats.messages_lost += 1;
+            return;
+        }
+        let delay = self.rng.range(self.cfg.latency_ticks.0, self.cfg.latency_ticks.1);
+        self.inflight.push(InFlight { deliver_at: self.tick + delay, to, msg });
+    }
+
+    /// Advance one tick: produce a block if due, deliver due messages, let nodes react
+    pub fn step(&mut self) {
+        if self.tick.is_multiple_of(self.cfg.slot_ticks.max(1)) {
+            self.produce();
+        }
+        self.deliver();
+    }
+
+    /// Move due messages into the receivers' channels and let every node handle its inbox
+    fn deliver(&mut self) {
+        let tick = self.tick;
+        if tick.is_multiple_of(self.cfg.slot_ticks.max(1)) {
+            if let Some(latest) = self.produced.last() {
+                let tip = Message::Tip { slot: latest.slot };
+                for to in 1..self.nodes.len() {
+                    self.send(to, tip.clone());
+                }
+            }
+        }
+        let (due, pending): (Vec<_>, Vec<_>) = std::mem::take(&mut self.inflight).into_iter().partition(|m| m.deliver_at <= tick);
+        self.inflight = pending;
+        for m in due {
+            let _ = self.nodes[m.to].inbox_tx.send(m.msg);
+        }
+        for i in 0..self.nodes.len() {
+            self.drain(i);
+        }
+        self.tick += 1;
+    }
+
+    pub fn run(&mut self, ticks: u64) {
+        for _ in 0..ticks {
+            self.step();
+        }
+    }
+
+    fn produce(&mut self) {
+        let slot = self.nodes[0].chain.slot;
+        let txs = self.mempool.take_block(self.cfg.max_txs_per_block);
+        let leader = &mut self.nodes[0];
+        for tx in &txs {
+            let _ = leader.chain.process_tx_witness(tx);
+        }
+        leader.chain.advance_slot();
+        leader.next_slot = slot + 1;
+        let bank_hash = leader.chain.bank_header(slot).unwrap().hash();
+        let block = Block { slot, txs, bank_hash };
+        self.produced.push(block.clone());
+        self.stats.blocks_produced += 1;
+        for to in 1..self.nodes.len() {
+            self.send(to, Message::Block(block.clone()));
+        }
+    }
+
+    fn drain(&mut self, i: usize) {
+        let msgs: Vec<Message> = self.nodes[i].inbox.try_iter().collect();
+        for msg in msgs {
+            match msg {
+                Message::Block(block) => {
+                    let node = &mut self.nodes[i];
+                    node.known_tip = node.known_tip.max(Some(block.slot));
+                    if block.slot >= node.next_slot {
+                        node.buffered.insert(block.slot, block);
+                    }
+                }
+                Message::Tip { slot } => {
+                    let node = &mut self.nodes[i];
+                    node.known_tip = node.known_tip.max(Some(slot));
+                }
+                Message::RepairRequest { from, slot } => {
+                    if let Some(block) = self.produced.get(slot as usize).cloned() {
+                        self.send(from, Message::Block(block));
+                    }
+                }
+            }
+        }
+        if i == 0 {
+            return;
+        }
+        self.replay(i);
+        // ask for the missing block once a gap has lasted long enough
+        let tick = self.tick;
+        let node = &mut self.nodes[i];
+        let has_gap = node.known_tip.is_some_and(|tip| tip >= node.next_slot);
+        let want = node.next_slot;
+        match (has_gap, node.gap_since) {
+            (false, _) => node.gap_since = None,
+            (true, None) => node.gap_since = Some(tick),
+            (true, Some(since)) if tick - since >= self.cfg.repair_timeout => {
+                node.gap_since = Some(tick);
+                self.stats.repair_requests += 1;
+                self.send(0, Message::RepairRequest { from: i, slot: want });
+            }
+            _ => {}
+        }
+    }
+
+    /// Apply buffered blocks that are next in line
+    fn replay(&mut self, i: usize) {
+        let node = &mut self.nodes[i];
+        while let Some(block) = node.buffered.remove(&node.next_slot) {
+            for tx in &block.txs {
+                let _ = node.chain.process_tx_witness(tx);
+            }
+            node.chain.advance_slot();
+            let replayed = node.chain.bank_header(block.slot).unwrap().hash();
+            if replayed != block.bank_hash {
+                self.stats.divergences += 1;
+            }
+            node.next_slot += 1;
+        }
+    }
+
+    /// True once every node has replayed every produced block to the same bank hash
+    pub fn converged(&self) -> bool {
+        let latest = match self.produced.last() {
+            Some(b) => b,
+            None => return true,
+        };
+        self.stats.divergences == 0 && self.nodes.iter().all(|n| {
+            n.chain.bank_header(latest.slot).map(|b| b.hash()) == Some(latest.bank_hash)
+        })
+    }
+
+    /// Run until converged (producing no new blocks) or `max_ticks` elapse; returns whether it converged
+    pub fn settle(&mut self, max_ticks: u64) -> bool {
+        for _ in 0..max_ticks {
+            if self.converged() {
+                return true;
+            }
+            self.deliver();
+        }
+        self.converged()
+    }
+}
+
+/// Four replicas over a lossy link, each starting from Acct1 holding `blob`: followers
+/// replay the leader's blocks and must agree
+pub fn demo(blob: &[u8], chunk_size: usize) -> Result<()> {
+    let genesis_root = MerkleTree::from_chunks(&chunk_blob(blob, chunk_size)).root();
+    let genesis_len = blob.len() as u64;
+    let mut net = NetworkSim::new(NetworkConfig { nodes: 4, latency_ticks: (1, 5), loss_rate: 0.2, seed: 7, ..NetworkConfig::default() }, || {
+        let mut node = ChainState::new();
+        node.register_program(DEMO_PROGRAM_ID, Box::new(DemoProgram));
+        node.put_stub("Acct1", AccountStub::new(DEMO_PROGRAM_ID, 1_000, genesis_root, genesis_len, chunk_size));
+        node
+    });
+    let mut net_blob = blob.to_vec();
+    for _ in 0..5 {
+        let tree = MerkleTree::from_chunks(&chunk_blob(&net_blob, chunk_size));
+        net.submit(WitnessTx {
+            program_id: DEMO_PROGRAM_ID.to_string(),
+            instruction_data: Vec::new(),
+            witnesses: vec![AccountWitness { pubkey: "Acct1".to_string(), blob: net_blob.clone(), leaf_index: 0, proof: tree.gen_proof(0), is_writable: true }],
+            recent_blockhash: net.leader().latest_blockhash(),
+            priority_fee: 0,
+            signatures: Vec::new(),
+        })?;
+        net.run(NetworkConfig::default().slot_ticks);
+        net_blob[0] = net_blob[0].wrapping_add(1);
+    }
+    let converged = net.settle(200);
+    let stats = net.stats();
+    println!("\nNetwork sim: {} blocks, {} msgs sent, {} lost, {} repairs, converged: {}",
+        stats.blocks_produced, stats.messages_sent, stats.messages_lost, stats.repair_requests, converged);
+    if !converged {
+        bail!("replicas did not converge");
+    }
+    let follower = net.node(3).get_stub("Acct1").unwrap().merkle_root;
+    println!("Follower 3 holds Acct1 at {} (same as the leader: {})", h(&follower), net.leader().get_stub("Acct1").unwrap().merkle_root == follower);
+    let leaves = net.leader().leaf_cache.stats();
+    println!("Leader leaf cache: {} hits, {} misses, {} leaf hashes reused, {} recomputed",
+        leaves.hits, leaves.misses, leaves.leaves_reused, leaves.leaves_hashed);
+    Ok(())
+}
@@ -0,0 +1,317 @@
+use std::collections::{BTreeMap, HashMap};
+
+use anyhow::Result;
+
+use crate::archive::ArchiveNode;
+use crate::compute::ComputeMeter;
+use crate::error::WitnessError;
+use crate::events::WitnessEvent;
+use crate::program::InvokeContext;
+use crate::{chunk_blob, sha256, AccountWitness, ChainState, MerkleTree, TxReceipt};
+
+/// Slots during which an optimistic update can still be challenged
+pub const CHALLENGE_WINDOW: u64 = 32;
+
+/// Share of a slashed stake paid to the successful challenger (the rest is burned)
+pub const CHALLENGER_REWARD_PCT: u64 = 50;
+
+/// A root update accepted without verification, claimed correct by a staked provider:
+/// "running `program_id` with `instruction_data` on the blob committed by `old_root`
+/// produces the blob committed by `new_root`"
+#[derive(Debug, Clone)]
+pub struct OptimisticUpdate {
+    pub pubkey: String,
+    pub provider: String,
+    pub program_id: String,
+    pub instruction_data: Vec<u8>,
+    pub old_root: [u8;32],
+    pub new_root: [u8;32],
+    pub new_data_len: u64,
+    pub submitted_slot: u64,
+}
+
+/// Evidence that an optimistic update is wrong: the full pre-state blob (checked
+/// against `old_root`) and one chunk of the claimed post-state, with its proof against
+/// `new_root`, that differs from what re-executing the program yields
+#[derive(Debug, Clone)]
+pub struct FraudProof {
+    pub old_blob: Vec<u8>,
+    pub leaf_index: usize,
+    pub claimed_chunk: Vec<u8>,
+    pub claimed_proof: Vec<([u8;32], bool)>,
+}
+
+/// Provider stakes and updates still inside their challenge window
+#[derive(Debug, Default)]
+pub struct OptimisticState {
+    pub stakes: HashMap<String, u64>,
+    pub rewards: HashMap<String, u64>,
+    pending: BTreeMap<u64, OptimisticUpdate>,
+    next_id: u64,
+}
+
+impl OptimisticState {
+    pub fn pending(&self, id: u64) -> Option<&OptimisticUpdate> {
+        self.pending.get(&id)
+    }
+}
+
+impl ChainState {
+    /// Lock `lamports` of stake for `provider`, required before posting optimistic updates
+    pub fn stake(&mut self, provider: &str, lamports: u64) {
+        *self.optimistic.stakes.entry(provider.to_string()).or_default() += lamports;
+    }
+
+    /// Accept `update` without checking any proof. The stub's root changes immediately;
+    /// the update stays challengeable for `CHALLENGE_WINDOW` slots. Returns the update id.
+    pub fn submit_optimistic(&mut self, update: OptimisticUpdate) -> Result<(u64, TxReceipt), WitnessError> {
+        if self.optimistic.stakes.get(&update.provider).copied().unwrap_or(0) == 0 {
+            return Err(WitnessError::NoStake(update.provider.clone()));
+        }
+        let mut stub = match self.get_stub(&update.pubkey) {
+            Some(s) => s.clone(),
+            None => return Err(WitnessError::UnknownAccount(update.pubkey.clone())),
+        };
+        if stub.owner != update.program_id {
+            return Err(WitnessError::NotOwner { program: update.program_id.clone(), pubkey: update.pubkey.clone(), owner: stub.owner.clone() });
+        }
+        if stub.merkle_root != update.old_root {
+            return Err(WitnessError::StaleOptimisticUpdate { stub: stub.merkle_root, update: update.old_root });
+        }
+        // only the two roots are copied, nothing is hashed
+        let mut meter = ComputeMeter::new(self.compute_budget);
+        meter.consume(self.compute_costs.mem_copy(64), "root copy")?;
+
+        stub.merkle_root = update.new_root;
+        stub.data_len = update.new_data_len;
+        self.put_stub(&update.pubkey, stub);
+        self.emit(WitnessEvent::RootUpdated { pubkey: update.pubkey.clone(), slot: self.slot, old: update.old_root, new: update.new_root });
+
+        let id = self.optimistic.next_id;
+        self.optimistic.next_id += 1;
+        let receipt = TxReceipt { compute_units: meter.consumed(), new_roots: vec![(update.pubkey.clone(), update.new_root)], frontiers: Vec::new() };
+        let mut update = update;
+        update.submitted_slot = self.slot;
+        self.optimistic.pending.insert(id, update);
+        Ok((id, receipt))
+    }
+
+    /// Challenge pending update `id`. If the fraud proof holds, the provider's stake is
+    /// slashed (part of it paid to `challenger`), the account reverts to `old_root`, and
+    /// later pending updates to the same account are dropped since they built on bad state.
+    /// Returns the slashed amount.
+    pub fn challenge(&mut self, id: u64, challenger: &str, fraud: &FraudProof) -> Result<u64, WitnessError> {
+        let update = match self.optimistic.pending.get(&id) {
+            Some(u) => u.clone(),
+            None => return Err(WitnessError::NoPendingUpdate(id)),
+        };
+        // pre-state as it was before the update; the layout never changes with the root
+        let stub = {
+            let mut s = match self.get_stub(&update.pubkey) {
+                Some(s) => s.clone(),
+                None => return Err(WitnessError::UnknownAccount(update.pubkey.clone())),
+            };
+            s.merkle_root = update.old_root;
+            s
+        };
+        let chunk_size = stub.chunk_size;
+        // the pre-state must be exactly what the update claimed to start from
+        let old_tree = MerkleTree::from_chunks(&chunk_blob(&fraud.old_blob, chunk_size));
+        if old_tree.root() != update.old_root {
+            return Err(WitnessError::FraudPreStateMismatch(update.old_root));
+        }
+        // the claimed chunk must really sit at `leaf_index` under the posted new root
+        let new_chunks = (update.new_data_len as usize).div_ceil(chunk_size).max(1);
+        witness_core::check_proof_shape(&fraud.claimed_proof, fraud.leaf_index, new_chunks)?;
+        if !MerkleTree::verify_proof(&fraud.claimed_chunk, &fraud.claimed_proof, &update.new_root) {
+            return Err(WitnessError::LeafNotCommitted { leaf_index: fraud.leaf_index, root: update.new_root });
+        }
+
+        // re-execute honestly and compare the leaf at the challenged index
+        let witnesses = [AccountWitness {
+            pubkey: update.pubkey.clone(),
+            blob: fraud.old_blob.clone(),
+            leaf_index: 0,
+            proof: Vec::new(),
+            is_writable: true,
+        }];
+        let stubs = [stub];
+        let meter = ComputeMeter::new(u64::MAX);
+        let mut ctx = InvokeContext::new(&self.programs, &witnesses, &stubs, self.compute_costs.clone(), meter);
+        ctx.invoke(&update.program_id, &[update.pubkey.as_str()], &update.instruction_data)?;
+        let (writes, _) = ctx.into_writes();
+        let expected_blob = writes.into_iter().next().map(|(_, b)| b).unwrap_or_else(|| fraud.old_blob.clone());
+        let expected_tree = MerkleTree::from_chunks(&chunk_blob(&expected_blob, chunk_size));
+        if expected_tree.leaves().get(fraud.leaf_index) == Some(&sha256(&fraud.claimed_chunk)) {
+            return Err(WitnessError::ChallengeRejected { id, leaf_index: fraud.leaf_index });
+        }
+
+        // fraud proven: slash, reward, revert
+        let slashed = self.optimistic.stakes.remove(&update.provider).unwrap_or(0);
+        let reward = slashed * CHALLENGER_REWARD_PCT / 100;
+        *self.optimistic.rewards.entry(challenger.to_string()).or_default() += reward;
+        self.optimistic.pending.retain(|&other, u| other < id || u.pubkey != update.pubkey);
+        if let Some(mut stub) = self.get_stub(&update.pubkey).cloned() {
+            let bad_root = stub.merkle_root;
+            stub.merkle_root = update.old_root;
+            stub.data_len = fraud.old_blob.len() as u64;
+            self.put_stub(&update.pubkey, stub);
+            self.emit(WitnessEvent::RootUpdated { pubkey: update.pubkey.clone(), slot: self.slot, old: bad_root, new: update.old_root });
+        }
+        self.emit(WitnessEvent::FraudProven { pubkey: update.pubkey.clone(), provider: update.provider.clone(), slashed });
+        Ok(slashed)
+    }
+
+    /// Drop updates whose challenge window has passed; they are final
+    pub fn finalize_optimistic(&mut self) {
+        let slot = self.slot;
+        self.optimistic.pending.retain(|_, u| slot <= u.submitted_slot + CHALLENGE_WINDOW);
+    }
+}
+
+/// A staked provider posts a bogus root for `pubkey`, and a challenger proves it wrong
+/// from the current blob in `archive`
+pub fn demo(chain: &mut ChainState, archive: &ArchiveNode, pubkey: &str) -> Result<()> {
+    println!("\nOptimistic update with a bad root...");
+    let stub = chain.get_stub(pubkey).unwrap().clone();
+    let chunk_size = stub.chunk_size;
+    let current_blob = archive.get_blob(pubkey, &stub.merkle_root).unwrap().data;
+    let mut bogus_blob = current_blob.clone();
+    bogus_blob[40] ^= 0xff;
+    let bogus_tree = MerkleTree::from_chunks(&chunk_blob(&bogus_blob, chunk_size));
+    chain.stake("provider_1", 5_000);
+    let (update_id, _) = chain.submit_optimistic(OptimisticUpdate {
+        pubkey: pubkey.to_string(),
+        provider: "provider_1".to_string(),
+        program_id: stub.owner.clone(),
+        instruction_data: Vec::new(),
+        old_root: stub.merkle_root,
+        new_root: bogus_tree.root(),
+        new_data_len: bogus_blob.len() as u64,
+        submitted_slot: 0,
+    })?;
+    let bad_leaf = 40 / chunk_size;
+    let was_pending = chain.optimistic.pending(update_id).is_some();
+    chain.challenge(update_id, "challenger_1", &FraudProof {
+        old_blob: current_blob,
+        leaf_index: bad_leaf,
+        claimed_chunk: chunk_blob(&bogus_blob, chunk_size)[bad_leaf].to_vec(),
+        claimed_proof: bogus_tree.gen_proof(bad_leaf),
+    })?;
+    println!("Update {} pending before the fraud proof: {}, after: {}", update_id, was_pending, chain.optimistic.pending(update_id).is_some());
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::program::{DemoProgram, DEMO_PROGRAM_ID};
+    use crate::AccountStub;
+
+    const CHUNK: usize = 32;
+
+    fn blob() -> Vec<u8> {
+        (0..96u8).collect()
+    }
+
+    fn chain() -> ChainState {
+        let mut chain = ChainState::new();
+        chain.register_program(DEMO_PROGRAM_ID, Box::new(DemoProgram));
+        let root = MerkleTree::from_chunks(&chunk_blob(&blob(), CHUNK)).root();
+        chain.put_stub("Acct", AccountStub::new(DEMO_PROGRAM_ID, 0, root, 96, CHUNK));
+        chain.stake("provider", 1_000);
+        chain
+    }
+
+    /// The demo program bumps byte 0; `honest` is what it really yields
+    fn post_state(honest: bool) -> Vec<u8> {
+        let mut blob = blob();
+        if honest { blob[0] += 1 } else { blob[40] ^= 0xff }
+        blob
+    }
+
+    fn update(chain: &ChainState, new_blob: &[u8]) -> OptimisticUpdate {
+        OptimisticUpdate {
+            pubkey: "Acct".to_string(),
+            provider: "provider".to_string(),
+            program_id: DEMO_PROGRAM_ID.to_string(),
+            instruction_data: Vec::new(),
+            old_root: chain.get_stub("Acct").unwrap().merkle_root,
+            new_root: MerkleTree::from_chunks(&chunk_blob(new_blob, CHUNK)).root(),
+            new_data_len: new_blob.len() as u64,
+            submitted_slot: 0,
+        }
+    }
+
+    fn fraud(new_blob: &[u8], leaf_index: usize) -> FraudProof {
+        FraudProof {
+            old_blob: blob(),
+            leaf_index,
+            claimed_chunk: chunk_blob(new_blob, CHUNK)[leaf_index].to_vec(),
+            claimed_proof: MerkleTree::from_chunks(&chunk_blob(new_blob, CHUNK)).gen_proof(leaf_index),
+        }
+    }
+
+    #[test]
+    fn a_proven_fraud_slashes_and_reverts() {
+        let mut chain = chain();
+        let old_root = chain.get_stub("Acct").unwrap().merkle_root;
+        let bogus = post_state(false);
+        let (id, _) = chain.submit_optimistic(update(&chain, &bogus)).unwrap();
+        assert_eq!(chain.get_stub("Acct").unwrap().merkle_root, MerkleTree::from_chunks(&chunk_blob(&bogus, CHUNK)).root());
+        // a later update built on the bad state goes with it
+        let (later, _) = chain.submit_optimistic(update(&chain, &blob())).unwrap();
+        assert_eq!(chain.challenge(id, "challenger", &fraud(&bogus, 1)).unwrap(), 1_000);
+        assert_eq!(chain.get_stub("Acct").unwrap().merkle_root, old_root);
+        assert_eq!(chain.optimistic.rewards["challenger"], 1_000 * CHALLENGER_REWARD_PCT / 100);
+        assert!(!chain.optimistic.stakes.contains_key("provider"));
+        assert!(chain.optimistic.pending(id).is_none() && chain.optimistic.pending(later).is_none());
+        assert!(matches!(chain.challenge(id, "challenger", &fraud(&bogus, 1)), Err(WitnessError::NoPendingUpdate(_))));
+    }
+
+    #[test]
+    fn an_honest_update_survives_challenges() {
+        let mut chain = chain();
+        let honest = post_state(true);
+        let (id, _) = chain.submit_optimistic(update(&chain, &honest)).unwrap();
+        assert!(matches!(chain.challenge(id, "challenger", &fraud(&honest, 0)), Err(WitnessError::ChallengeRejected { leaf_index: 0, .. })));
+        let mut wrong_pre = fraud(&honest, 0);
+        wrong_pre.old_blob[95] ^= 1;
+        assert!(matches!(chain.challenge(id, "challenger", &wrong_pre), Err(WitnessError::FraudPreStateMismatch(_))));
+        let mut uncommitted = fraud(&honest, 1);
+        uncommitted.claimed_chunk[0] ^= 1;
+        assert!(matches!(chain.challenge(id, "challenger", &uncommitted), Err(WitnessError::LeafNotCommitted { leaf_index: 1, .. })));
+        assert_eq!(chain.optimistic.stakes["provider"], 1_000);
+        assert!(chain.optimistic.pending(id).is_some());
+    }
+
+    #[test]
+    fn rejects_unstaked_stale_and_foreign_updates() {
+        let mut chain = chain();
+        let new_blob = post_state(true);
+        let mut unstaked = update(&chain, &new_blob);
+        unstaked.provider = "nobody".to_string();
+        assert!(matches!(chain.submit_optimistic(unstaked), Err(WitnessError::NoStake(_))));
+        let mut foreign = update(&chain, &new_blob);
+        foreign.program_id = "Other".to_string();
+        assert!(matches!(chain.submit_optimistic(foreign), Err(WitnessError::NotOwner { .. })));
+        let stale = update(&chain, &new_blob);
+        chain.submit_optimistic(stale.clone()).unwrap();
+        assert!(matches!(chain.submit_optimistic(stale), Err(WitnessError::StaleOptimisticUpdate { .. })));
+    }
+
+    #[test]
+    fn updates_past_their_window_are_final() {
+        let mut chain = chain();
+        let bogus = post_state(false);
+        let (id, _) = chain.submit_optimistic(update(&chain, &bogus)).unwrap();
+        for _ in 0..CHALLENGE_WINDOW {
+            chain.advance_slot();
+        }
+        chain.finalize_optimistic();
+        assert!(chain.optimistic.pending(id).is_some());
+        chain.advance_slot();
+        chain.finalize_optimistic();
+        assert!(matches!(chain.challenge(id, "challenger", &fraud(&bogus, 1)), Err(WitnessError::NoPendingUpdate(_))));
+    }
+}
@@ -0,0 +1,365 @@
+use std::collections::{BTreeSet, HashMap};
+
+use anyhow::Result;
+use witness_core::StreamingVerifier;
+
+use crate::error::WitnessError;
+use crate::view::ChunkWitness;
+use crate::{chunk_blob, sha256, MerkleTree};
+
+/// Largest serialized transaction a validator accepts
+pub const MAX_TX_SIZE: usize = 1232;
+/// Bytes of a packed transaction that are not instruction data: one signature, the
+/// message header, payer, session account and program keys, the blockhash and the
+/// instruction's own header
+pub const TX_OVERHEAD: usize = 1 + 64 + 3 + 1 + 3 * 32 + 32 + 1 + 1 + 3 + 3;
+
+/// session id, sequence number, part count
+const HEADER_LEN: usize = 32 + 4 + 1;
+/// Largest chunk a packed transaction can carry, the chunk part's own header included
+pub const MAX_PACKED_CHUNK: usize = MAX_TX_SIZE - TX_OVERHEAD - HEADER_LEN - (1 + 4 + 2);
+
+const PART_CHUNK: u8 = 0;
+const PART_STEPS: u8 = 1;
+
+/// A piece of one witness. A chunk starts that leaf's accumulator; steps fold more of
+/// its proof in, from `from_level` up.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Part {
+    Chunk { leaf_index: usize, chunk: Vec<u8> },
+    Steps { leaf_index: usize, from_level: usize, steps: Vec<([u8;32], bool)> },
+}
+
+impl Part {
+    fn encoded_len(&self) -> usize {
+        match self {
+            Part::Chunk { chunk, .. } => 1 + 4 + 2 + chunk.len(),
+            Part::Steps { steps, .. } => 1 + 4 + 1 + 1 + steps.len() * 33,
+        }
+    }
+}
+
+/// Instruction data of one transaction in a session
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PackedTx {
+    pub session: [u8;32],
+    pub seq: u32,
+    pub parts: Vec<Part>,
+}
+
+impl PackedTx {
+    pub fn encode(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(HEADER_LEN + self.parts.iter().map(Part::encoded_len).sum::<usize>());
+        out.extend_from_slice(&self.session);
+        out.extend_from_slice(&self.seq.to_le_bytes());
+        out.push(self.parts.len() as u8);
+        for part in &self.parts {
+            match part {
+                Part::Chunk { leaf_index, chunk } => {
+                    out.push(PART_CHUNK);
+                    out.extend_from_slice(&(*leaf_index as u32).to_le_bytes());
+                    out.extend_from_slice(&(chunk.len() as u16).to_le_bytes());
+                    out.extend_from_slice(chunk);
+                }
+                Part::Steps { leaf_index, from_level, steps } => {
+                    out.push(PART_STEPS);
+                    out.extend_from_slice(&(*leaf_index as u32).to_le_bytes());
+                    out.push(*from_level as u8);
+                    out.push(steps.len() as u8);
+                    out.extend_from_slice(&witness_core::encode_steps(steps));
+                }
+            }
+        }
+        out
+    }
+
+    pub fn decode(bytes: &[u8]) -> Result<Self, WitnessError> {
+        fn take<'a>(bytes: &mut &'a [u8], n: usize) -> Result<&'a [u8], WitnessError> {
+            if bytes.len() < n {
+                return Err(WitnessError::Truncated("packed transaction"));
+            }
+            let (head, rest) = bytes.split_at(n);
+            *bytes = rest;
+            Ok(head)
+        }
+        let mut b = bytes;
+        let session = take(&mut b, 32)?.try_into().unwrap();
+        let seq = u32::from_le_bytes(take(&mut b, 4)?.try_into().unwrap());
+        let count = take(&mut b, 1)?[0];
+        let mut parts = Vec::with_capacity(count as usize);
+        for _ in 0..count {
+            let tag = take(&mut b, 1)?[0];
+            let leaf_index = u32::from_le_bytes(take(&mut b, 4)?.try_into().unwrap()) as usize;
+            parts.push(match tag {
+                PART_CHUNK => {
+                    let len = u16::from_le_bytes(take(&mut b, 2)?.try_into().unwrap()) as usize;
+                    Part::Chunk { leaf_index, chunk: take(&mut b, len)?.to_vec() }
+                }
+                PART_STEPS => {
+                    let from_level = take(&mut b, 1)?[0] as usize;
+                    let n = take(&mut b, 1)?[0] as usize;
+                    let steps = match witness_core::decode_steps(take(&mut b, n * 33)?) {
+                        Some(steps) => steps,
+                        None => return Err(WitnessError::Malformed("malformed proof steps".to_string())),
+                    };
+                    Part::Steps { leaf_index, from_level, steps }
+                }
+                other => return Err(WitnessError::Malformed(format!("unknown part tag {}", other))),
+            });
+        }
+        if !b.is_empty() {
+            return Err(WitnessError::TrailingBytes { what: "packed transaction", len: b.len() });
+        }
+        Ok(Self { session, seq, parts })
+    }
+
+    /// Size on the wire, signature and message framing included
+    pub fn wire_size(&self) -> usize {
+        TX_OVERHEAD + self.encode().len()
+    }
+}
+
+/// Splits witnesses across as few transactions as fit under `max_tx_size`, cutting a
+/// proof between transactions wherever it runs out of room
+#[derive(Debug, Clone)]
+pub struct Packer {
+    pub max_tx_size: usize,
+}
+
+impl Default for Packer {
+    fn default() -> Self {
+        Self { max_tx_size: MAX_TX_SIZE }
+    }
+}
+
+impl Packer {
+    pub fn pack(&self, session: [u8;32], witnesses: &[ChunkWitness]) -> Result<Vec<PackedTx>, WitnessError> {
+        let capacity = self.max_tx_size.saturating_sub(TX_OVERHEAD + HEADER_LEN);
+        let steps_room = |free: usize| free.saturating_sub(1 + 4 + 1 + 1) / 33;
+        if steps_room(capacity) == 0 {
+            return Err(WitnessError::TxTooSmall(self.max_tx_size));
+        }
+        if witnesses.is_empty() {
+            return Ok(Vec::new());
+        }
+        let mut txs = vec![PackedTx { session, seq: 0, parts: Vec::new() }];
+        let mut used = 0;
+        let push = |txs: &mut Vec<PackedTx>, used: &mut usize, part: Part| {
+            let len = part.encoded_len();
+            if *used + len > capacity || txs.last().unwrap().parts.len() == u8::MAX as usize {
+                let seq = txs.len() as u32;
+                txs.push(PackedTx { session, seq, parts: Vec::new() });
+                *used = 0;
+            }
+            *used += len;
+            txs.last_mut().unwrap().parts.push(part);
+        };
+        for w in witnesses {
+            let chunk = Part::Chunk { leaf_index: w.leaf_index, chunk: w.chunk.clone() };
+            if chunk.encoded_len() > capacity {
+                return Err(WitnessError::ChunkTooLarge { leaf_index: w.leaf_index, len: w.chunk.len() });
+            }
+            push(&mut txs, &mut used, chunk);
+            let mut level = 0;
+            while level < w.proof.len() {
+                // fill what is left of this transaction, or start the next one
+                let room = match steps_room(capacity - used) {
+                    0 => steps_room(capacity),
+                    n => n,
+                };
+                let n = room.min(w.proof.len() - level).min(u8::MAX as usize);
+                push(&mut txs, &mut used, Part::Steps { leaf_index: w.leaf_index, from_level: level, steps: w.proof[level..level + n].to_vec() });
+                level += n;
+            }
+        }
+        Ok(txs)
+    }
+}
+
+/// On-chain side of a session: one running hash per leaf, carried from transaction to
+/// transaction. A leaf whose path reaches the root is checked there and then, so a bad
+/// chunk or step fails the transaction that completes it.
+#[derive(Debug, Clone)]
+pub struct Session {
+    pub id: [u8;32],
+    root: [u8;32],
+    leaf_count: usize,
+    depth: usize,
+    next_seq: u32,
+    /// Leaves whose proof is not complete yet
+    pending: HashMap<usize, StreamingVerifier>,
+    verified: BTreeSet<usize>,
+}
+
+impl Session {
+    /// A session proving leaves of a tree over `leaf_count` chunks under `root`. Its
+    /// depth follows from the count, and leaves past the last chunk are padding, which
+    /// a proof could reach but no chunk is.
+    pub fn new(id: [u8;32], root: [u8;32], leaf_count: usize) -> Self {
+        let depth = witness_core::tree_depth(leaf_count);
+        Self { id, root, leaf_count, depth, next_seq: 0, pending: HashMap::new(), verified: BTreeSet::new() }
+    }
+
+    /// Apply one transaction; on error the session is left as it was
+    pub fn apply(&mut self, tx: &PackedTx) -> Result<(), WitnessError> {
+        if tx.session != self.id {
+            return Err(WitnessError::WrongSession { session: self.id, tx: tx.session });
+        }
+        if tx.seq != self.next_seq {
+            return Err(WitnessError::SessionOutOfOrder { session: self.id, expected: self.next_seq, got: tx.seq });
+        }
+        let mut next = self.clone();
+        for part in &tx.parts {
+            next.apply_part(part)?;
+        }
+        next.next_seq += 1;
+        *self = next;
+        Ok(())
+    }
+
+    fn apply_part(&mut self, part: &Part) -> Result<(), WitnessError> {
+        match part {
+            Part::Chunk { leaf_index, chunk } => {
+                if self.verified.contains(leaf_index) || self.pending.contains_key(leaf_index) {
+                    return Err(WitnessError::LeafSentTwice(*leaf_index));
+                }
+                if *leaf_index >= self.leaf_count {
+                    return Err(WitnessError::ProofIndexOutOfRange { index: *leaf_index, chunks: self.leaf_count });
+                }
+                let verifier = StreamingVerifier::new(*leaf_index, chunk);
+                if self.depth > 0 {
+                    self.pending.insert(*leaf_index, verifier);
+                } else if verifier.finish(0, &self.root) {
+                    // a one-chunk tree: the leaf hash is the root
+                    self.verified.insert(*leaf_index);
+                } else {
+                    return Err(WitnessError::LeafNotCommitted { leaf_index: *leaf_index, root: self.root });
+                }
+            }
+            Part::Steps { leaf_index, from_level, steps } => {
+                let mut verifier = match self.pending.get(leaf_index) {
+                    Some(v) => *v,
+                    None => return Err(WitnessError::StepsBeforeChunk(*leaf_index)),
+                };
+                if *from_level != verifier.level() {
+                    return Err(WitnessError::StepLevelMismatch { leaf_index: *leaf_index, level: verifier.level(), from_level: *from_level });
+                }
+                if verifier.level() + steps.len() > self.depth {
+                    return Err(WitnessError::ProofLength { pubkey: format!("leaf {}", leaf_index), expected: self.depth, actual: verifier.level() + steps.len() });
+                }
+                for (sibling, is_left) in steps {
+                    if !verifier.push(sibling, *is_left) {
+                        return Err(WitnessError::StepDirection { leaf_index: *leaf_index, level: verifier.level() });
+                    }
+                }
+                if verifier.level() < self.depth {
+                    self.pending.insert(*leaf_index, verifier);
+                    return Ok(());
+                }
+                if !verifier.finish(self.depth, &self.root) {
+                    return Err(WitnessError::LeafNotCommitted { leaf_index: *leaf_index, root: self.root });
+                }
+                self.pending.remove(leaf_index);
+                self.verified.insert(*leaf_index);
+            }
+        }
+        Ok(())
+    }
+
+    /// Leaves fully proven so far
+    pub fn verified(&self) -> &BTreeSet<usize> {
+        &self.verified
+    }
+
+    /// Close the session: every leaf that was started must have reached the root
+    pub fn finish(self) -> Result<BTreeSet<usize>, WitnessError> {
+        if let Some(&leaf) = self.pending.keys().min() {
+            return Err(WitnessError::SessionIncomplete { session: self.id, leaf });
+        }
+        Ok(self.verified)
+    }
+}
+
+/// Pack three witnesses of a 256 KiB account into a session, then replay it with one
+/// proof step forged
+pub fn demo() -> Result<()> {
+    let blob: Vec<u8> = (0..256 * 1024u32).map(|i| (i.wrapping_mul(7) % 251) as u8).collect();
+    let chunks = chunk_blob(&blob, 256);
+    let tree = MerkleTree::from_chunks(&chunks);
+    let witnesses: Vec<ChunkWitness> = [3, 500, 1023].iter()
+        .map(|&i| ChunkWitness { pubkey: "BigAcct".to_string(), leaf_index: i, chunk: chunks[i].to_vec(), proof: tree.gen_proof(i) })
+        .collect();
+    let session_id = sha256(b"pack demo");
+    let packed = Packer::default().pack(session_id, &witnesses)?;
+    let mut session = Session::new(session_id, tree.root(), chunks.len());
+    for tx in &packed {
+        session.apply(&PackedTx::decode(&tx.encode())?)?;
+    }
+    println!("Packed 3 witnesses (depth {}) into {} txs of {:?} bytes; verified leaves {:?}",
+        tree.depth(), packed.len(), packed.iter().map(PackedTx::wire_size).collect::<Vec<_>>(), session.finish()?);
+    let mut forged = packed.clone();
+    if let Some(Part::Steps { steps, .. }) = forged.last_mut().and_then(|tx| tx.parts.last_mut()) {
+        steps[0].0[0] ^= 1;
+    }
+    let mut session = Session::new(session_id, tree.root(), chunks.len());
+    match forged.iter().try_for_each(|tx| session.apply(tx)) {
+        Ok(()) => println!("Forged step accepted?!"),
+        Err(e) => println!("Forged step rejected once leaves {:?} had verified: {}", session.verified(), e),
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Five 64-byte chunks, so the tree pads to eight leaves
+    fn witnesses() -> (Vec<ChunkWitness>, [u8;32], usize) {
+        let blob: Vec<u8> = (0..300u32).map(|i| (i * 3) as u8).collect();
+        let chunks = chunk_blob(&blob, 64);
+        let tree = MerkleTree::from_chunks(&chunks);
+        let witnesses = (0..chunks.len()).map(|i| ChunkWitness { pubkey: "A".to_string(), leaf_index: i, chunk: chunks[i].to_vec(), proof: tree.gen_proof(i) }).collect();
+        (witnesses, tree.root(), chunks.len())
+    }
+
+    #[test]
+    fn packs_and_verifies_across_small_transactions() {
+        let (witnesses, root, count) = witnesses();
+        let packer = Packer { max_tx_size: TX_OVERHEAD + HEADER_LEN + 80 };
+        let packed = packer.pack([1; 32], &witnesses).unwrap();
+        assert!(packed.len() > witnesses.len());
+        let mut session = Session::new([1; 32], root, count);
+        for tx in &packed {
+            assert!(tx.wire_size() <= packer.max_tx_size);
+            session.apply(&PackedTx::decode(&tx.encode()).unwrap()).unwrap();
+        }
+        assert_eq!(session.finish().unwrap(), (0..count).collect());
+    }
+
+    #[test]
+    fn rejects_padding_leaves_and_order_errors() {
+        let (witnesses, root, count) = witnesses();
+        // leaf 5 is a copy of leaf 4, whose proof reaches the root from there too
+        let mut padding = witnesses[4].clone();
+        padding.leaf_index = 5;
+        let packed = Packer::default().pack([1; 32], &[padding]).unwrap();
+        let mut session = Session::new([1; 32], root, count);
+        assert!(matches!(session.apply(&packed[0]), Err(WitnessError::ProofIndexOutOfRange { index: 5, chunks: 5 })));
+        let packed = Packer::default().pack([1; 32], &witnesses[..1]).unwrap();
+        let mut out_of_order = packed[0].clone();
+        out_of_order.seq = 1;
+        assert!(matches!(session.apply(&out_of_order), Err(WitnessError::SessionOutOfOrder { .. })));
+        session.apply(&packed[0]).unwrap();
+        assert!(matches!(session.clone().apply(&packed[0]), Err(WitnessError::SessionOutOfOrder { .. })));
+    }
+
+    #[test]
+    fn trees_of_64_levels_do_not_overflow() {
+        let (witnesses, root, _) = witnesses();
+        let mut session = Session::new([1; 32], root, usize::MAX);
+        let packed = Packer::default().pack([1; 32], &witnesses[..1]).unwrap();
+        // the three-step proof stays pending, 61 levels short of the root
+        session.apply(&packed[0]).unwrap();
+        assert!(matches!(session.finish(), Err(WitnessError::SessionIncomplete { leaf: 0, .. })));
+    }
+}
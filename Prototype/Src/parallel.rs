@@ -0,0 +1,100 @@
+use std::time::{Duration, Instant};
+
+use rayon::prelude::*;
+
+use crate::events::WitnessEvent;
+use crate::mempool::{AccountLocks, Mempool};
+use crate::{ChainState, PreVerified, TxReceipt, WitnessTx};
+
+/// Timings and counts of a block executed in lock-free batches
+#[derive(Debug, Default, serde::Serialize, serde::Deserialize)]
+pub struct ParallelBlockReport {
+    pub slot: u64,
+    pub receipts: Vec<TxReceipt>,
+    pub failed: usize,
+    /// Number of lock-free batches the block was split into
+    pub batches: usize,
+    /// Witnesses whose proofs were hashed up front, all txs of the block at once
+    pub witnesses_verified: usize,
+    /// Wall time of that up-front phase, signature checks included
+    pub verify_time: Duration,
+    /// Wall time spent running programs over the verified witnesses (parallel per batch)
+    pub execute_time: Duration,
+    /// Wall time spent applying effects (sequential phase)
+    pub commit_time: Duration,
+}
+
+impl ParallelBlockReport {
+    /// Executed txs per second of the parallel phase
+    pub fn execute_tps(&self) -> f64 {
+        let n = (self.receipts.len() + self.failed) as f64;
+        n / self.execute_time.as_secs_f64().max(f64::EPSILON)
+    }
+
+    /// Witnesses verified per second in the up-front phase
+    pub fn verify_throughput(&self) -> f64 {
+        self.witnesses_verified as f64 / self.verify_time.as_secs_f64().max(f64::EPSILON)
+    }
+}
+
+/// Split `txs` into batches with no read/write conflicts inside a batch, preserving the
+/// order of conflicting txs: each tx lands right after the last batch it conflicts with.
+/// Batches are indices into `txs`.
+pub fn schedule_batches(txs: &[WitnessTx]) -> Vec<Vec<usize>> {
+    let mut batches: Vec<(AccountLocks, Vec<usize>)> = Vec::new();
+    for (i, tx) in txs.iter().enumerate() {
+        let after = batches.iter().rposition(|(locks, _)| locks.conflicts(tx));
+        let idx = after.map_or(0, |b| b + 1);
+        if idx == batches.len() {
+            batches.push((AccountLocks::default(), Vec::new()));
+        }
+        batches[idx].0.lock(tx);
+        batches[idx].1.push(i);
+    }
+    batches.into_iter().map(|(_, b)| b).collect()
+}
+
+impl ChainState {
+    /// Execute `txs` as lock-free batches. Every tx's signatures and proofs are checked
+    /// up front, the whole block at once; then within a batch every tx is run
+    /// concurrently against the same state, its proven roots compared with the stubs as
+    /// they stand, and effects are committed in order.
+    pub fn execute_parallel(&mut self, txs: Vec<WitnessTx>) -> ParallelBlockReport {
+        let mut report = ParallelBlockReport { slot: self.slot, ..ParallelBlockReport::default() };
+        let started = Instant::now();
+        let state = &*self;
+        let mut pre: Vec<Option<PreVerified>> = txs.par_iter().map(|tx| Some(state.pre_verify(tx))).collect();
+        report.verify_time = started.elapsed();
+        report.witnesses_verified = pre.iter().flatten().map(|p| p.roots.iter().flatten().count()).sum();
+
+        for batch in schedule_batches(&txs) {
+            report.batches += 1;
+            let started = Instant::now();
+            let work: Vec<_> = batch.iter().map(|&i| (&txs[i], pre[i].take())).collect();
+            let state = &*self;
+            let results: Vec<_> = work.into_par_iter().map(|(tx, pre)| state.execute_pre_verified(tx, pre)).collect();
+            report.execute_time += started.elapsed();
+
+            let started = Instant::now();
+            for (tx, res) in batch.iter().map(|&i| &txs[i]).zip(results) {
+                match res.and_then(|executed| self.commit_tx(tx, executed)) {
+                    Ok(r) => report.receipts.push(r),
+                    Err(e) => {
+                        report.failed += 1;
+                        self.emit(WitnessEvent::TxFailed { pubkey: tx.primary_pubkey(), reason: e.to_string() });
+                    }
+                }
+            }
+            report.commit_time += started.elapsed();
+        }
+        report
+    }
+
+    /// Execute the next conflict-free set of mempool txs with `execute_parallel`, then
+    /// close the slot
+    pub fn produce_block_parallel(&mut self, mempool: &mut Mempool, max_txs: usize) -> ParallelBlockReport {
+        let report = self.execute_parallel(mempool.take_block(max_txs));
+        self.advance_slot();
+        report
+    }
+}
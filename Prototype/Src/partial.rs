@@ -0,0 +1,266 @@
+use std::collections::{HashMap, HashSet};
+use std::time::Instant;
+
+use ed25519_dalek::SigningKey;
+
+use crate::compute::ComputeMeter;
+use crate::error::WitnessError;
+use crate::events::WitnessEvent;
+use crate::metrics::metrics;
+use crate::multisig::{sign_message, verify_signatures};
+use crate::view::ChunkWitness;
+use crate::{h, sha256, AccountStub, ChainState, TxReceipt};
+
+/// A write touching only some chunks of one account: the client sends those chunks with
+/// their proofs instead of the full blob, and the new root is rebuilt from the proofs
+#[derive(Debug, Clone)]
+pub struct ChunkTx {
+    pub program_id: String,
+    pub instruction_data: Vec<u8>,
+    pub pubkey: String,
+    pub chunks: Vec<ChunkWitness>,
+    pub recent_blockhash: [u8;32],
+    pub signatures: Vec<([u8;32], [u8;64])>,
+}
+
+impl ChunkTx {
+    /// Hash over every field except the signatures. Every variable-length field and list
+    /// is length-prefixed, so no two txs encode alike.
+    pub fn id(&self) -> [u8;32] {
+        fn put(data: &mut Vec<u8>, bytes: &[u8]) {
+            data.extend_from_slice(&(bytes.len() as u64).to_le_bytes());
+            data.extend_from_slice(bytes);
+        }
+        let mut data = b"chunk-tx".to_vec();
+        put(&mut data, self.program_id.as_bytes());
+        put(&mut data, &self.instruction_data);
+        put(&mut data, self.pubkey.as_bytes());
+        data.extend_from_slice(&(self.chunks.len() as u64).to_le_bytes());
+        for c in &self.chunks {
+            data.extend_from_slice(&(c.leaf_index as u64).to_le_bytes());
+            put(&mut data, &c.chunk);
+            data.extend_from_slice(&(c.proof.len() as u64).to_le_bytes());
+            for (sibling, is_left) in &c.proof {
+                data.extend_from_slice(sibling);
+                data.push(*is_left as u8);
+            }
+        }
+        data.extend_from_slice(&self.recent_blockhash);
+        sha256(&data)
+    }
+
+    pub fn sign(&mut self, key: &SigningKey) {
+        let sig = sign_message(key, &self.id());
+        self.signatures.push(sig);
+    }
+}
+
+/// One account's chunk writes, verified and run but not yet applied
+pub(crate) struct ChunkWrites {
+    /// With the rebuilt root
+    pub stub: AccountStub,
+    pub old_root: [u8;32],
+    pub new_chunks: Vec<ChunkWitness>,
+}
+
+/// (leaf index, leaf hash, proof) of one leaf taking part in a multi-leaf recomputation
+pub type ProvenLeaf<'a> = (usize, [u8;32], &'a [([u8;32], bool)]);
+
+/// Root of a tree given some of its leaves (index, hash) and a proof for each. Nodes on
+/// the leaves' paths are recomputed; everything else comes from the proofs' siblings.
+pub fn root_from_proofs(leaves: &[ProvenLeaf]) -> Result<[u8;32], WitnessError> {
+    let depth = match leaves.first() {
+        Some((_, _, proof)) => proof.len(),
+        None => return Err(WitnessError::NoLeaves),
+    };
+    let mut known: HashMap<(usize, usize), [u8;32]> = HashMap::new();
+    for (index, _, proof) in leaves {
+        if proof.len() != depth {
+            return Err(WitnessError::ProofDepthMismatch(depth, proof.len()));
+        }
+        for (level, (sibling, _)) in proof.iter().enumerate() {
+            known.insert((level, (index >> level) ^ 1), *sibling);
+        }
+    }
+    let mut path: Vec<usize> = leaves.iter().map(|(i, _, _)| *i).collect();
+    for (index, leaf, _) in leaves {
+        known.insert((0, *index), *leaf);
+    }
+    for level in 0..depth {
+        path = path.iter().map(|i| i >> 1).collect::<HashSet<_>>().into_iter().collect();
+        for &parent in &path {
+            let (left, right) = (known[&(level, 2 * parent)], known[&(level, 2 * parent + 1)]);
+            let mut data = [0u8; 64];
+            data[..32].copy_from_slice(&left);
+            data[32..].copy_from_slice(&right);
+            known.insert((level + 1, parent), sha256(&data));
+        }
+    }
+    Ok(known[&(depth, 0)])
+}
+
+/// `root_from_proofs` over a tree of `len` leaves padded with copies of the last: when
+/// that leaf is among `leaves`, its copies change with it. Every sibling of a copy is
+/// itself a copy or on the last leaf's path, so borrowing that leaf's proof for the
+/// copies only ever supplies nodes that get recomputed.
+pub fn padded_root(len: usize, leaves: &[ProvenLeaf]) -> Result<[u8;32], WitnessError> {
+    let mut all = leaves.to_vec();
+    if let Some(&(_, last, proof)) = leaves.iter().find(|(i, _, _)| *i + 1 == len) {
+        let width = 1usize << proof.len();
+        all.extend((len..width).map(|i| (i, last, proof)));
+    }
+    root_from_proofs(&all)
+}
+
+impl ChainState {
+    /// Apply a `ChunkTx`. Emits `ProofVerified`, `RootUpdated` and `ChunksWritten` on
+    /// success, `TxFailed` on any error.
+    pub fn process_chunk_tx(&mut self, tx: &ChunkTx) -> Result<TxReceipt, WitnessError> {
+        let _span = tracing::debug_span!("chunk_tx", pubkey = %tx.pubkey, program = %tx.program_id, chunks = tx.chunks.len()).entered();
+        let res = self.try_chunk_tx(tx);
+        metrics().transactions.with_label_values(&[if res.is_ok() { "ok" } else { "failed" }]).inc();
+        if let Err(e) = &res {
+            tracing::info!(error = %e, "chunk tx failed");
+            self.emit(WitnessEvent::TxFailed { pubkey: tx.pubkey.clone(), reason: e.to_string() });
+        }
+        res
+    }
+
+    fn try_chunk_tx(&mut self, tx: &ChunkTx) -> Result<TxReceipt, WitnessError> {
+        if !self.recent_blockhashes.contains(&tx.recent_blockhash) {
+            return Err(WitnessError::BlockhashNotFound(tx.recent_blockhash));
+        }
+        let tx_id = tx.id();
+        if self.is_processed(&tx.recent_blockhash, &tx_id) {
+            return Err(WitnessError::AlreadyProcessed(tx_id));
+        }
+        let costs = self.compute_costs.clone();
+        let mut meter = ComputeMeter::new(self.compute_budget);
+        meter.consume(costs.ed25519_verify * tx.signatures.len() as u64, "signature verification")?;
+        let signers = verify_signatures(&tx_id, &tx.signatures)?;
+        let writes = self.execute_chunks(&tx.program_id, &tx.pubkey, &tx.chunks, &tx.instruction_data, &signers, &mut meter)?;
+        let new_root = self.apply_chunks(&tx.pubkey, &tx.chunks, writes);
+        match self.staging.as_mut() {
+            Some(staging) => staging.processed.push((tx.recent_blockhash, tx_id)),
+            None => { self.status_cache.entry(tx.recent_blockhash).or_default().insert(tx_id); }
+        }
+        Ok(TxReceipt { compute_units: meter.consumed(), new_roots: vec![(tx.pubkey.clone(), new_root)], frontiers: Vec::new() })
+    }
+
+    /// Check `chunks` of `pubkey` against its stub, let `program_id` rewrite them and
+    /// rebuild the root from their proofs, without applying anything
+    pub(crate) fn execute_chunks(&self, program_id: &str, pubkey: &str, chunks: &[ChunkWitness], instruction_data: &[u8], signers: &HashSet<[u8;32]>, meter: &mut ComputeMeter) -> Result<ChunkWrites, WitnessError> {
+        let costs = &self.compute_costs;
+        let mut stub = match self.get_stub(pubkey) {
+            Some(s) => s.clone(),
+            None => return Err(WitnessError::UnknownAccount(pubkey.to_string())),
+        };
+        stub.check_layout()?;
+        if stub.owner != program_id {
+            return Err(WitnessError::NotOwner { program: program_id.to_string(), pubkey: pubkey.to_string(), owner: stub.owner.clone() });
+        }
+        if stub.executable {
+            return Err(WitnessError::Executable(pubkey.to_string()));
+        }
+        if let Some(multisig) = &stub.multisig {
+            multisig.check(signers, pubkey)?;
+        }
+        if let Some(lock) = &stub.timelock {
+            lock.check(pubkey, self.slot, self.epoch())?;
+        }
+
+        let leaf_count = stub.chunk_count();
+        let started = Instant::now();
+        let mut seen = HashSet::new();
+        for c in chunks {
+            if c.pubkey != pubkey {
+                return Err(WitnessError::ChunkForOtherAccount { chunk: c.pubkey.clone(), tx: pubkey.to_string() });
+            }
+            if c.chunk.len() != stub.chunk_size || c.leaf_index >= leaf_count || !seen.insert(c.leaf_index) {
+                return Err(WitnessError::BadChunk { pubkey: pubkey.to_string(), leaf_index: c.leaf_index });
+            }
+            meter.consume(costs.mem_copy(c.chunk.len()), "chunk load")?;
+            meter.consume(costs.verify_proof(stub.chunk_size, c.proof.len()), "proof verification")?;
+            stub.check_proof_shape(pubkey, c.leaf_index, &c.proof)?;
+            let computed = witness_core::compute_root(&c.chunk, &c.proof);
+            if !witness_core::ct::eq(&computed, &stub.merkle_root) {
+                return Err(WitnessError::ProofVerificationFailed { pubkey: pubkey.to_string(), leaf_index: c.leaf_index, expected: stub.merkle_root, computed });
+            }
+        }
+
+        metrics().observe_verification("chunk_tx", started.elapsed());
+        let program = match self.programs.get(program_id) {
+            Some(p) => p,
+            None => return Err(WitnessError::UnknownProgram(program_id.to_string())),
+        };
+        let writes = program.process_chunks(chunks, instruction_data, signers)?;
+        let mut new_chunks: Vec<ChunkWitness> = chunks.to_vec();
+        for (leaf_index, data) in writes {
+            let slot = match new_chunks.iter_mut().find(|c| c.leaf_index == leaf_index) {
+                Some(c) => c,
+                None => return Err(WitnessError::UnprovenChunkWrite(leaf_index)),
+            };
+            if data.len() != stub.chunk_size {
+                return Err(WitnessError::ChunkSizeMismatch { pubkey: pubkey.to_string(), expected: stub.chunk_size, actual: data.len() });
+            }
+            let used = stub.data_len as usize - leaf_index * stub.chunk_size;
+            if used < stub.chunk_size && data[used..].iter().any(|&b| b != 0) {
+                return Err(WitnessError::WritePastEnd(pubkey.to_string()));
+            }
+            slot.chunk = data;
+        }
+        let hashes: Vec<ProvenLeaf> = new_chunks.iter().map(|c| (c.leaf_index, sha256(&c.chunk), c.proof.as_slice())).collect();
+        for (_, _, proof) in &hashes {
+            meter.consume(costs.sha256(stub.chunk_size) + costs.sha256(64) * proof.len() as u64, "root recomputation")?;
+        }
+        let old_root = stub.merkle_root;
+        // a write to the last leaf rewrites the copies padding the tree too
+        let new_root = padded_root(leaf_count, &hashes)?;
+
+        stub.merkle_root = new_root;
+        if let Some(lock) = stub.timelock.as_mut() {
+            lock.last_write_epoch = Some(self.epoch());
+        }
+        if let Some(dirty) = stub.dirty.as_mut() {
+            for (old, new) in chunks.iter().zip(&new_chunks) {
+                if old.chunk != new.chunk {
+                    dirty.mark(old.leaf_index);
+                }
+            }
+        }
+        Ok(ChunkWrites { stub, old_root, new_chunks })
+    }
+
+    /// Put the stub `execute_chunks` rebuilt and emit its events; returns the new root
+    pub(crate) fn apply_chunks(&mut self, pubkey: &str, chunks: &[ChunkWitness], writes: ChunkWrites) -> [u8;32] {
+        let ChunkWrites { stub, old_root, new_chunks } = writes;
+        let new_root = stub.merkle_root;
+        let chunk_size = stub.chunk_size;
+        tracing::info!(pubkey = %pubkey, slot = self.slot, old = %h(&old_root), new = %h(&new_root), chunks = chunks.len(), "root updated from chunk proofs");
+        self.put_stub(pubkey, stub);
+        for c in chunks {
+            self.emit(WitnessEvent::ProofVerified { pubkey: pubkey.to_string(), leaf_index: c.leaf_index, root: old_root });
+        }
+        self.emit(WitnessEvent::RootUpdated { pubkey: pubkey.to_string(), slot: self.slot, old: old_root, new: new_root });
+        let written = new_chunks.into_iter().map(|c| (c.leaf_index, c.chunk)).collect();
+        self.emit(WitnessEvent::ChunksWritten { pubkey: pubkey.to_string(), slot: self.slot, old_root, root: new_root, chunk_size, chunks: written });
+        new_root
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn id_separates_every_field() {
+        let chunk = ChunkWitness { pubkey: "A".to_string(), leaf_index: 0, chunk: vec![1; 4], proof: vec![([2; 32], false)] };
+        let tx = ChunkTx { program_id: "Prog1".to_string(), instruction_data: Vec::new(), pubkey: "A".to_string(), chunks: vec![chunk], recent_blockhash: [0; 32], signatures: Vec::new() };
+        // unprefixed, a proof step reads the same as the end of a longer chunk
+        let mut moved = tx.clone();
+        moved.chunks[0].chunk.extend_from_slice(&[2; 32]);
+        moved.chunks[0].chunk.push(0);
+        moved.chunks[0].proof.clear();
+        assert_ne!(tx.id(), moved.id());
+    }
+}
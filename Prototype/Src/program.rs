@@ -0,0 +1,273 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::compute::{ComputeCosts, ComputeMeter};
+use crate::error::WitnessError;
+use crate::view::ChunkWitness;
+use crate::{AccountStub, AccountWitness};
+
+/// Max height of the invoke stack, top-level instruction included (Solana uses 5)
+pub const MAX_INVOKE_DEPTH: usize = 5;
+
+/// Max bytes an account may grow by within one transaction (same as Solana)
+pub const MAX_PERMITTED_DATA_INCREASE: usize = 10 * 1024;
+
+/// Max size of a single account blob (same as Solana)
+pub const MAX_ACCOUNT_DATA_LEN: usize = 10 * 1024 * 1024;
+
+/// Read-only view of one account handed to a program. `data` is the full blob,
+/// already verified against `stub.merkle_root` by the runtime (plus any writes
+/// made earlier in the same transaction).
+#[derive(Debug, Clone, Copy)]
+pub struct AccountView<'a> {
+    pub pubkey: &'a str,
+    pub stub: &'a AccountStub,
+    pub data: &'a [u8],
+}
+
+/// State changes requested by a program. The runtime checks write authority and
+/// recomputes roots; programs never touch stubs directly.
+#[derive(Debug, Clone, Default)]
+pub struct Effects {
+    /// (pubkey, new length) resizes, applied before `writes`: growth is zero-filled
+    pub reallocs: Vec<(String, usize)>,
+    /// (pubkey, new blob) for every account the program rewrote
+    pub writes: Vec<(String, Vec<u8>)>,
+}
+
+impl Effects {
+    pub fn write(&mut self, pubkey: &str, data: Vec<u8>) {
+        self.writes.push((pubkey.to_string(), data));
+    }
+
+    /// Grow (zero-filled) or truncate the account's data to `new_len` bytes
+    pub fn realloc(&mut self, pubkey: &str, new_len: usize) {
+        self.reallocs.push((pubkey.to_string(), new_len));
+    }
+}
+
+/// A simulated on-chain program. `accounts[i]` is the verified view for `witnesses[i]`.
+/// Programs may call other programs through `ctx.invoke`, and turn an instruction down
+/// with `WitnessError::Rejected`.
+pub trait Program: Send + Sync {
+    fn process(&self, ctx: &mut InvokeContext, accounts: &[AccountView], instruction_data: &[u8], witnesses: &[AccountWitness]) -> Result<Effects, WitnessError>;
+
+    /// Read-only instruction over individually proven chunks; returns data, never writes
+    fn view(&self, _chunks: &[ChunkWitness], _instruction_data: &[u8]) -> Result<Vec<u8>, WitnessError> {
+        Err(WitnessError::Rejected("program does not support view instructions".to_string()))
+    }
+
+    /// Write instruction over individually proven chunks of one account (`ChunkTx`).
+    /// Returns `(leaf_index, new chunk)` for each chunk to rewrite; `signers` are the
+    /// keys that signed the tx.
+    fn process_chunks(&self, _chunks: &[ChunkWitness], _instruction_data: &[u8], _signers: &HashSet<[u8;32]>) -> Result<Vec<(usize, Vec<u8>)>, WitnessError> {
+        Err(WitnessError::Rejected("program does not support partial chunk writes".to_string()))
+    }
+}
+
+/// Per-transaction execution state shared by the top-level instruction and every CPI:
+/// the verified accounts, their current data, the call stack and the compute meter.
+pub struct InvokeContext<'a> {
+    programs: &'a HashMap<String, Box<dyn Program>>,
+    witnesses: &'a [AccountWitness],
+    stubs: &'a [AccountStub],
+    /// Current blob per account index, updated as programs write
+    data: Vec<Vec<u8>>,
+    /// Account indices written so far, in first-write order
+    dirty: Vec<usize>,
+    /// (program id, account indices) of every frame currently executing, outermost first
+    stack: Vec<(String, Vec<usize>)>,
+    costs: ComputeCosts,
+    pub meter: ComputeMeter,
+    /// ed25519 keys with a valid signature on the transaction
+    signers: HashSet<[u8;32]>,
+}
+
+impl<'a> InvokeContext<'a> {
+    pub fn new(programs: &'a HashMap<String, Box<dyn Program>>, witnesses: &'a [AccountWitness], stubs: &'a [AccountStub], costs: ComputeCosts, meter: ComputeMeter) -> Self {
+        let data = witnesses.iter().map(|w| w.blob.clone()).collect();
+        Self { programs, witnesses, stubs, data, dirty: Vec::new(), stack: Vec::new(), costs, meter, signers: HashSet::new() }
+    }
+
+    pub fn with_signers(mut self, signers: HashSet<[u8;32]>) -> Self {
+        self.signers = signers;
+        self
+    }
+
+    /// Whether `key` signed the transaction being executed
+    pub fn is_signer(&self, key: &[u8;32]) -> bool {
+        self.signers.contains(key)
+    }
+
+    /// Call `program_id` over `pubkeys`, which must be a subset of the caller's accounts.
+    /// The callee's writes are checked against its own authority and become visible to
+    /// later instructions; they are also returned so the caller can read them.
+    pub fn invoke(&mut self, program_id: &str, pubkeys: &[&str], instruction_data: &[u8]) -> Result<Effects, WitnessError> {
+        if self.stack.len() >= MAX_INVOKE_DEPTH {
+            return Err(WitnessError::InvokeDepth { program: program_id.to_string(), max: MAX_INVOKE_DEPTH });
+        }
+        // reentrancy: a program already on the stack may only call itself directly
+        let caller = self.stack.last().map(|(p, _)| p.as_str());
+        if self.stack.iter().any(|(p, _)| p == program_id) && caller != Some(program_id) {
+            let stack = self.stack.iter().map(|(p, _)| p.clone()).collect();
+            return Err(WitnessError::Reentrancy { program: program_id.to_string(), stack });
+        }
+        // copy the shared references out so borrows of them don't hold `self`
+        let (programs, all_witnesses, stubs) = (self.programs, self.witnesses, self.stubs);
+        let program = match programs.get(program_id) {
+            Some(p) => p,
+            None => return Err(WitnessError::UnknownProgram(program_id.to_string())),
+        };
+
+        // resolve the account subset
+        let mut indices = Vec::with_capacity(pubkeys.len());
+        for pk in pubkeys {
+            let idx = match all_witnesses.iter().position(|w| w.pubkey == *pk) {
+                Some(i) => i,
+                None => return Err(WitnessError::AccountNotAvailable { program: program_id.to_string(), pubkey: pk.to_string() }),
+            };
+            if let Some((caller, caller_accounts)) = self.stack.last() {
+                if !caller_accounts.contains(&idx) {
+                    return Err(WitnessError::AccountNotPassed { caller: caller.clone(), program: program_id.to_string(), pubkey: pk.to_string() });
+                }
+            }
+            indices.push(idx);
+        }
+        let witnesses: Vec<AccountWitness> = indices.iter().map(|&i| all_witnesses[i].clone()).collect();
+        // the callee gets its own copy of the current data
+        let snapshot: Vec<Vec<u8>> = indices.iter().map(|&i| self.data[i].clone()).collect();
+        for d in &snapshot {
+            self.meter.consume(self.costs.mem_copy(d.len()), "cpi account copy")?;
+        }
+        let views: Vec<AccountView> = indices.iter().zip(&snapshot)
+            .map(|(&i, d)| AccountView { pubkey: &all_witnesses[i].pubkey, stub: &stubs[i], data: d })
+            .collect();
+
+        self.stack.push((program_id.to_string(), indices.clone()));
+        let res = program.process(self, &views, instruction_data, &witnesses);
+        self.stack.pop();
+        let effects = res?;
+
+        for (pubkey, new_len) in &effects.reallocs {
+            let idx = self.writable_index(program_id, &indices, pubkey)?;
+            // before allocating, so a huge length fails here instead of aborting
+            self.check_len(idx, *new_len)?;
+            let mut resized = self.data[idx].clone();
+            resized.resize(*new_len, 0);
+            self.set_data(idx, resized)?;
+        }
+        for (pubkey, new_blob) in &effects.writes {
+            let idx = self.writable_index(program_id, &indices, pubkey)?;
+            self.set_data(idx, new_blob.clone())?;
+        }
+        Ok(effects)
+    }
+
+    /// Index of `pubkey` among the callee's accounts, if `program_id` may write it
+    fn writable_index(&self, program_id: &str, indices: &[usize], pubkey: &str) -> Result<usize, WitnessError> {
+        let (program, pubkey) = (program_id.to_string(), pubkey.to_string());
+        let idx = match indices.iter().find(|&&i| self.witnesses[i].pubkey == pubkey) {
+            Some(&i) => i,
+            None => return Err(WitnessError::WroteUngivenAccount { program, pubkey }),
+        };
+        if !self.witnesses[idx].is_writable {
+            return Err(WitnessError::ReadOnly { program, pubkey });
+        }
+        let stub = &self.stubs[idx];
+        if stub.owner != program_id {
+            return Err(WitnessError::NotOwner { program, pubkey, owner: stub.owner.clone() });
+        }
+        if stub.executable {
+            return Err(WitnessError::Executable(pubkey));
+        }
+        if let Some(pda) = &stub.pda {
+            if !pda.derives(program_id, &pubkey) {
+                return Err(WitnessError::NotDerived { program, pubkey });
+            }
+        }
+        Ok(idx)
+    }
+
+    /// The realloc limits on an account's length, against its size at tx start
+    fn check_len(&self, idx: usize, len: usize) -> Result<(), WitnessError> {
+        let original = self.witnesses[idx].blob.len();
+        let pubkey = || self.witnesses[idx].pubkey.clone();
+        if len > MAX_ACCOUNT_DATA_LEN {
+            return Err(WitnessError::AccountTooLarge { pubkey: pubkey(), len, max: MAX_ACCOUNT_DATA_LEN });
+        }
+        if len > original + MAX_PERMITTED_DATA_INCREASE {
+            return Err(WitnessError::GrewTooMuch { pubkey: pubkey(), grown: len - original, max: MAX_PERMITTED_DATA_INCREASE });
+        }
+        Ok(())
+    }
+
+    /// Replace an account's data, enforcing the realloc limits
+    fn set_data(&mut self, idx: usize, data: Vec<u8>) -> Result<(), WitnessError> {
+        self.check_len(idx, data.len())?;
+        self.data[idx] = data;
+        if !self.dirty.contains(&idx) {
+            self.dirty.push(idx);
+        }
+        Ok(())
+    }
+
+    /// Final (account index, blob) for every account written during the transaction
+    pub fn into_writes(self) -> (Vec<(usize, Vec<u8>)>, ComputeMeter) {
+        let mut data = self.data;
+        let writes = self.dirty.iter().map(|&i| (i, std::mem::take(&mut data[i]))).collect();
+        (writes, self.meter)
+    }
+}
+
+/// Program id the demos register `DemoProgram` under
+pub const DEMO_PROGRAM_ID: &str = "WitnessProgram1";
+
+/// The original demo mutation as a program: bump the first byte of every account it is given
+pub struct DemoProgram;
+
+impl Program for DemoProgram {
+    fn process(&self, _ctx: &mut InvokeContext, accounts: &[AccountView], _instruction_data: &[u8], _witnesses: &[AccountWitness]) -> Result<Effects, WitnessError> {
+        let mut effects = Effects::default();
+        for acc in accounts {
+            let mut new_blob = acc.data.to_vec();
+            if new_blob.is_empty() {
+                new_blob.push(1u8);
+            } else {
+                new_blob[0] = new_blob[0].wrapping_add(1);
+            }
+            effects.write(acc.pubkey, new_blob);
+        }
+        Ok(effects)
+    }
+}
+
+/// Resizes every account it is given to the little-endian u64 length in its instruction
+/// data; accounts whose stub already records that length are left alone
+pub struct ReallocProgram;
+
+impl Program for ReallocProgram {
+    fn process(&self, _ctx: &mut InvokeContext, accounts: &[AccountView], instruction_data: &[u8], _witnesses: &[AccountWitness]) -> Result<Effects, WitnessError> {
+        let new_len = match instruction_data.try_into() {
+            Ok(bytes) => u64::from_le_bytes(bytes) as usize,
+            Err(_) => return Err(WitnessError::Rejected(format!("realloc expects an 8-byte length, got {} bytes", instruction_data.len()))),
+        };
+        let mut effects = Effects::default();
+        for acc in accounts.iter().filter(|acc| acc.stub.data_len != new_len as u64) {
+            effects.realloc(acc.pubkey, new_len);
+        }
+        Ok(effects)
+    }
+}
+
+/// Forwards its instruction unchanged to `target` over all of its accounts,
+/// e.g. a marketplace delegating the actual state change to an NFT program
+pub struct ProxyProgram {
+    pub target: String,
+}
+
+impl Program for ProxyProgram {
+    fn process(&self, ctx: &mut InvokeContext, accounts: &[AccountView], instruction_data: &[u8], _witnesses: &[AccountWitness]) -> Result<Effects, WitnessError> {
+        let pubkeys: Vec<&str> = accounts.iter().map(|a| a.pubkey).collect();
+        ctx.invoke(&self.target, &pubkeys, instruction_data)?;
+        Ok(Effects::default())
+    }
+}
@@ -0,0 +1,314 @@
+use anyhow::{Context, Result, bail};
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+use serde::{Deserialize, Serialize};
+
+use crate::error::WitnessError;
+use crate::MerkleTree;
+
+/// Leading bytes of the binary encoding
+pub const MAGIC: &[u8;4] = b"SBPF";
+/// Bumped whenever any encoding changes; decoders reject other versions
+pub const VERSION: u8 = 1;
+/// Start of the text encodings' header, `sbpf<version>:<b64|b58>:`
+pub const TEXT_PREFIX: &str = "sbpf";
+
+/// How proofs and roots are written for scripts and other tools
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum OutputFormat {
+    Json,
+    /// The binary encoding as one line of hex
+    Hex,
+    /// The binary encoding in standard base64 behind a `sbpf1:b64:` header, for
+    /// JSON-RPC payloads
+    Base64,
+    /// The binary encoding in base58 behind a `sbpf1:b58:` header, for memos and
+    /// anywhere Solana addresses go
+    Base58,
+    Binary,
+}
+
+/// Inclusion proof of one chunk, in the stable interchange schema. JSON:
+/// `{"version":1,"root":hex,"leaf_index":n,"chunk_size":n,"chunk":hex,
+/// "siblings":[hex, leaf level first],"directions":["left"|"right", side of each sibling]}`.
+/// Binary: `MAGIC`, version u8, root, leaf_index u64, chunk_size u32, chunk len u32 and
+/// bytes, step count u16, then per step the sibling and 0 (right) or 1 (left); integers
+/// little-endian.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProofFile {
+    pub root: [u8;32],
+    pub leaf_index: usize,
+    pub chunk_size: usize,
+    pub chunk: Vec<u8>,
+    pub siblings: Vec<[u8;32]>,
+    /// true when the sibling at that level is the left child
+    pub directions: Vec<bool>,
+}
+
+/// The JSON schema, field for field
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct ProofJson {
+    pub version: u8,
+    pub root: String,
+    pub leaf_index: usize,
+    pub chunk_size: usize,
+    pub chunk: String,
+    pub siblings: Vec<String>,
+    pub directions: Vec<String>,
+}
+
+fn hash(s: &str, what: &str) -> Result<[u8;32]> {
+    let bytes = hex::decode(s).with_context(|| format!("{} is not hex", what))?;
+    match bytes.try_into() {
+        Ok(b) => Ok(b),
+        Err(_) => bail!("{} must be 32 bytes", what),
+    }
+}
+
+fn take<'a>(bytes: &mut &'a [u8], n: usize) -> Result<&'a [u8]> {
+    if bytes.len() < n {
+        bail!("proof truncated");
+    }
+    let (head, rest) = bytes.split_at(n);
+    *bytes = rest;
+    Ok(head)
+}
+
+impl ProofFile {
+    pub fn new(root: [u8;32], leaf_index: usize, chunk_size: usize, chunk: Vec<u8>, proof: &[([u8;32], bool)]) -> Self {
+        Self {
+            root,
+            leaf_index,
+            chunk_size,
+            chunk,
+            siblings: proof.iter().map(|(s, _)| *s).collect(),
+            directions: proof.iter().map(|(_, is_left)| *is_left).collect(),
+        }
+    }
+
+    /// Proof steps in the shape `MerkleTree::verify_proof` takes
+    pub fn steps(&self) -> Vec<([u8;32], bool)> {
+        self.siblings.iter().copied().zip(self.directions.iter().copied()).collect()
+    }
+
+    pub(crate) fn json(&self) -> ProofJson {
+        ProofJson {
+            version: VERSION,
+            root: hex::encode(self.root),
+            leaf_index: self.leaf_index,
+            chunk_size: self.chunk_size,
+            chunk: hex::encode(&self.chunk),
+            siblings: self.siblings.iter().map(hex::encode).collect(),
+            directions: self.directions.iter().map(|&l| if l { "left" } else { "right" }.to_string()).collect(),
+        }
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(&self.json()).unwrap()
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(4 + 1 + 32 + 8 + 4 + 4 + self.chunk.len() + 2 + self.siblings.len() * 33);
+        out.extend_from_slice(MAGIC);
+        out.push(VERSION);
+        out.extend_from_slice(&self.root);
+        out.extend_from_slice(&(self.leaf_index as u64).to_le_bytes());
+        out.extend_from_slice(&(self.chunk_size as u32).to_le_bytes());
+        out.extend_from_slice(&(self.chunk.len() as u32).to_le_bytes());
+        out.extend_from_slice(&self.chunk);
+        out.extend_from_slice(&(self.siblings.len() as u16).to_le_bytes());
+        for (sibling, is_left) in self.siblings.iter().zip(&self.directions) {
+            out.extend_from_slice(sibling);
+            out.push(*is_left as u8);
+        }
+        out
+    }
+
+    pub fn to_base64(&self) -> String {
+        format!("{}{}:b64:{}", TEXT_PREFIX, VERSION, STANDARD.encode(self.to_bytes()))
+    }
+
+    pub fn to_base58(&self) -> String {
+        format!("{}{}:b58:{}", TEXT_PREFIX, VERSION, bs58::encode(self.to_bytes()).into_string())
+    }
+
+    pub fn encode(&self, format: OutputFormat) -> Vec<u8> {
+        match format {
+            OutputFormat::Json => format!("{}\n", self.to_json()).into_bytes(),
+            OutputFormat::Hex => format!("{}\n", hex::encode(self.to_bytes())).into_bytes(),
+            OutputFormat::Base64 => format!("{}\n", self.to_base64()).into_bytes(),
+            OutputFormat::Base58 => format!("{}\n", self.to_base58()).into_bytes(),
+            OutputFormat::Binary => self.to_bytes(),
+        }
+    }
+
+    pub fn from_json(text: &str) -> Result<Self> {
+        Self::from_json_schema(serde_json::from_str(text).context("parsing JSON proof")?)
+    }
+
+    pub(crate) fn from_json_schema(json: ProofJson) -> Result<Self> {
+        if json.version != VERSION {
+            bail!("unsupported proof version {}", json.version);
+        }
+        if json.siblings.len() != json.directions.len() {
+            bail!("{} siblings but {} directions", json.siblings.len(), json.directions.len());
+        }
+        Ok(Self {
+            root: hash(&json.root, "root")?,
+            leaf_index: json.leaf_index,
+            chunk_size: json.chunk_size,
+            chunk: hex::decode(&json.chunk).context("chunk is not hex")?,
+            siblings: json.siblings.iter().map(|s| hash(s, "sibling")).collect::<Result<_>>()?,
+            directions: json.directions.iter().map(|d| match d.as_str() {
+                "left" => Ok(true),
+                "right" => Ok(false),
+                other => bail!("direction must be \"left\" or \"right\", got {:?}", other),
+            }).collect::<Result<_>>()?,
+        })
+    }
+
+    pub fn from_bytes(mut bytes: &[u8]) -> Result<Self> {
+        let b = &mut bytes;
+        if take(b, 4)? != MAGIC {
+            bail!("not a binary proof (bad magic)");
+        }
+        let version = take(b, 1)?[0];
+        if version != VERSION {
+            bail!("unsupported proof version {}", version);
+        }
+        let root: [u8;32] = take(b, 32)?.try_into().unwrap();
+        let leaf_index = u64::from_le_bytes(take(b, 8)?.try_into().unwrap()) as usize;
+        let chunk_size = u32::from_le_bytes(take(b, 4)?.try_into().unwrap()) as usize;
+        let chunk_len = u32::from_le_bytes(take(b, 4)?.try_into().unwrap()) as usize;
+        let chunk = take(b, chunk_len)?.to_vec();
+        let steps = u16::from_le_bytes(take(b, 2)?.try_into().unwrap()) as usize;
+        let mut siblings = Vec::with_capacity(steps);
+        let mut directions = Vec::with_capacity(steps);
+        for _ in 0..steps {
+            siblings.push(take(b, 32)?.try_into().unwrap());
+            directions.push(match take(b, 1)?[0] {
+                0 => false,
+                1 => true,
+                d => bail!("bad direction byte {}", d),
+            });
+        }
+        if !b.is_empty() {
+            bail!("{} trailing bytes after proof", b.len());
+        }
+        Ok(Self { root, leaf_index, chunk_size, chunk, siblings, directions })
+    }
+
+    /// Read a `to_base64` or `to_base58` string. Only the exact string the encoder
+    /// would write is accepted: no whitespace, no missing or extra padding, no header
+    /// version other than the one inside the binary encoding.
+    pub fn from_text(text: &str) -> Result<Self> {
+        let rest = text.strip_prefix(TEXT_PREFIX).context("not a text proof (no sbpf header)")?;
+        let (version, rest) = rest.split_once(':').context("text proof header has no version")?;
+        let version: u8 = version.parse().with_context(|| format!("bad text proof version {:?}", version))?;
+        if version != VERSION {
+            bail!("unsupported proof version {}", version);
+        }
+        let (encoding, data) = rest.split_once(':').context("text proof header has no encoding")?;
+        let bytes = match encoding {
+            "b64" => STANDARD.decode(data).context("proof is not valid base64")?,
+            "b58" => bs58::decode(data).into_vec().context("proof is not valid base58")?,
+            other => bail!("unknown text proof encoding {:?}", other),
+        };
+        let proof = Self::from_bytes(&bytes)?;
+        let again = if encoding == "b64" { proof.to_base64() } else { proof.to_base58() };
+        if again != text {
+            bail!("text proof is not in canonical form");
+        }
+        Ok(proof)
+    }
+
+    /// Read any of the encodings, telling them apart by their first bytes
+    pub fn decode(bytes: &[u8]) -> Result<Self> {
+        if bytes.starts_with(MAGIC) {
+            return Self::from_bytes(bytes);
+        }
+        let text = std::str::from_utf8(bytes).context("proof is neither binary nor text")?.trim();
+        if text.starts_with('{') {
+            return Self::from_json(text);
+        }
+        if text.starts_with(TEXT_PREFIX) {
+            return Self::from_text(text);
+        }
+        Self::from_bytes(&hex::decode(text).context("proof is not JSON, hex, base64, base58 or binary")?)
+    }
+
+    /// Check the proof self-consistent and leading to `root`, a tree of `chunk_count`
+    /// chunks. The count must come from whoever vouches for the root: the proof is only as
+    /// deep as its prover made it.
+    pub fn verify(&self, root: &[u8;32], chunk_count: usize) -> Result<(), WitnessError> {
+        if self.chunk.len() != self.chunk_size {
+            return Err(WitnessError::ProofChunkSize { expected: self.chunk_size, actual: self.chunk.len() });
+        }
+        let steps = self.steps();
+        witness_core::check_proof_shape(&steps, self.leaf_index, chunk_count)?;
+        if self.root != *root {
+            return Err(WitnessError::WrongRoot { proof: self.root, expected: *root });
+        }
+        if !MerkleTree::verify_proof(&self.chunk, &steps, root) {
+            return Err(WitnessError::LeafNotCommitted { leaf_index: self.leaf_index, root: *root });
+        }
+        Ok(())
+    }
+}
+
+/// Serde sees the same schema as `to_json`, so proofs embed as is in JSON fixtures
+impl Serialize for ProofFile {
+    fn serialize<S: serde::Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
+        self.json().serialize(s)
+    }
+}
+
+impl<'de> Deserialize<'de> for ProofFile {
+    fn deserialize<D: serde::Deserializer<'de>>(d: D) -> Result<Self, D::Error> {
+        Self::from_json_schema(ProofJson::deserialize(d)?).map_err(|e| serde::de::Error::custom(format!("{:#}", e)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blob_file::BlobFile;
+    use crate::chunk_blob;
+
+    fn committed() -> (Vec<u8>, BlobFile, MerkleTree) {
+        let blob: Vec<u8> = (0..256u32).map(|i| (i * 7) as u8).collect();
+        let container = BlobFile::commit(&blob, 64, None).unwrap();
+        let tree = MerkleTree::from_chunks(&chunk_blob(&blob, 64));
+        (blob, container, tree)
+    }
+
+    #[test]
+    fn honest_proof_verifies_against_container() {
+        let (blob, container, tree) = committed();
+        let file = ProofFile::new(container.root, 2, 64, blob[128..192].to_vec(), &tree.gen_proof(2));
+        file.verify(&container.root, container.chunk_count).unwrap();
+        let decoded = ProofFile::decode(&file.to_bytes()).unwrap();
+        decoded.verify(&container.root, container.chunk_count).unwrap();
+    }
+
+    #[test]
+    fn rejects_interior_node_forgery() {
+        let (_, container, tree) = committed();
+        // the two leaf hashes under node (1, 0) pass for a 64-byte chunk one level up
+        let mut node = tree.layer(0)[0].to_vec();
+        node.extend_from_slice(&tree.layer(0)[1]);
+        let proof = [(tree.layer(1)[1], false)];
+        assert!(MerkleTree::verify_proof(&node, &proof, &container.root));
+        let forged = ProofFile::new(container.root, 0, 64, node, &proof);
+        let err = forged.verify(&container.root, container.chunk_count).unwrap_err();
+        assert!(err.to_string().contains("1 steps, the tree is 2 deep"), "{}", err);
+    }
+
+    #[test]
+    fn rejects_leaf_past_chunk_count() {
+        let (blob, container, tree) = committed();
+        let file = ProofFile::new(container.root, 3, 64, blob[192..].to_vec(), &tree.gen_proof(3));
+        assert!(file.verify(&container.root, 3).is_err());
+        assert!(file.verify(&[0; 32], container.chunk_count).is_err());
+    }
+}
@@ -0,0 +1,203 @@
+use std::collections::{BTreeMap, HashMap};
+use std::mem::size_of;
+
+use crate::{chunk_blob, MerkleTree};
+
+/// How a prover holds the trees it serves proofs from
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum TreeMode {
+    /// Every layer, so a proof is a lookup per level
+    #[default]
+    Full,
+    /// Leaf hashes only; the nodes proofs need are recomputed and the last
+    /// `cached_nodes` of them kept
+    Pruned { cached_nodes: usize },
+}
+
+/// Nodes the pruned tree keeps if nothing says otherwise: the paths of a few hundred
+/// recently proven leaves in a deep tree
+pub const DEFAULT_CACHED_NODES: usize = 4096;
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PrunedStats {
+    pub proofs: u64,
+    /// Siblings found in the cache
+    pub hits: u64,
+    /// Siblings recomputed from the leaves below them
+    pub misses: u64,
+    pub hashes: u64,
+    pub evictions: u64,
+}
+
+/// A tree that keeps only its padded leaf hashes and its root. A proof's sibling at
+/// level `l` hashes the `2^l` leaves under it, so a proof from scratch costs about as
+/// much as building the tree; the siblings and path nodes of recent proofs are kept in
+/// a bounded LRU, so proofs for the same or nearby leaves mostly hit it.
+#[derive(Debug, Clone)]
+pub struct PrunedTree {
+    leaves: Vec<[u8;32]>,
+    root: [u8;32],
+    max_cached: usize,
+    /// (level, index) -> (hash, last-use tick)
+    cache: HashMap<(usize, usize), ([u8;32], u64)>,
+    /// last-use tick -> node, oldest first
+    lru: BTreeMap<u64, (usize, usize)>,
+    tick: u64,
+    stats: PrunedStats,
+}
+
+impl PrunedTree {
+    /// Keep `tree`'s leaves and drop the layers above them
+    pub fn prune(tree: &MerkleTree, max_cached: usize) -> Self {
+        Self {
+            leaves: tree.leaves().to_vec(),
+            root: tree.root(),
+            max_cached,
+            cache: HashMap::new(),
+            lru: BTreeMap::new(),
+            tick: 0,
+            stats: PrunedStats::default(),
+        }
+    }
+
+    pub fn root(&self) -> [u8;32] {
+        self.root
+    }
+
+    pub fn depth(&self) -> usize {
+        self.leaves.len().trailing_zeros() as usize
+    }
+
+    pub fn leaves(&self) -> &[[u8;32]] {
+        &self.leaves
+    }
+
+    pub fn stats(&self) -> PrunedStats {
+        self.stats.clone()
+    }
+
+    pub fn cached_nodes(&self) -> usize {
+        self.cache.len()
+    }
+
+    /// Leaf hashes plus the node cache, counted the way `memory.rs` counts
+    pub fn memory_usage(&self) -> usize {
+        let node = size_of::<((usize, usize), ([u8;32], u64))>();
+        let lru_entry = size_of::<(u64, (usize, usize))>();
+        size_of::<Self>() + self.leaves.capacity() * 32 + self.cache.capacity() * node + self.lru.len() * lru_entry
+    }
+
+    /// The same proof `MerkleTree::gen_proof` gives for `leaf_index`
+    pub fn gen_proof(&mut self, leaf_index: usize) -> Vec<([u8;32], bool)> {
+        self.stats.proofs += 1;
+        let mut path = self.leaves[leaf_index];
+        let mut proof = Vec::with_capacity(self.depth());
+        for level in 0..self.depth() {
+            let idx = leaf_index >> level;
+            let sibling = if level == 0 {
+                self.leaves[idx ^ 1]
+            } else if let Some(hash) = self.lookup(level, idx ^ 1) {
+                self.stats.hits += 1;
+                hash
+            } else {
+                self.stats.misses += 1;
+                let hash = self.compute(level, idx ^ 1);
+                self.insert(level, idx ^ 1, hash);
+                hash
+            };
+            proof.push((sibling, idx & 1 == 1));
+            path = if idx & 1 == 1 { witness_core::hash_pair(&sibling, &path) } else { witness_core::hash_pair(&path, &sibling) };
+            self.stats.hashes += 1;
+            if level + 1 < self.depth() {
+                // the next proof for a neighbour will want this node as its sibling
+                self.insert(level + 1, idx >> 1, path);
+            }
+        }
+        debug_assert_eq!(path, self.root, "pruned tree climbed to a different root");
+        proof
+    }
+
+    fn lookup(&mut self, level: usize, index: usize) -> Option<[u8;32]> {
+        self.tick += 1;
+        let tick = self.tick;
+        let (hash, last) = self.cache.get_mut(&(level, index))?;
+        self.lru.remove(last);
+        *last = tick;
+        self.lru.insert(tick, (level, index));
+        Some(*hash)
+    }
+
+    /// Node `index` of `level`, from whatever cached nodes sit below it or else the leaves
+    fn compute(&mut self, level: usize, index: usize) -> [u8;32] {
+        if let Some((hash, _)) = self.cache.get(&(level, index)) {
+            return *hash;
+        }
+        if level == 0 {
+            return self.leaves[index];
+        }
+        let left = self.compute(level - 1, 2 * index);
+        let right = self.compute(level - 1, 2 * index + 1);
+        self.stats.hashes += 1;
+        witness_core::hash_pair(&left, &right)
+    }
+
+    fn insert(&mut self, level: usize, index: usize, hash: [u8;32]) {
+        if level == 0 || self.max_cached == 0 {
+            return;
+        }
+        if let Some((_, last)) = self.cache.remove(&(level, index)) {
+            self.lru.remove(&last);
+        }
+        while self.cache.len() >= self.max_cached {
+            let Some((_, oldest)) = self.lru.pop_first() else { break };
+            self.cache.remove(&oldest);
+            self.stats.evictions += 1;
+        }
+        self.tick += 1;
+        self.cache.insert((level, index), (hash, self.tick));
+        self.lru.insert(self.tick, (level, index));
+    }
+}
+
+/// A 64 KiB tree with everything above the leaves dropped, for a prover with many
+/// accounts and few proof requests
+pub fn demo(chunk_size: usize) {
+    let big: Vec<u8> = (0..64 * 1024u32).map(|i| (i * 31 % 251) as u8).collect();
+    let full = MerkleTree::from_chunks(&chunk_blob(&big, chunk_size));
+    let mut pruned = PrunedTree::prune(&full, DEFAULT_CACHED_NODES);
+    let same = [7, 8, 7].iter().all(|&i| pruned.gen_proof(i) == full.gen_proof(i));
+    let stats = pruned.stats();
+    println!("Pruned 64 KiB tree: {} bytes ({} leaf hashes, {} cached nodes) instead of {}; 3 proofs match the full tree: {} ({} nodes recomputed, {} from cache, {} hashes)",
+        pruned.memory_usage(), pruned.leaves().len(), pruned.cached_nodes(), full.memory_usage(), same, stats.misses, stats.hits, stats.hashes);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn proves_like_the_full_tree_within_its_cache_bound() {
+        let blob: Vec<u8> = (0..4000u32).map(|i| (i * 13) as u8).collect();
+        let full = MerkleTree::from_chunks(&chunk_blob(&blob, 32));
+        let mut pruned = PrunedTree::prune(&full, 16);
+        assert_eq!(pruned.leaves(), full.leaves());
+        for leaf in [0, 1, 64, 124, 0] {
+            assert_eq!(pruned.gen_proof(leaf), full.gen_proof(leaf));
+            assert!(pruned.cached_nodes() <= 16);
+        }
+        assert_eq!(pruned.root(), full.root());
+        let stats = pruned.stats();
+        assert!(stats.hits > 0 && stats.evictions > 0, "{:?}", stats);
+    }
+
+    #[test]
+    fn caches_nothing_when_told_not_to() {
+        let full = MerkleTree::from_chunks(&chunk_blob(&[9u8; 300], 32));
+        let mut pruned = PrunedTree::prune(&full, 0);
+        for leaf in 0..10 {
+            assert_eq!(pruned.gen_proof(leaf), full.gen_proof(leaf));
+        }
+        assert_eq!(pruned.cached_nodes(), 0);
+        assert_eq!(pruned.stats().hits, 0);
+    }
+}
@@ -0,0 +1,161 @@
+use std::fmt;
+
+use anyhow::Result;
+use ed25519_dalek::VerifyingKey;
+
+use crate::error::WitnessError;
+use crate::events::StdoutSubscriber;
+use crate::program::{DemoProgram, DEMO_PROGRAM_ID};
+use crate::{chunk_blob, sha256, AccountStub, AccountWitness, ChainState, MerkleTree, WitnessTx};
+
+/// Max seeds per derived address and max bytes per seed (same as Solana)
+pub const MAX_SEEDS: usize = 16;
+pub const MAX_SEED_LEN: usize = 32;
+
+const PDA_MARKER: &[u8] = b"ProgramDerivedAddress";
+
+/// 32-byte account address. Accounts are still keyed by string; `to_string()` gives the key.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Pubkey(pub [u8;32]);
+
+impl fmt::Display for Pubkey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&hex::encode(self.0))
+    }
+}
+
+impl Pubkey {
+    /// Whether these bytes are a valid ed25519 point, i.e. could have a private key
+    pub fn is_on_curve(&self) -> bool {
+        VerifyingKey::from_bytes(&self.0).is_ok()
+    }
+
+    /// Address for `seeds` + `bump` under `program_id`; fails if it lands on the curve,
+    /// since then someone could hold its private key
+    pub fn create_program_address(program_id: &str, seeds: &[&[u8]], bump: u8) -> Result<Pubkey, WitnessError> {
+        if seeds.len() >= MAX_SEEDS {
+            return Err(WitnessError::TooManySeeds(seeds.len()));
+        }
+        let mut data = Vec::new();
+        for seed in seeds {
+            if seed.len() > MAX_SEED_LEN {
+                return Err(WitnessError::SeedTooLong(seed.len()));
+            }
+            data.extend_from_slice(seed);
+        }
+        data.push(bump);
+        data.extend_from_slice(program_id.as_bytes());
+        data.extend_from_slice(PDA_MARKER);
+        let key = Pubkey(sha256(&data));
+        if key.is_on_curve() {
+            return Err(WitnessError::OnCurve);
+        }
+        Ok(key)
+    }
+
+    /// Program-derived address of `seeds` under `program_id` with the highest bump that
+    /// lands off the curve, like Solana's `find_program_address`
+    pub fn derive(program_id: &str, seeds: &[&[u8]]) -> Result<(Pubkey, u8), WitnessError> {
+        for bump in (0..=u8::MAX).rev() {
+            if let Ok(key) = Self::create_program_address(program_id, seeds, bump) {
+                return Ok((key, bump));
+            }
+        }
+        Err(WitnessError::NoOffCurveAddress(program_id.to_string()))
+    }
+}
+
+/// Seeds a program-derived stub was created from; the runtime re-derives the address
+/// from them and the writing program before allowing a write
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct PdaSeeds {
+    #[serde(with = "crate::serde_support::hex_list")]
+    pub seeds: Vec<Vec<u8>>,
+    pub bump: u8,
+}
+
+impl PdaSeeds {
+    /// Whether `program_id` derives `pubkey` from these seeds
+    pub fn derives(&self, program_id: &str, pubkey: &str) -> bool {
+        let seeds: Vec<&[u8]> = self.seeds.iter().map(Vec::as_slice).collect();
+        Pubkey::create_program_address(program_id, &seeds, self.bump).is_ok_and(|k| k.to_string() == pubkey)
+    }
+
+    pub fn encode(&self) -> Vec<u8> {
+        let mut out = vec![self.seeds.len() as u8];
+        for seed in &self.seeds {
+            out.push(seed.len() as u8);
+            out.extend_from_slice(seed);
+        }
+        out.push(self.bump);
+        out
+    }
+}
+
+impl ChainState {
+    /// Store `stub` at the address `program_id` derives from `seeds`, owned by that program.
+    /// Returns the address.
+    pub fn create_pda_account(&mut self, program_id: &str, seeds: &[&[u8]], mut stub: AccountStub) -> Result<String, WitnessError> {
+        let (key, bump) = Pubkey::derive(program_id, seeds)?;
+        let pubkey = key.to_string();
+        if self.get_stub(&pubkey).is_some() {
+            return Err(WitnessError::AccountExists(pubkey));
+        }
+        stub.owner = program_id.to_string();
+        stub.pda = Some(PdaSeeds { seeds: seeds.iter().map(|s| s.to_vec()).collect(), bump });
+        self.put_stub(&pubkey, stub);
+        Ok(pubkey)
+    }
+}
+
+/// A vault PDA that only the program deriving its address may write
+pub fn demo(chunk_size: usize) -> Result<()> {
+    let mut chain = ChainState::new();
+    chain.subscribe(Box::new(StdoutSubscriber));
+    chain.register_program(DEMO_PROGRAM_ID, Box::new(DemoProgram));
+    let blob = b"vault for Acct1".to_vec();
+    let tree = MerkleTree::from_chunks(&chunk_blob(&blob, chunk_size));
+    let vault = chain.create_pda_account(DEMO_PROGRAM_ID, &[b"vault", b"Acct1"], AccountStub::new(DEMO_PROGRAM_ID, 0, tree.root(), blob.len() as u64, chunk_size))?;
+    chain.process_tx_witness(&WitnessTx {
+        program_id: DEMO_PROGRAM_ID.to_string(),
+        instruction_data: Vec::new(),
+        witnesses: vec![AccountWitness { pubkey: vault.clone(), blob, leaf_index: 0, proof: tree.gen_proof(0), is_writable: true }],
+        recent_blockhash: chain.latest_blockhash(),
+        priority_fee: 0,
+        signatures: Vec::new(),
+    })?;
+    println!("Vault PDA {}… (bump {}) updated by its program", &vault[..16], chain.get_stub(&vault).unwrap().pda.as_ref().unwrap().bump);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn derives_off_curve_with_the_highest_bump() {
+        let (key, bump) = Pubkey::derive("Prog1", &[b"vault", b"Acct1"]).unwrap();
+        assert!(!key.is_on_curve());
+        assert_eq!(Pubkey::create_program_address("Prog1", &[b"vault", b"Acct1"], bump).unwrap(), key);
+        assert!((bump as u16 + 1..=u8::MAX as u16).all(|b| Pubkey::create_program_address("Prog1", &[b"vault", b"Acct1"], b as u8).is_err()));
+        assert_ne!(Pubkey::derive("Prog2", &[b"vault", b"Acct1"]).unwrap().0, key);
+        assert_ne!(Pubkey::derive("Prog1", &[b"vault", b"Acct2"]).unwrap().0, key);
+    }
+
+    #[test]
+    fn rejects_too_many_or_too_long_seeds() {
+        let seeds = [&b"s"[..]; MAX_SEEDS];
+        assert!(Pubkey::derive("Prog1", &seeds).is_err());
+        assert!(Pubkey::derive("Prog1", &seeds[1..]).is_ok());
+        assert!(Pubkey::derive("Prog1", &[&[0u8; MAX_SEED_LEN + 1]]).is_err());
+    }
+
+    #[test]
+    fn seeds_derive_only_their_address_under_their_program() {
+        let (key, bump) = Pubkey::derive("Prog1", &[b"vault"]).unwrap();
+        let seeds = PdaSeeds { seeds: vec![b"vault".to_vec()], bump };
+        assert!(seeds.derives("Prog1", &key.to_string()));
+        assert!(!seeds.derives("Prog2", &key.to_string()));
+        assert!(!seeds.derives("Prog1", &Pubkey([0; 32]).to_string()));
+    }
+}
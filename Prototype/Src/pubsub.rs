@@ -0,0 +1,153 @@
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, Mutex};
+
+use crate::events::{EventSubscriber, WitnessEvent};
+use crate::{chunk_blob, sha256, ChainState};
+
+/// Which root changes a subscription receives
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SubscriptionFilter {
+    Account(String),
+    /// Every account owned by this program
+    Owner(String),
+}
+
+/// Pushed to subscribers whenever an account's Merkle root changes
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RootNotification {
+    pub pubkey: String,
+    pub owner: Option<String>,
+    pub slot: u64,
+    pub old_root: [u8;32],
+    pub root: [u8;32],
+    /// Leaves whose contents changed; None when only the root was posted
+    /// (optimistic updates) or the previous version was never seen
+    pub changed_leaves: Option<Vec<usize>>,
+}
+
+/// Receives notifications with the subscription id; returning false (e.g. the client
+/// hung up) ends the subscription
+pub type NotificationSink = Box<dyn FnMut(u64, &RootNotification) -> bool + Send>;
+
+#[derive(Default)]
+struct PubSubInner {
+    subscriptions: BTreeMap<u64, (SubscriptionFilter, NotificationSink)>,
+    next_id: u64,
+    owners: HashMap<String, String>,
+    /// Leaf hashes of the last full version seen, to tell which leaves a write touched
+    leaves: HashMap<String, Vec<[u8;32]>>,
+    /// Root update waiting for the data event that follows it with the changed leaves
+    pending: Option<RootNotification>,
+}
+
+impl PubSubInner {
+    fn deliver(&mut self, notification: RootNotification) {
+        self.subscriptions.retain(|id, (filter, sink)| {
+            let matches = match filter {
+                SubscriptionFilter::Account(pubkey) => *pubkey == notification.pubkey,
+                SubscriptionFilter::Owner(owner) => notification.owner.as_ref() == Some(owner),
+            };
+            !matches || sink(*id, &notification)
+        });
+    }
+
+    fn flush(&mut self) {
+        if let Some(n) = self.pending.take() {
+            self.deliver(n);
+        }
+    }
+
+    /// Send the pending update for `pubkey` at `root` with its changed leaves
+    fn complete(&mut self, pubkey: &str, root: &[u8;32], changed: Option<Vec<usize>>) {
+        match self.pending.take() {
+            Some(mut n) if n.pubkey == pubkey && n.root == *root => {
+                n.changed_leaves = changed;
+                self.deliver(n);
+            }
+            other => {
+                self.pending = other;
+                self.flush();
+            }
+        }
+    }
+}
+
+/// Root-change subscriptions for provers that must refresh cached proofs. Subscribe
+/// it to the chain; a root update is held until the data event right after it says
+/// which leaves changed, so transports should `flush` after each request to push
+/// updates that never get one. Clones share the same subscriptions.
+#[derive(Clone, Default)]
+pub struct PubSub {
+    inner: Arc<Mutex<PubSubInner>>,
+}
+
+impl PubSub {
+    pub fn new() -> Self { Self::default() }
+
+    pub fn subscribe(&self, filter: SubscriptionFilter, sink: NotificationSink) -> u64 {
+        let mut inner = self.inner.lock().unwrap();
+        inner.next_id += 1;
+        let id = inner.next_id;
+        inner.subscriptions.insert(id, (filter, sink));
+        id
+    }
+
+    pub fn unsubscribe(&self, id: u64) -> bool {
+        self.inner.lock().unwrap().subscriptions.remove(&id).is_some()
+    }
+
+    /// Learn account owners from the chain; events don't carry them
+    pub(crate) fn sync(&self, chain: &ChainState) {
+        let mut inner = self.inner.lock().unwrap();
+        for (pubkey, stub) in &chain.stubs {
+            inner.owners.insert(pubkey.clone(), stub.owner.clone());
+        }
+    }
+
+    /// Remember the current contents of `pubkey`, so the next write to it can
+    /// report exactly which leaves changed
+    pub fn track_blob(&self, pubkey: &str, chunk_size: usize, data: &[u8]) {
+        let leaves = witness_core::hash_leaves(chunk_blob(data, chunk_size).iter());
+        self.inner.lock().unwrap().leaves.insert(pubkey.to_string(), leaves);
+    }
+
+    /// Push a held root update without waiting any longer for its leaves
+    pub fn flush(&self) {
+        self.inner.lock().unwrap().flush();
+    }
+}
+
+impl EventSubscriber for PubSub {
+    fn on_event(&mut self, event: &WitnessEvent) {
+        let mut inner = self.inner.lock().unwrap();
+        match event {
+            WitnessEvent::RootUpdated { pubkey, slot, old, new } => {
+                inner.flush();
+                let owner = inner.owners.get(pubkey).cloned();
+                inner.pending = Some(RootNotification { pubkey: pubkey.clone(), owner, slot: *slot, old_root: *old, root: *new, changed_leaves: None });
+            }
+            WitnessEvent::AccountDataWritten { pubkey, root, chunk_size, data, .. } => {
+                let leaves = witness_core::hash_leaves(chunk_blob(data, *chunk_size).iter());
+                let changed = inner.leaves.get(pubkey).map(|old| {
+                    (0..leaves.len().max(old.len())).filter(|&i| leaves.get(i) != old.get(i)).collect()
+                });
+                inner.leaves.insert(pubkey.clone(), leaves);
+                inner.complete(pubkey, root, changed);
+            }
+            WitnessEvent::ChunksWritten { pubkey, root, chunks, .. } => {
+                if let Some(leaves) = inner.leaves.get_mut(pubkey) {
+                    for (index, chunk) in chunks {
+                        if let Some(l) = leaves.get_mut(*index) {
+                            *l = sha256(chunk);
+                        }
+                    }
+                }
+                inner.complete(pubkey, root, Some(chunks.iter().map(|(i, _)| *i).collect()));
+            }
+            WitnessEvent::EntryAppended { pubkey, index, root, .. } => {
+                inner.complete(pubkey, root, Some(vec![*index as usize]));
+            }
+            _ => inner.flush(),
+        }
+    }
+}
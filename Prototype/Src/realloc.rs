@@ -0,0 +1,137 @@
+use anyhow::Result;
+
+use crate::dirty::DirtyBitmap;
+use crate::events::StdoutSubscriber;
+use crate::program::ReallocProgram;
+use crate::{chunk_blob, AccountStub, AccountWitness, ChainState, MerkleTree, WitnessTx};
+
+/// Leaves touched by a resize, each with its proof against the new root: the old last
+/// leaf (its contents or padding changed) through the new last leaf. Provers holding
+/// cached proofs for the untouched prefix only need to refresh these.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct Frontier {
+    pub old_len: u64,
+    pub new_len: u64,
+    /// In leaf order
+    pub leaves: Vec<FrontierLeaf>,
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct FrontierLeaf {
+    pub index: usize,
+    #[serde(with = "crate::serde_support::hex_form")]
+    pub chunk: Vec<u8>,
+    #[serde(with = "crate::serde_support::steps")]
+    pub proof: Vec<([u8;32], bool)>,
+}
+
+impl Frontier {
+    /// Frontier of a blob resized from `old_len` bytes to `new_blob`, under `tree`
+    /// (the tree already built over `new_blob`)
+    pub fn build(old_len: u64, new_blob: &[u8], chunk_size: usize, tree: &MerkleTree) -> Self {
+        let chunks = chunk_blob(new_blob, chunk_size);
+        let old_last = (old_len as usize).saturating_sub(1) / chunk_size;
+        let new_last = chunks.len() - 1;
+        let first = old_last.min(new_last);
+        let leaves = (first..=new_last)
+            .map(|i| FrontierLeaf { index: i, chunk: chunks[i].to_vec(), proof: tree.gen_proof(i) })
+            .collect();
+        Self { old_len, new_len: new_blob.len() as u64, leaves }
+    }
+
+    /// Every listed leaf is a `chunk_size`-byte chunk of the resized blob committed by `root`
+    pub fn verify(&self, root: &[u8;32], chunk_size: usize) -> bool {
+        let leaf_count = (self.new_len as usize).div_ceil(chunk_size).max(1);
+        self.leaves.iter().all(|l| l.chunk.len() == chunk_size && MerkleTree::verify_proof_at(&l.chunk, &l.proof, root, l.index, leaf_count))
+    }
+}
+
+/// Grow a 100-byte account to 300 through `ReallocProgram`: only the leaves around the
+/// new end need fresh proofs
+pub fn demo(chunk_size: usize) -> Result<()> {
+    let mut chain = ChainState::new();
+    chain.subscribe(Box::new(StdoutSubscriber));
+    let realloc_id = "ReallocProgram1";
+    chain.register_program(realloc_id, Box::new(ReallocProgram));
+    let blob = vec![1u8; 100];
+    let tree = MerkleTree::from_chunks(&chunk_blob(&blob, chunk_size));
+    let mut stub = AccountStub::new(realloc_id, 1_000, tree.root(), blob.len() as u64, chunk_size);
+    stub.dirty = Some(DirtyBitmap::new(chain.slot));
+    chain.put_stub("Acct3", stub);
+    let grown = chain.process_tx_witness(&WitnessTx {
+        program_id: realloc_id.to_string(),
+        instruction_data: 300u64.to_le_bytes().to_vec(),
+        witnesses: vec![AccountWitness { pubkey: "Acct3".to_string(), blob, leaf_index: 0, proof: tree.gen_proof(0), is_writable: true }],
+        recent_blockhash: chain.latest_blockhash(),
+        priority_fee: 0,
+        signatures: Vec::new(),
+    })?;
+    let (_, frontier) = &grown.frontiers[0];
+    println!("Realloc Acct3 {} -> {} bytes: {} frontier leaves, proofs verify: {}",
+        frontier.old_len, frontier.new_len, frontier.leaves.len(), frontier.verify(&grown.new_roots[0].1, chunk_size));
+    let stub = chain.get_stub("Acct3").unwrap();
+    let dirty = stub.dirty.as_ref().unwrap();
+    println!("Acct3 dirty chunks since slot {}: {:?}", dirty.since_slot, dirty.dirty_indices((stub.data_len as usize).div_ceil(chunk_size)));
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::WitnessError;
+    use crate::program::{MAX_ACCOUNT_DATA_LEN, MAX_PERMITTED_DATA_INCREASE};
+
+    const CHUNK: usize = 32;
+
+    fn chain_with(blob: &[u8]) -> ChainState {
+        let mut chain = ChainState::new();
+        chain.register_program("Realloc1", Box::new(ReallocProgram));
+        let root = MerkleTree::from_chunks(&chunk_blob(blob, CHUNK)).root();
+        chain.put_stub("Acct1", AccountStub::new("Realloc1", 0, root, blob.len() as u64, CHUNK));
+        chain
+    }
+
+    fn resize_tx(chain: &ChainState, blob: &[u8], new_len: u64) -> WitnessTx {
+        let tree = MerkleTree::from_chunks(&chunk_blob(blob, CHUNK));
+        WitnessTx {
+            program_id: "Realloc1".to_string(),
+            instruction_data: new_len.to_le_bytes().to_vec(),
+            witnesses: vec![AccountWitness { pubkey: "Acct1".to_string(), blob: blob.to_vec(), leaf_index: 0, proof: tree.gen_proof(0), is_writable: true }],
+            recent_blockhash: chain.latest_blockhash(),
+            priority_fee: 0,
+            signatures: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn grows_and_shrinks_with_a_verifying_frontier() {
+        let blob = vec![7u8; 100];
+        let mut chain = chain_with(&blob);
+        let receipt = chain.process_tx_witness(&resize_tx(&chain, &blob, 300)).unwrap();
+        let (_, frontier) = &receipt.frontiers[0];
+        assert_eq!((frontier.old_len, frontier.new_len), (100, 300));
+        assert!(frontier.verify(&receipt.new_roots[0].1, CHUNK));
+        let mut grown = blob.clone();
+        grown.resize(300, 0);
+        assert_eq!(chain.get_stub("Acct1").unwrap().merkle_root, MerkleTree::from_chunks(&chunk_blob(&grown, CHUNK)).root());
+
+        let receipt = chain.process_tx_witness(&resize_tx(&chain, &grown, 40)).unwrap();
+        assert!(receipt.frontiers[0].1.verify(&receipt.new_roots[0].1, CHUNK));
+        assert_eq!(chain.get_stub("Acct1").unwrap().data_len, 40);
+    }
+
+    #[test]
+    fn rejects_growth_past_the_limits_before_allocating() {
+        let blob = vec![7u8; 100];
+        let mut chain = chain_with(&blob);
+        let too_much = (blob.len() + MAX_PERMITTED_DATA_INCREASE + 1) as u64;
+        assert!(matches!(chain.process_tx_witness(&resize_tx(&chain, &blob, too_much)), Err(WitnessError::GrewTooMuch { .. })));
+        chain.process_tx_witness(&resize_tx(&chain, &blob, (blob.len() + MAX_PERMITTED_DATA_INCREASE) as u64)).unwrap();
+        // would abort on allocation if the limit were only checked after resizing
+        let mut chain = chain_with(&blob);
+        for len in [MAX_ACCOUNT_DATA_LEN as u64 + 1, u64::MAX] {
+            assert!(matches!(chain.process_tx_witness(&resize_tx(&chain, &blob, len)), Err(WitnessError::AccountTooLarge { .. })));
+        }
+        assert_eq!(chain.get_stub("Acct1").unwrap().data_len, 100);
+    }
+}
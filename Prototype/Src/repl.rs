@@ -0,0 +1,200 @@
+use std::collections::HashMap;
+use std::io::{BufRead, Write};
+
+use anyhow::{Context, Result, bail};
+
+use crate::config::Config;
+use crate::proof_file::{OutputFormat, ProofFile};
+use crate::{chunk_blob, h, sha256, MerkleTree};
+
+const HELP: &str = "\
+commands:
+  load <file>              read a blob and commit to it
+  text <words...>          use the text as the blob
+  chunk-size <n>           re-chunk the blob and commit again
+  layers                   every layer of the current tree
+  chunk <i>                contents of chunk i
+  prove <i> [format]       proof of chunk i (json, hex, base64 or base58), saved for `verify`
+  tamper <i> <off> <hex>   overwrite bytes of chunk i without touching the committed root
+  verify <i>               check chunk i against the committed root, showing where it diverges
+  commit                   accept the current chunks as the new committed root
+  reset                    undo tampering
+  help, quit";
+
+/// Everything the session is looking at: the committed tree and the chunks as edited since
+struct Session {
+    chunk_size: usize,
+    data_len: usize,
+    committed: Vec<Vec<u8>>,
+    committed_tree: MerkleTree,
+    chunks: Vec<Vec<u8>>,
+    /// Proofs from `prove`, by leaf; a stale one shows what a cached proof would do
+    proofs: HashMap<usize, Vec<([u8;32], bool)>>,
+}
+
+impl Session {
+    fn new(blob: &[u8], chunk_size: usize) -> Self {
+        let chunks = chunk_blob(blob, chunk_size).to_vecs();
+        Self { chunk_size, data_len: blob.len(), committed_tree: MerkleTree::from_chunks(&chunks), committed: chunks.clone(), chunks, proofs: HashMap::new() }
+    }
+
+    fn blob(&self) -> Vec<u8> {
+        let mut blob = self.chunks.concat();
+        blob.truncate(self.data_len);
+        blob
+    }
+
+    fn leaf(&self, arg: Option<&str>) -> Result<usize> {
+        let i: usize = arg.context("missing chunk index")?.parse().context("chunk index must be a number")?;
+        if i >= self.chunks.len() {
+            bail!("chunk {} out of range: {} chunks", i, self.chunks.len());
+        }
+        Ok(i)
+    }
+
+    fn commit(&mut self) {
+        self.committed = self.chunks.clone();
+        self.committed_tree = MerkleTree::from_chunks(&self.committed);
+        println!("committed root {}", hex::encode(self.committed_tree.root()));
+    }
+
+    fn verify(&self, i: usize) {
+        let root = self.committed_tree.root();
+        let current = MerkleTree::from_chunks(&self.chunks);
+        let proof = match self.proofs.get(&i) {
+            Some(p) => { println!("using proof saved by `prove {}`", i); p.clone() }
+            None => current.gen_proof(i),
+        };
+        if MerkleTree::verify_proof_at(&self.chunks[i], &proof, &root, i, self.chunks.len()) {
+            println!("ok: chunk {} verifies against {}", i, h(&root));
+            return;
+        }
+        println!("MISMATCH: chunk {} does not verify against {}", i, h(&root));
+        // walk the path up, comparing what the proof computes with the committed tree
+        let mut computed = sha256(&self.chunks[i]);
+        let mut idx = i;
+        for (level, (sibling, is_left)) in proof.iter().enumerate() {
+            let expected = self.committed_tree.layer(level)[idx];
+            let stale_sibling = self.committed_tree.layer(level).get(idx ^ 1).is_some_and(|s| s != sibling);
+            println!("  level {:>2} node {:>4}: computed {} committed {}{}{}", level, idx, h(&computed), h(&expected),
+                if computed == expected { "" } else { "  <- differs" },
+                if stale_sibling { ", proof sibling differs too" } else { "" });
+            let mut data = [0u8; 64];
+            if *is_left {
+                data[..32].copy_from_slice(sibling);
+                data[32..].copy_from_slice(&computed);
+            } else {
+                data[..32].copy_from_slice(&computed);
+                data[32..].copy_from_slice(sibling);
+            }
+            computed = sha256(&data);
+            idx /= 2;
+        }
+        let changed: Vec<usize> = (0..self.chunks.len()).filter(|&c| self.chunks[c] != self.committed[c]).collect();
+        println!("  root:            computed {} committed {}; chunks edited since commit: {:?}", h(&computed), h(&root), changed);
+    }
+
+    fn run(&mut self, line: &str) -> Result<bool> {
+        let mut words = line.split_whitespace();
+        let command = match words.next() {
+            Some(c) => c,
+            None => return Ok(true),
+        };
+        match command {
+            "help" | "?" => println!("{}", HELP),
+            "quit" | "exit" => return Ok(false),
+            "load" => {
+                let path = words.next().context("usage: load <file>")?;
+                let blob = std::fs::read(path).with_context(|| format!("reading {}", path))?;
+                *self = Session::new(&blob, self.chunk_size);
+                println!("{} bytes, {} chunks, root {}", blob.len(), self.chunks.len(), hex::encode(self.committed_tree.root()));
+            }
+            "text" => {
+                let text = words.collect::<Vec<_>>().join(" ");
+                *self = Session::new(text.as_bytes(), self.chunk_size);
+                println!("{} bytes, {} chunks, root {}", text.len(), self.chunks.len(), hex::encode(self.committed_tree.root()));
+            }
+            "chunk-size" => {
+                let n: usize = words.next().context("usage: chunk-size <n>")?.parse().context("chunk size must be a number")?;
+                if n == 0 {
+                    bail!("chunk size must be positive");
+                }
+                *self = Session::new(&self.blob(), n);
+                println!("{} chunks of {}, root {}", self.chunks.len(), n, hex::encode(self.committed_tree.root()));
+            }
+            "layers" => {
+                let tree = MerkleTree::from_chunks(&self.chunks);
+                for (level, layer) in tree.layers().enumerate() {
+                    let nodes: Vec<String> = layer.iter().enumerate()
+                        .map(|(i, n)| if self.committed_tree.layer(level).get(i) == Some(n) { h(n) } else { format!("{}*", h(n)) })
+                        .collect();
+                    println!("layer {:>2}: {}", level, nodes.join(" "));
+                }
+                println!("(* differs from the committed tree)");
+            }
+            "chunk" => {
+                let i = self.leaf(words.next())?;
+                println!("{} {:?}", hex::encode(&self.chunks[i]), String::from_utf8_lossy(&self.chunks[i]));
+            }
+            "prove" => {
+                let i = self.leaf(words.next())?;
+                let format = match words.next() {
+                    None | Some("json") => OutputFormat::Json,
+                    Some("hex") => OutputFormat::Hex,
+                    Some("base64") => OutputFormat::Base64,
+                    Some("base58") => OutputFormat::Base58,
+                    Some(other) => bail!("unknown format {}", other),
+                };
+                let tree = MerkleTree::from_chunks(&self.chunks);
+                let proof = tree.gen_proof(i);
+                let file = ProofFile::new(tree.root(), i, self.chunk_size, self.chunks[i].clone(), &proof);
+                print!("{}", String::from_utf8_lossy(&file.encode(format)));
+                self.proofs.insert(i, proof);
+            }
+            "tamper" => {
+                let i = self.leaf(words.next())?;
+                let offset: usize = words.next().context("usage: tamper <i> <off> <hex>")?.parse().context("offset must be a number")?;
+                let bytes = hex::decode(words.next().context("usage: tamper <i> <off> <hex>")?).context("bytes must be hex")?;
+                if offset + bytes.len() > self.chunk_size {
+                    bail!("chunk {} is {} bytes", i, self.chunk_size);
+                }
+                self.chunks[i][offset..offset + bytes.len()].copy_from_slice(&bytes);
+                println!("chunk {} now {}", i, hex::encode(&self.chunks[i]));
+            }
+            "verify" => {
+                let i = self.leaf(words.next())?;
+                self.verify(i);
+            }
+            "commit" => self.commit(),
+            "reset" => {
+                self.chunks = self.committed.clone();
+                println!("back to root {}", h(&self.committed_tree.root()));
+            }
+            other => bail!("unknown command {:?}; try help", other),
+        }
+        Ok(true)
+    }
+}
+
+/// `repl`: read commands from stdin until `quit` or end of input
+pub fn run(cfg: &Config) -> Result<()> {
+    let mut session = Session::new(b"", cfg.chunk_size);
+    println!("solana-bloat repl ({}-byte chunks); `help` for commands", cfg.chunk_size);
+    let stdin = std::io::stdin();
+    let mut lines = stdin.lock().lines();
+    loop {
+        print!("bloat> ");
+        std::io::stdout().flush()?;
+        let line = match lines.next() {
+            Some(line) => line?,
+            None => break,
+        };
+        match session.run(&line) {
+            Ok(true) => {}
+            Ok(false) => break,
+            Err(e) => println!("error: {:#}", e),
+        }
+    }
+    println!();
+    Ok(())
+}
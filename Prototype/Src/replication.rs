@@ -0,0 +1,245 @@
+use std::collections::{BTreeMap, HashSet};
+use std::sync::Arc;
+
+use anyhow::Result;
+use ed25519_dalek::SigningKey;
+
+use crate::archive::ArchiveNode;
+use crate::blob_store::{BlobStore, MemoryBlobStore};
+use crate::error::WitnessError;
+use crate::rng::SimRng;
+use crate::storage::{RetrievalChallenge, StorageProvider};
+use crate::{chunk_blob, sha256, MerkleTree};
+
+/// Where one blob is replicated and how to audit it
+#[derive(Debug, Clone)]
+pub struct Replicated {
+    pub chunk_size: usize,
+    pub leaves: usize,
+    pub holders: Vec<String>,
+}
+
+/// Outcome of one audit round
+#[derive(Debug, Clone, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct AuditReport {
+    pub audited: usize,
+    pub failed: usize,
+    pub repaired: usize,
+    /// Blobs still below the replication factor after repair (not enough nodes)
+    pub under_replicated: usize,
+    /// Blobs with no healthy replica left to repair from
+    pub lost: usize,
+}
+
+/// Keeps every registered blob on `factor` storage providers. Each audit round
+/// challenges every replica for a random chunk and proof, the same way on-chain
+/// retrieval challenges do, drops replicas that fail, and re-copies the blob from a
+/// healthy replica onto the least-loaded providers that lack it.
+#[derive(Debug)]
+pub struct ReplicationManager {
+    pub factor: usize,
+    pub providers: Vec<StorageProvider>,
+    /// Providers that currently do not answer at all
+    pub offline: HashSet<String>,
+    blobs: BTreeMap<(String, [u8;32]), Replicated>,
+    rng: SimRng,
+}
+
+impl ReplicationManager {
+    pub fn new(factor: usize, providers: Vec<StorageProvider>, seed: u64) -> Self {
+        Self { factor, providers, offline: HashSet::new(), blobs: BTreeMap::new(), rng: SimRng::new(seed) }
+    }
+
+    pub fn replicas(&self, pubkey: &str, root: &[u8;32]) -> Option<&Replicated> {
+        self.blobs.get(&(pubkey.to_string(), *root))
+    }
+
+    fn load(&self, id: &str) -> usize {
+        self.blobs.values().filter(|b| b.holders.iter().any(|h| h == id)).count()
+    }
+
+    /// Online providers not yet holding `holders`, least loaded first
+    fn candidates(&self, holders: &[String]) -> Vec<usize> {
+        let mut c: Vec<usize> = (0..self.providers.len())
+            .filter(|&i| !self.offline.contains(&self.providers[i].id) && !holders.contains(&self.providers[i].id))
+            .collect();
+        c.sort_by_key(|&i| (self.load(&self.providers[i].id), i));
+        c
+    }
+
+    /// Place `blob` (committed by `root`) on `factor` providers; returns their ids
+    pub fn replicate(&mut self, pubkey: &str, root: &[u8;32], chunk_size: usize, blob: &[u8]) -> Result<Vec<String>, WitnessError> {
+        let chunks = chunk_blob(blob, chunk_size);
+        let computed = MerkleTree::from_chunks(&chunks).root();
+        if computed != *root {
+            return Err(WitnessError::BlobRootMismatch { blob: computed, stub: *root });
+        }
+        let mut holders = Vec::new();
+        for i in self.candidates(&[]).into_iter().take(self.factor) {
+            self.providers[i].archive.ingest(pubkey, 0, chunk_size, blob)?;
+            holders.push(self.providers[i].id.clone());
+        }
+        if holders.is_empty() {
+            return Err(WitnessError::NoProvider(pubkey.to_string()));
+        }
+        self.blobs.insert((pubkey.to_string(), *root), Replicated { chunk_size, leaves: chunks.len(), holders: holders.clone() });
+        Ok(holders)
+    }
+
+    /// Challenge `provider` for a random chunk of the blob; true if it proves it
+    fn audit(&mut self, provider: &str, pubkey: &str, root: &[u8;32], leaves: usize) -> bool {
+        if self.offline.contains(provider) {
+            return false;
+        }
+        let leaf_index = self.rng.range(0, leaves as u64 - 1) as usize;
+        let challenge = RetrievalChallenge {
+            challenger: "replication-manager".to_string(),
+            provider: provider.to_string(),
+            pubkey: pubkey.to_string(),
+            root: *root,
+            leaf_index,
+            leaf_count: leaves,
+            deadline_slot: 0,
+        };
+        let p = match self.providers.iter().find(|p| p.id == provider) {
+            Some(p) => p,
+            None => return false,
+        };
+        p.respond(&challenge).is_some_and(|r| {
+            MerkleTree::verify_proof_at(&r.chunk, &r.proof, root, leaf_index, leaves)
+        })
+    }
+
+    /// Audit every replica of every blob, then repair what failed
+    pub fn audit_round(&mut self) -> Result<AuditReport, WitnessError> {
+        let mut report = AuditReport::default();
+        let keys: Vec<(String, [u8;32])> = self.blobs.keys().cloned().collect();
+        for (pubkey, root) in keys {
+            let entry = self.blobs[&(pubkey.clone(), root)].clone();
+            let mut healthy = Vec::new();
+            for holder in &entry.holders {
+                report.audited += 1;
+                if self.audit(holder, &pubkey, &root, entry.leaves) {
+                    healthy.push(holder.clone());
+                } else {
+                    report.failed += 1;
+                }
+            }
+            // copy from a replica that just passed its audit, re-checking the whole blob
+            let source = healthy.first().and_then(|id| self.providers.iter().find(|p| &p.id == id));
+            let blob = source.and_then(|p| p.archive.get_blob(&pubkey, &root))
+                .filter(|b| MerkleTree::from_chunks(&chunk_blob(&b.data, entry.chunk_size)).root() == root);
+            match blob {
+                Some(blob) => {
+                    for i in self.candidates(&healthy).into_iter().take(self.factor.saturating_sub(healthy.len())) {
+                        self.providers[i].archive.ingest(&pubkey, blob.slot, entry.chunk_size, &blob.data)?;
+                        healthy.push(self.providers[i].id.clone());
+                        report.repaired += 1;
+                    }
+                    if healthy.len() < self.factor {
+                        report.under_replicated += 1;
+                    }
+                }
+                None => report.lost += 1,
+            }
+            if let Some(e) = self.blobs.get_mut(&(pubkey, root)) {
+                e.holders = healthy;
+            }
+        }
+        Ok(report)
+    }
+}
+
+/// Keep `pubkey`'s blob on 3 of 5 providers through an outage and silent data loss
+pub fn demo(pubkey: &str, root: &[u8;32], chunk_size: usize, blob: &[u8]) -> Result<()> {
+    let stores: Vec<Arc<MemoryBlobStore>> = (0..5).map(|_| Arc::new(MemoryBlobStore::new())).collect();
+    let nodes = stores.iter().enumerate()
+        .map(|(i, store)| {
+            let id = format!("replica_{}", i);
+            StorageProvider::new(&id, SigningKey::from_bytes(&sha256(id.as_bytes())), ArchiveNode::with_store(store.clone()))
+        })
+        .collect();
+    let mut replication = ReplicationManager::new(3, nodes, 5);
+    let holders = replication.replicate(pubkey, root, chunk_size, blob)?;
+    replication.offline.insert(holders[0].clone());
+    let wiped = replication.providers.iter().position(|p| p.id == holders[1]).unwrap();
+    stores[wiped].delete(root)?;
+    let audit = replication.audit_round()?;
+    println!("Replication audit on {:?}: {} of {} replicas failed, {} repaired; now on {:?}",
+        holders, audit.failed, audit.audited, audit.repaired, replication.replicas(pubkey, root).unwrap().holders);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn blob() -> (Vec<u8>, [u8;32]) {
+        let blob: Vec<u8> = (0..500u32).map(|i| i as u8).collect();
+        let root = MerkleTree::from_chunks(&chunk_blob(&blob, 32)).root();
+        (blob, root)
+    }
+
+    fn manager(nodes: usize, factor: usize) -> (ReplicationManager, Vec<Arc<MemoryBlobStore>>) {
+        let stores: Vec<Arc<MemoryBlobStore>> = (0..nodes).map(|_| Arc::new(MemoryBlobStore::new())).collect();
+        let providers = stores.iter().enumerate().map(|(i, store)| {
+            let id = format!("replica_{}", i);
+            StorageProvider::new(&id, SigningKey::from_bytes(&sha256(id.as_bytes())), ArchiveNode::with_store(store.clone()))
+        }).collect();
+        (ReplicationManager::new(factor, providers, 1), stores)
+    }
+
+    fn index(manager: &ReplicationManager, id: &str) -> usize {
+        manager.providers.iter().position(|p| p.id == id).unwrap()
+    }
+
+    #[test]
+    fn spreads_blobs_over_the_least_loaded_providers() {
+        let (mut manager, _) = manager(4, 2);
+        let (blob, root) = blob();
+        let first = manager.replicate("A", &root, 32, &blob).unwrap();
+        let other = [blob.clone(), vec![1]].concat();
+        let second = manager.replicate("B", &MerkleTree::from_chunks(&chunk_blob(&other, 32)).root(), 32, &other).unwrap();
+        assert_eq!(first.len(), 2);
+        assert!(second.iter().all(|id| !first.contains(id)));
+        assert_eq!(manager.audit_round().unwrap(), AuditReport { audited: 4, ..AuditReport::default() });
+    }
+
+    #[test]
+    fn rejects_blobs_that_miss_their_root() {
+        let (mut manager, _) = manager(3, 2);
+        let (blob, root) = blob();
+        assert!(matches!(manager.replicate("A", &root, 64, &blob), Err(WitnessError::BlobRootMismatch { .. })));
+        assert!(manager.replicas("A", &root).is_none());
+    }
+
+    #[test]
+    fn repairs_offline_and_wiped_replicas() {
+        let (mut manager, stores) = manager(5, 3);
+        let (blob, root) = blob();
+        let holders = manager.replicate("A", &root, 32, &blob).unwrap();
+        manager.offline.insert(holders[0].clone());
+        stores[index(&manager, &holders[1])].delete(&root).unwrap();
+        let report = manager.audit_round().unwrap();
+        assert_eq!(report, AuditReport { audited: 3, failed: 2, repaired: 2, under_replicated: 0, lost: 0 });
+        let now = &manager.replicas("A", &root).unwrap().holders;
+        assert_eq!(now.len(), 3);
+        assert!(now.contains(&holders[2]) && !now.contains(&holders[0]) && !now.contains(&holders[1]));
+        let copy = manager.providers[index(&manager, &now[1])].archive.get_blob("A", &root).unwrap();
+        assert_eq!(copy.data, blob);
+        assert_eq!(manager.audit_round().unwrap().failed, 0);
+    }
+
+    #[test]
+    fn counts_blobs_it_cannot_repair() {
+        let (mut manager, stores) = manager(3, 3);
+        let (blob, root) = blob();
+        let holders = manager.replicate("A", &root, 32, &blob).unwrap();
+        manager.offline.insert(holders[0].clone());
+        assert_eq!(manager.audit_round().unwrap(), AuditReport { audited: 3, failed: 1, under_replicated: 1, ..AuditReport::default() });
+        for id in &holders[1..] {
+            stores[index(&manager, id)].delete(&root).unwrap();
+        }
+        assert_eq!(manager.audit_round().unwrap(), AuditReport { audited: 2, failed: 2, lost: 1, ..AuditReport::default() });
+    }
+}
@@ -0,0 +1,274 @@
+use std::collections::HashMap;
+
+use anyhow::Result;
+use ed25519_dalek::SigningKey;
+
+use crate::archive::ArchiveNode;
+use crate::error::WitnessError;
+use crate::storage::StorageProvider;
+use crate::{chunk_blob, sha256, ChainState, MerkleTree};
+
+/// One chunk as sent by a provider, in order, with the right-hand sibling hashes the
+/// receiver has not been sent yet. Leaf i needs tz(i) of them (the whole right edge
+/// of the path for leaf 0), so a full stream carries about one hash per chunk.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StreamFrame {
+    pub index: usize,
+    pub chunk: Vec<u8>,
+    pub siblings: Vec<[u8;32]>,
+}
+
+/// Where frames come from; an untrusted provider over whatever transport
+pub trait ChunkSource {
+    /// Next frame, or None once the provider has nothing more to send
+    fn next_frame(&mut self) -> Result<Option<StreamFrame>>;
+}
+
+fn sibling_count(index: usize, depth: usize) -> usize {
+    if index == 0 { depth } else { (index.trailing_zeros() as usize).min(depth) }
+}
+
+fn depth_for(leaves: usize) -> usize {
+    leaves.next_power_of_two().trailing_zeros() as usize
+}
+
+fn hash_pair(left: &[u8;32], right: &[u8;32]) -> [u8;32] {
+    let mut data = [0u8; 64];
+    data[..32].copy_from_slice(left);
+    data[32..].copy_from_slice(right);
+    sha256(&data)
+}
+
+/// Serving side: the frames of one blob, in leaf order
+#[derive(Debug)]
+pub struct ChunkStream {
+    blob: Vec<u8>,
+    chunk_size: usize,
+    tree: MerkleTree,
+    next: usize,
+}
+
+impl ChunkStream {
+    pub fn new(blob: &[u8], chunk_size: usize) -> Self {
+        let tree = MerkleTree::from_chunks(&chunk_blob(blob, chunk_size));
+        Self { blob: blob.to_vec(), chunk_size, tree, next: 0 }
+    }
+}
+
+impl Iterator for ChunkStream {
+    type Item = StreamFrame;
+
+    fn next(&mut self) -> Option<StreamFrame> {
+        let index = self.next;
+        let chunk = chunk_blob(&self.blob, self.chunk_size).get(index)?.to_vec();
+        self.next += 1;
+        let depth = self.tree.depth();
+        let siblings = (0..sibling_count(index, depth)).map(|l| self.tree.layer(l)[(index >> l) + 1]).collect();
+        Some(StreamFrame { index, chunk, siblings })
+    }
+}
+
+impl ChunkSource for ChunkStream {
+    fn next_frame(&mut self) -> Result<Option<StreamFrame>> {
+        Ok(self.next())
+    }
+}
+
+impl StorageProvider {
+    /// Stream the blob of `pubkey` committed by `root`, if this provider holds it
+    pub fn stream(&self, pubkey: &str, root: &[u8;32]) -> Option<ChunkStream> {
+        let blob = self.archive.get_blob(pubkey, root)?;
+        Some(ChunkStream::new(&blob.data, blob.chunk_size))
+    }
+}
+
+/// Receiving side: checks each frame against the committed root as it arrives.
+/// Only hashes of not-yet-received subtrees are kept, O(log n) at any time.
+#[derive(Debug)]
+pub struct StreamVerifier {
+    chunk_size: usize,
+    data_len: usize,
+    leaves: usize,
+    depth: usize,
+    /// Verified hashes of subtrees still to come, by (level, index)
+    pending: HashMap<(usize, usize), [u8;32]>,
+    next: usize,
+    data: Vec<u8>,
+}
+
+impl StreamVerifier {
+    /// Expect the `data_len`-byte blob committed by `root` in `chunk_size`-byte leaves
+    pub fn new(root: &[u8;32], data_len: usize, chunk_size: usize) -> Self {
+        let leaves = data_len.div_ceil(chunk_size).max(1);
+        let depth = depth_for(leaves);
+        Self { chunk_size, data_len, leaves, depth, pending: HashMap::from([((depth, 0), *root)]), next: 0, data: Vec::with_capacity(data_len) }
+    }
+
+    pub fn is_complete(&self) -> bool {
+        self.next == self.leaves
+    }
+
+    /// Verify the next frame; on success returns the chunk's bytes of blob data. A frame
+    /// with bad data, or out of order, is `StreamChunkRejected` naming the chunk to blame.
+    pub fn push(&mut self, frame: StreamFrame) -> Result<&[u8], WitnessError> {
+        let index = self.next;
+        let bad = |reason: String| WitnessError::StreamChunkRejected { index, reason };
+        if index >= self.leaves {
+            return Err(WitnessError::StreamTooLong(self.leaves));
+        }
+        if frame.index != index {
+            return Err(bad(format!("expected chunk {}, got frame for {}", index, frame.index)));
+        }
+        if frame.chunk.len() != self.chunk_size {
+            return Err(bad(format!("{} bytes, chunks are {}", frame.chunk.len(), self.chunk_size)));
+        }
+        let t = sibling_count(index, self.depth);
+        if frame.siblings.len() != t {
+            return Err(bad(format!("{} sibling hashes, expected {}", frame.siblings.len(), t)));
+        }
+        let mut computed = sha256(&frame.chunk);
+        for sibling in &frame.siblings {
+            computed = hash_pair(&computed, sibling);
+        }
+        match self.pending.remove(&(t, index >> t)) {
+            Some(expected) if expected == computed => {}
+            Some(expected) => {
+                self.pending.insert((t, index >> t), expected);
+                return Err(bad("does not verify against the committed root".to_string()));
+            }
+            None => return Err(bad("verifier has no commitment for it".to_string())),
+        }
+        // the siblings are now bound to the root too; they check the chunks still to come
+        for (l, sibling) in frame.siblings.into_iter().enumerate() {
+            self.pending.insert((l, (index >> l) + 1), sibling);
+        }
+        self.next += 1;
+        let start = index * self.chunk_size;
+        let take = self.chunk_size.min(self.data_len.saturating_sub(start));
+        let from = self.data.len();
+        self.data.extend_from_slice(&frame.chunk[..take]);
+        Ok(&self.data[from..])
+    }
+
+    /// The whole blob, once every chunk has verified
+    pub fn finish(self) -> Result<Vec<u8>, WitnessError> {
+        if !self.is_complete() {
+            return Err(WitnessError::StreamIncomplete { received: self.next, chunks: self.leaves });
+        }
+        Ok(self.data)
+    }
+}
+
+/// Pull the blob committed by `root` from `source`, stopping at the first bad chunk
+pub fn retrieve(source: &mut dyn ChunkSource, root: &[u8;32], data_len: usize, chunk_size: usize) -> Result<Vec<u8>, WitnessError> {
+    let mut verifier = StreamVerifier::new(root, data_len, chunk_size);
+    while !verifier.is_complete() {
+        match source.next_frame()? {
+            Some(frame) => { verifier.push(frame)?; }
+            None => break,
+        }
+    }
+    verifier.finish()
+}
+
+impl ChainState {
+    /// Stream `pubkey`'s committed bytes from an untrusted `source`, verified against its stub
+    pub fn retrieve_verified(&self, pubkey: &str, source: &mut dyn ChunkSource) -> Result<Vec<u8>, WitnessError> {
+        let stub = match self.get_stub(pubkey) {
+            Some(s) => s,
+            None => return Err(WitnessError::UnknownAccount(pubkey.to_string())),
+        };
+        stub.check_tree()?;
+        retrieve(source, &stub.merkle_root, stub.data_len as usize, stub.chunk_size)
+    }
+}
+
+/// Stream `pubkey` from a provider over `archive`, checking each chunk against the stub
+/// root as it arrives, then again with chunk 2 flipped in flight
+pub fn demo(chain: &ChainState, archive: &ArchiveNode, pubkey: &str) -> Result<()> {
+    struct Tampering(ChunkStream);
+    impl ChunkSource for Tampering {
+        fn next_frame(&mut self) -> Result<Option<StreamFrame>> {
+            Ok(self.0.next().map(|mut f| { if f.index == 2 { f.chunk[0] ^= 1; } f }))
+        }
+    }
+    let root = chain.get_stub(pubkey).unwrap().merkle_root;
+    let provider = StorageProvider::new("provider_2", SigningKey::from_bytes(&sha256(b"provider_2")), archive.clone());
+    let mut stream = provider.stream(pubkey, &root).unwrap();
+    println!("Streamed {} from {}: {} bytes verified", pubkey, provider.id, chain.retrieve_verified(pubkey, &mut stream)?.len());
+    let mut tampered = Tampering(provider.stream(pubkey, &root).unwrap());
+    match chain.retrieve_verified(pubkey, &mut tampered) {
+        Ok(_) => println!("Tampered stream accepted"),
+        Err(e) => {
+            let bad = match &e { WitnessError::StreamChunkRejected { index, .. } => Some(*index), _ => None };
+            println!("Tampered stream aborted, bad chunk {:?}: {}", bad, e);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Frames replayed as given, however they were edited
+    struct Frames(std::vec::IntoIter<StreamFrame>);
+
+    impl ChunkSource for Frames {
+        fn next_frame(&mut self) -> Result<Option<StreamFrame>> {
+            Ok(self.0.next())
+        }
+    }
+
+    fn blob(len: usize) -> (Vec<u8>, [u8;32]) {
+        let blob: Vec<u8> = (0..len).map(|i| (i * 13) as u8).collect();
+        let root = MerkleTree::from_chunks(&chunk_blob(&blob, 16)).root();
+        (blob, root)
+    }
+
+    fn retrieve_edited(len: usize, edit: impl FnOnce(&mut Vec<StreamFrame>)) -> Result<Vec<u8>, WitnessError> {
+        let (blob, root) = blob(len);
+        let mut frames: Vec<StreamFrame> = ChunkStream::new(&blob, 16).collect();
+        edit(&mut frames);
+        retrieve(&mut Frames(frames.into_iter()), &root, len, 16)
+    }
+
+    #[test]
+    fn accepts_every_honest_stream() {
+        for len in [0, 1, 16, 70, 128, 129, 300] {
+            let (blob, root) = blob(len);
+            let mut stream = ChunkStream::new(&blob, 16);
+            assert_eq!(retrieve(&mut stream, &root, len, 16).unwrap(), blob);
+        }
+        // about one hash per chunk
+        let frames: Vec<StreamFrame> = ChunkStream::new(&blob(256).0, 16).collect();
+        assert_eq!(frames.iter().map(|f| f.siblings.len()).sum::<usize>(), 15);
+    }
+
+    #[test]
+    fn names_the_chunk_a_tampered_stream_breaks_at() {
+        let rejected = |r: Result<Vec<u8>, WitnessError>| match r {
+            Err(WitnessError::StreamChunkRejected { index, .. }) => Some(index),
+            _ => None,
+        };
+        assert_eq!(rejected(retrieve_edited(300, |f| f[5].chunk[0] ^= 1)), Some(5));
+        assert_eq!(rejected(retrieve_edited(300, |f| f[0].siblings[2][0] ^= 1)), Some(0));
+        assert_eq!(rejected(retrieve_edited(300, |f| f[4].siblings[1][0] ^= 1)), Some(4));
+        assert_eq!(rejected(retrieve_edited(300, |f| f.swap(3, 4))), Some(3));
+        assert_eq!(rejected(retrieve_edited(300, |f| f[2].chunk.push(0))), Some(2));
+        assert_eq!(rejected(retrieve_edited(300, |f| { f[6].siblings.pop(); })), Some(6));
+    }
+
+    #[test]
+    fn rejects_short_and_overlong_streams() {
+        assert!(matches!(retrieve_edited(300, |f| f.truncate(10)), Err(WitnessError::StreamIncomplete { received: 10, chunks: 19 })));
+        let (blob, root) = blob(70);
+        let mut verifier = StreamVerifier::new(&root, 70, 16);
+        for frame in ChunkStream::new(&blob, 16) {
+            verifier.push(frame).unwrap();
+        }
+        let extra = StreamFrame { index: 5, chunk: vec![0; 16], siblings: Vec::new() };
+        assert!(matches!(verifier.push(extra), Err(WitnessError::StreamTooLong(5))));
+        assert_eq!(verifier.finish().unwrap(), blob);
+    }
+}
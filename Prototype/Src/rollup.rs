@@ -0,0 +1,407 @@
+//! Rollup batching: updates to many accounts in one transaction under one two-level
+//! proof. Each account's chunks are proven against its stub's root as in a `ChunkTx`,
+//! and every stub against the state root (`StateTree`, one leaf per account) by its
+//! `AccountProof`. The state proofs are checked together, so the levels they share are
+//! hashed once, and the blockhash, replay and signature checks are paid once for the
+//! whole batch. The new state root is rebuilt from the same proofs over the rewritten
+//! stubs, which is all a verifier holding only the state root would need.
+
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+
+use anyhow::{Result, bail};
+use ed25519_dalek::SigningKey;
+
+use crate::archive::ArchiveNode;
+use crate::compute::ComputeMeter;
+use crate::error::WitnessError;
+use crate::events::WitnessEvent;
+use crate::metrics::metrics;
+use crate::multisig::{sign_message, verify_signatures};
+use crate::partial::{padded_root, ChunkTx, ChunkWrites};
+use crate::state::{account_leaf, AccountProof, StateTree};
+use crate::token::{ledger_blob, transfer_instruction, TokenProgram, RECORD_SIZE, TOKEN_PROGRAM_ID};
+use crate::view::ChunkWitness;
+use crate::{h, sha256, AccountStub, ChainState, MerkleTree};
+
+/// One account's part of a rollup
+#[derive(Debug, Clone)]
+pub struct RollupUpdate {
+    /// The account as the state root the batch starts from commits to it
+    pub account: AccountProof,
+    pub instruction_data: Vec<u8>,
+    pub chunks: Vec<ChunkWitness>,
+}
+
+/// Updates to distinct accounts owned by one program, applied together or not at all
+#[derive(Debug, Clone)]
+pub struct RollupTx {
+    pub program_id: String,
+    /// State root every `AccountProof` is against
+    pub state_root: [u8;32],
+    pub updates: Vec<RollupUpdate>,
+    pub recent_blockhash: [u8;32],
+    pub signatures: Vec<([u8;32], [u8;64])>,
+}
+
+impl RollupTx {
+    /// Hash over every field except the signatures. Every variable-length field and list
+    /// is length-prefixed, so no two txs encode alike.
+    pub fn id(&self) -> [u8;32] {
+        fn put(data: &mut Vec<u8>, bytes: &[u8]) {
+            data.extend_from_slice(&(bytes.len() as u64).to_le_bytes());
+            data.extend_from_slice(bytes);
+        }
+        let mut data = b"rollup-tx".to_vec();
+        put(&mut data, self.program_id.as_bytes());
+        data.extend_from_slice(&self.state_root);
+        data.extend_from_slice(&(self.updates.len() as u64).to_le_bytes());
+        for u in &self.updates {
+            put(&mut data, &account_leaf(&u.account.pubkey, &u.account.stub));
+            put(&mut data, &u.instruction_data);
+            data.extend_from_slice(&(u.chunks.len() as u64).to_le_bytes());
+            for c in &u.chunks {
+                data.extend_from_slice(&(c.leaf_index as u64).to_le_bytes());
+                put(&mut data, &c.chunk);
+                put(&mut data, &witness_core::encode_steps(&c.proof));
+            }
+        }
+        data.extend_from_slice(&self.recent_blockhash);
+        sha256(&data)
+    }
+
+    pub fn sign(&mut self, key: &SigningKey) {
+        let sig = sign_message(key, &self.id());
+        self.signatures.push(sig);
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct RollupReceipt {
+    pub compute_units: u64,
+    pub state_root: [u8;32],
+    /// (pubkey, new root) of every account in the batch, in batch order
+    pub new_roots: Vec<(String, [u8;32])>,
+    /// State tree nodes hashed per pass, against one proof's depth per account
+    pub state_nodes: usize,
+}
+
+/// Distinct nodes above `indices` in a tree of `depth` levels
+fn path_nodes(indices: impl IntoIterator<Item = usize>, depth: usize) -> usize {
+    let mut level: BTreeSet<usize> = indices.into_iter().collect();
+    let mut nodes = 0;
+    for _ in 0..depth {
+        level = level.iter().map(|i| i >> 1).collect();
+        nodes += level.len();
+    }
+    nodes
+}
+
+impl ChainState {
+    /// Apply a `RollupTx`. Emits what a `ChunkTx` does for every account in it on
+    /// success, `TxFailed` on any error.
+    pub fn process_rollup(&mut self, tx: &RollupTx) -> Result<RollupReceipt, WitnessError> {
+        let _span = tracing::debug_span!("rollup", program = %tx.program_id, updates = tx.updates.len()).entered();
+        let res = self.try_rollup(tx);
+        metrics().transactions.with_label_values(&[if res.is_ok() { "ok" } else { "failed" }]).inc();
+        if let Err(e) = &res {
+            tracing::info!(error = %e, "rollup failed");
+            let pubkey = tx.updates.first().map(|u| u.account.pubkey.clone()).unwrap_or_default();
+            self.emit(WitnessEvent::TxFailed { pubkey, reason: e.to_string() });
+        }
+        res
+    }
+
+    fn try_rollup(&mut self, tx: &RollupTx) -> Result<RollupReceipt, WitnessError> {
+        // the state proofs are against committed stubs only
+        if self.staging.is_some() {
+            return Err(WitnessError::BatchAlreadyOpen);
+        }
+        if tx.updates.is_empty() {
+            return Err(WitnessError::NoLeaves);
+        }
+        if !self.recent_blockhashes.contains(&tx.recent_blockhash) {
+            return Err(WitnessError::BlockhashNotFound(tx.recent_blockhash));
+        }
+        let tx_id = tx.id();
+        if self.is_processed(&tx.recent_blockhash, &tx_id) {
+            return Err(WitnessError::AlreadyProcessed(tx_id));
+        }
+        let costs = self.compute_costs.clone();
+        let mut meter = ComputeMeter::new(self.compute_budget);
+        meter.consume(costs.ed25519_verify * tx.signatures.len() as u64, "signature verification")?;
+        let signers = verify_signatures(&tx_id, &tx.signatures)?;
+
+        let state_root = self.state_root();
+        if tx.state_root != state_root {
+            return Err(WitnessError::StaleStateRoot { expected: state_root, batch: tx.state_root });
+        }
+        let accounts = self.stubs.len();
+        let depth = accounts.next_power_of_two().trailing_zeros() as usize;
+        let (mut seen, mut indices) = (HashSet::new(), HashSet::new());
+        let mut old_leaves = Vec::with_capacity(tx.updates.len());
+        for u in &tx.updates {
+            let AccountProof { pubkey, stub, proof } = &u.account;
+            if !seen.insert(pubkey.as_str()) {
+                return Err(WitnessError::DuplicateAccount(pubkey.clone()));
+            }
+            if proof.len() != depth {
+                return Err(WitnessError::ProofLength { pubkey: pubkey.clone(), expected: depth, actual: proof.len() });
+            }
+            let index = MerkleTree::proof_index(proof);
+            if index >= accounts {
+                return Err(WitnessError::ProofIndexOutOfRange { index, chunks: accounts });
+            }
+            // a second proof of one leaf would replace the first in the recomputation unchecked
+            if !indices.insert(index) {
+                return Err(WitnessError::DuplicateStateLeaf(index));
+            }
+            let leaf = account_leaf(pubkey, stub);
+            meter.consume(costs.mem_copy(leaf.len()) + costs.sha256(leaf.len()), "state leaf")?;
+            old_leaves.push((index, sha256(&leaf), proof.as_slice()));
+        }
+        let state_nodes = path_nodes(old_leaves.iter().map(|(i, _, _)| *i), depth);
+        meter.consume(state_nodes as u64 * costs.sha256(64), "state proof verification")?;
+        let computed = padded_root(accounts, &old_leaves)?;
+        if !witness_core::ct::eq(&computed, &state_root) {
+            return Err(WitnessError::StateProofFailed { expected: state_root, computed });
+        }
+
+        // the proven stubs are the committed ones, so each update runs as its chunk tx would
+        let mut executed: Vec<ChunkWrites> = Vec::with_capacity(tx.updates.len());
+        for u in &tx.updates {
+            executed.push(self.execute_chunks(&tx.program_id, &u.account.pubkey, &u.chunks, &u.instruction_data, &signers, &mut meter)?);
+        }
+        let mut new_leaves = Vec::with_capacity(tx.updates.len());
+        for ((index, _, proof), (u, writes)) in old_leaves.iter().zip(tx.updates.iter().zip(&executed)) {
+            let leaf = account_leaf(&u.account.pubkey, &writes.stub);
+            meter.consume(costs.sha256(leaf.len()), "state leaf")?;
+            new_leaves.push((*index, sha256(&leaf), *proof));
+        }
+        meter.consume(state_nodes as u64 * costs.sha256(64), "state root recomputation")?;
+        let new_state_root = padded_root(accounts, &new_leaves)?;
+
+        let mut new_roots = Vec::with_capacity(tx.updates.len());
+        for (u, writes) in tx.updates.iter().zip(executed) {
+            let root = self.apply_chunks(&u.account.pubkey, &u.chunks, writes);
+            new_roots.push((u.account.pubkey.clone(), root));
+        }
+        self.status_cache.entry(tx.recent_blockhash).or_default().insert(tx_id);
+        tracing::info!(slot = self.slot, accounts = new_roots.len(), old = %h(&state_root), new = %h(&new_state_root), "rollup applied");
+        Ok(RollupReceipt { compute_units: meter.consumed(), state_root: new_state_root, new_roots, state_nodes })
+    }
+}
+
+/// Collects one update per account and seals them into a `RollupTx` against the
+/// chain's current state
+pub struct Batcher {
+    program_id: String,
+    /// pubkey -> (instruction data, chunk indices)
+    pending: BTreeMap<String, (Vec<u8>, Vec<usize>)>,
+}
+
+impl Batcher {
+    pub fn new(program_id: &str) -> Self {
+        Self { program_id: program_id.to_string(), pending: BTreeMap::new() }
+    }
+
+    /// Queue `instruction_data` over chunks `leaves` of `pubkey`. Proofs are taken
+    /// against the state at `seal`, so an account takes one update per batch.
+    pub fn push(&mut self, pubkey: &str, instruction_data: Vec<u8>, leaves: Vec<usize>) -> Result<(), WitnessError> {
+        if self.pending.contains_key(pubkey) {
+            return Err(WitnessError::DuplicateAccount(pubkey.to_string()));
+        }
+        self.pending.insert(pubkey.to_string(), (instruction_data, leaves));
+        Ok(())
+    }
+
+    pub fn len(&self) -> usize {
+        self.pending.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pending.is_empty()
+    }
+
+    /// Build the batch from the chain's stubs and the archive's chunks, emptying the
+    /// queue. The result still needs the signatures its program asks for.
+    pub fn seal(&mut self, chain: &ChainState, archive: &ArchiveNode) -> Result<RollupTx, WitnessError> {
+        let tree = StateTree::build(&chain.stubs);
+        let mut updates = Vec::with_capacity(self.pending.len());
+        for (pubkey, (instruction_data, leaves)) in std::mem::take(&mut self.pending) {
+            let stub = match chain.stubs.get(&pubkey) {
+                Some(stub) => stub,
+                None => return Err(WitnessError::UnknownAccount(pubkey)),
+            };
+            let account = tree.prove(&pubkey, stub).unwrap();
+            let mut chunks = Vec::with_capacity(leaves.len());
+            for leaf in leaves {
+                match archive.get_chunk_witness(&pubkey, &stub.merkle_root, leaf) {
+                    Some(c) => chunks.push(c),
+                    None => return Err(WitnessError::ChunkNotArchived { pubkey, leaf_index: leaf, root: stub.merkle_root }),
+                }
+            }
+            updates.push(RollupUpdate { account, instruction_data, chunks });
+        }
+        Ok(RollupTx { program_id: self.program_id.clone(), state_root: tree.root(), updates, recent_blockhash: chain.latest_blockhash(), signatures: Vec::new() })
+    }
+}
+
+/// One token transfer in each of several ledgers, as a rollup and as separate chunk txs
+pub fn demo() -> Result<()> {
+    const LEDGERS: usize = 8;
+    const HOLDERS: u32 = 256;
+    let key = |i: u32| SigningKey::from_bytes(&sha256(format!("rollup-holder-{}", i).as_bytes()));
+    let setup = || -> Result<(ChainState, ArchiveNode)> {
+        let mut chain = ChainState::new();
+        let archive = ArchiveNode::new();
+        chain.subscribe(Box::new(archive.clone()));
+        chain.register_program(TOKEN_PROGRAM_ID, Box::new(TokenProgram));
+        let holders: Vec<([u8;32], u64)> = (0..HOLDERS).map(|i| (key(i).verifying_key().to_bytes(), 1_000)).collect();
+        let blob = ledger_blob(&holders);
+        for l in 0..LEDGERS {
+            let pubkey = format!("Ledger{}", l);
+            let root = archive.ingest(&pubkey, chain.slot, RECORD_SIZE, &blob)?;
+            chain.put_stub(&pubkey, AccountStub::new(TOKEN_PROGRAM_ID, 0, root, blob.len() as u64, RECORD_SIZE));
+        }
+        Ok((chain, archive))
+    };
+    // ledger l moves 10 tokens from holder l to holder l + 100
+    let transfer = |l: usize| (format!("Ledger{}", l), l as u32, l as u32 + 100);
+
+    let (mut chain, archive) = setup()?;
+    let mut batcher = Batcher::new(TOKEN_PROGRAM_ID);
+    for l in 0..LEDGERS {
+        let (pubkey, from, to) = transfer(l);
+        batcher.push(&pubkey, transfer_instruction(10), vec![from as usize, to as usize])?;
+    }
+    let queued = batcher.len();
+    let mut rollup = batcher.seal(&chain, &archive)?;
+    if !batcher.is_empty() {
+        bail!("sealing left {} updates queued", batcher.len());
+    }
+    for l in 0..LEDGERS {
+        rollup.sign(&key(transfer(l).1));
+    }
+    let receipt = chain.process_rollup(&rollup)?;
+    if receipt.state_root != chain.state_root() {
+        bail!("rollup state root does not match the applied state");
+    }
+    if receipt.new_roots.iter().any(|(pubkey, root)| chain.get_stub(pubkey).map(|s| s.merkle_root) != Some(*root)) {
+        bail!("rollup receipt roots do not match the stubs");
+    }
+    if chain.process_rollup(&rollup).is_ok() {
+        bail!("replayed rollup was accepted");
+    }
+
+    let (mut alone, archive) = setup()?;
+    let mut separate = 0;
+    for l in 0..LEDGERS {
+        let (pubkey, from, to) = transfer(l);
+        let root = alone.get_stub(&pubkey).unwrap().merkle_root;
+        let chunks = [from, to].iter().map(|&i| archive.get_chunk_witness(&pubkey, &root, i as usize).unwrap()).collect();
+        let mut tx = ChunkTx { program_id: TOKEN_PROGRAM_ID.to_string(), instruction_data: transfer_instruction(10), pubkey, chunks, recent_blockhash: alone.latest_blockhash(), signatures: Vec::new() };
+        tx.sign(&key(from));
+        separate += alone.process_chunk_tx(&tx)?.compute_units;
+    }
+    if alone.state_root() != chain.state_root() {
+        bail!("rollup and separate transfers disagree");
+    }
+    // both verify and rebuild the state root; alone, every tx hashes its whole path twice
+    let depth = LEDGERS.next_power_of_two().trailing_zeros() as usize;
+    let node = chain.compute_costs.sha256(64);
+    let shared = 2 * receipt.state_nodes as u64 * node;
+    let alone_proofs = 2 * (LEDGERS * depth) as u64 * node;
+    println!("Rollup of {} transfers over {} ledgers: {} CU in one tx, {} of it hashing {} shared state nodes",
+        queued, LEDGERS, receipt.compute_units, shared, receipt.state_nodes);
+    println!("  as {} chunk txs: {} CU trusting the stubs on chain; {} more to prove each against the state root alone",
+        LEDGERS, separate, alone_proofs);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HOLDERS: u32 = 3;
+
+    fn key(i: u32) -> SigningKey {
+        SigningKey::from_bytes(&sha256(format!("rollup-test-{}", i).as_bytes()))
+    }
+
+    /// Three ledgers of three holders each, so both trees pad
+    fn chain() -> (ChainState, ArchiveNode) {
+        let mut chain = ChainState::new();
+        let archive = ArchiveNode::new();
+        chain.subscribe(Box::new(archive.clone()));
+        chain.register_program(TOKEN_PROGRAM_ID, Box::new(TokenProgram));
+        let holders: Vec<([u8;32], u64)> = (0..HOLDERS).map(|i| (key(i).verifying_key().to_bytes(), 100)).collect();
+        let blob = ledger_blob(&holders);
+        for l in 0..3 {
+            let pubkey = format!("Ledger{}", l);
+            let root = archive.ingest(&pubkey, chain.slot, RECORD_SIZE, &blob).unwrap();
+            chain.put_stub(&pubkey, AccountStub::new(TOKEN_PROGRAM_ID, 0, root, blob.len() as u64, RECORD_SIZE));
+        }
+        (chain, archive)
+    }
+
+    /// Holder 2 pays holder 0 in every ledger
+    fn rollup(chain: &ChainState, archive: &ArchiveNode) -> RollupTx {
+        let mut batcher = Batcher::new(TOKEN_PROGRAM_ID);
+        for l in 0..3 {
+            batcher.push(&format!("Ledger{}", l), transfer_instruction(10), vec![2, 0]).unwrap();
+        }
+        batcher.seal(chain, archive).unwrap()
+    }
+
+    #[test]
+    fn applies_every_update_against_the_state_root() {
+        let (mut chain, archive) = chain();
+        let mut tx = rollup(&chain, &archive);
+        tx.sign(&key(2));
+        let receipt = chain.process_rollup(&tx).unwrap();
+        assert_eq!(receipt.state_root, chain.state_root());
+        for (pubkey, root) in &receipt.new_roots {
+            let blob = archive.get_blob(pubkey, root).unwrap().data;
+            assert_eq!(crate::token::decode_record(&blob[2 * RECORD_SIZE..]).unwrap().1, 90);
+            assert_eq!(chain.get_stub(pubkey).unwrap().merkle_root, *root);
+        }
+        assert!(matches!(chain.process_rollup(&tx), Err(WitnessError::AlreadyProcessed(_))));
+    }
+
+    #[test]
+    fn rejects_a_leaf_proven_twice() {
+        let (mut chain, archive) = chain();
+        let mut tx = rollup(&chain, &archive);
+        // the forged first update borrows the proof the genuine second one checks
+        tx.updates[0].account.proof = tx.updates[1].account.proof.clone();
+        tx.sign(&key(2));
+        let index = MerkleTree::proof_index(&tx.updates[1].account.proof);
+        assert!(matches!(chain.process_rollup(&tx), Err(WitnessError::DuplicateStateLeaf(i)) if i == index));
+        let mut twice = rollup(&chain, &archive);
+        twice.updates.push(twice.updates[0].clone());
+        assert!(matches!(chain.process_rollup(&twice), Err(WitnessError::DuplicateAccount(_))));
+    }
+
+    #[test]
+    fn rejects_unsigned_and_stale_rollups() {
+        let (mut chain, archive) = chain();
+        let unsigned = rollup(&chain, &archive);
+        assert!(matches!(chain.process_rollup(&unsigned), Err(WitnessError::Rejected(_))));
+        let mut stale = rollup(&chain, &archive);
+        stale.state_root[0] ^= 1;
+        stale.sign(&key(2));
+        assert!(matches!(chain.process_rollup(&stale), Err(WitnessError::StaleStateRoot { .. })));
+    }
+
+    #[test]
+    fn id_separates_every_field() {
+        let (chain, archive) = chain();
+        let tx = rollup(&chain, &archive);
+        // unprefixed, a proof step reads the same as the end of a longer chunk
+        let mut moved = tx.clone();
+        let c = &mut moved.updates[0].chunks[0];
+        let step = c.proof.pop().unwrap();
+        c.chunk.extend_from_slice(&witness_core::encode_steps(&[step]));
+        assert_ne!(tx.id(), moved.id());
+    }
+}
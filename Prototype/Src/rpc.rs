@@ -0,0 +1,289 @@
+use anyhow::{Context, Result, bail};
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+use serde_json::{json, Value};
+
+use crate::archive::ArchiveNode;
+use crate::indexer::Indexer;
+use crate::program::DemoProgram;
+use crate::pubsub::{PubSub, SubscriptionFilter};
+use crate::{chunk_blob, h, AccountStub, AccountWitness, ChainState, MerkleTree, WitnessTx};
+
+// JSON-RPC 2.0 error codes, plus Solana's code for a transaction that failed to apply
+const PARSE_ERROR: i64 = -32700;
+const INVALID_REQUEST: i64 = -32600;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+const TX_FAILED: i64 = -32002;
+
+/// Method failure carried back to the caller as a JSON-RPC error object
+struct RpcError {
+    code: i64,
+    message: String,
+}
+
+fn invalid_params(e: anyhow::Error) -> RpcError {
+    RpcError { code: INVALID_PARAMS, message: format!("{:#}", e) }
+}
+
+fn error_response(id: Value, code: i64, message: &str) -> Value {
+    json!({ "jsonrpc": "2.0", "error": { "code": code, "message": message }, "id": id })
+}
+
+fn hex32(v: &Value, what: &str) -> Result<[u8;32]> {
+    let s = v.as_str().with_context(|| format!("{} must be a hex string", what))?;
+    let bytes = hex::decode(s).with_context(|| format!("{} is not hex", what))?;
+    match bytes.try_into() {
+        Ok(b) => Ok(b),
+        Err(_) => bail!("{} must be 32 bytes", what),
+    }
+}
+
+fn hex_bytes(v: &Value, what: &str) -> Result<Vec<u8>> {
+    hex::decode(v.as_str().unwrap_or_default()).with_context(|| format!("{} is not hex", what))
+}
+
+fn proof_json(proof: &[([u8;32], bool)]) -> Value {
+    proof.iter().map(|(sibling, is_left)| json!({ "sibling": hex::encode(sibling), "isLeft": is_left })).collect()
+}
+
+fn proof_from_json(v: &Value) -> Result<Vec<([u8;32], bool)>> {
+    let steps = v.as_array().context("proof must be an array")?;
+    steps.iter().map(|s| Ok((hex32(&s["sibling"], "proof sibling")?, s["isLeft"].as_bool().context("proof isLeft must be a bool")?))).collect()
+}
+
+/// `AccountWitness` in the wire format `getWitness` returns and `sendWitnessTransaction` takes
+fn witness_json(w: &AccountWitness) -> Value {
+    json!({
+        "pubkey": w.pubkey,
+        "blob": hex::encode(&w.blob),
+        "leafIndex": w.leaf_index,
+        "proof": proof_json(&w.proof),
+        "isWritable": w.is_writable,
+    })
+}
+
+fn witness_from_json(v: &Value) -> Result<AccountWitness> {
+    Ok(AccountWitness {
+        pubkey: v["pubkey"].as_str().context("witness pubkey")?.to_string(),
+        blob: hex_bytes(&v["blob"], "witness blob")?,
+        leaf_index: v["leafIndex"].as_u64().context("witness leafIndex")? as usize,
+        proof: proof_from_json(&v["proof"])?,
+        is_writable: v["isWritable"].as_bool().unwrap_or(true),
+    })
+}
+
+/// Transaction object of `sendWitnessTransaction`; field names follow Solana's camelCase
+pub fn tx_from_json(v: &Value) -> Result<WitnessTx> {
+    let witnesses = v["witnesses"].as_array().context("witnesses must be an array")?;
+    let signatures = match v["signatures"].as_array() {
+        Some(sigs) => sigs.iter().map(|s| {
+            let sig = hex_bytes(&s["signature"], "signature")?;
+            match sig.try_into() {
+                Ok(sig) => Ok((hex32(&s["signer"], "signer")?, sig)),
+                Err(_) => bail!("signature must be 64 bytes"),
+            }
+        }).collect::<Result<_>>()?,
+        None => Vec::new(),
+    };
+    Ok(WitnessTx {
+        program_id: v["programId"].as_str().context("programId")?.to_string(),
+        instruction_data: hex_bytes(&v["instructionData"], "instructionData")?,
+        witnesses: witnesses.iter().map(witness_from_json).collect::<Result<_>>()?,
+        recent_blockhash: hex32(&v["recentBlockhash"], "recentBlockhash")?,
+        priority_fee: v["priorityFee"].as_u64().unwrap_or(0),
+        signatures,
+    })
+}
+
+pub fn tx_to_json(tx: &WitnessTx) -> Value {
+    json!({
+        "programId": tx.program_id,
+        "instructionData": hex::encode(&tx.instruction_data),
+        "witnesses": tx.witnesses.iter().map(witness_json).collect::<Vec<_>>(),
+        "recentBlockhash": hex::encode(tx.recent_blockhash),
+        "priorityFee": tx.priority_fee,
+        "signatures": tx.signatures.iter().map(|(signer, sig)| json!({ "signer": hex::encode(signer), "signature": hex::encode(sig) })).collect::<Vec<_>>(),
+    })
+}
+
+/// JSON-RPC 2.0 front end over a chain, shaped after Solana's RPC: account reads
+/// return `{ context: { slot }, value }`, transactions are identified by their id,
+/// and an array body is a batch answered by an array. The archive and indexer
+/// should be subscribed to `chain` so witnesses follow the transactions sent here.
+pub struct RpcServer {
+    chain: ChainState,
+    archive: ArchiveNode,
+    indexer: Indexer,
+    pubsub: Option<PubSub>,
+}
+
+impl RpcServer {
+    pub(crate) fn new(chain: ChainState, archive: ArchiveNode, indexer: Indexer) -> Self {
+        Self { chain, archive, indexer, pubsub: None }
+    }
+
+    /// Keep `pubsub` (subscribed to the chain) told about owners and flushed after
+    /// every request, so root updates from transactions sent here reach subscribers.
+    /// Current blobs the archive holds are tracked so pushes list changed leaves.
+    pub fn with_pubsub(mut self, pubsub: PubSub) -> Self {
+        pubsub.sync(&self.chain);
+        for (pubkey, stub) in &self.chain.stubs {
+            if let Some(blob) = self.archive.get_blob(pubkey, &stub.merkle_root) {
+                pubsub.track_blob(pubkey, blob.chunk_size, &blob.data);
+            }
+        }
+        self.pubsub = Some(pubsub);
+        self
+    }
+
+    #[cfg(feature = "http")]
+    pub(crate) fn chain(&self) -> &ChainState {
+        &self.chain
+    }
+
+    /// Answer one request body; `None` when it held only notifications
+    pub fn handle(&mut self, body: &str) -> Option<String> {
+        let response = match serde_json::from_str::<Value>(body) {
+            Err(e) => Some(error_response(Value::Null, PARSE_ERROR, &e.to_string())),
+            Ok(Value::Array(batch)) if batch.is_empty() => Some(error_response(Value::Null, INVALID_REQUEST, "empty batch")),
+            Ok(Value::Array(batch)) => {
+                let responses: Vec<Value> = batch.iter().filter_map(|r| self.handle_one(r)).collect();
+                (!responses.is_empty()).then_some(Value::Array(responses))
+            }
+            Ok(request) => self.handle_one(&request),
+        };
+        if let Some(pubsub) = &self.pubsub {
+            pubsub.flush();
+        }
+        response.map(|r| r.to_string())
+    }
+
+    fn handle_one(&mut self, request: &Value) -> Option<Value> {
+        let id = request.get("id").cloned();
+        let method = match (request["jsonrpc"].as_str(), request["method"].as_str()) {
+            (Some("2.0"), Some(m)) => m,
+            _ => return Some(error_response(id.unwrap_or(Value::Null), INVALID_REQUEST, "expected a JSON-RPC 2.0 request")),
+        };
+        let params = request.get("params").cloned().unwrap_or(Value::Array(Vec::new()));
+        let result = self.call(method, &params);
+        // requests without an id are notifications: run them, reply with nothing
+        let id = id?;
+        Some(match result {
+            Ok(result) => json!({ "jsonrpc": "2.0", "result": result, "id": id }),
+            Err(e) => error_response(id, e.code, &e.message),
+        })
+    }
+
+    fn call(&mut self, method: &str, params: &Value) -> Result<Value, RpcError> {
+        let param = |i: usize| params.get(i).cloned().unwrap_or(Value::Null);
+        match method {
+            "getCompressedAccount" => {
+                let pubkey = param(0).as_str().map(str::to_string).ok_or_else(|| invalid_params(anyhow::anyhow!("expected [pubkey]")))?;
+                let value = self.chain.get_stub(&pubkey).map(|stub| json!({
+                    "owner": stub.owner,
+                    "lamports": stub.lamports,
+                    "merkleRoot": hex::encode(stub.merkle_root),
+                    "executable": stub.executable,
+                    "dataLen": stub.data_len,
+                    "rentEpoch": stub.rent_epoch,
+                    "chunkSize": stub.chunk_size,
+                    "locations": self.indexer.locate(&pubkey),
+                }));
+                Ok(self.with_context(value.unwrap_or(Value::Null)))
+            }
+            "getWitness" => {
+                let (pubkey, leaf) = match (param(0).as_str(), param(1).as_u64()) {
+                    (Some(p), Some(l)) => (p.to_string(), l as usize),
+                    _ => return Err(invalid_params(anyhow::anyhow!("expected [pubkey, leafIndex]"))),
+                };
+                let value = self.chain.get_stub(&pubkey)
+                    .and_then(|stub| self.archive.get_witness(&pubkey, &stub.merkle_root, leaf))
+                    .map_or(Value::Null, |w| witness_json(&w));
+                Ok(self.with_context(value))
+            }
+            "sendWitnessTransaction" => {
+                // either the JSON form or, as a string, base64 of the Borsh encoding
+                let tx = match param(0).as_str() {
+                    Some(encoded) => STANDARD.decode(encoded).context("transaction is not base64")
+                        .and_then(|bytes| borsh::from_slice(&bytes).context("transaction is not a Borsh WitnessTx")),
+                    None => tx_from_json(&param(0)),
+                }.map_err(invalid_params)?;
+                if let Some(pubsub) = &self.pubsub {
+                    pubsub.sync(&self.chain);
+                }
+                match self.chain.process_tx_witness(&tx) {
+                    Ok(_) => Ok(json!(hex::encode(tx.id()))),
+                    Err(e) => Err(RpcError { code: TX_FAILED, message: format!("transaction failed: {:#}", e) }),
+                }
+            }
+            "getStateRoot" => Ok(self.with_context(json!(hex::encode(self.chain.state_root())))),
+            "getLatestBlockhash" => Ok(self.with_context(json!({ "blockhash": hex::encode(self.chain.latest_blockhash()) }))),
+            _ => Err(RpcError { code: METHOD_NOT_FOUND, message: format!("method {} not found", method) }),
+        }
+    }
+
+    fn with_context(&self, value: Value) -> Value {
+        json!({ "context": { "slot": self.chain.slot }, "value": value })
+    }
+}
+
+/// Drive the RPC the way a client would: one batch to fetch a blockhash and a
+/// witness, then a transaction assembled purely from those responses
+pub fn demo() -> Result<()> {
+    let blob = b"Compressed account served over JSON-RPC; clients fetch witnesses and send txs.".to_vec();
+    let chunk_size = 32;
+    let mut chain = ChainState::new();
+    chain.register_program("WitnessProgram1", Box::new(DemoProgram));
+    let root = MerkleTree::from_chunks(&chunk_blob(&blob, chunk_size)).root();
+    chain.put_stub("RpcAcct", AccountStub::new("WitnessProgram1", 1_000, root, blob.len() as u64, chunk_size));
+    let archive = ArchiveNode::new();
+    archive.ingest("RpcAcct", chain.slot, chunk_size, &blob)?;
+    let indexer = Indexer::new().with_location("rpc:getWitness");
+    chain.subscribe(Box::new(archive.clone()));
+    chain.subscribe(Box::new(indexer.clone()));
+    let pubsub = PubSub::new();
+    chain.subscribe(Box::new(pubsub.clone()));
+    indexer.sync(&chain);
+    let (notify, notifications) = std::sync::mpsc::channel();
+    pubsub.subscribe(SubscriptionFilter::Owner("WitnessProgram1".to_string()), Box::new(move |_, n| notify.send(n.clone()).is_ok()));
+    let (account_notify, account_notifications) = std::sync::mpsc::channel();
+    let account_sub = pubsub.subscribe(SubscriptionFilter::Account("RpcAcct".to_string()), Box::new(move |_, n| account_notify.send(n.clone()).is_ok()));
+    let mut rpc = RpcServer::new(chain, archive, indexer).with_pubsub(pubsub.clone());
+
+    let batch = json!([
+        { "jsonrpc": "2.0", "id": 1, "method": "getLatestBlockhash" },
+        { "jsonrpc": "2.0", "id": 2, "method": "getWitness", "params": ["RpcAcct", 0] },
+        { "jsonrpc": "2.0", "id": 3, "method": "getCompressedAccount", "params": ["RpcAcct"] },
+    ]);
+    let responses: Value = serde_json::from_str(&rpc.handle(&batch.to_string()).context("batch had no response")?)?;
+    println!("Batch of 3 answered with {} responses", responses.as_array().map_or(0, Vec::len));
+    let blockhash = &responses[0]["result"]["value"]["blockhash"];
+    let witness = &responses[1]["result"]["value"];
+    println!("getCompressedAccount: root {} at slot {}", responses[2]["result"]["value"]["merkleRoot"], responses[2]["result"]["context"]["slot"]);
+
+    let tx = json!({ "programId": "WitnessProgram1", "instructionData": "", "witnesses": [witness], "recentBlockhash": blockhash });
+    let send = json!({ "jsonrpc": "2.0", "id": 4, "method": "sendWitnessTransaction", "params": [tx] });
+    println!("sendWitnessTransaction -> {}", rpc.handle(&send.to_string()).unwrap_or_default());
+    for n in notifications.try_iter() {
+        println!("Subscription push: {} root {} -> {} at slot {}, changed leaves {:?}", n.pubkey, h(&n.old_root), h(&n.root), n.slot, n.changed_leaves);
+    }
+    println!("RpcAcct subscription got {} push(es); unsubscribed: {}", account_notifications.try_iter().count(), pubsub.unsubscribe(account_sub));
+    // the same tx again, Borsh-encoded, is a replay; an unknown method and a
+    // notification round it out
+    let borsh_tx = STANDARD.encode(borsh::to_vec(&tx_from_json(&tx)?)?);
+    let batch = json!([
+        { "jsonrpc": "2.0", "id": 4, "method": "sendWitnessTransaction", "params": [borsh_tx] },
+        { "jsonrpc": "2.0", "id": 5, "method": "getStateRoot" },
+        { "jsonrpc": "2.0", "id": 6, "method": "getBalance", "params": ["RpcAcct"] },
+        { "jsonrpc": "2.0", "method": "getStateRoot" },
+    ]);
+    let responses: Value = serde_json::from_str(&rpc.handle(&batch.to_string()).context("batch had no response")?)?;
+    for r in responses.as_array().into_iter().flatten() {
+        match r.get("error") {
+            Some(e) => println!("  id {}: error {} {}", r["id"], e["code"], e["message"]),
+            None => println!("  id {}: {}", r["id"], r["result"]),
+        }
+    }
+    Ok(())
+}
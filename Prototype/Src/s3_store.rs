@@ -0,0 +1,135 @@
+use std::thread;
+use std::time::Duration;
+
+use anyhow::{Context, Result, bail};
+use object_store::path::Path;
+use object_store::{Error as ObjectError, ObjectStore, PutPayload};
+use tokio::runtime::Runtime;
+use url::Url;
+
+use crate::blob_store::BlobStore;
+use crate::{chunk_blob, h, MerkleTree};
+
+/// Retry and integrity settings for `ObjectBlobStore`
+#[derive(Debug, Clone)]
+pub struct ObjectStoreConfig {
+    /// Chunk size the deployment commits blobs with; fetched bytes must rebuild the root
+    pub chunk_size: usize,
+    /// Attempts per operation before giving up (at least 1)
+    pub max_attempts: u32,
+    /// Delay before the first retry; doubled after each failed attempt
+    pub backoff: Duration,
+}
+
+impl ObjectStoreConfig {
+    pub fn new(chunk_size: usize) -> Self {
+        Self { chunk_size, max_attempts: 4, backoff: Duration::from_millis(100) }
+    }
+}
+
+/// Blobs in S3, GCS or any S3-compatible service (MinIO, R2, ...), one object per root
+/// under the URL's path, e.g. `s3://bucket/witness-blobs/<hex root>`. Credentials and
+/// endpoints come from `options` (`aws_access_key_id`, `aws_endpoint`, ...) or the
+/// usual environment variables.
+#[derive(Debug)]
+pub struct ObjectBlobStore {
+    store: Box<dyn ObjectStore>,
+    prefix: Path,
+    config: ObjectStoreConfig,
+    runtime: Runtime,
+}
+
+impl ObjectBlobStore {
+    pub fn new(url: &str, options: &[(&str, &str)], config: ObjectStoreConfig) -> Result<Self> {
+        if config.chunk_size == 0 || config.max_attempts == 0 {
+            bail!("object store needs a chunk size and at least one attempt");
+        }
+        let url = Url::parse(url).with_context(|| format!("object store URL {}", url))?;
+        let (store, prefix) = object_store::parse_url_opts(&url, options.iter().map(|(k, v)| (k.to_ascii_lowercase(), *v)))
+            .with_context(|| format!("opening object store {}", url))?;
+        let runtime = tokio::runtime::Builder::new_current_thread().enable_all().build()?;
+        Ok(Self { store, prefix, config, runtime })
+    }
+
+    fn path(&self, root: &[u8;32]) -> Path {
+        self.prefix.child(hex::encode(root))
+    }
+
+    fn root_of(&self, bytes: &[u8]) -> [u8;32] {
+        MerkleTree::from_chunks(&chunk_blob(bytes, self.config.chunk_size)).root()
+    }
+
+    /// Run `op` until it succeeds, it reports a missing object, or attempts run out
+    fn retry<T>(&self, what: &str, root: &[u8;32], mut op: impl FnMut() -> Result<Option<T>>) -> Result<Option<T>> {
+        let mut delay = self.config.backoff;
+        let mut attempt = 1;
+        loop {
+            match op() {
+                Ok(v) => return Ok(v),
+                Err(e) if attempt >= self.config.max_attempts => {
+                    return Err(e).with_context(|| format!("{} blob {} failed after {} attempts", what, h(root), attempt));
+                }
+                Err(_) => {
+                    thread::sleep(delay);
+                    delay *= 2;
+                    attempt += 1;
+                }
+            }
+        }
+    }
+}
+
+impl BlobStore for ObjectBlobStore {
+    fn put(&self, root: &[u8;32], bytes: &[u8]) -> Result<()> {
+        // refuse to publish bytes that fetchers would reject anyway
+        if self.root_of(bytes) != *root {
+            bail!("blob does not hash to {} with chunk size {}", h(root), self.config.chunk_size);
+        }
+        let path = self.path(root);
+        self.retry("uploading", root, || {
+            self.runtime.block_on(self.store.put(&path, PutPayload::from(bytes.to_vec())))?;
+            Ok(Some(()))
+        })?;
+        Ok(())
+    }
+
+    /// A fetch whose bytes don't rebuild `root` (torn read, bit rot, tampering) counts as a
+    /// failed attempt and is retried
+    fn get(&self, root: &[u8;32]) -> Result<Option<Vec<u8>>> {
+        let path = self.path(root);
+        self.retry("fetching", root, || {
+            let bytes = match self.runtime.block_on(async { self.store.get(&path).await?.bytes().await }) {
+                Ok(b) => b.to_vec(),
+                Err(ObjectError::NotFound { .. }) => return Ok(None),
+                Err(e) => return Err(e.into()),
+            };
+            if self.root_of(&bytes) != *root {
+                bail!("fetched bytes do not match root {}", h(root));
+            }
+            Ok(Some(bytes))
+        })
+    }
+
+    fn has(&self, root: &[u8;32]) -> Result<bool> {
+        let path = self.path(root);
+        let found = self.retry("checking", root, || match self.runtime.block_on(self.store.head(&path)) {
+            Ok(_) => Ok(Some(true)),
+            Err(ObjectError::NotFound { .. }) => Ok(Some(false)),
+            Err(e) => Err(e.into()),
+        })?;
+        Ok(found.unwrap_or(false))
+    }
+
+    fn delete(&self, root: &[u8;32]) -> Result<()> {
+        let path = self.path(root);
+        self.retry("deleting", root, || match self.runtime.block_on(self.store.delete(&path)) {
+            Ok(()) | Err(ObjectError::NotFound { .. }) => Ok(Some(())),
+            Err(e) => Err(e.into()),
+        })?;
+        Ok(())
+    }
+
+    fn checks_roots(&self) -> bool {
+        true
+    }
+}
@@ -0,0 +1,228 @@
+//! `simulate --slots`: a workload run slot by slot with and without witness offloading,
+//! and what each costs. The offloaded side is the real chain: stubs on chain, every
+//! update carrying its account and a proof, every read its chunks, all verified and
+//! metered. The other side is Solana as it is: the same ops against accounts that hold
+//! their data, where a creation carries the data and an update or read only its
+//! instruction, and nothing is verified or kept off chain.
+
+use std::path::Path;
+use std::time::Instant;
+
+use anyhow::{Result, bail};
+
+use crate::rent::ACCOUNT_STORAGE_OVERHEAD;
+use crate::sim_metrics::{Export, ExportFormat, TxRecord};
+use crate::workload::{Driver, Op, OpKind, Workload, WorkloadConfig, WorkloadStats};
+use crate::ChainState;
+
+/// Slots run when a workload is given without `--slots`
+pub const DEFAULT_SLOTS: u64 = 10;
+
+/// What one way of holding the accounts costs
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Side {
+    /// Account bytes on chain, with Solana's per-account overhead
+    pub onchain_bytes: u64,
+    /// Bytes carried by transactions so far
+    pub tx_bytes: u64,
+    /// Compute units spent verifying witnesses so far
+    pub compute_units: u64,
+    /// Bytes kept off chain to build witnesses: the data and every tree node
+    pub prover_bytes: u64,
+}
+
+/// Both sides at the end of a slot
+#[derive(Debug, Clone)]
+pub struct SlotReport {
+    pub slot: u64,
+    pub ops: u64,
+    pub failed: u64,
+    pub accounts: usize,
+    pub onchain: Side,
+    pub offloaded: Side,
+}
+
+#[derive(Debug, Clone)]
+pub struct SavingsReport {
+    pub chunk_size: usize,
+    /// Accounts created before the first slot
+    pub genesis: SlotReport,
+    pub slots: Vec<SlotReport>,
+    pub stats: WorkloadStats,
+}
+
+/// Nodes of the padded binary tree over `data_len` bytes, leaves included, times 32
+fn tree_bytes(data_len: usize, chunk_size: usize) -> u64 {
+    let leaves = data_len.div_ceil(chunk_size).max(1).next_power_of_two() as u64;
+    (2 * leaves - 1) * 32
+}
+
+/// Transaction bytes of `op` when accounts hold their data
+fn onchain_tx_bytes(op: &Op) -> u64 {
+    match op {
+        Op::Create { data, .. } => data.len() as u64,
+        Op::Update { bytes, .. } => 8 + bytes.len() as u64,
+        Op::Read { .. } => 16,
+    }
+}
+
+struct Run<'a> {
+    chain: ChainState,
+    driver: Driver,
+    onchain_tx_bytes: u64,
+    export: Option<&'a mut Export>,
+    /// Ops applied so far
+    seq: u64,
+}
+
+impl Run<'_> {
+    fn apply(&mut self, op: &Op) -> Result<()> {
+        let onchain = onchain_tx_bytes(op);
+        self.onchain_tx_bytes += onchain;
+        let stats = &self.driver.stats;
+        let (failed, tx_bytes, compute_units) = (stats.failed, stats.tx_bytes, stats.compute_units);
+        let start = Instant::now();
+        self.driver.apply(&mut self.chain, op)?;
+        let latency_us = start.elapsed().as_micros() as u64;
+        if let Some(export) = self.export.as_deref_mut() {
+            let stats = &self.driver.stats;
+            export.tx(&TxRecord {
+                slot: self.chain.slot,
+                seq: self.seq,
+                kind: op.kind(),
+                pubkey: op.pubkey().to_string(),
+                ok: stats.failed == failed,
+                onchain_tx_bytes: onchain,
+                offloaded_tx_bytes: stats.tx_bytes - tx_bytes,
+                compute_units: stats.compute_units - compute_units,
+                latency_us,
+            })?;
+        }
+        self.seq += 1;
+        Ok(())
+    }
+
+    /// Record a finished slot with the export, if any
+    fn export_slot(&mut self, report: &SlotReport, start: Instant) -> Result<()> {
+        match self.export.as_deref_mut() {
+            Some(export) => export.slot(report, start.elapsed().as_micros() as u64),
+            None => Ok(()),
+        }
+    }
+
+    fn report(&self, slot: u64, ops: u64, failed_before: u64) -> SlotReport {
+        let blobs = &self.driver.blobs;
+        let data: u64 = blobs.values().map(|b| b.len() as u64).sum();
+        let overhead = blobs.len() as u64 * ACCOUNT_STORAGE_OVERHEAD;
+        let stubs: u64 = self.chain.stubs.values().map(|s| s.encode().len() as u64).sum();
+        let stats = &self.driver.stats;
+        SlotReport {
+            slot,
+            ops,
+            failed: stats.failed - failed_before,
+            accounts: blobs.len(),
+            onchain: Side { onchain_bytes: data + overhead, tx_bytes: self.onchain_tx_bytes, compute_units: 0, prover_bytes: 0 },
+            offloaded: Side {
+                onchain_bytes: stubs + overhead,
+                tx_bytes: stats.tx_bytes,
+                compute_units: stats.compute_units,
+                prover_bytes: blobs.values().map(|b| b.len() as u64 + tree_bytes(b.len(), self.driver.chunk_size)).sum(),
+            },
+        }
+    }
+}
+
+/// Create `cfg`'s initial accounts, then run `slots` slots of `ops_per_slot` ops each,
+/// advancing the chain a slot after each. `cfg.ops` is ignored. Every op and slot is
+/// also written to `export`, if given, as it finishes.
+pub fn simulate(cfg: WorkloadConfig, slots: u64, ops_per_slot: usize, chunk_size: usize, export: Option<&mut Export>) -> Result<SavingsReport> {
+    if chunk_size == 0 {
+        bail!("chunk size must be positive");
+    }
+    let initial = cfg.initial_accounts;
+    let ops = usize::try_from(slots).ok().and_then(|s| s.checked_mul(ops_per_slot));
+    let mut workload = match ops {
+        Some(ops) => Workload::new(WorkloadConfig { ops, ..cfg })?,
+        None => bail!("{} slots of {} ops is too many", slots, ops_per_slot),
+    };
+    let mut chain = ChainState::new();
+    let driver = Driver::new(&mut chain, chunk_size);
+    let mut run = Run { chain, driver, onchain_tx_bytes: 0, export, seq: 0 };
+
+    let start = Instant::now();
+    for op in workload.by_ref().take(initial) {
+        run.apply(&op)?;
+    }
+    let genesis = run.report(0, initial as u64, 0);
+    run.export_slot(&genesis, start)?;
+    run.chain.advance_slot();
+    let mut reports = Vec::new();
+    for _ in 0..slots {
+        let failed_before = run.driver.stats.failed;
+        let start = Instant::now();
+        let mut ops = 0;
+        for op in workload.by_ref().take(ops_per_slot) {
+            run.apply(&op)?;
+            ops += 1;
+        }
+        let report = run.report(run.chain.slot, ops, failed_before);
+        run.export_slot(&report, start)?;
+        reports.push(report);
+        run.chain.advance_slot();
+    }
+    Ok(SavingsReport { chunk_size, genesis, slots: reports, stats: run.driver.stats })
+}
+
+/// `offloaded` relative to `onchain`, as the percentage saved or added
+fn change(onchain: u64, offloaded: u64) -> String {
+    match onchain {
+        0 if offloaded == 0 => "same".to_string(),
+        0 => "all added".to_string(),
+        _ if offloaded <= onchain => format!("{:.1}% saved", 100.0 * (onchain - offloaded) as f64 / onchain as f64),
+        _ => format!("{:.1}x", offloaded as f64 / onchain as f64),
+    }
+}
+
+impl SavingsReport {
+    pub fn last(&self) -> &SlotReport {
+        self.slots.last().unwrap_or(&self.genesis)
+    }
+
+    /// A line per slot, then both sides at the end
+    pub fn summary(&self) -> String {
+        let mut out = String::new();
+        for s in &self.slots {
+            out += &format!("slot {:>4}: {:>5} ops, {:>3} failed, {:>5} accounts, on chain {:>10} vs {:>8} bytes\n",
+                s.slot, s.ops, s.failed, s.accounts, s.onchain.onchain_bytes, s.offloaded.onchain_bytes);
+        }
+        let stats = &self.stats;
+        let last = self.last();
+        out += &format!("\n{} slots, {} ops ({} creates, {} small and {} large updates, {} reads, {} failed), {} accounts, {}-byte chunks\n",
+            self.slots.len(), stats.total(), stats.count(OpKind::Create), stats.count(OpKind::SmallUpdate), stats.count(OpKind::LargeUpdate),
+            stats.count(OpKind::Read), stats.failed, last.accounts, self.chunk_size);
+        out += &format!("{:<22} {:>14} {:>14}   {}\n", "", "data on chain", "offloaded", "change");
+        let rows = [
+            ("on-chain bytes", last.onchain.onchain_bytes, last.offloaded.onchain_bytes),
+            ("transaction bytes", last.onchain.tx_bytes, last.offloaded.tx_bytes),
+            ("verification CU", last.onchain.compute_units, last.offloaded.compute_units),
+            ("prover storage bytes", last.onchain.prover_bytes, last.offloaded.prover_bytes),
+        ];
+        for (name, onchain, offloaded) in rows {
+            out += &format!("{:<22} {:>14} {:>14}   {}\n", name, onchain, offloaded, change(onchain, offloaded));
+        }
+        out
+    }
+}
+
+/// `simulate --slots`: run, exporting metrics to `export_dir` if given, then print the
+/// summary
+pub fn run(cfg: WorkloadConfig, slots: u64, ops_per_slot: usize, chunk_size: usize, export_dir: Option<&Path>, format: ExportFormat) -> Result<()> {
+    let mut export = export_dir.map(|dir| Export::create(dir, format)).transpose()?;
+    let report = simulate(cfg, slots, ops_per_slot, chunk_size, export.as_mut())?;
+    print!("{}", report.summary());
+    if let Some(export) = export {
+        let [slots, txs] = export.finish()?;
+        println!("Metrics written to {} and {}", slots.display(), txs.display());
+    }
+    Ok(())
+}
@@ -0,0 +1,270 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::ops::Range;
+
+use anyhow::Result;
+
+use crate::error::WitnessError;
+use crate::view::ChunkWitness;
+use crate::{chunk_blob, MerkleTree};
+
+/// Borsh field types the schema layer understands
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FieldType {
+    Bool,
+    U8,
+    U16,
+    U32,
+    U64,
+    U128,
+    /// 32-byte address
+    Pubkey,
+    /// `[u8; N]`
+    Array(usize),
+    /// u32 length prefix, then UTF-8 bytes
+    String,
+    /// u32 length prefix, then raw bytes
+    Bytes,
+}
+
+impl FieldType {
+    /// Encoded size if it doesn't depend on the data
+    fn fixed_len(&self) -> Option<usize> {
+        match self {
+            FieldType::Bool | FieldType::U8 => Some(1),
+            FieldType::U16 => Some(2),
+            FieldType::U32 => Some(4),
+            FieldType::U64 => Some(8),
+            FieldType::U128 => Some(16),
+            FieldType::Pubkey => Some(32),
+            FieldType::Array(n) => Some(*n),
+            FieldType::String | FieldType::Bytes => None,
+        }
+    }
+}
+
+/// A decoded field
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FieldValue {
+    Bool(bool),
+    Uint(u128),
+    Bytes(Vec<u8>),
+    Str(String),
+}
+
+/// Proof that an account blob holds a value in field `field`: the chunks covering the
+/// field plus those covering every length prefix before it, each proven against the root
+#[derive(Debug, Clone)]
+pub struct FieldProof {
+    pub field: String,
+    pub chunks: Vec<ChunkWitness>,
+}
+
+/// Layout of a Borsh-serialized struct, fields in declaration order
+#[derive(Debug, Clone)]
+pub struct Schema {
+    pub fields: Vec<(String, FieldType)>,
+}
+
+impl Schema {
+    pub fn new(fields: &[(&str, FieldType)]) -> Self {
+        Self { fields: fields.iter().map(|(n, t)| (n.to_string(), t.clone())).collect() }
+    }
+
+    /// Walk the fields up to `name`, reading length prefixes through `read`. Returns the
+    /// byte range of `name`'s value (after its prefix, if any) and every range read.
+    fn locate(&self, name: &str, read: &mut dyn FnMut(Range<usize>) -> Result<Vec<u8>, WitnessError>) -> Result<(Range<usize>, FieldType), WitnessError> {
+        let mut offset = 0usize;
+        for (field_name, ty) in &self.fields {
+            let len = match ty.fixed_len() {
+                Some(n) => n,
+                None => {
+                    let prefix = read(offset..offset + 4)?;
+                    offset += 4;
+                    u32::from_le_bytes(prefix.try_into().unwrap()) as usize
+                }
+            };
+            if field_name == name {
+                return Ok((offset..offset + len, ty.clone()));
+            }
+            offset += len;
+        }
+        Err(WitnessError::UnknownField(name.to_string()))
+    }
+
+    /// Prover side: chunks of `blob` needed to prove field `name`, with proofs
+    pub fn prove_field(&self, pubkey: &str, blob: &[u8], chunk_size: usize, name: &str) -> Result<FieldProof, WitnessError> {
+        let mut touched = BTreeSet::new();
+        let mut read = |r: Range<usize>| {
+            if r.end > blob.len() {
+                return Err(WitnessError::BlobTooShort { len: blob.len(), need: r.end });
+            }
+            touched.extend(r.start / chunk_size..r.end.div_ceil(chunk_size));
+            Ok(blob[r].to_vec())
+        };
+        let (range, _) = self.locate(name, &mut read)?;
+        read(range)?;
+        let chunks = chunk_blob(blob, chunk_size);
+        let tree = MerkleTree::from_chunks(&chunks);
+        let chunks = touched.into_iter().map(|i| ChunkWitness {
+            pubkey: pubkey.to_string(),
+            leaf_index: i,
+            chunk: chunks[i].to_vec(),
+            proof: tree.gen_proof(i),
+        }).collect();
+        Ok(FieldProof { field: name.to_string(), chunks })
+    }
+
+    /// Verifier side: check every chunk against `root`, a tree of `chunk_count` chunks,
+    /// then decode the field using only the proven bytes. Fails if the schema walk needs a
+    /// chunk the proof didn't include.
+    pub fn verify_field(&self, proof: &FieldProof, root: &[u8;32], chunk_size: usize, chunk_count: usize) -> Result<FieldValue, WitnessError> {
+        let mut proven = BTreeMap::new();
+        for c in &proof.chunks {
+            if c.chunk.len() != chunk_size {
+                return Err(WitnessError::ChunkSizeMismatch { pubkey: c.pubkey.clone(), expected: chunk_size, actual: c.chunk.len() });
+            }
+            witness_core::check_proof_shape(&c.proof, c.leaf_index, chunk_count)?;
+            if !MerkleTree::verify_proof(&c.chunk, &c.proof, root) {
+                return Err(WitnessError::LeafNotCommitted { leaf_index: c.leaf_index, root: *root });
+            }
+            proven.insert(c.leaf_index, &c.chunk);
+        }
+        let mut read = |r: Range<usize>| -> Result<Vec<u8>, WitnessError> {
+            let mut out = Vec::with_capacity(r.len());
+            for pos in r {
+                match proven.get(&(pos / chunk_size)) {
+                    Some(chunk) => out.push(chunk[pos % chunk_size]),
+                    None => return Err(WitnessError::UnprovenByte { pos, chunk: pos / chunk_size }),
+                }
+            }
+            Ok(out)
+        };
+        let (range, ty) = self.locate(&proof.field, &mut read)?;
+        let bytes = read(range)?;
+        Ok(match ty {
+            FieldType::Bool => FieldValue::Bool(bytes[0] != 0),
+            FieldType::U8 | FieldType::U16 | FieldType::U32 | FieldType::U64 | FieldType::U128 => {
+                let mut buf = [0u8; 16];
+                buf[..bytes.len()].copy_from_slice(&bytes);
+                FieldValue::Uint(u128::from_le_bytes(buf))
+            }
+            FieldType::Pubkey | FieldType::Array(_) | FieldType::Bytes => FieldValue::Bytes(bytes),
+            FieldType::String => match String::from_utf8(bytes) {
+                Ok(s) => FieldValue::Str(s),
+                Err(_) => return Err(WitnessError::FieldNotUtf8(proof.field.clone())),
+            },
+        })
+    }
+}
+
+/// Prove single fields of a listing that has one of every field type
+pub fn demo(chunk_size: usize) -> Result<()> {
+    let listing = Schema::new(&[
+        ("owner", FieldType::Pubkey), ("price", FieldType::U64), ("active", FieldType::Bool), ("royalty_bps", FieldType::U16),
+        ("edition", FieldType::U32), ("decimals", FieldType::U8), ("supply", FieldType::U128), ("collection", FieldType::Array(32)),
+        ("name", FieldType::String), ("uri", FieldType::String), ("extra", FieldType::Bytes),
+    ]);
+    let mut blob = [7u8; 32].to_vec();
+    blob.extend_from_slice(&42_000u64.to_le_bytes());
+    blob.push(1);
+    blob.extend_from_slice(&500u16.to_le_bytes());
+    blob.extend_from_slice(&3u32.to_le_bytes());
+    blob.push(0);
+    blob.extend_from_slice(&1u128.to_le_bytes());
+    blob.extend_from_slice(&[9u8; 32]);
+    for s in ["Sword of Witnessing", "https://example.com/metadata/sword-of-witnessing.json"] {
+        blob.extend_from_slice(&(s.len() as u32).to_le_bytes());
+        blob.extend_from_slice(s.as_bytes());
+    }
+    blob.extend_from_slice(&2u32.to_le_bytes());
+    blob.extend_from_slice(&[0xbe, 0xef]);
+    let chunks = chunk_blob(&blob, chunk_size);
+    let root = MerkleTree::from_chunks(&chunks).root();
+    for field in ["price", "active", "royalty_bps", "uri", "extra"] {
+        let proof = listing.prove_field("Listing1", &blob, chunk_size, field)?;
+        let value = listing.verify_field(&proof, &root, chunk_size, chunks.len())?;
+        let leaves: Vec<usize> = proof.chunks.iter().map(|c| c.leaf_index).collect();
+        println!("Field {} proven with chunks {:?}: {:?}", field, leaves, value);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CHUNK: usize = 16;
+
+    fn schema() -> Schema {
+        Schema::new(&[("owner", FieldType::Pubkey), ("name", FieldType::String), ("price", FieldType::U64), ("active", FieldType::Bool), ("extra", FieldType::Bytes)])
+    }
+
+    fn listing(name: &[u8]) -> Vec<u8> {
+        let mut blob = [7u8; 32].to_vec();
+        blob.extend_from_slice(&(name.len() as u32).to_le_bytes());
+        blob.extend_from_slice(name);
+        blob.extend_from_slice(&42_000u64.to_le_bytes());
+        blob.push(1);
+        blob.extend_from_slice(&2u32.to_le_bytes());
+        blob.extend_from_slice(&[0xbe, 0xef]);
+        blob
+    }
+
+    fn verify(schema: &Schema, blob: &[u8], proof: &FieldProof) -> Result<FieldValue, WitnessError> {
+        let chunks = chunk_blob(blob, CHUNK);
+        schema.verify_field(proof, &MerkleTree::from_chunks(&chunks).root(), CHUNK, chunks.len())
+    }
+
+    #[test]
+    fn every_field_proves_its_value() {
+        let (schema, blob) = (schema(), listing(b"a sword of some length"));
+        let expect = [
+            ("owner", FieldValue::Bytes(vec![7; 32])),
+            ("name", FieldValue::Str("a sword of some length".to_string())),
+            ("price", FieldValue::Uint(42_000)),
+            ("active", FieldValue::Bool(true)),
+            ("extra", FieldValue::Bytes(vec![0xbe, 0xef])),
+        ];
+        for (field, value) in expect {
+            let proof = schema.prove_field("Listing1", &blob, CHUNK, field).unwrap();
+            assert_eq!(verify(&schema, &blob, &proof).unwrap(), value);
+        }
+        // price sits after the name, so its proof carries the name's length prefix too
+        let leaves: Vec<usize> = schema.prove_field("Listing1", &blob, CHUNK, "price").unwrap().chunks.iter().map(|c| c.leaf_index).collect();
+        assert_eq!(leaves, vec![2, 3, 4]);
+    }
+
+    #[test]
+    fn rejects_unknown_fields() {
+        let (schema, blob) = (schema(), listing(b"sword"));
+        assert!(matches!(schema.prove_field("Listing1", &blob, CHUNK, "color"), Err(WitnessError::UnknownField(f)) if f == "color"));
+        let mut proof = schema.prove_field("Listing1", &blob, CHUNK, "price").unwrap();
+        proof.field = "color".to_string();
+        assert!(matches!(verify(&schema, &blob, &proof), Err(WitnessError::UnknownField(_))));
+    }
+
+    #[test]
+    fn rejects_missing_and_uncommitted_chunks() {
+        let (schema, blob) = (schema(), listing(b"a sword of some length"));
+        let mut proof = schema.prove_field("Listing1", &blob, CHUNK, "price").unwrap();
+        let prefix = proof.chunks.remove(0);
+        assert!(matches!(verify(&schema, &blob, &proof), Err(WitnessError::UnprovenByte { pos: 32, chunk: 2 })));
+        let mut forged = prefix.clone();
+        forged.chunk[0] = 0;
+        proof.chunks.insert(0, forged);
+        assert!(matches!(verify(&schema, &blob, &proof), Err(WitnessError::LeafNotCommitted { leaf_index: 2, .. })));
+        proof.chunks[0] = prefix;
+        proof.chunks[0].chunk.push(0);
+        assert!(matches!(verify(&schema, &blob, &proof), Err(WitnessError::ChunkSizeMismatch { actual: 17, .. })));
+    }
+
+    #[test]
+    fn rejects_short_blobs_and_bad_strings() {
+        let schema = schema();
+        let blob = listing(b"sword");
+        assert!(matches!(schema.prove_field("Listing1", &blob[..40], CHUNK, "price"), Err(WitnessError::BlobTooShort { len: 40, need: 49 })));
+        let blob = listing(&[0xff, 0xfe]);
+        let proof = schema.prove_field("Listing1", &blob, CHUNK, "name").unwrap();
+        assert!(matches!(verify(&schema, &blob, &proof), Err(WitnessError::FieldNotUtf8(_))));
+    }
+}
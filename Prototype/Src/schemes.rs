@@ -0,0 +1,152 @@
+//! `compare-schemes`: commit the same corpus under every commitment scheme with a
+//! backend and report side by side what a one-chunk witness weighs, and what
+//! committing, opening and verifying cost in wall-clock time.
+//!
+//! A backend implements `CommitmentScheme` and joins `all`. The binary SHA-256 Merkle
+//! tree is the only one so far. Verkle and KZG backends will show up in the same report
+//! once they exist.
+
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use anyhow::{Result, bail};
+
+use crate::{chunk_blob, MerkleTree};
+
+/// A way of committing to account data and opening single chunks of it
+pub trait CommitmentScheme {
+    fn name(&self) -> String;
+    /// Commit to `data`, keeping what the prover needs to open chunks later
+    fn commit(&self, data: &[u8]) -> Box<dyn Committed>;
+    /// Whether `witness` opens chunk `index` of what `commitment` commits to as `chunk`
+    fn verify(&self, commitment: &[u8], index: usize, chunk: &[u8], witness: &[u8]) -> bool;
+}
+
+/// The prover's side of one commitment
+pub trait Committed {
+    /// Bytes kept on chain
+    fn commitment(&self) -> Vec<u8>;
+    fn chunks(&self) -> usize;
+    /// Chunk `index` and its witness
+    fn open(&self, index: usize) -> (Vec<u8>, Vec<u8>);
+}
+
+/// Binary SHA-256 Merkle tree; a witness is the encoded proof steps
+pub struct Merkle {
+    pub chunk_size: usize,
+}
+
+struct MerkleCommitted {
+    chunks: Vec<Vec<u8>>,
+    tree: MerkleTree,
+}
+
+impl CommitmentScheme for Merkle {
+    fn name(&self) -> String {
+        format!("merkle-sha256/{}", self.chunk_size)
+    }
+
+    fn commit(&self, data: &[u8]) -> Box<dyn Committed> {
+        let chunks = chunk_blob(data, self.chunk_size);
+        Box::new(MerkleCommitted { tree: MerkleTree::from_chunks(chunks.iter()), chunks: chunks.to_vecs() })
+    }
+
+    fn verify(&self, commitment: &[u8], index: usize, chunk: &[u8], witness: &[u8]) -> bool {
+        let Ok(root) = <[u8;32]>::try_from(commitment) else { return false };
+        witness_core::proof_index_encoded(witness) == Some(index) && witness_core::verify_encoded(chunk, witness, &root)
+    }
+}
+
+impl Committed for MerkleCommitted {
+    fn commitment(&self) -> Vec<u8> {
+        self.tree.root().to_vec()
+    }
+
+    fn chunks(&self) -> usize {
+        self.chunks.len()
+    }
+
+    fn open(&self, index: usize) -> (Vec<u8>, Vec<u8>) {
+        (self.chunks[index].clone(), witness_core::encode_steps(&self.tree.gen_proof(index)))
+    }
+}
+
+/// Every scheme with a backend
+pub fn all(chunk_size: usize) -> Vec<Box<dyn CommitmentScheme>> {
+    vec![Box::new(Merkle { chunk_size })]
+}
+
+/// One scheme over the whole corpus
+#[derive(Debug, Clone, Default)]
+pub struct SchemeReport {
+    pub name: String,
+    pub accounts: usize,
+    pub commitment_bytes: u64,
+    pub witnesses: u64,
+    pub witness_bytes: u64,
+    pub commit_time: Duration,
+    pub open_time: Duration,
+    pub verify_time: Duration,
+}
+
+/// Up to `n` chunk indices spread evenly over `chunks`
+fn sample(chunks: usize, n: usize) -> impl Iterator<Item = usize> {
+    let n = n.min(chunks);
+    (0..n).map(move |i| i * chunks / n)
+}
+
+/// Commit every account under `scheme`, then open and verify up to `openings` chunks
+/// of each
+pub fn measure(scheme: &dyn CommitmentScheme, corpus: &[(String, Vec<u8>)], openings: usize) -> Result<SchemeReport> {
+    let mut report = SchemeReport { name: scheme.name(), accounts: corpus.len(), ..SchemeReport::default() };
+    for (name, data) in corpus {
+        let start = Instant::now();
+        let committed = scheme.commit(data);
+        let commitment = committed.commitment();
+        report.commit_time += start.elapsed();
+        report.commitment_bytes += commitment.len() as u64;
+        for index in sample(committed.chunks(), openings) {
+            let start = Instant::now();
+            let (chunk, witness) = committed.open(index);
+            report.open_time += start.elapsed();
+            let start = Instant::now();
+            let ok = scheme.verify(&commitment, index, &chunk, &witness);
+            report.verify_time += start.elapsed();
+            if !ok {
+                bail!("{}: the witness for chunk {} of {} does not verify", report.name, index, name);
+            }
+            report.witnesses += 1;
+            report.witness_bytes += witness.len() as u64;
+        }
+    }
+    Ok(report)
+}
+
+fn per(total: Duration, n: u64) -> Duration {
+    total / n.max(1) as u32
+}
+
+/// One row per scheme
+pub fn table(reports: &[SchemeReport]) -> String {
+    let mut out = format!("{:<22} {:>12} {:>12} {:>14} {:>14} {:>14}\n", "scheme", "commitment", "witness", "commit/acct", "open/witness", "verify/witness");
+    for r in reports {
+        out += &format!("{:<22} {:>11}B {:>11.1}B {:>14} {:>14} {:>14}\n",
+            r.name, r.commitment_bytes / r.accounts.max(1) as u64, r.witness_bytes as f64 / r.witnesses.max(1) as f64,
+            format!("{:.1?}", per(r.commit_time, r.accounts as u64)), format!("{:.1?}", per(r.open_time, r.witnesses)),
+            format!("{:.1?}", per(r.verify_time, r.witnesses)));
+    }
+    out
+}
+
+/// `compare-schemes`: measure every scheme over the dumps in `dir`
+pub fn run(dir: &Path, chunk_size: usize, openings: usize) -> Result<()> {
+    if chunk_size == 0 {
+        bail!("chunk size must be positive");
+    }
+    let corpus = crate::analyze::load_data(dir)?;
+    let bytes: usize = corpus.iter().map(|(_, data)| data.len()).sum();
+    let reports = all(chunk_size).iter().map(|s| measure(s.as_ref(), &corpus, openings)).collect::<Result<Vec<_>>>()?;
+    println!("{} accounts, {} bytes, up to {} openings each", corpus.len(), bytes, openings);
+    print!("{}", table(&reports));
+    Ok(())
+}
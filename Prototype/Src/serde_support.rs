@@ -0,0 +1,126 @@
+//! Serde forms for stubs, trees, proofs and reports. Hashes and byte strings are hex, as in every
+//! JSON the CLI and RPC write; types derive with `serde(with = ...)` pointing here.
+
+use serde::de::Error as _;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::MerkleTree;
+
+/// Values written as one hex string
+pub trait HexForm: Sized {
+    fn to_hex(&self) -> String;
+    fn from_hex(s: &str) -> Result<Self, String>;
+}
+
+impl HexForm for [u8;32] {
+    fn to_hex(&self) -> String {
+        hex::encode(self)
+    }
+
+    fn from_hex(s: &str) -> Result<Self, String> {
+        let bytes = hex::decode(s).map_err(|e| format!("{:?} is not hex: {}", s, e))?;
+        bytes.try_into().map_err(|_| format!("{:?} is not 32 bytes", s))
+    }
+}
+
+impl HexForm for Vec<u8> {
+    fn to_hex(&self) -> String {
+        hex::encode(self)
+    }
+
+    fn from_hex(s: &str) -> Result<Self, String> {
+        hex::decode(s).map_err(|e| format!("{:?} is not hex: {}", s, e))
+    }
+}
+
+/// A hash or byte string
+pub mod hex_form {
+    use super::*;
+
+    pub fn serialize<T: HexForm, S: Serializer>(v: &T, s: S) -> Result<S::Ok, S::Error> {
+        v.to_hex().serialize(s)
+    }
+
+    pub fn deserialize<'de, T: HexForm, D: Deserializer<'de>>(d: D) -> Result<T, D::Error> {
+        T::from_hex(&String::deserialize(d)?).map_err(D::Error::custom)
+    }
+}
+
+/// A list of hashes or byte strings
+pub mod hex_list {
+    use super::*;
+
+    pub fn serialize<T: HexForm, S: Serializer>(v: &[T], s: S) -> Result<S::Ok, S::Error> {
+        s.collect_seq(v.iter().map(HexForm::to_hex))
+    }
+
+    pub fn deserialize<'de, T: HexForm, D: Deserializer<'de>>(d: D) -> Result<Vec<T>, D::Error> {
+        Vec::<String>::deserialize(d)?.iter().map(|s| T::from_hex(s).map_err(D::Error::custom)).collect()
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct Step {
+    sibling: String,
+    is_left: bool,
+}
+
+/// Proof steps as `[{"sibling": hex, "is_left": bool}]`, leaf level first
+pub mod steps {
+    use super::*;
+
+    pub fn serialize<S: Serializer>(v: &[([u8;32], bool)], s: S) -> Result<S::Ok, S::Error> {
+        s.collect_seq(v.iter().map(|(sibling, is_left)| Step { sibling: hex::encode(sibling), is_left: *is_left }))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(d: D) -> Result<Vec<([u8;32], bool)>, D::Error> {
+        Vec::<Step>::deserialize(d)?.into_iter()
+            .map(|step| Ok((<[u8;32]>::from_hex(&step.sibling).map_err(D::Error::custom)?, step.is_left)))
+            .collect()
+    }
+}
+
+/// `(pubkey, hash or bytes)` pairs as `[[pubkey, hex]]`
+pub mod named_hex {
+    use super::*;
+
+    pub fn serialize<T: HexForm, S: Serializer>(v: &[(String, T)], s: S) -> Result<S::Ok, S::Error> {
+        s.collect_seq(v.iter().map(|(pubkey, value)| (pubkey, value.to_hex())))
+    }
+
+    pub fn deserialize<'de, T: HexForm, D: Deserializer<'de>>(d: D) -> Result<Vec<(String, T)>, D::Error> {
+        Vec::<(String, String)>::deserialize(d)?.into_iter()
+            .map(|(pubkey, value)| Ok((pubkey, T::from_hex(&value).map_err(D::Error::custom)?)))
+            .collect()
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct TreeLayers {
+    layers: Vec<Vec<String>>,
+}
+
+/// `{"layers": [[hex]]}`, leaf hashes first and the root last. Reading one back rebuilds
+/// every layer above the leaves and rejects the tree unless they all match.
+impl Serialize for MerkleTree {
+    fn serialize<S: Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
+        TreeLayers { layers: self.layers().map(|l| l.iter().map(hex::encode).collect()).collect() }.serialize(s)
+    }
+}
+
+impl<'de> Deserialize<'de> for MerkleTree {
+    fn deserialize<D: Deserializer<'de>>(d: D) -> Result<Self, D::Error> {
+        let layers = TreeLayers::deserialize(d)?.layers.iter()
+            .map(|l| l.iter().map(|s| <[u8;32]>::from_hex(s).map_err(D::Error::custom)).collect::<Result<Vec<_>, _>>())
+            .collect::<Result<Vec<_>, _>>()?;
+        let leaves = layers.first().ok_or_else(|| D::Error::custom("tree has no layers"))?;
+        if !leaves.len().is_power_of_two() {
+            return Err(D::Error::custom(format!("{} leaves; the leaf layer is padded to a power of two", leaves.len())));
+        }
+        let tree = MerkleTree::from_leaves(leaves.clone());
+        if !tree.layers().eq(layers.iter().map(Vec::as_slice)) {
+            return Err(D::Error::custom("tree layers do not hash up to the root they list"));
+        }
+        Ok(tree)
+    }
+}
@@ -0,0 +1,123 @@
+use std::sync::Mutex;
+
+use anyhow::{Result, bail};
+use ed25519_dalek::SigningKey;
+
+use crate::{h, WitnessTx};
+
+/// Whatever holds the private half of an ed25519 key: a keypair file, a wallet, a
+/// remote service. Only the public key and signatures ever leave it.
+pub trait Signer {
+    fn pubkey(&self) -> [u8;32];
+    fn sign_message(&self, message: &[u8]) -> Result<[u8;64]>;
+}
+
+impl Signer for SigningKey {
+    fn pubkey(&self) -> [u8;32] {
+        self.verifying_key().to_bytes()
+    }
+
+    fn sign_message(&self, message: &[u8]) -> Result<[u8;64]> {
+        Ok(ed25519_dalek::Signer::sign(self, message).to_bytes())
+    }
+}
+
+/// Stand-in for an external wallet in the simulator: signs with a local key when it
+/// approves, refuses otherwise, and remembers every message it was asked to sign
+#[derive(Debug)]
+pub struct MockSigner {
+    key: SigningKey,
+    approve: bool,
+    requests: Mutex<Vec<Vec<u8>>>,
+}
+
+impl MockSigner {
+    pub fn new(key: SigningKey) -> Self {
+        Self { key, approve: true, requests: Mutex::new(Vec::new()) }
+    }
+
+    /// A wallet whose user rejects every request
+    pub fn refusing(key: SigningKey) -> Self {
+        Self { approve: false, ..Self::new(key) }
+    }
+
+    /// Messages asked for so far, approved or not
+    pub fn requests(&self) -> Vec<Vec<u8>> {
+        self.requests.lock().unwrap().clone()
+    }
+}
+
+impl Signer for MockSigner {
+    fn pubkey(&self) -> [u8;32] {
+        self.key.pubkey()
+    }
+
+    fn sign_message(&self, message: &[u8]) -> Result<[u8;64]> {
+        self.requests.lock().unwrap().push(message.to_vec());
+        if !self.approve {
+            bail!("signer {} rejected the request", h(&self.pubkey()));
+        }
+        self.key.sign_message(message)
+    }
+}
+
+/// Signing service over HTTP, for keys kept in a wallet daemon, KMS or hardware device
+/// the prototype never sees: `GET {url}/pubkey` answers `{ "pubkey": base58 }` and
+/// `POST {url}/sign` with `{ "pubkey", "message": hex }` answers `{ "signature": hex }`.
+/// Returned signatures are checked before use.
+#[cfg(feature = "remote-signer")]
+#[derive(Debug, Clone)]
+pub struct RemoteSigner {
+    url: String,
+    pubkey: [u8;32],
+}
+
+#[cfg(feature = "remote-signer")]
+impl RemoteSigner {
+    /// Ask the service at `url` which key it signs with
+    pub fn connect(url: &str) -> Result<Self> {
+        use anyhow::Context;
+        let url = url.trim_end_matches('/').to_string();
+        let reply: serde_json::Value = ureq::get(&format!("{}/pubkey", url)).call().with_context(|| format!("asking {} for its key", url))?
+            .into_json().context("pubkey reply is not JSON")?;
+        let bytes = bs58::decode(reply["pubkey"].as_str().unwrap_or_default()).into_vec().context("pubkey is not base58")?;
+        let pubkey = match bytes.try_into() {
+            Ok(k) => k,
+            Err(_) => bail!("pubkey must be 32 bytes"),
+        };
+        Ok(Self { url, pubkey })
+    }
+}
+
+#[cfg(feature = "remote-signer")]
+impl Signer for RemoteSigner {
+    fn pubkey(&self) -> [u8;32] {
+        self.pubkey
+    }
+
+    fn sign_message(&self, message: &[u8]) -> Result<[u8;64]> {
+        use anyhow::Context;
+        use ed25519_dalek::{Signature, Verifier, VerifyingKey};
+        let request = serde_json::json!({ "pubkey": crate::keypair::pubkey_base58(&self.pubkey), "message": hex::encode(message) });
+        let reply: serde_json::Value = ureq::post(&format!("{}/sign", self.url)).send_json(request).with_context(|| format!("signing with {}", self.url))?
+            .into_json().context("sign reply is not JSON")?;
+        let sig: [u8;64] = match hex::decode(reply["signature"].as_str().unwrap_or_default()).map(<[u8;64]>::try_from) {
+            Ok(Ok(sig)) => sig,
+            _ => bail!("{} did not return a 64-byte hex signature", self.url),
+        };
+        let key = VerifyingKey::from_bytes(&self.pubkey).context("remote pubkey is not a valid ed25519 key")?;
+        if key.verify(message, &Signature::from_bytes(&sig)).is_err() {
+            bail!("{} returned a signature that does not verify for {}", self.url, h(&self.pubkey));
+        }
+        Ok(sig)
+    }
+}
+
+impl WitnessTx {
+    /// `sign` through any `Signer`; fails if the signer does
+    pub fn sign_with(&mut self, signer: &dyn Signer) -> Result<()> {
+        let sig = signer.sign_message(&self.id())?;
+        self.signatures.push((signer.pubkey(), sig));
+        Ok(())
+    }
+}
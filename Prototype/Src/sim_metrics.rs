@@ -0,0 +1,298 @@
+//! Per-slot and per-transaction metrics of `simulate --slots`, streamed to files for
+//! pandas or duckdb as the run goes: `slots.csv` and `txs.csv` in the export directory,
+//! or `.parquet` files with the `parquet` feature.
+//!
+//! The columns are `SLOT_COLUMNS` and `TX_COLUMNS`, in that order and under those
+//! names; new columns are only ever appended. Byte and CU counts are unsigned, savings
+//! signed (negative when offloading costs more), latencies wall-clock microseconds.
+//! `onchain_*` is the account holding its data, `offloaded_*` the stub and witnesses.
+
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+
+use crate::savings::SlotReport;
+use crate::workload::OpKind;
+
+/// File format of an export
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum ExportFormat {
+    Csv,
+    #[cfg(feature = "parquet")]
+    Parquet,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColumnType {
+    U64,
+    I64,
+    Str,
+    Bool,
+}
+
+pub const SLOT_COLUMNS: &[(&str, ColumnType)] = &[
+    ("slot", ColumnType::U64),
+    ("ops", ColumnType::U64),
+    ("failed", ColumnType::U64),
+    ("accounts", ColumnType::U64),
+    ("onchain_state_bytes", ColumnType::U64),
+    ("onchain_tx_bytes", ColumnType::U64),
+    ("onchain_compute_units", ColumnType::U64),
+    ("onchain_prover_bytes", ColumnType::U64),
+    ("offloaded_state_bytes", ColumnType::U64),
+    ("offloaded_tx_bytes", ColumnType::U64),
+    ("offloaded_compute_units", ColumnType::U64),
+    ("offloaded_prover_bytes", ColumnType::U64),
+    ("state_bytes_saved", ColumnType::I64),
+    ("tx_bytes_saved", ColumnType::I64),
+    ("latency_us", ColumnType::U64),
+];
+
+pub const TX_COLUMNS: &[(&str, ColumnType)] = &[
+    ("slot", ColumnType::U64),
+    ("seq", ColumnType::U64),
+    ("kind", ColumnType::Str),
+    ("pubkey", ColumnType::Str),
+    ("ok", ColumnType::Bool),
+    ("onchain_tx_bytes", ColumnType::U64),
+    ("offloaded_tx_bytes", ColumnType::U64),
+    ("tx_bytes_saved", ColumnType::I64),
+    ("compute_units", ColumnType::U64),
+    ("latency_us", ColumnType::U64),
+];
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    U64(u64),
+    I64(i64),
+    Str(String),
+    Bool(bool),
+}
+
+/// One op of the run, as `TX_COLUMNS`
+#[derive(Debug, Clone)]
+pub struct TxRecord {
+    pub slot: u64,
+    /// Position in the whole run, genesis creations included
+    pub seq: u64,
+    pub kind: OpKind,
+    pub pubkey: String,
+    pub ok: bool,
+    pub onchain_tx_bytes: u64,
+    pub offloaded_tx_bytes: u64,
+    pub compute_units: u64,
+    pub latency_us: u64,
+}
+
+impl TxRecord {
+    fn row(&self) -> Vec<Value> {
+        vec![
+            Value::U64(self.slot),
+            Value::U64(self.seq),
+            Value::Str(self.kind.name().to_string()),
+            Value::Str(self.pubkey.clone()),
+            Value::Bool(self.ok),
+            Value::U64(self.onchain_tx_bytes),
+            Value::U64(self.offloaded_tx_bytes),
+            Value::I64(self.onchain_tx_bytes as i64 - self.offloaded_tx_bytes as i64),
+            Value::U64(self.compute_units),
+            Value::U64(self.latency_us),
+        ]
+    }
+}
+
+fn slot_row(r: &SlotReport, latency_us: u64) -> Vec<Value> {
+    let (on, off) = (&r.onchain, &r.offloaded);
+    vec![
+        Value::U64(r.slot),
+        Value::U64(r.ops),
+        Value::U64(r.failed),
+        Value::U64(r.accounts as u64),
+        Value::U64(on.onchain_bytes),
+        Value::U64(on.tx_bytes),
+        Value::U64(on.compute_units),
+        Value::U64(on.prover_bytes),
+        Value::U64(off.onchain_bytes),
+        Value::U64(off.tx_bytes),
+        Value::U64(off.compute_units),
+        Value::U64(off.prover_bytes),
+        Value::I64(on.onchain_bytes as i64 - off.onchain_bytes as i64),
+        Value::I64(on.tx_bytes as i64 - off.tx_bytes as i64),
+        Value::U64(latency_us),
+    ]
+}
+
+/// A file rows are appended to
+trait Table {
+    fn write_row(&mut self, row: Vec<Value>) -> Result<()>;
+    fn finish(&mut self) -> Result<()>;
+}
+
+struct CsvTable {
+    out: BufWriter<File>,
+}
+
+impl CsvTable {
+    fn create(path: &Path, columns: &[(&str, ColumnType)]) -> Result<Self> {
+        let mut out = BufWriter::new(File::create(path).with_context(|| format!("creating {}", path.display()))?);
+        let header: Vec<&str> = columns.iter().map(|(name, _)| *name).collect();
+        writeln!(out, "{}", header.join(","))?;
+        Ok(Self { out })
+    }
+}
+
+fn csv_value(v: &Value) -> String {
+    match v {
+        Value::U64(n) => n.to_string(),
+        Value::I64(n) => n.to_string(),
+        Value::Bool(b) => b.to_string(),
+        Value::Str(s) if s.contains([',', '"', '\n']) => format!("\"{}\"", s.replace('"', "\"\"")),
+        Value::Str(s) => s.clone(),
+    }
+}
+
+impl Table for CsvTable {
+    fn write_row(&mut self, row: Vec<Value>) -> Result<()> {
+        let fields: Vec<String> = row.iter().map(csv_value).collect();
+        writeln!(self.out, "{}", fields.join(","))?;
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        self.out.flush()?;
+        Ok(())
+    }
+}
+
+#[cfg(feature = "parquet")]
+mod parquet_table {
+    use std::fs::File;
+    use std::path::Path;
+    use std::sync::Arc;
+
+    use anyhow::{Context, Result};
+    use parquet::data_type::{BoolType, ByteArray, ByteArrayType, Int64Type};
+    use parquet::file::properties::WriterProperties;
+    use parquet::file::writer::SerializedFileWriter;
+    use parquet::schema::parser::parse_message_type;
+
+    use super::{ColumnType, Table, Value};
+
+    /// Rows buffered before they are written out as a row group
+    const ROW_GROUP_ROWS: usize = 8192;
+
+    pub struct ParquetTable {
+        writer: Option<SerializedFileWriter<File>>,
+        rows: Vec<Vec<Value>>,
+    }
+
+    impl ParquetTable {
+        pub fn create(path: &Path, name: &str, columns: &[(&str, ColumnType)]) -> Result<Self> {
+            let fields: String = columns.iter().map(|(name, ty)| match ty {
+                ColumnType::U64 => format!("REQUIRED INT64 {} (INTEGER(64,false)); ", name),
+                ColumnType::I64 => format!("REQUIRED INT64 {}; ", name),
+                ColumnType::Str => format!("REQUIRED BYTE_ARRAY {} (UTF8); ", name),
+                ColumnType::Bool => format!("REQUIRED BOOLEAN {}; ", name),
+            }).collect();
+            let schema = Arc::new(parse_message_type(&format!("message {} {{ {}}}", name, fields))?);
+            let file = File::create(path).with_context(|| format!("creating {}", path.display()))?;
+            let writer = SerializedFileWriter::new(file, schema, Arc::new(WriterProperties::builder().build()))?;
+            Ok(Self { writer: Some(writer), rows: Vec::new() })
+        }
+
+        fn flush_rows(&mut self) -> Result<()> {
+            let writer = self.writer.as_mut().unwrap();
+            if self.rows.is_empty() {
+                return Ok(());
+            }
+            let mut group = writer.next_row_group()?;
+            let mut i = 0;
+            while let Some(mut column) = group.next_column()? {
+                let values = self.rows.iter().map(|row| &row[i]);
+                match self.rows[0][i] {
+                    Value::U64(_) | Value::I64(_) => {
+                        let ints: Vec<i64> = values.map(|v| match v { Value::U64(n) => *n as i64, Value::I64(n) => *n, _ => unreachable!() }).collect();
+                        column.typed::<Int64Type>().write_batch(&ints, None, None)?;
+                    }
+                    Value::Str(_) => {
+                        let strs: Vec<ByteArray> = values.map(|v| match v { Value::Str(s) => ByteArray::from(s.as_str()), _ => unreachable!() }).collect();
+                        column.typed::<ByteArrayType>().write_batch(&strs, None, None)?;
+                    }
+                    Value::Bool(_) => {
+                        let bools: Vec<bool> = values.map(|v| matches!(v, Value::Bool(true))).collect();
+                        column.typed::<BoolType>().write_batch(&bools, None, None)?;
+                    }
+                }
+                column.close()?;
+                i += 1;
+            }
+            group.close()?;
+            self.rows.clear();
+            Ok(())
+        }
+    }
+
+    impl Table for ParquetTable {
+        fn write_row(&mut self, row: Vec<Value>) -> Result<()> {
+            self.rows.push(row);
+            if self.rows.len() >= ROW_GROUP_ROWS {
+                self.flush_rows()?;
+            }
+            Ok(())
+        }
+
+        fn finish(&mut self) -> Result<()> {
+            self.flush_rows()?;
+            if let Some(writer) = self.writer.take() {
+                writer.close()?;
+            }
+            Ok(())
+        }
+    }
+}
+
+/// The two tables of one run
+pub struct Export {
+    slots: Box<dyn Table>,
+    txs: Box<dyn Table>,
+    paths: [PathBuf; 2],
+}
+
+impl Export {
+    /// Create `dir` if need be and start `slots` and `txs` files in it
+    pub fn create(dir: &Path, format: ExportFormat) -> Result<Self> {
+        std::fs::create_dir_all(dir).with_context(|| format!("creating {}", dir.display()))?;
+        match format {
+            ExportFormat::Csv => {
+                let paths = [dir.join("slots.csv"), dir.join("txs.csv")];
+                let slots = CsvTable::create(&paths[0], SLOT_COLUMNS)?;
+                let txs = CsvTable::create(&paths[1], TX_COLUMNS)?;
+                Ok(Self { slots: Box::new(slots), txs: Box::new(txs), paths })
+            }
+            #[cfg(feature = "parquet")]
+            ExportFormat::Parquet => {
+                let paths = [dir.join("slots.parquet"), dir.join("txs.parquet")];
+                let slots = parquet_table::ParquetTable::create(&paths[0], "slots", SLOT_COLUMNS)?;
+                let txs = parquet_table::ParquetTable::create(&paths[1], "txs", TX_COLUMNS)?;
+                Ok(Self { slots: Box::new(slots), txs: Box::new(txs), paths })
+            }
+        }
+    }
+
+    pub fn slot(&mut self, report: &SlotReport, latency_us: u64) -> Result<()> {
+        self.slots.write_row(slot_row(report, latency_us))
+    }
+
+    pub fn tx(&mut self, record: &TxRecord) -> Result<()> {
+        self.txs.write_row(record.row())
+    }
+
+    /// Flush both files; returns their paths
+    pub fn finish(mut self) -> Result<[PathBuf; 2]> {
+        self.slots.finish()?;
+        self.txs.finish()?;
+        Ok(self.paths)
+    }
+}
@@ -0,0 +1,49 @@
+use crate::error::WitnessError;
+use crate::state::StateTree;
+use crate::{ChainState, WitnessTx};
+
+/// Would-be outcome of a tx, computed without committing anything
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct Simulation {
+    pub compute_units: u64,
+    /// (pubkey, new root) for every account the tx would write
+    #[serde(with = "crate::serde_support::named_hex")]
+    pub new_roots: Vec<(String, [u8;32])>,
+    /// (pubkey, new blob) for every account the tx would write
+    #[serde(with = "crate::serde_support::named_hex")]
+    pub writes: Vec<(String, Vec<u8>)>,
+    /// Bytes the rebuilt trees of those accounts hold, what a prover keeps per version
+    pub tree_bytes: usize,
+    /// State root after applying the tx on top of the current committed state
+    #[serde(with = "crate::serde_support::hex_form")]
+    pub state_root_after: [u8;32],
+}
+
+impl ChainState {
+    /// Dry-run `tx`: full replay checks, witness verification, program execution and
+    /// metering, but stubs, the status cache and subscribers are left untouched.
+    /// Clients use the returned roots as the post-state they sign over.
+    pub fn simulate_tx(&self, tx: &WitnessTx) -> Result<Simulation, WitnessError> {
+        let executed = self.execute_tx(tx)?;
+        let mut stubs = self.stubs.clone();
+        if let Some(staging) = &self.staging {
+            stubs.extend(staging.stubs.clone());
+        }
+        let mut new_roots = Vec::with_capacity(executed.updates.len());
+        let mut writes = Vec::with_capacity(executed.updates.len());
+        let mut tree_bytes = 0;
+        for w in executed.updates {
+            tree_bytes += w.tree_bytes;
+            new_roots.push((w.pubkey.clone(), w.stub.merkle_root));
+            stubs.insert(w.pubkey.clone(), w.stub);
+            writes.push((w.pubkey, w.data));
+        }
+        Ok(Simulation {
+            compute_units: executed.compute_units,
+            new_roots,
+            writes,
+            tree_bytes,
+            state_root_after: StateTree::build(&stubs).root(),
+        })
+    }
+}
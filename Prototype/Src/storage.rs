@@ -0,0 +1,325 @@
+use std::collections::{BTreeMap, HashMap};
+
+use anyhow::Result;
+use ed25519_dalek::{Signature, Signer, SigningKey, Verifier, VerifyingKey};
+
+use crate::archive::ArchiveNode;
+use crate::error::WitnessError;
+use crate::events::WitnessEvent;
+use crate::optimistic::CHALLENGER_REWARD_PCT;
+use crate::{chunk_blob, sha256, ChainState, MerkleTree};
+
+/// Stake a provider must hold before its attestations are accepted
+pub const MIN_PROVIDER_STAKE: u64 = 1_000;
+
+/// Slots a provider has to answer a retrieval challenge
+pub const RETRIEVAL_WINDOW: u64 = 8;
+
+/// Share of its stake a provider loses per failed retrieval challenge
+pub const RETRIEVAL_SLASH_PCT: u64 = 25;
+
+/// A provider's claim that it holds the blob of `pubkey` committed by `root`,
+/// signed with the provider's ed25519 key
+#[derive(Debug, Clone)]
+pub struct Attestation {
+    pub provider: String,
+    pub pubkey: String,
+    pub root: [u8;32],
+    pub slot: u64,
+    pub signature: [u8;64],
+}
+
+impl Attestation {
+    /// What the provider signs; strings carry a u32 length so no two claims share bytes
+    fn digest(provider: &str, pubkey: &str, root: &[u8;32], slot: u64) -> [u8;32] {
+        let mut data = Vec::new();
+        for field in [provider, pubkey] {
+            data.extend_from_slice(&(field.len() as u32).to_le_bytes());
+            data.extend_from_slice(field.as_bytes());
+        }
+        data.extend_from_slice(root);
+        data.extend_from_slice(&slot.to_le_bytes());
+        sha256(&data)
+    }
+
+    /// Whether `signature` is `key`'s over this claim
+    pub fn verify(&self, key: &[u8;32]) -> bool {
+        let digest = Self::digest(&self.provider, &self.pubkey, &self.root, self.slot);
+        VerifyingKey::from_bytes(key).is_ok_and(|k| k.verify(&digest, &Signature::from_bytes(&self.signature)).is_ok())
+    }
+}
+
+/// Demand that `provider` produce chunk `leaf_index` of an attested blob by `deadline_slot`
+#[derive(Debug, Clone)]
+pub struct RetrievalChallenge {
+    pub challenger: String,
+    pub provider: String,
+    pub pubkey: String,
+    pub root: [u8;32],
+    pub leaf_index: usize,
+    /// Chunks the root commits to, which fixes the depth of the answer's proof
+    pub leaf_count: usize,
+    pub deadline_slot: u64,
+}
+
+/// A provider's answer to a retrieval challenge
+#[derive(Debug, Clone)]
+pub struct RetrievalResponse {
+    pub chunk: Vec<u8>,
+    pub proof: Vec<([u8;32], bool)>,
+}
+
+/// Provider keys, attestations on file and retrieval challenges still open.
+/// Provider stakes live in `OptimisticState::stakes`; one stake backs both roles.
+#[derive(Debug, Default)]
+pub struct StorageState {
+    keys: HashMap<String, [u8;32]>,
+    attestations: HashMap<(String, [u8;32]), Vec<Attestation>>,
+    challenges: BTreeMap<u64, RetrievalChallenge>,
+    next_id: u64,
+}
+
+impl StorageState {
+    /// Providers currently attesting to the blob of `pubkey` committed by `root`
+    pub fn providers_for(&self, pubkey: &str, root: &[u8;32]) -> Vec<String> {
+        self.attestations.get(&(pubkey.to_string(), *root)).map_or(Vec::new(), |list| list.iter().map(|a| a.provider.clone()).collect())
+    }
+
+    pub fn challenge(&self, id: u64) -> Option<&RetrievalChallenge> {
+        self.challenges.get(&id)
+    }
+}
+
+/// An off-chain storage provider: its blob store, its signing key and the logic to
+/// attest and answer challenges
+#[derive(Debug, Clone)]
+pub struct StorageProvider {
+    pub id: String,
+    key: SigningKey,
+    pub archive: ArchiveNode,
+}
+
+impl StorageProvider {
+    pub fn new(id: &str, key: SigningKey, archive: ArchiveNode) -> Self {
+        Self { id: id.to_string(), key, archive }
+    }
+
+    /// The key `ChainState::register_storage_provider` records for this provider
+    pub fn pubkey(&self) -> [u8;32] {
+        self.key.verifying_key().to_bytes()
+    }
+
+    /// Signed claim of holding the blob committed by `root`
+    pub fn attest(&self, pubkey: &str, root: &[u8;32], slot: u64) -> Attestation {
+        let signature = self.key.sign(&Attestation::digest(&self.id, pubkey, root, slot)).to_bytes();
+        Attestation { provider: self.id.clone(), pubkey: pubkey.to_string(), root: *root, slot, signature }
+    }
+
+    /// The challenged chunk and its proof, if the provider still has the blob
+    pub fn respond(&self, challenge: &RetrievalChallenge) -> Option<RetrievalResponse> {
+        let blob = self.archive.get_blob(&challenge.pubkey, &challenge.root)?;
+        let chunks = chunk_blob(&blob.data, blob.chunk_size);
+        let chunk = chunks.get(challenge.leaf_index)?.to_vec();
+        Some(RetrievalResponse { chunk, proof: MerkleTree::from_chunks(&chunks).gen_proof(challenge.leaf_index) })
+    }
+}
+
+impl ChainState {
+    /// Record the key `provider` signs its attestations with. A provider keeps the
+    /// first key it registers, so no one else can attest in its name.
+    pub fn register_storage_provider(&mut self, provider: &str, key: [u8;32]) -> Result<(), WitnessError> {
+        if VerifyingKey::from_bytes(&key).is_err() {
+            return Err(WitnessError::InvalidSignerKey(key));
+        }
+        match self.storage.keys.get(provider) {
+            Some(k) if *k != key => Err(WitnessError::ProviderRegistered(provider.to_string())),
+            _ => {
+                self.storage.keys.insert(provider.to_string(), key);
+                Ok(())
+            }
+        }
+    }
+
+    /// Record `attestation`. The provider must be registered and staked, its signature
+    /// must verify under its registered key, and the root must be the account's current
+    /// commitment.
+    pub fn submit_attestation(&mut self, attestation: Attestation) -> Result<(), WitnessError> {
+        let key = match self.storage.keys.get(&attestation.provider) {
+            Some(k) => *k,
+            None => return Err(WitnessError::UnregisteredProvider(attestation.provider)),
+        };
+        if !attestation.verify(&key) {
+            return Err(WitnessError::BadAttestation(attestation.provider));
+        }
+        let stake = self.optimistic.stakes.get(&attestation.provider).copied().unwrap_or(0);
+        if stake < MIN_PROVIDER_STAKE {
+            return Err(WitnessError::StakeTooLow { provider: attestation.provider, stake });
+        }
+        match self.get_stub(&attestation.pubkey) {
+            Some(s) if s.merkle_root == attestation.root => {}
+            Some(s) => return Err(WitnessError::StaleAttestation { attested: attestation.root, current: s.merkle_root }),
+            None => return Err(WitnessError::UnknownAccount(attestation.pubkey)),
+        }
+        let list = self.storage.attestations.entry((attestation.pubkey.clone(), attestation.root)).or_default();
+        list.retain(|a| a.provider != attestation.provider);
+        list.push(attestation);
+        Ok(())
+    }
+
+    /// Open a retrieval challenge against an attesting provider. The chunk index is derived
+    /// from the latest blockhash so neither side picks it. Returns the challenge id.
+    pub fn challenge_retrieval(&mut self, challenger: &str, provider: &str, pubkey: &str, root: &[u8;32]) -> Result<u64, WitnessError> {
+        let attested = self.storage.attestations.get(&(pubkey.to_string(), *root)).is_some_and(|list| list.iter().any(|a| a.provider == provider));
+        if !attested {
+            return Err(WitnessError::NotAttested { provider: provider.to_string(), pubkey: pubkey.to_string(), root: *root });
+        }
+        let (data_len, chunk_size) = match self.get_stub(pubkey) {
+            Some(s) => (s.data_len, s.chunk_size.max(1)),
+            None => return Err(WitnessError::UnknownAccount(pubkey.to_string())),
+        };
+        let id = self.storage.next_id;
+        self.storage.next_id += 1;
+        let mut seed = self.latest_blockhash().to_vec();
+        seed.extend_from_slice(&id.to_le_bytes());
+        let chunks = (data_len as usize).div_ceil(chunk_size).max(1);
+        let leaf_index = (u64::from_le_bytes(sha256(&seed)[..8].try_into().unwrap()) % chunks as u64) as usize;
+        self.storage.challenges.insert(id, RetrievalChallenge {
+            challenger: challenger.to_string(),
+            provider: provider.to_string(),
+            pubkey: pubkey.to_string(),
+            root: *root,
+            leaf_index,
+            leaf_count: chunks,
+            deadline_slot: self.slot + RETRIEVAL_WINDOW,
+        });
+        Ok(id)
+    }
+
+    /// Close challenge `id` with the requested chunk and its proof against the attested root
+    pub fn answer_retrieval(&mut self, id: u64, response: &RetrievalResponse) -> Result<(), WitnessError> {
+        let challenge = match self.storage.challenges.get(&id) {
+            Some(c) => c,
+            None => return Err(WitnessError::NoRetrievalChallenge(id)),
+        };
+        witness_core::check_proof_shape(&response.proof, challenge.leaf_index, challenge.leaf_count)?;
+        if !MerkleTree::verify_proof(&response.chunk, &response.proof, &challenge.root) {
+            return Err(WitnessError::LeafNotCommitted { leaf_index: challenge.leaf_index, root: challenge.root });
+        }
+        self.storage.challenges.remove(&id);
+        Ok(())
+    }
+
+    /// Slash providers whose challenges passed their deadline unanswered and drop their
+    /// attestation for the challenged blob. Returns the total slashed.
+    pub fn expire_retrieval_challenges(&mut self) -> u64 {
+        let slot = self.slot;
+        let (expired, open): (BTreeMap<_, _>, BTreeMap<_, _>) = std::mem::take(&mut self.storage.challenges)
+            .into_iter().partition(|(_, c)| slot > c.deadline_slot);
+        self.storage.challenges = open;
+        let mut total = 0;
+        for c in expired.into_values() {
+            let stake = self.optimistic.stakes.entry(c.provider.clone()).or_default();
+            let slashed = *stake * RETRIEVAL_SLASH_PCT / 100;
+            *stake -= slashed;
+            *self.optimistic.rewards.entry(c.challenger.clone()).or_default() += slashed * CHALLENGER_REWARD_PCT / 100;
+            if let Some(list) = self.storage.attestations.get_mut(&(c.pubkey.clone(), c.root)) {
+                list.retain(|a| a.provider != c.provider);
+            }
+            total += slashed;
+            self.emit(WitnessEvent::RetrievalFailed { pubkey: c.pubkey, provider: c.provider, slashed });
+        }
+        total
+    }
+}
+
+/// Two providers stake and attest to holding `pubkey`'s data, one serving it from
+/// `archive` and one holding nothing; only the first survives its retrieval challenge
+pub fn demo(chain: &mut ChainState, archive: &ArchiveNode, pubkey: &str) -> Result<()> {
+    let root = chain.get_stub(pubkey).unwrap().merkle_root;
+    let honest = StorageProvider::new("provider_2", SigningKey::from_bytes(&sha256(b"provider_2")), archive.clone());
+    let lazy = StorageProvider::new("provider_3", SigningKey::from_bytes(&sha256(b"provider_3")), ArchiveNode::new());
+    for p in [&honest, &lazy] {
+        chain.stake(&p.id, 4_000);
+        chain.register_storage_provider(&p.id, p.pubkey())?;
+        chain.submit_attestation(p.attest(pubkey, &root, chain.slot))?;
+    }
+    for p in [&honest, &lazy] {
+        let id = chain.challenge_retrieval("challenger_2", &p.id, pubkey, &root)?;
+        if let Some(response) = p.respond(chain.storage.challenge(id).unwrap()) {
+            chain.answer_retrieval(id, &response)?;
+        }
+    }
+    for _ in 0..=RETRIEVAL_WINDOW {
+        chain.advance_slot();
+    }
+    println!("Providers still attesting to {}: {:?}", pubkey, chain.storage.providers_for(pubkey, &root));
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::AccountStub;
+
+    fn provider(id: &str) -> StorageProvider {
+        StorageProvider::new(id, SigningKey::from_bytes(&sha256(id.as_bytes())), ArchiveNode::new())
+    }
+
+    /// A chain holding `Acct1` with a staked, registered `p1`; returns the account root
+    fn setup(chain: &mut ChainState, p: &StorageProvider) -> [u8;32] {
+        let blob = b"attested account data".to_vec();
+        let root = MerkleTree::from_chunks(&chunk_blob(&blob, 8)).root();
+        chain.put_stub("Acct1", AccountStub::new("Prog1", 0, root, blob.len() as u64, 8));
+        chain.stake(&p.id, MIN_PROVIDER_STAKE);
+        chain.register_storage_provider(&p.id, p.pubkey()).unwrap();
+        root
+    }
+
+    #[test]
+    fn accepts_a_signed_attestation_from_a_registered_provider() {
+        let mut chain = ChainState::new();
+        let p = provider("p1");
+        let root = setup(&mut chain, &p);
+        chain.submit_attestation(p.attest("Acct1", &root, chain.slot)).unwrap();
+        assert_eq!(chain.storage.providers_for("Acct1", &root), vec!["p1".to_string()]);
+    }
+
+    #[test]
+    fn rejects_forged_and_tampered_attestations() {
+        let mut chain = ChainState::new();
+        let p = provider("p1");
+        let root = setup(&mut chain, &p);
+        // someone else's key signing in p1's name
+        let forger = StorageProvider::new("p1", SigningKey::from_bytes(&sha256(b"forger")), ArchiveNode::new());
+        assert!(matches!(chain.submit_attestation(forger.attest("Acct1", &root, 0)), Err(WitnessError::BadAttestation(_))));
+        let signed = p.attest("Acct1", &root, 0);
+        let mut other_slot = signed.clone();
+        other_slot.slot = 1;
+        let mut other_account = signed.clone();
+        other_account.pubkey = "Acct2".to_string();
+        // moving bytes between the provider and account ids must not keep the signature valid
+        let mut shifted = signed.clone();
+        shifted.provider = "p1A".to_string();
+        shifted.pubkey = "cct1".to_string();
+        let mut flipped = signed;
+        flipped.signature[0] ^= 1;
+        for a in [other_slot, other_account, flipped] {
+            assert!(matches!(chain.submit_attestation(a), Err(WitnessError::BadAttestation(_))));
+        }
+        assert!(!shifted.verify(&p.pubkey()));
+        assert!(chain.storage.providers_for("Acct1", &root).is_empty());
+    }
+
+    #[test]
+    fn unregistered_providers_and_key_swaps_are_refused() {
+        let mut chain = ChainState::new();
+        let p = provider("p1");
+        let root = setup(&mut chain, &p);
+        let stranger = provider("p2");
+        chain.stake(&stranger.id, MIN_PROVIDER_STAKE);
+        assert!(matches!(chain.submit_attestation(stranger.attest("Acct1", &root, 0)), Err(WitnessError::UnregisteredProvider(_))));
+        let other = SigningKey::from_bytes(&sha256(b"other")).verifying_key().to_bytes();
+        assert!(matches!(chain.register_storage_provider("p1", other), Err(WitnessError::ProviderRegistered(_))));
+        chain.register_storage_provider("p1", p.pubkey()).unwrap();
+    }
+}
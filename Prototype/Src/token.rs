@@ -0,0 +1,204 @@
+use std::collections::HashSet;
+
+use anyhow::{Result, bail};
+use ed25519_dalek::SigningKey;
+
+use crate::archive::ArchiveNode;
+use crate::error::WitnessError;
+use crate::partial::ChunkTx;
+use crate::program::{AccountView, Effects, InvokeContext, Program};
+use crate::view::ChunkWitness;
+use crate::{chunk_blob, h, sha256, AccountStub, AccountWitness, ChainState, MerkleTree, TxReceipt};
+
+pub const TOKEN_PROGRAM_ID: &str = "CompressedToken1";
+
+/// One holder per leaf: owner key, then balance (u64 LE). The ledger's chunk size is
+/// the record size so a transfer touches exactly two leaves.
+pub const RECORD_SIZE: usize = 40;
+
+/// Owner and balance stored in one record
+pub fn decode_record(record: &[u8]) -> Result<([u8;32], u64), WitnessError> {
+    if record.len() != RECORD_SIZE {
+        return Err(WitnessError::RecordSize { expected: RECORD_SIZE, actual: record.len() });
+    }
+    Ok((record[..32].try_into().unwrap(), u64::from_le_bytes(record[32..].try_into().unwrap())))
+}
+
+pub fn encode_record(owner: &[u8;32], amount: u64) -> Vec<u8> {
+    let mut out = owner.to_vec();
+    out.extend_from_slice(&amount.to_le_bytes());
+    out
+}
+
+/// Ledger blob holding one record per `(owner, amount)`, in order
+pub fn ledger_blob(holders: &[([u8;32], u64)]) -> Vec<u8> {
+    holders.iter().flat_map(|(owner, amount)| encode_record(owner, *amount)).collect()
+}
+
+/// `amount` as u64 LE; the only instruction is a transfer from the first proven record
+/// to the second
+pub fn transfer_instruction(amount: u64) -> Vec<u8> {
+    amount.to_le_bytes().to_vec()
+}
+
+/// Balances of every holder in one offloaded account. Transfers go through `ChunkTx`
+/// and only ever see the two records they move tokens between.
+pub struct TokenProgram;
+
+impl Program for TokenProgram {
+    fn process(&self, _ctx: &mut InvokeContext, _accounts: &[AccountView], _instruction_data: &[u8], _witnesses: &[AccountWitness]) -> Result<Effects, WitnessError> {
+        Err(WitnessError::Rejected("token transfers are partial writes; submit them as a ChunkTx".to_string()))
+    }
+
+    fn process_chunks(&self, chunks: &[ChunkWitness], instruction_data: &[u8], signers: &HashSet<[u8;32]>) -> Result<Vec<(usize, Vec<u8>)>, WitnessError> {
+        let (from, to) = match chunks {
+            [from, to] => (from, to),
+            _ => return Err(WitnessError::Rejected("transfer takes exactly the source and destination records".to_string())),
+        };
+        let amount = match instruction_data.try_into() {
+            Ok(b) => u64::from_le_bytes(b),
+            Err(_) => return Err(WitnessError::Rejected(format!("malformed transfer instruction ({} bytes)", instruction_data.len()))),
+        };
+        let (from_owner, from_balance) = decode_record(&from.chunk)?;
+        let (to_owner, to_balance) = decode_record(&to.chunk)?;
+        if !signers.contains(&from_owner) {
+            return Err(WitnessError::Rejected(format!("record {} is owned by {}, who did not sign", from.leaf_index, h(&from_owner))));
+        }
+        if amount > from_balance {
+            return Err(WitnessError::Rejected(format!("insufficient balance in record {}: {} < {}", from.leaf_index, from_balance, amount)));
+        }
+        let to_balance = match to_balance.checked_add(amount) {
+            Some(b) => b,
+            None => return Err(WitnessError::Rejected(format!("balance overflow in record {}", to.leaf_index))),
+        };
+        Ok(vec![
+            (from.leaf_index, encode_record(&from_owner, from_balance - amount)),
+            (to.leaf_index, encode_record(&to_owner, to_balance)),
+        ])
+    }
+}
+
+/// Thousands of holders in one account; each transfer proves only its two records
+pub fn demo() -> Result<()> {
+    let mut chain = ChainState::new();
+    let archive = ArchiveNode::new();
+    chain.subscribe(Box::new(archive.clone()));
+    chain.register_program(TOKEN_PROGRAM_ID, Box::new(TokenProgram));
+    let key = |i: u32| SigningKey::from_bytes(&sha256(format!("holder-{}", i).as_bytes()));
+    let holders: Vec<([u8;32], u64)> = (0..4096u32).map(|i| (key(i).verifying_key().to_bytes(), 1_000)).collect();
+
+    let blob = ledger_blob(&holders);
+    let root = archive.ingest("Token1", chain.slot, RECORD_SIZE, &blob)?;
+    chain.put_stub("Token1", AccountStub::new(TOKEN_PROGRAM_ID, 0, root, blob.len() as u64, RECORD_SIZE));
+
+    let transfer = |chain: &mut ChainState, from: u32, to: u32, amount: u64| -> Result<TxReceipt> {
+        let root = chain.get_stub("Token1").unwrap().merkle_root;
+        let mut chunks = Vec::new();
+        for leaf in [from as usize, to as usize] {
+            match archive.get_chunk_witness("Token1", &root, leaf) {
+                Some(c) => chunks.push(c),
+                None => bail!("archive has no record {} under root {}", leaf, h(&root)),
+            }
+        }
+        let mut tx = ChunkTx {
+            program_id: TOKEN_PROGRAM_ID.to_string(),
+            instruction_data: transfer_instruction(amount),
+            pubkey: "Token1".to_string(),
+            chunks,
+            recent_blockhash: chain.latest_blockhash(),
+            signatures: Vec::new(),
+        };
+        tx.sign(&key(from));
+        Ok(chain.process_chunk_tx(&tx)?)
+    };
+
+    let receipt = transfer(&mut chain, 7, 3000, 250)?;
+    transfer(&mut chain, 3000, 4095, 1_100)?;
+    if transfer(&mut chain, 12, 13, 5_000).is_ok() {
+        bail!("transfer above the balance went through");
+    }
+    println!("Transfer over {} holders in {} CU, proving 2 of {} records", holders.len(), receipt.compute_units, holders.len());
+
+    // the archive rebuilt each version from the patches alone
+    let stub = chain.get_stub("Token1").unwrap();
+    let blob = archive.get_blob("Token1", &stub.merkle_root).unwrap().data;
+    if MerkleTree::from_chunks(&chunk_blob(&blob, RECORD_SIZE)).root() != stub.merkle_root {
+        bail!("archived ledger does not match the on-chain root");
+    }
+    for i in [7usize, 3000, 4095] {
+        let (_, amount) = decode_record(&blob[i * RECORD_SIZE..(i + 1) * RECORD_SIZE])?;
+        println!("  holder {}: {}", i, amount);
+    }
+    println!("Ledger root {} over {} bytes, {} archived versions", h(&stub.merkle_root), stub.data_len, archive.version_count("Token1"));
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Five holders, so the ledger's tree pads with three copies of the last record
+    const HOLDERS: u32 = 5;
+
+    fn key(i: u32) -> SigningKey {
+        SigningKey::from_bytes(&sha256(format!("token-holder-{}", i).as_bytes()))
+    }
+
+    fn ledger() -> (ChainState, ArchiveNode) {
+        let mut chain = ChainState::new();
+        let archive = ArchiveNode::new();
+        chain.subscribe(Box::new(archive.clone()));
+        chain.register_program(TOKEN_PROGRAM_ID, Box::new(TokenProgram));
+        let holders: Vec<([u8;32], u64)> = (0..HOLDERS).map(|i| (key(i).verifying_key().to_bytes(), 100)).collect();
+        let blob = ledger_blob(&holders);
+        let root = archive.ingest("Token1", chain.slot, RECORD_SIZE, &blob).unwrap();
+        chain.put_stub("Token1", AccountStub::new(TOKEN_PROGRAM_ID, 0, root, blob.len() as u64, RECORD_SIZE));
+        (chain, archive)
+    }
+
+    fn transfer(chain: &mut ChainState, archive: &ArchiveNode, from: u32, to: u32, amount: u64) -> Result<TxReceipt, WitnessError> {
+        let root = chain.get_stub("Token1").unwrap().merkle_root;
+        let chunks = [from, to].iter().map(|&i| archive.get_chunk_witness("Token1", &root, i as usize).unwrap()).collect();
+        let mut tx = ChunkTx {
+            program_id: TOKEN_PROGRAM_ID.to_string(),
+            instruction_data: transfer_instruction(amount),
+            pubkey: "Token1".to_string(),
+            chunks,
+            recent_blockhash: chain.latest_blockhash(),
+            signatures: Vec::new(),
+        };
+        tx.sign(&key(from));
+        chain.process_chunk_tx(&tx)
+    }
+
+    fn balances(chain: &ChainState, archive: &ArchiveNode) -> Vec<u64> {
+        let stub = chain.get_stub("Token1").unwrap();
+        let blob = archive.get_blob("Token1", &stub.merkle_root).unwrap().data;
+        assert_eq!(MerkleTree::from_chunks(&chunk_blob(&blob, RECORD_SIZE)).root(), stub.merkle_root);
+        blob.chunks(RECORD_SIZE).map(|r| decode_record(r).unwrap().1).collect()
+    }
+
+    #[test]
+    fn last_holder_sends_and_receives() {
+        let (mut chain, archive) = ledger();
+        transfer(&mut chain, &archive, 4, 1, 30).unwrap();
+        assert_eq!(balances(&chain, &archive), [100, 130, 100, 100, 70]);
+        transfer(&mut chain, &archive, 2, 4, 50).unwrap();
+        assert_eq!(balances(&chain, &archive), [100, 130, 50, 100, 120]);
+        // the next transfer proves the last record against the root its padding moved to
+        transfer(&mut chain, &archive, 4, 0, 120).unwrap();
+        assert_eq!(balances(&chain, &archive), [220, 130, 50, 100, 0]);
+    }
+
+    #[test]
+    fn rejects_unsigned_and_overdrawn_transfers() {
+        let (mut chain, archive) = ledger();
+        assert!(matches!(transfer(&mut chain, &archive, 4, 0, 101), Err(WitnessError::Rejected(_))));
+        let root = chain.get_stub("Token1").unwrap().merkle_root;
+        let chunks = [0usize, 4].iter().map(|&i| archive.get_chunk_witness("Token1", &root, i).unwrap()).collect();
+        let mut tx = ChunkTx { program_id: TOKEN_PROGRAM_ID.to_string(), instruction_data: transfer_instruction(1), pubkey: "Token1".to_string(), chunks, recent_blockhash: chain.latest_blockhash(), signatures: Vec::new() };
+        tx.sign(&key(4));
+        assert!(matches!(chain.process_chunk_tx(&tx), Err(WitnessError::Rejected(_))));
+        assert_eq!(balances(&chain, &archive), [100; HOLDERS as usize]);
+    }
+}
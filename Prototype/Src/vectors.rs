@@ -0,0 +1,252 @@
+//! Golden test vectors: blobs, their trees and proofs, frozen as JSON so every other
+//! implementation (the wasm/TypeScript bindings, the on-chain verifier, FFI callers) can
+//! be checked against exactly the bytes this one commits. `vectors generate` writes the
+//! canonical set; `vectors check` takes a set written by any implementation and checks
+//! it agrees with this one.
+//!
+//! Each vector holds the blob, its chunk size, the padded leaf layer and root, proofs
+//! in the `ProofFile` JSON schema plus their compact `steps` and binary encodings, and
+//! `invalid` witnesses that a verifier must reject as the leaf they name.
+
+use anyhow::{Context, Result, bail};
+use serde::{Deserialize, Serialize};
+
+use crate::proof_file::{self, ProofFile, ProofJson};
+use crate::rng::SimRng;
+use crate::{chunk_blob, MerkleTree};
+
+/// Bumped whenever the vector file layout changes
+pub const VECTORS_VERSION: u8 = 1;
+
+/// (name, blob length, chunk size, seed of the blob bytes; 0 for all zeros)
+const CASES: &[(&str, usize, usize, u64)] = &[
+    ("empty blob", 0, 32, 1),
+    ("one byte", 1, 32, 2),
+    ("one full chunk", 32, 32, 3),
+    ("one chunk and a byte", 33, 32, 4),
+    ("three chunks, last leaf repeated", 96, 32, 5),
+    ("five chunks, short last", 150, 32, 6),
+    ("eight chunks, no padding", 256, 32, 7),
+    ("identical chunks", 128, 32, 0),
+    ("one-byte chunks", 7, 1, 8),
+    ("chunk size not a power of two", 1000, 100, 9),
+    ("nine 256-byte chunks", 9 * 256 - 100, 256, 10),
+    ("1 KiB chunks", 2 * 1024 + 5, 1024, 11),
+];
+
+/// Vectors of more chunks than this prove only a few leaves: both ends and the middle
+const ALL_PROOFS_UP_TO: usize = 8;
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct VectorSet {
+    pub version: u8,
+    /// `ProofFile` encoding version the proofs are written in
+    pub proof_version: u8,
+    pub hash: String,
+    pub vectors: Vec<Vector>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Vector {
+    pub name: String,
+    pub chunk_size: usize,
+    pub blob: String,
+    pub chunk_count: usize,
+    pub depth: usize,
+    /// The padded leaf layer
+    pub leaves: Vec<String>,
+    pub root: String,
+    pub proofs: Vec<VectorProof>,
+    pub invalid: Vec<InvalidWitness>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct VectorProof {
+    #[serde(flatten)]
+    pub proof: ProofJson,
+    /// `encode_steps` of the proof
+    pub steps: String,
+    /// `ProofFile::to_bytes`
+    pub binary: String,
+}
+
+/// A chunk and encoded steps that must not verify as `leaf_index` under the vector's root
+#[derive(Debug, Serialize, Deserialize)]
+pub struct InvalidWitness {
+    pub name: String,
+    pub leaf_index: usize,
+    pub chunk: String,
+    pub steps: String,
+}
+
+fn blob(len: usize, seed: u64) -> Vec<u8> {
+    if seed == 0 {
+        return vec![0; len];
+    }
+    let mut rng = SimRng::new(seed);
+    (0..len).map(|_| rng.next_u64() as u8).collect()
+}
+
+/// Leaves a vector proves
+fn proven_leaves(chunks: usize) -> Vec<usize> {
+    if chunks <= ALL_PROOFS_UP_TO {
+        return (0..chunks).collect();
+    }
+    let mut leaves = vec![0, 1, chunks / 2, chunks - 2, chunks - 1];
+    leaves.dedup();
+    leaves
+}
+
+/// Ways to break the witness of `leaf`, each still claiming to be that leaf
+fn invalid_witnesses(chunks: &[Vec<u8>], tree: &MerkleTree, leaf: usize) -> Vec<InvalidWitness> {
+    let chunk = &chunks[leaf];
+    let steps = witness_core::encode_steps(&tree.gen_proof(leaf));
+    let mut cases = Vec::new();
+    let mut add = |name: &str, chunk: &[u8], steps: &[u8]| cases.push(InvalidWitness {
+        name: name.to_string(), leaf_index: leaf, chunk: hex::encode(chunk), steps: hex::encode(steps),
+    });
+
+    let mut flipped = chunk.clone();
+    flipped[0] ^= 1;
+    add("chunk bit flipped", &flipped, &steps);
+    let mut appended = steps.clone();
+    appended.extend_from_slice(&[0; witness_core::STEP_LEN]);
+    add("extra step", chunk, &appended);
+    if steps.is_empty() {
+        return cases;
+    }
+    let mut broken = steps.clone();
+    broken[0] ^= 0x80;
+    add("sibling bit flipped", chunk, &broken);
+    let mut broken = steps.clone();
+    broken[32] ^= 1;
+    add("direction flipped", chunk, &broken);
+    let mut broken = steps.clone();
+    broken[32] = 2;
+    add("direction byte not 0 or 1", chunk, &broken);
+    add("last step dropped", chunk, &steps[..steps.len() - witness_core::STEP_LEN]);
+    add("trailing byte", chunk, &[steps.as_slice(), &[0]].concat());
+    let other = (leaf + 1) % chunks.len();
+    if other != leaf {
+        if chunks[other] != *chunk {
+            add("another leaf's chunk", &chunks[other], &steps);
+        }
+        add("another leaf's proof", chunk, &witness_core::encode_steps(&tree.gen_proof(other)));
+    }
+    cases
+}
+
+fn vector(name: &str, blob: &[u8], chunk_size: usize) -> Vector {
+    let chunks = chunk_blob(blob, chunk_size).to_vecs();
+    let tree = MerkleTree::from_chunks(&chunks);
+    let root = tree.root();
+    let proven = proven_leaves(chunks.len());
+    let proofs = proven.iter().map(|&leaf| {
+        let steps = tree.gen_proof(leaf);
+        let file = ProofFile::new(root, leaf, chunk_size, chunks[leaf].clone(), &steps);
+        VectorProof { proof: file.json(), steps: hex::encode(witness_core::encode_steps(&steps)), binary: hex::encode(file.to_bytes()) }
+    }).collect();
+    Vector {
+        name: name.to_string(),
+        chunk_size,
+        blob: hex::encode(blob),
+        chunk_count: chunks.len(),
+        depth: tree.depth(),
+        leaves: tree.leaves().iter().map(hex::encode).collect(),
+        root: hex::encode(root),
+        proofs,
+        invalid: invalid_witnesses(&chunks, &tree, proven[proven.len() / 2]),
+    }
+}
+
+/// The canonical set, the same on every run
+pub fn generate() -> VectorSet {
+    VectorSet {
+        version: VECTORS_VERSION,
+        proof_version: proof_file::VERSION,
+        hash: "sha256".to_string(),
+        vectors: CASES.iter().map(|&(name, len, chunk_size, seed)| vector(name, &blob(len, seed), chunk_size)).collect(),
+    }
+}
+
+fn hex_field(s: &str, what: &str) -> Result<Vec<u8>> {
+    hex::decode(s).with_context(|| format!("{} is not hex", what))
+}
+
+/// Check one vector against this implementation: the tree it lists is the tree of its
+/// blob, its proofs are the ones this implementation makes and verify, and none of its
+/// invalid witnesses verify
+pub fn check_vector(v: &Vector) -> Result<()> {
+    if v.chunk_size == 0 {
+        bail!("chunk_size is 0");
+    }
+    let blob = hex_field(&v.blob, "blob")?;
+    let chunks = chunk_blob(&blob, v.chunk_size);
+    let tree = MerkleTree::from_chunks(&chunks);
+    let root = tree.root();
+    if v.chunk_count != chunks.len() {
+        bail!("chunk_count is {}, the blob has {} chunks", v.chunk_count, chunks.len());
+    }
+    if v.depth != tree.depth() {
+        bail!("depth is {}, the tree is {} deep", v.depth, tree.depth());
+    }
+    let leaves: Vec<String> = tree.leaves().iter().map(hex::encode).collect();
+    if let Some(i) = (0..leaves.len().max(v.leaves.len())).find(|&i| leaves.get(i) != v.leaves.get(i)) {
+        bail!("leaf {} is {}, expected {}", i, v.leaves.get(i).map_or("missing", String::as_str), leaves.get(i).map_or("missing", String::as_str));
+    }
+    if v.root != hex::encode(root) {
+        bail!("root is {}, expected {}", v.root, hex::encode(root));
+    }
+    for p in &v.proofs {
+        let leaf = p.proof.leaf_index;
+        let file = ProofFile::from_json_schema(p.proof.clone()).with_context(|| format!("proof of leaf {}", leaf))?;
+        file.verify(&root, chunks.len()).with_context(|| format!("proof of leaf {}", leaf))?;
+        if leaf >= chunks.len() || file.chunk != chunks[leaf] {
+            bail!("proof of leaf {} carries a chunk the blob does not have there", leaf);
+        }
+        let expected = tree.gen_proof(leaf);
+        if file.steps() != expected {
+            bail!("proof of leaf {} differs from this implementation's", leaf);
+        }
+        if hex_field(&p.steps, "steps")? != witness_core::encode_steps(&expected) {
+            bail!("steps of leaf {} are not the compact encoding of its proof", leaf);
+        }
+        if hex_field(&p.binary, "binary")? != file.to_bytes() {
+            bail!("binary proof of leaf {} is not the ProofFile encoding", leaf);
+        }
+    }
+    for w in &v.invalid {
+        let chunk = hex_field(&w.chunk, "chunk")?;
+        let steps = hex_field(&w.steps, "steps")?;
+        let accepted = witness_core::decode_steps(&steps)
+            .is_some_and(|proof| MerkleTree::verify_proof_at(&chunk, &proof, &root, w.leaf_index, chunks.len()));
+        if accepted {
+            bail!("invalid witness {:?} for leaf {} verifies", w.name, w.leaf_index);
+        }
+    }
+    Ok(())
+}
+
+/// `check_vector` over a whole set, printing a line per vector; fails if any did
+pub fn check(set: &VectorSet) -> Result<()> {
+    if set.version != VECTORS_VERSION {
+        bail!("unsupported vector file version {}", set.version);
+    }
+    if set.hash != "sha256" {
+        bail!("vectors are for {}, this implementation commits sha256 trees", set.hash);
+    }
+    let mut failed = 0;
+    for v in &set.vectors {
+        match check_vector(v) {
+            Ok(()) => println!("ok   {}: root {}, {} proofs, {} invalid witnesses rejected", v.name, &v.root[..16], v.proofs.len(), v.invalid.len()),
+            Err(e) => {
+                failed += 1;
+                println!("FAIL {}: {:#}", v.name, e);
+            }
+        }
+    }
+    if failed > 0 {
+        bail!("{} of {} vectors failed", failed, set.vectors.len());
+    }
+    Ok(())
+}
@@ -0,0 +1,226 @@
+use std::time::Instant;
+
+use anyhow::Result;
+
+use crate::compute::ComputeMeter;
+use crate::error::WitnessError;
+use crate::events::{StdoutSubscriber, WitnessEvent};
+use crate::metrics::metrics;
+use crate::program::{AccountView, Effects, InvokeContext, Program, DEMO_PROGRAM_ID};
+use crate::{chunk_blob, AccountStub, AccountWitness, ChainState, MerkleTree};
+
+/// One chunk of an account proven against its stub root; no other account data is sent
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct ChunkWitness {
+    pub pubkey: String,
+    pub leaf_index: usize,
+    #[serde(with = "crate::serde_support::hex_form")]
+    pub chunk: Vec<u8>,
+    #[serde(with = "crate::serde_support::steps")]
+    pub proof: Vec<([u8;32], bool)>,
+}
+
+/// A read-only instruction: `program_id` inspects the proven chunks and may fail or
+/// return data, but nothing it does changes state
+#[derive(Debug, Clone)]
+pub struct ViewTx {
+    pub program_id: String,
+    pub instruction_data: Vec<u8>,
+    pub chunks: Vec<ChunkWitness>,
+    pub recent_blockhash: [u8;32],
+}
+
+#[derive(Debug, Clone)]
+pub struct ViewReceipt {
+    pub compute_units: u64,
+    /// Whatever the program returned (Solana's `set_return_data`)
+    pub return_data: Vec<u8>,
+}
+
+/// Checks that a `u64` stored little-endian in a chunk is at least a threshold,
+/// e.g. "my balance is ≥ X" without revealing or touching the rest of the account.
+/// Instruction data: offset within the chunk (u16 LE) then the threshold (u64 LE).
+pub struct MinBalanceProgram;
+
+impl Program for MinBalanceProgram {
+    fn process(&self, _ctx: &mut InvokeContext, _accounts: &[AccountView], _instruction_data: &[u8], _witnesses: &[AccountWitness]) -> Result<Effects, WitnessError> {
+        Err(WitnessError::Rejected("MinBalanceProgram only supports view instructions".to_string()))
+    }
+
+    fn view(&self, chunks: &[ChunkWitness], instruction_data: &[u8]) -> Result<Vec<u8>, WitnessError> {
+        if instruction_data.len() != 10 || chunks.len() != 1 {
+            return Err(WitnessError::Rejected("expected 10 bytes of instruction data and one chunk".to_string()));
+        }
+        let offset = u16::from_le_bytes([instruction_data[0], instruction_data[1]]) as usize;
+        let threshold = u64::from_le_bytes(instruction_data[2..10].try_into().unwrap());
+        let balance = match chunks[0].chunk.get(offset..offset + 8) {
+            Some(b) => u64::from_le_bytes(b.try_into().unwrap()),
+            None => return Err(WitnessError::Rejected(format!("offset {} out of chunk bounds", offset))),
+        };
+        if balance < threshold {
+            return Err(WitnessError::Rejected(format!("balance below {}", threshold)));
+        }
+        Ok(vec![1])
+    }
+}
+
+impl ChainState {
+    /// Verify every chunk of `tx` against its stub, then let the program read them.
+    /// Stubs, the status cache and the slot are left untouched.
+    pub fn process_view(&self, tx: &ViewTx) -> Result<ViewReceipt, WitnessError> {
+        let _span = tracing::debug_span!("process_view", program = %tx.program_id, chunks = tx.chunks.len()).entered();
+        if !self.recent_blockhashes.contains(&tx.recent_blockhash) {
+            return Err(WitnessError::BlockhashNotFound(tx.recent_blockhash));
+        }
+        let costs = &self.compute_costs;
+        let mut meter = ComputeMeter::new(self.compute_budget);
+        let started = Instant::now();
+        for c in &tx.chunks {
+            let stub = match self.get_stub(&c.pubkey) {
+                Some(s) => s,
+                None => return Err(WitnessError::UnknownAccount(c.pubkey.clone())),
+            };
+            stub.check_layout()?;
+            if c.chunk.len() != stub.chunk_size {
+                return Err(WitnessError::ChunkSizeMismatch { pubkey: c.pubkey.clone(), expected: stub.chunk_size, actual: c.chunk.len() });
+            }
+            meter.consume(costs.mem_copy(c.chunk.len()), "chunk load")?;
+            meter.consume(costs.verify_proof(stub.chunk_size, c.proof.len()), "proof verification")?;
+            stub.check_proof_shape(&c.pubkey, c.leaf_index, &c.proof)?;
+            let computed = witness_core::compute_root(&c.chunk, &c.proof);
+            if !witness_core::ct::eq(&computed, &stub.merkle_root) {
+                return Err(WitnessError::ProofVerificationFailed { pubkey: c.pubkey.clone(), leaf_index: c.leaf_index, expected: stub.merkle_root, computed });
+            }
+            tracing::debug!(pubkey = %c.pubkey, leaf_index = c.leaf_index, "chunk verified");
+        }
+        metrics().observe_verification("view", started.elapsed());
+        let program = match self.programs.get(&tx.program_id) {
+            Some(p) => p,
+            None => return Err(WitnessError::UnknownProgram(tx.program_id.clone())),
+        };
+        let return_data = program.view(&tx.chunks, &tx.instruction_data)?;
+        Ok(ViewReceipt { compute_units: meter.consumed(), return_data })
+    }
+
+    /// `process_view` plus events: `ProofVerified` per chunk, or `TxFailed`
+    pub fn submit_view(&mut self, tx: &ViewTx) -> Result<ViewReceipt, WitnessError> {
+        let res = self.process_view(tx);
+        match &res {
+            Ok(_) => for c in &tx.chunks {
+                let root = self.get_stub(&c.pubkey).unwrap().merkle_root;
+                self.emit(WitnessEvent::ProofVerified { pubkey: c.pubkey.clone(), leaf_index: c.leaf_index, root });
+            },
+            Err(e) => {
+                tracing::info!(program = %tx.program_id, error = %e, "view failed");
+                let pubkey = tx.chunks.first().map(|c| c.pubkey.clone()).unwrap_or_default();
+                self.emit(WitnessEvent::TxFailed { pubkey, reason: e.to_string() });
+            }
+        }
+        res
+    }
+}
+
+/// Show the u64 at the start of a chunk is ≥ 1000 without rewriting anything
+pub fn demo(chunk_size: usize) -> Result<()> {
+    let mut chain = ChainState::new();
+    chain.subscribe(Box::new(StdoutSubscriber));
+    let balances_id = "BalanceCheck1";
+    chain.register_program(balances_id, Box::new(MinBalanceProgram));
+    let mut balances = vec![0u8; 4 * chunk_size];
+    balances[2 * chunk_size..2 * chunk_size + 8].copy_from_slice(&5_000u64.to_le_bytes());
+    let tree = MerkleTree::from_chunks(&chunk_blob(&balances, chunk_size));
+    chain.put_stub("Balances1", AccountStub::new(DEMO_PROGRAM_ID, 1_000, tree.root(), balances.len() as u64, chunk_size));
+    let mut check = 0u16.to_le_bytes().to_vec();
+    check.extend_from_slice(&1_000u64.to_le_bytes());
+    let view = chain.submit_view(&ViewTx {
+        program_id: balances_id.to_string(),
+        instruction_data: check,
+        chunks: vec![ChunkWitness { pubkey: "Balances1".to_string(), leaf_index: 2, chunk: balances[2 * chunk_size..3 * chunk_size].to_vec(), proof: tree.gen_proof(2) }],
+        recent_blockhash: chain.latest_blockhash(),
+    })?;
+    println!("Balance ≥ 1000 proven from one chunk: {} CU, returned {:?}, root unchanged: {}",
+        view.compute_units, view.return_data, chain.get_stub("Balances1").unwrap().merkle_root == tree.root());
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CHUNK: usize = 32;
+
+    /// "Balances1" holds 5000 at the start of chunk 2 of 4
+    fn chain() -> (ChainState, Vec<u8>, MerkleTree) {
+        let mut chain = ChainState::new();
+        chain.register_program("BalanceCheck1", Box::new(MinBalanceProgram));
+        let mut balances = vec![0u8; 4 * CHUNK];
+        balances[2 * CHUNK..2 * CHUNK + 8].copy_from_slice(&5_000u64.to_le_bytes());
+        let tree = MerkleTree::from_chunks(&chunk_blob(&balances, CHUNK));
+        chain.put_stub("Balances1", AccountStub::new(DEMO_PROGRAM_ID, 0, tree.root(), balances.len() as u64, CHUNK));
+        (chain, balances, tree)
+    }
+
+    fn view(chain: &ChainState, balances: &[u8], tree: &MerkleTree, leaf_index: usize, threshold: u64) -> ViewTx {
+        let mut check = 0u16.to_le_bytes().to_vec();
+        check.extend_from_slice(&threshold.to_le_bytes());
+        ViewTx {
+            program_id: "BalanceCheck1".to_string(),
+            instruction_data: check,
+            chunks: vec![ChunkWitness { pubkey: "Balances1".to_string(), leaf_index, chunk: balances[leaf_index * CHUNK..(leaf_index + 1) * CHUNK].to_vec(), proof: tree.gen_proof(leaf_index) }],
+            recent_blockhash: chain.latest_blockhash(),
+        }
+    }
+
+    #[test]
+    fn a_proven_chunk_answers_without_changing_state() {
+        let (mut chain, balances, tree) = chain();
+        let slot = chain.slot;
+        let tx = view(&chain, &balances, &tree, 2, 5_000);
+        let receipt = chain.submit_view(&tx).unwrap();
+        assert_eq!(receipt.return_data, vec![1]);
+        assert!(receipt.compute_units > 0);
+        // nothing is recorded, so the same view runs again
+        chain.submit_view(&tx).unwrap();
+        assert_eq!((chain.slot, chain.get_stub("Balances1").unwrap().merkle_root), (slot, tree.root()));
+    }
+
+    #[test]
+    fn the_program_can_refuse() {
+        let (chain, balances, tree) = chain();
+        assert!(matches!(chain.process_view(&view(&chain, &balances, &tree, 2, 5_001)), Err(WitnessError::Rejected(_))));
+        let mut bad_offset = view(&chain, &balances, &tree, 2, 0);
+        bad_offset.instruction_data[..2].copy_from_slice(&(CHUNK as u16 - 7).to_le_bytes());
+        assert!(matches!(chain.process_view(&bad_offset), Err(WitnessError::Rejected(_))));
+        let mut no_chunks = view(&chain, &balances, &tree, 2, 0);
+        no_chunks.chunks.clear();
+        assert!(matches!(chain.process_view(&no_chunks), Err(WitnessError::Rejected(_))));
+    }
+
+    #[test]
+    fn rejects_chunks_the_stub_does_not_commit_to() {
+        let (chain, balances, tree) = chain();
+        let mut forged = view(&chain, &balances, &tree, 1, 0);
+        forged.chunks[0].chunk[0..8].copy_from_slice(&9_000u64.to_le_bytes());
+        assert!(matches!(chain.process_view(&forged), Err(WitnessError::ProofVerificationFailed { leaf_index: 1, .. })));
+        let mut moved = view(&chain, &balances, &tree, 2, 0);
+        moved.chunks[0].leaf_index = 3;
+        assert!(matches!(chain.process_view(&moved), Err(WitnessError::ProofIndexMismatch { claimed: 3, proven: 2 })));
+        let mut short = view(&chain, &balances, &tree, 2, 0);
+        short.chunks[0].chunk.pop();
+        assert!(matches!(chain.process_view(&short), Err(WitnessError::ChunkSizeMismatch { actual: 31, .. })));
+        let mut unknown = view(&chain, &balances, &tree, 2, 0);
+        unknown.chunks[0].pubkey = "Other".to_string();
+        assert!(matches!(chain.process_view(&unknown), Err(WitnessError::UnknownAccount(_))));
+    }
+
+    #[test]
+    fn rejects_unknown_programs_and_blockhashes() {
+        let (chain, balances, tree) = chain();
+        let mut unknown = view(&chain, &balances, &tree, 2, 0);
+        unknown.program_id = "Nobody".to_string();
+        assert!(matches!(chain.process_view(&unknown), Err(WitnessError::UnknownProgram(_))));
+        let mut stale = view(&chain, &balances, &tree, 2, 0);
+        stale.recent_blockhash = [9; 32];
+        assert!(matches!(chain.process_view(&stale), Err(WitnessError::BlockhashNotFound(_))));
+    }
+}
@@ -0,0 +1,223 @@
+//! `.witness` files: one chunk's inclusion proof as a self-describing bundle for
+//! archiving and passing between tools. Unlike a `ProofFile` it says which hash built
+//! the tree and how many leaves the tree has, so a reader can tell its depth and reject
+//! a proof padded or cut to a different tree without knowing anything else about it.
+//!
+//! Layout, integers little-endian: `MAGIC`, version u8, hasher id u8, chunk_size u32,
+//! leaf_index u64, leaf_count u64, root, chunk len u32 and bytes, sibling count u8, then
+//! the siblings leaf level first. A sibling's side is the leaf index's bit at its level,
+//! so no direction flags are stored.
+
+use std::path::Path;
+
+use anyhow::{Context, Result};
+
+use crate::compute::SyscallHasher;
+use crate::error::WitnessError;
+use crate::proof_file::ProofFile;
+
+/// Leading bytes of a `.witness` file
+pub const MAGIC: &[u8;4] = b"SBWT";
+/// Bumped whenever the layout changes; readers reject other versions
+pub const VERSION: u8 = 1;
+
+fn hasher_id(hasher: SyscallHasher) -> u8 {
+    match hasher {
+        SyscallHasher::Sha256 => 0,
+        SyscallHasher::Keccak256 => 1,
+        SyscallHasher::Blake3 => 2,
+        SyscallHasher::Poseidon => 3,
+    }
+}
+
+fn hasher_from_id(id: u8) -> Result<SyscallHasher, WitnessError> {
+    Ok(match id {
+        0 => SyscallHasher::Sha256,
+        1 => SyscallHasher::Keccak256,
+        2 => SyscallHasher::Blake3,
+        3 => SyscallHasher::Poseidon,
+        other => return Err(WitnessError::Malformed(format!("unknown hasher id {}", other))),
+    })
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WitnessFile {
+    pub hasher: SyscallHasher,
+    pub chunk_size: usize,
+    pub leaf_index: usize,
+    /// Chunks in the tree before padding
+    pub leaf_count: usize,
+    pub root: [u8;32],
+    pub chunk: Vec<u8>,
+    pub siblings: Vec<[u8;32]>,
+}
+
+fn take<'a>(bytes: &mut &'a [u8], n: usize) -> Result<&'a [u8], WitnessError> {
+    if bytes.len() < n {
+        return Err(WitnessError::Truncated("witness file"));
+    }
+    let (head, rest) = bytes.split_at(n);
+    *bytes = rest;
+    Ok(head)
+}
+
+impl WitnessFile {
+    /// Bundle a SHA-256 proof of chunk `leaf_index` of `leaf_count`
+    pub fn new(root: [u8;32], leaf_index: usize, leaf_count: usize, chunk_size: usize, chunk: Vec<u8>, proof: &[([u8;32], bool)]) -> Self {
+        Self { hasher: SyscallHasher::Sha256, chunk_size, leaf_index, leaf_count, root, chunk, siblings: proof.iter().map(|(s, _)| *s).collect() }
+    }
+
+    /// Proof steps with their sides spelled out, as `MerkleTree::verify_proof` takes them
+    pub fn steps(&self) -> Vec<([u8;32], bool)> {
+        self.siblings.iter().enumerate().map(|(level, s)| (*s, (self.leaf_index >> level) & 1 == 1)).collect()
+    }
+
+    /// The same proof in the interchange schema
+    pub fn to_proof(&self) -> ProofFile {
+        ProofFile::new(self.root, self.leaf_index, self.chunk_size, self.chunk.clone(), &self.steps())
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(4 + 2 + 4 + 16 + 32 + 4 + self.chunk.len() + 1 + self.siblings.len() * 32);
+        out.extend_from_slice(MAGIC);
+        out.push(VERSION);
+        out.push(hasher_id(self.hasher));
+        out.extend_from_slice(&(self.chunk_size as u32).to_le_bytes());
+        out.extend_from_slice(&(self.leaf_index as u64).to_le_bytes());
+        out.extend_from_slice(&(self.leaf_count as u64).to_le_bytes());
+        out.extend_from_slice(&self.root);
+        out.extend_from_slice(&(self.chunk.len() as u32).to_le_bytes());
+        out.extend_from_slice(&self.chunk);
+        out.push(self.siblings.len() as u8);
+        for sibling in &self.siblings {
+            out.extend_from_slice(sibling);
+        }
+        out
+    }
+
+    /// Parse and check the header against itself: the leaf is inside the tree and the
+    /// proof is as deep as the tree. The leaf count is the file's own word for it; `verify`
+    /// holds it to a trusted one.
+    pub fn from_bytes(mut bytes: &[u8]) -> Result<Self, WitnessError> {
+        let b = &mut bytes;
+        if take(b, 4)? != MAGIC {
+            return Err(WitnessError::BadMagic("witness file"));
+        }
+        let version = take(b, 1)?[0];
+        if version != VERSION {
+            return Err(WitnessError::UnsupportedVersion { what: "witness file", version });
+        }
+        let hasher = hasher_from_id(take(b, 1)?[0])?;
+        let chunk_size = u32::from_le_bytes(take(b, 4)?.try_into().unwrap()) as usize;
+        let leaf_index = u64::from_le_bytes(take(b, 8)?.try_into().unwrap()) as usize;
+        let leaf_count = u64::from_le_bytes(take(b, 8)?.try_into().unwrap()) as usize;
+        let root: [u8;32] = take(b, 32)?.try_into().unwrap();
+        let chunk_len = u32::from_le_bytes(take(b, 4)?.try_into().unwrap()) as usize;
+        let chunk = take(b, chunk_len)?.to_vec();
+        let steps = take(b, 1)?[0] as usize;
+        let siblings = (0..steps).map(|_| Ok(take(b, 32)?.try_into().unwrap())).collect::<Result<Vec<[u8;32]>, WitnessError>>()?;
+        if !b.is_empty() {
+            return Err(WitnessError::TrailingBytes { what: "witness", len: b.len() });
+        }
+        if leaf_index >= leaf_count {
+            return Err(WitnessError::LeafOutOfTree { leaf_index, leaf_count });
+        }
+        if steps != witness_core::tree_depth(leaf_count) {
+            return Err(WitnessError::WrongDepth { steps, leaf_count, depth: witness_core::tree_depth(leaf_count) });
+        }
+        Ok(Self { hasher, chunk_size, leaf_index, leaf_count, root, chunk, siblings })
+    }
+
+    pub fn read(path: &Path) -> Result<Self> {
+        let bytes = std::fs::read(path).with_context(|| format!("reading {}", path.display()))?;
+        Self::from_bytes(&bytes).with_context(|| format!("reading {}", path.display()))
+    }
+
+    pub fn write(&self, path: &Path) -> Result<()> {
+        std::fs::write(path, self.to_bytes()).with_context(|| format!("writing {}", path.display()))
+    }
+
+    /// Check the proof leads to its own root and, when given, that this is `root` and
+    /// the tree has `leaf_count` leaves. A root alone does not pin the depth: a witness
+    /// claiming fewer leaves can prove an interior node as a chunk.
+    pub fn verify(&self, root: Option<&[u8;32]>, leaf_count: Option<usize>) -> Result<(), WitnessError> {
+        if self.hasher != SyscallHasher::Sha256 {
+            return Err(WitnessError::UnsupportedHasher(self.hasher));
+        }
+        if let Some(root) = root {
+            if *root != self.root {
+                return Err(WitnessError::WrongRoot { proof: self.root, expected: *root });
+            }
+        }
+        if let Some(count) = leaf_count {
+            if count != self.leaf_count {
+                return Err(WitnessError::LeafCountMismatch { claimed: self.leaf_count, committed: count });
+            }
+        }
+        self.to_proof().verify(&self.root, self.leaf_count)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blob_file::BlobFile;
+    use crate::{chunk_blob, MerkleTree};
+
+    fn tree() -> (Vec<u8>, MerkleTree) {
+        let blob: Vec<u8> = (0..256u32).map(|i| (i * 13) as u8).collect();
+        let tree = MerkleTree::from_chunks(&chunk_blob(&blob, 64));
+        (blob, tree)
+    }
+
+    #[test]
+    fn round_trips_and_verifies() {
+        let (blob, tree) = tree();
+        let witness = WitnessFile::new(tree.root(), 1, 4, 64, blob[64..128].to_vec(), &tree.gen_proof(1));
+        let read = WitnessFile::from_bytes(&witness.to_bytes()).unwrap();
+        assert_eq!(read, witness);
+        read.verify(Some(&tree.root()), Some(4)).unwrap();
+        assert_eq!(read.steps(), tree.gen_proof(1));
+    }
+
+    #[test]
+    fn rejects_inconsistent_header() {
+        let (blob, tree) = tree();
+        let mut witness = WitnessFile::new(tree.root(), 1, 4, 64, blob[64..128].to_vec(), &tree.gen_proof(1));
+        witness.leaf_count = 8;
+        assert!(WitnessFile::from_bytes(&witness.to_bytes()).unwrap_err().to_string().contains("2 siblings"));
+        witness.leaf_count = 1;
+        assert!(WitnessFile::from_bytes(&witness.to_bytes()).unwrap_err().to_string().contains("out of range"));
+        witness.leaf_count = 4;
+        let mut bytes = witness.to_bytes();
+        bytes.push(0);
+        assert!(WitnessFile::from_bytes(&bytes).is_err());
+        bytes[4] = VERSION + 1;
+        assert!(WitnessFile::from_bytes(&bytes).is_err());
+    }
+
+    #[test]
+    fn rejects_leaf_count_the_container_does_not_have() {
+        let (blob, tree) = tree();
+        let container = BlobFile::commit(&blob, 64, None).unwrap();
+        // claiming two leaves makes the two leaf hashes under node (1, 0) a 64-byte chunk
+        let mut node = tree.layer(0)[0].to_vec();
+        node.extend_from_slice(&tree.layer(0)[1]);
+        let forged = WitnessFile::new(tree.root(), 0, 2, 64, node, &[(tree.layer(1)[1], false)]);
+        let read = WitnessFile::from_bytes(&forged.to_bytes()).unwrap();
+        read.verify(Some(&container.root), None).unwrap();
+        assert!(matches!(read.verify(Some(&container.root), Some(container.chunk_count)), Err(WitnessError::LeafCountMismatch { claimed: 2, committed: 4 })));
+    }
+
+    #[test]
+    fn rejects_other_root_and_hasher() {
+        let (blob, tree) = tree();
+        let mut witness = WitnessFile::new(tree.root(), 0, 4, 64, blob[..64].to_vec(), &tree.gen_proof(0));
+        assert!(witness.verify(Some(&[0; 32]), None).is_err());
+        witness.chunk[0] ^= 1;
+        assert!(witness.verify(None, Some(4)).is_err());
+        witness.chunk[0] ^= 1;
+        witness.hasher = SyscallHasher::Keccak256;
+        assert!(witness.verify(None, None).is_err());
+    }
+}
@@ -0,0 +1,494 @@
+//! Synthetic workloads for performance and cost experiments: a seeded stream of account
+//! creations, small updates, large updates and reads, with account popularity following
+//! a Zipf law. The same config and seed give the same ops on every run and platform.
+//!
+//! ```toml
+//! seed = 1
+//! initial_accounts = 16
+//! ops = 1000
+//! zipf_exponent = 1.0       # 0 picks accounts uniformly
+//! account_size = [256, 4096]
+//! small_update = [1, 32]    # bytes written, inclusive range
+//! large_update = [512, 2048]
+//! read_len = [1, 64]
+//!
+//! [mix]                     # relative weights
+//! create = 0.05
+//! small_update = 0.6
+//! large_update = 0.1
+//! read = 0.25
+//! ```
+//!
+//! `Driver` feeds the ops to a `ChainState`: creations become stubs, updates witness
+//! transactions carrying the account, reads view transactions carrying only the chunks
+//! they cover.
+
+use std::collections::BTreeMap;
+
+use anyhow::{Context, Result, bail};
+use serde::Deserialize;
+
+use crate::error::WitnessError;
+use crate::program::{AccountView, Effects, InvokeContext, Program, MAX_ACCOUNT_DATA_LEN};
+use crate::rng::SimRng;
+use crate::view::{ChunkWitness, ViewTx};
+use crate::{chunk_blob, AccountStub, AccountWitness, ChainState, MerkleTree, WitnessTx};
+
+/// Program workload accounts are owned by
+pub const WORKLOAD_PROGRAM: &str = "WorkloadProgram1";
+
+/// Relative weights of the op kinds
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct OpMix {
+    pub create: f64,
+    pub small_update: f64,
+    pub large_update: f64,
+    pub read: f64,
+}
+
+impl Default for OpMix {
+    fn default() -> Self {
+        Self { create: 0.05, small_update: 0.6, large_update: 0.1, read: 0.25 }
+    }
+}
+
+/// Shape of a workload; every key is optional
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct WorkloadConfig {
+    pub seed: u64,
+    /// Accounts created before the first drawn op
+    pub initial_accounts: usize,
+    /// Ops drawn from `mix` after them
+    pub ops: usize,
+    pub mix: OpMix,
+    /// Exponent of the Zipf law over accounts, earliest created most popular
+    pub zipf_exponent: f64,
+    /// Inclusive byte ranges, drawn uniformly
+    pub account_size: (usize, usize),
+    pub small_update: (usize, usize),
+    pub large_update: (usize, usize),
+    pub read_len: (usize, usize),
+}
+
+impl Default for WorkloadConfig {
+    fn default() -> Self {
+        Self {
+            seed: 1,
+            initial_accounts: 16,
+            ops: 1000,
+            mix: OpMix::default(),
+            zipf_exponent: 1.0,
+            account_size: (256, 4096),
+            small_update: (1, 32),
+            large_update: (512, 2048),
+            read_len: (1, 64),
+        }
+    }
+}
+
+impl WorkloadConfig {
+    pub fn from_toml_str(text: &str) -> Result<Self> {
+        toml::from_str(text).context("parsing workload")
+    }
+
+    pub fn load(path: &std::path::Path) -> Result<Self> {
+        let text = std::fs::read_to_string(path).with_context(|| format!("reading workload {}", path.display()))?;
+        Self::from_toml_str(&text).with_context(|| format!("in {}", path.display()))
+    }
+
+    pub fn validate(&self) -> Result<()> {
+        let weights = [self.mix.create, self.mix.small_update, self.mix.large_update, self.mix.read];
+        if weights.iter().any(|w| !w.is_finite() || *w < 0.0) || weights.iter().sum::<f64>() <= 0.0 {
+            bail!("op mix weights must be non-negative with a positive sum");
+        }
+        if !self.zipf_exponent.is_finite() || self.zipf_exponent < 0.0 {
+            bail!("zipf_exponent {} must be non-negative", self.zipf_exponent);
+        }
+        for (name, (lo, hi)) in [("account_size", self.account_size), ("small_update", self.small_update), ("large_update", self.large_update), ("read_len", self.read_len)] {
+            if lo == 0 || lo > hi {
+                bail!("{} range [{}, {}] must be non-empty and start at 1 or more", name, lo, hi);
+            }
+        }
+        if self.account_size.1 > MAX_ACCOUNT_DATA_LEN {
+            bail!("account_size {} exceeds the account size limit", self.account_size.1);
+        }
+        if self.initial_accounts == 0 && self.mix.create == 0.0 && self.ops > 0 {
+            bail!("no initial accounts and no creations: nothing to update or read");
+        }
+        Ok(())
+    }
+}
+
+/// Zipf law over ranks `0..n` by rejection-inversion (Hörmann and Derflinger), which
+/// needs no table and so follows `n` as accounts are created
+#[derive(Debug, Clone, Copy)]
+pub struct Zipf {
+    exponent: f64,
+}
+
+/// `f(x) / x` for `f` = `ln_1p` or `exp_m1`, taking the limit 1 near zero
+fn over_x(f: fn(f64) -> f64, x: f64) -> f64 {
+    if x.abs() > 1e-8 { f(x) / x } else { 1.0 }
+}
+
+impl Zipf {
+    pub fn new(exponent: f64) -> Self {
+        Self { exponent }
+    }
+
+    fn h(&self, x: f64) -> f64 {
+        (-self.exponent * x.ln()).exp()
+    }
+
+    /// Integral of `h`, `(x^(1-s) - 1) / (1-s)`
+    fn h_integral(&self, x: f64) -> f64 {
+        let ln_x = x.ln();
+        over_x(f64::exp_m1, (1.0 - self.exponent) * ln_x) * ln_x
+    }
+
+    fn h_integral_inverse(&self, x: f64) -> f64 {
+        let t = (x * (1.0 - self.exponent)).max(-1.0);
+        (over_x(f64::ln_1p, t) * x).exp()
+    }
+
+    /// A rank in `0..n`, rank 0 the most likely
+    pub fn sample(&self, rng: &mut SimRng, n: usize) -> usize {
+        if n <= 1 {
+            return 0;
+        }
+        let x1 = self.h_integral(1.5) - 1.0;
+        let xn = self.h_integral(n as f64 + 0.5);
+        let s = 2.0 - self.h_integral_inverse(self.h_integral(2.5) - self.h(2.0));
+        loop {
+            let u = xn + rng.next_f64() * (x1 - xn);
+            let x = self.h_integral_inverse(u);
+            let k = (x + 0.5).floor().clamp(1.0, n as f64);
+            if k - x <= s || u >= self.h_integral(k + 0.5) - self.h(k) {
+                return k as usize - 1;
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum OpKind {
+    Create,
+    SmallUpdate,
+    LargeUpdate,
+    Read,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Op {
+    Create { pubkey: String, data: Vec<u8> },
+    /// Overwrite `bytes.len()` bytes at `offset`
+    Update { pubkey: String, offset: usize, bytes: Vec<u8>, large: bool },
+    Read { pubkey: String, offset: usize, len: usize },
+}
+
+impl OpKind {
+    /// Name used in exported metrics
+    pub fn name(self) -> &'static str {
+        match self {
+            OpKind::Create => "create",
+            OpKind::SmallUpdate => "small_update",
+            OpKind::LargeUpdate => "large_update",
+            OpKind::Read => "read",
+        }
+    }
+}
+
+impl Op {
+    pub fn kind(&self) -> OpKind {
+        match self {
+            Op::Create { .. } => OpKind::Create,
+            Op::Update { large: false, .. } => OpKind::SmallUpdate,
+            Op::Update { large: true, .. } => OpKind::LargeUpdate,
+            Op::Read { .. } => OpKind::Read,
+        }
+    }
+
+    pub fn pubkey(&self) -> &str {
+        match self {
+            Op::Create { pubkey, .. } | Op::Update { pubkey, .. } | Op::Read { pubkey, .. } => pubkey,
+        }
+    }
+}
+
+/// Name of the `i`th account a workload creates
+pub fn account_name(i: usize) -> String {
+    format!("Load{:06}", i)
+}
+
+/// The ops of a `WorkloadConfig`: its initial creations, then `ops` drawn ones
+pub struct Workload {
+    cfg: WorkloadConfig,
+    rng: SimRng,
+    zipf: Zipf,
+    /// Size of every account created so far, by creation order
+    sizes: Vec<usize>,
+    drawn: usize,
+}
+
+impl Workload {
+    pub fn new(cfg: WorkloadConfig) -> Result<Self> {
+        cfg.validate()?;
+        Ok(Self { rng: SimRng::new(cfg.seed), zipf: Zipf::new(cfg.zipf_exponent), sizes: Vec::new(), drawn: 0, cfg })
+    }
+
+    fn range(&mut self, (lo, hi): (usize, usize)) -> usize {
+        self.rng.range(lo as u64, hi as u64) as usize
+    }
+
+    fn create(&mut self) -> Op {
+        let mut data = vec![0; self.range(self.cfg.account_size)];
+        self.rng.fill(&mut data);
+        let pubkey = account_name(self.sizes.len());
+        self.sizes.push(data.len());
+        Op::Create { pubkey, data }
+    }
+
+    fn draw_kind(&mut self) -> OpKind {
+        let mix = &self.cfg.mix;
+        let weights = [(OpKind::Create, mix.create), (OpKind::SmallUpdate, mix.small_update), (OpKind::LargeUpdate, mix.large_update), (OpKind::Read, mix.read)];
+        let mut at = self.rng.next_f64() * weights.iter().map(|(_, w)| w).sum::<f64>();
+        for (kind, weight) in weights {
+            if at < weight {
+                return kind;
+            }
+            at -= weight;
+        }
+        // rounding left `at` at the very top of the range
+        weights.iter().rev().find(|(_, w)| *w > 0.0).unwrap().0
+    }
+
+    /// `len` bytes drawn from `range`, at most the account's size, and where they start
+    fn span(&mut self, account: usize, range: (usize, usize)) -> (usize, usize) {
+        let size = self.sizes[account];
+        let len = self.range(range).min(size);
+        let offset = self.rng.range(0, (size - len) as u64) as usize;
+        (offset, len)
+    }
+
+    fn draw(&mut self) -> Op {
+        let kind = self.draw_kind();
+        if kind == OpKind::Create || self.sizes.is_empty() {
+            return self.create();
+        }
+        let account = self.zipf.sample(&mut self.rng, self.sizes.len());
+        let pubkey = account_name(account);
+        let range = match kind {
+            OpKind::SmallUpdate => self.cfg.small_update,
+            OpKind::LargeUpdate => self.cfg.large_update,
+            _ => self.cfg.read_len,
+        };
+        let (offset, len) = self.span(account, range);
+        if kind == OpKind::Read {
+            return Op::Read { pubkey, offset, len };
+        }
+        let mut bytes = vec![0; len];
+        self.rng.fill(&mut bytes);
+        Op::Update { pubkey, offset, bytes, large: kind == OpKind::LargeUpdate }
+    }
+}
+
+impl Iterator for Workload {
+    type Item = Op;
+
+    fn next(&mut self) -> Option<Op> {
+        if self.sizes.len() < self.cfg.initial_accounts && self.drawn == 0 {
+            return Some(self.create());
+        }
+        if self.drawn == self.cfg.ops {
+            return None;
+        }
+        self.drawn += 1;
+        Some(self.draw())
+    }
+}
+
+/// Writes and reads the bytes a workload op names. Instruction data: the offset (u64
+/// LE), then for `process` the bytes to write into every account, for `view` the
+/// length (u64 LE) to return, starting in the first proven chunk.
+pub struct WorkloadProgram;
+
+fn split_offset(instruction_data: &[u8]) -> Result<(usize, &[u8])> {
+    match instruction_data.split_first_chunk::<8>() {
+        Some((offset, rest)) => Ok((u64::from_le_bytes(*offset) as usize, rest)),
+        None => bail!("expected an 8-byte offset"),
+    }
+}
+
+impl Program for WorkloadProgram {
+    fn process(&self, _ctx: &mut InvokeContext, accounts: &[AccountView], instruction_data: &[u8], _witnesses: &[AccountWitness]) -> Result<Effects, WitnessError> {
+        let (offset, bytes) = split_offset(instruction_data)?;
+        let mut effects = Effects::default();
+        for acc in accounts {
+            let mut data = acc.data.to_vec();
+            match data.get_mut(offset..offset + bytes.len()) {
+                Some(span) => span.copy_from_slice(bytes),
+                None => return Err(WitnessError::Rejected(format!("write of {} bytes at {} is past the end of {}", bytes.len(), offset, acc.pubkey))),
+            }
+            effects.write(acc.pubkey, data);
+        }
+        Ok(effects)
+    }
+
+    fn view(&self, chunks: &[ChunkWitness], instruction_data: &[u8]) -> Result<Vec<u8>, WitnessError> {
+        let (offset, rest) = split_offset(instruction_data)?;
+        let len = match rest.try_into() {
+            Ok(len) => u64::from_le_bytes(len) as usize,
+            Err(_) => return Err(WitnessError::Rejected("expected an 8-byte length".to_string())),
+        };
+        if chunks.windows(2).any(|w| w[1].leaf_index != w[0].leaf_index + 1 || w[1].pubkey != w[0].pubkey) {
+            return Err(WitnessError::Rejected("chunks must be consecutive leaves of one account".to_string()));
+        }
+        let start = match chunks.first() {
+            Some(first) => offset.checked_sub(first.leaf_index * first.chunk.len()),
+            None => None,
+        };
+        let data: Vec<u8> = chunks.iter().flat_map(|c| c.chunk.iter().copied()).collect();
+        match start.and_then(|start| data.get(start..start + len)) {
+            Some(bytes) => Ok(bytes.to_vec()),
+            None => Err(WitnessError::Rejected(format!("{} bytes at {} are not in the proven chunks", len, offset))),
+        }
+    }
+}
+
+/// What a driven workload did
+#[derive(Debug, Clone, Default)]
+pub struct WorkloadStats {
+    pub ops: BTreeMap<OpKind, u64>,
+    /// Ops the chain rejected
+    pub failed: u64,
+    pub compute_units: u64,
+    /// Stubs, account data, proofs and instruction data carried by transactions
+    pub tx_bytes: u64,
+    /// Ops per account
+    pub touches: BTreeMap<String, u64>,
+}
+
+impl WorkloadStats {
+    pub fn count(&self, kind: OpKind) -> u64 {
+        self.ops.get(&kind).copied().unwrap_or(0)
+    }
+
+    pub fn total(&self) -> u64 {
+        self.ops.values().sum()
+    }
+
+    /// Share of all ops that went to the most touched account
+    pub fn hottest_share(&self) -> f64 {
+        let max = self.touches.values().max().copied().unwrap_or(0);
+        max as f64 / self.total().max(1) as f64
+    }
+}
+
+/// The client side of a workload: holds each account's data, as an off-chain store
+/// would, and turns ops into the transactions that carry it
+pub struct Driver {
+    pub chunk_size: usize,
+    pub blobs: BTreeMap<String, Vec<u8>>,
+    pub stats: WorkloadStats,
+}
+
+impl Driver {
+    /// Registers `WorkloadProgram` with `chain`
+    pub fn new(chain: &mut ChainState, chunk_size: usize) -> Self {
+        chain.register_program(WORKLOAD_PROGRAM, Box::new(WorkloadProgram));
+        Self { chunk_size, blobs: BTreeMap::new(), stats: WorkloadStats::default() }
+    }
+
+    fn blob<'a>(blobs: &'a BTreeMap<String, Vec<u8>>, pubkey: &str) -> Result<&'a Vec<u8>> {
+        match blobs.get(pubkey) {
+            Some(blob) => Ok(blob),
+            None => bail!("workload op on {} before it was created", pubkey),
+        }
+    }
+
+    /// Run `op` against `chain`. A transaction the chain rejects is counted, not an
+    /// error; errors mean the driver and chain disagree about an account.
+    pub fn apply(&mut self, chain: &mut ChainState, op: &Op) -> Result<()> {
+        *self.stats.ops.entry(op.kind()).or_default() += 1;
+        *self.stats.touches.entry(op.pubkey().to_string()).or_default() += 1;
+        match op {
+            Op::Create { pubkey, data } => {
+                let root = MerkleTree::from_chunks(&chunk_blob(data, self.chunk_size)).root();
+                let stub = AccountStub::new(WORKLOAD_PROGRAM, 0, root, data.len() as u64, self.chunk_size);
+                // what creating it takes is the stub, the data stays with the client
+                self.stats.tx_bytes += stub.encode().len() as u64;
+                chain.put_stub(pubkey, stub);
+                self.blobs.insert(pubkey.clone(), data.clone());
+            }
+            Op::Update { pubkey, offset, bytes, .. } => {
+                let blob = Self::blob(&self.blobs, pubkey)?;
+                let leaf_index = offset / self.chunk_size;
+                let proof = MerkleTree::from_chunks(&chunk_blob(blob, self.chunk_size)).gen_proof(leaf_index);
+                let mut instruction_data = (*offset as u64).to_le_bytes().to_vec();
+                instruction_data.extend_from_slice(bytes);
+                self.stats.tx_bytes += (blob.len() + proof.len() * witness_core::STEP_LEN + instruction_data.len()) as u64;
+                let tx = WitnessTx {
+                    program_id: WORKLOAD_PROGRAM.to_string(),
+                    instruction_data,
+                    witnesses: vec![AccountWitness { pubkey: pubkey.clone(), blob: blob.clone(), leaf_index, proof, is_writable: true }],
+                    recent_blockhash: chain.latest_blockhash(),
+                    priority_fee: 0,
+                    signatures: Vec::new(),
+                };
+                match chain.process_tx_witness(&tx) {
+                    Ok(receipt) => {
+                        self.stats.compute_units += receipt.compute_units;
+                        self.blobs.get_mut(pubkey).unwrap()[*offset..offset + bytes.len()].copy_from_slice(bytes);
+                    }
+                    Err(_) => self.stats.failed += 1,
+                }
+            }
+            Op::Read { pubkey, offset, len } => {
+                let blob = Self::blob(&self.blobs, pubkey)?;
+                let chunks = chunk_blob(blob, self.chunk_size);
+                let tree = MerkleTree::from_chunks(&chunks);
+                let last = (offset + (*len).max(1) - 1) / self.chunk_size;
+                let witnesses: Vec<ChunkWitness> = (offset / self.chunk_size..=last).map(|i| ChunkWitness {
+                    pubkey: pubkey.clone(), leaf_index: i, chunk: chunks[i].to_vec(), proof: tree.gen_proof(i),
+                }).collect();
+                let mut instruction_data = (*offset as u64).to_le_bytes().to_vec();
+                instruction_data.extend_from_slice(&(*len as u64).to_le_bytes());
+                self.stats.tx_bytes += witnesses.iter().map(|c| (c.chunk.len() + c.proof.len() * witness_core::STEP_LEN) as u64).sum::<u64>() + instruction_data.len() as u64;
+                let expected = blob[*offset..offset + len].to_vec();
+                let tx = ViewTx { program_id: WORKLOAD_PROGRAM.to_string(), instruction_data, chunks: witnesses, recent_blockhash: chain.latest_blockhash() };
+                match chain.process_view(&tx) {
+                    Ok(receipt) if receipt.return_data == expected => self.stats.compute_units += receipt.compute_units,
+                    Ok(_) => bail!("read of {} bytes at {} of {} returned other data than the account holds", len, offset, pubkey),
+                    Err(_) => self.stats.failed += 1,
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Apply every op of `workload`
+    pub fn run(&mut self, chain: &mut ChainState, workload: Workload) -> Result<()> {
+        for op in workload {
+            self.apply(chain, &op)?;
+        }
+        Ok(())
+    }
+}
+
+/// A seeded 300-op workload, run twice: the same seed must reach the same state
+pub fn demo(chunk_size: usize) -> Result<()> {
+    let run = || -> Result<(ChainState, WorkloadStats)> {
+        let mut chain = ChainState::new();
+        let mut driver = Driver::new(&mut chain, chunk_size);
+        driver.run(&mut chain, Workload::new(WorkloadConfig { ops: 300, ..Default::default() })?)?;
+        Ok((chain, driver.stats))
+    };
+    let (chain, stats) = run()?;
+    println!("\nWorkload: {} ops ({} creates, {} small and {} large updates, {} reads) over {} accounts, {} failed, {} CU, {} tx bytes; hottest account took {:.0}% of ops",
+        stats.total(), stats.count(OpKind::Create), stats.count(OpKind::SmallUpdate), stats.count(OpKind::LargeUpdate), stats.count(OpKind::Read),
+        chain.stubs.len(), stats.failed, stats.compute_units, stats.tx_bytes, stats.hottest_share() * 100.0);
+    println!("Workload rerun with the same seed reaches the same state root: {}", run()?.0.state_root() == chain.state_root());
+    Ok(())
+}
@@ -0,0 +1,353 @@
+//! Experimental: a Groth16 proof over BN254 that `new_root` is what applying a batch of
+//! writes to `old_root` gives, so a verifier checks one 128-byte proof and a few public
+//! inputs however many chunks changed, instead of a witness per chunk.
+//!
+//! SHA-256 costs tens of thousands of constraints per compression, so the circuit works
+//! over a tree of its own: leaves are Poseidon hashes of the chunks (31-byte limbs, one
+//! field element each), nodes Poseidon hashes of their children, padded by duplicating
+//! the last leaf like `MerkleTree`. Its root would sit in the stub next to the SHA-256
+//! one. The public inputs are the old and new roots, then each write's chunk index and
+//! new leaf. The old leaves, the siblings and the roots between writes stay private.
+//!
+//! Keys come from a circuit-specific setup for one (depth, writes) shape, run here
+//! with randomness from the OS. Whoever runs a setup could forge proofs, so the keys
+//! are only fit for measuring.
+
+use std::time::{Duration, Instant};
+
+use anyhow::Result;
+use ark_bn254::{Bn254, Fr};
+use ark_crypto_primitives::sponge::constraints::CryptographicSpongeVar;
+use ark_crypto_primitives::sponge::poseidon::constraints::PoseidonSpongeVar;
+use ark_crypto_primitives::sponge::poseidon::{find_poseidon_ark_and_mds, PoseidonConfig, PoseidonSponge};
+use ark_crypto_primitives::sponge::CryptographicSponge;
+use ark_ff::PrimeField;
+use ark_groth16::{Groth16, PreparedVerifyingKey, Proof, ProvingKey};
+use ark_r1cs_std::fields::fp::FpVar;
+use ark_r1cs_std::prelude::*;
+use ark_relations::r1cs::{ConstraintSynthesizer, ConstraintSystem, ConstraintSystemRef, SynthesisError};
+use ark_serialize::{CanonicalDeserialize, CanonicalSerialize};
+use ark_snark::SNARK;
+use ark_std::rand::rngs::StdRng;
+use ark_std::rand::SeedableRng;
+
+use crate::chunk_blob;
+use crate::error::WitnessError;
+
+/// Bytes of a chunk packed into one field element, safely below BN254's 254 bits
+const LIMB: usize = 31;
+
+/// Poseidon over BN254's scalar field: width 3, x^5, 8 full and 57 partial rounds
+pub fn poseidon_config() -> PoseidonConfig<Fr> {
+    let (ark, mds) = find_poseidon_ark_and_mds::<Fr>(Fr::MODULUS_BIT_SIZE as u64, 2, 8, 57, 0);
+    PoseidonConfig::new(8, 57, 5, mds, ark, 2, 1)
+}
+
+fn hash_pair(cfg: &PoseidonConfig<Fr>, left: Fr, right: Fr) -> Fr {
+    let mut sponge = PoseidonSponge::new(cfg);
+    sponge.absorb(&vec![left, right]);
+    sponge.squeeze_field_elements(1)[0]
+}
+
+/// Leaf of a chunk
+pub fn hash_chunk(cfg: &PoseidonConfig<Fr>, chunk: &[u8]) -> Fr {
+    let limbs: Vec<Fr> = chunk.chunks(LIMB).map(Fr::from_le_bytes_mod_order).collect();
+    let mut sponge = PoseidonSponge::new(cfg);
+    sponge.absorb(&limbs);
+    sponge.squeeze_field_elements(1)[0]
+}
+
+/// Poseidon tree over an account's chunks, every level kept
+#[derive(Clone)]
+pub struct PoseidonTree {
+    cfg: PoseidonConfig<Fr>,
+    /// Leaves first, the root last
+    layers: Vec<Vec<Fr>>,
+    /// Chunks before padding
+    leaf_count: usize,
+    chunk_size: usize,
+}
+
+impl PoseidonTree {
+    pub fn new(cfg: PoseidonConfig<Fr>, blob: &[u8], chunk_size: usize) -> Self {
+        let mut leaves: Vec<Fr> = chunk_blob(blob, chunk_size).iter().map(|c| hash_chunk(&cfg, c)).collect();
+        let leaf_count = leaves.len();
+        leaves.resize(leaves.len().next_power_of_two(), *leaves.last().unwrap());
+        let mut layers = vec![leaves];
+        while layers.last().unwrap().len() > 1 {
+            let below = layers.last().unwrap();
+            layers.push(below.chunks(2).map(|pair| hash_pair(&cfg, pair[0], pair[1])).collect());
+        }
+        Self { cfg, layers, leaf_count, chunk_size }
+    }
+
+    pub fn root(&self) -> Fr {
+        self.layers.last().unwrap()[0]
+    }
+
+    pub fn depth(&self) -> usize {
+        self.layers.len() - 1
+    }
+
+    pub fn leaf(&self, index: usize) -> Fr {
+        self.layers[0][index]
+    }
+
+    /// Siblings from the leaf up
+    pub fn siblings(&self, index: usize) -> Vec<Fr> {
+        (0..self.depth()).map(|level| self.layers[level][(index >> level) ^ 1]).collect()
+    }
+
+    /// Replace a leaf and rehash its path
+    pub fn set(&mut self, index: usize, leaf: Fr) {
+        self.layers[0][index] = leaf;
+        for level in 1..=self.depth() {
+            let i = index >> level;
+            self.layers[level][i] = hash_pair(&self.cfg, self.layers[level - 1][2 * i], self.layers[level - 1][2 * i + 1]);
+        }
+    }
+}
+
+/// Chunk `index` overwritten with `chunk`
+#[derive(Debug, Clone)]
+pub struct Write {
+    pub index: usize,
+    pub chunk: Vec<u8>,
+}
+
+/// One write as the circuit sees it
+#[derive(Clone)]
+struct Step {
+    index: u64,
+    old_leaf: Fr,
+    new_leaf: Fr,
+    /// Against the tree as the writes before this one left it
+    siblings: Vec<Fr>,
+}
+
+/// "Applying these writes to `old_root` gives `new_root`", for a tree of `depth` levels
+#[derive(Clone)]
+pub struct WriteCircuit {
+    cfg: PoseidonConfig<Fr>,
+    old_root: Fr,
+    new_root: Fr,
+    steps: Vec<Step>,
+}
+
+impl WriteCircuit {
+    /// Apply `writes` to `tree` in order, recording what proving the transition needs
+    pub fn apply(tree: &mut PoseidonTree, writes: &[Write]) -> Result<Self, WitnessError> {
+        let old_root = tree.root();
+        let mut steps = Vec::new();
+        for w in writes {
+            if w.index >= tree.leaf_count {
+                return Err(WitnessError::ProofIndexOutOfRange { index: w.index, chunks: tree.leaf_count });
+            }
+            if w.chunk.len() != tree.chunk_size {
+                return Err(WitnessError::WriteChunkSize { index: w.index, expected: tree.chunk_size, actual: w.chunk.len() });
+            }
+            let step = Step { index: w.index as u64, old_leaf: tree.leaf(w.index), new_leaf: hash_chunk(&tree.cfg, &w.chunk), siblings: tree.siblings(w.index) };
+            tree.set(w.index, step.new_leaf);
+            steps.push(step);
+        }
+        Ok(Self { cfg: tree.cfg.clone(), old_root, new_root: tree.root(), steps })
+    }
+
+    /// The circuit for `writes` writes into a tree of `depth` levels, with placeholder
+    /// values; its constraints, and so the keys, are those of any such batch
+    pub fn shape(cfg: PoseidonConfig<Fr>, depth: usize, writes: usize) -> Self {
+        let step = Step { index: 0, old_leaf: Fr::from(0u64), new_leaf: Fr::from(0u64), siblings: vec![Fr::from(0u64); depth] };
+        Self { cfg, old_root: Fr::from(0u64), new_root: Fr::from(0u64), steps: vec![step; writes] }
+    }
+
+    /// Old root, new root, then each write's index and new leaf
+    pub fn public_inputs(&self) -> Vec<Fr> {
+        let mut inputs = vec![self.old_root, self.new_root];
+        for s in &self.steps {
+            inputs.extend([Fr::from(s.index), s.new_leaf]);
+        }
+        inputs
+    }
+
+    fn root_var(&self, cs: &ConstraintSystemRef<Fr>, leaf: FpVar<Fr>, bits: &[Boolean<Fr>], siblings: &[FpVar<Fr>]) -> Result<FpVar<Fr>, SynthesisError> {
+        let mut node = leaf;
+        for (bit, sibling) in bits.iter().zip(siblings) {
+            // a set bit puts the node on the right
+            let left = bit.select(sibling, &node)?;
+            let right = bit.select(&node, sibling)?;
+            let mut sponge = PoseidonSpongeVar::new(cs.clone(), &self.cfg);
+            sponge.absorb(&vec![left, right])?;
+            node = sponge.squeeze_field_elements(1)?.remove(0);
+        }
+        Ok(node)
+    }
+}
+
+impl ConstraintSynthesizer<Fr> for WriteCircuit {
+    fn generate_constraints(self, cs: ConstraintSystemRef<Fr>) -> Result<(), SynthesisError> {
+        let old_root = FpVar::new_input(cs.clone(), || Ok(self.old_root))?;
+        let new_root = FpVar::new_input(cs.clone(), || Ok(self.new_root))?;
+        let mut root = old_root;
+        for s in &self.steps {
+            let index = FpVar::new_input(cs.clone(), || Ok(Fr::from(s.index)))?;
+            let new_leaf = FpVar::new_input(cs.clone(), || Ok(s.new_leaf))?;
+            let old_leaf = FpVar::new_witness(cs.clone(), || Ok(s.old_leaf))?;
+            let bits = (0..s.siblings.len()).map(|i| Boolean::new_witness(cs.clone(), || Ok(s.index >> i & 1 == 1))).collect::<Result<Vec<_>, _>>()?;
+            // the path bits spell out the public index
+            let sum = bits.iter().enumerate().fold(FpVar::zero(), |acc, (i, bit)| acc + FpVar::from(bit.clone()) * Fr::from(1u64 << i));
+            sum.enforce_equal(&index)?;
+            let siblings = s.siblings.iter().map(|sib| FpVar::new_witness(cs.clone(), || Ok(*sib))).collect::<Result<Vec<_>, _>>()?;
+            self.root_var(&cs, old_leaf, &bits, &siblings)?.enforce_equal(&root)?;
+            root = self.root_var(&cs, new_leaf, &bits, &siblings)?;
+        }
+        root.enforce_equal(&new_root)
+    }
+}
+
+/// Proving and verifying keys for one circuit shape
+pub struct Keys {
+    pub proving: ProvingKey<Bn254>,
+    pub verifying: PreparedVerifyingKey<Bn254>,
+}
+
+fn rng() -> Result<StdRng, WitnessError> {
+    let mut seed = [0u8;32];
+    getrandom::getrandom(&mut seed)?;
+    Ok(StdRng::from_seed(seed))
+}
+
+pub fn setup(depth: usize, writes: usize) -> Result<Keys, WitnessError> {
+    let (proving, vk) = Groth16::<Bn254>::circuit_specific_setup(WriteCircuit::shape(poseidon_config(), depth, writes), &mut rng()?)?;
+    Ok(Keys { verifying: Groth16::<Bn254>::process_vk(&vk)?, proving })
+}
+
+/// The compressed proof
+pub fn prove(keys: &Keys, circuit: WriteCircuit) -> Result<Vec<u8>, WitnessError> {
+    let proof = Groth16::<Bn254>::prove(&keys.proving, circuit, &mut rng()?)?;
+    let mut bytes = Vec::new();
+    proof.serialize_compressed(&mut bytes)?;
+    Ok(bytes)
+}
+
+pub fn verify(keys: &Keys, public_inputs: &[Fr], proof: &[u8]) -> Result<bool, WitnessError> {
+    let proof = Proof::<Bn254>::deserialize_compressed(proof)?;
+    Ok(Groth16::<Bn254>::verify_with_processed_vk(&keys.verifying, public_inputs, &proof)?)
+}
+
+/// One proved batch
+#[derive(Debug, Clone)]
+pub struct Measurement {
+    pub depth: usize,
+    pub writes: usize,
+    pub constraints: usize,
+    pub setup_time: Duration,
+    pub prove_time: Duration,
+    pub verify_time: Duration,
+    pub proof_bytes: usize,
+    pub public_input_bytes: usize,
+    /// What the same writes take as SHA-256 chunk witnesses instead
+    pub witness_bytes: usize,
+    /// The proof was rejected once the new root was tampered with
+    pub rejects_wrong_root: bool,
+}
+
+/// Prove `writes` random chunk writes into a `data_len`-byte account and check the proof
+pub fn measure(data_len: usize, chunk_size: usize, writes: usize) -> Result<Measurement, WitnessError> {
+    if chunk_size == 0 || data_len == 0 || writes == 0 {
+        return Err(WitnessError::Rejected("data length, chunk size and writes must be positive".to_string()));
+    }
+    let mut blob = vec![0u8; data_len];
+    getrandom::getrandom(&mut blob)?;
+    let mut tree = PoseidonTree::new(poseidon_config(), &blob, chunk_size);
+    let chunks = data_len.div_ceil(chunk_size);
+    let batch: Vec<Write> = (0..writes).map(|i| Write { index: i * chunks / writes, chunk: vec![i as u8; chunk_size] }).collect();
+    let depth = tree.depth();
+    let circuit = WriteCircuit::apply(&mut tree, &batch)?;
+
+    let cs = ConstraintSystem::new_ref();
+    circuit.clone().generate_constraints(cs.clone())?;
+    if !cs.is_satisfied()? {
+        return Err(WitnessError::Unsatisfied);
+    }
+    let start = Instant::now();
+    let keys = setup(depth, writes)?;
+    let setup_time = start.elapsed();
+    let inputs = circuit.public_inputs();
+    let start = Instant::now();
+    let proof = prove(&keys, circuit)?;
+    let prove_time = start.elapsed();
+    let start = Instant::now();
+    if !verify(&keys, &inputs, &proof)? {
+        return Err(WitnessError::ValidProofRejected);
+    }
+    let verify_time = start.elapsed();
+    let mut wrong = inputs.clone();
+    wrong[1] += Fr::from(1u64);
+    Ok(Measurement {
+        depth,
+        writes,
+        constraints: cs.num_constraints(),
+        setup_time,
+        prove_time,
+        verify_time,
+        proof_bytes: proof.len(),
+        public_input_bytes: inputs.len() * 32,
+        witness_bytes: writes * (chunk_size + depth * witness_core::STEP_LEN),
+        rejects_wrong_root: !verify(&keys, &wrong, &proof)?,
+    })
+}
+
+/// `zk`: measure one batch and print it
+pub fn run(data_len: usize, chunk_size: usize, writes: usize) -> Result<()> {
+    let m = measure(data_len, chunk_size, writes)?;
+    println!("{} writes into {} bytes in {}-byte chunks (depth {})", m.writes, data_len, chunk_size, m.depth);
+    println!("constraints: {}", m.constraints);
+    println!("setup {:.1?}, prove {:.1?}, verify {:.1?}", m.setup_time, m.prove_time, m.verify_time);
+    println!("proof {} bytes + {} bytes of public inputs, versus {} bytes of chunk witnesses", m.proof_bytes, m.public_input_bytes, m.witness_bytes);
+    println!("proof rejected against a wrong new root: {}", m.rejects_wrong_root);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tree() -> PoseidonTree {
+        // 5 chunks of 32 bytes, padded to 8 leaves
+        let blob: Vec<u8> = (0..150).map(|i| i as u8).collect();
+        PoseidonTree::new(poseidon_config(), &blob, 32)
+    }
+
+    #[test]
+    fn applied_writes_satisfy_the_circuit() {
+        let mut tree = tree();
+        let old_root = tree.root();
+        let circuit = WriteCircuit::apply(&mut tree, &[Write { index: 1, chunk: vec![7; 32] }, Write { index: 3, chunk: vec![9; 32] }]).unwrap();
+        let mut blob: Vec<u8> = (0..150).map(|i| i as u8).collect();
+        blob[32..64].fill(7);
+        blob[96..128].fill(9);
+        assert_eq!(tree.root(), PoseidonTree::new(poseidon_config(), &blob, 32).root());
+        assert_eq!(circuit.public_inputs()[..2], [old_root, tree.root()]);
+        let cs = ConstraintSystem::new_ref();
+        circuit.generate_constraints(cs.clone()).unwrap();
+        assert!(cs.is_satisfied().unwrap());
+    }
+
+    #[test]
+    fn rejects_writes_to_padding_leaves() {
+        let mut tree = tree();
+        let root = tree.root();
+        assert!(matches!(WriteCircuit::apply(&mut tree, &[Write { index: 5, chunk: vec![0; 32] }]), Err(WitnessError::ProofIndexOutOfRange { index: 5, chunks: 5 })));
+        assert!(WriteCircuit::apply(&mut tree, &[Write { index: 4, chunk: vec![0; 32] }]).is_ok());
+        assert_ne!(tree.root(), root);
+    }
+
+    #[test]
+    fn rejects_chunks_of_the_wrong_size() {
+        let mut tree = tree();
+        let root = tree.root();
+        assert!(matches!(WriteCircuit::apply(&mut tree, &[Write { index: 0, chunk: vec![0; 31] }]), Err(WitnessError::WriteChunkSize { index: 0, expected: 32, actual: 31 })));
+        assert!(matches!(WriteCircuit::apply(&mut tree, &[Write { index: 0, chunk: vec![0; 33] }]), Err(WitnessError::WriteChunkSize { actual: 33, .. })));
+        assert_eq!(tree.root(), root);
+    }
+}
@@ -0,0 +1,10 @@
+// Generates the gRPC types from proto/ when the `grpc` feature is on. Uses protox
+// (a pure-Rust protobuf compiler), so no protoc install is needed.
+fn main() {
+    #[cfg(feature = "grpc")]
+    {
+        println!("cargo:rerun-if-changed=proto/witness.proto");
+        let fds = protox::compile(["proto/witness.proto"], ["proto"]).expect("compiling proto/witness.proto");
+        tonic_build::configure().compile_fds(fds).expect("generating gRPC code");
+    }
+}
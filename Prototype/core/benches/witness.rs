@@ -0,0 +1,328 @@
+//! `cargo bench -p witness-core`: tree build, proof generation and single / batch
+//! verification of the binary sha256 tree across blob and chunk sizes, then the same
+//! work over k-ary trees with other hashers, the layouts `estimate` prices on chain.
+//! Criterion reports each case as usual; a table comparing them is printed at the end.
+//!
+//! Only arity 2 with sha256 is a layout the prototype commits. The k-ary trees here
+//! hash a node as H(child_0 || .. || child_k-1) and pad like `build_layers`, enough
+//! to measure what a wider tree or another hasher would cost.
+
+use std::hint::black_box;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use criterion::{BenchmarkId, Criterion, Throughput};
+use sha3::Digest as _;
+
+const KIB: usize = 1024;
+const BLOB_SIZES: [usize; 3] = [64 * KIB, KIB * KIB, 8 * KIB * KIB];
+const CHUNK_SIZES: [usize; 3] = [256, KIB, 4 * KIB];
+/// Leaves proven per batch
+const BATCH: usize = 64;
+const ARITIES: [usize; 4] = [2, 4, 8, 16];
+
+/// One row of the closing table
+#[derive(Default)]
+struct Row {
+    iters: u64,
+    elapsed: Duration,
+    /// Bytes hashed per iteration, for throughput
+    bytes: Option<usize>,
+    proof_bytes: Option<usize>,
+}
+
+/// (bench, case, totals), in the order the cases first ran
+static ROWS: Mutex<Vec<(String, String, Row)>> = Mutex::new(Vec::new());
+
+/// Time `f` for criterion, keeping the total for the table as well
+fn measure<O>(group: &str, case: &str, bytes: Option<usize>, proof_bytes: Option<usize>, mut f: impl FnMut() -> O) -> impl FnMut(u64) -> Duration {
+    let (group, case) = (group.to_string(), case.to_string());
+    move |iters| {
+        let start = Instant::now();
+        for _ in 0..iters {
+            black_box(f());
+        }
+        let elapsed = start.elapsed();
+        let mut rows = ROWS.lock().unwrap();
+        let at = match rows.iter().position(|(g, c, _)| *g == group && *c == case) {
+            Some(at) => at,
+            None => {
+                rows.push((group.clone(), case.clone(), Row::default()));
+                rows.len() - 1
+            }
+        };
+        let row = &mut rows[at].2;
+        row.iters += iters;
+        row.elapsed += elapsed;
+        row.bytes = bytes;
+        row.proof_bytes = proof_bytes;
+        elapsed
+    }
+}
+
+fn blob(len: usize) -> Vec<u8> {
+    // xorshift, so no chunk repeats and nothing compresses
+    let mut x = 0x9e37_79b9_7f4a_7c15u64;
+    (0..len).map(|_| {
+        x ^= x << 13;
+        x ^= x >> 7;
+        x ^= x << 17;
+        x as u8
+    }).collect()
+}
+
+fn size_name(bytes: usize) -> String {
+    if bytes >= KIB * KIB { format!("{}MiB", bytes / (KIB * KIB)) } else { format!("{}KiB", bytes / KIB) }
+}
+
+/// Leaves spread evenly over the tree, so a batch touches every part of it
+fn spread(leaves: usize, n: usize) -> Vec<usize> {
+    (0..n.min(leaves)).map(|i| i * leaves / n.min(leaves)).collect()
+}
+
+fn binary(c: &mut Criterion) {
+    for &size in &BLOB_SIZES {
+        let data = blob(size);
+        let mut build = c.benchmark_group(format!("build/{}", size_name(size)));
+        build.sample_size(10).throughput(Throughput::Bytes(size as u64));
+        for &chunk_size in &CHUNK_SIZES {
+            let case = format!("{} B chunks", chunk_size);
+            build.bench_function(BenchmarkId::from_parameter(chunk_size), |b| {
+                b.iter_custom(measure("build", &format!("{} {}", size_name(size), case), Some(size), None,
+                    || witness_core::build_layers(&witness_core::chunk_blob(&data, chunk_size))))
+            });
+        }
+        build.finish();
+
+        let trees: Vec<_> = CHUNK_SIZES.iter().map(|&chunk_size| {
+            let chunks = witness_core::chunk_blob(&data, chunk_size);
+            let layers = witness_core::build_layers(&chunks);
+            (chunk_size, chunks, layers)
+        }).collect();
+
+        let mut prove = c.benchmark_group(format!("prove/{}", size_name(size)));
+        for (chunk_size, chunks, layers) in &trees {
+            let case = format!("{} {} B chunks", size_name(size), chunk_size);
+            let proof_bytes = (layers.len() - 1) * witness_core::STEP_LEN;
+            prove.bench_function(BenchmarkId::from_parameter(chunk_size), |b| {
+                b.iter_custom(measure("prove", &case, None, Some(proof_bytes), || witness_core::gen_proof(layers, chunks.len() / 2)))
+            });
+        }
+        prove.finish();
+
+        let mut verify = c.benchmark_group(format!("verify/{}", size_name(size)));
+        for (chunk_size, chunks, layers) in &trees {
+            let chunk_size = *chunk_size;
+            let root = layers.last().unwrap()[0];
+            let case = format!("{} {} B chunks", size_name(size), chunk_size);
+            let proof_bytes = (layers.len() - 1) * witness_core::STEP_LEN;
+            let leaf = chunks.len() / 2;
+            let steps = witness_core::encode_steps(&witness_core::gen_proof(layers, leaf));
+            verify.throughput(Throughput::Elements(1));
+            verify.bench_function(BenchmarkId::new("single", chunk_size), |b| {
+                b.iter_custom(measure("verify", &case, Some(chunk_size), Some(proof_bytes), || {
+                    assert!(witness_core::verify_encoded(&chunks[leaf], &steps, &root))
+                }))
+            });
+            let leaves = spread(chunks.len(), BATCH);
+            let proofs: Vec<Vec<u8>> = leaves.iter().map(|&i| witness_core::encode_steps(&witness_core::gen_proof(layers, i))).collect();
+            verify.throughput(Throughput::Elements(leaves.len() as u64));
+            verify.bench_function(BenchmarkId::new(format!("batch of {}", leaves.len()), chunk_size), |b| {
+                b.iter_custom(measure(&format!("verify {}", leaves.len()), &case, Some(chunk_size * leaves.len()), Some(proof_bytes * leaves.len()), || {
+                    assert!(leaves.iter().zip(&proofs).all(|(&i, steps)| witness_core::verify_encoded(&chunks[i], steps, &root)))
+                }))
+            });
+        }
+        verify.finish();
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Hasher {
+    Sha256,
+    Keccak256,
+    Blake3,
+}
+
+impl Hasher {
+    const ALL: [Hasher; 3] = [Hasher::Sha256, Hasher::Keccak256, Hasher::Blake3];
+
+    fn name(self) -> &'static str {
+        match self {
+            Hasher::Sha256 => "sha256",
+            Hasher::Keccak256 => "keccak256",
+            Hasher::Blake3 => "blake3",
+        }
+    }
+
+    fn hash(self, parts: &[&[u8]]) -> [u8;32] {
+        match self {
+            Hasher::Sha256 => {
+                let mut h = sha2::Sha256::new();
+                parts.iter().for_each(|p| h.update(p));
+                h.finalize().into()
+            }
+            Hasher::Keccak256 => {
+                let mut h = sha3::Keccak256::new();
+                parts.iter().for_each(|p| h.update(p));
+                h.finalize().into()
+            }
+            Hasher::Blake3 => {
+                let mut h = blake3::Hasher::new();
+                parts.iter().for_each(|p| { h.update(p); });
+                h.finalize().into()
+            }
+        }
+    }
+}
+
+/// Layers of an `arity`-ary tree, leaf hashes first and the root last
+fn kary_layers(data: &[u8], chunk_size: usize, arity: usize, hasher: Hasher) -> Vec<Vec<[u8;32]>> {
+    let mut leaves: Vec<[u8;32]> = witness_core::chunk_blob(data, chunk_size).iter().map(|c| hasher.hash(&[c])).collect();
+    let mut width = 1;
+    while width < leaves.len() {
+        width *= arity;
+    }
+    let last = *leaves.last().unwrap();
+    leaves.resize(width, last);
+    let mut layers = vec![leaves];
+    while layers.last().unwrap().len() > 1 {
+        let next = layers.last().unwrap().chunks(arity).map(|children| {
+            let parts: Vec<&[u8]> = children.iter().map(|c| c.as_slice()).collect();
+            hasher.hash(&parts)
+        }).collect();
+        layers.push(next);
+    }
+    layers
+}
+
+/// Per level, leaf level first: every child of the node on the path, own slot included
+fn kary_proof(layers: &[Vec<[u8;32]>], arity: usize, leaf: usize) -> Vec<Vec<[u8;32]>> {
+    let mut idx = leaf;
+    layers[..layers.len() - 1].iter().map(|layer| {
+        let start = idx - idx % arity;
+        idx /= arity;
+        layer[start..start + arity].to_vec()
+    }).collect()
+}
+
+fn kary_verify(chunk: &[u8], leaf: usize, proof: &[Vec<[u8;32]>], root: &[u8;32], arity: usize, hasher: Hasher) -> bool {
+    let mut hash = hasher.hash(&[chunk]);
+    let mut idx = leaf;
+    for siblings in proof {
+        let mut children = siblings.clone();
+        children[idx % arity] = hash;
+        let parts: Vec<&[u8]> = children.iter().map(|c| c.as_slice()).collect();
+        hash = hasher.hash(&parts);
+        idx /= arity;
+    }
+    hash == *root
+}
+
+fn layouts(c: &mut Criterion) {
+    let size = KIB * KIB;
+    let chunk_size = KIB;
+    let data = blob(size);
+    // the model must agree with the real tree where the layouts coincide
+    assert_eq!(kary_layers(&data, chunk_size, 2, Hasher::Sha256), witness_core::build_layers(&witness_core::chunk_blob(&data, chunk_size)));
+
+    let mut build = c.benchmark_group("layout build/1MiB");
+    build.sample_size(10).throughput(Throughput::Bytes(size as u64));
+    for hasher in Hasher::ALL {
+        for arity in ARITIES {
+            build.bench_function(BenchmarkId::new(hasher.name(), arity), |b| {
+                b.iter_custom(measure("layout build", &format!("{} arity {}", hasher.name(), arity), Some(size), None,
+                    || kary_layers(&data, chunk_size, arity, hasher)))
+            });
+        }
+    }
+    build.finish();
+
+    let mut verify = c.benchmark_group("layout verify/1MiB");
+    for hasher in Hasher::ALL {
+        for arity in ARITIES {
+            let layers = kary_layers(&data, chunk_size, arity, hasher);
+            let root = layers.last().unwrap()[0];
+            let chunks = witness_core::chunk_blob(&data, chunk_size);
+            let leaf = chunks.len() / 2 + 1;
+            let chunk = chunks[leaf].to_vec();
+            let proof = kary_proof(&layers, arity, leaf);
+            // what goes on the wire: the siblings, and the slot at each level
+            let proof_bytes = proof.len() * ((arity - 1) * 32 + 1);
+            verify.bench_function(BenchmarkId::new(hasher.name(), arity), |b| {
+                b.iter_custom(measure("layout verify", &format!("{} arity {}", hasher.name(), arity), Some(chunk_size), Some(proof_bytes), || {
+                    assert!(kary_verify(&chunk, leaf, &proof, &root, arity, hasher))
+                }))
+            });
+        }
+    }
+    verify.finish();
+}
+
+/// Leaf hashing one chunk at a time through `sha2` against eight at a time through the
+/// multi-buffer hasher. `sha2` picks up SHA instructions on its own, so which one wins
+/// depends on the CPU; `hash_leaves` takes the multi-buffer path only when it has none.
+#[cfg(feature = "simd")]
+fn leaf_hashing(c: &mut Criterion) {
+    // every padding case: tails that fit the last block, that need one more, and none
+    for len in 0..200 {
+        let data = blob(len * witness_core::LANES);
+        let inputs: [&[u8]; witness_core::LANES] = core::array::from_fn(|l| &data[l * len..(l + 1) * len]);
+        assert_eq!(witness_core::sha256_x8(&inputs), inputs.map(witness_core::sha256), "{}-byte messages", len);
+    }
+    let size = KIB * KIB;
+    let data = blob(size);
+    let mut group = c.benchmark_group("leaf hash/1MiB");
+    group.sample_size(10).throughput(Throughput::Bytes(size as u64));
+    for &chunk_size in &CHUNK_SIZES {
+        let chunks = witness_core::chunk_blob(&data, chunk_size);
+        let scalar = || chunks.iter().map(witness_core::sha256).collect::<Vec<_>>();
+        assert_eq!(witness_core::hash_leaves_x8(&chunks), scalar());
+        group.bench_function(BenchmarkId::new("sha2", chunk_size), |b| {
+            b.iter_custom(measure("leaf hash", &format!("sha2 {} B chunks", chunk_size), Some(size), None, scalar))
+        });
+        group.bench_function(BenchmarkId::new("x8", chunk_size), |b| {
+            b.iter_custom(measure("leaf hash", &format!("x8 {} B chunks", chunk_size), Some(size), None,
+                || witness_core::hash_leaves_x8(&chunks)))
+        });
+    }
+    group.finish();
+}
+
+fn per_iter(d: Duration) -> String {
+    let ns = d.as_nanos();
+    match ns {
+        0..=9_999 => format!("{} ns", ns),
+        10_000..=999_999 => format!("{:.1} µs", ns as f64 / 1e3),
+        _ => format!("{:.1} ms", ns as f64 / 1e6),
+    }
+}
+
+fn print_table() {
+    let rows = ROWS.lock().unwrap();
+    if rows.is_empty() {
+        return;
+    }
+    println!();
+    println!("| {:<13} | {:<24} | {:>10} | {:>12} | {:>11} |", "bench", "case", "per iter", "throughput", "proof bytes");
+    println!("|{:-<15}|{:-<26}|{:->12}|{:->14}|{:->13}|", "", "", "", "", "");
+    for (group, case, row) in rows.iter() {
+        let mean = Duration::from_secs_f64(row.elapsed.as_secs_f64() / row.iters.max(1) as f64);
+        let throughput = match row.bytes {
+            Some(bytes) if !mean.is_zero() => format!("{:.0} MiB/s", bytes as f64 / mean.as_secs_f64() / (KIB * KIB) as f64),
+            _ => String::new(),
+        };
+        let proof = row.proof_bytes.map(|b| b.to_string()).unwrap_or_default();
+        println!("| {:<13} | {:<24} | {:>10} | {:>12} | {:>11} |", group, case, per_iter(mean), throughput, proof);
+    }
+}
+
+fn main() {
+    let mut c = Criterion::default().configure_from_args();
+    binary(&mut c);
+    layouts(&mut c);
+    #[cfg(feature = "simd")]
+    leaf_hashing(&mut c);
+    c.final_summary();
+    print_table();
+}
@@ -0,0 +1,49 @@
+//! `chunk_blob` over arbitrary blobs and chunk sizes: the chunks are the blob, in order,
+//! zero-padded to whole chunks, and every way of hashing them (layers, the flat buffer,
+//! the multi-buffer hasher) comes to the same tree.
+#![no_main]
+
+use arbitrary::Arbitrary;
+use libfuzzer_sys::fuzz_target;
+use witness_core::{build_flat, build_layers, chunk_blob, hash_leaves_x8, layer_offset, sha256};
+
+#[derive(Debug, Arbitrary)]
+struct Input {
+    blob: Vec<u8>,
+    chunk_size: u16,
+}
+
+fuzz_target!(|input: Input| {
+    let blob = &input.blob;
+    let chunk_size = input.chunk_size as usize + 1;
+    let chunks = chunk_blob(blob, chunk_size);
+
+    assert_eq!(chunks.len(), blob.len().div_ceil(chunk_size).max(1));
+    assert!(!chunks.is_empty());
+    assert!(chunks.get(chunks.len()).is_none());
+    assert_eq!(chunks.iter().count(), chunks.len());
+    assert_eq!(chunks.to_vecs().len(), chunks.len());
+
+    let joined: Vec<u8> = chunks.iter().flatten().copied().collect();
+    assert!(chunks.iter().all(|c| c.len() == chunk_size), "a chunk is not {} bytes", chunk_size);
+    assert_eq!(joined.len(), chunks.len() * chunk_size);
+    assert_eq!(&joined[..blob.len()], &blob[..], "chunks do not spell out the blob");
+    assert!(joined[blob.len()..].iter().all(|&b| b == 0), "padding is not zeros");
+    for i in 0..chunks.len() {
+        assert_eq!(&chunks[i], &joined[i * chunk_size..(i + 1) * chunk_size]);
+    }
+
+    let scalar: Vec<[u8;32]> = chunks.iter().map(sha256).collect();
+    assert_eq!(hash_leaves_x8(&chunks), scalar, "multi-buffer leaf hashes differ from sha2");
+
+    let layers = build_layers(&chunks);
+    let flat = build_flat(&chunks);
+    let width = layers[0].len();
+    assert!(width.is_power_of_two());
+    assert_eq!(&layers[0][..chunks.len()], &scalar[..]);
+    assert_eq!(flat.len(), 2 * width - 1);
+    for (level, layer) in layers.iter().enumerate() {
+        let at = layer_offset(width, level);
+        assert_eq!(&flat[at..at + layer.len()], &layer[..], "flat layer {} differs", level);
+    }
+});
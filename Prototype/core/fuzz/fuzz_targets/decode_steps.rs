@@ -0,0 +1,55 @@
+//! Arbitrary bytes as an encoded proof. `decode_steps` takes exactly what
+//! `encode_steps` writes, and the verifiers that read the encoding directly agree with
+//! it: on what is malformed, on the leaf index and on the root.
+#![no_main]
+
+use arbitrary::Arbitrary;
+use libfuzzer_sys::fuzz_target;
+use witness_core::{
+    compute_root, compute_root_encoded, decode_steps, encode_steps, proof_index, proof_index_encoded, verify_encoded, verify_proof,
+    StreamingVerifier, STEP_LEN,
+};
+
+#[derive(Debug, Arbitrary)]
+struct Input<'a> {
+    chunk: &'a [u8],
+    root: [u8;32],
+    leaf_index: usize,
+    steps: &'a [u8],
+}
+
+fuzz_target!(|input: Input| {
+    let Input { chunk, root, leaf_index, steps } = input;
+    let well_formed = steps.len() % STEP_LEN == 0 && steps.chunks(STEP_LEN).all(|s| s[32] <= 1);
+    let fresh = StreamingVerifier::new(leaf_index, chunk);
+    let mut streaming = fresh;
+    let pushed = streaming.push_encoded(steps);
+
+    let Some(proof) = decode_steps(steps) else {
+        assert!(!well_formed, "well-formed encoding rejected");
+        assert_eq!(proof_index_encoded(steps), None);
+        assert_eq!(compute_root_encoded(chunk, steps), None);
+        assert!(!verify_encoded(chunk, steps, &root));
+        assert!(!pushed && streaming == fresh, "streaming verifier took a malformed encoding");
+        return;
+    };
+    assert!(well_formed, "malformed encoding accepted");
+    assert_eq!(encode_steps(&proof), steps, "decoding does not round-trip");
+    assert_eq!(proof_index_encoded(steps), Some(proof_index(&proof)));
+
+    let computed = compute_root(chunk, &proof);
+    assert_eq!(compute_root_encoded(chunk, steps), Some(computed));
+    assert_eq!(verify_encoded(chunk, steps, &root), verify_proof(chunk, &proof, &root));
+    assert_eq!(verify_proof(chunk, &proof, &root), computed == root);
+
+    // the streaming verifier also holds the directions to `leaf_index`
+    let low_bits = if proof.len() >= usize::BITS as usize { leaf_index } else { leaf_index & ((1 << proof.len()) - 1) };
+    let follows = proof.len() <= usize::BITS as usize && proof_index(&proof) == low_bits;
+    assert_eq!(pushed, follows, "streaming verifier and proof_index disagree on the path");
+    if pushed {
+        assert_eq!(streaming.hash(), computed);
+        assert_eq!(streaming.level(), proof.len());
+    } else {
+        assert_eq!(streaming, fresh, "rejected steps changed the streaming verifier");
+    }
+});
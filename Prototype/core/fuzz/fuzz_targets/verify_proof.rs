@@ -0,0 +1,100 @@
+//! An honest proof over a tree of an arbitrary blob, then tampered with. Whatever the
+//! change, `verify_proof` may only reach the real root, `verify_proof_at` may only
+//! accept the chunk at the leaf it is asked about, and the encoded and streaming
+//! verifiers must give the same answers.
+#![no_main]
+
+use arbitrary::Arbitrary;
+use libfuzzer_sys::fuzz_target;
+use witness_core::{build_layers, chunk_blob, encode_steps, gen_proof, proof_index, verify_encoded, verify_encoded_at, verify_proof, verify_proof_at, StreamingVerifier};
+
+#[derive(Debug, Arbitrary)]
+enum Tamper {
+    None,
+    /// Flip one bit of the chunk
+    Chunk { bit: usize },
+    /// Swap in another chunk of the blob, or bytes of the input's choosing
+    OtherChunk { index: usize },
+    Bytes(Vec<u8>),
+    Sibling { step: usize, bit: u8 },
+    ReplaceSibling { step: usize, sibling: [u8;32] },
+    Direction { step: usize },
+    Drop { step: usize },
+    Push { sibling: [u8;32], is_left: bool },
+    Truncate { len: usize },
+    Root { bit: u8 },
+}
+
+#[derive(Debug, Arbitrary)]
+struct Input {
+    blob: Vec<u8>,
+    chunk_size: u8,
+    leaf: usize,
+    tampers: Vec<Tamper>,
+}
+
+fuzz_target!(|input: Input| {
+    let chunk_size = input.chunk_size as usize + 1;
+    let chunks = chunk_blob(&input.blob, chunk_size);
+    let layers = build_layers(&chunks);
+    let root = layers.last().unwrap()[0];
+    let depth = layers.len() - 1;
+    let leaf_index = input.leaf % chunks.len();
+
+    let mut chunk = chunks[leaf_index].to_vec();
+    let mut proof = gen_proof(&layers, leaf_index);
+    let mut against = root;
+    assert!(verify_proof_at(&chunk, &proof, &root, leaf_index, chunks.len()), "honest proof of leaf {} rejected", leaf_index);
+
+    for tamper in input.tampers {
+        match tamper {
+            Tamper::None => {}
+            Tamper::Chunk { bit } if !chunk.is_empty() => {
+                let at = bit / 8 % chunk.len();
+                chunk[at] ^= 1 << (bit % 8);
+            }
+            Tamper::OtherChunk { index } => chunk = chunks[index % chunks.len()].to_vec(),
+            Tamper::Bytes(bytes) => chunk = bytes,
+            Tamper::Sibling { step, bit } if !proof.is_empty() => {
+                let at = step % proof.len();
+                proof[at].0[bit as usize / 8 % 32] ^= 1 << (bit % 8);
+            }
+            Tamper::ReplaceSibling { step, sibling } if !proof.is_empty() => {
+                let at = step % proof.len();
+                proof[at].0 = sibling;
+            }
+            Tamper::Direction { step } if !proof.is_empty() => {
+                let at = step % proof.len();
+                proof[at].1 = !proof[at].1;
+            }
+            Tamper::Drop { step } if !proof.is_empty() => {
+                proof.remove(step % proof.len());
+            }
+            Tamper::Push { sibling, is_left } => proof.push((sibling, is_left)),
+            Tamper::Truncate { len } => proof.truncate(len),
+            Tamper::Root { bit } => against[bit as usize / 8] ^= 1 << (bit % 8),
+            _ => {}
+        }
+    }
+
+    // a bare root check binds no leaf: it accepts a short proof of an interior node
+    let reaches = verify_proof(&chunk, &proof, &against);
+    if reaches {
+        // anything else would be a second preimage of sha256
+        assert_eq!(against, root, "proof reached a root the tree does not have");
+    }
+    let verified = verify_proof_at(&chunk, &proof, &against, leaf_index, chunks.len());
+    if verified {
+        assert!(reaches, "shape check accepted a proof that misses the root");
+        assert_eq!(proof.len(), depth, "proof of the wrong depth verified");
+        assert_eq!(proof_index(&proof), leaf_index, "proof of another leaf verified");
+        assert_eq!(chunk, &chunks[leaf_index], "proof verified a chunk that is not leaf {}", leaf_index);
+    }
+
+    let steps = encode_steps(&proof);
+    assert_eq!(verify_encoded(&chunk, &steps, &against), reaches, "encoded verifier disagrees");
+    assert_eq!(verify_encoded_at(&chunk, &steps, &against, leaf_index, chunks.len()), verified, "encoded shape check disagrees");
+    let mut streaming = StreamingVerifier::new(leaf_index, &chunk);
+    let pushed = proof.iter().all(|(sibling, is_left)| streaming.push(sibling, *is_left));
+    assert_eq!(pushed && streaming.finish(depth, &against), verified, "streaming verifier disagrees");
+});
@@ -0,0 +1,45 @@
+//! Constant-time comparison. A verifier that stops at the first byte of a computed root
+//! that differs from the expected one tells a caller, through its timing, how much of a
+//! forged root it got right; these look at every byte whatever they find.
+
+/// Whether `a == b`, in time that depends only on their lengths. Lengths are not secret:
+/// slices of different lengths are unequal straight away.
+#[inline(never)]
+pub fn eq_bytes(a: &[u8], b: &[u8]) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+    let diff = a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y));
+    // keep the compiler from turning the fold back into an early exit
+    core::hint::black_box(diff) == 0
+}
+
+/// Whether two hashes are equal, looking at all 32 bytes
+#[inline]
+pub fn eq(a: &[u8;32], b: &[u8;32]) -> bool {
+    eq_bytes(a, b)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn equal_only_when_every_byte_is() {
+        let a = [7u8; 32];
+        assert!(eq(&a, &a));
+        for i in [0, 15, 31] {
+            let mut b = a;
+            b[i] ^= 0x80;
+            assert!(!eq(&a, &b));
+        }
+    }
+
+    #[test]
+    fn different_lengths_are_unequal() {
+        assert!(eq_bytes(b"", b""));
+        assert!(eq_bytes(b"abc", b"abc"));
+        assert!(!eq_bytes(b"abc", b"ab"));
+        assert!(!eq_bytes(b"", b"\0"));
+    }
+}
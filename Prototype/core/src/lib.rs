@@ -0,0 +1,550 @@
+//! Hashing and proof verification shared by the prototype and everything that must
+//! agree with it byte for byte (browser, FFI and on-chain verifiers). `no_std`; the
+//! `alloc` parts are only needed to build trees, not to check proofs.
+#![no_std]
+
+extern crate alloc;
+
+use alloc::vec::Vec;
+use sha2::{Digest, Sha256};
+
+pub mod ct;
+#[cfg(feature = "simd")]
+mod multi_sha;
+#[cfg(feature = "simd")]
+pub use multi_sha::{hardware_sha256, sha256_x8, LANES};
+
+/// proptest strategies and invariants for downstream property tests
+#[cfg(any(test, feature = "test-utils"))]
+pub mod test_utils;
+
+/// Bytes per encoded proof step: the sibling hash, then 1 if it is the left child
+pub const STEP_LEN: usize = 33;
+
+pub fn sha256(data: &[u8]) -> [u8;32] {
+    let mut out = [0u8; 32];
+    out.copy_from_slice(&Sha256::digest(data));
+    out
+}
+
+/// Interior node over two children: sha256(left || right)
+pub fn hash_pair(left: &[u8;32], right: &[u8;32]) -> [u8;32] {
+    let mut hasher = Sha256::new();
+    hasher.update(left);
+    hasher.update(right);
+    let mut out = [0u8; 32];
+    out.copy_from_slice(&hasher.finalize());
+    out
+}
+
+/// A blob split into `chunk_size`-byte leaves without copying it: full chunks are
+/// slices of the blob, and only a short last chunk is copied, zero-padded, into a buffer
+/// of its own. An empty blob is one zero chunk so every tree has a root.
+#[derive(Debug, Clone)]
+pub struct Chunks<'a> {
+    /// The whole chunks of the blob
+    full: &'a [u8],
+    chunk_size: usize,
+    tail: Option<Vec<u8>>,
+}
+
+impl<'a> Chunks<'a> {
+    pub fn new(blob: &'a [u8], chunk_size: usize) -> Self {
+        assert!(chunk_size > 0, "chunk size must be positive");
+        let split = blob.len() - blob.len() % chunk_size;
+        let (full, rest) = blob.split_at(split);
+        let tail = (!rest.is_empty() || blob.is_empty()).then(|| {
+            let mut tail = alloc::vec![0u8; chunk_size];
+            tail[..rest.len()].copy_from_slice(rest);
+            tail
+        });
+        Self { full, chunk_size, tail }
+    }
+
+    pub fn chunk_size(&self) -> usize {
+        self.chunk_size
+    }
+
+    pub fn len(&self) -> usize {
+        self.full.len() / self.chunk_size + self.tail.is_some() as usize
+    }
+
+    /// Never true: even an empty blob has its one zero chunk
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn get(&self, i: usize) -> Option<&[u8]> {
+        let full = self.full.len() / self.chunk_size;
+        match i {
+            i if i < full => Some(&self.full[i * self.chunk_size..(i + 1) * self.chunk_size]),
+            i if i == full => self.tail.as_deref(),
+            _ => None,
+        }
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &[u8]> + '_ {
+        self.into_iter()
+    }
+
+    /// Owned copies, for callers that edit chunks in place
+    pub fn to_vecs(&self) -> Vec<Vec<u8>> {
+        self.iter().map(<[u8]>::to_vec).collect()
+    }
+}
+
+impl core::ops::Index<usize> for Chunks<'_> {
+    type Output = [u8];
+
+    fn index(&self, i: usize) -> &[u8] {
+        match self.get(i) {
+            Some(chunk) => chunk,
+            None => panic!("chunk {} out of range: {} chunks", i, self.len()),
+        }
+    }
+}
+
+impl<'b> IntoIterator for &'b Chunks<'_> {
+    type Item = &'b [u8];
+    type IntoIter = core::iter::Chain<core::slice::ChunksExact<'b, u8>, core::option::IntoIter<&'b [u8]>>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.full.chunks_exact(self.chunk_size).chain(self.tail.as_deref())
+    }
+}
+
+/// Split a blob into `chunk_size`-byte leaves, zero-padding the last; see `Chunks`
+pub fn chunk_blob(blob: &[u8], chunk_size: usize) -> Chunks<'_> {
+    Chunks::new(blob, chunk_size)
+}
+
+/// Every layer of the tree over `chunks`, leaf hashes first and the root last. The leaf
+/// count is padded to a power of two by repeating the last leaf hash.
+pub fn build_layers<I>(chunks: I) -> Vec<Vec<[u8;32]>>
+where
+    I: IntoIterator,
+    I::Item: AsRef<[u8]>,
+{
+    layers_from_leaves(hash_leaves(chunks))
+}
+
+/// Leaf hash of every chunk. With the `simd` feature, on CPUs without SHA instructions,
+/// the chunks are hashed eight at a time by `sha256_x8`.
+pub fn hash_leaves<I>(chunks: I) -> Vec<[u8;32]>
+where
+    I: IntoIterator,
+    I::Item: AsRef<[u8]>,
+{
+    #[cfg(feature = "simd")]
+    if !hardware_sha256() {
+        return hash_leaves_x8(chunks);
+    }
+    chunks.into_iter().map(|c| sha256(c.as_ref())).collect()
+}
+
+/// `hash_leaves` through the multi-buffer hasher whatever the CPU has; a group of eight
+/// with mixed lengths, and the last few chunks, are hashed one by one
+#[cfg(feature = "simd")]
+pub fn hash_leaves_x8<I>(chunks: I) -> Vec<[u8;32]>
+where
+    I: IntoIterator,
+    I::Item: AsRef<[u8]>,
+{
+    let chunks: Vec<I::Item> = chunks.into_iter().collect();
+    let mut leaves = Vec::with_capacity(chunks.len());
+    let mut groups = chunks.chunks_exact(LANES);
+    for group in &mut groups {
+        let inputs: [&[u8]; LANES] = core::array::from_fn(|l| group[l].as_ref());
+        if inputs.iter().all(|i| i.len() == inputs[0].len()) {
+            leaves.extend(sha256_x8(&inputs));
+        } else {
+            leaves.extend(inputs.iter().map(|i| sha256(i)));
+        }
+    }
+    leaves.extend(groups.remainder().iter().map(|c| sha256(c.as_ref())));
+    leaves
+}
+
+/// Every layer above already-hashed `leaves`, padding them the way `build_layers` does
+pub fn layers_from_leaves(mut leaves: Vec<[u8;32]>) -> Vec<Vec<[u8;32]>> {
+    if let Some(&last) = leaves.last() {
+        leaves.resize(leaves.len().next_power_of_two(), last);
+    }
+    let mut layers = alloc::vec![leaves];
+    while layers.last().unwrap().len() > 1 {
+        let next = layers.last().unwrap().chunks(2).map(|pair| hash_pair(&pair[0], &pair[1])).collect();
+        layers.push(next);
+    }
+    layers
+}
+
+/// Where layer `level` starts in a flat node buffer over `width` padded leaves: the
+/// layers sit back to back, leaves first and the root last, `2 * width - 1` nodes in all
+pub fn layer_offset(width: usize, level: usize) -> usize {
+    2 * width - 2 * (width >> level)
+}
+
+/// `build_layers` into one buffer laid out as `layer_offset` describes
+pub fn build_flat<I>(chunks: I) -> Vec<[u8;32]>
+where
+    I: IntoIterator,
+    I::Item: AsRef<[u8]>,
+{
+    flat_from_leaves(hash_leaves(chunks))
+}
+
+/// `layers_from_leaves` into one buffer laid out as `layer_offset` describes
+pub fn flat_from_leaves(mut nodes: Vec<[u8;32]>) -> Vec<[u8;32]> {
+    let Some(&last) = nodes.last() else { return nodes };
+    let width = nodes.len().next_power_of_two();
+    nodes.reserve_exact(2 * width - 1 - nodes.len());
+    nodes.resize(width, last);
+    let (mut start, mut len) = (0, width);
+    while len > 1 {
+        for i in (start..start + len).step_by(2) {
+            let parent = hash_pair(&nodes[i], &nodes[i + 1]);
+            nodes.push(parent);
+        }
+        start += len;
+        len /= 2;
+    }
+    nodes
+}
+
+/// (sibling, sibling_is_left) from leaf `leaf_index` up to just below the root
+pub fn gen_proof(layers: &[Vec<[u8;32]>], leaf_index: usize) -> Vec<([u8;32], bool)> {
+    let mut proof = Vec::new();
+    let mut idx = leaf_index;
+    for layer in layers {
+        if layer.len() == 1 { break; }
+        let pair_idx = idx ^ 1;
+        proof.push((layer[pair_idx], pair_idx < idx));
+        idx /= 2;
+    }
+    proof
+}
+
+/// Root reached from `leaf_chunk` by folding in each (sibling, sibling_is_left) step
+pub fn compute_root(leaf_chunk: &[u8], proof: &[([u8;32], bool)]) -> [u8;32] {
+    let mut computed = sha256(leaf_chunk);
+    for (sibling, is_left) in proof {
+        computed = if *is_left { hash_pair(sibling, &computed) } else { hash_pair(&computed, sibling) };
+    }
+    computed
+}
+
+/// Whether the proof leads from `leaf_chunk` to `expected_root`, compared in constant time.
+/// Says nothing about which leaf, or even whether a leaf: see `verify_proof_at`.
+pub fn verify_proof(leaf_chunk: &[u8], proof: &[([u8;32], bool)], expected_root: &[u8;32]) -> bool {
+    ct::eq(&compute_root(leaf_chunk, proof), expected_root)
+}
+
+/// Leaf index a proof is for: the direction flags spell it out bit by bit. A proof of
+/// more than `usize::BITS` steps is for no leaf an index can name, and gets `usize::MAX`.
+pub fn proof_index(proof: &[([u8;32], bool)]) -> usize {
+    if proof.len() > usize::BITS as usize {
+        return usize::MAX;
+    }
+    proof.iter().enumerate().fold(0usize, |acc, (level, (_, is_left))| acc | ((*is_left as usize) << level))
+}
+
+/// Levels above the leaves of a tree over `leaf_count` chunks, so the steps in every
+/// proof against it. Past `usize::MAX / 2 + 1` leaves the padded width no longer fits
+/// a usize, and the depth is `usize::BITS`.
+pub fn tree_depth(leaf_count: usize) -> usize {
+    leaf_count.checked_next_power_of_two().map_or(usize::BITS as usize, |width| width.trailing_zeros() as usize)
+}
+
+/// Why a proof cannot be for the leaf it claims, whatever root it reaches
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ShapeError {
+    /// The leaf is past the last chunk: a padding leaf only repeats the last one
+    LeafRange { leaf_index: usize, leaf_count: usize },
+    /// A short proof passes off an interior node (or, empty, the root) as a leaf
+    Depth { expected: usize, actual: usize },
+    /// The directions spell out another leaf, which is what the proof then proves
+    Index { claimed: usize, proven: usize },
+    /// Encoded steps that are not whole `STEP_LEN`-byte steps with 0 or 1 directions
+    Malformed,
+}
+
+impl core::fmt::Display for ShapeError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            ShapeError::LeafRange { leaf_index, leaf_count } => write!(f, "leaf {} out of range: the tree has {} leaves", leaf_index, leaf_count),
+            ShapeError::Depth { expected, actual } => write!(f, "proof has {} steps, the tree is {} deep", actual, expected),
+            ShapeError::Index { claimed, proven } => write!(f, "proof directions are for leaf {}, not {}", proven, claimed),
+            ShapeError::Malformed => write!(f, "malformed proof steps"),
+        }
+    }
+}
+
+impl core::error::Error for ShapeError {}
+
+/// Reject proofs that could reach the root without proving leaf `leaf_index` of a tree
+/// over `leaf_count` chunks. `verify_proof` only checks the root, so every verifier that
+/// is told which leaf it checks runs this too.
+pub fn check_proof_shape(proof: &[([u8;32], bool)], leaf_index: usize, leaf_count: usize) -> Result<(), ShapeError> {
+    if leaf_index >= leaf_count {
+        return Err(ShapeError::LeafRange { leaf_index, leaf_count });
+    }
+    let expected = tree_depth(leaf_count);
+    if proof.len() != expected {
+        return Err(ShapeError::Depth { expected, actual: proof.len() });
+    }
+    let proven = proof_index(proof);
+    if proven != leaf_index {
+        return Err(ShapeError::Index { claimed: leaf_index, proven });
+    }
+    Ok(())
+}
+
+/// `verify_proof` for leaf `leaf_index` of a tree over `leaf_count` chunks: the proof
+/// must have the tree's shape as well as reach `expected_root`
+pub fn verify_proof_at(leaf_chunk: &[u8], proof: &[([u8;32], bool)], expected_root: &[u8;32], leaf_index: usize, leaf_count: usize) -> bool {
+    check_proof_shape(proof, leaf_index, leaf_count).is_ok() && verify_proof(leaf_chunk, proof, expected_root)
+}
+
+/// A proof checked a step at a time, for proofs too long for one instruction or frame.
+/// Holds only the running hash and how far up it is, so it can be saved in account data
+/// between instructions and resumed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StreamingVerifier {
+    leaf_index: usize,
+    level: usize,
+    hash: [u8;32],
+}
+
+impl StreamingVerifier {
+    /// Start at leaf `leaf_index`, whose bytes are `leaf_chunk`
+    pub fn new(leaf_index: usize, leaf_chunk: &[u8]) -> Self {
+        Self { leaf_index, level: 0, hash: sha256(leaf_chunk) }
+    }
+
+    /// Pick up where `level` and `hash` of an earlier verifier left off
+    pub fn resume(leaf_index: usize, level: usize, hash: [u8;32]) -> Self {
+        Self { leaf_index, level, hash }
+    }
+
+    pub fn leaf_index(&self) -> usize {
+        self.leaf_index
+    }
+
+    /// Steps folded in so far
+    pub fn level(&self) -> usize {
+        self.level
+    }
+
+    pub fn hash(&self) -> [u8;32] {
+        self.hash
+    }
+
+    /// Fold in the next step. False, leaving the verifier as it was, if the step points
+    /// the wrong way: every direction is a bit of the leaf index, anything else proves
+    /// another leaf.
+    #[must_use]
+    pub fn push(&mut self, sibling: &[u8;32], is_left: bool) -> bool {
+        if self.level >= usize::BITS as usize || is_left != ((self.leaf_index >> self.level) & 1 == 1) {
+            return false;
+        }
+        self.hash = if is_left { hash_pair(sibling, &self.hash) } else { hash_pair(&self.hash, sibling) };
+        self.level += 1;
+        true
+    }
+
+    /// Fold in steps encoded as `encode_steps` writes them. False, leaving the verifier
+    /// as it was, for a malformed encoding or a step pointing the wrong way.
+    #[must_use]
+    pub fn push_encoded(&mut self, steps: &[u8]) -> bool {
+        if !steps.len().is_multiple_of(STEP_LEN) {
+            return false;
+        }
+        let mut next = *self;
+        for step in steps.chunks(STEP_LEN) {
+            let is_left = match step[32] {
+                0 => false,
+                1 => true,
+                _ => return false,
+            };
+            if !next.push(step[..32].try_into().unwrap(), is_left) {
+                return false;
+            }
+        }
+        *self = next;
+        true
+    }
+
+    /// Whether the proof is complete for a tree of `depth` levels and reached `root`
+    pub fn finish(&self, depth: usize, root: &[u8;32]) -> bool {
+        self.level == depth && ct::eq(&self.hash, root)
+    }
+}
+
+/// `compute_root` over proof steps encoded back to back, `STEP_LEN` bytes each; None
+/// for a malformed encoding. Needs no allocation, for verifiers without a heap.
+pub fn compute_root_encoded(leaf_chunk: &[u8], steps: &[u8]) -> Option<[u8;32]> {
+    if !steps.len().is_multiple_of(STEP_LEN) {
+        return None;
+    }
+    let mut computed = sha256(leaf_chunk);
+    for step in steps.chunks(STEP_LEN) {
+        let sibling: &[u8;32] = step[..32].try_into().unwrap();
+        computed = match step[32] {
+            0 => hash_pair(&computed, sibling),
+            1 => hash_pair(sibling, &computed),
+            _ => return None,
+        };
+    }
+    Some(computed)
+}
+
+/// `verify_proof` over encoded steps; false for a malformed encoding
+pub fn verify_encoded(leaf_chunk: &[u8], steps: &[u8], expected_root: &[u8;32]) -> bool {
+    compute_root_encoded(leaf_chunk, steps).is_some_and(|root| ct::eq(&root, expected_root))
+}
+
+/// `check_proof_shape` over encoded steps
+pub fn check_encoded_shape(steps: &[u8], leaf_index: usize, leaf_count: usize) -> Result<(), ShapeError> {
+    if leaf_index >= leaf_count {
+        return Err(ShapeError::LeafRange { leaf_index, leaf_count });
+    }
+    let Some(proven) = proof_index_encoded(steps) else { return Err(ShapeError::Malformed) };
+    let expected = tree_depth(leaf_count);
+    if steps.len() != expected * STEP_LEN {
+        return Err(ShapeError::Depth { expected, actual: steps.len() / STEP_LEN });
+    }
+    if proven != leaf_index {
+        return Err(ShapeError::Index { claimed: leaf_index, proven });
+    }
+    Ok(())
+}
+
+/// `verify_proof_at` over encoded steps; false for a malformed encoding
+pub fn verify_encoded_at(leaf_chunk: &[u8], steps: &[u8], expected_root: &[u8;32], leaf_index: usize, leaf_count: usize) -> bool {
+    check_encoded_shape(steps, leaf_index, leaf_count).is_ok() && verify_encoded(leaf_chunk, steps, expected_root)
+}
+
+/// `proof_index` over encoded steps; None for a malformed encoding
+pub fn proof_index_encoded(steps: &[u8]) -> Option<usize> {
+    if !steps.len().is_multiple_of(STEP_LEN) {
+        return None;
+    }
+    if steps.len() / STEP_LEN > usize::BITS as usize {
+        return steps.chunks(STEP_LEN).all(|step| step[32] <= 1).then_some(usize::MAX);
+    }
+    steps.chunks(STEP_LEN).enumerate().try_fold(0usize, |acc, (level, step)| match step[32] {
+        0 => Some(acc),
+        1 => Some(acc | (1 << level)),
+        _ => None,
+    })
+}
+
+pub fn encode_steps(proof: &[([u8;32], bool)]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(proof.len() * STEP_LEN);
+    for (sibling, is_left) in proof {
+        out.extend_from_slice(sibling);
+        out.push(*is_left as u8);
+    }
+    out
+}
+
+/// Inverse of `encode_steps`; None if the length or a direction byte is off
+pub fn decode_steps(bytes: &[u8]) -> Option<Vec<([u8;32], bool)>> {
+    if !bytes.len().is_multiple_of(STEP_LEN) {
+        return None;
+    }
+    bytes.chunks(STEP_LEN).map(|step| {
+        let is_left = match step[32] {
+            0 => false,
+            1 => true,
+            _ => return None,
+        };
+        Some((step[..32].try_into().unwrap(), is_left))
+    }).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::vec;
+
+    fn tree(leaves: usize) -> (Vec<Vec<u8>>, Vec<Vec<[u8;32]>>) {
+        let chunks: Vec<Vec<u8>> = (0..leaves).map(|i| vec![i as u8; 8]).collect();
+        let layers = build_layers(&chunks);
+        (chunks, layers)
+    }
+
+    #[test]
+    fn accepts_every_leaf() {
+        for leaves in [1, 2, 3, 5, 8] {
+            let (chunks, layers) = tree(leaves);
+            let root = layers.last().unwrap()[0];
+            for (i, chunk) in chunks.iter().enumerate() {
+                let proof = gen_proof(&layers, i);
+                assert!(verify_proof_at(chunk, &proof, &root, i, leaves));
+                assert!(verify_encoded_at(chunk, &encode_steps(&proof), &root, i, leaves));
+            }
+        }
+    }
+
+    #[test]
+    fn rejects_short_proof_of_interior_node() {
+        let (_, layers) = tree(4);
+        let root = layers[2][0];
+        // a "chunk" of two child hashes reaches the root with one step fewer than a leaf
+        let mut node = [0u8; 64];
+        node[..32].copy_from_slice(&layers[1][0]);
+        node[32..].copy_from_slice(&layers[1][1]);
+        let proof = [(layers[1][1], false)];
+        let mut interior = [0u8; 64];
+        interior[..32].copy_from_slice(&layers[0][0]);
+        interior[32..].copy_from_slice(&layers[0][1]);
+        assert!(verify_proof(&node, &[], &root));
+        assert!(verify_proof(&interior, &proof, &root));
+        assert_eq!(check_proof_shape(&proof, 0, 4), Err(ShapeError::Depth { expected: 2, actual: 1 }));
+        assert!(!verify_proof_at(&interior, &proof, &root, 0, 4));
+        assert!(!verify_proof_at(&node, &[], &root, 0, 4));
+        assert!(!verify_encoded_at(&interior, &encode_steps(&proof), &root, 0, 4));
+    }
+
+    #[test]
+    fn rejects_long_proof() {
+        let (chunks, layers) = tree(3);
+        let root = layers.last().unwrap()[0];
+        let mut proof = gen_proof(&layers, 1);
+        proof.push(([7; 32], false));
+        assert_eq!(check_proof_shape(&proof, 1, 3), Err(ShapeError::Depth { expected: 2, actual: 3 }));
+        assert!(!verify_proof_at(&chunks[1], &proof, &root, 1, 3));
+        assert!(!verify_encoded_at(&chunks[1], &encode_steps(&proof), &root, 1, 3));
+    }
+
+    #[test]
+    fn rejects_padding_leaf_and_wrong_directions() {
+        let (chunks, layers) = tree(3);
+        let root = layers.last().unwrap()[0];
+        // leaf 3 pads the tree with a copy of leaf 2
+        let padding = gen_proof(&layers, 3);
+        assert!(verify_proof(&chunks[2], &padding, &root));
+        assert_eq!(check_proof_shape(&padding, 3, 3), Err(ShapeError::LeafRange { leaf_index: 3, leaf_count: 3 }));
+        assert_eq!(check_proof_shape(&padding, 2, 3), Err(ShapeError::Index { claimed: 2, proven: 3 }));
+        assert!(!verify_proof_at(&chunks[2], &padding, &root, 2, 3));
+        assert!(!verify_encoded_at(&chunks[2], &encode_steps(&padding), &root, 2, 3));
+    }
+
+    #[test]
+    fn rejects_malformed_encoding() {
+        let (chunks, layers) = tree(2);
+        let root = layers.last().unwrap()[0];
+        let mut steps = encode_steps(&gen_proof(&layers, 0));
+        steps[32] = 2;
+        assert_eq!(check_encoded_shape(&steps, 0, 2), Err(ShapeError::Malformed));
+        assert_eq!(check_encoded_shape(&steps[..32], 0, 2), Err(ShapeError::Malformed));
+        assert!(!verify_encoded_at(&chunks[0], &steps, &root, 0, 2));
+    }
+
+    #[test]
+    fn empty_tree_has_no_leaves() {
+        assert!(!verify_proof_at(b"", &[], &sha256(b""), 0, 0));
+        assert!(!verify_encoded_at(b"", &[], &sha256(b""), 0, 0));
+    }
+}
@@ -0,0 +1,137 @@
+//! Multi-buffer SHA-256: eight equal-length messages hashed in lockstep, one message per
+//! 32-bit lane. Every round is the same operation across the lanes, so with AVX2 the
+//! compiler turns them into 256-bit vector instructions. Leaves are all one chunk long,
+//! which is the case this is for.
+
+/// Messages hashed per call
+pub const LANES: usize = 8;
+
+type Lanes = [u32; LANES];
+
+const K: [u32; 64] = [
+    0x428a2f98, 0x71374491, 0xb5c0fbcf, 0xe9b5dba5, 0x3956c25b, 0x59f111f1, 0x923f82a4, 0xab1c5ed5,
+    0xd807aa98, 0x12835b01, 0x243185be, 0x550c7dc3, 0x72be5d74, 0x80deb1fe, 0x9bdc06a7, 0xc19bf174,
+    0xe49b69c1, 0xefbe4786, 0x0fc19dc6, 0x240ca1cc, 0x2de92c6f, 0x4a7484aa, 0x5cb0a9dc, 0x76f988da,
+    0x983e5152, 0xa831c66d, 0xb00327c8, 0xbf597fc7, 0xc6e00bf3, 0xd5a79147, 0x06ca6351, 0x14292967,
+    0x27b70a85, 0x2e1b2138, 0x4d2c6dfc, 0x53380d13, 0x650a7354, 0x766a0abb, 0x81c2c92e, 0x92722c85,
+    0xa2bfe8a1, 0xa81a664b, 0xc24b8b70, 0xc76c51a3, 0xd192e819, 0xd6990624, 0xf40e3585, 0x106aa070,
+    0x19a4c116, 0x1e376c08, 0x2748774c, 0x34b0bcb5, 0x391c0cb3, 0x4ed8aa4a, 0x5b9cca4f, 0x682e6ff3,
+    0x748f82ee, 0x78a5636f, 0x84c87814, 0x8cc70208, 0x90befffa, 0xa4506ceb, 0xbef9a3f7, 0xc67178f2,
+];
+
+const H0: [u32; 8] = [0x6a09e667, 0xbb67ae85, 0x3c6ef372, 0xa54ff53a, 0x510e527f, 0x9b05688c, 0x1f83d9ab, 0x5be0cd19];
+
+#[inline(always)]
+fn lanes(f: impl Fn(usize) -> u32) -> Lanes {
+    core::array::from_fn(f)
+}
+
+/// One 64-byte block per lane, `block[word][lane]`
+#[inline(always)]
+fn compress(state: &mut [Lanes; 8], block: &[Lanes; 16]) {
+    let mut w = [[0u32; LANES]; 64];
+    w[..16].copy_from_slice(block);
+    for t in 16..64 {
+        w[t] = lanes(|l| {
+            let (x, y) = (w[t - 15][l], w[t - 2][l]);
+            let s0 = x.rotate_right(7) ^ x.rotate_right(18) ^ (x >> 3);
+            let s1 = y.rotate_right(17) ^ y.rotate_right(19) ^ (y >> 10);
+            w[t - 16][l].wrapping_add(s0).wrapping_add(w[t - 7][l]).wrapping_add(s1)
+        });
+    }
+    let [mut a, mut b, mut c, mut d, mut e, mut f, mut g, mut h] = *state;
+    for t in 0..64 {
+        let t1 = lanes(|l| {
+            let s1 = e[l].rotate_right(6) ^ e[l].rotate_right(11) ^ e[l].rotate_right(25);
+            let ch = (e[l] & f[l]) ^ (!e[l] & g[l]);
+            h[l].wrapping_add(s1).wrapping_add(ch).wrapping_add(K[t]).wrapping_add(w[t][l])
+        });
+        let t2 = lanes(|l| {
+            let s0 = a[l].rotate_right(2) ^ a[l].rotate_right(13) ^ a[l].rotate_right(22);
+            let maj = (a[l] & b[l]) ^ (a[l] & c[l]) ^ (b[l] & c[l]);
+            s0.wrapping_add(maj)
+        });
+        h = g;
+        g = f;
+        f = e;
+        e = lanes(|l| d[l].wrapping_add(t1[l]));
+        d = c;
+        c = b;
+        b = a;
+        a = lanes(|l| t1[l].wrapping_add(t2[l]));
+    }
+    for (s, v) in state.iter_mut().zip([a, b, c, d, e, f, g, h]) {
+        *s = lanes(|l| s[l].wrapping_add(v[l]));
+    }
+}
+
+#[inline(always)]
+fn load(blocks: [&[u8]; LANES]) -> [Lanes; 16] {
+    core::array::from_fn(|i| lanes(|l| u32::from_be_bytes(blocks[l][4 * i..4 * i + 4].try_into().unwrap())))
+}
+
+#[inline(always)]
+fn hash_lanes(inputs: &[&[u8]; LANES]) -> [[u8;32]; LANES] {
+    let len = inputs[0].len();
+    let mut state = [[0u32; LANES]; 8];
+    for (s, h) in state.iter_mut().zip(H0) {
+        *s = [h; LANES];
+    }
+    let full = len / 64;
+    for i in 0..full {
+        compress(&mut state, &load(core::array::from_fn(|l| &inputs[l][64 * i..64 * i + 64])));
+    }
+    // the rest of each message, 0x80, zeros and the bit length: one or two more blocks
+    let rest = len - 64 * full;
+    let tail_len = if rest + 9 <= 64 { 64 } else { 128 };
+    let mut tails = [[0u8; 128]; LANES];
+    for (tail, input) in tails.iter_mut().zip(inputs) {
+        tail[..rest].copy_from_slice(&input[64 * full..]);
+        tail[rest] = 0x80;
+        tail[tail_len - 8..tail_len].copy_from_slice(&(len as u64 * 8).to_be_bytes());
+    }
+    for i in 0..tail_len / 64 {
+        compress(&mut state, &load(core::array::from_fn(|l| &tails[l][64 * i..64 * i + 64])));
+    }
+    core::array::from_fn(|l| {
+        let mut out = [0u8; 32];
+        for (i, word) in state.iter().enumerate() {
+            out[4 * i..4 * i + 4].copy_from_slice(&word[l].to_be_bytes());
+        }
+        out
+    })
+}
+
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+cpufeatures::new!(cpuid_avx2, "avx2");
+
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+#[target_feature(enable = "avx2")]
+unsafe fn hash_avx2(inputs: &[&[u8]; LANES]) -> [[u8;32]; LANES] {
+    hash_lanes(inputs)
+}
+
+/// SHA-256 of eight messages of one length, the same digests `sha256` gives
+pub fn sha256_x8(inputs: &[&[u8]; LANES]) -> [[u8;32]; LANES] {
+    assert!(inputs.iter().all(|i| i.len() == inputs[0].len()), "multi-buffer inputs must be one length");
+    #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+    if cpuid_avx2::get() {
+        // SAFETY: the CPU was just checked for AVX2
+        return unsafe { hash_avx2(inputs) };
+    }
+    hash_lanes(inputs)
+}
+
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+cpufeatures::new!(cpuid_sha, "sha", "sse2", "ssse3", "sse4.1");
+#[cfg(target_arch = "aarch64")]
+cpufeatures::new!(cpuid_sha, "sha2");
+
+/// Whether the CPU has SHA-256 instructions, which `sha2` already uses one message at a
+/// time faster than eight lanes of general-purpose vector code
+pub fn hardware_sha256() -> bool {
+    #[cfg(any(target_arch = "x86", target_arch = "x86_64", target_arch = "aarch64"))]
+    return cpuid_sha::get();
+    #[cfg(not(any(target_arch = "x86", target_arch = "x86_64", target_arch = "aarch64")))]
+    false
+}
@@ -0,0 +1,216 @@
+//! proptest strategies and invariant checks for crates that embed the verifier, so their
+//! property tests hold it to the same rules as the prototype: every proof a tree hands
+//! out verifies, and no single flipped bit of a chunk, proof or root still does.
+//!
+//! ```ignore
+//! proptest! {
+//!     #[test]
+//!     fn proofs_verify(tree in witness_core::test_utils::trees()) {
+//!         witness_core::test_utils::check_proofs_verify(&tree)?;
+//!     }
+//! }
+//! ```
+
+use alloc::vec::Vec;
+
+use proptest::prelude::*;
+use proptest::test_runner::TestCaseError;
+
+use crate::{build_layers, chunk_blob, decode_steps, encode_steps, gen_proof, hash_pair, verify_encoded_at, verify_proof, verify_proof_at, StreamingVerifier};
+
+/// Longest blob `blobs` makes unless asked for more
+pub const MAX_BLOB_LEN: usize = 16 * 1024;
+/// Largest chunk size `chunk_sizes` picks
+pub const MAX_CHUNK_SIZE: usize = 1024;
+
+/// Blobs of up to `max_len` bytes, the empty blob included
+pub fn blobs(max_len: usize) -> impl Strategy<Value = Vec<u8>> {
+    proptest::collection::vec(any::<u8>(), 0..=max_len)
+}
+
+/// Chunk sizes up to `MAX_CHUNK_SIZE`, half of them powers of two as configs use
+pub fn chunk_sizes() -> impl Strategy<Value = usize> {
+    prop_oneof![
+        (0..=MAX_CHUNK_SIZE.trailing_zeros()).prop_map(|shift| 1usize << shift),
+        1..=MAX_CHUNK_SIZE,
+    ]
+}
+
+/// A blob and the tree over its chunks
+#[derive(Debug, Clone)]
+pub struct TestTree {
+    pub blob: Vec<u8>,
+    pub chunk_size: usize,
+    pub layers: Vec<Vec<[u8;32]>>,
+}
+
+impl TestTree {
+    pub fn new(blob: Vec<u8>, chunk_size: usize) -> Self {
+        let layers = build_layers(&chunk_blob(&blob, chunk_size));
+        Self { blob, chunk_size, layers }
+    }
+
+    pub fn root(&self) -> [u8;32] {
+        self.layers.last().unwrap()[0]
+    }
+
+    pub fn depth(&self) -> usize {
+        self.layers.len() - 1
+    }
+
+    /// Chunks of the blob, not counting the padding leaves
+    pub fn chunks(&self) -> usize {
+        chunk_blob(&self.blob, self.chunk_size).len()
+    }
+
+    /// Chunk `i`, zero-padded the way it is hashed
+    pub fn chunk(&self, i: usize) -> Vec<u8> {
+        chunk_blob(&self.blob, self.chunk_size)[i].to_vec()
+    }
+
+    pub fn proof(&self, i: usize) -> Vec<([u8;32], bool)> {
+        gen_proof(&self.layers, i)
+    }
+}
+
+/// Trees over `blobs(MAX_BLOB_LEN)` chunked by `chunk_sizes()`
+pub fn trees() -> impl Strategy<Value = TestTree> {
+    (blobs(MAX_BLOB_LEN), chunk_sizes()).prop_map(|(blob, chunk_size)| TestTree::new(blob, chunk_size))
+}
+
+/// A tree and the index of one of its chunks
+pub fn tree_and_leaf() -> impl Strategy<Value = (TestTree, usize)> {
+    trees().prop_flat_map(|tree| {
+        let chunks = tree.chunks();
+        (Just(tree), 0..chunks)
+    })
+}
+
+/// Whether a verifier should take `chunk` as leaf `leaf_index` of the `leaf_count`-chunk
+/// tree under `root`: `verify_proof_at`, as every verifier in the prototype checks
+pub fn accepts(chunk: &[u8], proof: &[([u8;32], bool)], root: &[u8;32], leaf_index: usize, leaf_count: usize) -> bool {
+    verify_proof_at(chunk, proof, root, leaf_index, leaf_count)
+}
+
+/// Every chunk's proof verifies against the root, through the slice, encoded and
+/// streaming verifiers alike, and names the chunk it was made for
+pub fn check_proofs_verify(tree: &TestTree) -> Result<(), TestCaseError> {
+    let root = tree.root();
+    for i in 0..tree.chunks() {
+        let (chunk, proof) = (tree.chunk(i), tree.proof(i));
+        prop_assert_eq!(proof.len(), tree.depth(), "proof of leaf {} has the wrong depth", i);
+        prop_assert!(accepts(&chunk, &proof, &root, i, tree.chunks()), "proof of leaf {} does not verify", i);
+        let encoded = encode_steps(&proof);
+        prop_assert!(verify_encoded_at(&chunk, &encoded, &root, i, tree.chunks()), "encoded proof of leaf {} does not verify", i);
+        let decoded = decode_steps(&encoded);
+        prop_assert_eq!(decoded.as_ref(), Some(&proof), "proof of leaf {} does not round-trip", i);
+        let mut streaming = StreamingVerifier::new(i, &chunk);
+        prop_assert!(streaming.push_encoded(&encoded) && streaming.finish(tree.depth(), &root),
+            "streaming verifier rejects leaf {}", i);
+    }
+    Ok(())
+}
+
+/// Flipping any one bit of leaf `leaf_index`'s chunk, of its encoded proof or of the
+/// root makes it fail to verify as that leaf. A proof step is a sibling hash and a
+/// direction byte, so this covers swapped directions as well as altered hashes. Costs a
+/// verification per bit: about `8 * (chunk_size + 33 * depth + 32)` of them.
+pub fn check_bit_flips_fail(tree: &TestTree, leaf_index: usize) -> Result<(), TestCaseError> {
+    let (root, chunks) = (tree.root(), tree.chunks());
+    let chunk = tree.chunk(leaf_index);
+    let proof = tree.proof(leaf_index);
+    let encoded = encode_steps(&proof);
+
+    let mut flipped = chunk.clone();
+    for bit in 0..chunk.len() * 8 {
+        flipped[bit / 8] ^= 1 << (bit % 8);
+        prop_assert!(!accepts(&flipped, &proof, &root, leaf_index, chunks), "chunk {} verifies with bit {} flipped", leaf_index, bit);
+        flipped[bit / 8] ^= 1 << (bit % 8);
+    }
+
+    let mut flipped = encoded.clone();
+    for bit in 0..encoded.len() * 8 {
+        flipped[bit / 8] ^= 1 << (bit % 8);
+        // a direction byte that is no longer 0 or 1 is malformed, which also fails
+        let accepted = decode_steps(&flipped).is_some_and(|steps| accepts(&chunk, &steps, &root, leaf_index, chunks));
+        prop_assert!(!accepted, "proof of leaf {} verifies with bit {} flipped", leaf_index, bit);
+        let mut streaming = StreamingVerifier::new(leaf_index, &chunk);
+        prop_assert!(!(streaming.push_encoded(&flipped) && streaming.finish(tree.depth(), &root)),
+            "streaming verifier takes leaf {} with proof bit {} flipped", leaf_index, bit);
+        flipped[bit / 8] ^= 1 << (bit % 8);
+    }
+
+    let mut flipped = root;
+    for bit in 0..256 {
+        flipped[bit / 8] ^= 1 << (bit % 8);
+        prop_assert!(!accepts(&chunk, &proof, &flipped, leaf_index, chunks), "leaf {} verifies with root bit {} flipped", leaf_index, bit);
+        flipped[bit / 8] ^= 1 << (bit % 8);
+    }
+    Ok(())
+}
+
+/// Leaf `leaf_index`'s proof with a step dropped or added never verifies as that leaf,
+/// though both reshapings reach a root: with the first step dropped and the two hashes
+/// under it as the chunk, the real one, which `verify_proof` alone takes an interior
+/// node to.
+pub fn check_reshaped_proofs_fail(tree: &TestTree, leaf_index: usize) -> Result<(), TestCaseError> {
+    let (root, chunks) = (tree.root(), tree.chunks());
+    let chunk = tree.chunk(leaf_index);
+    let proof = tree.proof(leaf_index);
+    if let Some(&(sibling, is_left)) = proof.first() {
+        let own = tree.layers[0][leaf_index];
+        let (left, right) = if is_left { (sibling, own) } else { (own, sibling) };
+        let mut node = left.to_vec();
+        node.extend_from_slice(&right);
+        prop_assert!(verify_proof(&node, &proof[1..], &root));
+        for claimed in [leaf_index, leaf_index >> 1] {
+            prop_assert!(!accepts(&node, &proof[1..], &root, claimed, chunks), "interior node above leaf {} accepted as leaf {}", leaf_index, claimed);
+        }
+        prop_assert!(!accepts(&chunk, &proof[..proof.len() - 1], &root, leaf_index, chunks), "leaf {} accepted without its top step", leaf_index);
+    }
+    // one more step over a copy of the root reaches a root one level up
+    let mut long = proof.clone();
+    long.push((root, false));
+    let above = hash_pair(&root, &root);
+    prop_assert!(verify_proof(&chunk, &long, &above));
+    prop_assert!(!accepts(&chunk, &long, &above, leaf_index, chunks), "leaf {} accepted with an extra step", leaf_index);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Trees small enough for a debug build to check every leaf, or every bit, of
+    fn small_trees(max_len: usize, max_chunk_size: usize) -> impl Strategy<Value = (TestTree, usize)> {
+        (blobs(max_len), chunk_sizes()).prop_flat_map(move |(blob, chunk_size)| {
+            let tree = TestTree::new(blob, chunk_size.min(max_chunk_size));
+            let chunks = tree.chunks();
+            (Just(tree), 0..chunks)
+        })
+    }
+
+    proptest! {
+        #![proptest_config(ProptestConfig::with_cases(64))]
+
+        #[test]
+        fn proofs_verify((tree, _) in small_trees(2048, 256)) {
+            check_proofs_verify(&tree)?;
+        }
+
+        #[test]
+        fn reshaped_proofs_fail((tree, leaf) in tree_and_leaf()) {
+            check_reshaped_proofs_fail(&tree, leaf)?;
+        }
+    }
+
+    proptest! {
+        // a verification per bit of chunk, proof and root
+        #![proptest_config(ProptestConfig::with_cases(16))]
+
+        #[test]
+        fn bit_flips_fail((tree, leaf) in small_trees(512, 64)) {
+            check_bit_flips_fail(&tree, leaf)?;
+        }
+    }
+}
@@ -0,0 +1,72 @@
+//! The checked-in vectors hold: every tree builds to its root, every proof leads there
+//! as its leaf, and no invalid witness is taken for the leaf it names
+
+use serde_json::Value;
+use witness_core::{build_layers, chunk_blob, compute_root, compute_root_encoded, decode_steps, gen_proof, tree_depth, verify_encoded_at, verify_proof, verify_proof_at};
+
+const VECTORS: &str = include_str!("../../vectors/sha256-v1.json");
+
+fn bytes(v: &Value) -> Vec<u8> {
+    hex::decode(v.as_str().unwrap()).unwrap()
+}
+
+fn hash(v: &Value) -> [u8;32] {
+    bytes(v).try_into().unwrap()
+}
+
+fn vectors() -> Vec<Value> {
+    let set: Value = serde_json::from_str(VECTORS).unwrap();
+    assert_eq!(set["hash"], "sha256");
+    set["vectors"].as_array().unwrap().clone()
+}
+
+#[test]
+fn trees_build_to_their_roots() {
+    for v in vectors() {
+        let name = v["name"].as_str().unwrap();
+        let blob = bytes(&v["blob"]);
+        let chunks = chunk_blob(&blob, v["chunk_size"].as_u64().unwrap() as usize);
+        let layers = build_layers(&chunks);
+        assert_eq!(chunks.len() as u64, v["chunk_count"].as_u64().unwrap(), "{}", name);
+        assert_eq!(tree_depth(chunks.len()) as u64, v["depth"].as_u64().unwrap(), "{}", name);
+        assert_eq!(layers.last().unwrap()[0], hash(&v["root"]), "{}", name);
+        let leaves: Vec<[u8;32]> = v["leaves"].as_array().unwrap().iter().map(hash).collect();
+        assert_eq!(layers[0], leaves, "{}", name);
+    }
+}
+
+#[test]
+fn proofs_verify_as_their_leaves() {
+    for v in vectors() {
+        let (name, root, count) = (v["name"].as_str().unwrap(), hash(&v["root"]), v["chunk_count"].as_u64().unwrap() as usize);
+        let blob = bytes(&v["blob"]);
+        let layers = build_layers(&chunk_blob(&blob, v["chunk_size"].as_u64().unwrap() as usize));
+        for p in v["proofs"].as_array().unwrap() {
+            let leaf = p["leaf_index"].as_u64().unwrap() as usize;
+            let (chunk, steps) = (bytes(&p["chunk"]), bytes(&p["steps"]));
+            let proof = decode_steps(&steps).unwrap();
+            assert_eq!(proof, gen_proof(&layers, leaf), "{} leaf {}", name, leaf);
+            assert_eq!(compute_root(&chunk, &proof), root, "{} leaf {}", name, leaf);
+            assert_eq!(compute_root_encoded(&chunk, &steps), Some(root), "{} leaf {}", name, leaf);
+            assert!(verify_proof(&chunk, &proof, &root), "{} leaf {}", name, leaf);
+            assert!(verify_proof_at(&chunk, &proof, &root, leaf, count), "{} leaf {}", name, leaf);
+            assert!(verify_encoded_at(&chunk, &steps, &root, leaf, count), "{} leaf {}", name, leaf);
+        }
+    }
+}
+
+#[test]
+fn invalid_witnesses_fail() {
+    for v in vectors() {
+        let (name, root, count) = (v["name"].as_str().unwrap(), hash(&v["root"]), v["chunk_count"].as_u64().unwrap() as usize);
+        for w in v["invalid"].as_array().unwrap() {
+            let leaf = w["leaf_index"].as_u64().unwrap() as usize;
+            let (chunk, steps) = (bytes(&w["chunk"]), bytes(&w["steps"]));
+            let what = format!("{}: {}", name, w["name"]);
+            assert!(!verify_encoded_at(&chunk, &steps, &root, leaf, count), "{}", what);
+            if let Some(proof) = decode_steps(&steps) {
+                assert!(!verify_proof_at(&chunk, &proof, &root, leaf, count), "{}", what);
+            }
+        }
+    }
+}
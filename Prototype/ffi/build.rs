@@ -0,0 +1,10 @@
+// Regenerates include/witness_ffi.h so the checked-in header always matches the exported ABI
+fn main() {
+    let dir = std::env::var("CARGO_MANIFEST_DIR").unwrap();
+    println!("cargo:rerun-if-changed=src/lib.rs");
+    println!("cargo:rerun-if-changed=cbindgen.toml");
+    let config = cbindgen::Config::from_file(format!("{}/cbindgen.toml", dir)).expect("reading cbindgen.toml");
+    cbindgen::generate_with_config(&dir, config)
+        .expect("generating C header")
+        .write_to_file(format!("{}/include/witness_ffi.h", dir));
+}
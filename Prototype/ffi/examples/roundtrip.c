@@ -0,0 +1,29 @@
+/* Build a root, prove a leaf and verify it through the C ABI:
+ *   cargo build -p witness-ffi --release
+ *   cc ffi/examples/roundtrip.c -Iffi/include -Ltarget/release -lwitness_ffi -o roundtrip
+ */
+#include <stdio.h>
+#include <string.h>
+#include "witness_ffi.h"
+
+int main(void) {
+    const char *blob = "Example account blob: this could be an NFT metadata JSON or game state.";
+    size_t chunk_size = 32, leaf = 1;
+    uint8_t root[WITNESS_HASH_LEN], proof[64 * WITNESS_STEP_LEN], chunk[32] = {0};
+    size_t proof_len = 0;
+
+    if (witness_build_root((const uint8_t *)blob, strlen(blob), chunk_size, root) != WITNESS_OK) return 1;
+    if (witness_gen_proof((const uint8_t *)blob, strlen(blob), chunk_size, leaf, proof, sizeof proof, &proof_len) != WITNESS_OK) return 1;
+    memcpy(chunk, blob + leaf * chunk_size, chunk_size);
+
+    printf("root ");
+    for (size_t i = 0; i < WITNESS_HASH_LEN; i++) printf("%02x", root[i]);
+    printf("\n");
+    printf("abi %u, %zu chunks, proof %zu bytes\n", witness_abi_version(), witness_chunk_count(strlen(blob), chunk_size), proof_len);
+    size_t leaves = witness_chunk_count(strlen(blob), chunk_size);
+    printf("valid: %d\n", witness_verify_proof(root, proof, proof_len, chunk, chunk_size, leaf, leaves));
+    printf("short: %d\n", witness_verify_proof(root, proof + WITNESS_STEP_LEN, proof_len - WITNESS_STEP_LEN, chunk, chunk_size, leaf, leaves));
+    chunk[0] ^= 1;
+    printf("tampered: %d\n", witness_verify_proof(root, proof, proof_len, chunk, chunk_size, leaf, leaves));
+    return 0;
+}
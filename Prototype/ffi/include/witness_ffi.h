@@ -0,0 +1,109 @@
+/* C ABI of the account-witness proof core. Link with libwitness_ffi. */
+
+#ifndef WITNESS_FFI_H
+#define WITNESS_FFI_H
+
+/* Generated by cbindgen from ffi/src/lib.rs; do not edit. */
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+/**
+ * Bumped on any incompatible change to the functions or encodings below
+ */
+#define WITNESS_ABI_VERSION 2
+
+#define WITNESS_HASH_LEN 32
+
+#define WITNESS_STEP_LEN 33
+
+#define WITNESS_OK 0
+
+/**
+ * A required pointer was null
+ */
+#define WITNESS_ERR_NULL -1
+
+#define WITNESS_ERR_CHUNK_SIZE -2
+
+#define WITNESS_ERR_LEAF_RANGE -3
+
+/**
+ * The output buffer is too small; the needed size was written to `out_len`
+ */
+#define WITNESS_ERR_BUFFER_TOO_SMALL -4
+
+/**
+ * Proof bytes are not a whole number of steps, or a direction byte is not 0/1
+ */
+#define WITNESS_ERR_MALFORMED -5
+
+/**
+ * The proof has more or fewer steps than the tree has levels
+ */
+#define WITNESS_ERR_DEPTH -6
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+uint32_t witness_abi_version(void);
+
+/**
+ * Number of leaves `blob_len` bytes split into (at least 1), or 0 if `chunk_size` is 0
+ */
+size_t witness_chunk_count(size_t blob_len, size_t chunk_size);
+
+/**
+ * Write the Merkle root of `blob` split into `chunk_size`-byte leaves to `out_root`
+ *
+ * # Safety
+ * `blob` must be valid for `blob_len` reads and `out_root` for `WITNESS_HASH_LEN` writes
+ */
+int32_t witness_build_root(const uint8_t *blob,
+                           size_t blob_len,
+                           size_t chunk_size,
+                           uint8_t *out_root);
+
+/**
+ * Write the encoded proof of leaf `leaf_index` to `out_proof` (capacity `out_cap`
+ * bytes) and its length to `out_len`. Call with `out_cap` 0 to learn the size.
+ *
+ * # Safety
+ * `blob` must be valid for `blob_len` reads, `out_proof` for `out_cap` writes and
+ * `out_len` for one write
+ */
+int32_t witness_gen_proof(const uint8_t *blob,
+                          size_t blob_len,
+                          size_t chunk_size,
+                          size_t leaf_index,
+                          uint8_t *out_proof,
+                          size_t out_cap,
+                          size_t *out_len);
+
+/**
+ * 1 if `chunk` (zero-padded to the chunk size, as committed) is leaf `leaf_index` of
+ * the `leaf_count`-chunk tree under `root` by `proof`, 0 if not (including a proof
+ * whose directions are for another leaf), or a negative `WITNESS_ERR_*`. A proof must
+ * have exactly as many steps as the tree has levels: `WITNESS_ERR_DEPTH` otherwise.
+ *
+ * # Safety
+ * `root` must be valid for `WITNESS_HASH_LEN` reads, `proof` for `proof_len` and
+ * `chunk` for `chunk_len`
+ */
+int32_t witness_verify_proof(const uint8_t *root,
+                             const uint8_t *proof,
+                             size_t proof_len,
+                             const uint8_t *chunk,
+                             size_t chunk_len,
+                             size_t leaf_index,
+                             size_t leaf_count);
+
+#ifdef __cplusplus
+}  // extern "C"
+#endif  // __cplusplus
+
+#endif  /* WITNESS_FFI_H */
@@ -0,0 +1,194 @@
+//! Stable C ABI over `witness-core`, so C/C++ validators and tooling build and check
+//! trees with exactly the prototype's bytes. The header is `include/witness_ffi.h`,
+//! regenerated by the build script.
+//!
+//! Conventions: every function returns a `WITNESS_*` status (negative on error) unless
+//! documented otherwise; pointers may be null only when their length is 0; proofs use
+//! the compact step encoding, `WITNESS_STEP_LEN` bytes per level (sibling hash, then 1
+//! if it is the left child, 0 if the right).
+
+use std::slice;
+
+/// Bumped on any incompatible change to the functions or encodings below
+pub const WITNESS_ABI_VERSION: u32 = 2;
+pub const WITNESS_HASH_LEN: usize = 32;
+pub const WITNESS_STEP_LEN: usize = 33;
+const _: () = assert!(WITNESS_STEP_LEN == witness_core::STEP_LEN);
+
+pub const WITNESS_OK: i32 = 0;
+/// A required pointer was null
+pub const WITNESS_ERR_NULL: i32 = -1;
+pub const WITNESS_ERR_CHUNK_SIZE: i32 = -2;
+pub const WITNESS_ERR_LEAF_RANGE: i32 = -3;
+/// The output buffer is too small; the needed size was written to `out_len`
+pub const WITNESS_ERR_BUFFER_TOO_SMALL: i32 = -4;
+/// Proof bytes are not a whole number of steps, or a direction byte is not 0/1
+pub const WITNESS_ERR_MALFORMED: i32 = -5;
+/// The proof has more or fewer steps than the tree has levels
+pub const WITNESS_ERR_DEPTH: i32 = -6;
+
+/// # Safety
+/// `ptr` must be valid for `len` reads, or `len` must be 0
+unsafe fn bytes<'a>(ptr: *const u8, len: usize) -> Option<&'a [u8]> {
+    if len == 0 {
+        return Some(&[]);
+    }
+    if ptr.is_null() {
+        return None;
+    }
+    Some(slice::from_raw_parts(ptr, len))
+}
+
+#[no_mangle]
+pub extern "C" fn witness_abi_version() -> u32 {
+    WITNESS_ABI_VERSION
+}
+
+/// Number of leaves `blob_len` bytes split into (at least 1), or 0 if `chunk_size` is 0
+#[no_mangle]
+pub extern "C" fn witness_chunk_count(blob_len: usize, chunk_size: usize) -> usize {
+    if chunk_size == 0 {
+        return 0;
+    }
+    blob_len.div_ceil(chunk_size).max(1)
+}
+
+/// Write the Merkle root of `blob` split into `chunk_size`-byte leaves to `out_root`
+///
+/// # Safety
+/// `blob` must be valid for `blob_len` reads and `out_root` for `WITNESS_HASH_LEN` writes
+#[no_mangle]
+pub unsafe extern "C" fn witness_build_root(blob: *const u8, blob_len: usize, chunk_size: usize, out_root: *mut u8) -> i32 {
+    let Some(blob) = bytes(blob, blob_len) else { return WITNESS_ERR_NULL };
+    if out_root.is_null() {
+        return WITNESS_ERR_NULL;
+    }
+    if chunk_size == 0 {
+        return WITNESS_ERR_CHUNK_SIZE;
+    }
+    let layers = witness_core::build_layers(&witness_core::chunk_blob(blob, chunk_size));
+    let root = layers.last().unwrap()[0];
+    slice::from_raw_parts_mut(out_root, WITNESS_HASH_LEN).copy_from_slice(&root);
+    WITNESS_OK
+}
+
+/// Write the encoded proof of leaf `leaf_index` to `out_proof` (capacity `out_cap`
+/// bytes) and its length to `out_len`. Call with `out_cap` 0 to learn the size.
+///
+/// # Safety
+/// `blob` must be valid for `blob_len` reads, `out_proof` for `out_cap` writes and
+/// `out_len` for one write
+#[no_mangle]
+pub unsafe extern "C" fn witness_gen_proof(
+    blob: *const u8,
+    blob_len: usize,
+    chunk_size: usize,
+    leaf_index: usize,
+    out_proof: *mut u8,
+    out_cap: usize,
+    out_len: *mut usize,
+) -> i32 {
+    let Some(blob) = bytes(blob, blob_len) else { return WITNESS_ERR_NULL };
+    if out_len.is_null() {
+        return WITNESS_ERR_NULL;
+    }
+    if chunk_size == 0 {
+        return WITNESS_ERR_CHUNK_SIZE;
+    }
+    let chunks = witness_core::chunk_blob(blob, chunk_size);
+    if leaf_index >= chunks.len() {
+        return WITNESS_ERR_LEAF_RANGE;
+    }
+    let encoded = witness_core::encode_steps(&witness_core::gen_proof(&witness_core::build_layers(&chunks), leaf_index));
+    *out_len = encoded.len();
+    if out_cap < encoded.len() {
+        return WITNESS_ERR_BUFFER_TOO_SMALL;
+    }
+    if out_proof.is_null() && !encoded.is_empty() {
+        return WITNESS_ERR_NULL;
+    }
+    if !encoded.is_empty() {
+        slice::from_raw_parts_mut(out_proof, encoded.len()).copy_from_slice(&encoded);
+    }
+    WITNESS_OK
+}
+
+/// 1 if `chunk` (zero-padded to the chunk size, as committed) is leaf `leaf_index` of
+/// the `leaf_count`-chunk tree under `root` by `proof`, 0 if not (including a proof
+/// whose directions are for another leaf), or a negative `WITNESS_ERR_*`. A proof must
+/// have exactly as many steps as the tree has levels: `WITNESS_ERR_DEPTH` otherwise.
+///
+/// # Safety
+/// `root` must be valid for `WITNESS_HASH_LEN` reads, `proof` for `proof_len` and
+/// `chunk` for `chunk_len`
+#[no_mangle]
+pub unsafe extern "C" fn witness_verify_proof(root: *const u8, proof: *const u8, proof_len: usize, chunk: *const u8, chunk_len: usize, leaf_index: usize, leaf_count: usize) -> i32 {
+    let (Some(root), Some(proof), Some(chunk)) = (bytes(root, WITNESS_HASH_LEN), bytes(proof, proof_len), bytes(chunk, chunk_len)) else {
+        return WITNESS_ERR_NULL;
+    };
+    match witness_core::check_encoded_shape(proof, leaf_index, leaf_count) {
+        Ok(()) => witness_core::verify_encoded(chunk, proof, root.try_into().unwrap()) as i32,
+        Err(witness_core::ShapeError::LeafRange { .. }) => WITNESS_ERR_LEAF_RANGE,
+        Err(witness_core::ShapeError::Malformed) => WITNESS_ERR_MALFORMED,
+        Err(witness_core::ShapeError::Depth { .. }) => WITNESS_ERR_DEPTH,
+        Err(witness_core::ShapeError::Index { .. }) => 0,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BLOB: &[u8] = b"Example account blob: this could be an NFT metadata JSON or game state.";
+
+    fn setup(leaf: usize) -> ([u8; WITNESS_HASH_LEN], Vec<u8>, Vec<u8>, usize) {
+        let mut root = [0u8; WITNESS_HASH_LEN];
+        assert_eq!(unsafe { witness_build_root(BLOB.as_ptr(), BLOB.len(), 16, root.as_mut_ptr()) }, WITNESS_OK);
+        let mut proof = vec![0u8; 64 * WITNESS_STEP_LEN];
+        let mut len = 0;
+        assert_eq!(unsafe { witness_gen_proof(BLOB.as_ptr(), BLOB.len(), 16, leaf, proof.as_mut_ptr(), proof.len(), &mut len) }, WITNESS_OK);
+        proof.truncate(len);
+        let chunk = witness_core::chunk_blob(BLOB, 16)[leaf].to_vec();
+        (root, proof, chunk, witness_chunk_count(BLOB.len(), 16))
+    }
+
+    unsafe fn verify(root: &[u8], proof: &[u8], chunk: &[u8], leaf: usize, leaves: usize) -> i32 {
+        witness_verify_proof(root.as_ptr(), proof.as_ptr(), proof.len(), chunk.as_ptr(), chunk.len(), leaf, leaves)
+    }
+
+    #[test]
+    fn verifies_every_leaf() {
+        for leaf in 0..witness_chunk_count(BLOB.len(), 16) {
+            let (root, proof, chunk, leaves) = setup(leaf);
+            assert_eq!(unsafe { verify(&root, &proof, &chunk, leaf, leaves) }, 1);
+        }
+    }
+
+    #[test]
+    fn rejects_wrong_depth() {
+        let (root, proof, chunk, leaves) = setup(1);
+        assert_eq!(unsafe { verify(&root, &proof[WITNESS_STEP_LEN..], &chunk, 1, leaves) }, WITNESS_ERR_DEPTH);
+        let mut long = proof.clone();
+        long.extend_from_slice(&[0; WITNESS_STEP_LEN]);
+        assert_eq!(unsafe { verify(&root, &long, &chunk, 1, leaves) }, WITNESS_ERR_DEPTH);
+    }
+
+    #[test]
+    fn rejects_wrong_leaf_and_tampering() {
+        let (root, proof, mut chunk, leaves) = setup(2);
+        assert_eq!(unsafe { verify(&root, &proof, &chunk, 3, leaves) }, 0);
+        assert_eq!(unsafe { verify(&root, &proof, &chunk, leaves, leaves) }, WITNESS_ERR_LEAF_RANGE);
+        chunk[0] ^= 1;
+        assert_eq!(unsafe { verify(&root, &proof, &chunk, 2, leaves) }, 0);
+    }
+
+    #[test]
+    fn rejects_malformed_and_null() {
+        let (root, mut proof, chunk, leaves) = setup(0);
+        assert_eq!(unsafe { verify(&root, &proof[1..], &chunk, 0, leaves) }, WITNESS_ERR_MALFORMED);
+        proof[32] = 2;
+        assert_eq!(unsafe { verify(&root, &proof, &chunk, 0, leaves) }, WITNESS_ERR_MALFORMED);
+        let null = unsafe { witness_verify_proof(std::ptr::null(), proof.as_ptr(), proof.len(), chunk.as_ptr(), chunk.len(), 0, leaves) };
+        assert_eq!(null, WITNESS_ERR_NULL);
+    }
+}
@@ -0,0 +1,42 @@
+//! The checked-in vectors through the C ABI: each blob builds to its root and proofs,
+//! every proof verifies as its leaf and every invalid witness does not
+
+use serde_json::Value;
+use witness_ffi::*;
+
+const VECTORS: &str = include_str!("../../vectors/sha256-v1.json");
+
+fn bytes(v: &Value) -> Vec<u8> {
+    hex::decode(v.as_str().unwrap()).unwrap()
+}
+
+unsafe fn verify(root: &[u8], proof: &[u8], chunk: &[u8], leaf: usize, leaves: usize) -> i32 {
+    witness_verify_proof(root.as_ptr(), proof.as_ptr(), proof.len(), chunk.as_ptr(), chunk.len(), leaf, leaves)
+}
+
+#[test]
+fn vectors_verify() {
+    let set: Value = serde_json::from_str(VECTORS).unwrap();
+    for v in set["vectors"].as_array().unwrap() {
+        let (name, blob, root) = (v["name"].as_str().unwrap(), bytes(&v["blob"]), bytes(&v["root"]));
+        let (chunk_size, count) = (v["chunk_size"].as_u64().unwrap() as usize, v["chunk_count"].as_u64().unwrap() as usize);
+        assert_eq!(witness_chunk_count(blob.len(), chunk_size), count, "{}", name);
+        let mut built = [0u8; WITNESS_HASH_LEN];
+        assert_eq!(unsafe { witness_build_root(blob.as_ptr(), blob.len(), chunk_size, built.as_mut_ptr()) }, WITNESS_OK, "{}", name);
+        assert_eq!(built[..], root[..], "{}", name);
+        for p in v["proofs"].as_array().unwrap() {
+            let leaf = p["leaf_index"].as_u64().unwrap() as usize;
+            let (chunk, steps) = (bytes(&p["chunk"]), bytes(&p["steps"]));
+            let mut proof = vec![0u8; 64 * WITNESS_STEP_LEN];
+            let mut len = 0;
+            assert_eq!(unsafe { witness_gen_proof(blob.as_ptr(), blob.len(), chunk_size, leaf, proof.as_mut_ptr(), proof.len(), &mut len) }, WITNESS_OK);
+            assert_eq!(proof[..len], steps[..], "{} leaf {}", name, leaf);
+            assert_eq!(unsafe { verify(&root, &steps, &chunk, leaf, count) }, 1, "{} leaf {}", name, leaf);
+        }
+        for w in v["invalid"].as_array().unwrap() {
+            let leaf = w["leaf_index"].as_u64().unwrap() as usize;
+            let (chunk, steps) = (bytes(&w["chunk"]), bytes(&w["steps"]));
+            assert_ne!(unsafe { verify(&root, &steps, &chunk, leaf, count) }, 1, "{}: {}", name, w["name"]);
+        }
+    }
+}
@@ -0,0 +1,77 @@
+//! Builders for the verifier's instructions, encoding arguments the way
+//! `idl/witness_verifier.json` describes them. The root account must already exist,
+//! owned by the program with at least `ROOT_ACCOUNT_LEN` bytes of data.
+//!
+//! From TypeScript, load the IDL into an Anchor `Program` and call e.g.
+//! `program.methods.verify(leaf, chunk, steps).accounts({ rootAccount }).rpc()`, with
+//! `steps` as `{ sibling: number[], isLeft: boolean }[]`.
+
+use alloc::vec::Vec;
+
+use solana_program::instruction::{AccountMeta, Instruction};
+use solana_program::pubkey::Pubkey;
+
+use crate::{COMMIT_ROOT, VERIFY, VERIFY_AND_UPDATE};
+
+fn push_bytes(data: &mut Vec<u8>, bytes: &[u8]) {
+    data.extend_from_slice(&(bytes.len() as u32).to_le_bytes());
+    data.extend_from_slice(bytes);
+}
+
+fn push_steps(data: &mut Vec<u8>, proof: &[([u8;32], bool)]) {
+    data.extend_from_slice(&(proof.len() as u32).to_le_bytes());
+    data.extend_from_slice(&witness_core::encode_steps(proof));
+}
+
+/// Publish `root` for `data_len` bytes in `chunk_size`-byte chunks; the first commit to an
+/// account makes `authority` its owner
+pub fn commit_root(program_id: &Pubkey, root_account: &Pubkey, authority: &Pubkey, root: &[u8;32], data_len: u64, chunk_size: u32) -> Instruction {
+    let mut data = Vec::with_capacity(1 + 32 + 8 + 4);
+    data.push(COMMIT_ROOT);
+    data.extend_from_slice(root);
+    data.extend_from_slice(&data_len.to_le_bytes());
+    data.extend_from_slice(&chunk_size.to_le_bytes());
+    Instruction {
+        program_id: *program_id,
+        accounts: alloc::vec![AccountMeta::new(*root_account, false), AccountMeta::new_readonly(*authority, true)],
+        data,
+    }
+}
+
+/// Fail the transaction unless `chunk` is leaf `leaf` under the committed root
+pub fn verify(program_id: &Pubkey, root_account: &Pubkey, leaf: u32, chunk: &[u8], proof: &[([u8;32], bool)]) -> Instruction {
+    let mut data = Vec::with_capacity(1 + 4 + 4 + chunk.len() + 4 + proof.len() * witness_core::STEP_LEN);
+    data.push(VERIFY);
+    data.extend_from_slice(&leaf.to_le_bytes());
+    push_bytes(&mut data, chunk);
+    push_steps(&mut data, proof);
+    Instruction {
+        program_id: *program_id,
+        accounts: alloc::vec![AccountMeta::new_readonly(*root_account, false)],
+        data,
+    }
+}
+
+/// Replace leaf `leaf`, proven by `old_chunk` and `proof`, with `new_chunk`; the root
+/// account moves to the root the same proof gives for the new chunk
+pub fn verify_and_update(
+    program_id: &Pubkey,
+    root_account: &Pubkey,
+    authority: &Pubkey,
+    leaf: u32,
+    old_chunk: &[u8],
+    new_chunk: &[u8],
+    proof: &[([u8;32], bool)],
+) -> Instruction {
+    let mut data = Vec::with_capacity(1 + 4 + 8 + old_chunk.len() + new_chunk.len() + 4 + proof.len() * witness_core::STEP_LEN);
+    data.push(VERIFY_AND_UPDATE);
+    data.extend_from_slice(&leaf.to_le_bytes());
+    push_bytes(&mut data, old_chunk);
+    push_bytes(&mut data, new_chunk);
+    push_steps(&mut data, proof);
+    Instruction {
+        program_id: *program_id,
+        accounts: alloc::vec![AccountMeta::new(*root_account, false), AccountMeta::new_readonly(*authority, true)],
+        data,
+    }
+}
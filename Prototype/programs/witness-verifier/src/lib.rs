@@ -0,0 +1,408 @@
+//! On-chain verifier for account witnesses. A root account holds the commitment an
+//! authority published; instructions prove chunks against it, and the authority can
+//! move it to a new root by proving the chunk it replaces. Hashing and the proof
+//! encoding are `witness-core`'s, so proofs from the off-chain prover verify here as is.
+//!
+//! Instruction data is a tag byte then the arguments in Borsh (little-endian integers,
+//! byte strings and lists prefixed by a u32 length), matching `idl/witness_verifier.json`
+//! so Anchor clients can encode them; `instruction` has Rust builders.
+//!
+//! | tag | instruction       | arguments                                             | accounts                          |
+//! |-----|-------------------|-------------------------------------------------------|-----------------------------------|
+//! | 0   | CommitRoot        | root [32], data_len u64, chunk_size u32               | root account (w), authority (s)   |
+//! | 1   | Verify            | leaf u32, chunk bytes, steps                          | root account                      |
+//! | 2   | VerifyAndUpdate   | leaf u32, old_chunk bytes, new_chunk bytes, steps     | root account (w), authority (s)   |
+//!
+//! `steps` is a list of `witness_core::STEP_LEN`-byte entries (sibling hash, then 1 if
+//! it is the left child), which is also how Borsh lays out `Vec<([u8;32], bool)>`.
+#![no_std]
+
+extern crate alloc;
+
+use solana_program::account_info::AccountInfo;
+use solana_program::msg;
+use solana_program::program_error::ProgramError;
+use solana_program::pubkey::Pubkey;
+
+pub mod instruction;
+
+#[cfg(not(feature = "no-entrypoint"))]
+solana_program::entrypoint!(process_instruction);
+
+pub const COMMIT_ROOT: u8 = 0;
+pub const VERIFY: u8 = 1;
+pub const VERIFY_AND_UPDATE: u8 = 2;
+
+/// Layout version written as the first byte of an initialised root account
+pub const ROOT_ACCOUNT_VERSION: u8 = 1;
+/// version u8, authority [32], root [32], data_len u64, chunk_size u32
+pub const ROOT_ACCOUNT_LEN: usize = 1 + 32 + 32 + 8 + 4;
+
+/// Failures reported as `ProgramError::Custom(code)`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u32)]
+pub enum VerifierError {
+    MalformedInstruction = 0,
+    /// The root account is not owned by this program, too small or not writable
+    BadRootAccount = 1,
+    /// The authority did not sign, or is not the one recorded in the root account
+    BadAuthority = 2,
+    /// Chunk length differs from the committed chunk size
+    ChunkSize = 3,
+    LeafOutOfRange = 4,
+    /// Proof length is not the depth of the committed tree, or its path is for another leaf
+    ProofShape = 5,
+    ProofMismatch = 6,
+    /// The new chunk has nonzero bytes past the committed data length
+    WritePastEnd = 7,
+}
+
+impl From<VerifierError> for ProgramError {
+    fn from(e: VerifierError) -> Self {
+        ProgramError::Custom(e as u32)
+    }
+}
+
+/// What a root account commits to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RootAccount {
+    pub authority: Pubkey,
+    pub root: [u8;32],
+    pub data_len: u64,
+    pub chunk_size: u32,
+}
+
+impl RootAccount {
+    /// None until the first `CommitRoot` writes the version byte
+    pub fn unpack(data: &[u8]) -> Option<Self> {
+        if data.len() < ROOT_ACCOUNT_LEN || data[0] != ROOT_ACCOUNT_VERSION {
+            return None;
+        }
+        Some(Self {
+            authority: Pubkey::new_from_array(data[1..33].try_into().unwrap()),
+            root: data[33..65].try_into().unwrap(),
+            data_len: u64::from_le_bytes(data[65..73].try_into().unwrap()),
+            chunk_size: u32::from_le_bytes(data[73..77].try_into().unwrap()),
+        })
+    }
+
+    pub fn pack(&self, data: &mut [u8]) {
+        data[0] = ROOT_ACCOUNT_VERSION;
+        data[1..33].copy_from_slice(self.authority.as_ref());
+        data[33..65].copy_from_slice(&self.root);
+        data[65..73].copy_from_slice(&self.data_len.to_le_bytes());
+        data[73..77].copy_from_slice(&self.chunk_size.to_le_bytes());
+    }
+
+    pub fn chunk_count(&self) -> u64 {
+        self.data_len.div_ceil(self.chunk_size as u64).max(1)
+    }
+
+    /// Proof steps from a leaf to the root: the tree is padded to a power of two
+    pub fn depth(&self) -> usize {
+        witness_core::tree_depth(self.chunk_count() as usize)
+    }
+}
+
+/// Little-endian reader over instruction data
+struct Reader<'a>(&'a [u8]);
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, n: usize) -> Result<&'a [u8], ProgramError> {
+        if self.0.len() < n {
+            return Err(VerifierError::MalformedInstruction.into());
+        }
+        let (head, rest) = self.0.split_at(n);
+        self.0 = rest;
+        Ok(head)
+    }
+
+    fn u32(&mut self) -> Result<u32, ProgramError> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    fn u64(&mut self) -> Result<u64, ProgramError> {
+        Ok(u64::from_le_bytes(self.take(8)?.try_into().unwrap()))
+    }
+
+    fn hash(&mut self) -> Result<[u8;32], ProgramError> {
+        Ok(self.take(32)?.try_into().unwrap())
+    }
+
+    fn bytes(&mut self) -> Result<&'a [u8], ProgramError> {
+        let len = self.u32()? as usize;
+        self.take(len)
+    }
+
+    /// Encoded proof steps, left in place for `witness_core`'s allocation-free checks
+    fn steps(&mut self) -> Result<&'a [u8], ProgramError> {
+        let count = self.u32()? as usize;
+        self.take(count.checked_mul(witness_core::STEP_LEN).ok_or(VerifierError::MalformedInstruction)?)
+    }
+
+    /// Trailing bytes mean the client and program disagree on the layout
+    fn finish(&self) -> Result<(), ProgramError> {
+        if !self.0.is_empty() {
+            return Err(VerifierError::MalformedInstruction.into());
+        }
+        Ok(())
+    }
+}
+
+fn root_account<'a, 'b>(program_id: &Pubkey, account: &'a AccountInfo<'b>, writable: bool) -> Result<&'a AccountInfo<'b>, ProgramError> {
+    if account.owner != program_id || account.data_len() < ROOT_ACCOUNT_LEN || (writable && !account.is_writable) {
+        return Err(VerifierError::BadRootAccount.into());
+    }
+    Ok(account)
+}
+
+fn load(account: &AccountInfo) -> Result<RootAccount, ProgramError> {
+    RootAccount::unpack(&account.try_borrow_data()?).ok_or(ProgramError::UninitializedAccount)
+}
+
+fn check_authority(state: &RootAccount, authority: &AccountInfo) -> Result<(), ProgramError> {
+    if !authority.is_signer || *authority.key != state.authority {
+        return Err(VerifierError::BadAuthority.into());
+    }
+    Ok(())
+}
+
+/// Root once `replacement` is the last leaf. The tree pads with copies of that leaf, so
+/// every sibling right of its path is a subtree of copies and changes with it.
+fn last_leaf_root(replacement: &[u8], steps: &[u8]) -> [u8;32] {
+    let mut computed = witness_core::sha256(replacement);
+    let mut padding = computed;
+    for step in steps.chunks(witness_core::STEP_LEN) {
+        computed = match step[32] {
+            1 => witness_core::hash_pair(step[..32].try_into().unwrap(), &computed),
+            _ => witness_core::hash_pair(&computed, &padding),
+        };
+        padding = witness_core::hash_pair(&padding, &padding);
+    }
+    computed
+}
+
+/// Check `chunk` at `leaf` against `state`, including the proof's shape, and return the
+/// root `steps` lead to from `replacement` (the same chunk if there is none)
+fn prove(state: &RootAccount, leaf: u32, chunk: &[u8], replacement: &[u8], steps: &[u8]) -> Result<[u8;32], ProgramError> {
+    if chunk.len() != state.chunk_size as usize || replacement.len() != chunk.len() {
+        return Err(VerifierError::ChunkSize.into());
+    }
+    // a shorter proof could pass off an interior node as a leaf
+    match witness_core::check_encoded_shape(steps, leaf as usize, state.chunk_count() as usize) {
+        Ok(()) => {}
+        Err(witness_core::ShapeError::LeafRange { .. }) => return Err(VerifierError::LeafOutOfRange.into()),
+        Err(_) => return Err(VerifierError::ProofShape.into()),
+    }
+    if !witness_core::verify_encoded(chunk, steps, &state.root) {
+        return Err(VerifierError::ProofMismatch.into());
+    }
+    // the proof verified, so the siblings right of the last leaf are its padding copies
+    if leaf as u64 + 1 == state.chunk_count() {
+        return Ok(last_leaf_root(replacement, steps));
+    }
+    witness_core::compute_root_encoded(replacement, steps).ok_or_else(|| VerifierError::ProofShape.into())
+}
+
+/// Bytes past the end of the data stay zero, as `chunk_blob` pads them
+fn check_tail(state: &RootAccount, leaf: u32, chunk: &[u8]) -> Result<(), ProgramError> {
+    let used = state.data_len.saturating_sub(leaf as u64 * state.chunk_size as u64);
+    if used < chunk.len() as u64 && chunk[used as usize..].iter().any(|&b| b != 0) {
+        return Err(VerifierError::WritePastEnd.into());
+    }
+    Ok(())
+}
+
+pub fn process_instruction(program_id: &Pubkey, accounts: &[AccountInfo], data: &[u8]) -> Result<(), ProgramError> {
+    let (&tag, rest) = data.split_first().ok_or(VerifierError::MalformedInstruction)?;
+    let mut r = Reader(rest);
+    match tag {
+        COMMIT_ROOT => {
+            let [account, authority, ..] = accounts else { return Err(ProgramError::NotEnoughAccountKeys) };
+            let account = root_account(program_id, account, true)?;
+            let new = RootAccount { authority: *authority.key, root: r.hash()?, data_len: r.u64()?, chunk_size: r.u32()? };
+            r.finish()?;
+            if new.chunk_size == 0 {
+                return Err(VerifierError::ChunkSize.into());
+            }
+            // the first commit claims the account; later ones need the same authority
+            match RootAccount::unpack(&account.try_borrow_data()?) {
+                Some(state) => check_authority(&state, authority)?,
+                None if authority.is_signer => {}
+                None => return Err(VerifierError::BadAuthority.into()),
+            }
+            new.pack(&mut account.try_borrow_mut_data()?);
+            msg!("committed root for {} bytes", new.data_len);
+            Ok(())
+        }
+        VERIFY => {
+            let [account, ..] = accounts else { return Err(ProgramError::NotEnoughAccountKeys) };
+            let state = load(root_account(program_id, account, false)?)?;
+            let (leaf, chunk, steps) = (r.u32()?, r.bytes()?, r.steps()?);
+            r.finish()?;
+            prove(&state, leaf, chunk, chunk, steps)?;
+            msg!("leaf {} verified", leaf);
+            Ok(())
+        }
+        VERIFY_AND_UPDATE => {
+            let [account, authority, ..] = accounts else { return Err(ProgramError::NotEnoughAccountKeys) };
+            let account = root_account(program_id, account, true)?;
+            let mut state = load(account)?;
+            check_authority(&state, authority)?;
+            let (leaf, old, new, steps) = (r.u32()?, r.bytes()?, r.bytes()?, r.steps()?);
+            r.finish()?;
+            state.root = prove(&state, leaf, old, new, steps)?;
+            check_tail(&state, leaf, new)?;
+            state.pack(&mut account.try_borrow_mut_data()?);
+            msg!("leaf {} updated", leaf);
+            Ok(())
+        }
+        _ => Err(VerifierError::MalformedInstruction.into()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::vec;
+    use alloc::vec::Vec;
+
+    use super::*;
+    use crate::instruction;
+
+    const PROGRAM: Pubkey = Pubkey::new_from_array([1; 32]);
+    const ACCOUNT: Pubkey = Pubkey::new_from_array([2; 32]);
+    const AUTHORITY: Pubkey = Pubkey::new_from_array([3; 32]);
+    const CHUNK_SIZE: usize = 32;
+
+    /// Five 32-byte chunks, padded to a tree of eight leaves
+    fn tree() -> (Vec<Vec<u8>>, Vec<Vec<[u8;32]>>) {
+        let blob: Vec<u8> = (0..150u32).map(|i| (i * 7) as u8).collect();
+        let chunks = witness_core::chunk_blob(&blob, CHUNK_SIZE).to_vecs();
+        let layers = witness_core::build_layers(&chunks);
+        (chunks, layers)
+    }
+
+    /// Run `data` over the root account, signed by `signer` when it is given
+    fn run(state: &mut [u8], owner: &Pubkey, signer: Option<&Pubkey>, data: &[u8]) -> Result<(), ProgramError> {
+        let (mut lamports, mut authority_lamports) = (0, 0);
+        let authority_key = signer.unwrap_or(&AUTHORITY);
+        let account = AccountInfo::new(&ACCOUNT, false, true, &mut lamports, state, owner, false);
+        let authority = AccountInfo::new(authority_key, signer.is_some(), false, &mut authority_lamports, &mut [], &PROGRAM, false);
+        process_instruction(&PROGRAM, &[account, authority], data)
+    }
+
+    fn committed(root: &[u8;32]) -> Vec<u8> {
+        let mut state = vec![0u8; ROOT_ACCOUNT_LEN];
+        let ix = instruction::commit_root(&PROGRAM, &ACCOUNT, &AUTHORITY, root, 150, CHUNK_SIZE as u32);
+        run(&mut state, &PROGRAM, Some(&AUTHORITY), &ix.data).unwrap();
+        state
+    }
+
+    fn custom(e: VerifierError) -> Result<(), ProgramError> {
+        Err(e.into())
+    }
+
+    #[test]
+    fn verifies_every_leaf() {
+        let (chunks, layers) = tree();
+        let mut state = committed(&layers[3][0]);
+        assert_eq!(RootAccount::unpack(&state).unwrap().depth(), 3);
+        for (leaf, chunk) in chunks.iter().enumerate() {
+            let ix = instruction::verify(&PROGRAM, &ACCOUNT, leaf as u32, chunk, &witness_core::gen_proof(&layers, leaf));
+            run(&mut state, &PROGRAM, None, &ix.data).unwrap();
+        }
+    }
+
+    #[test]
+    fn rejects_wrong_shape_and_chunk() {
+        let (chunks, layers) = tree();
+        let mut state = committed(&layers[3][0]);
+        let proof = witness_core::gen_proof(&layers, 1);
+        let short = instruction::verify(&PROGRAM, &ACCOUNT, 1, &chunks[1], &proof[..2]);
+        assert_eq!(run(&mut state, &PROGRAM, None, &short.data), custom(VerifierError::ProofShape));
+        let long = instruction::verify(&PROGRAM, &ACCOUNT, 1, &chunks[1], &[proof.clone(), proof.clone()].concat());
+        assert_eq!(run(&mut state, &PROGRAM, None, &long.data), custom(VerifierError::ProofShape));
+        let other_leaf = instruction::verify(&PROGRAM, &ACCOUNT, 2, &chunks[1], &proof);
+        assert_eq!(run(&mut state, &PROGRAM, None, &other_leaf.data), custom(VerifierError::ProofShape));
+        // leaves 5 to 7 are padding copies of leaf 4
+        let padding = instruction::verify(&PROGRAM, &ACCOUNT, 5, &chunks[4], &witness_core::gen_proof(&layers, 5));
+        assert_eq!(run(&mut state, &PROGRAM, None, &padding.data), custom(VerifierError::LeafOutOfRange));
+        let mut tampered = chunks[1].clone();
+        tampered[0] ^= 1;
+        let bad = instruction::verify(&PROGRAM, &ACCOUNT, 1, &tampered, &proof);
+        assert_eq!(run(&mut state, &PROGRAM, None, &bad.data), custom(VerifierError::ProofMismatch));
+        let short_chunk = instruction::verify(&PROGRAM, &ACCOUNT, 1, &chunks[1][1..], &proof);
+        assert_eq!(run(&mut state, &PROGRAM, None, &short_chunk.data), custom(VerifierError::ChunkSize));
+        let mut trailing = instruction::verify(&PROGRAM, &ACCOUNT, 1, &chunks[1], &proof).data;
+        trailing.push(0);
+        assert_eq!(run(&mut state, &PROGRAM, None, &trailing), custom(VerifierError::MalformedInstruction));
+    }
+
+    #[test]
+    fn update_moves_the_root_for_the_authority_only() {
+        let (chunks, layers) = tree();
+        let mut state = committed(&layers[3][0]);
+        let proof = witness_core::gen_proof(&layers, 3);
+        let new_chunk = vec![0xaa; CHUNK_SIZE];
+        let ix = instruction::verify_and_update(&PROGRAM, &ACCOUNT, &AUTHORITY, 3, &chunks[3], &new_chunk, &proof);
+        let stranger = Pubkey::new_from_array([9; 32]);
+        assert_eq!(run(&mut state, &PROGRAM, Some(&stranger), &ix.data), custom(VerifierError::BadAuthority));
+        assert_eq!(run(&mut state, &PROGRAM, None, &ix.data), custom(VerifierError::BadAuthority));
+        run(&mut state, &PROGRAM, Some(&AUTHORITY), &ix.data).unwrap();
+        let mut updated = chunks.clone();
+        updated[3] = new_chunk;
+        assert_eq!(RootAccount::unpack(&state).unwrap().root, witness_core::build_layers(&updated)[3][0]);
+        // the old chunk no longer verifies
+        let stale = instruction::verify(&PROGRAM, &ACCOUNT, 3, &chunks[3], &proof);
+        assert_eq!(run(&mut state, &PROGRAM, None, &stale.data), custom(VerifierError::ProofMismatch));
+    }
+
+    #[test]
+    fn update_of_the_last_leaf_moves_its_padding_copies() {
+        let (chunks, layers) = tree();
+        let mut state = committed(&layers[3][0]);
+        // 150 bytes leave 22 used in the last chunk
+        let mut new_chunk = vec![0u8; CHUNK_SIZE];
+        new_chunk[..22].fill(0x5c);
+        let proof = witness_core::gen_proof(&layers, 4);
+        let ix = instruction::verify_and_update(&PROGRAM, &ACCOUNT, &AUTHORITY, 4, &chunks[4], &new_chunk, &proof);
+        run(&mut state, &PROGRAM, Some(&AUTHORITY), &ix.data).unwrap();
+        let mut updated = chunks.clone();
+        updated[4] = new_chunk.clone();
+        let new_layers = witness_core::build_layers(&updated);
+        assert_eq!(RootAccount::unpack(&state).unwrap().root, new_layers[3][0]);
+        for (leaf, chunk) in updated.iter().enumerate() {
+            let ix = instruction::verify(&PROGRAM, &ACCOUNT, leaf as u32, chunk, &witness_core::gen_proof(&new_layers, leaf));
+            run(&mut state, &PROGRAM, None, &ix.data).unwrap();
+        }
+    }
+
+    #[test]
+    fn update_rejects_bytes_past_the_data() {
+        let (chunks, layers) = tree();
+        let mut state = committed(&layers[3][0]);
+        let mut new_chunk = chunks[4].clone();
+        new_chunk[22] = 1;
+        let ix = instruction::verify_and_update(&PROGRAM, &ACCOUNT, &AUTHORITY, 4, &chunks[4], &new_chunk, &witness_core::gen_proof(&layers, 4));
+        assert_eq!(run(&mut state, &PROGRAM, Some(&AUTHORITY), &ix.data), custom(VerifierError::WritePastEnd));
+        assert_eq!(RootAccount::unpack(&state).unwrap().root, layers[3][0]);
+        new_chunk[22] = 0;
+        new_chunk[21] = 1;
+        let ix = instruction::verify_and_update(&PROGRAM, &ACCOUNT, &AUTHORITY, 4, &chunks[4], &new_chunk, &witness_core::gen_proof(&layers, 4));
+        run(&mut state, &PROGRAM, Some(&AUTHORITY), &ix.data).unwrap();
+    }
+
+    #[test]
+    fn rejects_foreign_and_uninitialised_accounts() {
+        let (chunks, layers) = tree();
+        let ix = instruction::verify(&PROGRAM, &ACCOUNT, 0, &chunks[0], &witness_core::gen_proof(&layers, 0));
+        let mut state = committed(&layers[3][0]);
+        assert_eq!(run(&mut state, &AUTHORITY, None, &ix.data), custom(VerifierError::BadRootAccount));
+        let mut empty = vec![0u8; ROOT_ACCOUNT_LEN];
+        assert_eq!(run(&mut empty, &PROGRAM, None, &ix.data), Err(ProgramError::UninitializedAccount));
+        let commit = instruction::commit_root(&PROGRAM, &ACCOUNT, &AUTHORITY, &[0; 32], 150, CHUNK_SIZE as u32);
+        assert_eq!(run(&mut empty, &PROGRAM, None, &commit.data), custom(VerifierError::BadAuthority));
+        let zero = instruction::commit_root(&PROGRAM, &ACCOUNT, &AUTHORITY, &[0; 32], 150, 0);
+        assert_eq!(run(&mut empty, &PROGRAM, Some(&AUTHORITY), &zero.data), custom(VerifierError::ChunkSize));
+    }
+}
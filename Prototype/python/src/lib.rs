@@ -0,0 +1,167 @@
+//! `solana_bloat` Python module over `witness-core`, for analysing real account dumps
+//! in notebooks with the same chunking and hashing as the prototype.
+//! Build with `maturin develop` (or `pip install .`) from this directory.
+//!
+//! ```python
+//! import solana_bloat as sb
+//! tree = sb.MerkleTree.from_blob(data, 32)
+//! proof = tree.proof(3)
+//! assert sb.verify_proof(tree.chunk(3), proof, tree.root, 3, tree.leaf_count)
+//! ```
+
+use std::borrow::Cow;
+
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+
+/// Proof steps as Python sees them: (sibling hash, sibling is the left child)
+type PyProof = Vec<(Cow<'static, [u8]>, bool)>;
+
+fn check_chunk_size(chunk_size: usize) -> PyResult<()> {
+    if chunk_size == 0 {
+        return Err(PyValueError::new_err("chunk_size must be positive"));
+    }
+    Ok(())
+}
+
+fn hash32(b: &[u8], what: &str) -> PyResult<[u8;32]> {
+    b.try_into().map_err(|_| PyValueError::new_err(format!("{} must be 32 bytes, got {}", what, b.len())))
+}
+
+fn to_py(proof: Vec<([u8;32], bool)>) -> PyProof {
+    proof.into_iter().map(|(s, l)| (Cow::Owned(s.to_vec()), l)).collect()
+}
+
+fn from_py(proof: &[(Vec<u8>, bool)]) -> PyResult<Vec<([u8;32], bool)>> {
+    proof.iter().map(|(s, l)| Ok((hash32(s, "proof sibling")?, *l))).collect()
+}
+
+/// Tree over a blob's chunks; leaf hashes are layer 0, the root is the last layer
+#[pyclass(module = "solana_bloat", frozen)]
+struct MerkleTree {
+    chunks: Vec<Vec<u8>>,
+    layers: Vec<Vec<[u8;32]>>,
+}
+
+#[pymethods]
+impl MerkleTree {
+    /// Tree over `chunks` as given (all the same length, as `chunk_blob` returns them)
+    #[new]
+    fn new(chunks: Vec<Vec<u8>>) -> PyResult<Self> {
+        if chunks.is_empty() {
+            return Err(PyValueError::new_err("a tree needs at least one chunk"));
+        }
+        let layers = witness_core::build_layers(&chunks);
+        Ok(Self { chunks, layers })
+    }
+
+    #[staticmethod]
+    fn from_blob(blob: &[u8], chunk_size: usize) -> PyResult<Self> {
+        check_chunk_size(chunk_size)?;
+        Self::new(witness_core::chunk_blob(blob, chunk_size).to_vecs())
+    }
+
+    #[getter]
+    fn root(&self) -> Cow<'static, [u8]> {
+        Cow::Owned(self.layers.last().unwrap()[0].to_vec())
+    }
+
+    /// Chunks the tree was built over (padded leaves are not included)
+    #[getter]
+    fn leaf_count(&self) -> usize {
+        self.chunks.len()
+    }
+
+    #[getter]
+    fn depth(&self) -> usize {
+        self.layers.len() - 1
+    }
+
+    /// Hashes of every layer, leaves first
+    fn layers(&self) -> Vec<Vec<Cow<'static, [u8]>>> {
+        self.layers.iter().map(|l| l.iter().map(|h| Cow::Owned(h.to_vec())).collect()).collect()
+    }
+
+    fn chunk(&self, index: usize) -> PyResult<Cow<'static, [u8]>> {
+        self.chunks.get(index).map(|c| Cow::Owned(c.clone())).ok_or_else(|| PyValueError::new_err(format!("leaf {} out of range", index)))
+    }
+
+    fn proof(&self, index: usize) -> PyResult<PyProof> {
+        if index >= self.chunks.len() {
+            return Err(PyValueError::new_err(format!("leaf {} out of range: {} chunks", index, self.chunks.len())));
+        }
+        Ok(to_py(witness_core::gen_proof(&self.layers, index)))
+    }
+
+    fn __len__(&self) -> usize {
+        self.chunks.len()
+    }
+
+    fn __repr__(&self) -> String {
+        let root = self.layers.last().unwrap()[0];
+        let hex: String = root[..8].iter().map(|b| format!("{:02x}", b)).collect();
+        format!("MerkleTree(leaves={}, depth={}, root={}…)", self.chunks.len(), self.layers.len() - 1, hex)
+    }
+}
+
+/// Split `blob` into `chunk_size`-byte leaves, zero-padding the last
+#[pyfunction]
+fn chunk_blob(blob: &[u8], chunk_size: usize) -> PyResult<Vec<Cow<'static, [u8]>>> {
+    check_chunk_size(chunk_size)?;
+    Ok(witness_core::chunk_blob(blob, chunk_size).iter().map(|c| Cow::Owned(c.to_vec())).collect())
+}
+
+/// Root a stub would store for `blob`
+#[pyfunction]
+fn merkle_root(blob: &[u8], chunk_size: usize) -> PyResult<Cow<'static, [u8]>> {
+    Ok(MerkleTree::from_blob(blob, chunk_size)?.root())
+}
+
+#[pyfunction]
+fn gen_proof(blob: &[u8], chunk_size: usize, leaf_index: usize) -> PyResult<PyProof> {
+    MerkleTree::from_blob(blob, chunk_size)?.proof(leaf_index)
+}
+
+/// True if `chunk` is leaf `leaf_index` of the `leaf_count`-chunk tree under `root`
+/// by `proof`, which must be as deep as that tree and point at that leaf
+#[pyfunction]
+fn verify_proof(chunk: &[u8], proof: Vec<(Vec<u8>, bool)>, root: &[u8], leaf_index: usize, leaf_count: usize) -> PyResult<bool> {
+    Ok(witness_core::verify_proof_at(chunk, &from_py(&proof)?, &hash32(root, "root")?, leaf_index, leaf_count))
+}
+
+/// Leaf index a proof is for
+#[pyfunction]
+fn proof_index(proof: Vec<(Vec<u8>, bool)>) -> PyResult<usize> {
+    Ok(witness_core::proof_index(&from_py(&proof)?))
+}
+
+/// Compact step encoding shared with the FFI and browser verifiers
+#[pyfunction]
+fn encode_proof(proof: Vec<(Vec<u8>, bool)>) -> PyResult<Cow<'static, [u8]>> {
+    Ok(Cow::Owned(witness_core::encode_steps(&from_py(&proof)?)))
+}
+
+#[pyfunction]
+fn decode_proof(bytes: &[u8]) -> PyResult<PyProof> {
+    witness_core::decode_steps(bytes).map(to_py).ok_or_else(|| PyValueError::new_err("malformed proof encoding"))
+}
+
+#[pyfunction]
+fn sha256(data: &[u8]) -> Cow<'static, [u8]> {
+    Cow::Owned(witness_core::sha256(data).to_vec())
+}
+
+#[pymodule]
+fn solana_bloat(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_class::<MerkleTree>()?;
+    m.add_function(wrap_pyfunction!(chunk_blob, m)?)?;
+    m.add_function(wrap_pyfunction!(merkle_root, m)?)?;
+    m.add_function(wrap_pyfunction!(gen_proof, m)?)?;
+    m.add_function(wrap_pyfunction!(verify_proof, m)?)?;
+    m.add_function(wrap_pyfunction!(proof_index, m)?)?;
+    m.add_function(wrap_pyfunction!(encode_proof, m)?)?;
+    m.add_function(wrap_pyfunction!(decode_proof, m)?)?;
+    m.add_function(wrap_pyfunction!(sha256, m)?)?;
+    m.add("STEP_LEN", witness_core::STEP_LEN)?;
+    Ok(())
+}
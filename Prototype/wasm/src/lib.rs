@@ -0,0 +1,90 @@
+//! Browser bindings for checking witness data served by untrusted indexers.
+//! Build with `wasm-pack build wasm --target web` (or `cargo build -p witness-wasm
+//! --target wasm32-unknown-unknown` plus `wasm-bindgen`).
+//!
+//! Proofs are passed in the compact step encoding: per level the 32-byte sibling,
+//! then 1 if it is the left child, 0 if the right (`witness_core::encode_steps`).
+
+use wasm_bindgen::prelude::*;
+
+fn root32(root: &[u8]) -> Result<[u8;32], JsError> {
+    root.try_into().map_err(|_| JsError::new(&format!("root must be 32 bytes, got {}", root.len())))
+}
+
+/// True if `chunk` (as committed, zero-padded to the chunk size) is leaf `leafIndex` of
+/// the `leafCount`-chunk tree under `root`. The proof must be exactly as deep as that
+/// tree and its directions must spell `leafIndex`, so a short proof of an interior node
+/// or a proof of another leaf is false even though it reaches the root.
+#[wasm_bindgen(js_name = verifyProof)]
+pub fn verify_proof(root: &[u8], proof: &[u8], chunk: &[u8], leaf_index: usize, leaf_count: usize) -> Result<bool, JsError> {
+    let root = root32(root)?;
+    if !proof.len().is_multiple_of(witness_core::STEP_LEN) {
+        return Err(JsError::new(&format!("proof must be a multiple of {} bytes", witness_core::STEP_LEN)));
+    }
+    Ok(witness_core::verify_encoded_at(chunk, proof, &root, leaf_index, leaf_count))
+}
+
+/// Leaf index an encoded proof is for, so callers can check it is the chunk they asked for
+#[wasm_bindgen(js_name = proofIndex)]
+pub fn proof_index(proof: &[u8]) -> Result<usize, JsError> {
+    let steps = witness_core::decode_steps(proof).ok_or_else(|| JsError::new("malformed proof encoding"))?;
+    Ok(witness_core::proof_index(&steps))
+}
+
+/// Root `chunk` and `proof` lead to, to compare against a root from elsewhere
+#[wasm_bindgen(js_name = computeRoot)]
+pub fn compute_root(proof: &[u8], chunk: &[u8]) -> Result<Vec<u8>, JsError> {
+    let steps = witness_core::decode_steps(proof).ok_or_else(|| JsError::new("malformed proof encoding"))?;
+    Ok(witness_core::compute_root(chunk, &steps).to_vec())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // `JsError` only exists on wasm targets, so these stay on the `Ok` paths
+
+    fn tree() -> (Vec<Vec<u8>>, Vec<Vec<[u8;32]>>) {
+        let chunks: Vec<Vec<u8>> = (0..5u8).map(|i| vec![i; 32]).collect();
+        let layers = witness_core::build_layers(&chunks);
+        (chunks, layers)
+    }
+
+    #[test]
+    fn accepts_honest_proofs() {
+        let (chunks, layers) = tree();
+        let root = layers.last().unwrap()[0];
+        for (i, chunk) in chunks.iter().enumerate() {
+            let proof = witness_core::encode_steps(&witness_core::gen_proof(&layers, i));
+            assert!(verify_proof(&root, &proof, chunk, i, chunks.len()).unwrap());
+            assert_eq!(proof_index(&proof).unwrap(), i);
+            assert_eq!(compute_root(&proof, chunk).unwrap(), root);
+        }
+    }
+
+    #[test]
+    fn rejects_short_and_long_proofs() {
+        let (chunks, layers) = tree();
+        let root = layers.last().unwrap()[0];
+        let mut node = layers[0][0].to_vec();
+        node.extend_from_slice(&layers[0][1]);
+        let short = witness_core::encode_steps(&witness_core::gen_proof(&layers[1..], 0));
+        assert!(witness_core::verify_encoded(&node, &short, &root));
+        assert!(!verify_proof(&root, &short, &node, 0, chunks.len()).unwrap());
+        let mut long = witness_core::encode_steps(&witness_core::gen_proof(&layers, 1));
+        long.extend_from_slice(&[0; 33]);
+        assert!(!verify_proof(&root, &long, &chunks[1], 1, chunks.len()).unwrap());
+    }
+
+    #[test]
+    fn rejects_wrong_leaf() {
+        let (chunks, layers) = tree();
+        let root = layers.last().unwrap()[0];
+        let proof = witness_core::encode_steps(&witness_core::gen_proof(&layers, 2));
+        assert!(!verify_proof(&root, &proof, &chunks[2], 3, chunks.len()).unwrap());
+        assert!(!verify_proof(&root, &proof, &chunks[2], 2, 2).unwrap());
+        // leaf 5 pads the tree with a copy of leaf 4
+        let padding = witness_core::encode_steps(&witness_core::gen_proof(&layers, 5));
+        assert!(!verify_proof(&root, &padding, &chunks[4], 5, chunks.len()).unwrap());
+    }
+}
@@ -0,0 +1,35 @@
+//! The checked-in vectors through the browser bindings: every proof verifies as its
+//! leaf and no invalid witness does. `JsError` only exists on wasm targets, so witnesses
+//! the bindings reject with an error (a length that is not whole steps) are left out.
+
+use serde_json::Value;
+use witness_wasm::{compute_root, proof_index, verify_proof};
+
+const VECTORS: &str = include_str!("../../vectors/sha256-v1.json");
+
+fn bytes(v: &Value) -> Vec<u8> {
+    hex::decode(v.as_str().unwrap()).unwrap()
+}
+
+#[test]
+fn vectors_verify() {
+    let set: Value = serde_json::from_str(VECTORS).unwrap();
+    for v in set["vectors"].as_array().unwrap() {
+        let (name, root, count) = (v["name"].as_str().unwrap(), bytes(&v["root"]), v["chunk_count"].as_u64().unwrap() as usize);
+        for p in v["proofs"].as_array().unwrap() {
+            let leaf = p["leaf_index"].as_u64().unwrap() as usize;
+            let (chunk, steps) = (bytes(&p["chunk"]), bytes(&p["steps"]));
+            assert!(verify_proof(&root, &steps, &chunk, leaf, count).unwrap(), "{} leaf {}", name, leaf);
+            assert_eq!(proof_index(&steps).unwrap(), leaf, "{} leaf {}", name, leaf);
+            assert_eq!(compute_root(&steps, &chunk).unwrap(), root, "{} leaf {}", name, leaf);
+        }
+        for w in v["invalid"].as_array().unwrap() {
+            let leaf = w["leaf_index"].as_u64().unwrap() as usize;
+            let (chunk, steps) = (bytes(&w["chunk"]), bytes(&w["steps"]));
+            if !steps.len().is_multiple_of(witness_core::STEP_LEN) {
+                continue;
+            }
+            assert!(!verify_proof(&root, &steps, &chunk, leaf, count).unwrap(), "{}: {}", name, w["name"]);
+        }
+    }
+}